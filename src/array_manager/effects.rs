@@ -1,52 +1,207 @@
+use log::info;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 use error_stack::Result;
 
 use crate::defs::{self, DimmingAmount};
-use crate::defs::{EffectNodeDefinition, EffectUsage};
+use crate::defs::{DmxArray, EffectDefinition, EffectNodeDefinition, EffectUsage, SymbolTable};
 
 use super::error::DmxArrayError;
+use crate::messages::{UnresolvedEffects, ValidationProblems};
 use super::{ArrayManager, Scope};
-use crate::artnet_manager::EffectNodeRuntime;
+use crate::artnet_manager::{CompiledEffectNode, EffectNodeRuntime, InstantOffEffectNode};
 
 impl defs::EffectNodeDefinition {
+    pub fn compile(
+        &self,
+        scope: &Scope,
+    ) -> Result<CompiledEffectNode, DmxArrayError> {
+        match self {
+            defs::EffectNodeDefinition::Sequence(node) => node.compile(scope),
+            defs::EffectNodeDefinition::Parallel(node) => node.compile(scope),
+            defs::EffectNodeDefinition::Fade(ref node) => node.compile(scope),
+            defs::EffectNodeDefinition::Delay(ref node) => node.compile(scope),
+            defs::EffectNodeDefinition::Hold(ref node) => node.compile(scope),
+            defs::EffectNodeDefinition::WaitFor(ref node) => node.compile(scope),
+        }
+    }
+
     pub fn get_runtime_node(
         &self,
         scope: &Scope,
     ) -> Result<Box<dyn EffectNodeRuntime>, DmxArrayError> {
+        Ok(self.compile(scope)?.instantiate())
+    }
+
+    // One line summary of the node tree, e.g. sequence(fade @all to s(255) in 10 ticks, delay 5 ticks)
+    pub fn get_summary(&self) -> String {
+        let summarize_nodes = |nodes: &[defs::EffectNodeDefinition]| nodes.iter().map(|node| node.get_summary()).collect::<Vec<_>>().join(", ");
+
         match self {
-            defs::EffectNodeDefinition::Sequence(node) => node.get_runtime_node(scope),
-            defs::EffectNodeDefinition::Parallel(node) => node.get_runtime_node(scope),
-            defs::EffectNodeDefinition::Fade(ref node) => node.get_runtime_node(scope),
-            defs::EffectNodeDefinition::Delay(ref node) => node.get_runtime_node(scope),
+            defs::EffectNodeDefinition::Sequence(node) => format!("sequence({})", summarize_nodes(&node.nodes)),
+            defs::EffectNodeDefinition::Parallel(node) => format!("parallel({})", summarize_nodes(&node.nodes)),
+            defs::EffectNodeDefinition::Delay(node) => format!("delay {} ticks", node.ticks),
+            defs::EffectNodeDefinition::Fade(node) => format!("fade {} to {} in {} ticks", node.lights, node.target, node.ticks),
+            defs::EffectNodeDefinition::Hold(node) => format!("hold {} at {} for {} ticks", node.lights, node.target, node.ticks),
+            defs::EffectNodeDefinition::WaitFor(node) => match &node.equals {
+                Some(equals) => format!("wait_for {} == {}", node.value_name, equals),
+                None => format!("wait_for {} to change", node.value_name),
+            },
         }
     }
 }
 
+// Compiled effects are dropped when this number is reached (dimming amount is part of the key, so commands with many
+// different dimming amounts could otherwise grow the cache)
+const MAX_COMPILED_EFFECTS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct CompiledEffectKey {
+    array_id: Arc<str>,
+    usage: EffectUsage,
+    effect_id: Option<Arc<str>>,
+    lights: Option<String>,
+    dimming_amount: DimmingAmount,
+    values_epoch: u64,      // Compiled effects built before a value read by the array effects changed are not used
+}
+
+#[derive(Debug)]
+pub(super) struct CompiledEffect {
+    node: CompiledEffectNode,
+    warnings: Vec<String>,
+    value_names: Vec<Arc<str>>,     // Values read while compiling
+}
+
+pub(super) type GroupEffectRuntime = (Box<dyn EffectNodeRuntime>, Vec<String>, Vec<Arc<str>>);     // Runtime node, warnings and names of the values read
+
+// Not a valid array id (see defs::validate_id) so it never collides with an actual array
+const INLINE_EFFECT_ARRAY_ID: &str = "#inline";
+
 impl ArrayManager {
-    pub(super) fn add_effect(&mut self, effect_id: Arc<str>, effect: EffectNodeDefinition) -> Result<(), DmxArrayError> {
+    pub(super) fn add_effect(&mut self, effect_id: Arc<str>, effect: EffectDefinition) -> Result<(), DmxArrayError> {
+        self.invalidate_compiled_effects();
         self.effects.insert(effect_id, effect);
+        self.update_unresolved_effects();
         Ok(())
     }
 
-    pub(super) fn remove_effect(&mut self, effect_id: &str) -> Result<(), DmxArrayError> {
+    // Compile the effect for each array that would use it, without adding it. Values that are not found are warnings
+    // since they may be given by the commands
+    pub(super) fn validate_effect(&self, effect_id: Arc<str>, effect: &EffectDefinition) -> ValidationProblems {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+
+        for array_id in self.get_effect_referencing_arrays(&effect_id) {
+            let result = Scope::new(self, array_id.clone(), Some(&effect_id), defs::DIMMING_AMOUNT_MAX)
+                .map(|scope| scope.with_effect_defaults(Some(&effect.defaults).filter(|defaults| !defaults.is_empty())))
+                .and_then(|scope| effect.node.compile(&scope).map(|_| scope.take_warnings()));
+
+            match result {
+                Ok(scope_warnings) => warnings.extend(scope_warnings),
+                Err(e) if matches!(e.current_context(), DmxArrayError::ArrayValueNotFound(..)) => warnings.push(format!("Array {array_id}: {e}")),
+                Err(e) => errors.push(format!("Array {array_id}: {e}")),
+            }
+        }
+
+        (errors, warnings)
+    }
+
+    pub(super) fn remove_effect(&mut self, effect_id: &str, force: bool) -> Result<(), DmxArrayError> {
+        let referencing_arrays = self.get_effect_referencing_arrays(effect_id);
+
+        if !force && !referencing_arrays.is_empty() {
+            return Err(DmxArrayError::EffectInUse(Arc::from(effect_id), referencing_arrays.join(", ")).into());
+        }
+
+        self.invalidate_compiled_effects();
         self.effects.remove(effect_id);
+        self.update_unresolved_effects();
         Ok(())
     }
 
     //
-    // Get effect definition by looking for the effect_id in the array effects list, then the global effects list.
-    // If the effect_id is not found, return None.
+    // Arrays may be defined before the global effects they use (e.g. retained messages replayed in any order), so
+    // references to undefined effects are recorded instead of being rejected, and are resolved when the effects are added.
+    // On, off and dim effects that are not defined fall back to the default effects, startup effects are deferred
+    //
+    pub(super) fn update_unresolved_effects(&mut self) {
+        let unresolved_effects = self
+            .arrays
+            .iter()
+            .map(|(array_id, array)| (array_id.clone(), self.get_array_unresolved_effects(array)))
+            .filter(|(_, effect_ids)| !effect_ids.is_empty())
+            .collect::<HashMap<_, _>>();
+
+        for (array_id, effect_ids) in self.unresolved_effects.iter() {
+            let resolved = effect_ids.iter().filter(|effect_id| !unresolved_effects.get(array_id).is_some_and(|ids| ids.contains(effect_id)));
+
+            for effect_id in resolved {
+                info!("Array {} reference to effect {} is resolved", array_id, effect_id);
+            }
+        }
+
+        self.unresolved_effects = unresolved_effects;
+    }
+
+    // Effects referenced by the array (on, off, dim or startup) that are not defined, sorted
+    pub(super) fn get_array_unresolved_effects(&self, array: &DmxArray) -> Vec<Arc<str>> {
+        let mut effect_ids = [&array.on, &array.off, &array.dim, &array.startup]
+            .into_iter()
+            .filter(|effect_id| effect_id.as_ref() != defs::NO_STARTUP_EFFECT)
+            .filter(|effect_id| !array.effects.contains_key(effect_id.as_ref()) && !self.effects.contains_key(*effect_id))
+            .filter(|effect_id| !["on", "off", "dim"].contains(&effect_id.as_ref()))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        effect_ids.sort();
+        effect_ids.dedup();
+        effect_ids
+    }
+
+    pub(super) fn get_unresolved_effects(&self) -> UnresolvedEffects {
+        self.unresolved_effects.iter().map(|(array_id, effect_ids)| (array_id.clone(), effect_ids.clone())).collect()
+    }
+
+    //
+    // Get the (sorted) ids of arrays using a global effect as their on, off or dim effect.
+    // Arrays defining an effect with the same id in their own effects list do not use the global effect
     //
-    fn get_effect_definition(
+    fn get_effect_referencing_arrays(&self, effect_id: &str) -> Vec<Arc<str>> {
+        let mut referencing_arrays: Vec<Arc<str>> = self
+            .arrays
+            .iter()
+            .filter(|(_, array)| !array.effects.contains_key(effect_id))
+            .filter(|(_, array)| [&array.on, &array.off, &array.dim].iter().any(|id| id.as_ref() == effect_id))
+            .map(|(array_id, _)| array_id.clone())
+            .collect();
+
+        referencing_arrays.sort();
+        referencing_arrays
+    }
+
+    pub(super) fn get_effects(&self) -> Result<BTreeMap<Arc<str>, EffectDefinition>, DmxArrayError> {
+        Ok(self
+            .effects
+            .iter()
+            .map(|(effect_id, effect)| (effect_id.clone(), effect.clone()))
+            .collect())
+    }
+
+    // Default values of the effect used by the array for this usage (only global effects have defaults, an array
+    // effect with the same id hides the global effect and its defaults)
+    fn get_usage_effect_defaults(
         &self,
+        usage: &EffectUsage,
         array_id: &str,
-        effect_id: &str,
-    ) -> Result<Option<&EffectNodeDefinition>, DmxArrayError> {
-        let array = self.get_array(array_id)?;
-        Ok(array
-            .effects
-            .get(effect_id)
-            .or_else(|| self.effects.get(effect_id)))
+        effect_id: Option<&Arc<str>>,
+    ) -> Result<Option<&SymbolTable>, DmxArrayError> {
+        let effect_id = self.get_usage_effect_id(usage, array_id, effect_id)?;
+
+        if self.get_array(array_id)?.effects.contains_key(effect_id.as_ref()) {
+            return Ok(None);
+        }
+
+        Ok(self.effects.get(&effect_id).map(|effect| &effect.defaults).filter(|defaults| !defaults.is_empty()))
     }
 
     fn get_usage_effect_id(
@@ -74,26 +229,79 @@ impl ArrayManager {
         array_id: &str,
         effect_id: Option<&Arc<str>>,
     ) -> Result<&EffectNodeDefinition, DmxArrayError> {
+        self.resolve_usage_effect_definition(usage, array_id, effect_id).map(|(_, _, node)| node)
+    }
+
+    //
+    // Resolve the effect by looking for the effect_id in the array effects list, then the global effects list. If
+    // neither has it and it is the array's effect id for this usage (e.g. its on effect for On), the built-in default
+    // effect of the usage is used.
+    //
+    fn resolve_usage_effect_definition(
+        &self,
+        usage: &EffectUsage,
+        array_id: &str,
+        effect_id: Option<&Arc<str>>,
+    ) -> Result<(Arc<str>, defs::EffectSource, &EffectNodeDefinition), DmxArrayError> {
         let effect_id = self.get_usage_effect_id(usage, array_id, effect_id)?;
         let array = self.get_array(array_id)?;
 
-        let effect_definition = self
-            .get_effect_definition(array_id, &effect_id)?
-            .or_else(|| match usage {
-                EffectUsage::On if effect_id == array.on => Some(&self.default_on_effect),
-                EffectUsage::Off if effect_id == array.off => Some(&self.default_off_effect),
-                &EffectUsage::Dim if effect_id == array.dim => Some(&self.default_dim_effect),
-                _ => None,
-            });
-
-        effect_definition.ok_or_else(|| {
-            DmxArrayError::EffectNotFound(
+        if let Some(node) = array.effects.get(effect_id.as_ref()) {
+            return Ok((effect_id, defs::EffectSource::Array, node));
+        }
+
+        if let Some(effect) = self.effects.get(&effect_id) {
+            return Ok((effect_id, defs::EffectSource::Global, &effect.node));
+        }
+
+        let default_effect = match usage {
+            EffectUsage::On if effect_id == array.on => Some(&self.default_on_effect),
+            EffectUsage::Off if effect_id == array.off => Some(&self.default_off_effect),
+            &EffectUsage::Dim if effect_id == array.dim => Some(&self.default_dim_effect),
+            _ => None,
+        };
+
+        match default_effect {
+            Some(node) => Ok((effect_id, defs::EffectSource::Default, node)),
+            None => Err(DmxArrayError::EffectNotFound(
                 Arc::from(format!("{} ({})", array_id, array.description)),
                 effect_id.clone(),
-            ).into()
+            ).into()),
+        }
+    }
+
+    // Which definition On, Off or Dim of the array would use (ResolveEffect command and diagnostics)
+    pub(super) fn resolve_usage_effect(
+        &self,
+        usage: &EffectUsage,
+        array_id: &str,
+        effect_id: Option<&Arc<str>>,
+    ) -> Result<defs::EffectResolution, DmxArrayError> {
+        let (effect_id, source, node) = self.resolve_usage_effect_definition(usage, array_id, effect_id)?;
+
+        Ok(defs::EffectResolution {
+            usage: *usage,
+            effect_id,
+            source,
+            summary: node.get_summary(),
         })
     }
 
+    // The on, off and dim effects of the array that are neither array effects nor global effects, so the built-in
+    // default effect is used. The usage's own name (e.g. "on" for on) is the expected way to use the default effect, any
+    // other id is likely a typo (or a global effect that is not defined yet)
+    pub(super) fn get_default_effect_fallback_warnings(&self, array_id: &str, array: &DmxArray) -> Vec<String> {
+        [("on", &array.on), ("off", &array.off), ("dim", &array.dim)]
+            .into_iter()
+            .filter(|(usage_name, effect_id)| effect_id.as_ref() != *usage_name)
+            .filter(|(_, effect_id)| !array.effects.contains_key(effect_id.as_ref()) && !self.effects.contains_key(*effect_id))
+            .map(|(usage_name, effect_id)| format!(
+                "Array {array_id} {usage_name} effect {effect_id} is neither an array effect nor a global effect, the built-in default {usage_name} effect is used"
+            ))
+            .collect()
+    }
+
+    #[cfg(test)]
     pub fn get_usage_effect_runtime(
         &self,
         usage: &EffectUsage,
@@ -101,9 +309,130 @@ impl ArrayManager {
         effect_id: Option<&Arc<str>>,
         dimming_amount: DimmingAmount,
     ) -> Result<Box<dyn EffectNodeRuntime>, DmxArrayError> {
+        self.get_group_effect_runtime(usage, array_id, effect_id, None, dimming_amount).map(|(node, _, _)| node)
+    }
+
+    // Get runtime node of an array effect restricted to a light group of the array (None for the whole array), the
+    // warnings found while building it and the names of the values it read. The compiled effect is cached, so repeating
+    // a command only instantiates the runtime nodes
+    pub fn get_group_effect_runtime(
+        &self,
+        usage: &EffectUsage,
+        array_id: &str,
+        effect_id: Option<&Arc<str>>,
+        lights: Option<&str>,
+        dimming_amount: DimmingAmount,
+    ) -> Result<GroupEffectRuntime, DmxArrayError> {
+        let key = CompiledEffectKey {
+            array_id: Arc::from(array_id),
+            usage: *usage,
+            effect_id: effect_id.cloned(),
+            lights: lights.map(|lights| lights.to_string()),
+            dimming_amount,
+            values_epoch: self.values_epochs.get(array_id).copied().unwrap_or_default(),
+        };
+
+        if let Some(compiled_effect) = self.compiled_effects.borrow().get(&key) {
+            self.compiled_effect_hits.set(self.compiled_effect_hits.get() + 1);
+            return Ok((compiled_effect.node.instantiate(), compiled_effect.warnings.clone(), compiled_effect.value_names.clone()));
+        }
+
         let effect_definition = self.get_usage_effect_definition(usage, array_id, effect_id)?;
-        let scope = super::Scope::new(self, Arc::from(array_id), effect_id, dimming_amount)?;
+        let effect_defaults = self.get_usage_effect_defaults(usage, array_id, effect_id)?;
+        let scope = super::Scope::new(self, key.array_id.clone(), effect_id, dimming_amount)?
+            .with_lights_override(lights)?
+            .with_effect_defaults(effect_defaults);
+        let compiled_effect = CompiledEffect {
+            node: effect_definition.compile(&scope)?,
+            warnings: scope.take_warnings(),
+            value_names: scope.take_value_names(),
+        };
+        let result = (compiled_effect.node.instantiate(), compiled_effect.warnings.clone(), compiled_effect.value_names.clone());
+
+        let mut compiled_effects = self.compiled_effects.borrow_mut();
+        if compiled_effects.len() >= MAX_COMPILED_EFFECTS {
+            compiled_effects.clear();
+        }
+        compiled_effects.insert(key, compiled_effect);
+        self.compiled_effect_misses.set(self.compiled_effect_misses.get() + 1);
+
+        Ok(result)
+    }
+
+    // Get runtime node setting all the lights of the array (or of a light group) to zero. The array off effect is not
+    // used (see OnOffCommandParameters instant)
+    pub fn get_instant_off_runtime(&self, array_id: &str, lights: Option<&str>) -> Result<Box<dyn EffectNodeRuntime>, DmxArrayError> {
+        let lights = self.get_array_light_channels(array_id, lights.unwrap_or("@all"))?;
+
+        Ok(Box::new(InstantOffEffectNode { lights, done: false }))
+    }
+
+    // Called whenever arrays, effects or fixtures change, since compiled effects may depend on any of them
+    pub(super) fn invalidate_compiled_effects(&self) {
+        self.compiled_effects.borrow_mut().clear();
+    }
+
+    // Called when a value changes (of a given array, or a global value if array_id is None). The compiled effects of
+    // arrays which read the value are no longer found, since their key has the previous values epoch of the array
+    // (they are dropped when the cache is full)
+    pub(super) fn invalidate_value_compiled_effects(&mut self, array_id: Option<&str>, value_name: &str) {
+        let array_ids = self
+            .compiled_effects
+            .borrow()
+            .iter()
+            .filter(|(key, _)| array_id.is_none_or(|array_id| key.array_id.as_ref() == array_id))
+            .filter(|(_, compiled_effect)| compiled_effect.value_names.iter().any(|name| name.as_ref() == value_name))
+            .map(|(key, _)| key.array_id.clone())
+            .collect::<HashSet<_>>();
+
+        for array_id in array_ids {
+            *self.values_epochs.entry(array_id).or_default() += 1;
+        }
+    }
+
+    //
+    // Get runtime node of an effect applied to lights which are not part of any array. The effect is evaluated
+    // in a transient array (whose id cannot be used by real arrays) so only global values are available
+    //
+    pub(super) fn get_inline_effect_runtime(
+        &mut self,
+        lights: &str,
+        effect: &EffectNodeDefinition,
+        dimming_amount: DimmingAmount,
+    ) -> Result<Box<dyn EffectNodeRuntime>, DmxArrayError> {
+        let array_id: Arc<str> = Arc::from(INLINE_EFFECT_ARRAY_ID);
+
+        if self.default_universe_id.is_none() && !lights.trim_start().starts_with('$') {
+            return Err(DmxArrayError::ArrayMissingUniverseId(array_id).into());
+        }
+
+        let array = DmxArray {
+            description: "inline effect".to_string(),
+            universe_id: self.default_universe_id.clone().unwrap_or_default(),
+            lights: HashMap::from([("all".to_string(), lights.to_string())]),
+            on: Arc::from(""),
+            off: Arc::from(""),
+            dim: Arc::from(""),
+            effects: HashMap::new(),
+            default_values: HashMap::new(),
+            strict_values: false,
+            limits: HashMap::new(),
+            group_dimming: HashMap::new(),
+            max_lights_nesting: None,
+            linked_effects: Vec::new(),
+            default_dimming_amount: None,
+            startup: Arc::from(defs::NO_STARTUP_EFFECT),
+            startup_dimming_amount: None,
+            startup_on_redefine: true,
+            strict: true,
+            pre: Vec::new(),
+        };
+
+        self.arrays.insert(array_id.clone(), Box::new(array));
+        let result = Scope::new(self, array_id.clone(), None, dimming_amount)
+            .and_then(|scope| effect.get_runtime_node(&scope));
+        self.arrays.remove(&array_id);
 
-        effect_definition.get_runtime_node(&scope)
+        result
     }
 }
@@ -3,26 +3,76 @@ use std::sync::Arc;
 
 use thiserror::Error;
 use super::verify::ChannelUsage;
+use crate::defs::DimmingAmount;
+use crate::dmx::{ChannelComponent, ChannelType, DmxParseError};
 
 #[derive(Debug, Error)]
 pub enum DmxArrayError {
     #[error("Array with id '{0}' not found")]
     ArrayNotFound(Arc<str>),
 
+    #[error("Array with id '{0}' is already defined (remove it before renaming another array to it)")]
+    ArrayAlreadyDefined(Arc<str>),
+
+    #[error("Array template '{0}' not found")]
+    ArrayTemplateNotFound(Arc<str>),
+
+    #[error("Array template '{0}' is invalid: {1}")]
+    InvalidArrayTemplate(Arc<str>, String),
+
+    #[error("Array template '{0}' {1}: parameter '{2}' is not given")]
+    MissingTemplateParameter(Arc<str>, String, String),
+
+    #[error("Array template '{0}' {1}: {2}")]
+    InvalidTemplatePlaceholder(Arc<str>, String, String),
+
+    #[error("Invalid array id '{0}': {1}")]
+    InvalidArrayId(Arc<str>, String),
+
+    #[error("Array '{0}' has no universe_id and no default universe is configured")]
+    ArrayMissingUniverseId(Arc<str>),
+
     #[error("Array '{0}' Lights {1} does not contain definition for {2}")]
     ArrayLightsNotFound(String, String, String),
 
-    #[error("Array '{0}' Light '{1}' ({2}) contain circular reference to {3}")]
-    ArrayLightsCircularReference(String, String, String, String),
+    #[error("Array '{0}' has no light group '{1}' (lights of an array command must be @group)")]
+    ArrayLightGroupNotFound(Arc<str>, String),
+
+    #[error("Array '{0}' Light '{1}' contains circular reference via @{2}")]
+    ArrayLightsCircularReference(String, String, String),
+
+    #[error("Array '{0}' Light '{1}' nesting too deep (limit {2})")]
+    ArrayLightsNestingTooDeep(String, String, usize),
+
+    #[error("Array '{0}' Light '{1}' entry {2}: {3}")]
+    ArrayLightsInvalidChannel(String, String, usize, #[source] DmxParseError),
+
+    #[error("Array '{0}' Light '{1}' ({2}): {3} light has no {4} component")]
+    ArrayLightsInvalidComponent(String, String, String, ChannelType, ChannelComponent),
+
+    #[error("Array '{0}' Light '{1}' ({2}) uses fixture '{3}' which is not defined")]
+    ArrayLightsFixtureNotFound(String, String, String, String),
+
+    #[error("Array '{0}' Light '{1}' ({2}) is invalid set expression: {3}")]
+    ArrayLightsInvalidExpression(String, String, String, String),
 
-    #[error("Array '{0}' Light '{1}' ({2}) is invalid channel definition (s:n, rgb:n or w:n)")]
-    ArrayLightsInvalidChannelDefinition(String, String, String),
+    #[error("Fixture '{0}' is invalid: {1}")]
+    InvalidFixture(Arc<str>, String),
+
+    #[error("Fixture '{0}' is used by arrays: {1}")]
+    FixtureInUse(Arc<str>, String),
 
     #[error("Effect '{0}' not found in array '{1}' or in global effects list")]
     EffectNotFound(Arc<str>, Arc<str>),
 
-    #[error("Array '{0}' '{1}' has no value for {2}")]
-    ArrayValueNotFound(Arc<str>, String, String),
+    #[error("Effect '{0}' is used by arrays: {1} (use {{\"force\": true}} payload to remove anyway)")]
+    EffectInUse(Arc<str>, String),
+
+    #[error("Array '{0}' '{1}' has no value for {2} (looked in {3}){4}")]
+    ArrayValueNotFound(Arc<str>, String, String, String, String),
+
+    #[error("Array '{0}' '{1}': value {2} has a default but is not declared in the array default_values (strict_values is set)")]
+    StrictValueNotDeclared(Arc<str>, String, String),
 
     #[error("Array '{0}' '{1}' has unterminated `value` expression")]
     ValueExpressionNotTerminated(Arc<str>, Arc<str>),
@@ -35,4 +85,31 @@ pub enum DmxArrayError {
 
     #[error("{0} {1}: {2}")]
     ValueError(String, &'static str, String),
+
+    #[error("{0} {3}: value '{1}' is {2} but a number is required")]
+    ValueTypeMismatch(String, String, String, &'static str),
+
+    #[error("Array '{0}' limits: light group '{1}' is not defined")]
+    ArrayLimitGroupNotFound(String, String),
+
+    #[error("Array '{0}' limits: light group '{1}' has invalid limit: {2}")]
+    ArrayInvalidLimit(String, String, String),
+
+    #[error("Array '{0}' group_dimming: light group '{1}' is not defined")]
+    ArrayGroupDimmingGroupNotFound(String, String),
+
+    #[error("Array '{0}' group_dimming: light group '{1}' has invalid factor {2} (must be 0-{3})")]
+    ArrayInvalidGroupDimming(String, String, DimmingAmount, DimmingAmount),
+
+    #[error("Dimming preset '{0}' is not defined (defined presets: {1})")]
+    DimmingPresetNotFound(Arc<str>, String),
+
+    #[error("Dimming preset '{0}' has invalid amount {1} (must be 0-{2})")]
+    InvalidDimmingPreset(Arc<str>, DimmingAmount, DimmingAmount),
+
+    #[error("Array '{0}' pre commands form a cycle: {1}")]
+    ArrayPreCycle(Arc<str>, String),
+
+    #[error(transparent)]
+    Parse(#[from] DmxParseError),
 }
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+use error_stack::Result;
+
+use super::error::DmxArrayError;
+use super::ArrayManager;
+use super::lights::get_lights_terms;
+use crate::defs::FixtureDefinition;
+use crate::dmx::{ChannelDefinition, DmxParseError};
+
+// Channel types that are parsed by ChannelDefinition, so they cannot be used as fixture names in lights
+const CHANNEL_TYPE_PREFIXES: &[&str] = &["s", "rgb", "w"];
+
+impl ArrayManager {
+    pub(super) fn add_fixture(&mut self, fixture_name: Arc<str>, fixture: FixtureDefinition) -> Result<(), DmxArrayError> {
+        if CHANNEL_TYPE_PREFIXES.contains(&fixture_name.to_lowercase().as_str()) {
+            return Err(DmxArrayError::InvalidFixture(fixture_name, "name is a channel type".to_string()).into());
+        }
+
+        let offsets = &fixture.offsets;
+        let is_valid = offsets.len() == fixture.channel_type.get_channel_count()
+            && offsets.iter().enumerate().all(|(i, offset)| !offsets[..i].contains(offset));
+
+        if !is_valid {
+            return Err(DmxArrayError::InvalidFixture(
+                fixture_name,
+                format!("type {} needs {} different offsets (got {:?})", fixture.channel_type, fixture.channel_type.get_channel_count(), offsets),
+            ).into());
+        }
+
+        // Arrays were verified with the current definition, so it can only be redefined (e.g. retained message received
+        // again after reconnecting) with the same definition
+        if self.fixtures.get(&fixture_name).is_some_and(|existing| *existing != fixture) {
+            let referencing_arrays = self.get_fixture_referencing_arrays(&fixture_name);
+
+            if !referencing_arrays.is_empty() {
+                return Err(DmxArrayError::FixtureInUse(fixture_name, referencing_arrays.join(", ")).into());
+            }
+        }
+
+        self.invalidate_compiled_effects();
+        self.fixtures.insert(fixture_name, fixture);
+        Ok(())
+    }
+
+    pub(super) fn remove_fixture(&mut self, fixture_name: &str) -> Result<(), DmxArrayError> {
+        let referencing_arrays = self.get_fixture_referencing_arrays(fixture_name);
+
+        if !referencing_arrays.is_empty() {
+            return Err(DmxArrayError::FixtureInUse(Arc::from(fixture_name), referencing_arrays.join(", ")).into());
+        }
+
+        self.invalidate_compiled_effects();
+        self.fixtures.remove(fixture_name);
+        Ok(())
+    }
+
+    // Get the (sorted) ids of arrays whose lights use a fixture (name:base entries)
+    fn get_fixture_referencing_arrays(&self, fixture_name: &str) -> Vec<Arc<str>> {
+        let mut referencing_arrays: Vec<Arc<str>> = self
+            .arrays
+            .iter()
+            .filter(|(_, array)| {
+                array.lights.values().flat_map(|lights_list| get_lights_terms(lights_list)).any(|entry| {
+                    entry.split_once(':').is_some_and(|(name, _)| name.trim() == fixture_name)
+                })
+            })
+            .map(|(array_id, _)| array_id.clone())
+            .collect();
+
+        referencing_arrays.sort();
+        referencing_arrays
+    }
+
+    // Channel definition of a fixture entry (fixture-name:base) in array lights. Entries that are not fixtures are
+    // reported with the error of parsing them as channels and their (1 based) position in the lights list
+    pub(super) fn get_fixture_channel_definition(
+        &self,
+        array_id: &str,
+        lights: &str,
+        entry: &str,
+        position: usize,
+        channel_error: DmxParseError,
+    ) -> Result<ChannelDefinition, DmxArrayError> {
+        let invalid_channel = |reason: &'static str| {
+            DmxArrayError::ArrayLightsInvalidChannel(array_id.to_string(), lights.to_string(), position, DmxParseError::InvalidChannel(entry.to_string(), reason))
+        };
+        let (fixture_name, base) = match entry.split_once(':') {
+            Some((fixture_name, base)) if !CHANNEL_TYPE_PREFIXES.contains(&fixture_name.trim().to_lowercase().as_str()) => (fixture_name.trim(), base),
+            _ => return Err(DmxArrayError::ArrayLightsInvalidChannel(array_id.to_string(), lights.to_string(), position, channel_error).into()),
+        };
+
+        let fixture = self.fixtures.get(fixture_name).ok_or_else(|| {
+            DmxArrayError::ArrayLightsFixtureNotFound(array_id.to_string(), lights.to_string(), entry.to_string(), fixture_name.to_string())
+        })?;
+        let base = base.trim().parse::<u16>().map_err(|_| invalid_channel("fixture base address is not a number (0-65535)"))?;
+
+        Ok(ChannelDefinition::from_offsets(fixture.channel_type, base, &fixture.offsets).ok_or_else(|| invalid_channel("fixture channels are beyond channel 65535"))?)
+    }
+}
@@ -1,34 +1,126 @@
 
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::sync::Arc;
 use error_stack::Result;
 
 use super::manager::ArrayManager;
 use super::error::DmxArrayError;
-use crate::dmx::{UniverseChannelDefinitions, ChannelDefinition};
-use crate::defs::DmxArray;
+use crate::dmx::{UniverseChannelDefinitions, ChannelComponent, ChannelDefinition, ChannelDimming, ChannelLimits};
+use crate::defs::{DmxArray, TargetValue, DIMMING_AMOUNT_MAX};
 
 impl UniverseChannelDefinitions {
     pub (super) fn new(universe_id: String) -> Self {
         Self {
             universe_id,
             channels: Vec::new(),
+            components: HashMap::new(),
         }
     }
 
-    pub (super) fn add(&mut self, channel: ChannelDefinition) {
+    pub (super) fn add(&mut self, channel: ChannelDefinition, component: Option<ChannelComponent>) {
+        if let (ChannelDefinition::Single(c), Some(component)) = (&channel, component) {
+            self.components.insert(*c, component);
+        }
         self.channels.push(channel);
     }
 }
 
+pub (super) const DEFAULT_MAX_LIGHTS_NESTING: usize = 32;
+
+// Expands `value` references in light groups, None when values are not available (e.g. when verifying a new array)
+pub (super) type LightsValueExpander<'a> = Option<&'a dyn Fn(&str) -> Result<String, DmxArrayError>>;
+
+// Light group that can only be resolved once values are known (e.g. "bar": "rgb:`bar_base`")
+pub (super) fn is_parametric_lights(lights_list: &str) -> bool {
+    lights_list.contains('`')
+}
+
+// Set operators between the terms of a lights entry (e.g. "@all - @task"), surrounded by spaces since light group and
+// fixture names may contain '-'
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LightsOperator {
+    Difference,         // a - b: lights of a that are not in b
+    Intersection,       // a & b: lights of a that are also in b
+}
+
+const LIGHTS_OPERATORS: [(&str, LightsOperator); 2] = [(" - ", LightsOperator::Difference), (" & ", LightsOperator::Intersection)];
+
+// First term of a lights entry and the (operator, term) pairs that follow it (applied left to right)
+fn split_lights_expression(entry: &str) -> (&str, Vec<(LightsOperator, &str)>) {
+    let next_operator = |text: &str| {
+        LIGHTS_OPERATORS
+            .iter()
+            .filter_map(|(token, operator)| text.find(token).map(|index| (index, token.len(), *operator)))
+            .min_by_key(|(index, _, _)| *index)
+    };
+
+    let Some((index, length, mut operator)) = next_operator(entry) else { return (entry.trim(), Vec::new()) };
+    let first_term = entry[..index].trim();
+    let mut rest = &entry[index + length..];
+    let mut operations = Vec::new();
+
+    while let Some((index, length, next_operator)) = next_operator(rest) {
+        operations.push((operator, rest[..index].trim()));
+        operator = next_operator;
+        rest = &rest[index + length..];
+    }
+
+    operations.push((operator, rest.trim()));
+    (first_term, operations)
+}
+
+// Terms (light groups, channels, fixtures or $universe) of all the entries of a lights list
+pub (super) fn get_lights_terms(lights_list: &str) -> Vec<&str> {
+    lights_list
+        .split(',')
+        .flat_map(|entry| {
+            let (first_term, operations) = split_lights_expression(entry);
+            std::iter::once(first_term).chain(operations.into_iter().map(|(_, term)| term))
+        })
+        .collect()
+}
+
+// Apply a set operator to lights, channels are matched by universe and exact channel definition (so rgb:1 and s:1 are
+// different lights). The order of the left side is kept
+fn apply_lights_operator(lights: &mut Vec<UniverseChannelDefinitions>, operator: LightsOperator, other: &[UniverseChannelDefinitions]) {
+    for universe_channels in lights.iter_mut() {
+        let other_channels = other
+            .iter()
+            .find(|other_channels| other_channels.universe_id == universe_channels.universe_id)
+            .map(|other_channels| other_channels.channels.as_slice())
+            .unwrap_or_default();
+
+        universe_channels.channels.retain(|channel| other_channels.contains(channel) == (operator == LightsOperator::Intersection));
+        universe_channels.components.retain(|channel, _| universe_channels.channels.contains(&ChannelDefinition::Single(*channel)));
+    }
+
+    lights.retain(|universe_channels| !universe_channels.channels.is_empty());
+}
+
+fn add_light_channel(result: &mut Vec<UniverseChannelDefinitions>, universe_id: &str, channel: ChannelDefinition, component: Option<ChannelComponent>) {
+    match result.iter_mut().find(|universe_channels| universe_channels.universe_id == universe_id) {
+        Some(universe_channels) => universe_channels.add(channel, component),
+        None => {
+            let mut universe_channels = UniverseChannelDefinitions::new(universe_id.to_string());
+            universe_channels.add(channel, component);
+            result.push(universe_channels);
+        }
+    }
+}
+
 pub (super) struct ExpansionStack {
     stack: Vec<String>,
+    groups: Vec<String>,        // Light groups being expanded, a group that repeats is a circular reference
+    max_nesting: usize,
 }
 
 impl ExpansionStack {
-    fn new() -> Self {
+    fn new(max_nesting: usize) -> Self {
         Self {
             stack: Vec::new(),
+            groups: Vec::new(),
+            max_nesting,
         }
     }
 
@@ -40,8 +132,24 @@ impl ExpansionStack {
         self.stack.pop().unwrap();
     }
 
-    fn len(&self) -> usize {
-        self.stack.len()
+    fn push_group(&mut self, array_id: &str, group: &str, lights_list: &str) -> Result<(), DmxArrayError> {
+        self.push(lights_list.to_string());
+
+        if self.groups.iter().any(|g| g == group) {
+            return Err(DmxArrayError::ArrayLightsCircularReference(array_id.to_string(), self.to_string(), group.to_string()).into());
+        }
+
+        if self.groups.len() >= self.max_nesting {
+            return Err(DmxArrayError::ArrayLightsNestingTooDeep(array_id.to_string(), self.to_string(), self.max_nesting).into());
+        }
+
+        self.groups.push(group.to_string());
+        Ok(())
+    }
+
+    fn pop_group(&mut self) {
+        self.groups.pop().unwrap();
+        self.pop();
     }
 }
 
@@ -67,7 +175,22 @@ impl ArrayManager {
     //   <Entry1>,<Entry2>,<Entry3>,...
     //
     //  Entry:
-    //   s:n | rgb:n | w:n | @array-light-entry-id | $universe-id
+    //   <Term> | <Term> - <Term> | <Term> & <Term> | ...
+    //
+    //  Term:
+    //   s:n | rgb:n | w:n | fixture-name:n | @array-light-entry-id | $universe-id
+    //
+    //  Comma is union, '-' (difference) and '&' (intersection) are applied left to right (e.g. "@all - @task" or
+    //  "@living & @dimmable, s:20"). A channel term is a set with this single light, and $universe-id cannot be a term
+    //  of a set expression (it selects the universe of the entries that follow it)
+    //
+    //  fixture-name:n is a light whose channels are n plus the offsets of the fixture template (DMX/Fixture/<name>)
+    //
+    //  Light group entries may contain `value` references (e.g. "bar": "rgb:`bar_base`") which are expanded
+    //  using the array values (command, array default and global) when the group is resolved
+    //
+    //  A single component of an rgb or w light (or fixture) is selected with .r, .g, .b, .w1, .w2 or .w3 (e.g. "rgb:10.r"),
+    //  it is a single channel (set with s(n) targets) that verify treats as that component of the light
     //
     //  For example:
     //  {
@@ -91,51 +214,162 @@ impl ArrayManager {
     //          [ ChannelDefinition { channel: 100, channel_type: ChannelType::Tri_white } ]
     //      }
     //  ]
-    //      
-    pub (super) fn static_do_get_array_light_channels(array_id: &str, array: &DmxArray, lights_list: &str, result: &mut HashMap<String, UniverseChannelDefinitions>, stack: &mut ExpansionStack) -> Result<(), DmxArrayError> {
+    //
+    // Universes are returned in the order they are first mentioned, and channels within a universe in the order
+    // they are written (so effects iterating over lights always see the same order)
+    //
+    pub (super) fn do_get_array_light_channels(&self, array_id: &str, array: &DmxArray, lights_list: &str, result: &mut Vec<UniverseChannelDefinitions>, stack: &mut ExpansionStack, expander: LightsValueExpander) -> Result<(), DmxArrayError> {
         let mut universe_id = array.universe_id.as_str();
         
-        for entry in lights_list.split(',').map(|s| s.trim()) {
-            if let Some(nested_lighted_id) = entry.strip_prefix('@') {
-                let nested_lights_list = array.lights.get(nested_lighted_id).ok_or_else(|| DmxArrayError::ArrayLightsNotFound(array_id.to_string(), stack.to_string(), nested_lighted_id.to_string()))?;
+        for (index, entry) in lights_list.split(',').map(|s| s.trim()).enumerate() {
+            let (first_term, operations) = split_lights_expression(entry);
+
+            if !operations.is_empty() {
+                let terms = std::iter::once(first_term).chain(operations.iter().map(|(_, term)| *term));
+                let invalid_expression = |reason: String| DmxArrayError::ArrayLightsInvalidExpression(array_id.to_string(), stack.to_string(), entry.to_string(), reason);
+
+                for term in terms {
+                    if term.is_empty() {
+                        return Err(invalid_expression("missing term before or after '-' or '&'".to_string()).into());
+                    } else if term.starts_with('$') {
+                        return Err(invalid_expression(format!("{term} cannot be a term (put it as a separate entry before the expression)")).into());
+                    }
+                }
+
+                let mut lights = self.get_lights_expression_term(array_id, array, first_term, universe_id, stack, expander)?;
 
-                stack.push(nested_lights_list.to_string());
+                for (operator, term) in operations {
+                    let term_lights = self.get_lights_expression_term(array_id, array, term, universe_id, stack, expander)?;
+                    apply_lights_operator(&mut lights, operator, &term_lights);
+                }
+
+                for universe_channels in lights {
+                    for channel in universe_channels.channels {
+                        let component = match channel {
+                            ChannelDefinition::Single(c) => universe_channels.components.get(&c).copied(),
+                            _ => None,
+                        };
 
-                if stack.len() > 5 {
-                    // Array '{0}' Light '{1}' ({2}) contain circular reference to {3}
-                    return Err(DmxArrayError::ArrayLightsCircularReference(array_id.to_string(), stack.to_string(), nested_lights_list.to_string(), entry.to_string()).into());
+                        add_light_channel(result, &universe_channels.universe_id, channel, component);
+                    }
                 }
+            }
+            else if let Some(nested_lighted_id) = entry.strip_prefix('@') {
+                let nested_lights_list = array.lights.get(nested_lighted_id).ok_or_else(|| DmxArrayError::ArrayLightsNotFound(array_id.to_string(), stack.to_string(), nested_lighted_id.to_string()))?;
+
+                let nested_lights_list = match expander {
+                    Some(expand) if is_parametric_lights(nested_lights_list) => expand(nested_lights_list)?,
+                    None if is_parametric_lights(nested_lights_list) => continue,     // Cannot be resolved without values
+                    _ => nested_lights_list.clone(),
+                };
 
-                Self::static_do_get_array_light_channels(array_id, array, nested_lights_list, result, stack)?;
-                stack.pop();
+                stack.push_group(array_id, nested_lighted_id, &nested_lights_list)?;
+                self.do_get_array_light_channels(array_id, array, &nested_lights_list, result, stack, expander)?;
+                stack.pop_group();
             }
             else if let Some(entry) = entry.strip_prefix('$') {
                 universe_id = entry;
             }
             else {
-                let channel = entry.parse::<ChannelDefinition>().
-                    map_err(|_| DmxArrayError::ArrayLightsInvalidChannelDefinition(array_id.to_string(), stack.to_string(), entry.to_string()))?;
-                let universe_channels = result.entry(universe_id.to_string()).or_insert_with(|| UniverseChannelDefinitions::new(universe_id.to_string()));
-                universe_channels.add(channel); 
+                // A component of an rgb or w light (e.g. rgb:10.r or par56:100.g) is a single channel
+                let (light, component) = match entry.rsplit_once('.').map(|(light, component)| (light, component.parse::<ChannelComponent>())) {
+                    Some((light, Ok(component))) => (light.trim(), Some(component)),
+                    _ => (entry, None),
+                };
+
+                // Entries that are not s/rgb/w channels may be fixture templates (name:base)
+                let channel = match light.parse::<ChannelDefinition>() {
+                    Ok(channel) => channel,
+                    Err(e) => self.get_fixture_channel_definition(array_id, &stack.to_string(), light, index + 1, e)?,
+                };
+                let channel = match component {
+                    Some(component) => ChannelDefinition::Single(channel.get_component(component).ok_or_else(|| {
+                        DmxArrayError::ArrayLightsInvalidComponent(array_id.to_string(), stack.to_string(), entry.to_string(), channel.channel_type(), component)
+                    })?),
+                    None => channel,
+                };
+
+                add_light_channel(result, universe_id, channel, component);
             }
         }
 
         Ok(())
     }
 
-    pub (super) fn static_get_array_light_channels(array_id: &str, array: &DmxArray, lights_list: &str) -> Result<Vec<UniverseChannelDefinitions>, DmxArrayError> {
-        let mut result = HashMap::<String, UniverseChannelDefinitions>::new();
-        let mut stack = ExpansionStack::new();
+    // Lights of a term of a set expression (in the universe selected by the entries before the expression)
+    fn get_lights_expression_term(&self, array_id: &str, array: &DmxArray, term: &str, universe_id: &str, stack: &mut ExpansionStack, expander: LightsValueExpander) -> Result<Vec<UniverseChannelDefinitions>, DmxArrayError> {
+        let lights_list = if universe_id == array.universe_id { term.to_string() } else { format!("${universe_id},{term}") };
+        let mut lights = Vec::new();
+
+        self.do_get_array_light_channels(array_id, array, &lights_list, &mut lights, stack, expander)?;
+        Ok(lights)
+    }
+
+    pub (super) fn get_definition_light_channels(&self, array_id: &str, array: &DmxArray, lights_list: &str, expander: LightsValueExpander) -> Result<Vec<UniverseChannelDefinitions>, DmxArrayError> {
+        let mut result = Vec::<UniverseChannelDefinitions>::new();
+        let mut stack = ExpansionStack::new(array.max_lights_nesting.unwrap_or(DEFAULT_MAX_LIGHTS_NESTING));
 
         stack.push(lights_list.to_string());
-        Self::static_do_get_array_light_channels(array_id, array, lights_list, &mut result, &mut stack)?;
+        self.do_get_array_light_channels(array_id, array, lights_list, &mut result, &mut stack, expander)?;
         stack.pop();
 
-        Ok(result.into_values().collect())
+        Ok(result)
+    }
+
+    // Resolve the array light group limits into per channel limits (parametric light groups are not limited)
+    pub (super) fn get_definition_limits(&self, array_id: &str, array: &DmxArray) -> Result<ChannelLimits, DmxArrayError> {
+        let mut limits = ChannelLimits::default();
+
+        for (group_name, limit) in array.limits.iter() {
+            if !array.lights.contains_key(group_name) {
+                return Err(DmxArrayError::ArrayLimitGroupNotFound(array_id.to_string(), group_name.to_string()).into());
+            }
+
+            let limit = limit.parse::<TargetValue>().map_err(|e| DmxArrayError::ArrayInvalidLimit(array_id.to_string(), group_name.to_string(), e.to_string()))?;
+
+            for universe_channels in self.get_definition_light_channels(array_id, array, &format!("@{group_name}"), None)? {
+                for channel in universe_channels.channels.iter() {
+                    limits.add(&universe_channels.universe_id, channel, &limit);
+                }
+            }
+        }
+
+        Ok(limits)
+    }
+
+    // Resolve the array light group dimming factors into per light factors (parametric light groups are not dimmed)
+    pub (super) fn get_definition_group_dimming(&self, array_id: &str, array: &DmxArray) -> Result<ChannelDimming, DmxArrayError> {
+        let mut dimming = ChannelDimming::default();
+
+        for (group_name, factor) in array.group_dimming.iter() {
+            if !array.lights.contains_key(group_name) {
+                return Err(DmxArrayError::ArrayGroupDimmingGroupNotFound(array_id.to_string(), group_name.to_string()).into());
+            }
+
+            if *factor > DIMMING_AMOUNT_MAX {
+                return Err(DmxArrayError::ArrayInvalidGroupDimming(array_id.to_string(), group_name.to_string(), *factor, DIMMING_AMOUNT_MAX).into());
+            }
+
+            for universe_channels in self.get_definition_light_channels(array_id, array, &format!("@{group_name}"), None)? {
+                for channel in universe_channels.channels.iter() {
+                    dimming.add(&universe_channels.universe_id, channel, *factor);
+                }
+            }
+        }
+
+        Ok(dimming)
+    }
+
+    pub(super) fn get_array_limits(&self, array_id: &str) -> Result<Arc<ChannelLimits>, DmxArrayError> {
+        self.get_array(array_id)?;
+        Ok(self.limits.get(array_id).cloned().unwrap_or_default())
     }
 
     pub fn get_array_light_channels(&self, array_id: &str, lights_list: &str) -> Result<Vec<UniverseChannelDefinitions>, DmxArrayError> {
         let array = self.get_array(array_id)?;
-        Self::static_get_array_light_channels(array_id, array, lights_list)
+        let array_id_arc: Arc<str> = Arc::from(array_id);
+        let expand = |lights_list: &str| self.expand_values(array_id_arc.clone(), lights_list);
+
+        self.get_definition_light_channels(array_id, array, lights_list, Some(&expand))
     }
 }
@@ -1,23 +1,57 @@
 use log::info;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::{select, sync::mpsc::Receiver};
+use tokio::select;
 use tokio_util::sync::CancellationToken;
-use error_stack::Result;
+use error_stack::{Report, Result};
 
 use super::error::DmxArrayError;
-use crate::defs::{DmxArray, EffectNodeDefinition, SymbolTable};
-use crate::messages::ToArrayManagerMessage;
+use super::effects::{CompiledEffect, CompiledEffectKey};
+use crate::defs::{self, ArrayEpoch, ArrayState, DimmingAmount, DmxArray, EffectDefinition, EffectNodeDefinition, EffectUsage, FixtureDefinition, SymbolTable};
+use crate::definition_hash::get_definition_hash;
+use crate::dmx::{ChannelDimming, ChannelLimits};
+use crate::manager_channel::ManagerReceiver;
+use crate::messages::{RemovedArrays, ToArrayManagerMessage, ValidationProblems};
 
 #[derive(Debug)]
 pub struct ArrayManager {
     pub(super) arrays: HashMap<Arc<str>, Box<DmxArray>>,
-    pub(super) effects: HashMap<Arc<str>, EffectNodeDefinition>,
+    pub(super) effects: HashMap<Arc<str>, EffectDefinition>,
+    pub(super) fixtures: HashMap<Arc<str>, FixtureDefinition>,
     pub(super) global_values: SymbolTable,
     pub(super) values: HashMap<Arc<str>, SymbolTable>,
+    pub(super) limits: HashMap<Arc<str>, Arc<ChannelLimits>>,
+    pub(super) group_dimming: HashMap<Arc<str>, Arc<ChannelDimming>>,
+    pub(super) states: HashMap<Arc<str>, ArrayState>,     // Last On/Off/Dim command applied to each array
+    pub(super) epochs: HashMap<Arc<str>, ArrayEpoch>,     // Incremented when array is added or removed
     pub(super) default_on_effect: EffectNodeDefinition,
     pub(super) default_off_effect: EffectNodeDefinition,
     pub(super) default_dim_effect: EffectNodeDefinition,
+    pub(super) default_universe_id: Option<String>,    // Universe used by arrays that do not specify universe_id
+    pub(super) compiled_effects: RefCell<HashMap<CompiledEffectKey, CompiledEffect>>,
+    pub(super) compiled_effect_hits: Cell<u64>,       // Runtime nodes instantiated from a cached compiled effect
+    pub(super) compiled_effect_misses: Cell<u64>,     // Effects compiled from their definition
+    pub(super) values_epochs: HashMap<Arc<str>, u64>,     // Array ID -> incremented when a value read by the array effects changes
+    pub(super) unresolved_effects: HashMap<Arc<str>, Vec<Arc<str>>>,     // Array ID -> referenced effects that are not defined (yet)
+    pub(super) last_effects: HashMap<Arc<str>, LastEffect>,     // Array ID -> last effect built for the whole array
+    pub(super) dimming_presets: HashMap<Arc<str>, defs::DimmingPresetDefinition>,     // Named dimming amounts usable by commands
+    pub(super) array_templates: HashMap<Arc<str>, serde_json::Value>,      // Array definitions with {parameter} placeholders
+}
+
+// Array with its defaults applied and the problems found when verifying it (see ArrayManager::prepare_array)
+struct PreparedArray {
+    array: Box<DmxArray>,
+    limits: ChannelLimits,
+    group_dimming: ChannelDimming,
+    problems: Vec<Report<DmxArrayError>>,
+}
+
+// Effect last built for a whole array, started again when a retrigger value it read changes
+#[derive(Debug)]
+pub(super) struct LastEffect {
+    pub(super) effect: defs::RetriggerEffect,
+    pub(super) value_names: Vec<Arc<str>>,
 }
 
 impl ArrayManager {
@@ -54,29 +88,310 @@ impl ArrayManager {
         Self {
             arrays: HashMap::new(),
             effects: HashMap::new(),
+            fixtures: HashMap::new(),
             global_values: HashMap::new(),
             values: HashMap::new(),
+            limits: HashMap::new(),
+            group_dimming: HashMap::new(),
+            states: HashMap::new(),
+            epochs: HashMap::new(),
             default_on_effect,
             default_off_effect,
             default_dim_effect,
+            default_universe_id: None,
+            compiled_effects: RefCell::new(HashMap::new()),
+            compiled_effect_hits: Cell::new(0),
+            compiled_effect_misses: Cell::new(0),
+            values_epochs: HashMap::new(),
+            unresolved_effects: HashMap::new(),
+            last_effects: HashMap::new(),
+            dimming_presets: HashMap::new(),
+            array_templates: HashMap::new(),
+        }
+    }
+
+    pub fn with_default_universe(mut self, default_universe_id: Option<String>) -> Self {
+        self.default_universe_id = default_universe_id;
+        self
+    }
+
+    // Apply the array defaults and verify it, the problems found do not prevent adding a non strict array
+    fn prepare_array(&self, array_id: &Arc<str>, mut array: Box<DmxArray>) -> Result<PreparedArray, DmxArrayError> {
+        defs::validate_id(array_id).map_err(|e| DmxArrayError::InvalidArrayId(array_id.clone(), e))?;
+
+        if array.description.is_empty() {
+            array.description = array_id.to_string();
+        }
+
+        if array.universe_id.is_empty() {
+            array.universe_id = self
+                .default_universe_id
+                .clone()
+                .ok_or_else(|| DmxArrayError::ArrayMissingUniverseId(array_id.clone()))?;
+        }
+
+        if let Some(cycle) = self.find_pre_cycle(array_id, &array) {
+            return Err(DmxArrayError::ArrayPreCycle(array_id.clone(), cycle.join(" -> ")).into());
+        }
+
+        let mut problems = self.verify_array(array_id, &array);
+        let limits = self.get_definition_limits(array_id, &array).unwrap_or_else(|e| {
+            problems.push(e);
+            ChannelLimits::default()
+        });
+        let group_dimming = self.get_definition_group_dimming(array_id, &array).unwrap_or_else(|e| {
+            problems.push(e);
+            ChannelDimming::default()
+        });
+
+        Ok(PreparedArray { array, limits, group_dimming, problems })
+    }
+
+    // Arrays whose pre commands lead back to the array (the defined arrays have no cycles, so a cycle must go through it)
+    fn find_pre_cycle(&self, array_id: &Arc<str>, array: &DmxArray) -> Option<Vec<Arc<str>>> {
+        fn visit<'a>(array_manager: &'a ArrayManager, pre: &'a [defs::PreCommand], array_id: &Arc<str>, path: &mut Vec<Arc<str>>, visited: &mut HashSet<&'a str>) -> bool {
+            for pre_command in pre {
+                path.push(pre_command.array_id.clone());
+
+                if pre_command.array_id == *array_id {
+                    return true;
+                }
+
+                if visited.insert(pre_command.array_id.as_ref()) {
+                    if let Some(pre_array) = array_manager.arrays.get(&pre_command.array_id) {
+                        if visit(array_manager, &pre_array.pre, array_id, path, visited) {
+                            return true;
+                        }
+                    }
+                }
+
+                path.pop();
+            }
+
+            false
         }
+
+        let mut path = vec![array_id.clone()];
+        visit(self, &array.pre, array_id, &mut path, &mut HashSet::new()).then_some(path)
     }
 
+    // Add (or replace) array, non strict arrays are added even if they have problems, which are returned as warnings
     pub fn add_array(
         &mut self,
         array_id: Arc<str>,
         array: Box<DmxArray>,
-    ) -> Result<(), DmxArrayError> {
-        self.verify_array(&array_id, &array)?;
+    ) -> Result<Vec<String>, DmxArrayError> {
+        let PreparedArray { array, limits, group_dimming, mut problems } = self.prepare_array(&array_id, array)?;
+
+        if array.strict {
+            if let Some(e) = problems.into_iter().next() {
+                return Err(e);
+            }
+            problems = Vec::new();
+        }
+
+        let fallback_warnings = self.get_default_effect_fallback_warnings(&array_id, &array);
+
+        self.invalidate_compiled_effects();
+        self.limits.insert(array_id.clone(), Arc::new(limits));
+        self.group_dimming.insert(array_id.clone(), Arc::new(group_dimming));
+        *self.epochs.entry(array_id.clone()).or_default() += 1;
+        self.last_effects.remove(&array_id);
         self.arrays.insert(array_id, array);
-        Ok(())
+        self.update_unresolved_effects();
+        Ok(problems.iter().map(|e| e.to_string()).chain(fallback_warnings).collect())
+    }
+
+    // Same checks as add_array without adding the array: errors prevent adding it (all the problems of a strict array),
+    // warnings are problems of a non strict array and references to effects that are not defined
+    pub(super) fn validate_array(&self, array_id: Arc<str>, array: Box<DmxArray>) -> ValidationProblems {
+        let prepared_array = match self.prepare_array(&array_id, array) {
+            Ok(prepared_array) => prepared_array,
+            Err(e) => return (vec![e.to_string()], Vec::new()),
+        };
+        let problems = prepared_array.problems.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        let mut warnings = self
+            .get_array_unresolved_effects(&prepared_array.array)
+            .iter()
+            .map(|effect_id| format!("Array {array_id} refers to effect {effect_id} which is not defined"))
+            .collect::<Vec<_>>();
+
+        if prepared_array.array.strict {
+            (problems, warnings)
+        } else {
+            warnings.splice(0..0, problems);
+            (Vec::new(), warnings)
+        }
     }
 
     pub fn remove_array(&mut self, name: Arc<str>) -> Result<(), DmxArrayError> {
+        self.invalidate_compiled_effects();
         self.arrays.remove(&name);
+        self.values.remove(&name);
+        self.limits.remove(&name);
+        self.group_dimming.remove(&name);
+        self.states.remove(&name);
+        self.unresolved_effects.remove(&name);
+        self.last_effects.remove(&name);
+        *self.epochs.entry(name).or_default() += 1;
+        Ok(())
+    }
+
+    // Reset command: the arrays are removed (so effects built from them are not started anymore) and the effects,
+    // fixtures, values, dimming presets and templates are cleared. Returns the new epochs of the removed arrays
+    pub(super) fn clear_all(&mut self) -> Result<RemovedArrays, DmxArrayError> {
+        let mut array_ids = self.arrays.keys().cloned().collect::<Vec<_>>();
+        let mut epochs = Vec::new();
+
+        array_ids.sort();
+        for array_id in array_ids {
+            self.remove_array(array_id.clone())?;
+            epochs.push((array_id.clone(), self.get_array_epoch(&array_id)));
+        }
+
+        self.effects.clear();
+        self.fixtures.clear();
+        self.global_values.clear();
+        self.values.clear();
+        self.dimming_presets.clear();
+        self.array_templates.clear();
+        self.invalidate_compiled_effects();
+        Ok(epochs)
+    }
+
+    // Move the array with its runtime state (values, state and last effect) to a new ID. The epochs of both IDs are
+    // incremented, so effects built for the old ID are not started anymore
+    pub(super) fn rename_array(&mut self, from: Arc<str>, to: Arc<str>) -> Result<(), DmxArrayError> {
+        defs::validate_id(&to).map_err(|e| DmxArrayError::InvalidArrayId(to.clone(), e))?;
+
+        if self.arrays.contains_key(&to) {
+            return Err(DmxArrayError::ArrayAlreadyDefined(to).into());
+        }
+
+        let mut array = self.arrays.remove(&from).ok_or_else(|| DmxArrayError::ArrayNotFound(from.clone()))?;
+
+        // Description defaulted to the array ID
+        if array.description == from.as_ref() {
+            array.description = to.to_string();
+        }
+
+        fn move_entry<T>(map: &mut HashMap<Arc<str>, T>, from: &Arc<str>, to: &Arc<str>) {
+            if let Some(value) = map.remove(from) {
+                map.insert(to.clone(), value);
+            }
+        }
+
+        self.invalidate_compiled_effects();
+        move_entry(&mut self.values, &from, &to);
+        move_entry(&mut self.limits, &from, &to);
+        move_entry(&mut self.group_dimming, &from, &to);
+        move_entry(&mut self.states, &from, &to);
+        move_entry(&mut self.unresolved_effects, &from, &to);
+
+        if let Some(mut last_effect) = self.last_effects.remove(&from) {
+            last_effect.effect.array_id = to.clone();
+            self.last_effects.insert(to.clone(), last_effect);
+        }
+
+        *self.epochs.entry(from).or_default() += 1;
+        *self.epochs.entry(to.clone()).or_default() += 1;
+        self.arrays.insert(to, array);
+        Ok(())
+    }
+
+    pub(super) fn get_array_epoch(&self, array_id: &str) -> ArrayEpoch {
+        self.epochs.get(array_id).copied().unwrap_or_default()
+    }
+
+    pub(super) fn set_array_state(&mut self, array_id: Arc<str>, usage: EffectUsage, dimming_amount: Option<DimmingAmount>) -> Result<(), DmxArrayError> {
+        let dimming_amount = self.resolve_dimming_amount(&array_id, &usage, dimming_amount)?;
+        self.states.insert(array_id, ArrayState { usage, dimming_amount });
         Ok(())
     }
 
+    // Dimming amount of the command, otherwise (except for Off) the array default, otherwise the maximum
+    pub(super) fn resolve_dimming_amount(&self, array_id: &str, usage: &EffectUsage, dimming_amount: Option<DimmingAmount>) -> Result<DimmingAmount, DmxArrayError> {
+        let array = self.get_array(array_id)?;
+
+        Ok(match (dimming_amount, usage) {
+            (Some(dimming_amount), _) => dimming_amount,
+            (None, EffectUsage::Off) => defs::DIMMING_AMOUNT_MAX,
+            (None, _) => array.default_dimming_amount.unwrap_or(defs::DIMMING_AMOUNT_MAX),
+        })
+    }
+
+    pub(super) fn set_dimming_preset(&mut self, name: Arc<str>, definition: Option<defs::DimmingPresetDefinition>) -> Result<(), DmxArrayError> {
+        match definition {
+            None => {
+                self.dimming_presets.remove(&name);
+            }
+            Some(definition) => {
+                if definition.amount > defs::DIMMING_AMOUNT_MAX {
+                    return Err(DmxArrayError::InvalidDimmingPreset(name, definition.amount, defs::DIMMING_AMOUNT_MAX).into());
+                }
+
+                self.dimming_presets.insert(name, definition);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Dimming amount given by a command as a number or as the name of a dimming preset
+    pub(super) fn resolve_command_dimming_amount(&self, dimming_amount: Option<&defs::DimmingAmountOrPreset>) -> Result<Option<DimmingAmount>, DmxArrayError> {
+        match dimming_amount {
+            None => Ok(None),
+            Some(defs::DimmingAmountOrPreset::Amount(dimming_amount)) => Ok(Some(*dimming_amount)),
+            Some(defs::DimmingAmountOrPreset::Preset(name)) => match self.dimming_presets.get(name) {
+                Some(definition) => Ok(Some(definition.amount)),
+                None => {
+                    let mut names = self.dimming_presets.keys().map(|name| name.to_string()).collect::<Vec<_>>();
+                    names.sort();
+                    let names = if names.is_empty() { "none".to_string() } else { names.join(", ") };
+
+                    Err(DmxArrayError::DimmingPresetNotFound(name.clone(), names).into())
+                }
+            },
+        }
+    }
+
+    // Commands to run before turning the array On, pre commands of arrays turned On by them come first
+    pub(super) fn get_pre_commands(&self, array_id: &str) -> Result<Vec<defs::PreCommand>, DmxArrayError> {
+        let mut pre_commands = Vec::new();
+
+        for pre_command in self.get_array(array_id)?.pre.iter() {
+            if pre_command.usage == EffectUsage::On && self.arrays.contains_key(&pre_command.array_id) {
+                pre_commands.extend(self.get_pre_commands(&pre_command.array_id)?);
+            }
+
+            pre_commands.push(pre_command.clone());
+        }
+
+        Ok(pre_commands)
+    }
+
+    pub(super) fn get_array_state(&self, array_id: &str) -> Result<Option<ArrayState>, DmxArrayError> {
+        self.get_array(array_id)?;
+        Ok(self.states.get(array_id).copied())
+    }
+
+    pub(super) fn get_linked_effects(&self, array_id: &str) -> Result<Vec<Arc<str>>, DmxArrayError> {
+        Ok(self.get_array(array_id)?.linked_effects.clone())
+    }
+
+    // Effects of arrays whose last effect read the value
+    pub(super) fn get_retrigger_effects(&self, value_name: &str) -> Vec<defs::RetriggerEffect> {
+        let mut effects = self
+            .last_effects
+            .values()
+            .filter(|last_effect| last_effect.value_names.iter().any(|name| name.as_ref() == value_name))
+            .map(|last_effect| last_effect.effect.clone())
+            .collect::<Vec<_>>();
+
+        effects.sort_by(|a, b| a.array_id.cmp(&b.array_id));
+        effects
+    }
+
     pub(super) fn get_array(&self, array_id: &str) -> Result<&DmxArray, DmxArrayError> {
         match self.arrays.get(array_id) {
             None => Err(DmxArrayError::ArrayNotFound(Arc::from(array_id)).into()),
@@ -84,18 +399,108 @@ impl ArrayManager {
         }
     }
 
+    pub(super) fn get_diagnostics(&self, include_values: bool) -> defs::ArrayManagerDiagnostics {
+        let mut effects = self.effects.keys().cloned().collect::<Vec<_>>();
+        effects.sort();
+
+        defs::ArrayManagerDiagnostics {
+            arrays: self.arrays.iter().map(|(array_id, array)| (array_id.clone(), defs::ArrayDiagnostics {
+                universe_id: array.universe_id.clone(),
+                epoch: self.get_array_epoch(array_id),
+                values: self.values.get(array_id)
+                    .map(|values| defs::DiagnosticsValue::from_symbol_table(values, include_values))
+                    .unwrap_or_default(),
+                usage_effects: [EffectUsage::On, EffectUsage::Off, EffectUsage::Dim]
+                    .iter()
+                    .filter_map(|usage| self.resolve_usage_effect(usage, array_id, None).ok())
+                    .collect(),
+            })).collect(),
+            global_values: defs::DiagnosticsValue::from_symbol_table(&self.global_values, include_values),
+            effects,
+            compiled_effects: self.compiled_effects.borrow().len(),
+            compiled_effect_hits: self.compiled_effect_hits.get(),
+            compiled_effect_misses: self.compiled_effect_misses.get(),
+        }
+    }
+
+    // Runtime state kept for arrays that are not defined (see soak tests)
+    #[cfg(test)]
+    pub(crate) fn find_leaks(&self) -> Vec<String> {
+        let array_ids = [
+            ("values", self.values.keys().collect::<Vec<_>>()),
+            ("limits", self.limits.keys().collect()),
+            ("group dimming", self.group_dimming.keys().collect()),
+            ("state", self.states.keys().collect()),
+            ("unresolved effects", self.unresolved_effects.keys().collect()),
+            ("last effect", self.last_effects.keys().collect()),
+        ];
+
+        array_ids
+            .into_iter()
+            .flat_map(|(kind, array_ids)| array_ids.into_iter().map(move |array_id| (kind, array_id)))
+            .filter(|(_, array_id)| !self.arrays.contains_key(*array_id))
+            .map(|(kind, array_id)| format!("{kind} of removed array {array_id}"))
+            .collect()
+    }
+
     fn handle_message(&mut self, message: ToArrayManagerMessage) {
         match message {
             ToArrayManagerMessage::AddArray(array_id, array, reply_tx) => {
-                reply_tx.send(self.add_array(array_id, array)).unwrap()
+                let result = self.add_array(array_id.clone(), array).map(|warnings| (self.get_array_epoch(&array_id), warnings));
+                reply_tx.send(result).unwrap()
+            }
+
+            ToArrayManagerMessage::ResolveEffect(array_id, usage, effect_id, reply_tx) => {
+                reply_tx.send(self.resolve_usage_effect(&usage, &array_id, effect_id.as_ref())).unwrap()
+            }
+
+            ToArrayManagerMessage::ValidateArray(array_id, array, reply_tx) => {
+                reply_tx.send(self.validate_array(array_id, array)).unwrap()
+            }
+
+            ToArrayManagerMessage::ValidateEffect(effect_id, effect, reply_tx) => {
+                reply_tx.send(self.validate_effect(effect_id, &effect)).unwrap()
             }
 
             ToArrayManagerMessage::RemoveArray(array_id, reply_tx) => {
-                reply_tx.send(self.remove_array(array_id)).unwrap()
+                let result = self.remove_array(array_id.clone()).map(|_| self.get_array_epoch(&array_id));
+                reply_tx.send(result).unwrap()
+            }
+
+            ToArrayManagerMessage::ClearAll(reply_tx) => reply_tx.send(self.clear_all()).unwrap(),
+
+            ToArrayManagerMessage::RenameArray(from, to, reply_tx) => {
+                let result = self.rename_array(from.clone(), to.clone()).map(|_| (self.get_array_epoch(&from), self.get_array_epoch(&to)));
+                reply_tx.send(result).unwrap()
+            }
+
+            ToArrayManagerMessage::GetArrayLimits(array_id, reply_tx) => {
+                reply_tx.send(self.get_array_limits(&array_id)).unwrap()
+            }
+
+            ToArrayManagerMessage::SetArrayState(array_id, usage, dimming_amount, reply_tx) => {
+                reply_tx.send(self.set_array_state(array_id, usage, dimming_amount)).unwrap()
+            }
+
+            ToArrayManagerMessage::GetPreCommands(array_id, reply_tx) => {
+                reply_tx.send(self.get_pre_commands(&array_id)).unwrap()
+            }
+
+            ToArrayManagerMessage::GetLinkedEffects(array_id, reply_tx) => {
+                reply_tx.send(self.get_linked_effects(&array_id)).unwrap()
             }
 
-            ToArrayManagerMessage::AddGlobalValue(value_name, value, reply_tx) => {
-                reply_tx.send(self.set_global_value(value_name, &value)).unwrap()
+            ToArrayManagerMessage::GetArrayState(array_id, reply_tx) => {
+                reply_tx.send(self.get_array_state(&array_id)).unwrap()
+            }
+
+            ToArrayManagerMessage::AddGlobalValue(value_name, value, retrigger, reply_tx) => {
+                let changed = self.global_values.get(&value_name) != Some(&value);
+                let result = self.set_global_value(value_name.clone(), value).map(|_| {
+                    if retrigger && changed { self.get_retrigger_effects(&value_name) } else { Vec::new() }
+                });
+
+                reply_tx.send(result).unwrap()
             }
 
             ToArrayManagerMessage::InitializeArrayValues(array_id, values, reply_tx) => {
@@ -106,35 +511,112 @@ impl ArrayManager {
                 reply_tx.send(self.remove_global_value(&value_name)).unwrap()
             }
 
+            ToArrayManagerMessage::SetDimmingPreset(name, definition, reply_tx) => {
+                reply_tx.send(self.set_dimming_preset(name, definition)).unwrap()
+            }
+
+            ToArrayManagerMessage::ResolveDimmingAmount(dimming_amount, reply_tx) => {
+                reply_tx.send(self.resolve_command_dimming_amount(dimming_amount.as_ref())).unwrap()
+            }
+
+            ToArrayManagerMessage::GetDimmingPresets(reply_tx) => {
+                reply_tx.send(self.dimming_presets.iter().map(|(name, definition)| (name.clone(), definition.clone())).collect()).unwrap()
+            }
+
             ToArrayManagerMessage::AddEffect(effect_id, effect, reply_tx) => {
                 reply_tx.send(self.add_effect(effect_id, effect)).unwrap()
             }
 
-            ToArrayManagerMessage::RemoveEffect(effect_id, reply_tx) => {
-                reply_tx.send(self.remove_effect(&effect_id)).unwrap()
+            ToArrayManagerMessage::RemoveEffect(effect_id, force, reply_tx) => {
+                reply_tx.send(self.remove_effect(&effect_id, force)).unwrap()
+            }
+
+            ToArrayManagerMessage::AddFixture(fixture_name, fixture, reply_tx) => {
+                reply_tx.send(self.add_fixture(fixture_name, fixture)).unwrap()
+            }
+
+            ToArrayManagerMessage::RemoveFixture(fixture_name, reply_tx) => {
+                reply_tx.send(self.remove_fixture(&fixture_name)).unwrap()
+            }
+
+            ToArrayManagerMessage::GetEffects(reply_tx) => {
+                reply_tx.send(self.get_effects()).unwrap()
+            }
+
+            ToArrayManagerMessage::GetArrayDefinitionHash(array_id, reply_tx) => {
+                reply_tx.send(self.arrays.get(&array_id).map(get_definition_hash)).unwrap()
+            }
+
+            ToArrayManagerMessage::GetEffectDefinitionHash(effect_id, reply_tx) => {
+                reply_tx.send(self.effects.get(&effect_id).map(get_definition_hash)).unwrap()
+            }
+
+            ToArrayManagerMessage::GetDiagnostics(include_values, reply_tx) => {
+                reply_tx.send(self.get_diagnostics(include_values)).unwrap()
+            }
+
+            ToArrayManagerMessage::GetUnresolvedEffects(reply_tx) => {
+                reply_tx.send(self.get_unresolved_effects()).unwrap()
+            }
+
+            ToArrayManagerMessage::GetPatch(reply_tx) => {
+                reply_tx.send(self.get_patch()).unwrap()
+            }
+
+            ToArrayManagerMessage::GetInlineEffectRuntime(lights, effect, dimming_amount, reply_tx) => {
+                reply_tx.send(self.get_inline_effect_runtime(&lights, &effect, dimming_amount)).unwrap()
+            }
+
+            ToArrayManagerMessage::SetArrayTemplate(template_name, template, reply_tx) => {
+                reply_tx.send(self.set_array_template(template_name, template)).unwrap()
+            }
+
+            ToArrayManagerMessage::InstantiateArrayTemplate(instance, reply_tx) => {
+                reply_tx.send(self.instantiate_array_template(&instance)).unwrap()
+            }
+
+            ToArrayManagerMessage::GetInstantOffRuntime(array_id, lights, reply_tx) => {
+                let result = self.get_instant_off_runtime(&array_id, lights.as_deref())
+                    .map(|node| (node, self.get_array_epoch(&array_id), Vec::new()));
+
+                reply_tx.send(result).unwrap()
             }
 
             ToArrayManagerMessage::GetEffectRuntime(
                 array_id,
                 effect_usage,
                 effect_id,
+                lights,
                 dimming_amount,
                 reply_tx,
-            ) => reply_tx
-                .send(self.get_usage_effect_runtime(
-                    &effect_usage,
-                    &array_id,
-                    effect_id.as_ref(),
-                    dimming_amount,
-                ))
-                .unwrap(),
+            ) => {
+                let result = self.resolve_dimming_amount(&array_id, &effect_usage, dimming_amount)
+                    .and_then(|resolved_dimming_amount| self.get_group_effect_runtime(
+                        &effect_usage,
+                        &array_id,
+                        effect_id.as_ref(),
+                        lights.as_deref(),
+                        resolved_dimming_amount,
+                    ));
+
+                let result = result.map(|(node, warnings, value_names)| {
+                    if lights.is_none() {
+                        let effect = defs::RetriggerEffect { array_id: array_id.clone(), usage: effect_usage, effect_id, dimming_amount };
+                        self.last_effects.insert(array_id.clone(), LastEffect { effect, value_names });
+                    }
+
+                    (node, self.get_array_epoch(&array_id), warnings)
+                });
+
+                reply_tx.send(result).unwrap()
+            }
         }
     }
 
     pub async fn run(
         &mut self,
         cancel: CancellationToken,
-        mut receiver: Receiver<ToArrayManagerMessage>,
+        mut receiver: ManagerReceiver<ToArrayManagerMessage>,
     ) {
         loop {
             select! {
@@ -6,6 +6,9 @@ pub mod lights;
 mod scope;
 mod values;
 mod effects;
+mod fixtures;
+mod patch;
+mod templates;
 #[cfg(test)]
 mod tests;
 
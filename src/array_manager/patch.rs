@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use super::lights::is_parametric_lights;
+use super::verify::{get_channel_usages, ChannelUsage};
+use super::ArrayManager;
+use crate::defs;
+
+// Usage of a channel by one array
+#[derive(Default)]
+struct ArrayChannelUsage {
+    usages: Vec<ChannelUsage>,      // Different usages by the array light groups (more than one is a conflict)
+    groups: Vec<String>,
+}
+
+impl ArrayManager {
+    // Channel usage of all arrays, found by expanding every (non parametric) light group. Light groups that cannot be
+    // expanded (e.g. problems of non strict arrays) are skipped
+    pub(super) fn get_patch(&self) -> defs::Patch {
+        // Universe -> channel -> array -> usage
+        let mut channels: BTreeMap<String, BTreeMap<u16, BTreeMap<Arc<str>, ArrayChannelUsage>>> = BTreeMap::new();
+
+        for (array_id, array) in self.arrays.iter() {
+            // The all group first, so its usage is the reported role when light groups disagree
+            let mut light_groups = array.lights.iter().filter(|(_, lights_list)| !is_parametric_lights(lights_list)).collect::<Vec<_>>();
+            light_groups.sort_by_key(|(light_group_name, _)| (light_group_name.as_str() != "all", light_group_name.as_str()));
+
+            for (light_group_name, lights_list) in light_groups {
+                let Ok(lights) = self.get_definition_light_channels(array_id, array, lights_list, None) else {
+                    continue;
+                };
+
+                for (universe_id, channel, usage) in get_channel_usages(&lights) {
+                    let array_usage = channels
+                        .entry(universe_id.to_string())
+                        .or_default()
+                        .entry(channel)
+                        .or_default()
+                        .entry(array_id.clone())
+                        .or_default();
+
+                    if !array_usage.usages.contains(&usage) {
+                        array_usage.usages.push(usage);
+                    }
+
+                    if !array_usage.groups.contains(light_group_name) {
+                        array_usage.groups.push(light_group_name.clone());
+                    }
+                }
+            }
+        }
+
+        let universes = channels.into_iter().map(|(universe_id, universe_channels)| {
+            let entries = universe_channels.into_iter().flat_map(|(channel, array_usages)| {
+                let array_ids = array_usages.keys().cloned().collect::<Vec<_>>();
+
+                array_usages.into_iter().map(move |(array_id, mut array_usage)| {
+                    let mut conflicts = Vec::new();
+
+                    if array_usage.usages.len() > 1 {
+                        let roles = array_usage.usages.iter().map(|usage| usage.get_role()).collect::<Vec<_>>();
+                        conflicts.push(format!("used as {}", roles.join(" and ")));
+                    }
+
+                    let other_arrays = array_ids.iter().filter(|other| **other != array_id).map(|other| other.as_ref()).collect::<Vec<_>>();
+
+                    if !other_arrays.is_empty() {
+                        conflicts.push(format!("also used by {}", other_arrays.join(", ")));
+                    }
+
+                    array_usage.groups.sort();
+
+                    defs::PatchEntry {
+                        channel,
+                        array_id,
+                        groups: array_usage.groups,
+                        role: array_usage.usages[0].get_role(),
+                        conflict: (!conflicts.is_empty()).then(|| conflicts.join("; ")),
+                    }
+                })
+            }).collect();
+
+            (universe_id, entries)
+        }).collect();
+
+        defs::Patch { universes }
+    }
+}
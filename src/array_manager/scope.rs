@@ -1,11 +1,14 @@
 
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::sync::Arc;
 use error_stack::Result;
 
 use super::manager::ArrayManager;
+use super::values::get_value_names;
 use super::DmxArrayError;
-use crate::dmx::UniverseChannelDefinitions;
-use crate::defs::DimmingAmount;
+use crate::dmx::{ChannelDimming, ChannelLimits, UniverseChannelDefinitions};
+use crate::defs::{DimmingAmount, SymbolTable, SymbolValue};
 
 #[derive(Debug)]
 pub struct Scope<'a> {
@@ -13,6 +16,10 @@ pub struct Scope<'a> {
     pub array_id: Arc<str>,
     pub effect_id: Option<Arc<str>>,
     pub dimming_amount: DimmingAmount,
+    pub lights_override: Option<String>,    // Light group (@group) effects apply to instead of @all
+    effect_defaults: Option<&'a SymbolTable>,       // Defaults of the effect, used for values that are not found elsewhere
+    warnings: RefCell<Vec<String>>,         // Problems that do not prevent building the runtime node
+    value_names: RefCell<HashSet<Arc<str>>>,    // Values read while building the runtime node (see ValueDefinition retrigger)
 }
 
 impl std::fmt::Display for Scope<'_> {
@@ -29,8 +36,8 @@ impl std::fmt::Display for Scope<'_> {
     }
 }
 
-impl Scope<'_> {
-    pub fn new<'a>(array_manager: &'a ArrayManager, array_id: Arc<str>, effect_id: Option<&Arc<str>>, dimming_amount: DimmingAmount) -> Result<Scope<'a>, DmxArrayError> {
+impl<'a> Scope<'a> {
+    pub fn new(array_manager: &'a ArrayManager, array_id: Arc<str>, effect_id: Option<&Arc<str>>, dimming_amount: DimmingAmount) -> Result<Scope<'a>, DmxArrayError> {
         let array = array_manager.arrays.get(&array_id);
 
         if array.is_none() {
@@ -42,14 +49,74 @@ impl Scope<'_> {
             array_id,
             effect_id: effect_id.cloned(),
             dimming_amount,
+            lights_override: None,
+            effect_defaults: None,
+            warnings: RefCell::new(Vec::new()),
+            value_names: RefCell::new(HashSet::new()),
         })
     }
 
+    // Restrict effects to a light group of the array, effect nodes whose lights are @all are applied to this group
+    pub fn with_lights_override(mut self, lights: Option<&str>) -> Result<Self, DmxArrayError> {
+        if let Some(lights) = lights.map(|lights| lights.trim()) {
+            let array = self.array_manager.get_array(&self.array_id)?;
+
+            match lights.strip_prefix('@') {
+                Some(group) if array.lights.contains_key(group) => self.lights_override = Some(lights.to_string()),
+                _ => return Err(DmxArrayError::ArrayLightGroupNotFound(self.array_id.clone(), lights.to_string()).into()),
+            }
+        }
+
+        Ok(self)
+    }
+
+    pub fn with_effect_defaults(mut self, effect_defaults: Option<&'a SymbolTable>) -> Self {
+        self.effect_defaults = effect_defaults;
+        self
+    }
+
+    // Lights of an effect node, @all is replaced by the lights override (lights naming other groups are kept)
+    pub fn get_node_lights<'b>(&'b self, lights_list: &'b str) -> &'b str {
+        match &self.lights_override {
+            Some(lights_override) if lights_list.trim() == "@all" => lights_override,
+            _ => lights_list,
+        }
+    }
+
     pub fn get_light_channels(&self, lights_list: &str) -> Result<Vec<UniverseChannelDefinitions>, DmxArrayError> {
-        self.array_manager.get_array_light_channels(&self.array_id, lights_list)
+        let array = self.array_manager.get_array(&self.array_id)?;
+        let expand = |lights_list: &str| self.expand_values(lights_list);
+
+        self.array_manager.get_definition_light_channels(&self.array_id, array, lights_list, Some(&expand))
+    }
+
+    pub fn get_channel_limits(&self) -> Arc<ChannelLimits> {
+        self.array_manager.limits.get(&self.array_id).cloned().unwrap_or_default()
+    }
+
+    pub fn get_group_dimming(&self) -> Arc<ChannelDimming> {
+        self.array_manager.group_dimming.get(&self.array_id).cloned().unwrap_or_default()
     }
 
     pub fn expand_values(&self, unexpanded_value: &str) -> Result<String, DmxArrayError> {
-        self.array_manager.expand_values(self.array_id.clone(), unexpanded_value)
+        self.value_names.borrow_mut().extend(get_value_names(unexpanded_value).map(Arc::from));
+        self.array_manager.expand_scope_values(self.array_id.clone(), unexpanded_value, self.effect_defaults)
+    }
+
+    pub fn add_warning(&self, warning: String) {
+        self.warnings.borrow_mut().push(warning);
+    }
+
+    pub fn take_warnings(&self) -> Vec<String> {
+        self.warnings.take()
+    }
+
+    pub fn take_value_names(&self) -> Vec<Arc<str>> {
+        self.value_names.take().into_iter().collect()
+    }
+
+    pub fn get_value(&self, value_name: &str) -> Result<Option<SymbolValue>, DmxArrayError> {
+        self.value_names.borrow_mut().insert(Arc::from(value_name));
+        self.array_manager.get_value(self.array_id.clone(), value_name, self.effect_defaults)
     }
 }
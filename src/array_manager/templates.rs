@@ -0,0 +1,142 @@
+use std::{collections::BTreeMap, sync::Arc};
+use error_stack::Result;
+use serde_json::Value;
+
+use super::error::DmxArrayError;
+use super::ArrayManager;
+use crate::defs::{ArrayTemplateInstance, TemplateParameter};
+
+// Placeholder in a template string: {name}, {name+n} or {name-n} (n is added to the numeric parameter)
+#[derive(Debug, PartialEq, Eq)]
+struct Placeholder<'a> {
+    name: &'a str,
+    offset: Option<i64>,
+}
+
+fn parse_placeholder(text: &str) -> std::result::Result<Placeholder<'_>, String> {
+    let text = text.trim();
+    let (name, offset) = match text.find(['+', '-']) {
+        Some(index) => {
+            let (name, offset) = text.split_at(index);
+            let (sign, offset) = offset.split_at(1);
+            let offset = format!("{sign}{}", offset.trim()).parse::<i64>().map_err(|_| format!("invalid offset in {{{text}}}"))?;
+
+            (name.trim(), Some(offset))
+        }
+        None => (text, None),
+    };
+
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("invalid placeholder {{{text}}} (should be {{name}}, {{name+n}} or {{name-n}})"));
+    }
+
+    Ok(Placeholder { name, offset })
+}
+
+fn get_placeholder_value(placeholder: &Placeholder, parameter: &TemplateParameter) -> std::result::Result<String, String> {
+    match (placeholder.offset, parameter) {
+        (None, TemplateParameter::Number(value)) => Ok(value.to_string()),
+        (None, TemplateParameter::Text(value)) => Ok(value.clone()),
+        (Some(offset), TemplateParameter::Number(value)) => Ok((value + offset).to_string()),
+        (Some(offset), TemplateParameter::Text(value)) => match value.trim().parse::<i64>() {
+            Ok(value) => Ok((value + offset).to_string()),
+            Err(_) => Err(format!("parameter '{}' is '{}' which is not a number (needed for {:+})", placeholder.name, value, offset)),
+        },
+    }
+}
+
+// Substitute the placeholders of a template string. Without parameters only the placeholders syntax is checked
+fn substitute(
+    template_name: &Arc<str>,
+    path: &str,
+    text: &str,
+    parameters: Option<&BTreeMap<String, TemplateParameter>>,
+) -> Result<String, DmxArrayError> {
+    let placeholder_error = |reason: String| DmxArrayError::InvalidTemplatePlaceholder(template_name.clone(), format!("at {path}"), reason);
+    let mut result = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find(['{', '}']) {
+        if rest.as_bytes()[start] == b'}' {
+            return Err(placeholder_error(format!("'}}' without '{{' in '{text}'")).into());
+        }
+
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => return Err(placeholder_error(format!("placeholder is not closed in '{text}'")).into()),
+        };
+        let placeholder = parse_placeholder(&rest[start + 1..end]).map_err(placeholder_error)?;
+
+        result.push_str(&rest[..start]);
+        if let Some(parameters) = parameters {
+            let parameter = parameters.get(placeholder.name).ok_or_else(|| {
+                DmxArrayError::MissingTemplateParameter(template_name.clone(), format!("at {path}"), placeholder.name.to_string())
+            })?;
+
+            result.push_str(&get_placeholder_value(&placeholder, parameter).map_err(placeholder_error)?);
+        }
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+// Substitute the placeholders in all the strings of a template document (object keys are not changed)
+fn substitute_value(
+    template_name: &Arc<str>,
+    path: &str,
+    value: &Value,
+    parameters: Option<&BTreeMap<String, TemplateParameter>>,
+) -> Result<Value, DmxArrayError> {
+    match value {
+        Value::String(text) => Ok(Value::String(substitute(template_name, path, text, parameters)?)),
+        Value::Array(values) => Ok(Value::Array(
+            values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| substitute_value(template_name, &format!("{path}[{index}]"), value, parameters))
+                .collect::<Result<Vec<_>, _>>()?,
+        )),
+        Value::Object(values) => Ok(Value::Object(
+            values
+                .iter()
+                .map(|(key, value)| {
+                    let path = if path.is_empty() { key.clone() } else { format!("{path}.{key}") };
+                    substitute_value(template_name, &path, value, parameters).map(|value| (key.clone(), value))
+                })
+                .collect::<Result<serde_json::Map<_, _>, _>>()?,
+        )),
+        _ => Ok(value.clone()),
+    }
+}
+
+impl ArrayManager {
+    pub(super) fn set_array_template(&mut self, template_name: Arc<str>, template: Option<Value>) -> Result<(), DmxArrayError> {
+        match template {
+            None => {
+                self.array_templates.remove(&template_name);
+            }
+            Some(template) => {
+                if !template.is_object() {
+                    return Err(DmxArrayError::InvalidArrayTemplate(template_name, "template should be an array definition object".to_string()).into());
+                }
+
+                substitute_value(&template_name, "", &template, None)?;
+                self.array_templates.insert(template_name, template);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Array definition (before it is parsed as DmxArray) given by a template and its parameters
+    pub(super) fn instantiate_array_template(&self, instance: &ArrayTemplateInstance) -> Result<Value, DmxArrayError> {
+        let template = self
+            .array_templates
+            .get(&instance.template)
+            .ok_or_else(|| DmxArrayError::ArrayTemplateNotFound(instance.template.clone()))?;
+
+        substitute_value(&instance.template, "", template, Some(&instance.parameters))
+    }
+}
@@ -2,8 +2,8 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use super::*;
-use crate::defs::{DmxArray, DIMMING_AMOUNT_MAX, SymbolTable};
-use crate::dmx::ChannelDefinition;
+use crate::defs::{self, ArrayState, DmxArray, EffectUsage, FixtureDefinition, DIMMING_AMOUNT_MAX, NO_STARTUP_EFFECT, SymbolTable};
+use crate::dmx::{ChannelDefinition, ChannelValue, DimmerValue, DmxParseError};
 
 #[test]
 fn test_verify_array() {
@@ -265,7 +265,7 @@ fn test_verify_array() {
 
     if let Err(e) = array_manager.add_array(Arc::from("test2"), Box::new(array)) {
         let t = e.to_string();
-        assert_eq!(t, "Array 'test2' Light '@all -> rgb:0,x:5' (x:5) is invalid channel definition (s:n, rgb:n or w:n)");
+        assert_eq!(t, "Array 'test2' Light '@all -> rgb:0,x:5' (x:5) uses fixture 'x' which is not defined");
     }
 
     let array_json = r#"
@@ -300,10 +300,163 @@ fn test_verify_array() {
 
     let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
 
-    if let Err(e) = array_manager.add_array(Arc::from("test2"), Box::new(array)) {
-        let t = e.to_string();
-        assert_eq!(t, "Array 'test2' Light '@all -> rgb:0,@loop -> rgb:3,@circle -> @loop -> rgb:3,@circle -> @loop' (@loop) contain circular reference to @circle");
+    let e = array_manager.add_array(Arc::from("test2"), Box::new(array)).unwrap_err();
+    assert_eq!(e.to_string(), "Array 'test2' Light '@all -> rgb:0,@loop -> rgb:3,@circle -> @loop -> rgb:3,@circle' contains circular reference via @loop");
+}
+
+fn get_nested_lights_array(lights: &[(&str, &str)], max_lights_nesting: Option<usize>) -> DmxArray {
+    DmxArray {
+        description: "Nested lights".to_string(),
+        universe_id: "0".to_string(),
+        lights: lights.iter().map(|(name, list)| (name.to_string(), list.to_string())).collect(),
+        on: Arc::from("on"),
+        off: Arc::from("off"),
+        dim: Arc::from("dim"),
+        effects: HashMap::new(),
+        default_values: SymbolTable::new(),
+        strict_values: false,
+        limits: HashMap::new(),
+        group_dimming: HashMap::new(),
+        max_lights_nesting,
+        linked_effects: Vec::new(),
+        default_dimming_amount: None,
+        startup: Arc::from(NO_STARTUP_EFFECT),
+        startup_dimming_amount: None,
+        startup_on_redefine: true,
+        strict: true,
+        pre: Vec::new(),
+    }
+}
+
+#[test]
+fn test_nested_lights() {
+    let mut array_manager = ArrayManager::new();
+
+    // building -> floor -> room -> zone -> fixture type -> fixture -> channel is not circular
+    let lights = [
+        ("all", "@building"),
+        ("building", "@floor"),
+        ("floor", "@room"),
+        ("room", "@zone"),
+        ("zone", "@spots"),
+        ("spots", "@spot1"),
+        ("spot1", "@spot1_channel"),
+        ("spot1_channel", "s:1"),
+    ];
+    array_manager.add_array(Arc::from("house"), Box::new(get_nested_lights_array(&lights, None))).unwrap();
+
+    let universes = array_manager.get_array_light_channels("house", "@all").unwrap();
+    assert_eq!(universes.len(), 1);
+    assert_eq!(universes[0].channels, vec![ChannelDefinition::Single(1)]);
+
+    // Same group used twice but not nested is not circular
+    let lights = [("all", "@spots,@spots"), ("spots", "s:1")];
+    array_manager.add_array(Arc::from("twice"), Box::new(get_nested_lights_array(&lights, None))).unwrap();
+
+    let lights = [("all", "@a"), ("a", "@b"), ("b", "@a")];
+    let e = array_manager.add_array(Arc::from("cycle"), Box::new(get_nested_lights_array(&lights, None))).unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::ArrayLightsCircularReference(_, _, group) if group == "a"));
+
+    let lights = [("all", "@self"), ("self", "s:1,@self")];
+    let e = array_manager.add_array(Arc::from("self"), Box::new(get_nested_lights_array(&lights, None))).unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::ArrayLightsCircularReference(_, _, group) if group == "self"));
+
+    let lights = [("all", "@building"), ("building", "@floor"), ("floor", "@room"), ("room", "s:1")];
+    let e = array_manager.add_array(Arc::from("limited"), Box::new(get_nested_lights_array(&lights, Some(3)))).unwrap_err();
+    assert_eq!(e.to_string(), "Array 'limited' Light '@all -> @building -> @floor -> @room -> s:1' nesting too deep (limit 3)");
+}
+
+#[test]
+fn test_light_group_set_operations() {
+    let mut array_manager = ArrayManager::new();
+    let lights = [
+        ("all", "s:1,s:2,s:3,rgb:10,s:20"),
+        ("task", "s:2,rgb:10"),
+        ("living", "s:1,s:2,s:3"),
+        ("dimmable", "s:3,s:1,s:20"),
+        ("accent", "@all - @task"),
+        ("warm", "@living & @dimmable"),
+        ("mixed", "@all - @task & @dimmable - s:1, s:2"),
+    ];
+    array_manager.add_array(Arc::from("room"), Box::new(get_nested_lights_array(&lights, None))).unwrap();
+
+    let universes = array_manager.get_array_light_channels("room", "@accent").unwrap();
+    assert_eq!(universes.len(), 1);
+    assert_eq!(universes[0].channels, vec![ChannelDefinition::Single(1), ChannelDefinition::Single(3), ChannelDefinition::Single(20)]);
+
+    // Order of the left side is kept
+    let universes = array_manager.get_array_light_channels("room", "@warm").unwrap();
+    assert_eq!(universes[0].channels, vec![ChannelDefinition::Single(1), ChannelDefinition::Single(3)]);
+
+    // (((all - task) & dimmable) - s:1) = s:3,s:20 then union with s:2
+    let universes = array_manager.get_array_light_channels("room", "@mixed").unwrap();
+    assert_eq!(universes.len(), 1);
+    assert_eq!(universes[0].channels, vec![ChannelDefinition::Single(3), ChannelDefinition::Single(20), ChannelDefinition::Single(2)]);
+
+    // Channels of another universe are not the same lights
+    let universes = array_manager.get_array_light_channels("room", "$1,s:1,s:2 & @living").unwrap();
+    assert_eq!(universes[0].universe_id, "1");
+    assert_eq!(universes[0].channels, vec![ChannelDefinition::Single(1)]);
+    let universes = array_manager.get_array_light_channels("room", "$1,s:2 & s:2").unwrap();
+    assert_eq!(universes[0].channels, vec![ChannelDefinition::Single(2)]);
+
+    let array = array_manager.get_array("room").unwrap();
+    assert!(array_manager.verify_array("room", array).is_empty());
+
+    let lights = [("all", "@a"), ("a", "s:1,s:2 - @b"), ("b", "@a & s:2")];
+    let e = array_manager.add_array(Arc::from("cycle"), Box::new(get_nested_lights_array(&lights, None))).unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::ArrayLightsCircularReference(_, _, group) if group == "a"));
+
+    let lights = [("all", "s:1 - $2")];
+    let e = array_manager.add_array(Arc::from("universe"), Box::new(get_nested_lights_array(&lights, None))).unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::ArrayLightsInvalidExpression(_, _, entry, _) if entry == "s:1 - $2"));
+
+    let lights = [("all", "s:1 &  - s:2")];
+    let e = array_manager.add_array(Arc::from("empty"), Box::new(get_nested_lights_array(&lights, None))).unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::ArrayLightsInvalidExpression(_, _, _, reason) if reason.starts_with("missing term")));
+}
+
+#[test]
+fn test_invalid_array_id() {
+    let mut array_manager = ArrayManager::new();
+    let array_json = r#"{ "universe_id": "0", "description": "Test array", "lights": { "all": "rgb:0" } }"#;
+
+    for array_id in ["kitchen/spots", "a+b", "a#", "", "  "] {
+        let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+        let e = array_manager.add_array(Arc::from(array_id), Box::new(array)).unwrap_err();
+        assert!(matches!(e.current_context(), DmxArrayError::InvalidArrayId(id, _) if id.as_ref() == array_id));
     }
+
+    let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+    let e = array_manager.add_array(Arc::from("kitchen/spots"), Box::new(array)).unwrap_err();
+    assert_eq!(e.to_string(), "Invalid array id 'kitchen/spots': id must not contain '/'");
+}
+
+#[test]
+fn test_minimal_array() {
+    let array_json = r#"{ "lights": { "all": "s:1,$5,rgb:2" } }"#;
+
+    let mut array_manager = ArrayManager::new();
+    let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+    let e = array_manager.add_array(Arc::from("minimal"), Box::new(array)).unwrap_err();
+    assert_eq!(e.to_string(), "Array 'minimal' has no universe_id and no default universe is configured");
+
+    let mut array_manager = ArrayManager::new().with_default_universe(Some("3".to_string()));
+    let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+    array_manager.add_array(Arc::from("minimal"), Box::new(array)).unwrap();
+
+    let array = array_manager.get_array("minimal").unwrap();
+    assert_eq!(array.description, "minimal");
+    assert_eq!(array.universe_id, "3");
+
+    let mut universes = array_manager.get_array_light_channels("minimal", "@all").unwrap();
+    universes.sort_by(|a, b| a.universe_id.cmp(&b.universe_id));
+
+    assert_eq!(universes.len(), 2);
+    assert_eq!(universes[0].universe_id, "3");
+    assert_eq!(universes[0].channels, vec![ChannelDefinition::Single(1)]);
+    assert_eq!(universes[1].universe_id, "5");
+    assert_eq!(universes[1].channels, vec![ChannelDefinition::Rgb(2, 3, 4)]);
 }
 
 #[test]
@@ -353,21 +506,28 @@ fn test_get_array_light_channels() {
     .unwrap();
 
     let result = scope.get_light_channels("@all").unwrap();
-    let u0 = if result[0].universe_id == "0" { 0 } else { 1 };
-    let u1 = if result[0].universe_id == "2" { 0 } else { 1 };
 
     assert_eq!(result.len(), 2);
-    assert_eq!(result[u0].universe_id, "0");
-    assert_eq!(result[u0].channels.len(), 3);
-    assert_eq!(result[u0].channels[0], ChannelDefinition::Rgb(1, 2, 3));
-    assert_eq!(result[u0].channels[1], ChannelDefinition::Rgb(4, 5, 6));
-    assert_eq!(result[u0].channels[2], ChannelDefinition::Single(7));
-    assert_eq!(result[u1].universe_id, "2");
-    assert_eq!(result[u1].channels.len(), 1);
+    assert_eq!(result[0].universe_id, "0");
+    assert_eq!(result[0].channels.len(), 3);
+    assert_eq!(result[0].channels[0], ChannelDefinition::Rgb(1, 2, 3));
+    assert_eq!(result[0].channels[1], ChannelDefinition::Rgb(4, 5, 6));
+    assert_eq!(result[0].channels[2], ChannelDefinition::Single(7));
+    assert_eq!(result[1].universe_id, "2");
+    assert_eq!(result[1].channels.len(), 1);
     assert_eq!(
-        result[u1].channels[0],
+        result[1].channels[0],
         ChannelDefinition::TriWhite(100, 101, 102)
     );
+
+    // Universes are ordered by first mention, regardless of how many times the expansion is repeated
+    for _ in 0..10 {
+        let result = scope.get_light_channels("@spot,@frame,@center").unwrap();
+        let universe_ids = result.iter().map(|u| u.universe_id.as_str()).collect::<Vec<_>>();
+
+        assert_eq!(universe_ids, vec!["2", "0"]);
+        assert_eq!(result[1].channels, vec![ChannelDefinition::Single(7), ChannelDefinition::Rgb(1, 2, 3), ChannelDefinition::Rgb(4, 5, 6)]);
+    }
 }
 
 #[test]
@@ -412,9 +572,9 @@ fn test_expand_values() {
     //     "ticks": "20"
     // }
     let values: SymbolTable = HashMap::from([
-        (Arc::from("test"), "test-local-value".to_string()),
-        (Arc::from("test2"), "test2-local-value".to_string()),
-        (Arc::from("ticks"), "20".to_string()),
+        (Arc::from("test"), "test-local-value".into()),
+        (Arc::from("test2"), "test2-local-value".into()),
+        (Arc::from("ticks"), "20".into()),
     ]);
 
     array_manager.initialize_array_values(array_id.clone(), values).unwrap();
@@ -436,7 +596,7 @@ fn test_expand_values() {
     assert_eq!(result, "hello default world");
 
     let scope = Scope::new(
-        &&array_manager,
+        &array_manager,
         array_id.clone(),
         None,
         DIMMING_AMOUNT_MAX,
@@ -461,7 +621,7 @@ fn test_expand_values() {
 
     if let Err(e) = result {
         let t = e.to_string();
-        assert_eq!(t, "Array 'test' 'hello `NONE` world' has no value for NONE");
+        assert_eq!(t, "Array 'test' 'hello `NONE` world' has no value for NONE (looked in command values, array default values, global values)");
     }
 
     let result = scope.expand_values("hello `NONE world");
@@ -476,6 +636,109 @@ fn test_expand_values() {
     }
 }
 
+#[test]
+fn test_value_origin_and_strict_values() {
+    let mut array_manager = ArrayManager::new();
+    let array_json = r#"
+            {
+                "universe_id": "0",
+                "description": "Test array",
+                "lights": {
+                    "all": "rgb:1"
+                },
+                "default_values": {
+                    "ticks": "30"
+                },
+                "strict_values": true
+            }"#;
+
+    let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+    let array_id = <Arc<str>>::from("test");
+    array_manager.add_array(array_id.clone(), Box::new(array)).unwrap();
+
+    let array_json = r#"
+            {
+                "universe_id": "0",
+                "description": "Other array",
+                "lights": {
+                    "all": "rgb:10"
+                },
+                "default_values": {
+                    "speed": "5"
+                }
+            }"#;
+
+    let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+    array_manager.add_array(Arc::from("other"), Box::new(array)).unwrap();
+    array_manager.set_global_value(Arc::from("Level"), "100".into()).unwrap();
+
+    // Array default values are used when the command does not provide the value
+    let result = array_manager.expand_values(array_id.clone(), "`ticks`").unwrap();
+    assert_eq!(result, "30");
+
+    // Command values take precedence over array default values
+    let values: SymbolTable = HashMap::from([(Arc::from("ticks"), "20".into())]);
+    array_manager.initialize_array_values(array_id.clone(), values).unwrap();
+    let result = array_manager.expand_values(array_id.clone(), "`ticks`").unwrap();
+    assert_eq!(result, "20");
+
+    // Strict mode: default value can be used only for declared values
+    let result = array_manager.expand_values(array_id.clone(), "`ticks=10`").unwrap();
+    assert_eq!(result, "20");
+
+    let result = array_manager.expand_values(array_id.clone(), "`tikcs=10`");
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Array 'test' '`tikcs=10`': value tikcs has a default but is not declared in the array default_values (strict_values is set)"
+    );
+
+    let result = array_manager.expand_values(Arc::from("other"), "`tikcs=10`").unwrap();
+    assert_eq!(result, "10");
+
+    // Not found errors hint on similarly named values
+    let result = array_manager.expand_values(array_id.clone(), "`level`");
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Array 'test' '`level`' has no value for level (looked in command values, array default values, global values) (a value named 'Level' is defined in global values)"
+    );
+
+    let result = array_manager.expand_values(array_id.clone(), "`speed`");
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Array 'test' '`speed`' has no value for speed (looked in command values, array default values, global values) (a value named 'speed' is defined for array 'other')"
+    );
+}
+
+#[test]
+fn test_remove_referenced_effect() {
+    let mut array_manager = ArrayManager::new();
+    let fade_json = r#"{ "type": "fade", "lights": "@all", "ticks": 10, "target": "s(255)" }"#;
+
+    for (array_id, array_json) in [
+        ("kitchen", r#"{ "universe_id": "0", "description": "Kitchen", "lights": { "all": "s:0" }, "on": "fade_in" }"#),
+        ("hall", r#"{ "universe_id": "0", "description": "Hall", "lights": { "all": "s:1" }, "dim": "fade_in" }"#),
+        ("local", r#"{ "universe_id": "0", "description": "Local", "lights": { "all": "s:2" }, "on": "fade_in",
+                      "effects": { "fade_in": { "type": "fade", "lights": "@all", "ticks": 5, "target": "s(100)" } } }"#),
+    ] {
+        let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+        array_manager.add_array(Arc::from(array_id), Box::new(array)).unwrap();
+    }
+
+    array_manager.add_effect(Arc::from("fade_in"), serde_json::from_str(fade_json).unwrap()).unwrap();
+    array_manager.add_effect(Arc::from("unused"), serde_json::from_str(fade_json).unwrap()).unwrap();
+
+    // Array with a local effect of the same id is not considered as referencing the global one
+    let e = array_manager.remove_effect("fade_in", false).unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::EffectInUse(_, arrays) if arrays == "hall, kitchen"));
+    assert!(array_manager.effects.contains_key("fade_in"));
+
+    array_manager.remove_effect("unused", false).unwrap();
+    assert!(!array_manager.effects.contains_key("unused"));
+
+    array_manager.remove_effect("fade_in", true).unwrap();
+    assert!(!array_manager.effects.contains_key("fade_in"));
+}
+
 #[test]
 fn test_effect_management() {
     use crate::defs;
@@ -501,7 +764,7 @@ fn test_effect_management() {
     let t = format!("{:?}", on_effect);
     assert_eq!(
         t,
-        r#"Fade(FadeEffectNodeDefinition { lights: "@all", ticks: Variable("`on_ticks=10`"), target: "`target=s(255);rgb(255,255,255);w(255,255,255)`", no_dimming: false })"#
+        r#"Fade(FadeEffectNodeDefinition { lights: "@all", ticks: Variable("`on_ticks=10`"), target: "`target=s(255);rgb(255,255,255);w(255,255,255)`", from: None, no_dimming: false })"#
     );
 
     let _ = array_manager
@@ -572,3 +835,670 @@ fn test_effect_management() {
     let t = format!("{:?}", d);
     println!("{}", t);
 }
+
+#[test]
+fn test_compiled_effect_cache() {
+    let mut array_manager = ArrayManager::new();
+    let array_json = r#"{ "universe_id": "0", "lights": { "all": "rgb:1,s:4", "spot": "s:4" }, "default_values": { "level": "100" } }"#;
+    array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+    let get_counts = |array_manager: &ArrayManager| {
+        let diagnostics = array_manager.get_diagnostics(false);
+        (diagnostics.compiled_effect_misses, diagnostics.compiled_effect_hits)
+    };
+    let get_runtime = |array_manager: &ArrayManager, lights: Option<&str>, dimming_amount| {
+        array_manager.get_group_effect_runtime(&EffectUsage::On, "test", None, lights, dimming_amount).unwrap();
+    };
+
+    // Repeated command compiles the effect once
+    for _ in 0..10 {
+        get_runtime(&array_manager, None, DIMMING_AMOUNT_MAX);
+    }
+    assert_eq!(get_counts(&array_manager), (1, 9));
+
+    // Other light group or dimming amount is compiled separately
+    get_runtime(&array_manager, Some("@spot"), DIMMING_AMOUNT_MAX);
+    get_runtime(&array_manager, None, 500);
+    get_runtime(&array_manager, None, 500);
+    assert_eq!(get_counts(&array_manager), (3, 10));
+    assert_eq!(array_manager.get_diagnostics(false).compiled_effects, 3);
+
+    // Values not read by the effect, or set to their current value, keep the compiled effects
+    array_manager.set_global_value(Arc::from("level"), "50".into()).unwrap();
+    array_manager.initialize_array_values(Arc::from("test"), SymbolTable::from([(Arc::from("level"), "60".into())])).unwrap();
+    get_runtime(&array_manager, None, DIMMING_AMOUNT_MAX);
+    assert_eq!(get_counts(&array_manager), (3, 11));
+
+    // Changes to values read by the effect, effects and arrays invalidate the compiled effects
+    array_manager.set_global_value(Arc::from("on_ticks"), "20".into()).unwrap();
+    get_runtime(&array_manager, None, DIMMING_AMOUNT_MAX);
+    array_manager.set_global_value(Arc::from("on_ticks"), "20".into()).unwrap();
+    get_runtime(&array_manager, None, DIMMING_AMOUNT_MAX);
+    assert_eq!(get_counts(&array_manager), (4, 12));
+
+    array_manager.initialize_array_values(Arc::from("test"), SymbolTable::from([(Arc::from("on_ticks"), "30".into())])).unwrap();
+    get_runtime(&array_manager, None, DIMMING_AMOUNT_MAX);
+    array_manager.initialize_array_values(Arc::from("test"), SymbolTable::from([(Arc::from("on_ticks"), "30".into())])).unwrap();
+    get_runtime(&array_manager, None, DIMMING_AMOUNT_MAX);
+    assert_eq!(get_counts(&array_manager), (5, 13));
+
+    array_manager.remove_global_value("on_ticks").unwrap();
+    get_runtime(&array_manager, None, DIMMING_AMOUNT_MAX);
+    assert_eq!(get_counts(&array_manager), (6, 13));
+
+    array_manager.add_effect(Arc::from("blink"), serde_json::from_str(r#"{ "type": "delay", "ticks": 2 }"#).unwrap()).unwrap();
+    get_runtime(&array_manager, None, DIMMING_AMOUNT_MAX);
+    assert_eq!(get_counts(&array_manager), (7, 13));
+
+    array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+    get_runtime(&array_manager, None, DIMMING_AMOUNT_MAX);
+    get_runtime(&array_manager, None, DIMMING_AMOUNT_MAX);
+    assert_eq!(get_counts(&array_manager), (8, 14));
+}
+
+#[test]
+fn test_fixtures() {
+    let mut array_manager = ArrayManager::new();
+    let get_fixture = |json: &str| serde_json::from_str::<FixtureDefinition>(json).unwrap();
+    let get_array = |lights: &str| {
+        let array_json = format!(r#"{{ "universe_id": "0", "lights": {lights} }}"#);
+        Box::new(serde_json::from_str::<DmxArray>(&array_json).unwrap())
+    };
+
+    let e = array_manager.add_fixture(Arc::from("par56"), get_fixture(r#"{ "type": "rgb", "offsets": [0, 4] }"#)).unwrap_err();
+    assert_eq!(e.to_string(), "Fixture 'par56' is invalid: type rgb needs 3 different offsets (got [0, 4])");
+    assert!(array_manager.add_fixture(Arc::from("par56"), get_fixture(r#"{ "type": "rgb", "offsets": [0, 4, 4] }"#)).is_err());
+    assert!(array_manager.add_fixture(Arc::from("rgb"), get_fixture(r#"{ "type": "rgb", "offsets": [0, 4, 8] }"#)).is_err());
+
+    array_manager.add_fixture(Arc::from("par56"), get_fixture(r#"{ "type": "rgb", "offsets": [0, 4, 8] }"#)).unwrap();
+    array_manager.add_fixture(Arc::from("strobe"), get_fixture(r#"{ "type": "s", "offsets": [3] }"#)).unwrap();
+
+    // Expansion
+    array_manager.add_array(Arc::from("test"), get_array(r#"{ "all": "par56:100, strobe:10, s:1" }"#)).unwrap();
+    assert_eq!(
+        array_manager.get_array_light_channels("test", "@all").unwrap()[0].channels,
+        vec![ChannelDefinition::Rgb(100, 104, 108), ChannelDefinition::Single(13), ChannelDefinition::Single(1)]
+    );
+
+    // Unknown template
+    let e = array_manager.add_array(Arc::from("test2"), get_array(r#"{ "all": "par64:100" }"#)).unwrap_err();
+    assert_eq!(e.to_string(), "Array 'test2' Light '@all -> par64:100' (par64:100) uses fixture 'par64' which is not defined");
+    let e = array_manager.add_array(Arc::from("test2"), get_array(r#"{ "all": "par56:65530" }"#)).unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::ArrayLightsInvalidChannel(_, _, 1, DmxParseError::InvalidChannel(token, _)) if token == "par56:65530"));
+
+    // Overlap of the expanded channels with other lights
+    let e = array_manager.add_array(Arc::from("test2"), get_array(r#"{ "all": "@par,@spot", "par": "par56:100", "spot": "s:104" }"#)).unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::ArrayLightChannelUsageMismatch(_, _, 104, _, _, _)));
+
+    // Used fixture can be redefined only with the same definition, and cannot be removed
+    array_manager.add_fixture(Arc::from("par56"), get_fixture(r#"{ "type": "rgb", "offsets": [0, 4, 8] }"#)).unwrap();
+    let e = array_manager.add_fixture(Arc::from("par56"), get_fixture(r#"{ "type": "rgb", "offsets": [0, 1, 2] }"#)).unwrap_err();
+    assert_eq!(e.to_string(), "Fixture 'par56' is used by arrays: test");
+    assert!(array_manager.remove_fixture("par56").is_err());
+
+    array_manager.remove_array(Arc::from("test")).unwrap();
+    array_manager.remove_fixture("par56").unwrap();
+}
+
+#[test]
+fn test_unresolved_effects() {
+    let mut array_manager = ArrayManager::new();
+    let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" }, "on": "warm_white", "dim": "glow", "startup": "warm_white", "effects": { "glow": { "type": "delay", "ticks": 1 } } }"#;
+
+    array_manager.add_array(Arc::from("cove"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+    assert_eq!(array_manager.get_unresolved_effects().get("cove"), Some(&vec![Arc::from("warm_white")]));
+
+    array_manager.add_effect(Arc::from("warm_white"), serde_json::from_str(r#"{ "type": "delay", "ticks": 1 }"#).unwrap()).unwrap();
+    assert!(array_manager.get_unresolved_effects().is_empty());
+
+    // Forced removal of an effect in use makes the reference unresolved again
+    array_manager.remove_effect("warm_white", true).unwrap();
+    assert_eq!(array_manager.get_unresolved_effects().len(), 1);
+
+    array_manager.remove_array(Arc::from("cove")).unwrap();
+    assert!(array_manager.get_unresolved_effects().is_empty());
+}
+
+#[test]
+fn test_resolve_usage_effect() {
+    let mut array_manager = ArrayManager::new();
+    let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" }, "on": "warmwhite", "dim": "glow",
+                          "effects": { "glow": { "type": "sequence", "nodes": [{ "type": "fade", "lights": "@all", "ticks": 10, "target": "s(50)" }, { "type": "delay", "ticks": 5 }] } } }"#;
+    let add_array = |array_manager: &mut ArrayManager| array_manager.add_array(Arc::from("cove"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+    let resolve = |array_manager: &ArrayManager, usage: EffectUsage| {
+        let resolution = array_manager.resolve_usage_effect(&usage, "cove", None).unwrap();
+        (resolution.effect_id.to_string(), resolution.source, resolution.summary)
+    };
+
+    // An on effect that is neither an array nor a global effect falls back to the default effect, the default off id
+    // is not warned about
+    let warnings = add_array(&mut array_manager);
+    assert_eq!(warnings, vec!["Array cove on effect warmwhite is neither an array effect nor a global effect, the built-in default on effect is used"]);
+
+    assert_eq!(resolve(&array_manager, EffectUsage::On), ("warmwhite".to_string(), defs::EffectSource::Default, array_manager.default_on_effect.get_summary()));
+    assert_eq!(resolve(&array_manager, EffectUsage::Off), ("off".to_string(), defs::EffectSource::Default, array_manager.default_off_effect.get_summary()));
+    assert_eq!(resolve(&array_manager, EffectUsage::Dim), ("glow".to_string(), defs::EffectSource::Array, "sequence(fade @all to s(50) in 10 ticks, delay 5 ticks)".to_string()));
+
+    // Global effects are used once defined, unless the array has an effect with the same id
+    let fade_json = r#"{ "type": "fade", "lights": "@all", "ticks": "`ticks=20`", "target": "s(255)" }"#;
+    array_manager.add_effect(Arc::from("warmwhite"), serde_json::from_str(fade_json).unwrap()).unwrap();
+    array_manager.add_effect(Arc::from("glow"), serde_json::from_str(fade_json).unwrap()).unwrap();
+
+    assert_eq!(resolve(&array_manager, EffectUsage::On), ("warmwhite".to_string(), defs::EffectSource::Global, "fade @all to s(255) in `ticks=20` ticks".to_string()));
+    assert_eq!(resolve(&array_manager, EffectUsage::Dim).1, defs::EffectSource::Array);
+    assert!(add_array(&mut array_manager).is_empty());
+
+    // An effect given by the command does not fall back to the default effect
+    let e = array_manager.resolve_usage_effect(&EffectUsage::On, "cove", Some(&Arc::from("missing"))).unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::EffectNotFound(_, effect_id) if effect_id.as_ref() == "missing"));
+    assert_eq!(array_manager.resolve_usage_effect(&EffectUsage::Off, "cove", Some(&Arc::from("glow"))).unwrap().source, defs::EffectSource::Array);
+
+    let diagnostics = array_manager.get_diagnostics(false);
+    let sources = diagnostics.arrays["cove"].usage_effects.iter().map(|resolution| (resolution.usage, resolution.source)).collect::<Vec<_>>();
+    assert_eq!(sources, vec![
+        (EffectUsage::On, defs::EffectSource::Global),
+        (EffectUsage::Off, defs::EffectSource::Default),
+        (EffectUsage::Dim, defs::EffectSource::Array),
+    ]);
+}
+
+#[test]
+fn test_patch() {
+    let mut array_manager = ArrayManager::new();
+    let kitchen_json = r#"{ "universe_id": "0", "lights": { "all": "rgb:1,s:4,$garden,s:12", "spots": "s:4" } }"#;
+    let lounge_json = r#"{ "universe_id": "0", "strict": false, "lights": { "all": "s:4,@lamp", "lamp": "s:10", "odd": "rgb:10" } }"#;
+
+    array_manager.add_array(Arc::from("kitchen"), Box::new(serde_json::from_str::<DmxArray>(kitchen_json).unwrap())).unwrap();
+    assert_eq!(array_manager.add_array(Arc::from("lounge"), Box::new(serde_json::from_str::<DmxArray>(lounge_json).unwrap())).unwrap().len(), 3);
+
+    let patch = array_manager.get_patch();
+    let rows = patch.universes.iter().flat_map(|(universe_id, entries)| entries.iter().map(move |entry| {
+        (universe_id.as_str(), entry.channel, entry.array_id.as_ref(), entry.groups.join(" "), entry.role, entry.conflict.as_deref())
+    })).collect::<Vec<_>>();
+
+    assert_eq!(rows, vec![
+        ("0", 1, "kitchen", "all".to_string(), "r", None),
+        ("0", 2, "kitchen", "all".to_string(), "g", None),
+        ("0", 3, "kitchen", "all".to_string(), "b", None),
+        ("0", 4, "kitchen", "all spots".to_string(), "s", Some("also used by lounge")),
+        ("0", 4, "lounge", "all".to_string(), "s", Some("also used by kitchen")),
+        ("0", 10, "lounge", "all lamp odd".to_string(), "s", Some("used as s and r")),
+        ("0", 11, "lounge", "odd".to_string(), "g", None),
+        ("0", 12, "lounge", "odd".to_string(), "b", None),
+        ("garden", 12, "kitchen", "all".to_string(), "s", None),
+    ]);
+
+    let csv = patch.to_csv();
+    let mut csv_lines = csv.lines();
+    assert_eq!(csv_lines.next(), Some("universe,channel,array,groups,role,conflict"));
+    assert_eq!(csv_lines.nth(3), Some("0,4,kitchen,all spots,s,also used by lounge"));
+    assert_eq!(csv_lines.nth(1), Some("0,10,lounge,all lamp odd,s,used as s and r"));
+    assert_eq!(csv_lines.last(), Some("garden,12,kitchen,all,s,"));
+    assert_eq!(csv.lines().count(), 10);
+
+    array_manager.remove_array(Arc::from("lounge")).unwrap();
+    array_manager.remove_array(Arc::from("kitchen")).unwrap();
+    assert!(array_manager.get_patch().universes.is_empty());
+}
+
+#[test]
+fn test_light_components() {
+    let mut array_manager = ArrayManager::new();
+    let get_array = |lights: &str| {
+        let array_json = format!(r#"{{ "universe_id": "0", "lights": {{ "all": "rgb:10, w:20, par56:30", {lights} }} }}"#);
+        Box::new(serde_json::from_str::<DmxArray>(&array_json).unwrap())
+    };
+
+    array_manager.add_fixture(Arc::from("par56"), serde_json::from_str::<FixtureDefinition>(r#"{ "type": "rgb", "offsets": [0, 4, 8] }"#).unwrap()).unwrap();
+    array_manager.add_array(Arc::from("test"), get_array(r#""warm": "rgb:10.r, w:20.w3, par56:30.g""#)).unwrap();
+    assert_eq!(
+        array_manager.get_array_light_channels("test", "@warm").unwrap()[0].channels,
+        vec![ChannelDefinition::Single(10), ChannelDefinition::Single(22), ChannelDefinition::Single(34)]
+    );
+
+    // Component usage must match the usage of the channel in @all
+    let e = array_manager.add_array(Arc::from("test2"), get_array(r#""warm": "rgb:11.r""#)).unwrap_err();
+    assert_eq!(e.to_string(), "Array 'test2' in universe '0': channel 11 was defined as light green component and is redefined as light red component in group @warm");
+    let e = array_manager.add_array(Arc::from("test2"), get_array(r#""warm": "s:10""#)).unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::ArrayLightChannelUsageMismatch(..)));
+
+    // Component that the light does not have
+    let e = array_manager.add_array(Arc::from("test2"), get_array(r#""warm": "rgb:10.w1""#)).unwrap_err();
+    assert_eq!(e.to_string(), "Array 'test2' Light 'rgb:10.w1' (rgb:10.w1): rgb light has no w1 component");
+}
+
+#[test]
+fn test_parse_error_positions() {
+    let mut array_manager = ArrayManager::new();
+    let get_array = |lights: &str, target: &str| {
+        let array_json = format!(r#"{{ "universe_id": "0", "lights": {{ "all": "{lights}" }}, "effects": {{ "on": {{ "type": "fade", "lights": "@all", "ticks": 10, "target": "{target}" }} }} }}"#);
+        Box::new(serde_json::from_str::<DmxArray>(&array_json).unwrap())
+    };
+
+    // Invalid channel in the lights expansion
+    let e = array_manager.add_array(Arc::from("test"), get_array("rgb:1, s:4, rgb:7/8", "s(255)")).unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::ArrayLightsInvalidChannel(_, _, 3, DmxParseError::InvalidChannel(token, _)) if token == "rgb:7/8"));
+    assert_eq!(
+        e.to_string(),
+        "Array 'test' Light '@all -> rgb:1, s:4, rgb:7/8' entry 3: Invalid channel 'rgb:7/8': rgb channel needs 1 or 3 channel addresses (expected n, s:n, rgb:n, w:n, rgb:r/g/b or w:w1/w2/w3)"
+    );
+
+    // Invalid entry of the fade target
+    array_manager.add_array(Arc::from("test"), get_array("rgb:1, s:4", "s(255); rgb(255,0)")).unwrap();
+    let e = array_manager.get_group_effect_runtime(&EffectUsage::On, "test", None, None, DIMMING_AMOUNT_MAX).unwrap_err();
+    assert!(e.to_string().ends_with(
+        "fade target parameter: Entry 2 of 's(255); rgb(255,0)': Invalid dimmer value 'rgb(255,0)' (expected s(n), rgb(r,g,b) or w(w1,w2,w3))"
+    ), "{e}");
+}
+
+#[test]
+fn test_non_strict_array() {
+    let mut array_manager = ArrayManager::new();
+    let get_array = |strict: bool| {
+        let array_json = format!(r#"
+            {{
+                "universe_id": "0",
+                "strict": {strict},
+                "lights": {{
+                    "strip": "rgb:0",
+                    "spot": "s:20",
+                    "all": "@strip"
+                }},
+                "limits": {{
+                    "cabinet": "s(100)"
+                }}
+            }}"#);
+        Box::new(serde_json::from_str::<DmxArray>(&array_json).unwrap())
+    };
+
+    // Strict (default) array reports the first problem and is not added
+    let e = array_manager.add_array(Arc::from("test"), get_array(true)).unwrap_err();
+    assert_eq!(e.to_string(), "Array 'test' in universe '0': channel 20 is defined as single light channel in group @spot but is not included in @all group");
+    assert!(array_manager.get_array("test").is_err());
+
+    // Non strict array is added and all of its problems are returned
+    let warnings = array_manager.add_array(Arc::from("test"), get_array(false)).unwrap();
+    assert_eq!(warnings, vec![
+        "Array 'test' in universe '0': channel 20 is defined as single light channel in group @spot but is not included in @all group".to_string(),
+        "Array 'test' limits: light group 'cabinet' is not defined".to_string(),
+    ]);
+    assert_eq!(array_manager.get_array_light_channels("test", "@strip").unwrap()[0].channels, vec![ChannelDefinition::Rgb(0, 1, 2)]);
+}
+
+#[test]
+fn test_array_group_dimming() {
+    let mut array_manager = ArrayManager::new();
+    let get_array = |group_dimming: &str| {
+        let array_json = format!(r#"{{ "universe_id": "0", "lights": {{ "accent": "s:0", "all": "@accent,s:1" }}, "group_dimming": {group_dimming} }}"#);
+        Box::new(serde_json::from_str::<DmxArray>(&array_json).unwrap())
+    };
+
+    let e = array_manager.add_array(Arc::from("test"), get_array(r#"{ "task": 600 }"#)).unwrap_err();
+    assert_eq!(e.to_string(), "Array 'test' group_dimming: light group 'task' is not defined");
+
+    let e = array_manager.add_array(Arc::from("test"), get_array(r#"{ "accent": 1200 }"#)).unwrap_err();
+    assert_eq!(e.to_string(), "Array 'test' group_dimming: light group 'accent' has invalid factor 1200 (must be 0-1000)");
+
+    array_manager.add_array(Arc::from("test"), get_array(r#"{ "accent": 600 }"#)).unwrap();
+    let group_dimming = &array_manager.group_dimming[&Arc::from("test")];
+    assert_eq!(group_dimming.get_dimming_amount("0", &ChannelDefinition::Single(0), 500), 300);
+    assert_eq!(group_dimming.get_dimming_amount("0", &ChannelDefinition::Single(1), 500), 500);
+}
+
+#[test]
+fn test_array_limits() {
+    let mut array_manager = ArrayManager::new();
+
+    let array_json = r#"
+            {
+                "universe_id": "0",
+                "description": "Test array",
+                "lights": {
+                    "strip": "rgb:0",
+                    "all": "@strip,s:9"
+                },
+                "limits": {
+                    "cabinet": "rgb(200,100,50)"
+                }
+            }"#;
+
+    let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+
+    if let Err(e) = array_manager.add_array(Arc::from("test"), Box::new(array)) {
+        assert_eq!(e.to_string(), "Array 'test' limits: light group 'cabinet' is not defined");
+    } else {
+        panic!("Expected error");
+    }
+
+    let array_json = r#"
+            {
+                "universe_id": "0",
+                "description": "Test array",
+                "lights": {
+                    "strip": "rgb:0",
+                    "all": "@strip,s:9"
+                },
+                "limits": {
+                    "strip": "rgb(200,100)"
+                }
+            }"#;
+
+    let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+
+    if let Err(e) = array_manager.add_array(Arc::from("test"), Box::new(array)) {
+        assert_eq!(e.to_string(), "Array 'test' limits: light group 'strip' has invalid limit: Invalid dimmer value 'rgb(200,100)' (expected s(n), rgb(r,g,b) or w(w1,w2,w3))");
+    } else {
+        panic!("Expected error");
+    }
+
+    let array_json = r#"
+            {
+                "universe_id": "0",
+                "description": "Test array",
+                "lights": {
+                    "strip": "rgb:0",
+                    "all": "@strip,s:9"
+                },
+                "limits": {
+                    "strip": "rgb(200,100,50)",
+                    "all": "s(10);rgb(255,50,255)"
+                }
+            }"#;
+
+    let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+    array_manager
+        .add_array(Arc::from("test"), Box::new(array))
+        .unwrap();
+
+    let limits = array_manager.get_array_limits("test").unwrap();
+    let limited = limits.limit(
+        "0",
+        &ChannelValue {
+            channel: ChannelDefinition::Rgb(0, 1, 2),
+            value: DimmerValue::Rgb(255, 255, 255),
+        },
+    );
+    assert_eq!(limited.value, DimmerValue::Rgb(200, 50, 50));
+
+    let limited = limits.limit(
+        "0",
+        &ChannelValue {
+            channel: ChannelDefinition::Single(9),
+            value: DimmerValue::Single(255),
+        },
+    );
+    assert_eq!(limited.value, DimmerValue::Single(10));
+
+    array_manager.remove_array(Arc::from("test")).unwrap();
+    assert!(array_manager.limits.is_empty());
+}
+
+#[test]
+fn test_inline_effect_runtime() {
+    let mut array_manager = ArrayManager::new();
+    let effect = serde_json::from_str::<crate::defs::EffectNodeDefinition>(
+        r#"{ "type": "fade", "lights": "@all", "ticks": "`garden_ticks`", "target": "s(255)" }"#,
+    )
+    .unwrap();
+
+    // Only global values are available to inline effects
+    let e = array_manager
+        .get_inline_effect_runtime("$garden,s:12", &effect, DIMMING_AMOUNT_MAX)
+        .unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::ArrayValueNotFound(_, _, _, _, _)));
+
+    array_manager.set_global_value(Arc::from("garden_ticks"), "40".into()).unwrap();
+    let node = array_manager
+        .get_inline_effect_runtime("$garden,s:12", &effect, DIMMING_AMOUNT_MAX)
+        .unwrap();
+    assert_eq!(node.remaining_ticks(), Some(40));
+
+    // The transient array used for building the effect is removed
+    assert!(array_manager.get_array("#inline").is_err());
+
+    // Lights without a universe require a default universe
+    let e = array_manager
+        .get_inline_effect_runtime("s:12", &effect, DIMMING_AMOUNT_MAX)
+        .unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::ArrayMissingUniverseId(_)));
+
+    let mut array_manager = ArrayManager::new().with_default_universe(Some("garden".to_string()));
+    array_manager.set_global_value(Arc::from("garden_ticks"), "40".into()).unwrap();
+    array_manager
+        .get_inline_effect_runtime("s:12", &effect, DIMMING_AMOUNT_MAX)
+        .unwrap();
+}
+
+#[test]
+fn test_typed_values() {
+    let mut array_manager = ArrayManager::new();
+    let effect = serde_json::from_str::<crate::defs::EffectNodeDefinition>(
+        r#"{ "type": "fade", "lights": "@all", "ticks": "`speed`", "target": "s(255)" }"#,
+    )
+    .unwrap();
+    let set_value = |array_manager: &mut ArrayManager, json: &str| {
+        let definition = serde_json::from_str::<crate::defs::ValueDefinition>(json).unwrap();
+        array_manager.set_global_value(Arc::from("speed"), definition.value).unwrap();
+    };
+    let get_ticks = |array_manager: &mut ArrayManager| {
+        array_manager.get_inline_effect_runtime("$garden,s:12", &effect, DIMMING_AMOUNT_MAX).map(|node| node.remaining_ticks())
+    };
+
+    // Numbers are used as is, numeric strings are converted
+    set_value(&mut array_manager, r#"{ "value": 25 }"#);
+    assert_eq!(get_ticks(&mut array_manager).unwrap(), Some(25));
+    set_value(&mut array_manager, r#"{ "value": " 30 " }"#);
+    assert_eq!(get_ticks(&mut array_manager).unwrap(), Some(30));
+
+    for (json, described) in [(r#"{ "value": "fast" }"#, r#"the string "fast""#), (r#"{ "value": true }"#, "the boolean true"), (r#"{ "value": 2.5 }"#, "the number 2.5")] {
+        set_value(&mut array_manager, json);
+        let e = get_ticks(&mut array_manager).unwrap_err();
+
+        assert!(matches!(e.current_context(), DmxArrayError::ValueTypeMismatch(_, name, d, "fade ticks parameter") if name == "speed" && d == described));
+        assert!(e.current_context().to_string().ends_with(&format!("fade ticks parameter: value 'speed' is {described} but a number is required")));
+    }
+
+    // Expanded into strings as their text
+    set_value(&mut array_manager, r#"{ "value": 7 }"#);
+    array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(r#"{ "universe_id": "0", "lights": { "all": "s:1" } }"#).unwrap())).unwrap();
+    assert_eq!(array_manager.expand_values(Arc::from("test"), "s(`speed`)").unwrap(), "s(7)");
+}
+
+#[test]
+fn test_effect_defaults() {
+    let mut array_manager = ArrayManager::new();
+    let pulse_json = r#"{ "type": "fade", "lights": "@all", "ticks": "`ticks`", "target": "`level`", "defaults": { "ticks": 20, "level": "s(200)" } }"#;
+    let array_json = |on: &str| format!(r#"{{ "universe_id": "0", "lights": {{ "all": "s:1" }}, "on": "{on}" }}"#);
+    let get_ticks = |array_manager: &ArrayManager, array_id: &str| {
+        array_manager.get_usage_effect_runtime(&EffectUsage::On, array_id, None, DIMMING_AMOUNT_MAX).unwrap().remaining_ticks()
+    };
+
+    array_manager.add_effect(Arc::from("pulse"), serde_json::from_str(pulse_json).unwrap()).unwrap();
+    array_manager.add_array(Arc::from("hall"), Box::new(serde_json::from_str::<DmxArray>(&array_json("pulse")).unwrap())).unwrap();
+    array_manager.add_array(Arc::from("porch"), Box::new(serde_json::from_str::<DmxArray>(&array_json("pulse")).unwrap())).unwrap();
+
+    // The effect runs without any values given
+    assert_eq!(get_ticks(&array_manager, "hall"), Some(20));
+
+    // Command, array and global values are used before the effect defaults
+    array_manager.initialize_array_values(Arc::from("hall"), SymbolTable::from([(Arc::from("ticks"), defs::SymbolValue::Number(5.into()))])).unwrap();
+    assert_eq!(get_ticks(&array_manager, "hall"), Some(5));
+    assert_eq!(get_ticks(&array_manager, "porch"), Some(20));
+
+    array_manager.set_global_value(Arc::from("ticks"), defs::SymbolValue::Number(8.into())).unwrap();
+    assert_eq!(get_ticks(&array_manager, "hall"), Some(5));
+    assert_eq!(get_ticks(&array_manager, "porch"), Some(8));
+
+    // Defaults apply only to the effect that defines them
+    array_manager.add_effect(Arc::from("plain"), serde_json::from_str(r#"{ "type": "fade", "lights": "@all", "ticks": 1, "target": "`level`" }"#).unwrap()).unwrap();
+    array_manager.add_array(Arc::from("porch"), Box::new(serde_json::from_str::<DmxArray>(&array_json("plain")).unwrap())).unwrap();
+    let e = array_manager.get_usage_effect_runtime(&EffectUsage::On, "porch", None, DIMMING_AMOUNT_MAX).unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::ArrayValueNotFound(_, _, name, looked_in, _) if name == "level" && !looked_in.contains("effect")));
+
+    // Defaults are kept when the effect is exported
+    let exported = serde_json::to_value(&array_manager.get_effects().unwrap()["pulse"]).unwrap();
+    assert_eq!(exported["type"], "fade");
+    assert_eq!(exported["defaults"]["ticks"], 20);
+}
+
+#[test]
+fn test_default_dimming_amount() {
+    let mut array_manager = ArrayManager::new();
+    let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" }, "default_dimming_amount": 300 }"#;
+    array_manager.add_array(Arc::from("bedroom"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+    let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:2" } }"#;
+    array_manager.add_array(Arc::from("kitchen"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+    // Command dimming amount, then array default, then maximum
+    assert_eq!(array_manager.resolve_dimming_amount("bedroom", &EffectUsage::On, Some(700)).unwrap(), 700);
+    assert_eq!(array_manager.resolve_dimming_amount("bedroom", &EffectUsage::On, None).unwrap(), 300);
+    assert_eq!(array_manager.resolve_dimming_amount("bedroom", &EffectUsage::Dim, None).unwrap(), 300);
+    assert_eq!(array_manager.resolve_dimming_amount("kitchen", &EffectUsage::On, None).unwrap(), DIMMING_AMOUNT_MAX);
+
+    // Off is not affected by the array default
+    assert_eq!(array_manager.resolve_dimming_amount("bedroom", &EffectUsage::Off, None).unwrap(), DIMMING_AMOUNT_MAX);
+
+    array_manager.set_array_state(Arc::from("bedroom"), EffectUsage::Dim, None).unwrap();
+    assert_eq!(
+        array_manager.get_array_state("bedroom").unwrap(),
+        Some(ArrayState { usage: EffectUsage::Dim, dimming_amount: 300 })
+    );
+}
+
+#[test]
+fn test_legacy_dimmer_level() {
+    let mut array_manager = ArrayManager::new();
+    let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" }, "dimmer_level": 400 }"#;
+    array_manager.add_array(Arc::from("bedroom"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+    // dimmer_level of older definitions is the array default dimming amount
+    assert_eq!(array_manager.get_array("bedroom").unwrap().default_dimming_amount, Some(400));
+    assert_eq!(array_manager.resolve_dimming_amount("bedroom", &EffectUsage::Dim, None).unwrap(), 400);
+    assert_eq!(array_manager.resolve_dimming_amount("bedroom", &EffectUsage::Off, None).unwrap(), DIMMING_AMOUNT_MAX);
+
+    // Giving both is ambiguous
+    let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" }, "dimmer_level": 400, "default_dimming_amount": 300 }"#;
+    let e = serde_json::from_str::<DmxArray>(array_json).unwrap_err();
+    assert!(e.to_string().contains("duplicate field `default_dimming_amount`"));
+
+    // Unknown arrays are reported as errors
+    let e = array_manager.resolve_dimming_amount("kitchen", &EffectUsage::Dim, None).unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::ArrayNotFound(_)));
+}
+
+#[test]
+fn test_parametric_lights() {
+    let mut array_manager = ArrayManager::new();
+    let array_json = r#"{ "universe_id": "0", "lights": { "bar": "rgb:`bar_base`", "frame": "s:1", "all": "@frame,@bar" } }"#;
+
+    // Parametric groups cannot be verified before values are known
+    array_manager.add_array(Arc::from("bars"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+    let e = array_manager.get_array_light_channels("bars", "@bar").unwrap_err();
+    assert!(matches!(e.current_context(), DmxArrayError::ArrayValueNotFound(_, _, value_name, _, _) if value_name == "bar_base"));
+
+    let values = SymbolTable::from([(Arc::from("bar_base"), "40".into())]);
+    array_manager.initialize_array_values(Arc::from("bars"), values).unwrap();
+
+    let scope = Scope::new(&array_manager, Arc::from("bars"), None, DIMMING_AMOUNT_MAX).unwrap();
+    let result = scope.get_light_channels("@bar").unwrap();
+    assert_eq!(result[0].channels, vec![ChannelDefinition::Rgb(40, 41, 42)]);
+
+    let result = scope.get_light_channels("@all").unwrap();
+    assert_eq!(result[0].channels, vec![ChannelDefinition::Single(1), ChannelDefinition::Rgb(40, 41, 42)]);
+}
+
+#[test]
+fn test_effect_value_names() {
+    let array_json = r#"
+    {
+        "universe_id": "0",
+        "lights": { "all": "@bar", "bar": "s:`bar_base`" },
+        "effects": { "on": { "type": "fade", "lights": "@all", "ticks": "`on_ticks=5`", "target": "`cct`" } }
+    }"#;
+    let mut array_manager = ArrayManager::new();
+    array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+    array_manager.set_global_value(Arc::from("bar_base"), "4".into()).unwrap();
+    array_manager.set_global_value(Arc::from("cct"), "s(100)".into()).unwrap();
+
+    // Values of nested light groups and defaulted values are included, the cached compiled effect keeps them
+    for _ in 0..2 {
+        let (_, _, mut value_names) = array_manager.get_group_effect_runtime(&EffectUsage::On, "test", None, None, DIMMING_AMOUNT_MAX).unwrap();
+        value_names.sort();
+        assert_eq!(value_names, vec![Arc::from("bar_base"), Arc::from("cct"), Arc::from("on_ticks")]);
+    }
+}
+
+#[test]
+fn test_array_templates() {
+    let mut array_manager = ArrayManager::new();
+    let template_json = r#"
+    {
+        "universe_id": "{universe}",
+        "description": "Room {number}",
+        "lights": { "all": "@ceiling,@bed", "ceiling": "rgb:{base}", "bed": "s:{base+3},s:{ base + 4 }" },
+        "effects": { "on": { "type": "fade", "lights": "@all", "ticks": 2, "target": "s(255); rgb(255,255,255)" } }
+    }"#;
+    let template = serde_json::from_str::<serde_json::Value>(template_json).unwrap();
+    array_manager.set_array_template(Arc::from("room"), Some(template)).unwrap();
+
+    let rooms = [
+        ("room1", r#"{ "template": "room", "parameters": { "base": 40, "universe": "2", "number": 1 } }"#),
+        ("room2", r#"{ "template": "room", "parameters": { "base": "60", "universe": "3", "number": 2 } }"#),
+    ];
+
+    for (array_id, instance_json) in rooms {
+        let instance = serde_json::from_str::<defs::ArrayTemplateInstance>(instance_json).unwrap();
+        let definition = array_manager.instantiate_array_template(&instance).unwrap();
+        let array = serde_json::from_value::<DmxArray>(definition).unwrap();
+
+        array_manager.add_array(Arc::from(array_id), Box::new(array)).unwrap();
+    }
+
+    let get_channels = |array_id: &str| {
+        array_manager
+            .get_array_light_channels(array_id, "@all")
+            .unwrap()
+            .into_iter()
+            .map(|universe| (universe.universe_id, universe.channels))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        get_channels("room1"),
+        vec![("2".to_string(), vec![ChannelDefinition::Rgb(40, 41, 42), ChannelDefinition::Single(43), ChannelDefinition::Single(44)])]
+    );
+    assert_eq!(
+        get_channels("room2"),
+        vec![("3".to_string(), vec![ChannelDefinition::Rgb(60, 61, 62), ChannelDefinition::Single(63), ChannelDefinition::Single(64)])]
+    );
+    assert_eq!(array_manager.arrays["room2"].description, "Room 2");
+
+    // Missing parameters and invalid placeholders are reported with their location in the template
+    let errors = [
+        (r#"{ "template": "room", "parameters": { "base": 40, "universe": "2" } }"#, "at description: parameter 'number' is not given"),
+        (r#"{ "template": "room", "parameters": { "base": "high", "universe": "2", "number": 1 } }"#, "at lights.bed: parameter 'base' is 'high' which is not a number"),
+        (r#"{ "template": "suite" }"#, "Array template 'suite' not found"),
+    ];
+
+    for (instance_json, expected) in errors {
+        let instance = serde_json::from_str::<defs::ArrayTemplateInstance>(instance_json).unwrap();
+        let e = array_manager.instantiate_array_template(&instance).unwrap_err();
+        assert!(e.to_string().contains(expected), "{e}");
+    }
+
+    let invalid_templates = [
+        (r#"{ "lights": { "all": "rgb:{base" } }"#, "at lights.all: placeholder is not closed"),
+        (r#"{ "lights": { "all": "rgb:base}" } }"#, "'}' without '{'"),
+        (r#"{ "lights": { "all": "rgb:{base*2}" } }"#, "invalid placeholder"),
+        (r#"{ "pre": [{ "array_id": "{hall" }] }"#, "at pre[0].array_id"),
+        (r#""rgb:{base}""#, "should be an array definition object"),
+    ];
+
+    for (template_json, expected) in invalid_templates {
+        let template = serde_json::from_str::<serde_json::Value>(template_json).unwrap();
+        let e = array_manager.set_array_template(Arc::from("bad"), Some(template)).unwrap_err();
+        assert!(e.to_string().contains(expected), "{e}");
+    }
+    assert!(!array_manager.array_templates.contains_key("bad"));
+}
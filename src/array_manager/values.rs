@@ -1,25 +1,56 @@
 use std::sync::Arc;
 use error_stack::Result;
 
-use crate::defs::SymbolTable;
+use crate::defs::{SymbolTable, SymbolValue};
 
 use super::error::DmxArrayError;
 use super::manager::ArrayManager;
 use super::Scope;
 
+// Tables in which a value is looked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueTable {
+    Command,            // Values passed in the On/Off/Dim command
+    ArrayDefault,       // Array default_values
+    Global,             // Global values (set via DMX/Value/<name>)
+    EffectDefault,      // Defaults of the (global) effect being built
+}
+
+const VALUE_LOOKUP_ORDER: [ValueTable; 4] = [ValueTable::Command, ValueTable::ArrayDefault, ValueTable::Global, ValueTable::EffectDefault];
+
+impl std::fmt::Display for ValueTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            ValueTable::Command => write!(f, "command values"),
+            ValueTable::ArrayDefault => write!(f, "array default values"),
+            ValueTable::Global => write!(f, "global values"),
+            ValueTable::EffectDefault => write!(f, "effect default values"),
+        }
+    }
+}
+
+// Names of the values referenced (as `name` or `name=default`) by an unexpanded value
+pub(super) fn get_value_names(unexpanded_value: &str) -> impl Iterator<Item = &str> {
+    unexpanded_value
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(|value_name_expression| value_name_expression.split('=').next().unwrap())
+}
+
 impl ArrayManager {
     fn set_array_value(
         &mut self,
         array_id: Arc<str>,
         value_name: Arc<str>,
-        value: &str,
+        value: SymbolValue,
     ) -> Result<(), DmxArrayError> {
         let array_values = self
             .values
             .entry(array_id)
             .or_default();
 
-        array_values.insert(value_name, value.to_string());
+        array_values.insert(value_name, value);
         Ok(())
     }
 
@@ -28,14 +59,22 @@ impl ArrayManager {
         array_id: Arc<str>,
         symbol_table: SymbolTable,
     ) -> Result<(), DmxArrayError> {
+        self.get_array(&array_id)?;     // Values of an undefined array would be kept after the command fails
+
         for (value_name, value) in symbol_table {
-            self.set_array_value(array_id.clone(), value_name, &value)?;
+            if self.values.get(&array_id).and_then(|values| values.get(&value_name)) != Some(&value) {
+                self.invalidate_value_compiled_effects(Some(&array_id), &value_name);
+            }
+            self.set_array_value(array_id.clone(), value_name, value)?;
         }
         Ok(())
     }
 
-    pub(super) fn set_global_value(&mut self, value_name: Arc<str>, value: &str) -> Result<(), DmxArrayError> {
-        self.global_values.insert(value_name, value.to_string());
+    pub(super) fn set_global_value(&mut self, value_name: Arc<str>, value: SymbolValue) -> Result<(), DmxArrayError> {
+        if self.global_values.get(&value_name) != Some(&value) {
+            self.invalidate_value_compiled_effects(None, &value_name);
+        }
+        self.global_values.insert(value_name, value);
         Ok(())
     }
 
@@ -43,32 +82,81 @@ impl ArrayManager {
         &mut self,
         value_name: &str,
     ) -> Result<(), DmxArrayError> {
-        self.global_values.remove(value_name);
+        if self.global_values.remove(value_name).is_some() {
+            self.invalidate_value_compiled_effects(None, value_name);
+        }
         Ok(())
     }
 
-    fn get_value(
+    // Effect defaults are only available while building an effect that has them
+    fn get_value_table<'a>(&'a self, array_id: &str, table: ValueTable, effect_defaults: Option<&'a SymbolTable>) -> Option<&'a SymbolTable> {
+        match table {
+            ValueTable::Command => self.values.get(array_id),
+            ValueTable::ArrayDefault => self.arrays.get(array_id).map(|array| &array.default_values),
+            ValueTable::Global => Some(&self.global_values),
+            ValueTable::EffectDefault => effect_defaults,
+        }
+    }
+
+    pub(super) fn get_value(
         &self,
         array_id: Arc<str>,
         value_name: &str,
-    ) -> Result<Option<String>, DmxArrayError> {
+        effect_defaults: Option<&SymbolTable>,
+    ) -> Result<Option<SymbolValue>, DmxArrayError> {
         if !self.arrays.contains_key(&array_id) {
             return Err(DmxArrayError::ArrayNotFound(array_id).into());
         }
 
-        if let Some(array_values) = self.values.get(&array_id) {
-            if let Some(value) = array_values.get(value_name) {
-                return Ok(Some(value.to_string()));
+        Ok(VALUE_LOOKUP_ORDER
+            .iter()
+            .filter_map(|table| self.get_value_table(&array_id, *table, effect_defaults))
+            .find_map(|values| values.get(value_name))
+            .cloned())
+    }
+
+    //
+    // Look for a value with a similar name (same name with different case, or defined for another array)
+    // that may have been intended when the value is not found
+    //
+    fn get_similar_value_hint(&self, array_id: &str, value_name: &str, effect_defaults: Option<&SymbolTable>) -> Option<String> {
+        let is_similar = |name: &str| name != value_name && name.eq_ignore_ascii_case(value_name);
+
+        for table in VALUE_LOOKUP_ORDER {
+            if let Some(name) = self
+                .get_value_table(array_id, table, effect_defaults)
+                .and_then(|values| values.keys().find(|name| is_similar(name)))
+            {
+                return Some(format!("a value named '{name}' is defined in {table}"));
             }
         }
 
-        Ok(self.global_values.get(value_name).map(|s| s.to_string()))
+        for (other_array_id, other_array) in self.arrays.iter().filter(|(id, _)| id.as_ref() != array_id) {
+            let other_values = self.values.get(other_array_id).into_iter().chain(std::iter::once(&other_array.default_values));
+
+            for values in other_values {
+                if let Some(name) = values.keys().find(|name| name.as_ref() == value_name || is_similar(name)) {
+                    return Some(format!("a value named '{name}' is defined for array '{other_array_id}'"));
+                }
+            }
+        }
+
+        None
     }
 
     pub(super) fn expand_values(
         &self,
         array_id: Arc<str>,
         unexpanded_value: &str,
+    ) -> Result<String, DmxArrayError> {
+        self.expand_scope_values(array_id, unexpanded_value, None)
+    }
+
+    pub(super) fn expand_scope_values(
+        &self,
+        array_id: Arc<str>,
+        unexpanded_value: &str,
+        effect_defaults: Option<&SymbolTable>,
     ) -> Result<String, DmxArrayError> {
         let mut value = unexpanded_value;
         let mut result = String::new();
@@ -90,17 +178,37 @@ impl ArrayManager {
                         (value_name_expression, None)
                     };
 
-                let expanded_value = self.get_value(array_id.clone(), value_name)?;
+                let expanded_value = self.get_value(array_id.clone(), value_name, effect_defaults)?;
 
                 if let Some(expanded_value) = expanded_value {
-                    result.push_str(&expanded_value);
+                    result.push_str(&expanded_value.to_string());
                 } else if let Some(default_value) = default_value {
+                    let array = self.get_array(&array_id)?;
+
+                    // In strict mode, a default value may only be used for values declared in the array default_values
+                    if array.strict_values && !array.default_values.contains_key(value_name) {
+                        return Err(DmxArrayError::StrictValueNotDeclared(
+                            array_id.clone(),
+                            unexpanded_value.to_string(),
+                            value_name.to_string(),
+                        ).into());
+                    }
                     result.push_str(default_value);
                 } else {
+                    let looked_in = VALUE_LOOKUP_ORDER
+                        .iter()
+                        .filter(|table| **table != ValueTable::EffectDefault || effect_defaults.is_some())
+                        .map(|table| table.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let hint = self.get_similar_value_hint(&array_id, value_name, effect_defaults).map(|hint| format!(" ({hint})")).unwrap_or_default();
+
                     return Err(DmxArrayError::ArrayValueNotFound(
                         array_id.clone(),
                         unexpanded_value.to_string(),
                         value_name.to_string(),
+                        looked_in,
+                        hint,
                     ).into());
                 }
 
@@ -119,7 +227,20 @@ impl ArrayManager {
     }
 }
 
+// Name of the value if the unexpanded value is just a single value reference (`name` or `name=default`)
+fn get_single_value_name(unexpanded_value: &str) -> Option<&str> {
+    let value_name_expression = unexpanded_value.trim().strip_prefix('`')?.strip_suffix('`')?;
+
+    if value_name_expression.contains('`') {
+        None
+    } else {
+        value_name_expression.split('=').next()
+    }
+}
+
 impl crate::defs::NumberOrVariable {
+    // A variable that is a single value reference uses the value type, so a number value is used as is, a string
+    // value must be a number and other types are reported as a mismatch
     pub fn get_value(
         &self,
         scope: &Scope,
@@ -128,6 +249,20 @@ impl crate::defs::NumberOrVariable {
         match self {
             crate::defs::NumberOrVariable::Number(n) => Ok(*n),
             crate::defs::NumberOrVariable::Variable(s) => {
+                if let Some(value_name) = get_single_value_name(s) {
+                    if let Some(value) = scope.get_value(value_name)? {
+                        let number = match &value {
+                            SymbolValue::Number(n) => n.as_u64().map(|n| n as usize),
+                            SymbolValue::Text(text) => text.trim().parse().ok(),
+                            SymbolValue::Boolean(_) => None,
+                        };
+
+                        return number.ok_or_else(|| {
+                            DmxArrayError::ValueTypeMismatch(scope.to_string(), value_name.to_string(), value.describe(), description).into()
+                        });
+                    }
+                }
+
                 let value = scope.expand_values(s)?;
                 value.parse().map_err(|e: std::num::ParseIntError| {
                     DmxArrayError::ValueError(scope.to_string(), description, e.to_string()).into()
@@ -1,12 +1,13 @@
 
-use error_stack::Result;
+use error_stack::Report;
 use std::collections::HashMap;
 use std::fmt::Display;
 
 use super::manager::ArrayManager;
+use super::lights::is_parametric_lights;
 use super::error::DmxArrayError;
 use crate::defs::DmxArray;
-use crate::dmx::{UniverseChannelDefinitions, ChannelDefinition};
+use crate::dmx::{UniverseChannelDefinitions, ChannelComponent, ChannelDefinition};
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum ChannelUsage {
@@ -33,82 +34,124 @@ impl Display for ChannelUsage {
     }
 }
 
+impl ChannelUsage {
+    // A selected component (e.g. rgb:10.r) uses its channel the same way as the whole light does
+    fn from_component(component: ChannelComponent) -> ChannelUsage {
+        match component {
+            ChannelComponent::R => ChannelUsage::R,
+            ChannelComponent::G => ChannelUsage::G,
+            ChannelComponent::B => ChannelUsage::B,
+            ChannelComponent::W1 => ChannelUsage::W1,
+            ChannelComponent::W2 => ChannelUsage::W2,
+            ChannelComponent::W3 => ChannelUsage::W3,
+        }
+    }
+
+    // Role of the channel in the patch export
+    pub fn get_role(&self) -> &'static str {
+        match *self {
+            ChannelUsage::S => "s",
+            ChannelUsage::R => "r",
+            ChannelUsage::G => "g",
+            ChannelUsage::B => "b",
+            ChannelUsage::W1 => "w1",
+            ChannelUsage::W2 => "w2",
+            ChannelUsage::W3 => "w3",
+        }
+    }
+}
+
+// Usage of each channel (universe id, channel, usage) of the lights, in the order of the lights
+pub (super) fn get_channel_usages(lights: &[UniverseChannelDefinitions]) -> Vec<(&str, u16, ChannelUsage)> {
+    let mut usages = Vec::new();
+
+    for universe_channel_definition in lights.iter() {
+        let universe_id = universe_channel_definition.universe_id.as_str();
+
+        for channel_definition in universe_channel_definition.channels.iter() {
+            match channel_definition {
+                ChannelDefinition::Single(s) => {
+                    let usage = universe_channel_definition.components.get(s).map_or(ChannelUsage::S, |component| ChannelUsage::from_component(*component));
+                    usages.push((universe_id, *s, usage));
+                }
+                ChannelDefinition::Rgb(r, g, b) => {
+                    usages.push((universe_id, *r, ChannelUsage::R));
+                    usages.push((universe_id, *g, ChannelUsage::G));
+                    usages.push((universe_id, *b, ChannelUsage::B));
+                }
+                ChannelDefinition::TriWhite(w1, w2, w3) => {
+                    usages.push((universe_id, *w1, ChannelUsage::W1));
+                    usages.push((universe_id, *w2, ChannelUsage::W2));
+                    usages.push((universe_id, *w3, ChannelUsage::W3));
+                }
+            }
+        }
+    }
+
+    usages
+}
+
 impl ArrayManager {
-    pub (super) fn verify_array(&self, array_id: &str, array: &DmxArray) -> Result<(), DmxArrayError> {
-        Self::verify_array_lights(array_id, array)?;
-        Ok(())
+    // Verify array definition and return all the problems found (empty if the array is valid)
+    pub (super) fn verify_array(&self, array_id: &str, array: &DmxArray) -> Vec<Report<DmxArrayError>> {
+        self.verify_array_lights(array_id, array)
     }
 
-    pub (super) fn verify_array_lights(array_id: &str, array: &DmxArray) -> Result<(), DmxArrayError> {
+    pub (super) fn verify_array_lights(&self, array_id: &str, array: &DmxArray) -> Vec<Report<DmxArrayError>> {
         let add_light_usage = |group_name: &str,
                                channel_usage: &mut HashMap<String, HashMap<u16, ChannelUsage>>,
                                must_exist: bool,
-                               lights: Vec<UniverseChannelDefinitions>|
-         -> Result<(), DmxArrayError> {
-            for universe_channel_definition in lights.iter() {
-                let universe_usage = channel_usage
-                    .entry(universe_channel_definition.universe_id.clone())
-                    .or_default();
-                let mut add_channel_usage =
-                    |channel: u16, usage: ChannelUsage| -> Result<(), DmxArrayError> {
-                        if let Some(existing_usage) = universe_usage.get(&channel) {
-                            if *existing_usage != usage {
-                                return Err(DmxArrayError::ArrayLightChannelUsageMismatch(
-                                    array_id.to_string(),
-                                    universe_channel_definition.universe_id.clone(),
-                                    channel,
-                                    *existing_usage,
-                                    usage,
-                                    group_name.to_string(),
-                                ).into());
-                            }
-                        } else if must_exist {
-                            return Err(DmxArrayError::ArrayLightChannelNotInAllGroup(
-                                array_id.to_string(),
-                                universe_channel_definition.universe_id.clone(),
-                                channel,
-                                usage,
-                                group_name.to_string(),
-                            ).into());
-                        } else {
-                            universe_usage.insert(channel, usage);
-                        }
-
-                        Ok(())
-                    };
-
-                for channel_definition in universe_channel_definition.channels.iter() {
-                    match channel_definition {
-                        ChannelDefinition::Single(s) => {
-                            add_channel_usage(*s, ChannelUsage::S)?
-                        }
-                        ChannelDefinition::Rgb(r, g, b) => {
-                            add_channel_usage(*r, ChannelUsage::R)?;
-                            add_channel_usage(*g, ChannelUsage::G)?;
-                            add_channel_usage(*b, ChannelUsage::B)?;
-                        }
-                        ChannelDefinition::TriWhite(w1, w2, w3) => {
-                            add_channel_usage(*w1, ChannelUsage::W1)?;
-                            add_channel_usage(*w2, ChannelUsage::W2)?;
-                            add_channel_usage(*w3, ChannelUsage::W3)?;
-                        }
+                               lights: Vec<UniverseChannelDefinitions>,
+                               errors: &mut Vec<Report<DmxArrayError>>| {
+            for (universe_id, channel, usage) in get_channel_usages(&lights) {
+                let universe_usage = channel_usage.entry(universe_id.to_string()).or_default();
+
+                if let Some(existing_usage) = universe_usage.get(&channel) {
+                    if *existing_usage != usage {
+                        errors.push(DmxArrayError::ArrayLightChannelUsageMismatch(
+                            array_id.to_string(),
+                            universe_id.to_string(),
+                            channel,
+                            *existing_usage,
+                            usage,
+                            group_name.to_string(),
+                        ).into());
                     }
+                } else if must_exist {
+                    errors.push(DmxArrayError::ArrayLightChannelNotInAllGroup(
+                        array_id.to_string(),
+                        universe_id.to_string(),
+                        channel,
+                        usage,
+                        group_name.to_string(),
+                    ).into());
+                } else {
+                    universe_usage.insert(channel, usage);
                 }
             }
-            Ok(())
         };
 
+        let mut errors = Vec::new();
         let mut channel_usage: HashMap<String, HashMap<u16, ChannelUsage>> = HashMap::new();
-        let all_lights = Self::static_get_array_light_channels(array_id, array, "@all")?;
 
-        add_light_usage("@all", &mut channel_usage, false, all_lights)?;
+        // Parametric light groups (containing `value` references) are verified only when resolved
+        match self.get_definition_light_channels(array_id, array, "@all", None) {
+            Ok(all_lights) => add_light_usage("@all", &mut channel_usage, false, all_lights, &mut errors),
+            Err(e) => return vec![e],       // Groups cannot be checked against @all
+        }
 
-        for (light_group_name, lights_list) in array.lights.iter() {
-            let lights = Self::static_get_array_light_channels(array_id, array, lights_list)?;
-            add_light_usage(light_group_name, &mut channel_usage, true, lights)?;
+        // Sorted so problems are always reported in the same order
+        let mut light_groups = array.lights.iter().filter(|(_, lights_list)| !is_parametric_lights(lights_list)).collect::<Vec<_>>();
+        light_groups.sort_by_key(|(light_group_name, _)| *light_group_name);
+
+        for (light_group_name, lights_list) in light_groups {
+            match self.get_definition_light_channels(array_id, array, lights_list, None) {
+                Ok(lights) => add_light_usage(light_group_name, &mut channel_usage, true, lights, &mut errors),
+                Err(e) => errors.push(e),
+            }
         }
 
-        Ok(())
+        errors
     }
 
     // pub (super) fn verify_effects(&self, array_id: Option<&str>) -> Result<(), DmxArrayError> {
@@ -0,0 +1,74 @@
+// ArtDmx (OpOutput) packet layout
+//
+// Offset  Field
+//  0..8   "Art-Net\0"
+//  8..10  OpCode (0x5000, little endian)
+// 10..12  Protocol version (14, big endian)
+// 12      Sequence (incremented after each send)
+// 13      Physical
+// 14      SubUni (subnet << 4 | universe)
+// 15      Net
+// 16..18  Length of DMX data (even, 2..512, big endian)
+// 18..    DMX data
+
+use std::iter::repeat_n;
+
+pub(super) const DMX_DATA_OFFSET: usize = 18;
+pub(super) const DMX_SEQ_OFFSET: usize = 12;
+pub(super) const DMX_MAX_CHANNELS: usize = 512;
+const ARTNET_ID: &[u8; 8] = b"Art-Net\0";
+const ARTNET_OPCODE_OUTPUT: u16 = 0x5000;
+const ARTNET_PROTOCOL_VERSION: u16 = 14;
+
+// DMX data length must be even, so odd channel counts are rounded up
+pub(super) fn get_data_length(channel_count: usize) -> usize {
+    (channel_count + 1) & !1
+}
+
+// Build ArtDmx packet with sequence 0, data is zero padded to an even length (net, subnet and universe are assumed to be
+// validated by the caller)
+pub(super) fn build_artdmx(net: u8, subnet: u8, universe: u8, data: &[u8]) -> Vec<u8> {
+    let data_length = get_data_length(data.len());
+    assert!(data_length <= DMX_MAX_CHANNELS);
+
+    let mut packet_bytes = Vec::<u8>::with_capacity(DMX_DATA_OFFSET + data_length);
+
+    packet_bytes.extend_from_slice(ARTNET_ID);
+    packet_bytes.extend_from_slice(&ARTNET_OPCODE_OUTPUT.to_le_bytes());
+    packet_bytes.extend_from_slice(&ARTNET_PROTOCOL_VERSION.to_be_bytes());
+    packet_bytes.push(0x00); // Sequence
+    packet_bytes.push(0x00); // Physical
+    packet_bytes.push(subnet << 4 | universe); // SubUni
+    packet_bytes.push(net); // Net
+    packet_bytes.extend_from_slice(&(data_length as u16).to_be_bytes()); // Length
+
+    assert_eq!(packet_bytes.len(), DMX_DATA_OFFSET);
+    packet_bytes.extend_from_slice(data);
+    packet_bytes.extend(repeat_n(0x00, data_length - data.len()));
+    packet_bytes
+}
+
+pub(super) fn get_sequence(packet_bytes: &[u8]) -> u8 {
+    packet_bytes[DMX_SEQ_OFFSET]
+}
+
+pub(super) fn next_sequence(packet_bytes: &mut [u8]) {
+    packet_bytes[DMX_SEQ_OFFSET] = packet_bytes[DMX_SEQ_OFFSET].wrapping_add(1);
+}
+
+pub(super) fn set_sequence(packet_bytes: &mut [u8], sequence: u8) {
+    packet_bytes[DMX_SEQ_OFFSET] = sequence;
+}
+
+pub(super) fn get_data(packet_bytes: &[u8]) -> &[u8] {
+    &packet_bytes[DMX_DATA_OFFSET..]
+}
+
+pub(super) fn get_data_mut(packet_bytes: &mut [u8]) -> &mut [u8] {
+    &mut packet_bytes[DMX_DATA_OFFSET..]
+}
+
+// Replace the DMX data (data must have the packet data length)
+pub(super) fn set_data(packet_bytes: &mut [u8], data: &[u8]) {
+    get_data_mut(packet_bytes).copy_from_slice(data);
+}
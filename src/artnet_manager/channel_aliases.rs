@@ -0,0 +1,94 @@
+// Channel aliases remap the channels of a light to other addresses of the same universe (e.g. a replacement fixture
+// patched at another address) without changing the arrays. Channels are translated when set and when read, so
+// effects (e.g. fades starting from the current value) work as if the light was at its original address
+
+use error_stack::Result;
+use std::collections::{BTreeMap, HashMap};
+
+use super::ArtnetError;
+use crate::dmx::ChannelDefinition;
+
+#[derive(Debug, Default)]
+pub(super) struct ChannelAliases {
+    aliases: HashMap<String, Vec<(ChannelDefinition, ChannelDefinition)>>,     // Universe ID -> (from, to)
+}
+
+impl ChannelAliases {
+    // Add (or replace) the alias of from, to None removes it. Channels of from and to were validated by the caller
+    pub(super) fn set(&mut self, universe_id: &str, from: ChannelDefinition, to: Option<ChannelDefinition>) -> Result<(), ArtnetError> {
+        let aliases = self.aliases.entry(universe_id.to_string()).or_default();
+        let other_aliases = aliases.iter().filter(|(other_from, _)| *other_from != from);
+
+        if let Some(to) = &to {
+            let from_channels = from.channels();
+            let to_channels = to.channels();
+
+            // A channel can be translated by a single alias, and two aliases cannot set the same channel
+            for (other_from, other_to) in other_aliases {
+                let other_from_channels = other_from.channels();
+                let other_to_channels = other_to.channels();
+
+                if from_channels.iter().any(|c| other_from_channels.contains(c)) || to_channels.iter().any(|c| other_to_channels.contains(c)) {
+                    return Err(ArtnetError::ChannelAliasCollision(universe_id.to_string(), from.to_string(), other_from.to_string(), other_to.to_string()).into());
+                }
+            }
+        }
+
+        aliases.retain(|(other_from, _)| *other_from != from);
+
+        if let Some(to) = to {
+            aliases.push((from, to));
+        }
+
+        if aliases.is_empty() {
+            self.aliases.remove(universe_id);
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn remove_universe(&mut self, universe_id: &str) {
+        self.aliases.remove(universe_id);
+    }
+
+    pub(super) fn rename_universe(&mut self, from: &str, to: &str) {
+        if let Some(aliases) = self.aliases.remove(from) {
+            self.aliases.insert(to.to_string(), aliases);
+        }
+    }
+
+    // Channel definition with the aliased channels translated (None if no channel is aliased)
+    pub(super) fn translate(&self, universe_id: &str, channel_definition: &ChannelDefinition) -> Option<ChannelDefinition> {
+        let aliases = self.aliases.get(universe_id)?;
+        let channels = channel_definition.channels();
+        let mut translated = false;
+
+        let translated_channels = channels.iter().map(|channel| {
+            translate_channel(aliases, *channel).inspect(|_| translated = true).unwrap_or(*channel)
+        }).collect::<Vec<_>>();
+
+        if translated {
+            ChannelDefinition::from_offsets(channel_definition.channel_type(), 0, &translated_channels)
+        } else {
+            None
+        }
+    }
+
+    // Channel a single channel value is set to (the channel itself if it is not aliased)
+    pub(super) fn translate_single(&self, universe_id: &str, channel: u16) -> u16 {
+        self.aliases.get(universe_id).and_then(|aliases| translate_channel(aliases, channel)).unwrap_or(channel)
+    }
+
+    // Universe ID -> from -> to
+    pub(super) fn get_diagnostics(&self) -> BTreeMap<String, BTreeMap<String, String>> {
+        self.aliases.iter().map(|(universe_id, aliases)| {
+            (universe_id.clone(), aliases.iter().map(|(from, to)| (from.to_string(), to.to_string())).collect())
+        }).collect()
+    }
+}
+
+fn translate_channel(aliases: &[(ChannelDefinition, ChannelDefinition)], channel: u16) -> Option<u16> {
+    aliases.iter().find_map(|(from, to)| {
+        from.channels().iter().position(|c| *c == channel).map(|index| to.channels()[index])
+    })
+}
@@ -0,0 +1,63 @@
+// How effects actually run, used to tune tick counts: how often each effect is started, and whether it completes (and
+// after how many ticks) or is replaced by another effect with the same id before completing
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::defs::{EffectStatsEntry, EffectUsage};
+
+#[derive(Debug, Default)]
+struct EffectCounters {
+    started: usize,
+    completed: usize,
+    preempted: usize,
+    completed_ticks: usize,     // Total ticks of the completed runs
+}
+
+#[derive(Debug, Default)]
+pub(super) struct EffectStats {
+    counters: HashMap<(Arc<str>, Option<EffectUsage>), EffectCounters>,
+}
+
+impl EffectStats {
+    fn get_counters(&mut self, effect_id: &str, usage: Option<EffectUsage>) -> &mut EffectCounters {
+        self.counters.entry((Arc::from(effect_id), usage)).or_default()
+    }
+
+    pub(super) fn started(&mut self, effect_id: &str, usage: Option<EffectUsage>) {
+        self.get_counters(effect_id, usage).started += 1;
+    }
+
+    pub(super) fn completed(&mut self, effect_id: &str, usage: Option<EffectUsage>, elapsed_ticks: usize) {
+        let counters = self.get_counters(effect_id, usage);
+
+        counters.completed += 1;
+        counters.completed_ticks += elapsed_ticks;
+    }
+
+    pub(super) fn preempted(&mut self, effect_id: &str, usage: Option<EffectUsage>) {
+        self.get_counters(effect_id, usage).preempted += 1;
+    }
+
+    pub(super) fn reset(&mut self) {
+        self.counters.clear();
+    }
+
+    // Entries sorted by effect id and usage
+    pub(super) fn get_entries(&self) -> Vec<EffectStatsEntry> {
+        let mut entries = self
+            .counters
+            .iter()
+            .map(|((effect_id, usage), counters)| EffectStatsEntry {
+                effect_id: effect_id.clone(),
+                usage: *usage,
+                started: counters.started,
+                completed: counters.completed,
+                preempted: counters.preempted,
+                average_ticks: (counters.completed > 0).then(|| counters.completed_ticks as f64 / counters.completed as f64),
+            })
+            .collect::<Vec<_>>();
+
+        entries.sort_by(|a, b| (&a.effect_id, a.usage).cmp(&(&b.effect_id, b.usage)));
+        entries
+    }
+}
@@ -0,0 +1,40 @@
+// Values runtime nodes can read while ticking (e.g. wait_for). The array manager owns the values, the subscriber
+// forwards each change it accepted (DMX/Value and values of On commands) so effects see it on their next tick
+
+use std::{collections::HashMap, sync::Arc};
+
+#[derive(Debug, Default)]
+pub(super) struct EffectValues {
+    array_values: HashMap<Arc<str>, HashMap<Arc<str>, String>>,     // Array ID -> values set by On commands
+    global_values: HashMap<Arc<str>, String>,
+}
+
+impl EffectValues {
+    // Array ID None sets a global value, value None removes it
+    pub(super) fn set(&mut self, array_id: Option<Arc<str>>, value_name: Arc<str>, value: Option<String>) {
+        let values = match array_id {
+            Some(array_id) => self.array_values.entry(array_id).or_default(),
+            None => &mut self.global_values,
+        };
+
+        match value {
+            Some(value) => values.insert(value_name, value),
+            None => values.remove(&value_name),
+        };
+    }
+
+    pub(super) fn rename_array(&mut self, from: &str, to: Arc<str>) {
+        if let Some(values) = self.array_values.remove(from) {
+            self.array_values.insert(to, values);
+        }
+    }
+
+    // Array values take precedence over global values (array default values are not forwarded)
+    pub(super) fn get(&self, array_id: &str, value_name: &str) -> Option<&str> {
+        self.array_values
+            .get(array_id)
+            .and_then(|values| values.get(value_name))
+            .or_else(|| self.global_values.get(value_name))
+            .map(|value| value.as_str())
+    }
+}
@@ -1,6 +1,8 @@
 
 use thiserror::Error;
 
+use crate::dmx::DmxParseError;
+
 #[derive(Debug, Error)]
 pub enum ArtnetError {
     #[error("Invalid universe number: {0} (must be less than 16)")]
@@ -9,33 +11,147 @@ pub enum ArtnetError {
     #[error("No universe with ID '{0}' is defined")]
     InvalidUniverse(String),
 
+    #[error("Universe with ID '{0}' is already defined (remove it before renaming another universe to it)")]
+    UniverseAlreadyDefined(String),
+
+    #[error("Invalid universe ID '{0}': {1}")]
+    InvalidUniverseId(String, String),
+
     #[error("Invalid subnet number: {0} (must be less than 16)")]
     InvalidSubnet(u8),
 
     #[error("Invalid net number: {0} (must be less than 128)")]
     InvalidNet(u8),
 
-    #[error("Too many channels: {0} (must be less than 512)")]
+    #[error("Universe '{0}' does not specify net, subnet and universe, and its ID is not a port address number (0-32767) they can be derived from")]
+    MissingPortAddress(String),
+
+    #[error("Universe '{0}' {1} is {2} but {1} derived from its ID is {3} (omit {1} or make it consistent with the ID)")]
+    InconsistentPortAddress(String, &'static str, u8, u8),
+
+    #[error("Universe '{0}' has the same controller and port address ({1}) as universe '{2}' (set allow_duplicate_port_address if intentional)")]
+    DuplicatePortAddress(String, String, String),
+
+    #[error("No universe group named '{0}' is defined")]
+    UniverseGroupNotFound(String),
+
+    #[error("Universe group '{0}' member '{1}' is not a defined universe")]
+    UniverseGroupMemberNotFound(String, String),
+
+    #[error("Universe group '{0}' has no universes")]
+    EmptyUniverseGroup(String),
+
+    #[error("Too many channels: {0} (must be 512 or less)")]
     TooManyChannels(u16),
 
+    #[error("Universe has no channels (must have at least 1 channel)")]
+    NoChannels,
+
+    #[error("Universe {0} pad_to {1} must be between its channel count ({2}) and 512")]
+    InvalidPadTo(String, u16, u16),
+
+    #[error("Universe {0} send_divisor must be at least 1")]
+    InvalidSendDivisor(String),
+
+    #[error("Universe {0} keepalive_burst every_seconds and packets must be at least 1")]
+    InvalidKeepaliveBurst(String),
+
+    #[error("Universe {0} has invalid quiet_hours: {1}")]
+    InvalidQuietHours(String, String),
+
+    #[error("Universe {0} has both initial and initial_b64 (use only one of them)")]
+    ConflictingInitialValues(String),
+
+    #[error("Invalid initial_b64 data for universe {0}: {1}")]
+    InvalidInitialData(String, String),
+
+    #[error("Initial data of {1} channels does not fit universe {0} ({2} channels)")]
+    InitialDataOutOfRange(String, usize, usize),
+
     #[error("Invalid channel address for universe {0}: {1} (must be less than {2})")]
     InvalidChannel(String, u16, u16),
 
-    #[error("Invalid channel address: '{0}")]
-    InvalidChannelAddress(String),
-
     #[error("When: '{0}'")]
     Context(String),
 
-    #[error("Invalid dimmer value: '{0}'")]
-    InvalidDimmerValue(String),
-
-    #[error("Ambiguous target value: '{0}'")]
-    AmbiguousTargetValue(String),
+    #[error(transparent)]
+    Parse(#[from] DmxParseError),
 
     #[error("You try to set a value of channel {0} however target {1} has no value for this type of channel")]
     MissingTargetValue(String, String),
 
+    #[error("Target {1} has no value for channels {0} (nothing was set)")]
+    MissingTargetValues(String, String),
+
+    #[error("Invalid alias of channels {1} to {2} in universe {0}: {3}")]
+    InvalidChannelAlias(String, String, String, String),
+
+    #[error("Alias of channels {1} in universe {0} collides with the alias of {2} to {3}")]
+    ChannelAliasCollision(String, String, String, String),
+
+    #[error("Invalid frame data for universe {0}: {1}")]
+    InvalidFrameData(String, String),
+
+    #[error("Frame of {2} channels at offset {1} does not fit universe {0} ({3} channels)")]
+    FrameOutOfRange(String, u16, usize, u16),
+
+    #[error("Universe {0} is used by active effects {1} (use force to set the frame anyway)")]
+    FrameUniverseInUse(String, String),
+
+    #[error("Copy from universe {0} must have either channels or all (but not both)")]
+    InvalidCopyChannels(String),
+
+    #[error("Channel {0} copied with offset {1} is outside universe {2} ({3} channels, nothing was copied)")]
+    CopyOutOfRange(String, i32, String, u16),
+
+    #[error("Set channels entry {0} is invalid (nothing was set): {1}")]
+    InvalidSetChannelsBatchEntry(usize, String),
+
     #[error("Universe {0}: Channel {1} does not match value {2}")]
     ChannelValueMismatch(String, String, String),
+
+    #[error("Effect '{0}' sets channels of universe '{1}' which is not defined (check the universe and $universe-id references in the lights)")]
+    EffectUniverseNotFound(String, String),
+
+    #[error("No active effect with ID '{0}'")]
+    EffectNotActive(String),
+
+    #[error("Stop of array '{0}' with usage scope requires usage (On, Off or Dim)")]
+    MissingStopUsage(String),
+
+    #[error("Effect for array '{0}' was built from an outdated array definition (epoch {1}, current epoch {2})")]
+    StaleArrayEpoch(String, u64, u64),
+
+    #[error("Invalid watcher condition: '{0}' (expected >=n, >n, <=n, <n or ==n)")]
+    InvalidWatcherCondition(String),
+
+    #[error("Watcher channel '{0}' must be a single channel (n or s:n)")]
+    InvalidWatcherChannel(String),
+
+    #[error("Invalid watcher topic: '{0}' (must not be empty or contain + or #)")]
+    InvalidWatcherTopic(String),
+
+    #[error("No watcher named '{0}' is defined")]
+    WatcherNotFound(String),
+
+    #[error("Monitor channel '{0}' must be a single channel (n or s:n)")]
+    InvalidMonitorChannel(String),
+
+    #[error("Invalid monitor topic: '{0}' (must not be empty or contain + or #)")]
+    InvalidMonitorTopic(String),
+
+    #[error("No monitor named '{0}' is defined")]
+    MonitorNotFound(String),
+
+    #[error("Effect '{0}' ({1}) stopped: {2}\nNodes when it failed:\n{3}")]
+    EffectFailed(String, String, String, String),       // Effect id, ActiveEffect summary, error, node description
+
+    #[error("Value '{0}' did not match wait_for condition within {1} ticks")]
+    WaitForTimeout(String, usize),
+
+    #[error("Effects ticks took more than {0} for {1} consecutive ticks, stopped: {2}")]
+    EffectTickBudgetExceeded(String, usize, String),
+
+    #[error("Dimming preset '{0}' was not resolved")]
+    UnresolvedDimmingPreset(String),
 }
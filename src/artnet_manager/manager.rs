@@ -1,23 +1,29 @@
-use log::{info, debug, trace};
-use error_stack::{Result, ResultExt};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use log::{info, debug, trace, warn};
+use error_stack::{Report, Result, ResultExt};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::Debug,
-    iter::repeat,
     mem,
     net::{IpAddr, UdpSocket},
     sync::{Arc, Weak},
-    time::Duration,
+    time::{Duration, Instant},
 };
-use tokio::{select, sync::mpsc::Receiver, time::interval};
+use tokio::{select, sync::{broadcast, oneshot, watch}, time::{interval, interval_at}};
 use tokio_util::sync::CancellationToken;
 
-use super::ArtnetError;
+use super::{artnet_packet, channel_aliases::ChannelAliases, effect_stats::EffectStats, effect_values::EffectValues, monitors::Monitor, quiet_hours::QuietHours, watchers::Watcher, ArtnetError};
 use crate::{
+    definition_hash::get_definition_hash,
     defs::UniverseDefinition,
-    defs::{self, TargetValue},
+    defs::{self, EffectMaxTicks, EffectStatus, EffectUsage, RelativeTargetValue, StopScope, TargetValue, UniverseSendStatus},
     dmx::*,
-    messages::{ToArtnetManagerMessage, ToMqttPublisherMessage},
+    manager_channel::ManagerReceiver,
+    messages::{StartEffectRequest, ToArtnetManagerMessage, ToMqttPublisherMessage},
+    metrics::Metrics,
+    mqtt_publisher::ErrorCategory,
+    service::ServiceSettings,
+    sim::SimFrame,
 };
 
 //NOTE: Actual Artnet packet sending is commented out
@@ -32,124 +38,939 @@ pub(super) struct Universe {
     description: String,
 
     controller: Arc<ArtnetController>,
+    controller_address: IpAddr,
+    port_address: u16,          // Art-Net 15 bit port address (net, subnet, universe)
+    channel_count: u16,         // Channels that can be set, the packet data is longer if padded (pad_to)
+    padded: bool,
     packet_bytes: Vec<u8>,
-    modified: bool,
+    pub(super) modified: bool,
     log: bool,
-    disable_send: bool,
+    pub(super) disable_send: bool,
     non_modified_ticks: usize, // Number of ticks in which this universe was not modified (used to determine when to send a packet)
+    send_divisor: u32,          // Sent at most every send_divisor ticks
+    ticks_since_send: u32,
+    sent_ticks: u64,
+    paced_ticks: u64,
+    blackout_data: Option<Vec<u8>>, // While blacked out, channel data is saved here and the sent data is all zeros
+    send_status: UniverseSendStatus,
+    changed_channels: HashSet<u16>,     // Channels set since watchers were last evaluated
+    idle: Option<UniverseIdle>,
+    quiet_hours: Option<QuietHours>,
+    quiet: bool,        // Within quiet hours, nothing is sent
+    pub(super) max_delta_per_tick: Option<u8>,      // Slew limit, larger changes are spread over several ticks
+    pub(super) slewing_channels: HashMap<u16, SlewingChannel>,     // Channels still moving toward the value they were set to
+    definition_hash: String,        // See definition_hash.rs (the universe definition itself is not kept)
+    keepalive_burst: Option<KeepaliveBurst>,
+    #[cfg(test)]
+    pub(super) fail_send: bool,     // Simulate unreachable controller
+    #[cfg(test)]
+    pub(super) sent_packets: usize,
+    #[cfg(test)]
+    pub(super) last_sent_data: Vec<u8>,
+    #[cfg(test)]
+    pub(super) last_sent_sequence: u8,
+}
+
+// See KeepaliveBurstDefinition
+#[derive(Debug)]
+struct KeepaliveBurst {
+    every: Duration,
+    packets: u32,
+    remaining: u32,     // Repeats of the last sent packet still to be sent
+}
+
+#[derive(Debug)]
+pub(super) struct SlewingChannel {
+    target: u8,
+    stepped: bool,      // Already moved during this tick (by set_channel)
+}
+
+// Universe definition checked by Universe::validate_definition
+pub(super) struct ValidatedUniverse {
+    description: String,
+    pub(super) port_address: u16,
+    channel_count: usize,
+    frame_length: usize,        // Channel count, or pad_to if set
+    idle: Option<UniverseIdle>,
+    quiet_hours: Option<QuietHours>,
+    initial_data: Vec<u8>,
+}
+
+// Idle values applied once none of the idle channels was set for a while (see defs::UniverseIdleDefinition)
+#[derive(Debug)]
+struct UniverseIdle {
+    after: Duration,
+    values: Vec<ChannelValue>,
+    channels: HashSet<u16>,     // Individual channel addresses of values
+    last_set: Instant,          // Last time one of the channels was set
+    applied: bool,              // Idle values were applied and none of the channels was set since
 }
 
 pub trait EffectNodeRuntime: Debug + Send {
     fn tick(&mut self, artnet_manager: &mut ArtnetManager) -> Result<(), ArtnetError>;
     fn is_done(&self) -> bool;
+
+    // Estimated number of ticks until the node is done (None if infinite or unknown)
+    fn remaining_ticks(&self) -> Option<usize> {
+        None
+    }
+
+    // Universes whose channels the node sets (checked when the effect is started)
+    fn get_universe_ids(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    // Description of the node and its progress, one line per node indented by two spaces per level
+    fn describe(&self, indent: usize) -> String {
+        format!("{:width$}{:?}", "", self, width = indent * 2)
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct ActiveEffect {
+    pub(super) node: Box<dyn EffectNodeRuntime>,
+    pub(super) elapsed_ticks: usize,
+    pub(super) over_budget_ticks: usize,       // Number of consecutive ticks that took longer than the tick budget
+    pub(super) last_tick_duration: Duration,
+    pub(super) usage: Option<EffectUsage>,     // Usage (On, Off or Dim) the effect was started for
+    pub(super) paused: bool,        // Paused effects are not ticked, they keep their state until resumed
+    pub(super) origin: Option<Arc<str>>,       // Who sent the command that started the effect
+    pub(super) started_at: chrono::DateTime<chrono::Utc>,
+    pub(super) max_ticks: EffectMaxTicks,       // The effect is stopped once elapsed_ticks reaches this limit
+    pub(super) done_waiters: Vec<oneshot::Sender<()>>,      // Dropped (so the receivers are notified) when the effect is removed
+}
+
+impl ActiveEffect {
+    fn new(node: Box<dyn EffectNodeRuntime>, usage: Option<EffectUsage>, origin: Option<Arc<str>>, max_ticks: EffectMaxTicks) -> ActiveEffect {
+        ActiveEffect {
+            node,
+            elapsed_ticks: 0,
+            over_budget_ticks: 0,
+            last_tick_duration: Duration::ZERO,
+            usage,
+            paused: false,
+            origin,
+            started_at: chrono::Utc::now(),
+            max_ticks,
+            done_waiters: Vec::new(),
+        }
+    }
+
+    // When, for which usage and by whom the effect was started (reported when it fails)
+    fn get_summary(&self) -> String {
+        let usage = self.usage.map(|usage| format!(" for usage {usage}")).unwrap_or_default();
+
+        format!(
+            "started {}{} by origin {}, failed on tick {}",
+            self.started_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            usage,
+            get_origin_text(&self.origin),
+            self.elapsed_ticks + 1,
+        )
+    }
+}
+
+// Effect started when the active effect with the same id completes
+#[derive(Debug)]
+pub(super) struct QueuedEffect {
+    pub(super) node: Box<dyn EffectNodeRuntime>,
+    pub(super) usage: Option<EffectUsage>,
+    pub(super) origin: Option<Arc<str>>,
+    pub(super) max_ticks: EffectMaxTicks,
+}
+
+// Effects whose tick takes longer than max_tick_duration for max_over_budget_ticks consecutive ticks are stopped
+#[derive(Debug, Clone, Copy)]
+pub struct EffectTickBudget {
+    pub max_tick_duration: Duration,
+    pub max_over_budget_ticks: usize,
 }
 
 pub struct ArtnetManager {
     pub(super) universes: HashMap<String, Universe>,
     pub(super) controllers: HashMap<IpAddr, Weak<ArtnetController>>,
-    active_effects: HashMap<String, Box<dyn EffectNodeRuntime>>,
+    retained_controllers: HashMap<IpAddr, (Arc<ArtnetController>, Instant)>,     // Controllers with no universes kept until the given time
+    controller_retention: Duration,     // Keep a controller (and its socket) this long after its last universe is removed
+    pub(super) active_effects: HashMap<String, ActiveEffect>,
+    pub(super) queued_effects: HashMap<String, QueuedEffect>,     // Effect ID -> effect to start when the active effect completes (at most one)
+    channel_limits: HashMap<Arc<str>, Arc<ChannelLimits>>,     // Array ID -> channel limits of this array
+    merged_channel_limits: ChannelLimits,      // Limits of all the arrays (the lowest limit of each channel), applied by set_channel
+    tick_budget: Option<EffectTickBudget>,
+    array_epochs: HashMap<Arc<str>, defs::ArrayEpoch>,       // Array ID -> epoch of the current array definition
+    unreachable_threshold: usize,       // Consecutive send failures after which a universe is reported as unreachable
+    sim_frames: Option<broadcast::Sender<SimFrame>>,       // If set, sent universe frames are also pushed to the sim viewers
+    pub(super) dropped_publishes: usize,     // Messages dropped since the publisher channel was full (e.g. MQTT broker is down)
+    pub(super) watchers: HashMap<Arc<str>, Watcher>,
+    pub(super) monitors: HashMap<Arc<str>, Monitor>,
+    send_disabled_warnings: HashMap<String, Instant>,      // Universe ID -> when setting channels of the universe (whose sending is disabled) was last warned
+    effect_values: EffectValues,
+    channel_aliases: ChannelAliases,
+    effect_stats: EffectStats,
+    universe_groups: HashMap<Arc<str>, Vec<Arc<str>>>,     // Group name -> member universe IDs
+    renamed_universes: HashMap<Arc<str>, Arc<str>>,     // Old universe ID -> ID it was renamed to (see rename_universe)
+    max_delta_per_tick: Option<u8>,     // Slew limit of universes whose definition does not set max_delta_per_tick
+    messages_since_tick: usize,
+    max_messages_per_tick: usize,      // Most messages handled between two ticks (reported in diagnostics)
+    pub(super) tick_duration: Duration,
+    pub(super) max_effect_ticks: Option<usize>,      // Effects (that do not set their own limit) are stopped after this number of ticks
+    settings: Option<watch::Receiver<ServiceSettings>>,      // Checked for changes (DMX/Config) after each tick
+    pub(super) metrics: Arc<Metrics>,      // Shared with the subscriber, served by GET /metrics
     #[cfg(test)]
     pub(super) set_channel_log: Vec<ChannelValue>,
 }
 
-pub(super) const DMX_DATA_OFFSET: usize = 18;
-const DMX_SEQ_OFFSET: usize = 12;
 const DMX_UDP_PORT: u16 = 0x1936;
-const ARTNET_OPCODE_OUTPUT: u16 = 0x5000;
-const TICK_DURATION: Duration = Duration::from_millis(50);
+pub const DEFAULT_TICK_DURATION: Duration = Duration::from_millis(50);
 const SEND_UNMODIFIED_UNIVERSE_EVERY: usize = 20 * 4; // 20 ticks per second, send every 4 seconds
+const DEFAULT_UNREACHABLE_THRESHOLD: usize = 5;
+const SEND_DISABLED_WARNING_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const MAX_MESSAGES_PER_BATCH: usize = 32;    // Pending messages handled before checking again whether a tick is due
 
 impl ArtnetManager {
     pub fn new() -> ArtnetManager {
         ArtnetManager {
             universes: HashMap::new(),
             controllers: HashMap::new(),
+            retained_controllers: HashMap::new(),
+            controller_retention: Duration::ZERO,
             active_effects: HashMap::new(),
+            queued_effects: HashMap::new(),
+            channel_limits: HashMap::new(),
+            merged_channel_limits: ChannelLimits::default(),
+            tick_budget: None,
+            array_epochs: HashMap::new(),
+            unreachable_threshold: DEFAULT_UNREACHABLE_THRESHOLD,
+            sim_frames: None,
+            dropped_publishes: 0,
+            watchers: HashMap::new(),
+            monitors: HashMap::new(),
+            send_disabled_warnings: HashMap::new(),
+            effect_values: EffectValues::default(),
+            channel_aliases: ChannelAliases::default(),
+            effect_stats: EffectStats::default(),
+            universe_groups: HashMap::new(),
+            renamed_universes: HashMap::new(),
+            max_delta_per_tick: None,
+            messages_since_tick: 0,
+            max_messages_per_tick: 0,
+            tick_duration: DEFAULT_TICK_DURATION,
+            max_effect_ticks: None,
+            settings: None,
+            metrics: Arc::new(Metrics::default()),
             #[cfg(test)]
             set_channel_log: Vec::new(),
         }
     }
 
+    pub fn with_tick_budget(mut self, tick_budget: Option<EffectTickBudget>) -> ArtnetManager {
+        self.tick_budget = tick_budget;
+        self
+    }
+
+    pub fn with_unreachable_threshold(mut self, unreachable_threshold: usize) -> ArtnetManager {
+        self.unreachable_threshold = unreachable_threshold.max(1);
+        self
+    }
+
+    pub fn with_controller_retention(mut self, controller_retention: Duration) -> ArtnetManager {
+        self.controller_retention = controller_retention;
+        self
+    }
+
+    pub fn with_max_delta_per_tick(mut self, max_delta_per_tick: Option<u8>) -> ArtnetManager {
+        self.max_delta_per_tick = max_delta_per_tick;
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> ArtnetManager {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn with_sim(mut self, sim_frames: Option<broadcast::Sender<SimFrame>>) -> ArtnetManager {
+        self.sim_frames = sim_frames;
+        self
+    }
+
+    pub fn with_settings(mut self, mut settings: watch::Receiver<ServiceSettings>) -> ArtnetManager {
+        self.apply_settings(&settings.borrow_and_update());
+        self.settings = Some(settings);
+        self
+    }
+
+    fn apply_settings(&mut self, settings: &ServiceSettings) {
+        self.tick_duration = Duration::from_millis(settings.tick_ms);
+        self.max_effect_ticks = settings.max_effect_ticks;
+    }
+
+    // Apply the settings if they were changed since the last check, returns true if the tick duration was changed
+    pub(super) fn update_settings(&mut self) -> bool {
+        let settings = match self.settings.as_mut() {
+            Some(settings) if settings.has_changed().unwrap_or(false) => settings.borrow_and_update().clone(),
+            _ => return false,
+        };
+        let tick_duration = self.tick_duration;
+
+        self.apply_settings(&settings);
+
+        if self.tick_duration != tick_duration {
+            info!("Tick duration changed from {:?} to {:?}", tick_duration, self.tick_duration);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Returns warnings about the universe (it is added)
     pub(super) fn add_universe(
         &mut self,
         universe_id: &str,
         definition: UniverseDefinition,
-    ) -> Result<(), ArtnetError> {
-        let controller = match self.controllers.get(&definition.controller) {
-            Some(c) => c.upgrade().unwrap(),
-            None => {
-                let controller = Arc::new(ArtnetController::new(&definition.controller)?);
-                self.controllers
-                    .insert(definition.controller, Arc::downgrade(&controller));
-                controller
+    ) -> Result<Vec<String>, ArtnetError> {
+        defs::validate_id(universe_id).map_err(|e| ArtnetError::InvalidUniverseId(universe_id.to_string(), e))?;
+
+        let allow_duplicate_port_address = definition.allow_duplicate_port_address;
+        let controller = self.get_controller(&definition.controller)?;
+        let mut universe = Universe::new(controller, universe_id, definition)?;
+
+        if !allow_duplicate_port_address {
+            if let Err(e) = self.check_duplicate_port_address(universe_id, &universe.controller_address, universe.port_address) {
+                drop(universe);
+                self.remove_dead_controllers();
+                return Err(e);
             }
-        };
+        }
+
+        universe.max_delta_per_tick = universe.max_delta_per_tick.or(self.max_delta_per_tick);
+        let warnings = self.get_frame_length_warning(universe_id, &universe).into_iter().collect();
+
+        if let Some(replaced_universe) = self.universes.insert(universe_id.to_owned(), universe) {
+            self.release_controller(replaced_universe);
+        }
+
+        self.renamed_universes.remove(universe_id);     // The old ID refers to this universe again
+        self.merge_channel_limits();
+
+        Ok(warnings)
+    }
+
+    // A node may keep stale data beyond a shorter frame, so universes of the same controller that send frames of
+    // different lengths are reported, unless both are padded (pad_to)
+    fn get_frame_length_warning(&self, universe_id: &str, universe: &Universe) -> Option<String> {
+        let frame_length = universe.get_frame_length();
+        let mut mismatched = self.universes
+            .iter()
+            .filter(|(id, other)| id.as_str() != universe_id && other.controller_address == universe.controller_address)
+            .filter(|(_, other)| other.get_frame_length() != frame_length && !(other.padded && universe.padded))
+            .map(|(id, other)| (id.as_str(), other.get_frame_length()))
+            .collect::<Vec<_>>();
+
+        let max_frame_length = mismatched.iter().map(|(_, other_frame_length)| *other_frame_length).max()?.max(frame_length);
+
+        mismatched.sort();
+        Some(format!(
+            "Universe {universe_id} sends {frame_length} channel frames to controller {}, which also gets {} (set \"pad_to\": {max_frame_length} on universes with shorter frames)",
+            universe.controller_address,
+            mismatched.iter().map(|(id, other_frame_length)| format!("{other_frame_length} channel frames from universe {id}")).collect::<Vec<_>>().join(", "),
+        ))
+    }
 
-        let universe = Universe::new(controller, universe_id, definition)?;
-        self.universes.insert(universe_id.to_owned(), universe);
+    fn check_duplicate_port_address(&self, universe_id: &str, controller_address: &IpAddr, port_address: u16) -> Result<(), ArtnetError> {
+        let duplicate = self.universes.iter().find(|(id, u)|
+            id.as_str() != universe_id && u.controller_address == *controller_address && u.port_address == port_address
+        );
+
+        match duplicate {
+            Some((duplicate_id, _)) => {
+                let (net, subnet, universe_number) = split_port_address(port_address);
+
+                Err(ArtnetError::DuplicatePortAddress(
+                    universe_id.to_string(),
+                    format!("{} net {} subnet {} universe {}", controller_address, net, subnet, universe_number),
+                    duplicate_id.to_string(),
+                ).into())
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Same checks as add_universe, but nothing is changed (and no controller socket is created)
+    pub(super) fn validate_universe(&self, universe_id: &str, definition: &UniverseDefinition) -> Result<(), ArtnetError> {
+        defs::validate_id(universe_id).map_err(|e| ArtnetError::InvalidUniverseId(universe_id.to_string(), e))?;
+        let validated = Universe::validate_definition(universe_id, definition)?;
+
+        if !definition.allow_duplicate_port_address {
+            self.check_duplicate_port_address(universe_id, &definition.controller, validated.port_address)?;
+        }
 
         Ok(())
     }
 
-    pub(super) fn remove_universe(&mut self, universe_id: &str) -> Result<(), ArtnetError> {
-        self.universes
+    // Effects that set channels of the removed universe are stopped, including effects that also use other universes
+    // (they are not left running on part of their lights). Returns the (sorted) ids of the stopped effects
+    pub(super) fn remove_universe(&mut self, universe_id: &str) -> Result<Vec<Arc<str>>, ArtnetError> {
+        let universe = self.universes
             .remove(universe_id)
             .ok_or_else(|| ArtnetError::InvalidUniverse(universe_id.to_string()))?;
 
-        let to_remove = self
-            .controllers
+        self.release_controller(universe);
+        self.channel_aliases.remove_universe(universe_id);
+        self.send_disabled_warnings.remove(universe_id);
+        self.renamed_universes.retain(|_, renamed_universe_id| renamed_universe_id.as_ref() != universe_id);
+        self.merge_channel_limits();
+
+        let renamed_universes = &self.renamed_universes;
+        let mut effect_ids: Vec<Arc<str>> = self
+            .active_effects
             .iter()
-            .filter(|(_, c)| c.upgrade().is_none())
-            .map(|(ip, _)| *ip)
-            .collect::<Vec<IpAddr>>();
+            .filter(|(_, effect)| effect.node.get_universe_ids().into_iter().any(|id| resolve_universe_id(renamed_universes, id) == universe_id))
+            .map(|(effect_id, _)| Arc::from(effect_id.as_str()))
+            .collect();
+
+        effect_ids.sort();
 
-        for ip in to_remove.iter() {
-            self.controllers.remove(ip);
+        for effect_id in effect_ids.iter() {
+            self.stop_effect(effect_id)?;
         }
 
+        Ok(effect_ids)
+    }
+
+    // Reset command: the effects are stopped before the universes are removed, and the controller sockets (including
+    // retained ones) are closed. Returns the (sorted) ids of the stopped effects
+    pub(super) fn clear_all(&mut self) -> Result<Vec<Arc<str>>, ArtnetError> {
+        let mut effect_ids: Vec<Arc<str>> = self.active_effects.keys().map(|effect_id| Arc::from(effect_id.as_str())).collect();
+
+        effect_ids.sort();
+        for effect_id in effect_ids.iter() {
+            self.stop_effect(effect_id)?;
+        }
+        self.queued_effects.clear();
+
+        self.universes.clear();
+        self.retained_controllers.clear();
+        self.remove_dead_controllers();
+
+        self.channel_aliases = ChannelAliases::default();
+        self.channel_limits.clear();
+        self.merged_channel_limits = ChannelLimits::default();
+        self.effect_values = EffectValues::default();
+        self.watchers.clear();
+        self.monitors.clear();
+        self.universe_groups.clear();
+        self.renamed_universes.clear();
+        self.send_disabled_warnings.clear();
+        Ok(effect_ids)
+    }
+
+    // Move the universe (with its channel data and packet sequence) to a new ID. Effects running on the universe and
+    // arrays whose definition still refers to the old ID keep setting the universe until the old ID is defined again
+    pub(super) fn rename_universe(&mut self, from: &str, to: Arc<str>) -> Result<(), ArtnetError> {
+        defs::validate_id(&to).map_err(|e| ArtnetError::InvalidUniverseId(to.to_string(), e))?;
+
+        if self.universes.contains_key(to.as_ref()) {
+            return Err(ArtnetError::UniverseAlreadyDefined(to.to_string()).into());
+        }
+
+        let mut universe = self.universes.remove(from).ok_or_else(|| ArtnetError::InvalidUniverse(from.to_string()))?;
+
+        if let Some(description) = universe.description.strip_prefix(from) {
+            universe.description = format!("{to}{description}");
+        }
+
+        self.universes.insert(to.to_string(), universe);
+        self.channel_aliases.rename_universe(from, &to);
+
+        if let Some(last_warning) = self.send_disabled_warnings.remove(from) {
+            self.send_disabled_warnings.insert(to.to_string(), last_warning);
+        }
+
+        for watcher in self.watchers.values_mut().filter(|watcher| watcher.universe_id.as_ref() == from) {
+            watcher.universe_id = to.clone();
+        }
+
+        for monitor in self.monitors.values_mut().filter(|monitor| monitor.universe_id.as_ref() == from) {
+            monitor.universe_id = to.clone();
+        }
+
+        for universe_id in self.universe_groups.values_mut().flatten().filter(|universe_id| universe_id.as_ref() == from) {
+            *universe_id = to.clone();
+        }
+
+        // Renaming a universe back to an old ID, or renaming it again
+        self.renamed_universes.remove(to.as_ref());
+        for renamed_universe_id in self.renamed_universes.values_mut().filter(|renamed_universe_id| renamed_universe_id.as_ref() == from) {
+            *renamed_universe_id = to.clone();
+        }
+        self.renamed_universes.insert(Arc::from(from), to);
+        self.merge_channel_limits();
+
         Ok(())
     }
 
-    fn start_effect(
+    // Universes of the same controller share its socket. A controller that is still in use (or retained) is reused,
+    // otherwise a new one is created
+    fn get_controller(&mut self, controller_address: &IpAddr) -> Result<Arc<ArtnetController>, ArtnetError> {
+        self.remove_dead_controllers();
+
+        let controller = match self.controllers.get(controller_address).and_then(Weak::upgrade) {
+            Some(controller) => controller,
+            None => {
+                let controller = Arc::new(ArtnetController::new(controller_address)?);
+                self.controllers.insert(*controller_address, Arc::downgrade(&controller));
+                controller
+            }
+        };
+
+        self.retained_controllers.remove(controller_address);
+        Ok(controller)
+    }
+
+    // Called when a universe is removed (or replaced), if it was the last universe of its controller, the controller
+    // is retained for a while so quickly re-adding a universe does not create a new socket
+    fn release_controller(&mut self, universe: Universe) {
+        if !self.controller_retention.is_zero() && Arc::strong_count(&universe.controller) == 1 {
+            self.retained_controllers.insert(
+                universe.controller_address,
+                (universe.controller.clone(), Instant::now() + self.controller_retention),
+            );
+        }
+
+        drop(universe);
+        self.remove_dead_controllers();
+    }
+
+    pub(super) fn apply_idle_values(&mut self, now: Instant) {
+        for (universe_id, universe) in self.universes.iter_mut() {
+            match universe.apply_idle(now) {
+                Ok(true) => debug!("Universe {} is idle, idle values applied", universe_id),
+                Ok(false) => {}
+                Err(e) => warn!("Applying idle values of universe {} failed: {}", universe_id, e),
+            }
+        }
+    }
+
+    pub(super) fn expire_retained_controllers(&mut self, now: Instant) {
+        if !self.retained_controllers.is_empty() {
+            self.retained_controllers.retain(|_, (_, retain_until)| *retain_until > now);
+            self.remove_dead_controllers();
+        }
+    }
+
+    fn remove_dead_controllers(&mut self) {
+        self.controllers.retain(|_, controller| controller.strong_count() > 0);
+    }
+
+    pub(super) fn start_effect(
         &mut self,
         effect_id: &str,
         effect: Box<dyn EffectNodeRuntime>,
+        usage: Option<EffectUsage>,
+        origin: Option<Arc<str>>,
+        max_ticks: EffectMaxTicks,
     ) -> Result<(), ArtnetError> {
-        info!("Starting effect {}: {:?}", effect_id, effect);
-        self.active_effects.insert(effect_id.to_owned(), effect);
+        // Otherwise the effect would fail on its first tick without telling which effect referred to the universe
+        if let Some(universe_id) = effect.get_universe_ids().into_iter().find(|universe_id| !self.universes.contains_key(resolve_universe_id(&self.renamed_universes, universe_id))) {
+            return Err(ArtnetError::EffectUniverseNotFound(effect_id.to_string(), universe_id.to_string()).into());
+        }
+
+        info!("Starting effect {} (origin {}):\n{}", effect_id, get_origin_text(&origin), effect.describe(1));
+        self.drop_queued_effect(effect_id);     // Superseded by the effect that is started now
+        self.effect_stats.started(effect_id, usage);
+
+        let replaced_effect = self.active_effects.insert(effect_id.to_owned(), ActiveEffect::new(effect, usage, origin, max_ticks));
+
+        if let Some(replaced_effect) = replaced_effect {
+            self.effect_stats.preempted(effect_id, replaced_effect.usage);
+            info!(
+                "Effect {} (origin {}) replaced by effect started by origin {}",
+                effect_id,
+                get_origin_text(&replaced_effect.origin),
+                get_origin_text(&self.active_effects[effect_id].origin)
+            );
+        }
+
         Ok(())
     }
 
+    // Queue an effect to start when the active effect with the same id completes (replacing an effect queued before).
+    // If no effect with this id is active, the effect is started now
+    pub(super) fn enqueue_effect(
+        &mut self,
+        effect_id: &str,
+        effect: Box<dyn EffectNodeRuntime>,
+        usage: Option<EffectUsage>,
+        origin: Option<Arc<str>>,
+        max_ticks: EffectMaxTicks,
+    ) -> Result<(), ArtnetError> {
+        if !self.active_effects.contains_key(effect_id) {
+            return self.start_effect(effect_id, effect, usage, origin, max_ticks);
+        }
+
+        if let Some(universe_id) = effect.get_universe_ids().into_iter().find(|universe_id| !self.universes.contains_key(resolve_universe_id(&self.renamed_universes, universe_id))) {
+            return Err(ArtnetError::EffectUniverseNotFound(effect_id.to_string(), universe_id.to_string()).into());
+        }
+
+        info!("Queuing effect {} (origin {}) after the current effect:\n{}", effect_id, get_origin_text(&origin), effect.describe(1));
+        let replaced_effect = self.queued_effects.insert(effect_id.to_owned(), QueuedEffect { node: effect, usage, origin, max_ticks });
+
+        if let Some(replaced_effect) = replaced_effect {
+            info!("Queued effect {} (origin {}) replaced", effect_id, get_origin_text(&replaced_effect.origin));
+        }
+
+        Ok(())
+    }
+
+    fn drop_queued_effect(&mut self, effect_id: &str) {
+        if let Some(queued_effect) = self.queued_effects.remove(effect_id) {
+            info!("Dropping queued effect {} (origin {})", effect_id, get_origin_text(&queued_effect.origin));
+        }
+    }
+
+    // Effects of arrays are started with the array id (or <array_id>@<group> for a light group) as the effect id, reject
+    // effects built from an array definition that has since been replaced
+    fn check_array_epoch(&self, effect_id: &str, epoch: Option<defs::ArrayEpoch>) -> Result<(), ArtnetError> {
+        let array_id = get_effect_array_id(effect_id);
+
+        match (epoch, self.array_epochs.get(array_id)) {
+            (Some(epoch), Some(&current_epoch)) if epoch < current_epoch => {
+                Err(ArtnetError::StaleArrayEpoch(array_id.to_string(), epoch, current_epoch).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Current value for runtime nodes of the given array (None if the value was not set since the effect started)
+    pub(super) fn get_effect_value(&self, array_id: &str, value_name: &str) -> Option<&str> {
+        self.effect_values.get(array_id, value_name)
+    }
+
+    pub(super) fn set_array_epoch(&mut self, array_id: Arc<str>, epoch: defs::ArrayEpoch) -> Result<(), ArtnetError> {
+        self.array_epochs.insert(array_id, epoch);
+        Ok(())
+    }
+
+    // Running and queued effects of a renamed array (the array effect and its light group effects) keep running under
+    // the new array ID, so commands on the new ID replace or stop them
+    pub(super) fn rename_array_effects(&mut self, from: &str, to: Arc<str>) -> Result<Vec<Arc<str>>, ArtnetError> {
+        let renamed_effect_id = |effect_id: &str| {
+            is_array_effect_id(effect_id, from).then(|| format!("{to}{}", &effect_id[from.len()..]))
+        };
+
+        let mut effect_ids = self.active_effects.keys().filter_map(|effect_id| renamed_effect_id(effect_id).map(|renamed| (effect_id.clone(), renamed))).collect::<Vec<_>>();
+        effect_ids.sort();
+
+        for (effect_id, renamed) in effect_ids.iter() {
+            if let Some(effect) = self.active_effects.remove(effect_id) {
+                info!("Effect {} renamed to {}", effect_id, renamed);
+                self.active_effects.insert(renamed.clone(), effect);
+            }
+        }
+
+        let queued_effect_ids = self.queued_effects.keys().filter_map(|effect_id| renamed_effect_id(effect_id).map(|renamed| (effect_id.clone(), renamed))).collect::<Vec<_>>();
+
+        for (effect_id, renamed) in queued_effect_ids {
+            if let Some(queued_effect) = self.queued_effects.remove(&effect_id) {
+                self.queued_effects.insert(renamed, queued_effect);
+            }
+        }
+
+        if let Some(limits) = self.channel_limits.remove(from) {
+            self.channel_limits.insert(to.clone(), limits);
+        }
+
+        self.effect_values.rename_array(from, to);
+        Ok(effect_ids.into_iter().map(|(_, renamed)| Arc::from(renamed)).collect())
+    }
+
     fn stop_effect(&mut self, effect_id: &str) -> Result<(), ArtnetError> {
         info!("Stopping effect {}", effect_id);
         self.active_effects.remove(effect_id);
+        self.drop_queued_effect(effect_id);
         Ok(())
     }
 
-    fn tick(&mut self) -> Result<(), ArtnetError> {
+    // Stop the effects of an array according to the scope, returns the (sorted) ids of the stopped effects
+    pub(super) fn stop_effects(
+        &mut self,
+        array_id: &str,
+        scope: StopScope,
+        usage: Option<EffectUsage>,
+    ) -> Result<Vec<Arc<str>>, ArtnetError> {
+        if scope == StopScope::Usage && usage.is_none() {
+            return Err(ArtnetError::MissingStopUsage(array_id.to_string()).into());
+        }
+
+        let mut effect_ids: Vec<Arc<str>> = self
+            .active_effects
+            .iter()
+            .filter(|(effect_id, effect)| match scope {
+                StopScope::Exact => effect_id.as_str() == array_id,
+                StopScope::All => is_array_effect_id(effect_id, array_id),
+                StopScope::Usage => is_array_effect_id(effect_id, array_id) && effect.usage == usage,
+            })
+            .map(|(effect_id, _)| Arc::from(effect_id.as_str()))
+            .collect();
+
+        effect_ids.sort();
+
+        for effect_id in effect_ids.iter() {
+            self.stop_effect(effect_id)?;
+        }
+
+        Ok(effect_ids)
+    }
+
+    // Pause (or resume) an effect, or all active effects if effect_id is None. Returns the (sorted) ids of the affected effects
+    pub(super) fn pause_effects(&mut self, effect_id: Option<&str>, paused: bool) -> Result<Vec<Arc<str>>, ArtnetError> {
+        let mut effect_ids: Vec<Arc<str>> = match effect_id {
+            Some(effect_id) if !self.active_effects.contains_key(effect_id) => {
+                return Err(ArtnetError::EffectNotActive(effect_id.to_string()).into())
+            }
+            Some(effect_id) => vec![Arc::from(effect_id)],
+            None => self.active_effects.keys().map(|effect_id| Arc::from(effect_id.as_str())).collect(),
+        };
+
+        effect_ids.sort();
+
+        for effect_id in effect_ids.iter() {
+            info!("{} effect {}", if paused { "Pausing" } else { "Resuming" }, effect_id);
+            self.active_effects.get_mut(effect_id.as_ref()).unwrap().paused = paused;
+        }
+
+        Ok(effect_ids)
+    }
+
+    // The sender is dropped when the active effect is removed (or now if no effect with this id is active)
+    pub(super) fn wait_effect_done(&mut self, effect_id: &str, done_tx: oneshot::Sender<()>) {
+        if let Some(effect) = self.active_effects.get_mut(effect_id) {
+            effect.done_waiters.push(done_tx);
+        }
+    }
+
+    // Tick the active effects, returns an error for each effect that failed and one for the effects stopped for exceeding
+    // the tick budget
+    pub(super) fn tick(&mut self) -> Vec<Report<ArtnetError>> {
         let mut active_effects = mem::take(&mut self.active_effects);
         let mut completed_effect: Vec<String> = Vec::new();
+        let mut over_budget_effects: Vec<String> = Vec::new();
+        let mut failed_effects = Vec::new();
+
+        for (effect_id, effect) in active_effects.iter_mut().filter(|(_, effect)| !effect.paused) {
+            let start = Instant::now();
+
+            // A failing effect is stopped, the other effects keep running. The error names the effect and the state of
+            // its nodes (e.g. which step of a sequence failed)
+            if let Err(e) = effect.node.tick(self) {
+                let error = e.current_context().to_string();
+                let e = e.change_context(ArtnetError::EffectFailed(effect_id.clone(), effect.get_summary(), error, effect.node.describe(1)));
+
+                failed_effects.push((effect_id.clone(), e));
+                continue;
+            }
 
-        for (effect_id, effect) in active_effects.iter_mut() {
-            effect.tick(self)?;
+            effect.last_tick_duration = start.elapsed();
+            effect.elapsed_ticks += 1;
 
-            if effect.is_done() {
+            if effect.node.is_done() {
                 completed_effect.push(effect_id.clone());
+            } else if let Some(tick_budget) = self.tick_budget {
+                if effect.last_tick_duration > tick_budget.max_tick_duration {
+                    effect.over_budget_ticks += 1;
+
+                    if effect.over_budget_ticks >= tick_budget.max_over_budget_ticks {
+                        over_budget_effects.push(effect_id.clone());
+                    }
+                } else {
+                    effect.over_budget_ticks = 0;
+                }
             }
         }
 
+        // A queued effect replaces the completed one, so its first tick is the tick after the last tick of the completed one
         for id in completed_effect {
-            trace!("Effect {} completed", id);
-            active_effects.remove(&id);
+            if let Some(effect) = active_effects.remove(&id) {
+                trace!("Effect {} completed after {} ticks:\n{}", id, effect.elapsed_ticks, effect.node.describe(1));
+                self.effect_stats.completed(&id, effect.usage, effect.elapsed_ticks);
+            }
+
+            self.start_queued_effect(&mut active_effects, id);
         }
 
+        over_budget_effects.sort();
+        let stopped_effects = over_budget_effects
+            .iter()
+            .filter_map(|id| active_effects.remove(id).map(|effect| format!("{} ({:?})", id, effect.last_tick_duration)))
+            .collect::<Vec<_>>();
+
         self.active_effects = active_effects; // Move it back
+
+        // Effects queued after a stopped effect are dropped with it
+        for effect_id in over_budget_effects.iter() {
+            self.drop_queued_effect(effect_id);
+        }
+
+        failed_effects.sort_by(|(id1, _), (id2, _)| id1.cmp(id2));
+        let mut errors = Vec::new();
+
+        for (effect_id, e) in failed_effects {
+            warn!("Effect {} failed: {:?}", effect_id, e);
+            self.active_effects.remove(&effect_id);
+            self.drop_queued_effect(&effect_id);
+            errors.push(e);
+        }
+
+        if let Some(tick_budget) = self.tick_budget.filter(|_| !stopped_effects.is_empty()) {
+            let stopped_effects = stopped_effects.join(", ");
+
+            warn!("Stopped effects exceeding tick budget: {}", stopped_effects);
+            errors.push(Report::new(ArtnetError::EffectTickBudgetExceeded(
+                format!("{:?}", tick_budget.max_tick_duration),
+                tick_budget.max_over_budget_ticks,
+                stopped_effects,
+            )));
+        }
+
+        errors
+    }
+
+    fn start_queued_effect(&mut self, active_effects: &mut HashMap<String, ActiveEffect>, id: String) {
+        if let Some(queued_effect) = self.queued_effects.remove(&id) {
+            info!("Starting queued effect {} (origin {})", id, get_origin_text(&queued_effect.origin));
+            self.effect_stats.started(&id, queued_effect.usage);
+            active_effects.insert(id, ActiveEffect::new(queued_effect.node, queued_effect.usage, queued_effect.origin, queued_effect.max_ticks));
+        }
+    }
+
+    // Complete the effects that ran for their maximum number of ticks (the command max_ticks or the max_effect_ticks
+    // setting), as if they were done, so an effect queued after them starts
+    pub(super) fn stop_effects_over_max_ticks(&mut self) -> Vec<ToMqttPublisherMessage> {
+        let max_effect_ticks = self.max_effect_ticks;
+        let mut over_max_ticks_effects = self
+            .active_effects
+            .iter()
+            .filter_map(|(effect_id, effect)| {
+                effect.max_ticks.get_limit(max_effect_ticks).filter(|max_ticks| effect.elapsed_ticks >= *max_ticks).map(|max_ticks| (effect_id.clone(), max_ticks))
+            })
+            .collect::<Vec<_>>();
+
+        if over_max_ticks_effects.is_empty() {
+            return Vec::new();
+        }
+
+        over_max_ticks_effects.sort();
+
+        let mut active_effects = mem::take(&mut self.active_effects);
+        let mut warnings = Vec::new();
+
+        for (effect_id, max_ticks) in over_max_ticks_effects {
+            if let Some(effect) = active_effects.remove(&effect_id) {
+                warn!("Effect {} (origin {}) stopped after {} ticks (max ticks)", effect_id, get_origin_text(&effect.origin), max_ticks);
+                warnings.push(ToMqttPublisherMessage::Warning(format!(
+                    "Effect '{effect_id}' (origin {}) was stopped since it ran for its maximum of {max_ticks} ticks",
+                    get_origin_text(&effect.origin),
+                )));
+                self.effect_stats.completed(&effect_id, effect.usage, effect.elapsed_ticks);
+            }
+
+            self.start_queued_effect(&mut active_effects, effect_id);
+        }
+
+        self.active_effects = active_effects;
+        warnings
+    }
+
+    pub(super) fn get_effect_status(&self, effect_id: &str) -> Result<EffectStatus, ArtnetError> {
+        Ok(match self.active_effects.get(effect_id) {
+            Some(effect) => {
+                let remaining_ticks = effect.node.remaining_ticks();
+
+                EffectStatus {
+                    running: true,
+                    paused: effect.paused,
+                    elapsed_ticks: Some(effect.elapsed_ticks),
+                    remaining_ticks,
+                    remaining_ms: remaining_ticks
+                        .map(|ticks| ticks as u64 * self.tick_duration.as_millis() as u64),
+                    last_command: None,
+                    last_dimming_amount: None,
+                    origin: effect.origin.clone(),
+                    description: None,
+                }
+            }
+            None => EffectStatus {
+                running: false,
+                paused: false,
+                elapsed_ticks: None,
+                remaining_ticks: None,
+                remaining_ms: None,
+                last_command: None,
+                last_dimming_amount: None,
+                origin: None,
+                description: None,
+            },
+        })
+    }
+
+    // Description of the runtime node tree of an active effect (None if the effect is not active)
+    pub(super) fn get_effect_description(&self, effect_id: &str) -> Option<String> {
+        self.active_effects.get(effect_id).map(|effect| effect.node.describe(0))
+    }
+
+    pub(super) fn set_channel_limits(&mut self, array_id: &str, limits: Option<Arc<ChannelLimits>>) -> Result<(), ArtnetError> {
+        match limits {
+            Some(limits) if !limits.is_empty() => {
+                self.channel_limits.insert(Arc::from(array_id), limits);
+            }
+            _ => {
+                self.channel_limits.remove(array_id);
+            }
+        }
+
+        self.merge_channel_limits();
         Ok(())
     }
 
+    // Merged once when limits or universe renames change, so setting a channel looks up a single map instead of the limits
+    // of every array. Limits are set with the universe IDs of the array definitions, which may be IDs before a rename, so
+    // they are merged by the ID of the universe they now refer to
+    fn merge_channel_limits(&mut self) {
+        let mut merged_limits = self.channel_limits.values().fold(ChannelLimits::default(), |mut merged_limits, limits| {
+            merged_limits.merge(limits);
+            merged_limits
+        });
+
+        for (from, to) in self.renamed_universes.iter() {
+            merged_limits.rename_universe(from, to);
+        }
+        self.merged_channel_limits = merged_limits;
+    }
+
+    // Effects set channels using the universe IDs of the array definitions, which may be IDs before a rename
     pub fn set_channel(&mut self, universe_id: &str, v: &ChannelValue) -> Result<(), ArtnetError> {
+        match self.renamed_universes.get(universe_id).cloned() {
+            Some(renamed_universe_id) => self.set_universe_channel(&renamed_universe_id, v),
+            None => self.set_universe_channel(universe_id, v),
+        }
+    }
+
+    // Commands set channels of the universe with the given ID (old IDs of renamed universes are redirected by the
+    // subscriber only while their alias has not expired)
+    pub(super) fn set_universe_channel(&mut self, universe_id: &str, v: &ChannelValue) -> Result<(), ArtnetError> {
+        let v = &self.merged_channel_limits.limit(universe_id, v);
+
         trace!("Setting channel {} to {:?}", v.channel, v.value);
 
+        // Limits apply to the aliased channels, the value is set to the actual channels
+        let aliased_value;
+        let v = match self.channel_aliases.translate(universe_id, &v.channel) {
+            Some(channel) => {
+                aliased_value = ChannelValue { channel, value: v.value.clone() };
+                &aliased_value
+            }
+            None => v,
+        };
+
         match self.universes.get_mut(universe_id) {
             Some(u) => {
                 if u.log {
@@ -166,114 +987,827 @@ impl ArtnetManager {
         &self,
         universe_id: &str,
         channel_definition: &ChannelDefinition,
+    ) -> Result<ChannelValue, ArtnetError> {
+        self.get_universe_channel(resolve_universe_id(&self.renamed_universes, universe_id), channel_definition)
+    }
+
+    pub(super) fn get_universe_channel(
+        &self,
+        universe_id: &str,
+        channel_definition: &ChannelDefinition,
     ) -> Result<ChannelValue, ArtnetError> {
         match self.universes.get(universe_id) {
-            Some(u) => u.get_channel(channel_definition),
+            Some(u) => match self.channel_aliases.translate(universe_id, channel_definition) {
+                Some(channel) => Ok(ChannelValue { channel: channel_definition.clone(), value: u.get_channel(&channel)?.value }),
+                None => u.get_channel(channel_definition),
+            },
+            None => Err(ArtnetError::InvalidUniverse(universe_id.to_string()).into()),
+        }
+    }
+
+    // Alias channels to other channels of the same type in the same universe, to None (or to the same channels) removes
+    // the alias. Aliases are kept until removed (or the universe is removed)
+    pub(super) fn set_channel_alias(&mut self, parameters: &defs::AliasCommandParameters) -> Result<(), ArtnetError> {
+        let universe_id = parameters.universe_id.as_str();
+        let universe = self.universes.get(universe_id).ok_or_else(|| ArtnetError::InvalidUniverse(universe_id.to_string()))?;
+        let from = parameters.from.parse::<ChannelDefinition>().map_err(ArtnetError::from)?;
+        universe.get_channel(&from)?;
+
+        let to = match parameters.to.as_deref().map(|to| to.parse::<ChannelDefinition>()).transpose().map_err(ArtnetError::from)? {
+            Some(to) if to == from => None,
+            Some(to) => {
+                let invalid = |reason: &str| ArtnetError::InvalidChannelAlias(universe_id.to_string(), from.to_string(), to.to_string(), reason.to_string());
+
+                if to.channel_type() != from.channel_type() {
+                    return Err(invalid("channel types are different").into());
+                }
+
+                universe.get_channel(&to).change_context_lazy(|| invalid("channel is out of range"))?;
+                Some(to)
+            }
+            None => None,
+        };
+
+        match &to {
+            Some(to) => warn!("Universe {} channels {} are aliased to {}", universe_id, from, to),
+            None => info!("Removing alias of universe {} channels {}", universe_id, from),
+        }
+
+        self.channel_aliases.set(universe_id, from, to)
+    }
+
+    // Channel limits and aliases apply to the frame values as they do to channels set by effects. Unless forced, the frame
+    // is rejected if an active effect is using the universe, since the effect would overwrite the frame on its next tick anyway
+    pub(super) fn set_frame(&mut self, parameters: &defs::SetFrameCommandParameters) -> Result<(), ArtnetError> {
+        let universe_id = parameters.universe_id.as_str();
+        let universe = self.universes.get(universe_id).ok_or_else(|| ArtnetError::InvalidUniverse(universe_id.to_string()))?;
+
+        let data = BASE64_STANDARD
+            .decode(parameters.data_b64.trim())
+            .map_err(|e| ArtnetError::InvalidFrameData(universe_id.to_string(), e.to_string()))?;
+
+        let channel_count = universe.get_channel_count();
+        if data.is_empty() || parameters.offset as usize + data.len() > channel_count as usize {
+            return Err(ArtnetError::FrameOutOfRange(universe.description.clone(), parameters.offset, data.len(), channel_count).into());
+        }
+
+        if !parameters.force {
+            let mut effect_ids = self
+                .active_effects
+                .iter()
+                .filter(|(_, effect)| effect.node.get_universe_ids().into_iter().any(|id| resolve_universe_id(&self.renamed_universes, id) == universe_id))
+                .map(|(effect_id, _)| effect_id.as_str())
+                .collect::<Vec<_>>();
+
+            if !effect_ids.is_empty() {
+                effect_ids.sort();
+                return Err(ArtnetError::FrameUniverseInUse(universe_id.to_string(), effect_ids.join(", ")).into());
+            }
+        }
+
+        let dimming_amount = parameters.dimming_amount.unwrap_or(defs::DIMMING_AMOUNT_MAX);
+        let values = data.into_iter().zip(parameters.offset..).map(|(value, channel)| {
+            let value = (value as defs::DimmingAmount * dimming_amount / defs::DIMMING_AMOUNT_MAX) as u8;
+            let value = self.merged_channel_limits.limit_channel(universe_id, channel, value);
+
+            // Limits apply to the aliased channels, the value is set to the actual channels
+            (self.channel_aliases.translate_single(universe_id, channel), value)
+        }).collect::<Vec<_>>();
+
+        debug!("Setting universe {} frame of {} channels at offset {} (origin {})", universe_id, values.len(), parameters.offset, get_origin_text(&parameters.origin));
+        self.universes.get_mut(universe_id).unwrap().set_frame(&values);
+        Ok(())
+    }
+
+    // All the values are read and all the destination channels are checked before anything is set (so copying overlapping
+    // channels of the same universe copies the values from before the copy)
+    pub(super) fn copy_channels(&mut self, parameters: &defs::CopyCommandParameters) -> Result<(), ArtnetError> {
+        let from_universe = self.universes
+            .get(parameters.from_universe.as_str())
+            .ok_or_else(|| ArtnetError::InvalidUniverse(parameters.from_universe.clone()))?;
+        let to_channel_count = self.universes
+            .get(parameters.to_universe.as_str())
+            .ok_or_else(|| ArtnetError::InvalidUniverse(parameters.to_universe.clone()))?
+            .get_channel_count();
+
+        let channels = match (&parameters.channels, parameters.all) {
+            (Some(channels), false) => parse_list::<ChannelDefinition>(channels, ',').map_err(ArtnetError::from)?,
+            (None, true) => (0..from_universe.get_channel_count()).map(ChannelDefinition::Single).collect(),
+            _ => return Err(ArtnetError::InvalidCopyChannels(parameters.from_universe.clone()).into()),
+        };
+
+        let mut values = Vec::new();
+
+        for channel in channels.iter() {
+            let value = self.get_universe_channel(&parameters.from_universe, channel)?.value;
+            let to_channel = channel
+                .channels()
+                .into_iter()
+                .map(|c| u16::try_from(c as i32 + parameters.to_offset).ok().filter(|c| *c < to_channel_count))
+                .collect::<Option<Vec<_>>>()
+                .and_then(|to_channels| ChannelDefinition::from_offsets(channel.channel_type(), 0, &to_channels))
+                .ok_or_else(|| ArtnetError::CopyOutOfRange(channel.to_string(), parameters.to_offset, parameters.to_universe.clone(), to_channel_count))?;
+
+            values.push(ChannelValue { channel: to_channel, value });
+        }
+
+        info!("Copying {} channels of universe {} to universe {} with offset {} (origin {})",
+            values.len(), parameters.from_universe, parameters.to_universe, parameters.to_offset, get_origin_text(&parameters.origin));
+
+        for v in values.iter() {
+            self.set_universe_channel(&parameters.to_universe, v)?;
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn blackout_universe(&mut self, universe_id: &str, restore: bool) -> Result<(), ArtnetError> {
+        match self.universes.get_mut(universe_id) {
+            Some(u) => {
+                if restore {
+                    info!("Restoring universe {}", universe_id);
+                    u.restore();
+                } else {
+                    info!("Blackout universe {}", universe_id);
+                    u.blackout();
+                }
+                Ok(())
+            }
             None => Err(ArtnetError::InvalidUniverse(universe_id.to_string()).into()),
         }
     }
 
-    fn send_modified_universes(&mut self) -> Result<(), ArtnetError> {
-        for (universe_id, universe) in self.universes.iter_mut() {
-            if !universe.modified {
-                universe.non_modified_ticks += 1;
-                if universe.non_modified_ticks >= SEND_UNMODIFIED_UNIVERSE_EVERY {
-                    universe.modified = true;
-                }
-            }
+    pub(super) fn set_universe_group(&mut self, name: &str, definition: Option<defs::UniverseGroupDefinition>) -> Result<(), ArtnetError> {
+        match definition {
+            Some(definition) => {
+                if definition.universes.is_empty() {
+                    return Err(ArtnetError::EmptyUniverseGroup(name.to_string()).into());
+                }
+
+                if let Some(member) = definition.universes.iter().find(|universe_id| !self.universes.contains_key(universe_id.as_ref())) {
+                    return Err(ArtnetError::UniverseGroupMemberNotFound(name.to_string(), member.to_string()).into());
+                }
+
+                self.universe_groups.insert(Arc::from(name), definition.universes);
+            }
+            None => {
+                self.universe_groups
+                    .remove(name)
+                    .ok_or_else(|| ArtnetError::UniverseGroupNotFound(name.to_string()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Universes a command applies to, all of them are verified to exist so a group command changes either all or none
+    // of its universes
+    pub(super) fn get_target_universes(&self, target: &defs::UniverseTarget) -> Result<Vec<Arc<str>>, ArtnetError> {
+        let universe_ids = match target {
+            defs::UniverseTarget::Universe(universe_id) => vec![universe_id.clone()],
+            defs::UniverseTarget::Group(group) => self.universe_groups
+                .get(group)
+                .cloned()
+                .ok_or_else(|| ArtnetError::UniverseGroupNotFound(group.to_string()))?,
+        };
+
+        if let Some(universe_id) = universe_ids.iter().find(|universe_id| !self.universes.contains_key(universe_id.as_ref())) {
+            return Err(match target {
+                defs::UniverseTarget::Universe(_) => ArtnetError::InvalidUniverse(universe_id.to_string()),
+                defs::UniverseTarget::Group(group) => ArtnetError::UniverseGroupMemberNotFound(group.to_string(), universe_id.to_string()),
+            }.into());
+        }
+
+        Ok(universe_ids)
+    }
+
+    pub(super) fn blackout_universes(&mut self, target: &defs::UniverseTarget, restore: bool) -> Result<(), ArtnetError> {
+        for universe_id in self.get_target_universes(target)? {
+            self.blackout_universe(&universe_id, restore)?;
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn set_send_enabled(&mut self, target: &defs::UniverseTarget, enable: bool) -> Result<(), ArtnetError> {
+        for universe_id in self.get_target_universes(target)? {
+            info!("Sending to universe {} {}", universe_id, if enable { "enabled" } else { "disabled" });
+            self.universes.get_mut(universe_id.as_ref()).unwrap().set_send_disabled(!enable);
+            self.send_disabled_warnings.remove(universe_id.as_ref());
+        }
+
+        Ok(())
+    }
+
+    // Warn (at most once per SEND_DISABLED_WARNING_INTERVAL for each universe) when channels of a universe whose
+    // sending is disabled are set, since it looks like the lights do not respond
+    pub(super) fn evaluate_send_disabled(&mut self, now: Instant) -> Vec<ToMqttPublisherMessage> {
+        let mut warnings = Vec::new();
+
+        for (universe_id, universe) in self.universes.iter().filter(|(_, universe)| universe.modified && universe.disable_send) {
+            let warned = self.send_disabled_warnings
+                .get(universe_id)
+                .is_some_and(|last_warning| now.saturating_duration_since(*last_warning) < SEND_DISABLED_WARNING_INTERVAL);
+
+            if !warned {
+                warn!("Universe {} channels were set but sending is disabled", universe_id);
+                warnings.push(ToMqttPublisherMessage::Warning(format!(
+                    "Universe {universe_id} ({}): channels were set but sending is disabled (disable_send), output is suppressed",
+                    universe.description,
+                )));
+                self.send_disabled_warnings.insert(universe_id.clone(), now);
+            }
+        }
+
+        warnings
+    }
+
+    pub(super) fn get_universes_send_status(&self, target: &defs::UniverseTarget) -> Result<Vec<(Arc<str>, UniverseSendStatus)>, ArtnetError> {
+        self.get_target_universes(target)?
+            .into_iter()
+            .map(|universe_id| self.get_universe_send_status(&universe_id).map(|status| (universe_id, status)))
+            .collect()
+    }
+
+    // Send all modified universes, a universe with a send divisor waits until send_divisor ticks passed since it was
+    // last sent. A universe that fails to send is retried on the next tick, its becoming unreachable (and reachable
+    // again) is reported once instead of on every failed send. Repeats of a keep-alive burst are sent on the following
+    // (paced) ticks while the universe is not modified
+    pub(super) fn send_modified_universes(&mut self) -> Vec<ToMqttPublisherMessage> {
+        let mut notifications = Vec::new();
+
+        for (universe_id, universe) in self.universes.iter_mut() {
+            universe.advance_slewing_channels();
+            universe.ticks_since_send = universe.ticks_since_send.saturating_add(1);
+
+            let refresh_ticks = universe.get_refresh_ticks(self.tick_duration);
+            if !universe.modified {
+                universe.non_modified_ticks += 1;
+                if universe.non_modified_ticks >= refresh_ticks {
+                    universe.modified = true;
+                }
+            }
+            let refresh = universe.non_modified_ticks >= refresh_ticks;     // Also when retrying a failed refresh
+
+            if !universe.modified && universe.is_keepalive_burst_pending() && universe.ticks_since_send >= universe.send_divisor {
+                match universe.send_keepalive_repeat() {
+                    Ok(_) if !universe.disable_send && !universe.quiet => self.metrics.add_packet_sent(universe_id),
+                    Ok(_) => {}
+                    Err(e) => debug!("Sending keep-alive repeat of universe {} failed: {}", universe_id, e),
+                }
+                continue;
+            }
+
+            if universe.modified && universe.ticks_since_send < universe.send_divisor {
+                universe.paced_ticks += 1;
+                continue;
+            }
+
+            if universe.modified {
+                debug!("Sending packet to {}", universe_id);
+                let was_reachable = universe.send_status.reachable;
+
+                if let Err(e) = universe.send() {
+                    self.metrics.add_send_failure(universe_id);
+
+                    let consecutive_failures = universe.send_status.consecutive_failures;
+                    debug!("Sending universe {} failed ({} consecutive failures): {}", universe_id, consecutive_failures, e);
+
+                    if consecutive_failures == self.unreachable_threshold {
+                        universe.send_status.reachable = false;
+                        warn!("Universe {} unreachable ({} consecutive send failures)", universe_id, consecutive_failures);
+                        notifications.push(ToMqttPublisherMessage::Error(format!(
+                            "Universe {universe_id} unreachable ({consecutive_failures} consecutive send failures): {e}"
+                        ), None, ErrorCategory::Runtime));
+                        notifications.push(ToMqttPublisherMessage::UniverseSendStatus(Arc::from(universe_id.as_str()), universe.send_status.clone()));
+                    }
+                    continue;
+                }
+
+                if !universe.disable_send && !universe.quiet {
+                    self.metrics.add_packet_sent(universe_id);
+                }
+
+                if refresh {
+                    universe.start_keepalive_burst();
+                }
+
+                if !was_reachable {
+                    info!("Universe {} is reachable again", universe_id);
+                    notifications.push(ToMqttPublisherMessage::UniverseSendStatus(Arc::from(universe_id.as_str()), universe.send_status.clone()));
+                }
+
+                if let Some(sim_frames) = &self.sim_frames {
+                    // Sending fails only if no sim viewer is connected
+                    let _ = sim_frames.send(SimFrame {
+                        universe_id: universe_id.clone(),
+                        channels: artnet_packet::get_data(&universe.packet_bytes).to_vec(),
+                    });
+                }
+            }
+        }
+
+        notifications
+    }
+
+    // Universes entering their quiet hours send a final all-zero frame, universes leaving them send their channels on
+    // this tick. The changed send status is published
+    pub(super) fn evaluate_quiet_hours(&mut self, now: chrono::DateTime<chrono::Utc>) -> Vec<ToMqttPublisherMessage> {
+        let mut notifications = Vec::new();
+
+        for (universe_id, universe) in self.universes.iter_mut() {
+            let changed = match universe.update_quiet(now) {
+                Ok(changed) => changed,
+                Err(e) => {
+                    warn!("Sending final frame of universe {} before its quiet hours failed: {}", universe_id, e);
+                    true
+                }
+            };
+
+            if changed {
+                info!("Universe {} {} its quiet hours", universe_id, if universe.quiet { "entered" } else { "left" });
+                notifications.push(ToMqttPublisherMessage::UniverseSendStatus(Arc::from(universe_id.as_str()), universe.send_status.clone()));
+            }
+        }
+
+        notifications
+    }
+
+    pub(super) fn get_universe_send_status(&self, universe_id: &str) -> Result<UniverseSendStatus, ArtnetError> {
+        match self.universes.get(universe_id) {
+            Some(universe) => Ok(universe.send_status.clone()),
+            None => Err(ArtnetError::InvalidUniverse(universe_id.to_string()).into()),
+        }
+    }
+
+    pub(super) fn set_watcher(&mut self, name: &str, definition: Option<defs::WatcherDefinition>) -> Result<(), ArtnetError> {
+        match definition {
+            Some(definition) => {
+                let mut watcher = Watcher::new(name, definition)?;
+
+                if let Some(universe) = self.universes.get(watcher.universe_id.as_ref()) {
+                    if let DimmerValue::Single(value) = universe.get_channel(&ChannelDefinition::Single(watcher.channel))?.value {
+                        watcher.set_initial_value(value);
+                    }
+                }
+
+                info!("Set watcher {} on universe {} channel {}", name, watcher.universe_id, watcher.channel);
+                self.watchers.insert(Arc::from(name), watcher);
+            }
+            None => {
+                if self.watchers.remove(name).is_none() {
+                    return Err(ArtnetError::WatcherNotFound(name.to_string()).into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn set_monitor(&mut self, name: &str, definition: Option<defs::MonitorDefinition>) -> Result<(), ArtnetError> {
+        match definition {
+            Some(definition) => {
+                let monitor = Monitor::new(name, definition)?;
+
+                // Unlike watchers, the channel is checked against the universe channel count so the universe must be defined
+                match self.universes.get(monitor.universe_id.as_ref()) {
+                    Some(universe) => universe.validate_channel(monitor.channel)?,
+                    None => return Err(ArtnetError::InvalidUniverse(monitor.universe_id.to_string()).into()),
+                }
+
+                info!("Set monitor {} on universe {} channel {} (publishing to {})", name, monitor.universe_id, monitor.channel, monitor.topic);
+                self.monitors.insert(Arc::from(name), monitor);
+            }
+            None => {
+                if self.monitors.remove(name).is_none() {
+                    return Err(ArtnetError::MonitorNotFound(name.to_string()).into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns messages publishing the value of monitored channels (monitors on removed universes are skipped)
+    pub(super) fn evaluate_monitors(&mut self, now: Instant) -> Vec<ToMqttPublisherMessage> {
+        let mut messages = Vec::new();
+
+        for monitor in self.monitors.values_mut() {
+            let value = match self.universes.get(monitor.universe_id.as_ref()).and_then(|universe| universe.channel_data().get(monitor.channel as usize)) {
+                Some(value) => *value,
+                None => continue,
+            };
+
+            if monitor.update(value, now) {
+                messages.push(ToMqttPublisherMessage::Publish(monitor.topic.clone(), Arc::from(value.to_string())));
+            }
+        }
+
+        messages
+    }
+
+    // Evaluate watchers whose channel was set since the last evaluation, returns messages of watchers that fired
+    pub(super) fn evaluate_watchers(&mut self) -> Vec<ToMqttPublisherMessage> {
+        let mut messages = Vec::new();
+
+        for (name, watcher) in self.watchers.iter_mut() {
+            let value = match self.universes.get(watcher.universe_id.as_ref()) {
+                Some(universe) if universe.changed_channels.contains(&watcher.channel) => universe.channel_data()[watcher.channel as usize],
+                _ => continue,
+            };
+
+            if watcher.update(value) {
+                info!("Watcher {} fired (value {}), publishing to {}", name, value, watcher.topic);
+                messages.push(ToMqttPublisherMessage::Publish(watcher.topic.clone(), watcher.payload.clone()));
+            }
+        }
+
+        for universe in self.universes.values_mut() {
+            universe.changed_channels.clear();
+        }
+
+        messages
+    }
+
+    pub(super) fn get_diagnostics(&self) -> defs::ArtnetManagerDiagnostics {
+        defs::ArtnetManagerDiagnostics {
+            universes: self.universes.iter().map(|(universe_id, universe)| (universe_id.clone(), defs::UniverseDiagnostics {
+                description: universe.description.clone(),
+                send_disabled: universe.disable_send,
+                channels: universe.get_channel_count(),
+                modified: universe.modified,
+                non_modified_ticks: universe.non_modified_ticks,
+                sequence: artnet_packet::get_sequence(&universe.packet_bytes),
+                send_divisor: universe.send_divisor,
+                sent_ticks: universe.sent_ticks,
+                paced_ticks: universe.paced_ticks,
+            })).collect(),
+            active_effects: self.active_effects.iter().map(|(effect_id, effect)| (effect_id.clone(), defs::ActiveEffectDiagnostics {
+                elapsed_ticks: effect.elapsed_ticks,
+                paused: effect.paused,
+                origin: effect.origin.clone(),
+                queued: self.queued_effects.get(effect_id).map(|queued_effect| defs::QueuedEffectDiagnostics {
+                    usage: queued_effect.usage,
+                    origin: queued_effect.origin.clone(),
+                }),
+            })).collect(),
+            channel_aliases: self.channel_aliases.get_diagnostics(),
+            max_messages_per_tick: self.max_messages_per_tick,
+        }
+    }
+
+    // Statistics of the effects started since the last reset
+    pub(super) fn get_effect_stats(&mut self, reset: bool) -> Vec<defs::EffectStatsEntry> {
+        let entries = self.effect_stats.get_entries();
+
+        if reset {
+            info!("Resetting effect statistics");
+            self.effect_stats.reset();
+        }
+
+        entries
+    }
+
+    // State left behind by stopped effects and removed universes, empty once all effects were stopped (see soak tests)
+    #[cfg(test)]
+    pub(crate) fn find_leaks(&self) -> Vec<String> {
+        let mut leaks = Vec::new();
+        let controller_addresses = self.universes
+            .values()
+            .map(|universe| universe.controller_address)
+            .chain(self.retained_controllers.keys().copied())
+            .collect::<HashSet<_>>();
+
+        if !self.active_effects.is_empty() {
+            leaks.push(format!("{} active effects after all effects were stopped", self.active_effects.len()));
+        }
+
+        if !self.queued_effects.is_empty() {
+            leaks.push(format!("{} queued effects after all effects were stopped", self.queued_effects.len()));
+        }
+
+        if self.controllers.len() != controller_addresses.len() {
+            leaks.push(format!("{} controllers for {} controller addresses in use", self.controllers.len(), controller_addresses.len()));
+        }
+
+        for universe_id in self.send_disabled_warnings.keys().filter(|universe_id| !self.universes.contains_key(*universe_id)) {
+            leaks.push(format!("send disabled warning of removed universe {universe_id}"));
+        }
+
+        for (from, to) in self.renamed_universes.iter().filter(|(_, to)| !self.universes.contains_key(to.as_ref())) {
+            leaks.push(format!("universe {from} renamed to removed universe {to}"));
+        }
+
+        leaks
+    }
+
+    // Parse the channels and the target, and verify that the target has a value for each of the channels. Nothing is set
+    // before the whole request is validated, so an invalid request does not leave the lights partially set
+    fn get_set_channels_target(
+        &self,
+        parameters: &defs::SetChannelsParameters,
+    ) -> Result<(RelativeTargetValue, Vec<ChannelDefinition>, defs::DimmingAmount), ArtnetError> {
+        let target = parameters.target.parse::<RelativeTargetValue>().map_err(ArtnetError::from)?;
+
+        // Dimming presets are resolved (by the array manager) before the command is sent
+        let dimming_amount = match &parameters.dimming_amount {
+            None => defs::DIMMING_AMOUNT_MAX,
+            Some(defs::DimmingAmountOrPreset::Amount(dimming_amount)) => *dimming_amount,
+            Some(defs::DimmingAmountOrPreset::Preset(name)) => return Err(ArtnetError::UnresolvedDimmingPreset(name.to_string()).into()),
+        };
+        let channels = parse_list::<ChannelDefinition>(&parameters.channels, ',').map_err(ArtnetError::from)?;
 
-            if universe.modified {
-                debug!("Sending packet to {}", universe_id);
-                universe.send()?;
+        let mut missing_channels = Vec::new();
+
+        for channel_definition in channels.iter() {
+            let current = self.get_universe_channel(&parameters.universe_id, channel_definition)?.value;
+
+            if target.get(&current).is_none() {
+                missing_channels.push(channel_definition.to_string());
             }
         }
-        Ok(())
+
+        if !missing_channels.is_empty() {
+            return Err(ArtnetError::MissingTargetValues(missing_channels.join(", "), parameters.target.to_string()).into());
+        }
+
+        Ok((target, channels, dimming_amount))
     }
 
-    fn set_channels(
+    fn apply_set_channels(
         &mut self,
         parameters: &defs::SetChannelsParameters,
+        target: &RelativeTargetValue,
+        channels: &[ChannelDefinition],
+        dimming_amount: defs::DimmingAmount,
     ) -> Result<(), ArtnetError> {
-        let into_context = || ArtnetError::Context(format!("Setting channels {:?}", parameters));
-        let mut target = parameters.target.parse::<TargetValue>()?;
-        let channels = parameters
-            .channels
-            .split(',')
-            .map(|c| c.parse::<ChannelDefinition>().change_context_lazy(into_context))
-            .collect::<Result<Vec<ChannelDefinition>, _>>()?;
-
-        if let Some(dimming_amount) = parameters.dimming_amount {
-            target = target.get_dimmed_value(dimming_amount);
-        }
-
+        info!("Setting universe {} channels {} to {} (origin {})", parameters.universe_id, parameters.channels, parameters.target, get_origin_text(&parameters.origin));
         for channel_definition in channels.iter() {
-            let channel_value = target.get(channel_definition);
+            // Relative target components are applied to the current channel value
+            let current = self.get_universe_channel(&parameters.universe_id, channel_definition)?.value;
 
-            if let Some(channel_value) = channel_value {
+            if let Some(channel_value) = target.get(&current) {
                 let channel_value = ChannelValue {
                     channel: channel_definition.clone(),
-                    value: channel_value,
+                    value: channel_value.get_dimmed_value(dimming_amount),
                 };
-                self.set_channel(&parameters.universe_id, &channel_value)?;
-            } else {
-                return Err(ArtnetError::MissingTargetValue(
-                    channel_definition.to_string(),
-                    parameters.target.to_string(),
-                ).into());
+                debug!("Set universe {} channel {} (current {:?}) to {:?}", parameters.universe_id, channel_definition, current, channel_value.value);
+                self.set_universe_channel(&parameters.universe_id, &channel_value)?;
             }
         }
 
         Ok(())
     }
 
+    pub(super) fn set_channels(
+        &mut self,
+        parameters: &defs::SetChannelsParameters,
+    ) -> Result<(), ArtnetError> {
+        let (target, channels, dimming_amount) = self.get_set_channels_target(parameters)?;
+        self.apply_set_channels(parameters, &target, &channels, dimming_amount)
+    }
+
+    // All or nothing, entries are applied (in order) only if all of them are valid
+    pub(super) fn set_channels_batch(
+        &mut self,
+        batch: &[defs::SetChannelsParameters],
+    ) -> Result<(), ArtnetError> {
+        let targets = batch
+            .iter()
+            .enumerate()
+            .map(|(index, parameters)| {
+                self.get_set_channels_target(parameters).map_err(|e| {
+                    let reason = e.to_string();
+                    e.change_context(ArtnetError::InvalidSetChannelsBatchEntry(index, reason))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (parameters, (target, channels, dimming_amount)) in batch.iter().zip(targets.iter()) {
+            self.apply_set_channels(parameters, target, channels, *dimming_amount)?;
+        }
+
+        Ok(())
+    }
+
     fn handle_message(&mut self, message: ToArtnetManagerMessage) {
         match message {
             ToArtnetManagerMessage::AddUniverse(universe_id, definition, reply_tx) => reply_tx
                 .send(self.add_universe(&universe_id, definition))
                 .unwrap(),
+            ToArtnetManagerMessage::ValidateUniverse(universe_id, definition, reply_tx) => {
+                reply_tx.send(self.validate_universe(&universe_id, &definition)).unwrap()
+            }
             ToArtnetManagerMessage::RemoveUniverse(universe_id, sender) => {
                 sender.send(self.remove_universe(&universe_id)).unwrap()
             }
-            ToArtnetManagerMessage::StartEffect(effect_id, effect_node_runtime, reply_tx) => {
+            ToArtnetManagerMessage::ClearAll(reply_tx) => reply_tx.send(self.clear_all()).unwrap(),
+            ToArtnetManagerMessage::RenameUniverse(from, to, reply_tx) => {
+                reply_tx.send(self.rename_universe(&from, to)).unwrap()
+            }
+            ToArtnetManagerMessage::StartEffect(request, reply_tx) => {
+                let StartEffectRequest { effect_id, node, usage, epoch, origin, enqueue, max_ticks } = request;
+
                 reply_tx
-                    .send(self.start_effect(&effect_id, effect_node_runtime))
+                    .send(self.check_array_epoch(&effect_id, epoch).and_then(|_| {
+                        if enqueue {
+                            self.enqueue_effect(&effect_id, node, usage, origin, max_ticks)
+                        } else {
+                            self.start_effect(&effect_id, node, usage, origin, max_ticks)
+                        }
+                    }))
                     .unwrap()
             }
-            ToArtnetManagerMessage::StopEffect(effect_id, sender) => {
-                sender.send(self.stop_effect(&effect_id)).unwrap()
+            ToArtnetManagerMessage::PauseEffects(effect_id, paused, reply_tx) => {
+                reply_tx.send(self.pause_effects(effect_id.as_deref(), paused)).unwrap()
+            }
+            ToArtnetManagerMessage::WaitEffectDone(effect_id, done_tx) => {
+                self.wait_effect_done(&effect_id, done_tx)
+            }
+            ToArtnetManagerMessage::StopEffects(array_id, scope, usage, reply_tx) => {
+                reply_tx.send(self.stop_effects(&array_id, scope, usage)).unwrap()
             }
             ToArtnetManagerMessage::SetChannels(parameters, sender) => {
                 sender.send(self.set_channels(&parameters)).unwrap()
             }
+            ToArtnetManagerMessage::SetChannelsBatch(batch, sender) => {
+                sender.send(self.set_channels_batch(&batch)).unwrap()
+            }
+            ToArtnetManagerMessage::SetChannelAlias(parameters, sender) => {
+                sender.send(self.set_channel_alias(&parameters)).unwrap()
+            }
+            ToArtnetManagerMessage::SetFrame(parameters, sender) => {
+                sender.send(self.set_frame(&parameters)).unwrap()
+            }
+            ToArtnetManagerMessage::CopyChannels(parameters, sender) => {
+                sender.send(self.copy_channels(&parameters)).unwrap()
+            }
+            ToArtnetManagerMessage::BlackoutUniverse(target, reply_tx) => {
+                reply_tx.send(self.blackout_universes(&target, false)).unwrap()
+            }
+            ToArtnetManagerMessage::RestoreUniverse(target, reply_tx) => {
+                reply_tx.send(self.blackout_universes(&target, true)).unwrap()
+            }
+            ToArtnetManagerMessage::SetSendEnabled(target, enable, reply_tx) => {
+                reply_tx.send(self.set_send_enabled(&target, enable)).unwrap()
+            }
+            ToArtnetManagerMessage::SetUniverseGroup(name, definition, reply_tx) => {
+                reply_tx.send(self.set_universe_group(&name, definition)).unwrap()
+            }
+            ToArtnetManagerMessage::GetEffectStatus(effect_id, verbose, reply_tx) => {
+                let status = self.get_effect_status(&effect_id).map(|status| EffectStatus {
+                    description: if verbose { self.get_effect_description(&effect_id) } else { None },
+                    ..status
+                });
+
+                reply_tx.send(status).unwrap()
+            }
+            ToArtnetManagerMessage::SetChannelLimits(array_id, limits, reply_tx) => {
+                reply_tx.send(self.set_channel_limits(&array_id, limits)).unwrap()
+            }
+            ToArtnetManagerMessage::SetArrayEpoch(array_id, epoch, reply_tx) => {
+                reply_tx.send(self.set_array_epoch(array_id, epoch)).unwrap()
+            }
+            ToArtnetManagerMessage::RenameArrayEffects(from, to, reply_tx) => {
+                reply_tx.send(self.rename_array_effects(&from, to)).unwrap()
+            }
+            ToArtnetManagerMessage::SetEffectValue(array_id, value_name, value, reply_tx) => {
+                self.effect_values.set(array_id, value_name, value);
+                reply_tx.send(Ok(())).unwrap()
+            }
+            ToArtnetManagerMessage::GetUniverseSendStatus(target, reply_tx) => {
+                reply_tx.send(self.get_universes_send_status(&target)).unwrap()
+            }
+            ToArtnetManagerMessage::SetMonitor(name, definition, reply_tx) => {
+                reply_tx.send(self.set_monitor(&name, definition)).unwrap()
+            }
+            ToArtnetManagerMessage::SetWatcher(name, definition, reply_tx) => {
+                reply_tx.send(self.set_watcher(&name, definition)).unwrap()
+            }
+            ToArtnetManagerMessage::GetDiagnostics(reply_tx) => {
+                reply_tx.send(self.get_diagnostics()).unwrap()
+            }
+            ToArtnetManagerMessage::GetEffectStats(reset, reply_tx) => {
+                reply_tx.send(self.get_effect_stats(reset)).unwrap()
+            }
+            ToArtnetManagerMessage::GetUniverseDefinitionHash(universe_id, reply_tx) => {
+                reply_tx.send(self.universes.get(universe_id.as_ref()).map(|universe| universe.definition_hash.clone())).unwrap()
+            }
+        }
+    }
+
+    // Called on every tick timer. Must never wait for the MQTT publisher, otherwise lights freeze while the broker is down
+    pub(super) fn tick_and_publish(&mut self, to_mqtt_publisher: &async_channel::Sender<ToMqttPublisherMessage>) {
+        let start = Instant::now();
+        let mut messages = Vec::new();
+
+        for e in self.tick() {
+            messages.push(ToMqttPublisherMessage::Error(e.to_string(), None, ErrorCategory::Runtime));
+        }
+
+        messages.extend(self.stop_effects_over_max_ticks());
+        self.apply_idle_values(Instant::now());
+        messages.extend(self.evaluate_watchers());
+        messages.extend(self.evaluate_monitors(Instant::now()));
+        messages.extend(self.evaluate_send_disabled(Instant::now()));
+        messages.extend(self.evaluate_quiet_hours(chrono::Utc::now()));
+        messages.extend(self.send_modified_universes());
+        self.metrics.add_tick(start.elapsed(), self.active_effects.len());
+        self.publish(to_mqtt_publisher, messages);
+        self.expire_retained_controllers(Instant::now());
+    }
+
+    // Messages that do not fit in the publisher channel are dropped and counted, the count is published once
+    // there is room in the channel again
+    fn publish(&mut self, to_mqtt_publisher: &async_channel::Sender<ToMqttPublisherMessage>, messages: Vec<ToMqttPublisherMessage>) {
+        if self.dropped_publishes > 0 {
+            let dropped_notice = ToMqttPublisherMessage::Error(format!(
+                "{} messages were dropped while the MQTT publisher was not available", self.dropped_publishes
+            ), None, ErrorCategory::Runtime);
+
+            if to_mqtt_publisher.try_send(dropped_notice).is_ok() {
+                info!("MQTT publisher is available again, {} messages were dropped", self.dropped_publishes);
+                self.dropped_publishes = 0;
+            }
+        }
+
+        for message in messages {
+            if let Err(e) = to_mqtt_publisher.try_send(message) {
+                if self.dropped_publishes == 0 {
+                    warn!("MQTT publisher is not available, dropping messages");
+                }
+
+                debug!("Dropped message {:?}", e.into_inner());
+                self.dropped_publishes += 1;
+            }
+        }
+    }
+
+    // Handle the message and up to MAX_MESSAGES_PER_BATCH - 1 pending messages without waiting. Handling stops when
+    // the next tick is due, the remaining messages are handled after the tick
+    pub(super) fn handle_message_batch(
+        &mut self,
+        message: ToArtnetManagerMessage,
+        receiver: &mut ManagerReceiver<ToArtnetManagerMessage>,
+        next_tick: tokio::time::Instant,
+    ) -> usize {
+        let mut handled = 0;
+        let mut message = Some(message);
+
+        while let Some(current_message) = message.take() {
+            self.handle_message(current_message);
+            handled += 1;
+
+            if handled < MAX_MESSAGES_PER_BATCH && tokio::time::Instant::now() < next_tick {
+                message = receiver.try_recv();
+            }
+        }
+
+        self.messages_since_tick += handled;
+        handled
+    }
+
+    fn update_messages_per_tick(&mut self) {
+        if self.messages_since_tick > self.max_messages_per_tick {
+            self.max_messages_per_tick = self.messages_since_tick;
+            debug!("New maximum of {} messages handled between ticks", self.max_messages_per_tick);
         }
+
+        self.messages_since_tick = 0;
     }
 
     pub async fn run(
         &mut self,
         cancel: CancellationToken,
-        mut receiver: Receiver<ToArtnetManagerMessage>,
+        mut receiver: ManagerReceiver<ToArtnetManagerMessage>,
         to_mqtt_publisher: async_channel::Sender<ToMqttPublisherMessage>,
     ) {
         // Set tick timer
-        let mut tick_timer = interval(TICK_DURATION);
+        let mut tick_timer = interval(self.tick_duration);
+        let mut next_tick = tokio::time::Instant::now();
 
+        // Biased, so a due tick is never delayed by a backlog of messages (e.g. retained definitions replayed at startup)
         loop {
             select! {
+                biased;
+
                 _ = cancel.cancelled() => break,
 
-                _ = tick_timer.tick() => {
-                    if let Err(e) = self.tick() {
-                        to_mqtt_publisher.send(ToMqttPublisherMessage::Error(e.to_string())).await.unwrap();
-                    }
+                tick_time = tick_timer.tick() => {
+                    next_tick = tick_time + self.tick_duration;
+                    self.update_messages_per_tick();
+                    self.tick_and_publish(&to_mqtt_publisher);
 
-                    if let Err(e) = self.send_modified_universes() {
-                        to_mqtt_publisher.send(ToMqttPublisherMessage::Error(e.to_string())).await.unwrap();
+                    if self.update_settings() {
+                        tick_timer = interval_at(next_tick, self.tick_duration);
                     }
-                },
+                }
 
                 message = receiver.recv() => match message {
                     None => break,
-                    Some(message) => self.handle_message(message),
+                    Some(message) => {
+                        self.handle_message_batch(message, &mut receiver, next_tick);
+                    }
                 },
             }
         }
@@ -298,63 +1832,276 @@ impl ArtnetController {
     }
 }
 
+fn step_toward(current: u8, target: u8, max_delta: u8) -> u8 {
+    if target > current {
+        current.saturating_add(max_delta).min(target)
+    } else {
+        current.saturating_sub(max_delta).max(target)
+    }
+}
+
+fn get_origin_text(origin: &Option<Arc<str>>) -> &str {
+    origin.as_deref().unwrap_or("unknown")
+}
+
+const MAX_PORT_ADDRESS: u16 = 0x7fff;
+
+// Effects of an array are the array effect (the array ID) and its light group effects (<array_id>@<group>)
+fn is_array_effect_id(effect_id: &str, array_id: &str) -> bool {
+    effect_id.strip_prefix(array_id).is_some_and(|lights| lights.is_empty() || lights.starts_with('@'))
+}
+
+fn get_effect_array_id(effect_id: &str) -> &str {
+    effect_id.split_once('@').map_or(effect_id, |(array_id, _)| array_id)
+}
+
+// Universe ID after renames (see ArtnetManager::rename_universe)
+fn resolve_universe_id<'a>(renamed_universes: &'a HashMap<Arc<str>, Arc<str>>, universe_id: &'a str) -> &'a str {
+    renamed_universes.get(universe_id).map_or(universe_id, |renamed_universe_id| renamed_universe_id.as_ref())
+}
+
+fn split_port_address(port_address: u16) -> (u8, u8, u8) {
+    ((port_address >> 8) as u8, ((port_address >> 4) & 0xf) as u8, (port_address & 0xf) as u8)
+}
+
+// Net, subnet and universe of the definition. If any is omitted the universe ID must be a port address number, omitted
+// values are derived from it and given values must match it
+fn get_universe_address(universe_id: &str, definition: &UniverseDefinition) -> Result<(u8, u8, u8), ArtnetError> {
+    if let (Some(net), Some(subnet), Some(universe)) = (definition.net, definition.subnet, definition.universe) {
+        return Ok((net, subnet, universe));
+    }
+
+    let (net, subnet, universe) = universe_id
+        .trim()
+        .parse::<u16>()
+        .ok()
+        .filter(|port_address| *port_address <= MAX_PORT_ADDRESS)
+        .map(split_port_address)
+        .ok_or_else(|| ArtnetError::MissingPortAddress(universe_id.to_string()))?;
+
+    for (name, value, derived_value) in [("net", definition.net, net), ("subnet", definition.subnet, subnet), ("universe", definition.universe, universe)] {
+        if let Some(value) = value.filter(|value| *value != derived_value) {
+            return Err(ArtnetError::InconsistentPortAddress(universe_id.to_string(), name, value, derived_value).into());
+        }
+    }
+
+    Ok((net, subnet, universe))
+}
+
+// Values of target for each of the channels (all the channels must be in the universe), parse errors are in into_context
+fn get_channel_values(
+    description: &str,
+    channel_count: u16,
+    target: &str,
+    channels: &str,
+    into_context: impl Fn() -> ArtnetError,
+) -> Result<Vec<ChannelValue>, ArtnetError> {
+    let parsed_target = target.parse::<TargetValue>().change_context_lazy(&into_context)?;
+    let mut values = Vec::new();
+
+    for channel in channels.split(',') {
+        let channel = channel.parse::<ChannelDefinition>().change_context_lazy(&into_context)?;
+
+        if let Some(c) = channel.channels().into_iter().find(|c| *c >= channel_count) {
+            return Err(ArtnetError::InvalidChannel(description.to_string(), c, channel_count).into());
+        }
+
+        let value = parsed_target.get(&channel)
+            .ok_or_else(|| ArtnetError::MissingTargetValue(channel.to_string(), target.to_string()))?;
+        values.push(ChannelValue { channel, value });
+    }
+
+    Ok(values)
+}
+
+fn get_idle(description: &str, channel_count: u16, idle_definition: &defs::UniverseIdleDefinition) -> Result<UniverseIdle, ArtnetError> {
+    let into_context = || ArtnetError::Context(format!("Parsing idle values {:?}", idle_definition));
+    let values = get_channel_values(description, channel_count, &idle_definition.target, &idle_definition.channels, into_context)?;
+
+    Ok(UniverseIdle {
+        after: Duration::from_secs(idle_definition.after_seconds),
+        channels: values.iter().flat_map(|v| v.channel.channels()).collect(),
+        values,
+        last_set: Instant::now(),
+        applied: false,
+    })
+}
+
+// Channel data the universe starts with (all zeros unless the definition has initial or initial_b64)
+fn get_initial_data(description: &str, channel_count: usize, definition: &UniverseDefinition) -> Result<Vec<u8>, ArtnetError> {
+    let mut data = vec![0; channel_count];
+
+    match (&definition.initial, &definition.initial_b64) {
+        (Some(_), Some(_)) => return Err(ArtnetError::ConflictingInitialValues(description.to_string()).into()),
+        (Some(initial), None) => {
+            let into_context = || ArtnetError::Context(format!("Parsing initial values {:?}", initial));
+            let values = get_channel_values(description, channel_count as u16, &initial.target, &initial.channels, into_context)?;
+
+            for v in values {
+                for (channel, value) in v.channel.channels().into_iter().zip(v.value.components()) {
+                    data[channel as usize] = value;
+                }
+            }
+        }
+        (None, Some(initial_b64)) => {
+            let initial_data = BASE64_STANDARD
+                .decode(initial_b64.trim())
+                .map_err(|e| ArtnetError::InvalidInitialData(description.to_string(), e.to_string()))?;
+
+            if initial_data.len() > channel_count {
+                return Err(ArtnetError::InitialDataOutOfRange(description.to_string(), initial_data.len(), channel_count).into());
+            }
+            data[..initial_data.len()].copy_from_slice(&initial_data);
+        }
+        (None, None) => {}
+    }
+
+    Ok(data)
+}
+
 impl Universe {
-    pub fn new(
-        controller: Arc<ArtnetController>,
-        universe_id: &str,
-        definition: UniverseDefinition,
-    ) -> Result<Universe, ArtnetError> {
-        let into_context = || ArtnetError::Context(format!("Creating universe {}", universe_id));
+    // Checks of a definition that do not need the controller (so a definition can be validated without creating the
+    // controller socket)
+    pub(super) fn validate_definition(universe_id: &str, definition: &UniverseDefinition) -> Result<ValidatedUniverse, ArtnetError> {
+        let (net, subnet, universe_number) = get_universe_address(universe_id, definition)?;
 
-        if definition.universe > 15 {
-            return Err(ArtnetError::InvalidUniverseNumber(definition.universe)).change_context_lazy(into_context);
+        if universe_number > 15 {
+            return Err(ArtnetError::InvalidUniverseNumber(universe_number).into());
+        }
+        if subnet > 15 {
+            return Err(ArtnetError::InvalidSubnet(subnet).into());
+        }
+        if net > 127 {
+            return Err(ArtnetError::InvalidNet(net).into());
         }
-        if definition.subnet > 15 {
-            return Err(ArtnetError::InvalidSubnet(definition.subnet)).change_context_lazy(into_context);
+        if definition.channels == 0 {
+            return Err(ArtnetError::NoChannels.into());
         }
-        if definition.net > 127 {
-            return Err(ArtnetError::InvalidNet(definition.net)).change_context_lazy(into_context);
+        if definition.channels as usize > artnet_packet::DMX_MAX_CHANNELS {
+            return Err(ArtnetError::TooManyChannels(definition.channels).into());
         }
-        if definition.channels > 512 {
-            return Err(ArtnetError::TooManyChannels(definition.channels)).change_context_lazy(into_context);
+        if definition.send_divisor == Some(0) {
+            return Err(ArtnetError::InvalidSendDivisor(universe_id.to_string()).into());
+        }
+        if definition.keepalive_burst.as_ref().is_some_and(|burst| burst.every_seconds == 0 || burst.packets == 0) {
+            return Err(ArtnetError::InvalidKeepaliveBurst(universe_id.to_string()).into());
+        }
+        if let Some(pad_to) = definition.pad_to.filter(|pad_to| *pad_to < definition.channels || *pad_to as usize > artnet_packet::DMX_MAX_CHANNELS) {
+            return Err(ArtnetError::InvalidPadTo(universe_id.to_string(), pad_to, definition.channels).into());
         }
 
-        let channel_count = (definition.channels + 1) as usize & !1; // Round up to even number of channels
-        let mut packet_bytes = Vec::<u8>::with_capacity(channel_count + DMX_DATA_OFFSET);
+        let description = format!("{0} ({1})", universe_id, definition.description);
+        let channel_count = artnet_packet::get_data_length(definition.channels as usize);
+        let frame_length = artnet_packet::get_data_length(definition.pad_to.unwrap_or(definition.channels) as usize);
+        let idle = definition.idle.as_ref().map(|idle_definition| get_idle(&description, channel_count as u16, idle_definition)).transpose()?;
+        let initial_data = get_initial_data(&description, channel_count, definition)?;
+        let quiet_hours = definition.quiet_hours.as_ref().map(|quiet_hours| QuietHours::new(&description, quiet_hours)).transpose()?;
+
+        Ok(ValidatedUniverse {
+            description,
+            port_address: (net as u16) << 8 | (subnet as u16) << 4 | universe_number as u16,
+            channel_count,
+            frame_length,
+            idle,
+            quiet_hours,
+            initial_data,
+        })
+    }
 
-        packet_bytes.append(&mut vec![b'A', b'r', b't', b'-', b'N', b'e', b't', 0x00]);
-        packet_bytes.push((ARTNET_OPCODE_OUTPUT & 0xff) as u8);
-        packet_bytes.push((ARTNET_OPCODE_OUTPUT >> 8) as u8);
-        packet_bytes.push(0x00); // Protocol version Hi
-        packet_bytes.push(0x14); // Protocol version Lo
-        packet_bytes.push(0x00); // Sequence
-        packet_bytes.push(0x00); // Physical
-        packet_bytes.push(definition.subnet << 4 | definition.universe); // Subuniverse
-        packet_bytes.push(definition.net); // net
-        packet_bytes.push((channel_count >> 8) as u8); // Length Hi
-        packet_bytes.push((channel_count & 0xff) as u8); // Length Lo
+    pub fn new(
+        controller: Arc<ArtnetController>,
+        universe_id: &str,
+        definition: UniverseDefinition,
+    ) -> Result<Universe, ArtnetError> {
+        let into_context = || ArtnetError::Context(format!("Creating universe {}", universe_id));
+        let validated = Self::validate_definition(universe_id, &definition).change_context_lazy(into_context)?;
+        let (net, subnet, universe_number) = split_port_address(validated.port_address);
 
-        assert_eq!(packet_bytes.len(), DMX_DATA_OFFSET);
-        packet_bytes.extend(repeat(0x00).take(channel_count));
+        if validated.channel_count != definition.channels as usize {
+            info!("Universe {}: channel count {} rounded up to {} (DMX data length must be even)", universe_id, definition.channels, validated.channel_count);
+        }
+        // The initial data is the node's current state, so it is not marked as modified (it is sent with the periodic refresh)
+        let mut frame_data = validated.initial_data;
+        frame_data.resize(validated.frame_length, 0);
+        let packet_bytes = artnet_packet::build_artdmx(net, subnet, universe_number, &frame_data);
 
         Ok(Universe {
-            description: format!("{0} ({1})", universe_id, definition.description),
+            description: validated.description,
             controller,
+            controller_address: definition.controller,
+            port_address: validated.port_address,
+            channel_count: validated.channel_count as u16,
+            padded: definition.pad_to.is_some(),
             log: definition.log,
             disable_send: definition.disable_send,
             packet_bytes,
             modified: false,
             non_modified_ticks: 0,
+            send_divisor: definition.send_divisor.unwrap_or(1),
+            ticks_since_send: u32::MAX,        // The first modification is sent right away
+            sent_ticks: 0,
+            paced_ticks: 0,
+            blackout_data: None,
+            send_status: UniverseSendStatus { reachable: true, send_disabled: definition.disable_send, ..Default::default() },
+            changed_channels: HashSet::new(),
+            idle: validated.idle,
+            quiet_hours: validated.quiet_hours,
+            quiet: false,
+            max_delta_per_tick: definition.max_delta_per_tick,
+            slewing_channels: HashMap::new(),
+            definition_hash: get_definition_hash(&definition),
+            keepalive_burst: definition.keepalive_burst.as_ref().map(|burst| KeepaliveBurst {
+                every: Duration::from_secs(burst.every_seconds),
+                packets: burst.packets,
+                remaining: 0,
+            }),
+            #[cfg(test)]
+            fail_send: false,
+            #[cfg(test)]
+            sent_packets: 0,
+            #[cfg(test)]
+            last_sent_data: Vec::new(),
+            #[cfg(test)]
+            last_sent_sequence: 0,
         })
     }
 
+    // Set the idle channels to their idle values if none of them was set for the idle timeout, returns true if applied
+    fn apply_idle(&mut self, now: Instant) -> Result<bool, ArtnetError> {
+        let values = match &self.idle {
+            Some(idle) if !idle.applied && now.saturating_duration_since(idle.last_set) >= idle.after => idle.values.clone(),
+            _ => return Ok(false),
+        };
+
+        for v in values.iter() {
+            self.set_channel(v)?;
+        }
+
+        // Setting the values above marks the channels as set, so the idle values are applied once until an effect or Set
+        if let Some(idle) = self.idle.as_mut() {
+            idle.applied = true;
+        }
+
+        Ok(true)
+    }
+
     #[cfg(test)]
     pub(super) fn get_packet_bytes(&self) -> &Vec<u8> {
         &self.packet_bytes
     }
 
+    // Local port of the controller socket, identifies the socket used to send this universe
+    #[cfg(test)]
+    pub(super) fn get_controller_port(&self) -> u16 {
+        self.controller.socket.local_addr().unwrap().port()
+    }
+
     fn get_channel_count(&self) -> u16 {
-        (self.packet_bytes.len() - DMX_DATA_OFFSET) as u16
+        self.channel_count
+    }
+
+    fn get_frame_length(&self) -> usize {
+        artnet_packet::get_data(&self.packet_bytes).len()
     }
 
     fn validate_channel(&self, channel: u16) -> Result<(), ArtnetError> {
@@ -369,12 +2116,44 @@ impl Universe {
         }
     }
 
+    // Channel data effects operate on (the saved copy while the universe is blacked out)
+    fn channel_data(&self) -> &[u8] {
+        match &self.blackout_data {
+            Some(data) => data,
+            None => artnet_packet::get_data(&self.packet_bytes),
+        }
+    }
+
+    fn channel_data_mut(&mut self) -> &mut [u8] {
+        match &mut self.blackout_data {
+            Some(data) => data,
+            None => artnet_packet::get_data_mut(&mut self.packet_bytes),
+        }
+    }
+
+    pub fn blackout(&mut self) {
+        if self.blackout_data.is_none() {
+            let data = artnet_packet::get_data_mut(&mut self.packet_bytes);
+
+            self.blackout_data = Some(data.to_vec());
+            data.fill(0);
+            self.modified = true;
+        }
+    }
+
+    pub fn restore(&mut self) {
+        if let Some(data) = self.blackout_data.take() {
+            artnet_packet::set_data(&mut self.packet_bytes, &data);
+            self.modified = true;
+        }
+    }
+
     pub fn set_channel(&mut self, v: &ChannelValue) -> Result<(), ArtnetError> {
         match v.channel {
             ChannelDefinition::Single(channel) => {
                 self.validate_channel(channel)?;
                 if let DimmerValue::Single(value) = v.value {
-                    self.packet_bytes[DMX_DATA_OFFSET + channel as usize] = value;
+                    self.write_channel(channel, value);
                     Ok(())
                 } else {
                     Err(ArtnetError::ChannelValueMismatch(
@@ -389,9 +2168,9 @@ impl Universe {
                 self.validate_channel(g_channel)?;
                 self.validate_channel(b_channel)?;
                 if let DimmerValue::Rgb(r, g, b) = v.value {
-                    self.packet_bytes[DMX_DATA_OFFSET + r_channel as usize] = r;
-                    self.packet_bytes[DMX_DATA_OFFSET + g_channel as usize] = g;
-                    self.packet_bytes[DMX_DATA_OFFSET + b_channel as usize] = b;
+                    self.write_channel(r_channel, r);
+                    self.write_channel(g_channel, g);
+                    self.write_channel(b_channel, b);
                     Ok(())
                 } else {
                     Err(ArtnetError::ChannelValueMismatch(
@@ -406,9 +2185,9 @@ impl Universe {
                 self.validate_channel(w2_channel)?;
                 self.validate_channel(w3_channel)?;
                 if let DimmerValue::TriWhite(w1, w2, w3) = v.value {
-                    self.packet_bytes[DMX_DATA_OFFSET + w1_channel as usize] = w1;
-                    self.packet_bytes[DMX_DATA_OFFSET + w2_channel as usize] = w2;
-                    self.packet_bytes[DMX_DATA_OFFSET + w3_channel as usize] = w3;
+                    self.write_channel(w1_channel, w1);
+                    self.write_channel(w2_channel, w2);
+                    self.write_channel(w3_channel, w3);
                     Ok(())
                 } else {
                     Err(ArtnetError::ChannelValueMismatch(
@@ -420,21 +2199,100 @@ impl Universe {
             }
         }?;
 
-        self.modified = true;
+        let channels = v.channel.channels();
+
+        if let Some(idle) = self.idle.as_mut().filter(|idle| channels.iter().any(|c| idle.channels.contains(c))) {
+            idle.last_set = Instant::now();
+            idle.applied = false;
+        }
+
+        self.changed_channels.extend(channels);
+
+        // While blacked out, only the saved copy is updated so nothing new is sent
+        if self.blackout_data.is_none() {
+            self.modified = true;
+        }
         Ok(())
     }
 
+    // Set the channels of a frame (validated by the caller) to their values, the slew limit does not apply to frames
+    pub fn set_frame(&mut self, values: &[(u16, u8)]) {
+        let channel_data = self.channel_data_mut();
+        for (channel, value) in values {
+            channel_data[*channel as usize] = *value;
+        }
+
+        let channels = values.iter().map(|(channel, _)| *channel).collect::<HashSet<_>>();
+        self.slewing_channels.retain(|channel, _| !channels.contains(channel));
+
+        if let Some(idle) = self.idle.as_mut().filter(|idle| channels.iter().any(|c| idle.channels.contains(c))) {
+            idle.last_set = Instant::now();
+            idle.applied = false;
+        }
+
+        self.changed_channels.extend(channels);
+
+        if self.blackout_data.is_none() {
+            self.modified = true;
+        }
+    }
+
+    // With a slew limit, a channel moves at most max_delta_per_tick toward the value, the rest of the way is done by
+    // advance_slewing_channels on the following ticks
+    fn write_channel(&mut self, channel: u16, value: u8) {
+        let current = self.channel_data()[channel as usize];
+
+        let value = match self.max_delta_per_tick {
+            Some(max_delta) if current.abs_diff(value) > max_delta => {
+                self.slewing_channels.insert(channel, SlewingChannel { target: value, stepped: true });
+                step_toward(current, value, max_delta)
+            }
+            _ => {
+                self.slewing_channels.remove(&channel);
+                value
+            }
+        };
+
+        self.channel_data_mut()[channel as usize] = value;
+    }
+
+    // Move the slewing channels that were not set during this tick one step toward their target
+    fn advance_slewing_channels(&mut self) {
+        let Some(max_delta) = self.max_delta_per_tick else { return };
+        let mut slewing_channels = mem::take(&mut self.slewing_channels);
+
+        slewing_channels.retain(|&channel, slewing_channel| {
+            if mem::take(&mut slewing_channel.stepped) {
+                return true;
+            }
+
+            let data = self.channel_data_mut();
+            data[channel as usize] = step_toward(data[channel as usize], slewing_channel.target, max_delta);
+            self.changed_channels.insert(channel);
+
+            if self.blackout_data.is_none() {
+                self.modified = true;
+            }
+            self.channel_data()[channel as usize] != slewing_channel.target
+        });
+
+        self.slewing_channels = slewing_channels;
+    }
+
+    // Actual channel values, a slewing channel is returned at its current value and not at the value it was set to
     pub fn get_channel(
         &self,
         channel_definition: &ChannelDefinition,
     ) -> Result<ChannelValue, ArtnetError> {
+        let data = self.channel_data();
+
         match channel_definition {
             ChannelDefinition::Single(s) => {
                 self.validate_channel(*s)?;
                 Ok(ChannelValue {
                     channel: channel_definition.clone(),
                     value: DimmerValue::Single(
-                        self.packet_bytes[DMX_DATA_OFFSET + *s as usize],
+                        data[*s as usize],
                     ),
                 })
             },
@@ -445,9 +2303,9 @@ impl Universe {
                 Ok(ChannelValue {
                     channel: channel_definition.clone(),
                     value: DimmerValue::Rgb(
-                        self.packet_bytes[DMX_DATA_OFFSET + *r as usize],
-                        self.packet_bytes[DMX_DATA_OFFSET + *g as usize],
-                        self.packet_bytes[DMX_DATA_OFFSET + *b as usize],
+                        data[*r as usize],
+                        data[*g as usize],
+                        data[*b as usize],
                     ),
                 })
 
@@ -459,9 +2317,9 @@ impl Universe {
                 Ok(ChannelValue {
                     channel: channel_definition.clone(),
                     value: DimmerValue::TriWhite(
-                        self.packet_bytes[DMX_DATA_OFFSET + *w1 as usize],
-                        self.packet_bytes[DMX_DATA_OFFSET + *w2 as usize],
-                        self.packet_bytes[DMX_DATA_OFFSET + *w3 as usize],
+                        data[*w1 as usize],
+                        data[*w2 as usize],
+                        data[*w3 as usize],
                     ),
                 })
             },
@@ -469,12 +2327,114 @@ impl Universe {
     }
 
     pub fn send(&mut self) -> Result<(), ArtnetError> {
-        if !self.disable_send {
-            self.controller.send(self.packet_bytes.as_slice())?;
+        if let Err(e) = self.send_packet() {
+            self.send_status.consecutive_failures += 1;
+            self.send_status.total_failures += 1;
+            return Err(e);
         }
-        self.packet_bytes[DMX_SEQ_OFFSET] = self.packet_bytes[DMX_SEQ_OFFSET].wrapping_add(1);
+
+        self.send_status.reachable = true;
+        self.send_status.consecutive_failures = 0;
+        self.send_status.last_send_time = Some(chrono::Utc::now());
+        artnet_packet::next_sequence(&mut self.packet_bytes);
         self.modified = false;
         self.non_modified_ticks = 0;
+        self.ticks_since_send = 0;
+        self.sent_ticks += 1;
+
+        // A new frame ends the burst of the previous one
+        if let Some(burst) = self.keepalive_burst.as_mut() {
+            burst.remaining = 0;
+        }
+        Ok(())
+    }
+
+    // Number of unmodified ticks after which the universe is sent again
+    fn get_refresh_ticks(&self, tick_duration: Duration) -> usize {
+        match &self.keepalive_burst {
+            Some(burst) => (burst.every.as_millis() / tick_duration.as_millis().max(1)).max(1) as usize,
+            None => SEND_UNMODIFIED_UNIVERSE_EVERY,
+        }
+    }
+
+    fn start_keepalive_burst(&mut self) {
+        if let Some(burst) = self.keepalive_burst.as_mut() {
+            burst.remaining = burst.packets - 1;
+        }
+    }
+
+    fn is_keepalive_burst_pending(&self) -> bool {
+        self.keepalive_burst.as_ref().is_some_and(|burst| burst.remaining > 0)
+    }
+
+    // Send the last sent packet again. The channels were not modified since it was sent, so only the sequence (advanced
+    // after sending) is set back, and the next modification is sent with the sequence following it
+    fn send_keepalive_repeat(&mut self) -> Result<(), ArtnetError> {
+        if let Some(burst) = self.keepalive_burst.as_mut() {
+            burst.remaining -= 1;
+        }
+
+        let sequence = artnet_packet::get_sequence(&self.packet_bytes);
+        artnet_packet::set_sequence(&mut self.packet_bytes, sequence.wrapping_sub(1));
+        let result = self.send_packet();
+        artnet_packet::set_sequence(&mut self.packet_bytes, sequence);
+
+        if result.is_ok() {
+            self.ticks_since_send = 0;
+            self.sent_ticks += 1;
+        }
+        result
+    }
+
+    fn send_packet(&mut self) -> Result<(), ArtnetError> {
+        #[cfg(test)]
+        if self.fail_send {
+            return Err(ArtnetError::Context(format!("Sending to {} (simulated failure)", self.description)).into());
+        }
+
+        if !self.disable_send && !self.quiet {
+            self.controller.send(self.packet_bytes.as_slice())?;
+
+            #[cfg(test)]
+            {
+                self.sent_packets += 1;
+                self.last_sent_data = artnet_packet::get_data(&self.packet_bytes).to_vec();
+                self.last_sent_sequence = artnet_packet::get_sequence(&self.packet_bytes);
+            }
+        }
         Ok(())
     }
+
+    // Enter or leave the quiet hours, returns true if changed. An all-zero frame is sent when entering (the channels keep
+    // their values), and the channels are sent when leaving
+    fn update_quiet(&mut self, now: chrono::DateTime<chrono::Utc>) -> Result<bool, ArtnetError> {
+        let quiet = self.quiet_hours.as_ref().is_some_and(|quiet_hours| quiet_hours.is_quiet(now));
+
+        if quiet == self.quiet {
+            return Ok(false);
+        }
+
+        // The quiet hours are entered even if the final frame failed to be sent, so the channels are not sent
+        let result = if quiet {
+            let data = artnet_packet::get_data(&self.packet_bytes).to_vec();
+
+            artnet_packet::get_data_mut(&mut self.packet_bytes).fill(0);
+            let result = self.send();
+            artnet_packet::set_data(&mut self.packet_bytes, &data);
+            result
+        } else {
+            self.modified = true;
+            Ok(())
+        };
+
+        self.quiet = quiet;
+        self.send_status.quiet = quiet;
+        result.map(|_| true)
+    }
+
+    pub(super) fn set_send_disabled(&mut self, disable_send: bool) {
+        self.disable_send = disable_send;
+        self.send_status.send_disabled = disable_send;
+        self.modified = true;       // Send the current values once sending is enabled
+    }
 }
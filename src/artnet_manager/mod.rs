@@ -1,11 +1,22 @@
 
 mod manager;
+mod artnet_packet;
 mod error;
 mod runtime_nodes;
+mod watchers;
+mod monitors;
+mod quiet_hours;
+mod effect_values;
+mod channel_aliases;
+mod effect_stats;
 
 #[cfg(test)]
 mod tests;
 
 pub use error::ArtnetError;
 pub use manager::ArtnetManager;
+pub use manager::EffectTickBudget;
+pub use manager::DEFAULT_TICK_DURATION;
 pub use manager::EffectNodeRuntime;
+pub use runtime_nodes::CompiledEffectNode;
+pub use runtime_nodes::InstantOffEffectNode;
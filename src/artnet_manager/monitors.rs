@@ -0,0 +1,63 @@
+// Monitors mirror a channel to an arbitrary topic (e.g. a fan controller following the bathroom light level), the
+// current value (0-255) is published as a plain number
+//
+// Unlike watchers, monitors are not triggered by a condition: a monitor publishes when the value differs from the last
+// published value, at most once every min_interval_ms (a change made within the interval is published once it
+// elapses). Monitors that are not only_on_change also publish the unchanged value every min_interval_ms
+
+use error_stack::{Result, ResultExt};
+use std::{sync::Arc, time::{Duration, Instant}};
+
+use super::ArtnetError;
+use crate::defs::MonitorDefinition;
+use crate::dmx::ChannelDefinition;
+
+#[derive(Debug)]
+pub(super) struct Monitor {
+    pub(super) universe_id: Arc<str>,
+    pub(super) channel: u16,
+    pub(super) topic: Arc<str>,
+    min_interval: Duration,
+    only_on_change: bool,
+    last_published: Option<(u8, Instant)>,      // Value and time of the last publish
+}
+
+impl Monitor {
+    pub(super) fn new(name: &str, definition: MonitorDefinition) -> Result<Monitor, ArtnetError> {
+        let into_context = || ArtnetError::Context(format!("Setting monitor {name}"));
+
+        let channel = match definition.channel.parse::<ChannelDefinition>().change_context_lazy(into_context)? {
+            ChannelDefinition::Single(channel) => channel,
+            _ => return Err(ArtnetError::InvalidMonitorChannel(definition.channel)).change_context_lazy(into_context),
+        };
+
+        if definition.topic.is_empty() || definition.topic.contains(['+', '#']) {
+            return Err(ArtnetError::InvalidMonitorTopic(definition.topic)).change_context_lazy(into_context);
+        }
+
+        Ok(Monitor {
+            universe_id: definition.universe_id,
+            channel,
+            topic: Arc::from(definition.topic),
+            min_interval: Duration::from_millis(definition.min_interval_ms),
+            only_on_change: definition.only_on_change,
+            last_published: None,
+        })
+    }
+
+    // Returns true if the value should be published now (the first value is always published)
+    pub(super) fn update(&mut self, value: u8, now: Instant) -> bool {
+        let publish = match self.last_published {
+            None => true,
+            Some((last_value, last_time)) => {
+                now.duration_since(last_time) >= self.min_interval && (value != last_value || !self.only_on_change)
+            }
+        };
+
+        if publish {
+            self.last_published = Some((value, now));
+        }
+
+        publish
+    }
+}
@@ -0,0 +1,50 @@
+// Quiet hours of a universe (see defs::QuietHoursDefinition), a daily window in which the universe sends nothing. The
+// window is evaluated on every tick with the current time, so tests pass the time they want to check
+
+use chrono::{DateTime, FixedOffset, Local, NaiveTime, Utc};
+use error_stack::Result;
+
+use super::ArtnetError;
+use crate::defs::QuietHoursDefinition;
+
+#[derive(Debug)]
+pub(super) struct QuietHours {
+    from: NaiveTime,
+    to: NaiveTime,
+    utc_offset: Option<FixedOffset>,        // Local time if not set
+}
+
+impl QuietHours {
+    pub(super) fn new(description: &str, definition: &QuietHoursDefinition) -> Result<QuietHours, ArtnetError> {
+        let invalid = |reason: String| ArtnetError::InvalidQuietHours(description.to_string(), reason);
+        let parse_time = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| invalid(format!("invalid time '{time}' (expected HH:MM)")));
+
+        let from = parse_time(&definition.from)?;
+        let to = parse_time(&definition.to)?;
+
+        if from == to {
+            return Err(invalid(format!("from and to are both {} (the window is empty)", definition.from)).into());
+        }
+
+        let utc_offset = definition
+            .utc_offset
+            .as_ref()
+            .map(|utc_offset| utc_offset.trim().parse::<FixedOffset>().map_err(|_| invalid(format!("invalid utc_offset '{utc_offset}' (expected e.g. +02:00)"))))
+            .transpose()?;
+
+        Ok(QuietHours { from, to, utc_offset })
+    }
+
+    pub(super) fn is_quiet(&self, now: DateTime<Utc>) -> bool {
+        let time = match self.utc_offset {
+            Some(utc_offset) => now.with_timezone(&utc_offset).time(),
+            None => now.with_timezone(&Local).time(),
+        };
+
+        if self.from < self.to {
+            time >= self.from && time < self.to
+        } else {
+            time >= self.from || time < self.to
+        }
+    }
+}
@@ -3,8 +3,51 @@ use super::manager::{ArtnetManager, EffectNodeRuntime};
 use super::ArtnetError;
 use crate::array_manager::{error::DmxArrayError, Scope};
 use crate::defs;
-use crate::defs::TargetValue;
-use crate::dmx::{ChannelDefinition, ChannelValue, DimmerValue, UniverseChannelDefinitions};
+use crate::defs::{DimmingAmount, RelativeTargetValue, TargetValue};
+use crate::dmx::{ChannelDefinition, ChannelDimming, ChannelLimits, ChannelValue, DimmerValue, UniverseChannelDefinitions};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+// Effect node whose definition is resolved in its scope (values expanded, lights and targets parsed). Runtime nodes are
+// instantiated from it without resolving the definition again, so it is cached for commands that are repeated
+#[derive(Debug)]
+pub enum CompiledEffectNode {
+    Sequence(Vec<CompiledEffectNode>),
+    Parallel(Vec<CompiledEffectNode>),
+    Delay(usize),
+    Fade(Arc<FadeParameters>),
+    Hold(Box<CompiledEffectNode>, usize),      // Attack and attack plus hold ticks
+    WaitFor(WaitForEffectNode),     // Node that was not ticked yet
+}
+
+impl CompiledEffectNode {
+    pub fn instantiate(&self) -> Box<dyn EffectNodeRuntime> {
+        match self {
+            CompiledEffectNode::Sequence(nodes) => Box::new(SequenceEffectNode {
+                nodes: nodes.iter().map(|node| node.instantiate()).collect(),
+                current_node: 0,
+            }),
+            CompiledEffectNode::Parallel(nodes) => Box::new(ParallelEffectNode {
+                nodes: nodes.iter().map(|node| node.instantiate()).collect(),
+            }),
+            CompiledEffectNode::Delay(ticks) => Box::new(DelayEffectNode {
+                ticks: *ticks,
+                current_tick: 0,
+            }),
+            CompiledEffectNode::Fade(parameters) => Box::new(FadeEffectNode {
+                parameters: parameters.clone(),
+                current_tick: 0,
+                state: None,
+            }),
+            CompiledEffectNode::Hold(attack, ticks) => Box::new(HoldEffectNode {
+                attack: attack.instantiate(),
+                ticks: *ticks,
+                current_tick: 0,
+            }),
+            CompiledEffectNode::WaitFor(node) => Box::new(node.clone()),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct SequenceEffectNode {
@@ -13,19 +56,16 @@ pub struct SequenceEffectNode {
 }
 
 impl defs::SequenceEffectNodeDefinition {
-    pub fn get_runtime_node(
+    pub fn compile(
         &self,
         scope: &Scope,
-    ) -> Result<Box<dyn EffectNodeRuntime>, DmxArrayError> {
+    ) -> Result<CompiledEffectNode, DmxArrayError> {
         let nodes = self
             .nodes
             .iter()
-            .map(|node| node.get_runtime_node(scope))
-            .collect::<Result<Vec<Box<dyn EffectNodeRuntime>>, DmxArrayError>>()?;
-        Ok(Box::new(SequenceEffectNode {
-            nodes,
-            current_node: 0,
-        }))
+            .map(|node| node.compile(scope))
+            .collect::<Result<Vec<CompiledEffectNode>, DmxArrayError>>()?;
+        Ok(CompiledEffectNode::Sequence(nodes))
     }
 }
 
@@ -44,20 +84,37 @@ impl EffectNodeRuntime for SequenceEffectNode {
     fn is_done(&self) -> bool {
         self.current_node >= self.nodes.len()
     }
+
+    fn remaining_ticks(&self) -> Option<usize> {
+        self.nodes
+            .iter()
+            .skip(self.current_node)
+            .map(|node| node.remaining_ticks())
+            .sum()
+    }
+
+    fn get_universe_ids(&self) -> Vec<&str> {
+        self.nodes.iter().flat_map(|node| node.get_universe_ids()).collect()
+    }
+
+    fn describe(&self, indent: usize) -> String {
+        let step = (self.current_node + 1).min(self.nodes.len());
+        describe_nodes(format!("sequence step {}/{}", step, self.nodes.len()), &self.nodes, indent)
+    }
 }
 
 impl defs::ParallelEffectNodeDefinition {
-    pub fn get_runtime_node(
+    pub fn compile(
         &self,
         scope: &Scope,
-    ) -> Result<Box<dyn EffectNodeRuntime>, DmxArrayError> {
+    ) -> Result<CompiledEffectNode, DmxArrayError> {
         let nodes = self
             .nodes
             .iter()
-            .map(|node| node.get_runtime_node(scope))
-            .collect::<Result<Vec<Box<dyn EffectNodeRuntime>>, DmxArrayError>>()?;
+            .map(|node| node.compile(scope))
+            .collect::<Result<Vec<CompiledEffectNode>, DmxArrayError>>()?;
 
-        Ok(Box::new(ParallelEffectNode { nodes }))
+        Ok(CompiledEffectNode::Parallel(nodes))
     }
 }
 
@@ -78,17 +135,30 @@ impl EffectNodeRuntime for ParallelEffectNode {
     fn is_done(&self) -> bool {
         self.nodes.iter().all(|node| node.is_done())
     }
+
+    fn remaining_ticks(&self) -> Option<usize> {
+        self.nodes
+            .iter()
+            .map(|node| node.remaining_ticks())
+            .try_fold(0, |max, ticks| ticks.map(|ticks| max.max(ticks)))
+    }
+
+    fn get_universe_ids(&self) -> Vec<&str> {
+        self.nodes.iter().flat_map(|node| node.get_universe_ids()).collect()
+    }
+
+    fn describe(&self, indent: usize) -> String {
+        let done = self.nodes.iter().filter(|node| node.is_done()).count();
+        describe_nodes(format!("parallel {}/{} done", done, self.nodes.len()), &self.nodes, indent)
+    }
 }
 
 impl defs::DelayEffectNodeDefinition {
-    pub fn get_runtime_node(
+    pub fn compile(
         &self,
         scope: &Scope,
-    ) -> Result<Box<dyn EffectNodeRuntime>, DmxArrayError> {
-        Ok(Box::new(DelayEffectNode {
-            ticks: self.ticks.get_value(scope, "delay ticks parameter")?,
-            current_tick: 0,
-        }))
+    ) -> Result<CompiledEffectNode, DmxArrayError> {
+        Ok(CompiledEffectNode::Delay(self.ticks.get_value(scope, "delay ticks parameter")?))
     }
 }
 
@@ -110,63 +180,156 @@ impl EffectNodeRuntime for DelayEffectNode {
     fn is_done(&self) -> bool {
         self.current_tick >= self.ticks
     }
+
+    fn remaining_ticks(&self) -> Option<usize> {
+        Some(self.ticks - self.current_tick)
+    }
+
+    fn describe(&self, indent: usize) -> String {
+        format!("{:width$}delay tick {}/{}", "", self.current_tick, self.ticks, width = indent * 2)
+    }
+}
+
+// Sets all the channels of the lights to zero on its first tick (Off command with instant). It is not built from an
+// effect definition so it works even if the array off effect is broken
+#[derive(Debug)]
+pub struct InstantOffEffectNode {
+    pub lights: Vec<UniverseChannelDefinitions>,
+    pub done: bool,
+}
+
+impl EffectNodeRuntime for InstantOffEffectNode {
+    fn tick(&mut self, artnet_manager: &mut ArtnetManager) -> Result<(), ArtnetError> {
+        if !self.done {
+            for universe_channels in self.lights.iter() {
+                for channel in universe_channels.channels.iter() {
+                    let value = match channel {
+                        ChannelDefinition::Single(_) => DimmerValue::Single(0),
+                        ChannelDefinition::Rgb(_, _, _) => DimmerValue::Rgb(0, 0, 0),
+                        ChannelDefinition::TriWhite(_, _, _) => DimmerValue::TriWhite(0, 0, 0),
+                    };
+
+                    artnet_manager.set_channel(&universe_channels.universe_id, &ChannelValue { channel: channel.clone(), value })?;
+                }
+            }
+            self.done = true;
+        }
+
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn remaining_ticks(&self) -> Option<usize> {
+        Some(if self.done { 0 } else { 1 })
+    }
+
+    fn get_universe_ids(&self) -> Vec<&str> {
+        self.lights.iter().map(|universe_channels| universe_channels.universe_id.as_str()).collect()
+    }
+
+    fn describe(&self, indent: usize) -> String {
+        format!("{:width$}instant off{}", "", if self.done { " (done)" } else { "" }, width = indent * 2)
+    }
 }
 
 impl defs::FadeEffectNodeDefinition {
-    pub fn get_runtime_node(
+    pub fn compile(
         &self,
         scope: &Scope,
-    ) -> Result<Box<dyn EffectNodeRuntime>, DmxArrayError> {
+    ) -> Result<CompiledEffectNode, DmxArrayError> {
         let lights_list = scope.expand_values(&self.lights)?;
-        let lights = scope.get_light_channels(&lights_list)?;
+        let lights = scope.get_light_channels(scope.get_node_lights(&lights_list))?;
         let ticks = self.ticks.get_value(scope, "fade ticks parameter")?;
         let target = scope
             .expand_values(&self.target)?
-            .parse::<TargetValue>()
+            .parse::<RelativeTargetValue>()
             .map_err(|e| {
                 DmxArrayError::ValueError(scope.to_string(), "fade target parameter", e.to_string())
-            })?
-            .get_dimmed_value(if self.no_dimming { defs::DIMMING_AMOUNT_MAX } else { scope.dimming_amount });
+            })?;
 
-        Ok(Box::new(FadeEffectNode {
+        // Channels for which the target has no value are silently left unchanged, which usually means a mistake
+        let missing_types = lights
+            .iter()
+            .flat_map(|universe_channels| universe_channels.channels.iter().map(|channel| channel.channel_type()))
+            .filter(|channel_type| !target.has_value_for(*channel_type))
+            .collect::<BTreeSet<_>>();
+
+        for channel_type in missing_types {
+            scope.add_warning(format!(
+                "{scope}: target '{}' has no value for {channel_type} channels of lights '{}', these channels are not changed",
+                self.target,
+                scope.get_node_lights(&lights_list),
+            ));
+        }
+        let from = match &self.from {
+            Some(from) => Some(scope.expand_values(from)?.parse::<TargetValue>().map_err(|e| {
+                DmxArrayError::ValueError(scope.to_string(), "fade from parameter", e.to_string())
+            })?),
+            None => None,
+        };
+
+        Ok(CompiledEffectNode::Fade(Arc::new(FadeParameters {
             lights,
             ticks,
-            current_tick: 0,
             target,
-            state: None,
-        }))
+            from,
+            dimming_amount: if self.no_dimming { defs::DIMMING_AMOUNT_MAX } else { scope.dimming_amount },
+            group_dimming: if self.no_dimming { Arc::default() } else { scope.get_group_dimming() },
+            limits: scope.get_channel_limits(),
+        })))
     }
 }
 
 #[derive(Debug)]
 pub struct FadeEffectNode {
-    pub lights: Vec<UniverseChannelDefinitions>,
-    pub ticks: usize,
+    pub parameters: Arc<FadeParameters>,
     pub current_tick: usize,
-    pub target: TargetValue,
     state: Option<FadeEffectState>,
 }
 
+// Resolved fade definition, shared by the fade nodes instantiated from the same compiled effect
+#[derive(Debug)]
+pub struct FadeParameters {
+    pub lights: Vec<UniverseChannelDefinitions>,
+    pub ticks: usize,
+    pub target: RelativeTargetValue,
+    pub from: Option<TargetValue>,         // Channels are set to this value on the first tick and faded from it
+    pub dimming_amount: DimmingAmount,     // Applied to the target after relative components are resolved
+    pub group_dimming: Arc<ChannelDimming>,    // Per light factor of the dimming amount (array group_dimming)
+    pub limits: Arc<ChannelLimits>,
+}
+
 impl EffectNodeRuntime for FadeEffectNode {
     fn tick(&mut self, artnet_manager: &mut ArtnetManager) -> Result<(), ArtnetError> {
         if self.state.is_none() {
             let state = self.initialize_state(artnet_manager)?;
 
+            // Set even if no fade is needed (from is the same as target)
+            if self.parameters.from.is_some() {
+                state.set_channels(artnet_manager)?;
+            }
+
             if !state.fade_needed() {
-                self.current_tick = self.ticks;
+                self.current_tick = self.parameters.ticks;
             } else {
                 self.state = Some(state);
             }
         }
 
-        if self.current_tick < self.ticks {
+        if self.current_tick < self.parameters.ticks {
             for universe_state in self.state.as_mut().unwrap().universe_states.iter_mut() {
                 for channel_state in universe_state.channel_states.iter_mut() {
-                    channel_state.value.tick();
-                    artnet_manager.set_channel(
-                        &universe_state.universe_id,
-                        &channel_state.get_channel_value(),
-                    )?;
+                    // A slow fade does not change the value on every tick, setting it again would only mark the
+                    // universe as modified
+                    if channel_state.value.tick() {
+                        artnet_manager.set_channel(
+                            &universe_state.universe_id,
+                            &channel_state.get_channel_value(),
+                        )?;
+                    }
                 }
             }
             self.current_tick += 1;
@@ -176,7 +339,210 @@ impl EffectNodeRuntime for FadeEffectNode {
     }
 
     fn is_done(&self) -> bool {
-        self.current_tick >= self.ticks
+        self.current_tick >= self.parameters.ticks
+    }
+
+    fn remaining_ticks(&self) -> Option<usize> {
+        Some(self.parameters.ticks - self.current_tick)
+    }
+
+    fn get_universe_ids(&self) -> Vec<&str> {
+        self.parameters.lights.iter().map(|universe_channels| universe_channels.universe_id.as_str()).collect()
+    }
+
+    fn describe(&self, indent: usize) -> String {
+        let parameters = &self.parameters;
+        let complete = (self.current_tick * 100).checked_div(parameters.ticks).unwrap_or(100);
+
+        format!(
+            "{:width$}fade {}; {}% complete; target {}",
+            "",
+            get_lights_summary(&parameters.lights),
+            complete,
+            parameters.target,
+            width = indent * 2
+        )
+    }
+}
+
+impl defs::HoldEffectNodeDefinition {
+    pub fn compile(
+        &self,
+        scope: &Scope,
+    ) -> Result<CompiledEffectNode, DmxArrayError> {
+        let ticks = self.ticks.get_value(scope, "hold ticks parameter")?;
+        let attack_ticks = self.attack_ticks.get_value(scope, "hold attack_ticks parameter")?;
+
+        // A snap (no attack) is a one tick fade done on the first hold tick
+        let attack = defs::FadeEffectNodeDefinition {
+            lights: self.lights.clone(),
+            ticks: defs::NumberOrVariable::Number(attack_ticks.max(1)),
+            target: self.target.clone(),
+            from: None,
+            no_dimming: self.no_dimming,
+        };
+
+        Ok(CompiledEffectNode::Hold(Box::new(attack.compile(scope)?), attack_ticks + ticks))
+    }
+}
+
+#[derive(Debug)]
+pub struct HoldEffectNode {
+    pub attack: Box<dyn EffectNodeRuntime>,
+    pub ticks: usize,       // Attack and hold ticks
+    pub current_tick: usize,
+}
+
+impl EffectNodeRuntime for HoldEffectNode {
+    fn tick(&mut self, artnet_manager: &mut ArtnetManager) -> Result<(), ArtnetError> {
+        if !self.attack.is_done() {
+            self.attack.tick(artnet_manager)?;
+        }
+
+        if self.current_tick < self.ticks {
+            self.current_tick += 1;
+        }
+
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        self.current_tick >= self.ticks && self.attack.is_done()
+    }
+
+    fn remaining_ticks(&self) -> Option<usize> {
+        Some((self.ticks - self.current_tick).max(self.attack.remaining_ticks()?))
+    }
+
+    fn get_universe_ids(&self) -> Vec<&str> {
+        self.attack.get_universe_ids()
+    }
+
+    fn describe(&self, indent: usize) -> String {
+        format!("{:width$}hold tick {}/{}\n{}", "", self.current_tick, self.ticks, self.attack.describe(indent + 1), width = indent * 2)
+    }
+}
+
+impl defs::WaitForEffectNodeDefinition {
+    pub fn compile(
+        &self,
+        scope: &Scope,
+    ) -> Result<CompiledEffectNode, DmxArrayError> {
+        let timeout_ticks = match &self.timeout_ticks {
+            Some(ticks) => Some(ticks.get_value(scope, "wait_for timeout_ticks parameter")?),
+            None => None,
+        };
+
+        Ok(CompiledEffectNode::WaitFor(WaitForEffectNode {
+            array_id: scope.array_id.clone(),
+            value_name: self.value_name.clone(),
+            initial_value: scope.get_value(&self.value_name)?.map(|value| value.to_string()),
+            equals: self.equals.clone(),
+            timeout_ticks,
+            on_timeout: self.on_timeout,
+            current_tick: 0,
+            done: false,
+        }))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WaitForEffectNode {
+    pub array_id: Arc<str>,
+    pub value_name: Arc<str>,
+    pub initial_value: Option<String>,      // Value when the effect was started (used until a value change is forwarded)
+    pub equals: Option<String>,
+    pub timeout_ticks: Option<usize>,
+    pub on_timeout: defs::WaitForTimeout,
+    pub current_tick: usize,
+    pub done: bool,
+}
+
+impl EffectNodeRuntime for WaitForEffectNode {
+    fn tick(&mut self, artnet_manager: &mut ArtnetManager) -> Result<(), ArtnetError> {
+        if self.done {
+            return Ok(());
+        }
+
+        let value = artnet_manager.get_effect_value(&self.array_id, &self.value_name).or(self.initial_value.as_deref());
+        let matched = match &self.equals {
+            Some(equals) => value == Some(equals.as_str()),
+            None => value != self.initial_value.as_deref(),
+        };
+
+        self.current_tick += 1;
+
+        if matched {
+            self.done = true;
+        } else if let Some(timeout_ticks) = self.timeout_ticks.filter(|ticks| self.current_tick >= *ticks) {
+            self.done = true;
+
+            if self.on_timeout == defs::WaitForTimeout::Fail {
+                return Err(ArtnetError::WaitForTimeout(self.value_name.to_string(), timeout_ticks).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn describe(&self, indent: usize) -> String {
+        let condition = match &self.equals {
+            Some(equals) => format!("{} == {}", self.value_name, equals),
+            None => format!("{} changed", self.value_name),
+        };
+        let progress = match (self.done, self.timeout_ticks) {
+            (true, _) => "done".to_string(),
+            (false, Some(timeout_ticks)) => format!("tick {}/{}", self.current_tick, timeout_ticks),
+            (false, None) => format!("tick {}", self.current_tick),
+        };
+
+        format!("{:width$}wait_for {}; {}", "", condition, progress, width = indent * 2)
+    }
+}
+
+// Number of lights of each universe listed in node descriptions
+const DESCRIBED_LIGHTS: usize = 2;
+
+fn describe_nodes(header: String, nodes: &[Box<dyn EffectNodeRuntime>], indent: usize) -> String {
+    let mut lines = vec![format!("{:width$}{}", "", header, width = indent * 2)];
+    lines.extend(nodes.iter().map(|node| node.describe(indent + 1)));
+    lines.join("\n")
+}
+
+// First lights of each universe, e.g. "0: rgb:1,rgb:4 +1 more"
+fn get_lights_summary(lights: &[UniverseChannelDefinitions]) -> String {
+    lights
+        .iter()
+        .map(|universe_channels| {
+            let channels = universe_channels
+                .channels
+                .iter()
+                .take(DESCRIBED_LIGHTS)
+                .map(get_light_summary)
+                .collect::<Vec<_>>()
+                .join(",");
+
+            match universe_channels.channels.len().saturating_sub(DESCRIBED_LIGHTS) {
+                0 => format!("{}: {}", universe_channels.universe_id, channels),
+                more => format!("{}: {} +{} more", universe_channels.universe_id, channels, more),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Light channels in the lights syntax (the first channel if the channels are consecutive)
+fn get_light_summary(channel: &ChannelDefinition) -> String {
+    match *channel {
+        ChannelDefinition::Single(c) => format!("s:{}", c),
+        ChannelDefinition::Rgb(c1, c2, c3) if c2 == c1 + 1 && c3 == c1 + 2 => format!("rgb:{}", c1),
+        ChannelDefinition::TriWhite(c1, c2, c3) if c2 == c1 + 1 && c3 == c1 + 2 => format!("w:{}", c1),
+        ChannelDefinition::Rgb(c1, c2, c3) => format!("rgb:{}/{}/{}", c1, c2, c3),
+        ChannelDefinition::TriWhite(c1, c2, c3) => format!("w:{}/{}/{}", c1, c2, c3),
     }
 }
 
@@ -191,6 +557,16 @@ impl FadeEffectState {
             .iter()
             .any(|universe_state| universe_state.fade_needed())
     }
+
+    pub(self) fn set_channels(&self, artnet_manager: &mut ArtnetManager) -> Result<(), ArtnetError> {
+        for universe_state in self.universe_states.iter() {
+            for channel_state in universe_state.channel_states.iter() {
+                artnet_manager.set_channel(&universe_state.universe_id, &channel_state.get_channel_value())?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -244,20 +620,17 @@ enum FadeEffectDimmerState {
 }
 
 impl FadeEffectDimmerState {
-    pub(self) fn tick(&mut self) {
+    // True if the value of any of the components changed
+    pub(self) fn tick(&mut self) -> bool {
         match self {
-            FadeEffectDimmerState::Single(channel) => {
-                channel.tick();
-            }
+            FadeEffectDimmerState::Single(channel) => channel.tick(),
             FadeEffectDimmerState::Rgb(r, g, b) => {
-                r.tick();
-                g.tick();
-                b.tick();
+                let changed = [r.tick(), g.tick(), b.tick()];
+                changed.contains(&true)
             }
             FadeEffectDimmerState::TriWhite(w1, w2, w3) => {
-                w1.tick();
-                w2.tick();
-                w3.tick();
+                let changed = [w1.tick(), w2.tick(), w3.tick()];
+                changed.contains(&true)
             }
         }
     }
@@ -313,7 +686,10 @@ impl DmxChannelDelta {
         }
     }
 
-    pub fn tick(&mut self) {
+    // True if the value changed (the step of a slow fade is less than one on most ticks)
+    pub fn tick(&mut self) -> bool {
+        let previous_value = self.value;
+
         if self.is_increment {
             self.value += self.delta;
             if self.fraction >= 0 {
@@ -329,6 +705,7 @@ impl DmxChannelDelta {
         }
 
         self.fraction += self.dy as i32;
+        self.value != previous_value
     }
 
     pub(self) fn is_fade_needed(&self) -> bool {
@@ -343,7 +720,7 @@ impl FadeEffectNode {
     ) -> Result<FadeEffectState, ArtnetError> {
         let mut universe_states = Vec::<FadeEffectUniverseState>::new();
 
-        for universe in self.lights.iter() {
+        for universe in self.parameters.lights.iter() {
             universe_states.push(FadeEffectUniverseState {
                 universe_id: universe.universe_id.clone(),
                 channel_states: self.initialize_universe_state(artnet_manager, universe)?,
@@ -377,40 +754,65 @@ impl FadeEffectNode {
         universe_id: &str,
         channel_definition: &ChannelDefinition,
     ) -> Result<Option<FadeEffectChannelState>, ArtnetError> {
-        Ok(
-            match artnet_manager
-                .get_channel(universe_id, channel_definition)?
-                .value
-            {
-                DimmerValue::Rgb(current_r, current_g, current_b) => {
-                    self.target.rgb.map(|target| FadeEffectChannelState {
-                        channel: channel_definition.clone(),
-                        value: FadeEffectDimmerState::Rgb(
-                            DmxChannelDelta::new(current_r, target.0, self.ticks),
-                            DmxChannelDelta::new(current_g, target.1, self.ticks),
-                            DmxChannelDelta::new(current_b, target.2, self.ticks),
-                        ),
-                    })
-                }
-                DimmerValue::TriWhite(current_w1, current_w2, current_w3) => {
-                    self.target.tri_white.map(|target| FadeEffectChannelState {
-                        channel: channel_definition.clone(),
-                        value: FadeEffectDimmerState::TriWhite(
-                            DmxChannelDelta::new(current_w1, target.0, self.ticks),
-                            DmxChannelDelta::new(current_w2, target.1, self.ticks),
-                            DmxChannelDelta::new(current_w3, target.2, self.ticks),
-                        ),
-                    })
-                }
-                DimmerValue::Single(current) => {
-                    self.target.single.map(|target| FadeEffectChannelState {
-                        channel: channel_definition.clone(),
-                        value: FadeEffectDimmerState::Single(DmxChannelDelta::new(
-                            current, target, self.ticks,
-                        )),
-                    })
-                }
+        let dimming_amount = self.parameters.group_dimming.get_dimming_amount(universe_id, channel_definition, self.parameters.dimming_amount);
+
+        // Fade starts from the from value (if it has a value for this type of channel), otherwise from the current value
+        let from = self.parameters.from.as_ref().and_then(|from| from.get(channel_definition));
+        let current = match &from {
+            Some(from) => {
+                let from = ChannelValue {
+                    channel: channel_definition.clone(),
+                    value: from.get_dimmed_value(dimming_amount),
+                };
+                self.parameters.limits.limit(universe_id, &from).value
+            }
+            None => artnet_manager.get_channel(universe_id, channel_definition)?.value,
+        };
+        let target = match self.parameters.target.get(&current) {
+            Some(target) => ChannelValue {
+                channel: channel_definition.clone(),
+                value: target.get_dimmed_value(dimming_amount),
             },
-        )
+            None => return Ok(None),
+        };
+        let target = self.parameters.limits.limit(universe_id, &target).value;
+
+        let value = match (current, target) {
+            (DimmerValue::Rgb(current_r, current_g, current_b), DimmerValue::Rgb(r, g, b)) => {
+                FadeEffectDimmerState::Rgb(
+                    DmxChannelDelta::new(current_r, r, self.parameters.ticks),
+                    DmxChannelDelta::new(current_g, g, self.parameters.ticks),
+                    DmxChannelDelta::new(current_b, b, self.parameters.ticks),
+                )
+            }
+            (DimmerValue::TriWhite(current_w1, current_w2, current_w3), DimmerValue::TriWhite(w1, w2, w3)) => {
+                FadeEffectDimmerState::TriWhite(
+                    DmxChannelDelta::new(current_w1, w1, self.parameters.ticks),
+                    DmxChannelDelta::new(current_w2, w2, self.parameters.ticks),
+                    DmxChannelDelta::new(current_w3, w3, self.parameters.ticks),
+                )
+            }
+            (DimmerValue::Single(current), DimmerValue::Single(target)) => {
+                FadeEffectDimmerState::Single(DmxChannelDelta::new(current, target, self.parameters.ticks))
+            }
+            (_, target) => {
+                return Err(ArtnetError::ChannelValueMismatch(
+                    universe_id.to_string(),
+                    channel_definition.to_string(),
+                    target.to_string(),
+                )
+                .into())
+            }
+        };
+
+        // A channel already at its target is left alone, unless it is first set to the from value
+        if from.is_none() && !value.is_fade_needed() {
+            return Ok(None);
+        }
+
+        Ok(Some(FadeEffectChannelState {
+            channel: channel_definition.clone(),
+            value,
+        }))
     }
 }
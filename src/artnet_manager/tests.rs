@@ -1,21 +1,32 @@
 #[cfg(test)]
 mod test_universe {
-    use crate::artnet_manager::manager::{ArtnetController, Universe, DMX_DATA_OFFSET};
+    use crate::artnet_manager::artnet_packet::DMX_DATA_OFFSET;
+    use crate::artnet_manager::manager::{ArtnetController, Universe};
     use crate::artnet_manager::ArtnetError;
     use crate::defs::UniverseDefinition;
     use crate::dmx::{ChannelDefinition, ChannelValue, DimmerValue};
+    use error_stack::{Report, Result};
     use std::{net::IpAddr, str::FromStr, sync::Arc};
 
     fn get_universe_definition() -> UniverseDefinition {
         UniverseDefinition {
             description: "Test Universe".to_string(),
             controller: IpAddr::from_str("10.0.1.228").unwrap(),
-            net: 0,
-            subnet: 0,
-            universe: 0,
+            net: Some(0),
+            subnet: Some(0),
+            universe: Some(0),
             channels: 306,
+            pad_to: None,
             log: false,
             disable_send: true,
+            allow_duplicate_port_address: false,
+            idle: None,
+            max_delta_per_tick: None,
+            initial: None,
+            initial_b64: None,
+            send_divisor: None,
+            quiet_hours: None,
+            keepalive_burst: None,
         }
     }
 
@@ -32,6 +43,74 @@ mod test_universe {
         assert_eq!(universe.get_packet_bytes().len(), 306 + DMX_DATA_OFFSET);
     }
 
+    fn new_universe_with_channels(channels: u16) -> Result<Universe, ArtnetError> {
+        let controller =
+            Arc::new(ArtnetController::new(&IpAddr::from_str("10.0.1.228").unwrap()).unwrap());
+        let definition = UniverseDefinition {
+            channels,
+            ..get_universe_definition()
+        };
+
+        Universe::new(controller, "test", definition)
+    }
+
+    fn get_root_cause(e: &Report<ArtnetError>) -> &ArtnetError {
+        e.frames()
+            .filter_map(|frame| frame.downcast_ref::<ArtnetError>())
+            .last()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_universe_address_from_id() {
+        let new_universe = |universe_id: &str, net: Option<u8>, subnet: Option<u8>, universe: Option<u8>| {
+            let controller = Arc::new(ArtnetController::new(&IpAddr::from_str("10.0.1.228").unwrap()).unwrap());
+            let definition = UniverseDefinition { net, subnet, universe, ..get_universe_definition() };
+
+            Universe::new(controller, universe_id, definition)
+        };
+        // SubUni (subnet << 4 | universe) and Net bytes of the ArtDmx packet
+        let address_bytes = |universe: Universe| (universe.get_packet_bytes()[14], universe.get_packet_bytes()[15]);
+
+        for (universe_id, sub_uni, net) in [("0", 0x00, 0), ("15", 0x0f, 0), ("16", 0x10, 0), ("255", 0xff, 0), ("256", 0x00, 1), ("32767", 0xff, 127)] {
+            assert_eq!(address_bytes(new_universe(universe_id, None, None, None).unwrap()), (sub_uni, net), "universe {universe_id}");
+        }
+
+        for universe_id in ["32768", "-1", "kitchen"] {
+            let e = new_universe(universe_id, None, None, None).unwrap_err();
+            assert!(matches!(get_root_cause(&e), ArtnetError::MissingPortAddress(id) if id == universe_id));
+        }
+
+        // Given values must match the ones derived from the ID, a full explicit address is used as is
+        assert_eq!(address_bytes(new_universe("256", Some(1), None, Some(0)).unwrap()), (0x00, 1));
+        let e = new_universe("256", None, Some(1), None).unwrap_err();
+        assert!(matches!(get_root_cause(&e), ArtnetError::InconsistentPortAddress(_, "subnet", 1, 0)));
+        assert_eq!(address_bytes(new_universe("256", Some(0), Some(1), Some(2)).unwrap()), (0x12, 0));
+        assert_eq!(address_bytes(new_universe("kitchen", Some(2), Some(3), Some(4)).unwrap()), (0x34, 2));
+    }
+
+    #[test]
+    fn test_universe_channel_count() {
+        let e = new_universe_with_channels(0).unwrap_err();
+        assert!(matches!(get_root_cause(&e), ArtnetError::NoChannels));
+
+        let universe = new_universe_with_channels(1).unwrap();
+        assert_eq!(universe.get_packet_bytes().len(), 2 + DMX_DATA_OFFSET);
+
+        let universe = new_universe_with_channels(511).unwrap();
+        assert_eq!(universe.get_packet_bytes().len(), 512 + DMX_DATA_OFFSET);
+
+        let universe = new_universe_with_channels(512).unwrap();
+        assert_eq!(universe.get_packet_bytes().len(), 512 + DMX_DATA_OFFSET);
+
+        let e = new_universe_with_channels(513).unwrap_err();
+        assert!(matches!(get_root_cause(&e), ArtnetError::TooManyChannels(513)));
+        assert_eq!(
+            get_root_cause(&e).to_string(),
+            "Too many channels: 513 (must be 512 or less)"
+        );
+    }
+
     #[test]
     fn test_set_channel() {
         let mut universe = get_universe("test");
@@ -66,6 +145,34 @@ mod test_universe {
         assert_eq!(packet_bytes[DMX_DATA_OFFSET + 2], 30);
     }
 
+    #[test]
+    fn test_blackout() {
+        let mut universe = get_universe("test");
+        let rgb = ChannelDefinition::Rgb(0, 1, 2);
+
+        universe.set_channel(&ChannelValue { channel: rgb.clone(), value: DimmerValue::Rgb(10, 20, 30) }).unwrap();
+        universe.blackout();
+
+        // Live data is zeroed, but effects still see (and update) the saved values
+        assert_eq!(&universe.get_packet_bytes()[DMX_DATA_OFFSET..DMX_DATA_OFFSET + 3], &[0, 0, 0]);
+        assert_eq!(universe.get_channel(&rgb).unwrap().value, DimmerValue::Rgb(10, 20, 30));
+
+        universe.set_channel(&ChannelValue { channel: rgb.clone(), value: DimmerValue::Rgb(40, 50, 60) }).unwrap();
+        assert_eq!(&universe.get_packet_bytes()[DMX_DATA_OFFSET..DMX_DATA_OFFSET + 3], &[0, 0, 0]);
+        assert_eq!(universe.get_channel(&rgb).unwrap().value, DimmerValue::Rgb(40, 50, 60));
+
+        // Second blackout does not overwrite the saved values with zeros
+        universe.blackout();
+        assert_eq!(universe.get_channel(&rgb).unwrap().value, DimmerValue::Rgb(40, 50, 60));
+
+        universe.restore();
+        assert_eq!(&universe.get_packet_bytes()[DMX_DATA_OFFSET..DMX_DATA_OFFSET + 3], &[40, 50, 60]);
+        assert_eq!(universe.get_channel(&rgb).unwrap().value, DimmerValue::Rgb(40, 50, 60));
+
+        universe.set_channel(&ChannelValue { channel: ChannelDefinition::Single(0), value: DimmerValue::Single(70) }).unwrap();
+        assert_eq!(universe.get_packet_bytes()[DMX_DATA_OFFSET], 70);
+    }
+
     #[test]
     fn test_get_channel() {
         let mut universe = get_universe("test");
@@ -111,7 +218,7 @@ mod test_universe {
         };
         let result = universe.set_channel(&channel_value);
 
-        match result {
+        match result.as_ref().map_err(|e| e.current_context()) {
             Err(ArtnetError::InvalidChannel(d, 306, 306)) if d == "test (Test Universe)" => {}
             _ => panic!("Expected InvalidChannel error, got {:?}", result),
         }
@@ -126,7 +233,7 @@ mod test_universe {
             .is_ok());
         let result = universe.get_channel(&ChannelDefinition::Rgb(306, 100, 200));
 
-        match result {
+        match result.as_ref().map_err(|e| e.current_context()) {
             Err(ArtnetError::InvalidChannel(d, 306, 306)) if d == "test (Test Universe)" => {}
             _ => panic!("Expected InvalidChannel error, got {:?}", result),
         }
@@ -136,34 +243,46 @@ mod test_universe {
 #[cfg(test)]
 mod test_artnet_manager {
     use crate::{
-        artnet_manager::ArtnetManager,
-        defs::UniverseDefinition,
-        dmx::{ChannelDefinition, ChannelValue, DimmerValue},
-        messages::{ToArtnetManagerMessage, ToMqttPublisherMessage},
+        artnet_manager::{artnet_packet::DMX_DATA_OFFSET, quiet_hours::QuietHours, watchers::WatcherCondition, ArtnetError, ArtnetManager, EffectNodeRuntime},
+        defs::{self, EffectMaxTicks, MonitorDefinition, SetChannelsParameters, UniverseDefinition, UniverseIdleDefinition, UniverseInitialDefinition, UniverseTarget, WatcherDefinition},
+        dmx::{ChannelDefinition, ChannelLimits, ChannelValue, DimmerValue, DmxParseError},
+        messages::{StartEffectRequest, ToArtnetManagerMessage, ToMqttPublisherMessage},
+        manager_channel::{self, ManagerSender},
+        mqtt_publisher::ErrorCategory,
+        service::ServiceSettings,
+        sim,
     };
 
-    use std::{net::IpAddr, str::FromStr, sync::Arc};
-    use tokio::sync::mpsc::Sender;
+    use std::{net::IpAddr, str::FromStr, sync::Arc, time::{Duration, Instant}};
+    use tokio::io::{AsyncBufReadExt, BufReader};
     use tokio_util::sync::CancellationToken;
 
     fn get_universe_definition() -> UniverseDefinition {
         UniverseDefinition {
             description: "Test Universe".to_string(),
             controller: IpAddr::from_str("10.0.1.228").unwrap(),
-            net: 0,
-            subnet: 0,
-            universe: 0,
+            net: Some(0),
+            subnet: Some(0),
+            universe: Some(0),
             channels: 306,
+            pad_to: None,
             log: false,
             disable_send: true,
+            allow_duplicate_port_address: false,
+            idle: None,
+            max_delta_per_tick: None,
+            initial: None,
+            initial_b64: None,
+            send_divisor: None,
+            quiet_hours: None,
+            keepalive_burst: None,
         }
     }
 
-    fn start_artnet_manager(cancel: CancellationToken) -> Sender<ToArtnetManagerMessage> {
+    fn start_artnet_manager(cancel: CancellationToken) -> ManagerSender<ToArtnetManagerMessage> {
         let (to_artnet_manager_sender, to_artnet_manager_receiver) =
-            tokio::sync::mpsc::channel::<ToArtnetManagerMessage>(10);
-        let (to_mqtt_publisher_sender, _) =
-            tokio::sync::mpsc::channel::<ToMqttPublisherMessage>(10);
+            manager_channel::channel::<ToArtnetManagerMessage>("artnet", 10);
+        let (to_mqtt_publisher_sender, _) = async_channel::bounded::<ToMqttPublisherMessage>(10);
 
         tokio::spawn(async move {
             let mut manager = ArtnetManager::new();
@@ -194,7 +313,8 @@ mod test_artnet_manager {
         assert!(manager.controllers.len() == 1);
         assert!(manager.universes.len() == 1);
 
-        let universe_definition = get_universe_definition();
+        let mut universe_definition = get_universe_definition();
+        universe_definition.universe = Some(1);
         assert!(manager.add_universe("test2", universe_definition).is_ok());
         assert!(manager.controllers.len() == 1);
         assert!(manager.universes.len() == 2);
@@ -206,8 +326,43 @@ mod test_artnet_manager {
 
         // Remove the second universe and ensure that the controller is gone
         assert!(manager.remove_universe("test2").is_ok());
-        assert!(manager.universes.len() == 0);
-        assert!(manager.controllers.len() == 0);
+        assert!(manager.universes.is_empty());
+        assert!(manager.controllers.is_empty());
+    }
+
+    #[test]
+    fn test_controller_retention() {
+        let mut manager = ArtnetManager::new();
+
+        // Replacing the only universe of a controller keeps using the same socket
+        manager.add_universe("test", get_universe_definition()).unwrap();
+        let port = manager.universes["test"].get_controller_port();
+        manager.add_universe("test", get_universe_definition()).unwrap();
+        assert_eq!(manager.universes["test"].get_controller_port(), port);
+        assert_eq!(manager.controllers.len(), 1);
+
+        // Replacing the universe with one on another controller removes the unused controller
+        let mut definition = get_universe_definition();
+        definition.controller = IpAddr::from_str("10.0.1.229").unwrap();
+        manager.add_universe("test", definition).unwrap();
+        assert_eq!(manager.controllers.len(), 1);
+        manager.remove_universe("test").unwrap();
+
+        // Controller is retained after its last universe is removed, and reused when a universe is re-added
+        let mut manager = ArtnetManager::new().with_controller_retention(Duration::from_secs(10));
+        manager.add_universe("test", get_universe_definition()).unwrap();
+        let port = manager.universes["test"].get_controller_port();
+
+        manager.remove_universe("test").unwrap();
+        assert_eq!(manager.controllers.len(), 1);
+        manager.add_universe("test", get_universe_definition()).unwrap();
+        assert_eq!(manager.universes["test"].get_controller_port(), port);
+
+        manager.remove_universe("test").unwrap();
+        manager.expire_retained_controllers(Instant::now());
+        assert_eq!(manager.controllers.len(), 1);
+        manager.expire_retained_controllers(Instant::now() + Duration::from_secs(11));
+        assert!(manager.controllers.is_empty());
     }
 
     #[test]
@@ -240,267 +395,2787 @@ mod test_artnet_manager {
         assert_eq!(v.value, DimmerValue::Rgb(3, 5, 8));
     }
 
-    #[tokio::test]
-    async fn test_messaging() {
-        let cancel = CancellationToken::new();
-        let sender = start_artnet_manager(cancel.clone());
-        let universe_definition = get_universe_definition();
-        let (tx, rx) = tokio::sync::oneshot::channel();
+    #[test]
+    fn test_blackout_universe() {
+        let mut manager = ArtnetManager::new();
+        manager.add_universe("test", get_universe_definition()).unwrap();
+
+        let set_channels = SetChannelsParameters {
+            universe_id: "test".to_string(),
+            channels: "s:5".to_string(),
+            target: "s(100)".to_string(),
+            dimming_amount: None,
+            origin: None,
+        };
+        manager.set_channels(&set_channels).unwrap();
+        manager.blackout_universe("test", false).unwrap();
 
-        sender
-            .send(ToArtnetManagerMessage::AddUniverse(
-                Arc::from("test"),
-                universe_definition,
-                tx,
-            ))
-            .await
-            .unwrap();
-        let result = rx.await.unwrap();
-        cancel.cancel();
-        assert!(result.is_ok());
+        let channel = ChannelDefinition::Single(5);
+        assert_eq!(manager.get_channel("test", &channel).unwrap().value, DimmerValue::Single(100));
+
+        manager.blackout_universe("test", true).unwrap();
+        assert_eq!(manager.get_channel("test", &channel).unwrap().value, DimmerValue::Single(100));
+
+        let e = manager.blackout_universe("none", false).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidUniverse(_)));
     }
-}
 
-#[cfg(test)]
-mod test_effect_nodes {
-    use std::{net::IpAddr, str::FromStr, sync::Arc};
+    #[test]
+    fn test_universe_groups() {
+        let mut manager = ArtnetManager::new();
+        let group = |universes: &[&str]| Some(defs::UniverseGroupDefinition { universes: universes.iter().map(|id| Arc::from(*id)).collect() });
+        let bars = UniverseTarget::Group(Arc::from("bars"));
+        let channel = ChannelDefinition::Single(5);
+
+        for (universe_id, universe) in [("4", 4), ("5", 5)] {
+            manager.add_universe(universe_id, UniverseDefinition { universe: Some(universe), ..get_universe_definition() }).unwrap();
+            manager.set_channels(&SetChannelsParameters {
+                universe_id: universe_id.to_string(),
+                channels: "s:5".to_string(),
+                target: "s(100)".to_string(),
+                dimming_amount: None,
+                origin: None,
+            }).unwrap();
+        }
 
-    use crate::{
-        array_manager::ArrayManager,
-        artnet_manager::{ArtnetManager, EffectNodeRuntime},
-        defs,
-        defs::{DmxArray, UniverseDefinition},
-        dmx::{ChannelValue, DimmerValue, ChannelDefinition},
-    };
+        // Members must be defined universes
+        let e = manager.set_universe_group("bars", group(&["4", "6"])).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::UniverseGroupMemberNotFound(name, member) if name == "bars" && member == "6"));
+        let e = manager.set_universe_group("bars", group(&[])).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::EmptyUniverseGroup(_)));
 
-    fn get_universe_definition() -> UniverseDefinition {
-        UniverseDefinition {
-            description: "Test Universe".to_string(),
-            controller: IpAddr::from_str("10.0.1.228").unwrap(),
-            net: 0,
-            subnet: 0,
-            universe: 0,
-            channels: 306,
-            log: true,
-            disable_send: false,
+        manager.set_universe_group("bars", group(&["4", "5"])).unwrap();
+        assert_eq!(manager.get_target_universes(&bars).unwrap(), vec![Arc::from("4"), Arc::from("5")]);
+        assert_eq!(manager.get_universes_send_status(&bars).unwrap().len(), 2);
+
+        manager.blackout_universes(&bars, false).unwrap();
+        for universe_id in ["4", "5"] {
+            assert_eq!(manager.universes[universe_id].get_packet_bytes()[DMX_DATA_OFFSET + 5], 0);
         }
+
+        manager.blackout_universes(&bars, true).unwrap();
+        assert_eq!(manager.get_channel("4", &channel).unwrap().value, DimmerValue::Single(100));
+
+        // Removed member fails the command without changing the other universes
+        manager.remove_universe("5").unwrap();
+        let e = manager.blackout_universes(&bars, false).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::UniverseGroupMemberNotFound(_, member) if member == "5"));
+        assert_eq!(manager.universes["4"].get_packet_bytes()[DMX_DATA_OFFSET + 5], 100);
+
+        manager.set_universe_group("bars", None).unwrap();
+        let e = manager.blackout_universes(&bars, false).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::UniverseGroupNotFound(_)));
+        let e = manager.set_universe_group("bars", None).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::UniverseGroupNotFound(_)));
     }
 
     #[test]
-    fn test_fade_node1() {
-        let array_json = r#"
-        {
-            "universe_id": "0",
-            "description": "Test array",
-            "lights": {
-                "all": "s:0"
-            },
-            "effects": {
-                "on": {
-                    "type": "fade",
-                    "lights": "@all",
-                    "ticks": 4,
-                    "target": "s(255); rgb(255,255,255); w(255,255,255)"
-                },
-                "off": {
-                    "type": "fade",
-                    "lights": "@all",
-                    "ticks": 8,
-                    "target": "s(0); rgb(0,0,0); w(0,0,0)"
-                }
-            }
-        }"#;
+    fn test_max_delta_per_tick() {
+        let mut manager = ArtnetManager::new().with_max_delta_per_tick(Some(64));
+        manager.add_universe("test", get_universe_definition()).unwrap();
+        let set = |manager: &mut ArtnetManager, target: &str| manager.set_channels(&SetChannelsParameters {
+            universe_id: "test".to_string(),
+            channels: "s:5".to_string(),
+            target: target.to_string(),
+            dimming_amount: None,
+            origin: None,
+        }).unwrap();
+        let sent_value = |manager: &mut ArtnetManager| {
+            manager.send_modified_universes();
+            manager.universes["test"].get_packet_bytes()[DMX_DATA_OFFSET + 5]
+        };
 
-        let mut array_manager = ArrayManager::new();
-        let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+        // The first step is done by the set, each send moves one more step
+        set(&mut manager, "s(255)");
+        assert_eq!(manager.get_channel("test", &ChannelDefinition::Single(5)).unwrap().value, DimmerValue::Single(64));
+        assert_eq!([0; 4].map(|_| sent_value(&mut manager)), [64, 128, 192, 255]);
+        assert!(manager.universes["test"].slewing_channels.is_empty());
+
+        // Setting a channel while it is slewing moves from its current value toward the new one
+        set(&mut manager, "s(0)");
+        assert_eq!(sent_value(&mut manager), 191);
+        set(&mut manager, "s(150)");
+        assert_eq!(sent_value(&mut manager), 150);
+        assert_eq!(sent_value(&mut manager), 150);
+
+        // Universe setting overrides the service setting
+        let definition = UniverseDefinition { max_delta_per_tick: Some(200), ..get_universe_definition() };
+        manager.add_universe("test", definition).unwrap();
+        set(&mut manager, "s(255)");
+        assert_eq!([0; 2].map(|_| sent_value(&mut manager)), [200, 255]);
 
-        let mut artnet_manager = ArtnetManager::new();
-        artnet_manager
-            .add_universe("0", get_universe_definition())
-            .unwrap();
+        let mut manager = ArtnetManager::new();
+        manager.add_universe("test", get_universe_definition()).unwrap();
+        set(&mut manager, "s(255)");
+        assert_eq!(sent_value(&mut manager), 255);
+    }
 
-        array_manager.add_array(Arc::from("test"), Box::new(array)).unwrap();
-        let node = array_manager
-            .get_usage_effect_runtime(
-                &defs::EffectUsage::On,
-                "test",
-                None,
-                defs::DIMMING_AMOUNT_MAX,
-            )
-            .unwrap();
+    #[test]
+    fn test_invalid_universe_id() {
+        let mut manager = ArtnetManager::new();
 
-        println!("{:?}", node);
+        for universe_id in ["a/b", "a+b", "#", " "] {
+            let e = manager.add_universe(universe_id, get_universe_definition()).unwrap_err();
+            assert!(matches!(e.current_context(), ArtnetError::InvalidUniverseId(id, _) if id == universe_id));
+        }
+    }
 
-        run_node(node, &mut artnet_manager);
-        println!("{:?}", artnet_manager.set_channel_log);
+    #[test]
+    fn test_channel_limits() {
+        let mut manager = ArtnetManager::new();
+        manager.add_universe("test", get_universe_definition()).unwrap();
+
+        let mut limits = ChannelLimits::default();
+        limits.add("test", &ChannelDefinition::Rgb(10, 11, 12), &"rgb(100,150,200)".parse().unwrap());
+        manager.set_channel_limits("array1", Some(Arc::new(limits))).unwrap();
+
+        let mut limits = ChannelLimits::default();
+        limits.add("test", &ChannelDefinition::Single(11), &"s(120)".parse().unwrap());
+        manager.set_channel_limits("array2", Some(Arc::new(limits))).unwrap();
+
+        let set_channels = SetChannelsParameters {
+            universe_id: "test".to_string(),
+            channels: "rgb:10,s:20".to_string(),
+            target: "rgb(255,255,255);s(255)".to_string(),
+            dimming_amount: None,
+            origin: None,
+        };
 
-        assert_eq!(artnet_manager.set_channel_log.len(), 4);
-        assert_eq!(
-            artnet_manager.set_channel_log,
-            [
-                ChannelValue {
-                    channel: ChannelDefinition::Single(0),
-                    value: DimmerValue::Single(64)
-                },
-                ChannelValue {
-                    channel: ChannelDefinition::Single(0),
-                    value: DimmerValue::Single(128)
-                },
-                ChannelValue {
-                    channel: ChannelDefinition::Single(0),
-                    value: DimmerValue::Single(191)
-                },
-                ChannelValue {
-                    channel: ChannelDefinition::Single(0),
-                    value: DimmerValue::Single(255)
-                },
-            ]
-        );
+        // Both arrays limits are applied, the lower limit wins
+        manager.set_channels(&set_channels).unwrap();
+        let v = manager
+            .get_channel("test", &ChannelDefinition::Rgb(10, 11, 12))
+            .unwrap();
+        assert_eq!(v.value, DimmerValue::Rgb(100, 120, 200));
+        let v = manager.get_channel("test", &ChannelDefinition::Single(20)).unwrap();
+        assert_eq!(v.value, DimmerValue::Single(255));
 
-        let node = array_manager
-            .get_usage_effect_runtime(
-                &defs::EffectUsage::Off,
-                "test",
-                None,
-                defs::DIMMING_AMOUNT_MAX,
-            )
+        // Replacing the limits of an array replaces its part of the merged limits
+        let mut limits = ChannelLimits::default();
+        limits.add("test", &ChannelDefinition::Single(20), &"s(50)".parse().unwrap());
+        manager.set_channel_limits("array2", Some(Arc::new(limits))).unwrap();
+        manager.set_channels(&set_channels).unwrap();
+        let v = manager.get_channel("test", &ChannelDefinition::Rgb(10, 11, 12)).unwrap();
+        assert_eq!(v.value, DimmerValue::Rgb(100, 150, 200));
+        let v = manager.get_channel("test", &ChannelDefinition::Single(20)).unwrap();
+        assert_eq!(v.value, DimmerValue::Single(50));
+
+        // Removing the limits
+        manager.set_channel_limits("array1", None).unwrap();
+        manager.set_channel_limits("array2", Some(Arc::new(ChannelLimits::default()))).unwrap();
+        manager.set_channels(&set_channels).unwrap();
+        let v = manager
+            .get_channel("test", &ChannelDefinition::Rgb(10, 11, 12))
             .unwrap();
+        assert_eq!(v.value, DimmerValue::Rgb(255, 255, 255));
+    }
 
-        println!("{:?}", node);
+    #[test]
+    fn test_set_channels_missing_target_values() {
+        let mut manager = ArtnetManager::new();
+        manager.add_universe("test", get_universe_definition()).unwrap();
+
+        let set_channels = |channels: &str, target: &str| SetChannelsParameters {
+            universe_id: "test".to_string(),
+            channels: channels.to_string(),
+            target: target.to_string(),
+            dimming_amount: None,
+            origin: None,
+        };
 
-        run_node(node, &mut artnet_manager);
-        println!("{:?}", artnet_manager.set_channel_log);
+        // Nothing is set, and all the channels without a target value are reported
+        let e = manager.set_channels(&set_channels("rgb:10,s:20,s:21,rgb:30", "rgb(255,0,0)")).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::MissingTargetValues(channels, target) if channels == "s(20), s(21)" && target == "rgb(255,0,0)"));
+        assert_eq!(manager.get_channel("test", &ChannelDefinition::Rgb(10, 11, 12)).unwrap().value, DimmerValue::Rgb(0, 0, 0));
 
-        assert_eq!(artnet_manager.set_channel_log.len(), 8);
-        assert_eq!(
-            artnet_manager.set_channel_log,
-            [
-                ChannelValue {
-                    channel: ChannelDefinition::Single(0),
-                    value: DimmerValue::Single(223)
-                },
-                ChannelValue {
-                    channel: ChannelDefinition::Single(0),
-                    value: DimmerValue::Single(191)
-                },
-                ChannelValue {
-                    channel: ChannelDefinition::Single(0),
-                    value: DimmerValue::Single(159)
-                },
-                ChannelValue {
-                    channel: ChannelDefinition::Single(0),
-                    value: DimmerValue::Single(127)
-                },
-                ChannelValue {
-                    channel: ChannelDefinition::Single(0),
-                    value: DimmerValue::Single(96)
-                },
-                ChannelValue {
-                    channel: ChannelDefinition::Single(0),
-                    value: DimmerValue::Single(64)
-                },
-                ChannelValue {
-                    channel: ChannelDefinition::Single(0),
-                    value: DimmerValue::Single(32)
-                },
-                ChannelValue {
-                    channel: ChannelDefinition::Single(0),
-                    value: DimmerValue::Single(0)
-                },
-            ]
-        );
+        // Invalid channel after valid ones
+        manager.set_channels(&set_channels("rgb:10,s:1000", "rgb(255,0,0);s(1)")).unwrap_err();
+        assert_eq!(manager.get_channel("test", &ChannelDefinition::Rgb(10, 11, 12)).unwrap().value, DimmerValue::Rgb(0, 0, 0));
+
+        manager.set_channels(&set_channels("rgb:10,s:20", "rgb(255,0,0);s(1)")).unwrap();
+        assert_eq!(manager.get_channel("test", &ChannelDefinition::Rgb(10, 11, 12)).unwrap().value, DimmerValue::Rgb(255, 0, 0));
+        assert_eq!(manager.get_channel("test", &ChannelDefinition::Single(20)).unwrap().value, DimmerValue::Single(1));
     }
 
     #[test]
-    fn test_fade_node2() {
-        let array_json = r#"
-        {
-            "universe_id": "0",
-            "description": "Test array",
-            "lights": {
-                "all": "rgb:0"
-            },
-            "effects": {
-                "on": {
-                    "type": "fade",
-                    "lights": "@all",
-                    "ticks": 4,
-                    "target": "s(255); rgb(255,255,255); w(255,255,255)"
-                },
-                "off": {
-                    "type": "fade",
-                    "lights": "@all",
-                    "ticks": 8,
-                    "target": "s(0); rgb(0,0,0); w(0,0,0)"
-                }
-            }
-        }"#;
+    fn test_set_channels_parse_errors() {
+        let mut manager = ArtnetManager::new();
+        manager.add_universe("test", get_universe_definition()).unwrap();
+
+        let set_channels = |channels: &str, target: &str| SetChannelsParameters {
+            universe_id: "test".to_string(),
+            channels: channels.to_string(),
+            target: target.to_string(),
+            dimming_amount: None,
+            origin: None,
+        };
 
-        let mut array_manager = ArrayManager::new();
-        let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+        // The invalid token and its position in the list are reported
+        let e = manager.set_channels(&set_channels("rgb:10, s:20, rgb:x", "rgb(255,0,0);s(1)")).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::Parse(DmxParseError::Entry(_, 3, _))));
+        assert_eq!(
+            e.to_string(),
+            "Entry 3 of 'rgb:10, s:20, rgb:x': Invalid channel 'rgb:x': channel address is not a number (0-65535) (expected n, s:n, rgb:n, w:n, rgb:r/g/b or w:w1/w2/w3)"
+        );
 
-        let mut artnet_manager = ArtnetManager::new();
-        artnet_manager
-            .add_universe("0", get_universe_definition())
-            .unwrap();
+        let e = manager.set_channels(&set_channels("rgb:10", "s(1);rgb(1,2)")).unwrap_err();
+        assert_eq!(e.to_string(), "Entry 2 of 's(1);rgb(1,2)': Invalid dimmer value 'rgb(1,2)' (expected s(n), rgb(r,g,b) or w(w1,w2,w3))");
 
-        array_manager.add_array(Arc::from("test"), Box::new(array)).unwrap();
+        // Single entries are reported without a position
+        let e = manager.set_channels(&set_channels("s:20", "s(300)")).unwrap_err();
+        assert_eq!(e.to_string(), "Invalid dimmer value 's(300)': component 1 is 300 but must be 0-255");
+    }
 
-        let node = array_manager
-            .get_usage_effect_runtime(
-                &defs::EffectUsage::On,
-                "test",
-                None,
-                800, // 80% dimming
-            )
+    #[test]
+    fn test_validate_universe() {
+        let mut manager = ArtnetManager::new();
+        let definition = |universe: u8, channels: u16| UniverseDefinition { universe: Some(universe), channels, ..get_universe_definition() };
+
+        manager.validate_universe("test", &definition(0, 306)).unwrap();
+        assert!(manager.universes.is_empty() && manager.controllers.is_empty());
+
+        let e = manager.validate_universe("test", &definition(16, 306)).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidUniverseNumber(16)));
+        let e = manager.validate_universe("test", &definition(0, 513)).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::TooManyChannels(513)));
+
+        let idle = Some(UniverseIdleDefinition { after_seconds: 10, target: "s(10)".to_string(), channels: "s:1,s:306".to_string() });
+        let e = manager.validate_universe("test", &UniverseDefinition { idle, ..definition(0, 306) }).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidChannel(_, 306, 306)));
+
+        // Same checks as adding the universe, so a duplicate port address is rejected unless it replaces the universe
+        manager.add_universe("test", definition(0, 306)).unwrap();
+        manager.validate_universe("test", &definition(0, 16)).unwrap();
+        let e = manager.validate_universe("other", &definition(0, 16)).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::DuplicatePortAddress(..)));
+        assert!(manager.get_channel("test", &ChannelDefinition::Single(300)).is_ok());
+    }
+
+    #[test]
+    fn test_initial_values() {
+        let mut manager = ArtnetManager::new();
+        let get = |manager: &ArtnetManager, channel: &str| manager.get_channel("test", &channel.parse().unwrap()).unwrap().value;
+        let initial = |target: &str, channels: &str| Some(UniverseInitialDefinition { target: target.to_string(), channels: channels.to_string() });
+
+        let definition = UniverseDefinition { initial: initial("s(40);rgb(1,2,3)", "s:0,rgb:10,s:305"), ..get_universe_definition() };
+        manager.add_universe("test", definition).unwrap();
+        assert_eq!(get(&manager, "s:0"), DimmerValue::Single(40));
+        assert_eq!(get(&manager, "rgb:10"), DimmerValue::Rgb(1, 2, 3));
+        assert_eq!(get(&manager, "s:305"), DimmerValue::Single(40));
+        assert_eq!(get(&manager, "s:1"), DimmerValue::Single(0));
+
+        // The initial values are the node's state, so nothing is sent until the periodic refresh
+        assert!(!manager.universes["test"].modified);
+
+        // Replacing the universe starts again from its initial values
+        let definition = UniverseDefinition { initial_b64: Some("AQID".to_string()), ..get_universe_definition() };
+        manager.add_universe("test", definition).unwrap();
+        assert_eq!(get(&manager, "rgb:0"), DimmerValue::Rgb(1, 2, 3));
+        assert_eq!(get(&manager, "s:10"), DimmerValue::Single(0));
+
+        let invalid = [
+            UniverseDefinition { initial: initial("s(1)", "s:1"), initial_b64: Some("AQID".to_string()), ..get_universe_definition() },
+            UniverseDefinition { initial: initial("s(1)", "s:306"), ..get_universe_definition() },
+            UniverseDefinition { initial: initial("s(1)", "rgb:1"), ..get_universe_definition() },
+            UniverseDefinition { initial_b64: Some("not base64!".to_string()), ..get_universe_definition() },
+            UniverseDefinition { initial_b64: Some("AQID".to_string()), channels: 2, ..get_universe_definition() },
+        ];
+        let errors = invalid.map(|definition| manager.validate_universe("test", &definition).unwrap_err());
+
+        assert!(matches!(errors[0].current_context(), ArtnetError::ConflictingInitialValues(_)));
+        assert!(matches!(errors[1].current_context(), ArtnetError::InvalidChannel(_, 306, 306)));
+        assert!(matches!(errors[2].current_context(), ArtnetError::MissingTargetValue(..)));
+        assert!(matches!(errors[3].current_context(), ArtnetError::InvalidInitialData(..)));
+        assert!(matches!(errors[4].current_context(), ArtnetError::InitialDataOutOfRange(_, 3, 2)));
+
+        // Invalid initial values do not replace the universe
+        assert!(manager.add_universe("test", UniverseDefinition { initial: initial("s(1)", "s:306"), ..get_universe_definition() }).is_err());
+        assert_eq!(get(&manager, "rgb:0"), DimmerValue::Rgb(1, 2, 3));
+    }
+
+    #[test]
+    fn test_copy_channels() {
+        let mut manager = ArtnetManager::new();
+        manager.add_universe("0", UniverseDefinition { universe: Some(0), ..get_universe_definition() }).unwrap();
+        manager.add_universe("4", UniverseDefinition { universe: Some(4), channels: 120, ..get_universe_definition() }).unwrap();
+
+        let set = |manager: &mut ArtnetManager, channel: &str, value: &str| {
+            manager.set_channel("0", &ChannelValue { channel: channel.parse().unwrap(), value: value.parse().unwrap() }).unwrap();
+        };
+        let get = |manager: &ArtnetManager, universe_id: &str, channel: &str| manager.get_channel(universe_id, &channel.parse().unwrap()).unwrap().value;
+        let copy = |manager: &mut ArtnetManager, json: &str| manager.copy_channels(&serde_json::from_str(json).unwrap());
+
+        set(&mut manager, "rgb:1", "rgb(10,20,30)");
+        set(&mut manager, "rgb:4", "rgb(40,50,60)");
+        set(&mut manager, "s:7", "s(70)");
+
+        copy(&mut manager, r#"{ "from_universe": "0", "to_universe": "4", "channels": "rgb:1,rgb:4,s:7", "to_offset": 100 }"#).unwrap();
+        assert_eq!(get(&manager, "4", "rgb:101"), DimmerValue::Rgb(10, 20, 30));
+        assert_eq!(get(&manager, "4", "rgb:104"), DimmerValue::Rgb(40, 50, 60));
+        assert_eq!(get(&manager, "4", "s:107"), DimmerValue::Single(70));
+        assert_eq!(get(&manager, "4", "s:100"), DimmerValue::Single(0));
+
+        // Within a universe, overlapping channels are copied from their values before the copy
+        copy(&mut manager, r#"{ "from_universe": "0", "to_universe": "0", "channels": "rgb:1,rgb:4", "to_offset": -1 }"#).unwrap();
+        assert_eq!(get(&manager, "0", "rgb:0"), DimmerValue::Rgb(10, 20, 30));
+        assert_eq!(get(&manager, "0", "rgb:3"), DimmerValue::Rgb(40, 50, 60));
+
+        // Nothing is copied if any destination channel is outside the universe
+        let e = copy(&mut manager, r#"{ "from_universe": "0", "to_universe": "4", "channels": "s:5,rgb:4", "to_offset": 114 }"#).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::CopyOutOfRange(channel, 114, _, 120) if channel == "rgb:4/5/6"));
+        assert_eq!(get(&manager, "4", "s:119"), DimmerValue::Single(0));
+        let e = copy(&mut manager, r#"{ "from_universe": "0", "to_universe": "4", "channels": "s:7", "to_offset": -8 }"#).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::CopyOutOfRange(..)));
+
+        // All the channels fit only a universe that is large enough
+        let e = copy(&mut manager, r#"{ "from_universe": "0", "to_universe": "4", "all": true }"#).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::CopyOutOfRange(channel, 0, _, 120) if channel == "s(120)"));
+        copy(&mut manager, r#"{ "from_universe": "4", "to_universe": "0", "all": true, "to_offset": 10 }"#).unwrap();
+        assert_eq!(get(&manager, "0", "rgb:111"), DimmerValue::Rgb(10, 20, 30));
+        assert_eq!(get(&manager, "0", "s:129"), DimmerValue::Single(0));
+
+        for json in [r#"{ "from_universe": "0", "to_universe": "4" }"#, r#"{ "from_universe": "0", "to_universe": "4", "channels": "s:1", "all": true }"#] {
+            let e = copy(&mut manager, json).unwrap_err();
+            assert!(matches!(e.current_context(), ArtnetError::InvalidCopyChannels(_)));
+        }
+        let e = copy(&mut manager, r#"{ "from_universe": "0", "to_universe": "missing", "all": true }"#).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidUniverse(_)));
+    }
+
+    #[test]
+    fn test_channel_aliases() {
+        let mut manager = ArtnetManager::new();
+        manager.add_universe("test", get_universe_definition()).unwrap();
+
+        let alias = |from: &str, to: Option<&str>| defs::AliasCommandParameters {
+            universe_id: "test".to_string(),
+            from: from.to_string(),
+            to: to.map(|to| to.to_string()),
+        };
+        let set_channels = |channels: &str, target: &str| SetChannelsParameters {
+            universe_id: "test".to_string(),
+            channels: channels.to_string(),
+            target: target.to_string(),
+            dimming_amount: None,
+            origin: None,
+        };
+        let get_rgb = |manager: &ArtnetManager, channel: u16| {
+            manager.get_channel("test", &ChannelDefinition::Rgb(channel, channel + 1, channel + 2)).unwrap().value
+        };
+
+        manager.set_channel_alias(&alias("rgb:10", Some("rgb:40"))).unwrap();
+        manager.set_channels(&set_channels("rgb:10", "rgb(10,20,30)")).unwrap();
+        assert_eq!(get_rgb(&manager, 40), DimmerValue::Rgb(10, 20, 30));
+
+        // Reads go through the alias, so relative targets apply to the actual channels
+        manager.set_channels(&set_channels("rgb:10", "rgb(+5,+5,+5)")).unwrap();
+        assert_eq!(get_rgb(&manager, 10), DimmerValue::Rgb(15, 25, 35));
+        assert_eq!(manager.get_channel("test", &ChannelDefinition::Single(11)).unwrap().value, DimmerValue::Single(25));
+        assert_eq!(manager.get_channel("test", &ChannelDefinition::Single(41)).unwrap().value, DimmerValue::Single(25));
+
+        let diagnostics = manager.get_diagnostics();
+        assert_eq!(diagnostics.channel_aliases["test"]["rgb:10/11/12"], "rgb:40/41/42");
+
+        // Out of range, different type, and colliding aliases are rejected
+        let e = manager.set_channel_alias(&alias("rgb:20", Some("rgb:510"))).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidChannelAlias(..)));
+        let e = manager.set_channel_alias(&alias("rgb:20", Some("s:50"))).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidChannelAlias(..)));
+        let e = manager.set_channel_alias(&alias("s:12", Some("s:50"))).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::ChannelAliasCollision(..)));
+        let e = manager.set_channel_alias(&alias("s:20", Some("s:42"))).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::ChannelAliasCollision(..)));
+        manager.set_channel_alias(&alias("s:20", Some("s:43"))).unwrap();
+
+        // Replacing the alias, then removing it restores direct addressing
+        manager.set_channel_alias(&alias("rgb:10", Some("rgb:60"))).unwrap();
+        manager.set_channels(&set_channels("rgb:10", "rgb(1,2,3)")).unwrap();
+        assert_eq!(get_rgb(&manager, 60), DimmerValue::Rgb(1, 2, 3));
+
+        manager.set_channel_alias(&alias("rgb:10", None)).unwrap();
+        manager.set_channel_alias(&alias("s:20", Some("s:20"))).unwrap();
+        assert!(manager.get_diagnostics().channel_aliases.is_empty());
+
+        manager.set_channels(&set_channels("rgb:10", "rgb(7,8,9)")).unwrap();
+        assert_eq!(get_rgb(&manager, 10), DimmerValue::Rgb(7, 8, 9));
+        assert_eq!(get_rgb(&manager, 60), DimmerValue::Rgb(1, 2, 3));
+    }
+
+    #[test]
+    fn test_set_channels_batch() {
+        let mut manager = ArtnetManager::new();
+        manager.add_universe("0", get_universe_definition()).unwrap();
+
+        let mut definition = get_universe_definition();
+        definition.universe = Some(1);
+        manager.add_universe("1", definition).unwrap();
+
+        let set_channels = |universe_id: &str, channels: &str, target: &str| SetChannelsParameters {
+            universe_id: universe_id.to_string(),
+            channels: channels.to_string(),
+            target: target.to_string(),
+            dimming_amount: None,
+            origin: None,
+        };
+        let get_single = |manager: &ArtnetManager, universe_id: &str, channel: u16| {
+            manager.get_channel(universe_id, &ChannelDefinition::Single(channel)).unwrap().value
+        };
+
+        manager
+            .set_channels_batch(&[
+                set_channels("0", "s:1", "s(100)"),
+                set_channels("1", "s:2,rgb:3", "s(50);rgb(1,2,3)"),
+                set_channels("0", "s:1", "s(+10)"),
+            ])
             .unwrap();
+        assert_eq!(get_single(&manager, "0", 1), DimmerValue::Single(110));
+        assert_eq!(get_single(&manager, "1", 2), DimmerValue::Single(50));
+
+        // Any invalid entry (unknown universe, invalid channel, missing target value) and nothing is applied
+        for (index, invalid_entry) in [
+            (1, set_channels("9", "s:1", "s(0)")),
+            (1, set_channels("1", "s:1000", "s(0)")),
+            (1, set_channels("1", "x:1", "s(0)")),
+            (1, set_channels("1", "rgb:3", "s(0)")),
+        ] {
+            let e = manager
+                .set_channels_batch(&[set_channels("0", "s:1", "s(0)"), invalid_entry, set_channels("1", "s:2", "s(0)")])
+                .unwrap_err();
+
+            assert!(matches!(e.current_context(), ArtnetError::InvalidSetChannelsBatchEntry(i, _) if *i == index));
+            assert_eq!(get_single(&manager, "0", 1), DimmerValue::Single(110));
+            assert_eq!(get_single(&manager, "1", 2), DimmerValue::Single(50));
+        }
+    }
 
-        println!("{:?}", node);
+    #[test]
+    fn test_duplicate_port_address() {
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("living", get_universe_definition()).unwrap();
 
-        run_node(node, &mut artnet_manager);
-        println!("{:?}", artnet_manager.set_channel_log);
+        // Replacing a universe with the same addressing is allowed
+        artnet_manager.add_universe("living", get_universe_definition()).unwrap();
 
-        let node = array_manager
-            .get_usage_effect_runtime(
-                &defs::EffectUsage::On,
-                "test",
-                None,
-                defs::DIMMING_AMOUNT_MAX, // 100% dimming
-            )
+        let e = artnet_manager.add_universe("kitchen", get_universe_definition()).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::DuplicatePortAddress(id, _, other) if id == "kitchen" && other == "living"));
+        assert!(e.to_string().contains("10.0.1.228 net 0 subnet 0 universe 0"));
+
+        // Same port address on another controller, or another port address on the same controller
+        let mut definition = get_universe_definition();
+        definition.controller = IpAddr::from_str("10.0.1.229").unwrap();
+        artnet_manager.add_universe("kitchen", definition).unwrap();
+
+        let mut definition = get_universe_definition();
+        definition.universe = Some(1);
+        artnet_manager.add_universe("garden", definition).unwrap();
+
+        let mut definition = get_universe_definition();
+        definition.allow_duplicate_port_address = true;
+        artnet_manager.add_universe("mirror", definition).unwrap();
+        assert_eq!(artnet_manager.universes.len(), 4);
+    }
+
+    #[test]
+    fn test_pad_to() {
+        let mut artnet_manager = ArtnetManager::new();
+        let definition = |universe: u8, channels: u16, pad_to: Option<u16>| UniverseDefinition { universe: Some(universe), channels, pad_to, ..get_universe_definition() };
+
+        // The frame is padded, but channels beyond the universe channels cannot be set
+        assert!(artnet_manager.add_universe("main", definition(0, 512, None)).unwrap().is_empty());
+        assert!(artnet_manager.add_universe("porch", definition(1, 64, Some(512))).unwrap().is_empty());
+        let packet_bytes = artnet_manager.universes["porch"].get_packet_bytes();
+        assert_eq!(packet_bytes.len(), DMX_DATA_OFFSET + 512);
+        assert_eq!(packet_bytes[DMX_DATA_OFFSET - 2..DMX_DATA_OFFSET], [0x02, 0x00]);
+
+        artnet_manager.set_channel("porch", &ChannelValue { channel: ChannelDefinition::Single(63), value: DimmerValue::Single(10) }).unwrap();
+        let e = artnet_manager.set_channel("porch", &ChannelValue { channel: ChannelDefinition::Single(64), value: DimmerValue::Single(10) }).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidChannel(_, 64, 64)));
+        assert!(artnet_manager.get_channel("porch", &ChannelDefinition::Single(100)).is_err());
+
+        // Shorter frames to the same controller are reported, universes of other controllers are not compared
+        let warnings = artnet_manager.add_universe("garden", definition(2, 64, None)).unwrap();
+        assert_eq!(warnings, vec![
+            "Universe garden sends 64 channel frames to controller 10.0.1.228, which also gets 512 channel frames from universe main, 512 channel frames from universe porch (set \"pad_to\": 512 on universes with shorter frames)"
+        ]);
+        assert!(artnet_manager.add_universe("garden", definition(2, 64, Some(512))).unwrap().is_empty());
+
+        let other_controller = UniverseDefinition { controller: IpAddr::from_str("10.0.1.229").unwrap(), ..definition(3, 64, None) };
+        assert!(artnet_manager.add_universe("shed", other_controller).unwrap().is_empty());
+
+        // Both padded to different lengths is intentional, padding only one of them is not
+        let warnings = artnet_manager.add_universe("hall", definition(4, 64, Some(128))).unwrap();
+        assert!(warnings.len() == 1 && warnings[0].contains("also gets 512 channel frames from universe main (set"), "{warnings:?}");
+        let warnings = artnet_manager.add_universe("attic", definition(5, 128, None)).unwrap();
+        assert!(warnings.len() == 1 && !warnings[0].contains("hall"), "{warnings:?}");
+
+        let e = artnet_manager.validate_universe("bad", &definition(6, 64, Some(32))).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidPadTo(_, 32, 64)));
+        let e = artnet_manager.validate_universe("bad", &definition(6, 64, Some(513))).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidPadTo(_, 513, 64)));
+    }
+
+    #[test]
+    fn test_universe_send_failures() {
+        let mut artnet_manager = ArtnetManager::new().with_unreachable_threshold(3);
+        artnet_manager.add_universe("test", get_universe_definition()).unwrap();
+        artnet_manager.universes.get_mut("test").unwrap().fail_send = true;
+
+        let modify = |artnet_manager: &mut ArtnetManager, value: u8| {
+            let channel_value = ChannelValue { channel: ChannelDefinition::Single(1), value: DimmerValue::Single(value) };
+            artnet_manager.set_channel("test", &channel_value).unwrap();
+        };
+
+        // Failed universe is retried on every tick but reported only once when crossing the threshold
+        modify(&mut artnet_manager, 10);
+        assert!(artnet_manager.send_modified_universes().is_empty());
+        assert!(artnet_manager.send_modified_universes().is_empty());
+
+        let notifications = artnet_manager.send_modified_universes();
+        assert!(matches!(&notifications[0], ToMqttPublisherMessage::Error(e, None, ErrorCategory::Runtime) if e.contains("Universe test unreachable (3 consecutive send failures)")));
+        assert!(matches!(&notifications[1], ToMqttPublisherMessage::UniverseSendStatus(id, status)
+            if id.as_ref() == "test" && !status.reachable && status.consecutive_failures == 3 && status.last_send_time.is_none()));
+        assert!(artnet_manager.send_modified_universes().is_empty());
+
+        let status = artnet_manager.get_universe_send_status("test").unwrap();
+        assert_eq!((status.reachable, status.consecutive_failures, status.total_failures), (false, 4, 4));
+
+        // Recovery is reported once, total failures are kept
+        artnet_manager.universes.get_mut("test").unwrap().fail_send = false;
+        let notifications = artnet_manager.send_modified_universes();
+        assert!(matches!(&notifications[..], [ToMqttPublisherMessage::UniverseSendStatus(_, status)]
+            if status.reachable && status.consecutive_failures == 0 && status.total_failures == 4 && status.last_send_time.is_some()));
+
+        modify(&mut artnet_manager, 20);
+        assert!(artnet_manager.send_modified_universes().is_empty());
+        assert!(artnet_manager.get_universe_send_status("missing").is_err());
+
+        // Sending of the test universe is disabled, so only the failures are counted as packets
+        let metrics = artnet_manager.metrics.render();
+        assert!(metrics.contains("send_failures_total{universe=\"test\"} 4\n"));
+        assert!(!metrics.contains("packets_sent_total{"));
+    }
+
+    #[test]
+    fn test_send_divisor() {
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("fast", UniverseDefinition { universe: Some(0), ..get_universe_definition() }).unwrap();
+        artnet_manager.add_universe("slow", UniverseDefinition { universe: Some(1), send_divisor: Some(4), ..get_universe_definition() }).unwrap();
+
+        let counters = |artnet_manager: &ArtnetManager, universe_id: &str| {
+            let diagnostics = &artnet_manager.get_diagnostics().universes[universe_id];
+            (diagnostics.sent_ticks, diagnostics.paced_ticks)
+        };
+
+        // Both universes are modified on every tick (like a running chase), the slow one is sent every 4th tick
+        for tick in 0..40 {
+            for universe_id in ["fast", "slow"] {
+                let channel_value = ChannelValue { channel: ChannelDefinition::Single(1), value: DimmerValue::Single(tick) };
+                artnet_manager.set_channel(universe_id, &channel_value).unwrap();
+            }
+            artnet_manager.send_modified_universes();
+        }
+
+        assert_eq!(counters(&artnet_manager, "fast"), (40, 0));
+        assert_eq!(counters(&artnet_manager, "slow"), (10, 30));
+
+        // Its last values are sent once its divisor passed, even if it is no longer modified
+        artnet_manager.send_modified_universes();
+        assert_eq!(counters(&artnet_manager, "slow"), (11, 30));
+        artnet_manager.send_modified_universes();
+        assert_eq!(counters(&artnet_manager, "slow"), (11, 30));
+        assert!(!artnet_manager.universes["slow"].modified);
+
+        let e = artnet_manager.validate_universe("other", &UniverseDefinition { universe: Some(2), send_divisor: Some(0), ..get_universe_definition() }).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidSendDivisor(_)));
+    }
+
+    #[test]
+    fn test_keepalive_burst() {
+        let keepalive_burst = defs::KeepaliveBurstDefinition { every_seconds: 2, packets: 3 };
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("test", UniverseDefinition { disable_send: false, keepalive_burst: Some(keepalive_burst), ..get_universe_definition() }).unwrap();
+
+        let tick = |artnet_manager: &mut ArtnetManager, ticks: usize| {
+            for _ in 0..ticks {
+                artnet_manager.send_modified_universes();
+            }
+        };
+        let sent = |artnet_manager: &ArtnetManager| {
+            let universe = &artnet_manager.universes["test"];
+            (universe.sent_packets, universe.last_sent_sequence)
+        };
+
+        // Not modified for 2 seconds (40 ticks), then the same packet on 3 consecutive ticks
+        tick(&mut artnet_manager, 39);
+        assert_eq!(sent(&artnet_manager), (0, 0));
+        for sent_packets in 1..=3 {
+            tick(&mut artnet_manager, 1);
+            assert_eq!(sent(&artnet_manager), (sent_packets, 0));
+        }
+
+        // The next burst is 40 ticks after the previous one started, its packets have the following sequence
+        tick(&mut artnet_manager, 37);
+        assert_eq!(sent(&artnet_manager), (3, 0));
+        tick(&mut artnet_manager, 1);
+        assert_eq!(sent(&artnet_manager), (4, 1));
+
+        // A modification is sent at once with the next sequence and ends the burst
+        let channel_value = ChannelValue { channel: ChannelDefinition::Single(1), value: DimmerValue::Single(10) };
+        artnet_manager.set_channel("test", &channel_value).unwrap();
+        tick(&mut artnet_manager, 1);
+        assert_eq!(sent(&artnet_manager), (5, 2));
+        assert_eq!(artnet_manager.universes["test"].last_sent_data[1], 10);
+        tick(&mut artnet_manager, 39);
+        assert_eq!(sent(&artnet_manager), (5, 2));
+        tick(&mut artnet_manager, 2);
+        assert_eq!(sent(&artnet_manager), (7, 3));
+        assert!(artnet_manager.metrics.render().contains("packets_sent_total{universe=\"test\"} 7\n"));
+
+        // Repeats are paced by the send divisor
+        let keepalive_burst = defs::KeepaliveBurstDefinition { every_seconds: 1, packets: 2 };
+        let definition = UniverseDefinition { universe: Some(1), disable_send: false, send_divisor: Some(4), keepalive_burst: Some(keepalive_burst), ..get_universe_definition() };
+        artnet_manager.add_universe("slow", definition).unwrap();
+        let slow_sent_packets = |artnet_manager: &ArtnetManager| artnet_manager.universes["slow"].sent_packets;
+
+        tick(&mut artnet_manager, 20);
+        assert_eq!(slow_sent_packets(&artnet_manager), 1);
+        tick(&mut artnet_manager, 3);
+        assert_eq!(slow_sent_packets(&artnet_manager), 1);
+        tick(&mut artnet_manager, 1);
+        assert_eq!(slow_sent_packets(&artnet_manager), 2);
+
+        let keepalive_burst = defs::KeepaliveBurstDefinition { every_seconds: 2, packets: 0 };
+        let e = artnet_manager.validate_universe("other", &UniverseDefinition { universe: Some(2), keepalive_burst: Some(keepalive_burst), ..get_universe_definition() }).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidKeepaliveBurst(_)));
+    }
+
+    #[test]
+    fn test_send_disabled() {
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("test", get_universe_definition()).unwrap();
+        let start = Instant::now();
+        let after = |seconds: u64| start + Duration::from_secs(seconds);
+
+        let modify = |artnet_manager: &mut ArtnetManager, value: u8| {
+            let channel_value = ChannelValue { channel: ChannelDefinition::Single(1), value: DimmerValue::Single(value) };
+            artnet_manager.set_channel("test", &channel_value).unwrap();
+        };
+        let sent_packets = |artnet_manager: &ArtnetManager| artnet_manager.universes["test"].sent_packets;
+
+        // Not modified, no warning
+        assert!(artnet_manager.evaluate_send_disabled(start).is_empty());
+
+        // Warned once per hour
+        modify(&mut artnet_manager, 10);
+        let warnings = artnet_manager.evaluate_send_disabled(start);
+        assert!(matches!(&warnings[..], [ToMqttPublisherMessage::Warning(warning)] if warning.contains("Universe test") && warning.contains("output is suppressed")));
+        artnet_manager.send_modified_universes();
+        assert_eq!(sent_packets(&artnet_manager), 0);
+
+        modify(&mut artnet_manager, 20);
+        assert!(artnet_manager.evaluate_send_disabled(after(60 * 59)).is_empty());
+        artnet_manager.send_modified_universes();
+        modify(&mut artnet_manager, 30);
+        assert_eq!(artnet_manager.evaluate_send_disabled(after(60 * 60)).len(), 1);
+        artnet_manager.send_modified_universes();
+
+        // Enabled at runtime, the current values are sent on the next tick
+        let target = defs::UniverseTarget::Universe(Arc::from("test"));
+        artnet_manager.set_send_enabled(&target, true).unwrap();
+        assert!(!artnet_manager.get_universe_send_status("test").unwrap().send_disabled);
+        assert!(artnet_manager.evaluate_send_disabled(after(60 * 60)).is_empty());
+        artnet_manager.send_modified_universes();
+        assert_eq!(sent_packets(&artnet_manager), 1);
+
+        modify(&mut artnet_manager, 40);
+        artnet_manager.send_modified_universes();
+        assert_eq!(sent_packets(&artnet_manager), 2);
+
+        // Disabled again, warned at once since enabling clears the throttle
+        artnet_manager.set_send_enabled(&target, false).unwrap();
+        assert!(artnet_manager.get_universe_send_status("test").unwrap().send_disabled);
+        assert_eq!(artnet_manager.evaluate_send_disabled(after(60 * 60 + 1)).len(), 1);
+        artnet_manager.send_modified_universes();
+        assert_eq!(sent_packets(&artnet_manager), 2);
+        assert!(artnet_manager.metrics.render().contains("packets_sent_total{universe=\"test\"} 2\n"));
+
+        let e = artnet_manager.set_send_enabled(&defs::UniverseTarget::Universe(Arc::from("missing")), true).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidUniverse(_)));
+    }
+
+    #[test]
+    fn test_quiet_hours() {
+        let quiet_hours = defs::QuietHoursDefinition { from: "23:00".to_string(), to: "06:30".to_string(), utc_offset: Some("+02:00".to_string()) };
+        let definition = UniverseDefinition { disable_send: false, quiet_hours: Some(quiet_hours), ..get_universe_definition() };
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("test", definition).unwrap();
+
+        let at = |date_time: &str| chrono::DateTime::parse_from_rfc3339(&format!("2026-06-{date_time}:00+02:00")).unwrap().to_utc();
+        let modify = |artnet_manager: &mut ArtnetManager, value: u8| {
+            let channel_value = ChannelValue { channel: ChannelDefinition::Single(1), value: DimmerValue::Single(value) };
+            artnet_manager.set_channel("test", &channel_value).unwrap();
+        };
+        let sent = |artnet_manager: &ArtnetManager| {
+            let universe = &artnet_manager.universes["test"];
+            (universe.sent_packets, universe.last_sent_data[1])
+        };
+        let is_quiet = |notifications: &[ToMqttPublisherMessage]| match notifications {
+            [ToMqttPublisherMessage::UniverseSendStatus(_, status)] => status.quiet,
+            _ => panic!("Expected a send status notification, got {:?}", notifications),
+        };
+
+        modify(&mut artnet_manager, 10);
+        assert!(artnet_manager.evaluate_quiet_hours(at("01T22:59")).is_empty());
+        artnet_manager.send_modified_universes();
+        assert_eq!(sent(&artnet_manager), (1, 10));
+
+        // A final all-zero frame when the window starts, the channels keep their values
+        assert!(is_quiet(&artnet_manager.evaluate_quiet_hours(at("01T23:00"))));
+        assert_eq!(sent(&artnet_manager), (2, 0));
+        assert_eq!(artnet_manager.universes["test"].get_packet_bytes()[DMX_DATA_OFFSET + 1], 10);
+        assert!(artnet_manager.get_universe_send_status("test").unwrap().quiet);
+
+        // Channels are still set but not sent, also after midnight
+        modify(&mut artnet_manager, 20);
+        artnet_manager.send_modified_universes();
+        assert!(artnet_manager.evaluate_quiet_hours(at("02T00:00")).is_empty());
+        modify(&mut artnet_manager, 30);
+        assert!(artnet_manager.evaluate_quiet_hours(at("02T06:29")).is_empty());
+        artnet_manager.send_modified_universes();
+        assert_eq!(sent(&artnet_manager), (2, 0));
+        assert!(artnet_manager.evaluate_send_disabled(Instant::now()).is_empty());
+
+        // The current channels are sent when the window ends
+        assert!(!is_quiet(&artnet_manager.evaluate_quiet_hours(at("02T06:30"))));
+        artnet_manager.send_modified_universes();
+        assert_eq!(sent(&artnet_manager), (3, 30));
+        assert!(artnet_manager.evaluate_quiet_hours(at("02T22:59")).is_empty());
+    }
+
+    #[test]
+    fn test_quiet_hours_window() {
+        let quiet_hours = |from: &str, to: &str, utc_offset: Option<&str>| {
+            let definition = defs::QuietHoursDefinition { from: from.to_string(), to: to.to_string(), utc_offset: utc_offset.map(|s| s.to_string()) };
+            QuietHours::new("test (Test Universe)", &definition)
+        };
+        let at = |time: &str| chrono::DateTime::parse_from_rfc3339(&format!("2026-06-01T{time}:00Z")).unwrap().to_utc();
+
+        let window = quiet_hours("12:00", "13:30", Some("+00:00")).unwrap();
+        let quiet = ["11:59", "12:00", "13:29", "13:30", "00:00"].map(|time| window.is_quiet(at(time)));
+        assert_eq!(quiet, [false, true, true, false, false]);
+
+        // Spanning midnight, 21:00Z is 23:00 at +02:00
+        let window = quiet_hours("23:00", "06:30", Some("+02:00")).unwrap();
+        let quiet = ["20:59", "21:00", "22:00", "04:29", "04:30", "12:00"].map(|time| window.is_quiet(at(time)));
+        assert_eq!(quiet, [false, true, true, true, false, false]);
+
+        for (from, to, utc_offset, reason) in [
+            ("23:00", "24:00", None, "invalid time '24:00'"),
+            ("7", "08:00", None, "invalid time '7'"),
+            ("08:00", "08:00", None, "the window is empty"),
+            ("08:00", "09:00", Some("Asia/Jerusalem"), "invalid utc_offset"),
+        ] {
+            let e = quiet_hours(from, to, utc_offset).unwrap_err();
+            assert!(matches!(e.current_context(), ArtnetError::InvalidQuietHours(_, message) if message.contains(reason)), "{e}");
+        }
+
+        let quiet_hours = defs::QuietHoursDefinition { from: "7".to_string(), to: "08:00".to_string(), utc_offset: None };
+        let e = ArtnetManager::new().validate_universe("test", &UniverseDefinition { quiet_hours: Some(quiet_hours), ..get_universe_definition() }).unwrap_err();
+        assert_eq!(e.to_string(), "Universe test (Test Universe) has invalid quiet_hours: invalid time '7' (expected HH:MM)");
+    }
+
+    #[test]
+    fn test_publisher_channel_full() {
+        let mut artnet_manager = ArtnetManager::new().with_unreachable_threshold(1);
+        artnet_manager.add_universe("test", get_universe_definition()).unwrap();
+        artnet_manager.universes.get_mut("test").unwrap().fail_send = true;
+        artnet_manager.universes.get_mut("test").unwrap().disable_send = false;     // No send disabled warning
+        artnet_manager
+            .set_channel("test", &ChannelValue { channel: ChannelDefinition::Single(1), value: DimmerValue::Single(10) })
             .unwrap();
 
-        println!("{:?}", node);
+        let (to_mqtt_publisher_tx, to_mqtt_publisher_rx) = async_channel::bounded(1);
+        to_mqtt_publisher_tx.try_send(ToMqttPublisherMessage::Error("filler".to_string(), None, ErrorCategory::Runtime)).unwrap();
 
-        run_node(node, &mut artnet_manager);
-        println!("{:?}", artnet_manager.set_channel_log);
+        // Unreachable error and send status do not fit, tick completes and the messages are counted
+        artnet_manager.tick_and_publish(&to_mqtt_publisher_tx);
+        assert_eq!(artnet_manager.dropped_publishes, 2);
 
-        let node = array_manager
-            .get_usage_effect_runtime(
-                &defs::EffectUsage::On,
-                "test",
-                None,
-                100, // 10% dimming
-            )
+        artnet_manager.tick_and_publish(&to_mqtt_publisher_tx);
+        assert_eq!(artnet_manager.dropped_publishes, 2);
+
+        // Once there is room, the dropped count is published
+        to_mqtt_publisher_rx.try_recv().unwrap();
+        artnet_manager.tick_and_publish(&to_mqtt_publisher_tx);
+
+        assert_eq!(artnet_manager.dropped_publishes, 0);
+        assert!(matches!(to_mqtt_publisher_rx.try_recv().unwrap(), ToMqttPublisherMessage::Error(e, None, ErrorCategory::Runtime) if e.starts_with("2 messages were dropped")));
+    }
+
+    #[test]
+    fn test_universe_idle() {
+        let mut artnet_manager = ArtnetManager::new();
+        let mut definition = get_universe_definition();
+        definition.idle = Some(UniverseIdleDefinition {
+            after_seconds: 300,
+            target: "s(10);rgb(5,5,5)".to_string(),
+            channels: "s:1, rgb:4".to_string(),
+        });
+        artnet_manager.add_universe("test", definition.clone()).unwrap();
+
+        let set = |artnet_manager: &mut ArtnetManager, channel: u16, value: u8| {
+            artnet_manager
+                .set_channel("test", &ChannelValue { channel: ChannelDefinition::Single(channel), value: DimmerValue::Single(value) })
+                .unwrap();
+        };
+        let get = |artnet_manager: &ArtnetManager, channel: ChannelDefinition| artnet_manager.get_channel("test", &channel).unwrap().value;
+        let after = |seconds: u64| Instant::now() + Duration::from_secs(seconds);
+
+        set(&mut artnet_manager, 1, 200);
+        artnet_manager.apply_idle_values(after(100));
+        assert_eq!(get(&artnet_manager, ChannelDefinition::Single(1)), DimmerValue::Single(200));
+
+        // Channels that are not idle channels do not restart the timeout
+        set(&mut artnet_manager, 2, 50);
+        artnet_manager.apply_idle_values(after(301));
+        assert_eq!(get(&artnet_manager, ChannelDefinition::Single(1)), DimmerValue::Single(10));
+        assert_eq!(get(&artnet_manager, ChannelDefinition::Rgb(4, 5, 6)), DimmerValue::Rgb(5, 5, 5));
+        assert_eq!(get(&artnet_manager, ChannelDefinition::Single(2)), DimmerValue::Single(50));
+
+        // Idle values are applied once
+        assert!(artnet_manager.send_modified_universes().is_empty());
+        artnet_manager.apply_idle_values(after(1000));
+        assert!(!artnet_manager.universes["test"].modified);
+
+        // Setting an idle channel cancels idle until the timeout elapses again
+        set(&mut artnet_manager, 5, 100);
+        artnet_manager.apply_idle_values(after(200));
+        assert_eq!(get(&artnet_manager, ChannelDefinition::Rgb(4, 5, 6)), DimmerValue::Rgb(5, 100, 5));
+        artnet_manager.apply_idle_values(after(301));
+        assert_eq!(get(&artnet_manager, ChannelDefinition::Rgb(4, 5, 6)), DimmerValue::Rgb(5, 5, 5));
+
+        // Idle channels are validated when the universe is added
+        let invalid_idle = [("s(10);rgb(5,5,5)", "s:1,s:400"), ("s(10)", "s:1,rgb:4"), ("rgb(+5,0,0)", "rgb:4"), ("s(10)", "x:1")];
+
+        for (target, channels) in invalid_idle {
+            definition.idle = Some(UniverseIdleDefinition { after_seconds: 300, target: target.to_string(), channels: channels.to_string() });
+            assert!(artnet_manager.add_universe("invalid", definition.clone()).is_err(), "{target} {channels}");
+        }
+    }
+
+    fn get_watcher_definition(condition: &str, hysteresis: u8) -> WatcherDefinition {
+        WatcherDefinition {
+            universe_id: Arc::from("test"),
+            channel: "s:7".to_string(),
+            condition: condition.to_string(),
+            hysteresis,
+            topic: "AV/screen/down".to_string(),
+            payload: "true".to_string(),
+        }
+    }
+
+    // Set channel 7 and return the topics of the watchers that fired
+    fn set_watched_channel(artnet_manager: &mut ArtnetManager, value: u8) -> Vec<Arc<str>> {
+        artnet_manager
+            .set_channel("test", &ChannelValue { channel: ChannelDefinition::Single(7), value: DimmerValue::Single(value) })
             .unwrap();
 
-        println!("{:?}", node);
+        artnet_manager.evaluate_watchers().into_iter().map(|message| match message {
+            ToMqttPublisherMessage::Publish(topic, payload) => {
+                assert_eq!(payload.as_ref(), "true");
+                topic
+            }
+            message => panic!("Expected Publish message, got {:?}", message),
+        }).collect()
+    }
 
-        run_node(node, &mut artnet_manager);
-        println!("{:?}", artnet_manager.set_channel_log);
+    #[test]
+    fn test_watcher_conditions() {
+        let parse = |s: &str| s.parse::<WatcherCondition>().ok();
+
+        assert_eq!(parse(">=200"), Some(WatcherCondition::AtLeast(200)));
+        assert_eq!(parse(" > 199"), Some(WatcherCondition::AtLeast(200)));
+        assert_eq!(parse("<=10"), Some(WatcherCondition::AtMost(10)));
+        assert_eq!(parse("<11"), Some(WatcherCondition::AtMost(10)));
+        assert_eq!(parse("==255"), Some(WatcherCondition::Equal(255)));
+        assert_eq!(parse(">255"), None);
+        assert_eq!(parse("<0"), None);
+        assert_eq!(parse("=>5"), None);
+        assert_eq!(parse("200"), None);
+        assert_eq!(parse(">=256"), None);
+        assert_eq!(parse(">="), None);
+    }
 
-        let node = array_manager
-            .get_usage_effect_runtime(
-                &defs::EffectUsage::On,
-                "test",
-                None,
-                0, // 0% dimming
-            )
+    #[test]
+    fn test_watcher_crossing() {
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("test", get_universe_definition()).unwrap();
+        artnet_manager.set_watcher("screen", Some(get_watcher_definition(">=200", 5))).unwrap();
+
+        assert!(set_watched_channel(&mut artnet_manager, 100).is_empty());
+        assert_eq!(set_watched_channel(&mut artnet_manager, 200), vec![Arc::from("AV/screen/down")]);
+        assert!(set_watched_channel(&mut artnet_manager, 255).is_empty());
+
+        // Oscillating within the hysteresis does not fire again
+        assert!(set_watched_channel(&mut artnet_manager, 196).is_empty());
+        assert!(set_watched_channel(&mut artnet_manager, 200).is_empty());
+        assert!(set_watched_channel(&mut artnet_manager, 195).is_empty());
+        assert!(set_watched_channel(&mut artnet_manager, 201).is_empty());
+
+        // Moving back past the hysteresis re-arms the watcher
+        assert!(set_watched_channel(&mut artnet_manager, 194).is_empty());
+        assert_eq!(set_watched_channel(&mut artnet_manager, 200), vec![Arc::from("AV/screen/down")]);
+
+        // Channels that were not set are not evaluated
+        artnet_manager
+            .set_channel("test", &ChannelValue { channel: ChannelDefinition::Rgb(1, 2, 3), value: DimmerValue::Rgb(1, 2, 3) })
             .unwrap();
+        assert!(artnet_manager.evaluate_watchers().is_empty());
+
+        // Condition already met when the watcher is defined does not fire until it is cleared
+        assert!(set_watched_channel(&mut artnet_manager, 10).is_empty());
+        artnet_manager.set_watcher("screen_up", Some(get_watcher_definition("<=10", 0))).unwrap();
+        assert!(set_watched_channel(&mut artnet_manager, 10).is_empty());
+        assert!(set_watched_channel(&mut artnet_manager, 11).is_empty());
+        assert_eq!(set_watched_channel(&mut artnet_manager, 10), vec![Arc::from("AV/screen/down")]);
+    }
 
-        println!("{:?}", node);
+    #[test]
+    fn test_watcher_removal() {
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("test", get_universe_definition()).unwrap();
+        artnet_manager.set_watcher("screen", Some(get_watcher_definition("==255", 0))).unwrap();
+        artnet_manager.set_watcher("screen", None).unwrap();
 
-        run_node(node, &mut artnet_manager);
-        println!("{:?}", artnet_manager.set_channel_log);
+        assert!(set_watched_channel(&mut artnet_manager, 255).is_empty());
+
+        let e = artnet_manager.set_watcher("screen", None).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::WatcherNotFound(name) if name == "screen"));
+
+        let invalid_definitions = [
+            WatcherDefinition { channel: "rgb:1".to_string(), ..get_watcher_definition("==255", 0) },
+            WatcherDefinition { topic: "AV/#".to_string(), ..get_watcher_definition("==255", 0) },
+            get_watcher_definition("!=255", 0),
+        ];
+
+        for definition in invalid_definitions {
+            assert!(artnet_manager.set_watcher("bad", Some(definition)).is_err());
+        }
+        assert!(artnet_manager.watchers.is_empty());
+    }
+
+    fn get_monitor_definition(min_interval_ms: u64, only_on_change: bool) -> MonitorDefinition {
+        MonitorDefinition {
+            universe_id: Arc::from("test"),
+            channel: "s:7".to_string(),
+            topic: "bath/light/level".to_string(),
+            min_interval_ms,
+            only_on_change,
+        }
+    }
+
+    // Set channel 7 (unless value is None) and return the payloads published by the monitors
+    fn set_monitored_channel(artnet_manager: &mut ArtnetManager, value: Option<u8>, now: Instant) -> Vec<String> {
+        if let Some(value) = value {
+            artnet_manager
+                .set_channel("test", &ChannelValue { channel: ChannelDefinition::Single(7), value: DimmerValue::Single(value) })
+                .unwrap();
+        }
+
+        artnet_manager.evaluate_monitors(now).into_iter().map(|message| match message {
+            ToMqttPublisherMessage::Publish(topic, payload) => {
+                assert_eq!(topic.as_ref(), "bath/light/level");
+                payload.to_string()
+            }
+            message => panic!("Expected Publish message, got {:?}", message),
+        }).collect()
+    }
+
+    #[test]
+    fn test_monitor_change_and_interval() {
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("test", get_universe_definition()).unwrap();
+        artnet_manager.set_monitor("bath", Some(get_monitor_definition(500, true))).unwrap();
+
+        let start = Instant::now();
+        let after = |ms| start + Duration::from_millis(ms);
+
+        // Current value is published when the monitor is defined, then only changes are published
+        assert_eq!(set_monitored_channel(&mut artnet_manager, None, after(0)), vec!["0"]);
+        assert!(set_monitored_channel(&mut artnet_manager, None, after(600)).is_empty());
+        assert_eq!(set_monitored_channel(&mut artnet_manager, Some(100), after(700)), vec!["100"]);
+
+        // Changes within the interval are held back, the latest value is published once the interval elapses
+        assert!(set_monitored_channel(&mut artnet_manager, Some(150), after(900)).is_empty());
+        assert!(set_monitored_channel(&mut artnet_manager, Some(200), after(1100)).is_empty());
+        assert_eq!(set_monitored_channel(&mut artnet_manager, None, after(1200)), vec!["200"]);
+
+        // Setting the same value is not a change
+        assert!(set_monitored_channel(&mut artnet_manager, Some(200), after(2000)).is_empty());
+
+        // Without only_on_change, the value is published every interval
+        artnet_manager.set_monitor("bath", Some(get_monitor_definition(500, false))).unwrap();
+        assert_eq!(set_monitored_channel(&mut artnet_manager, None, after(2000)), vec!["200"]);
+        assert!(set_monitored_channel(&mut artnet_manager, None, after(2400)).is_empty());
+        assert_eq!(set_monitored_channel(&mut artnet_manager, None, after(2500)), vec!["200"]);
+
+        artnet_manager.set_monitor("bath", None).unwrap();
+        assert!(set_monitored_channel(&mut artnet_manager, Some(10), after(5000)).is_empty());
+    }
+
+    #[test]
+    fn test_monitor_validation() {
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("test", get_universe_definition()).unwrap();
+
+        let e = artnet_manager.set_monitor("bath", None).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::MonitorNotFound(name) if name == "bath"));
+
+        let invalid_definitions = [
+            MonitorDefinition { channel: "rgb:1".to_string(), ..get_monitor_definition(0, true) },
+            MonitorDefinition { channel: "s:600".to_string(), ..get_monitor_definition(0, true) },
+            MonitorDefinition { topic: "bath/+".to_string(), ..get_monitor_definition(0, true) },
+            MonitorDefinition { universe_id: Arc::from("missing"), ..get_monitor_definition(0, true) },
+        ];
+
+        for definition in invalid_definitions {
+            assert!(artnet_manager.set_monitor("bad", Some(definition)).is_err());
+        }
+        assert!(artnet_manager.monitors.is_empty());
+    }
+
+    #[test]
+    fn test_settings_propagation() {
+        let (settings_tx, settings_rx) = tokio::sync::watch::channel(ServiceSettings { tick_ms: 40, ..ServiceSettings::default() });
+        let mut artnet_manager = ArtnetManager::new().with_settings(settings_rx);
+
+        assert_eq!(artnet_manager.tick_duration, Duration::from_millis(40));
+        assert!(!artnet_manager.update_settings());
+
+        // Changing another setting does not change the tick
+        settings_tx.send_modify(|settings| settings.reconnect_delay_seconds = 5);
+        assert!(!artnet_manager.update_settings());
+
+        settings_tx.send_modify(|settings| settings.tick_ms = 25);
+        assert!(artnet_manager.update_settings());
+        assert_eq!(artnet_manager.tick_duration, Duration::from_millis(25));
+        assert!(!artnet_manager.update_settings());
+    }
+
+    #[tokio::test]
+    async fn test_messaging() {
+        let cancel = CancellationToken::new();
+        let sender = start_artnet_manager(cancel.clone());
+        let universe_definition = get_universe_definition();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        sender
+            .send(ToArtnetManagerMessage::AddUniverse(
+                Arc::from("test"),
+                universe_definition,
+                tx,
+            ))
+            .await
+            .unwrap();
+        let result = rx.await.unwrap();
+        cancel.cancel();
+        assert!(result.is_ok());
+    }
+
+    fn set_effect_value_message(value: usize) -> (ToArtnetManagerMessage, tokio::sync::oneshot::Receiver<error_stack::Result<(), ArtnetError>>) {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        (ToArtnetManagerMessage::SetEffectValue(None, Arc::from("level"), Some(value.to_string()), tx), rx)
+    }
+
+    #[tokio::test]
+    async fn test_message_batch() {
+        let mut manager = ArtnetManager::new();
+        let (sender, mut receiver) = manager_channel::channel::<ToArtnetManagerMessage>("artnet", 100);
+        let mut replies = Vec::new();
+
+        for value in 0..40 {
+            let (message, reply) = set_effect_value_message(value);
+            sender.send(message).await.unwrap();
+            replies.push(reply);
+        }
+
+        // A batch is limited in size, pending messages are left for the next batch
+        let next_tick = tokio::time::Instant::now() + Duration::from_secs(3600);
+        let message = receiver.recv().await.unwrap();
+        assert_eq!(manager.handle_message_batch(message, &mut receiver, next_tick), 32);
+        assert_eq!(manager.get_effect_value("", "level"), Some("31"));
+
+        // Once the tick is due, only the received message is handled
+        let message = receiver.recv().await.unwrap();
+        assert_eq!(manager.handle_message_batch(message, &mut receiver, tokio::time::Instant::now()), 1);
+        assert_eq!(receiver.len(), 7);
+    }
+
+    #[derive(Debug)]
+    struct TickRecorderNode {
+        tick_times: Arc<std::sync::Mutex<Vec<tokio::time::Instant>>>,
+    }
+
+    impl EffectNodeRuntime for TickRecorderNode {
+        fn tick(&mut self, _: &mut ArtnetManager) -> error_stack::Result<(), ArtnetError> {
+            self.tick_times.lock().unwrap().push(tokio::time::Instant::now());
+            Ok(())
+        }
+
+        fn is_done(&self) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ticks_during_message_flood() {
+        let cancel = CancellationToken::new();
+        let sender = start_artnet_manager(cancel.clone());
+        let tick_times = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        let node = Box::new(TickRecorderNode { tick_times: tick_times.clone() });
+        sender.send(ToArtnetManagerMessage::StartEffect(StartEffectRequest {
+            effect_id: Arc::from("recorder"),
+            node,
+            usage: None,
+            epoch: None,
+            origin: None,
+            enqueue: false,
+            max_ticks: EffectMaxTicks::Default,
+        }, tx)).await.unwrap();
+        rx.await.unwrap().unwrap();
+
+        // Bursts of messages (like retained definitions replayed at startup) while the effect is running
+        let flood_sender = sender.clone();
+        let flood = tokio::spawn(async move {
+            let mut replies = Vec::new();
+
+            for burst in 0..50 {
+                for value in 0..100 {
+                    let (message, reply) = set_effect_value_message(burst * 100 + value);
+                    flood_sender.send(message).await.unwrap();
+                    replies.push(reply);
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+
+            for reply in replies {
+                reply.await.unwrap().unwrap();
+            }
+        });
+
+        flood.await.unwrap();
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        sender.send(ToArtnetManagerMessage::GetDiagnostics(tx)).await.unwrap();
+        assert!(rx.await.unwrap().max_messages_per_tick >= 100);
+        cancel.cancel();
+
+        let tick_times = tick_times.lock().unwrap();
+        assert!(tick_times.len() >= 10, "only {} ticks", tick_times.len());
+        for (previous, next) in tick_times.iter().zip(tick_times.iter().skip(1)) {
+            assert_eq!(*next - *previous, Duration::from_millis(50));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sim_frames() {
+        let cancel = CancellationToken::new();
+        let sim_frames = sim::channel();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(sim::serve(cancel.clone(), listener, sim_frames.clone(), 50));
+
+        let mut artnet_manager = ArtnetManager::new().with_sim(Some(sim_frames.clone()));
+        artnet_manager.add_universe("test", get_universe_definition()).unwrap();
+
+        let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+
+        // Wait for the listener to subscribe the new client
+        while sim_frames.receiver_count() == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        artnet_manager
+            .set_channel(
+                "test",
+                &ChannelValue {
+                    channel: ChannelDefinition::Rgb(1, 2, 3),
+                    value: DimmerValue::Rgb(10, 20, 30),
+                },
+            )
+            .unwrap();
+        assert!(artnet_manager.send_modified_universes().is_empty());
+
+        let line = tokio::time::timeout(Duration::from_secs(2), lines.next_line())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let frame: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(frame["universe_id"], "test");
+        assert_eq!(frame["channels"][1], 10);
+        assert_eq!(frame["channels"][2], 20);
+        assert_eq!(frame["channels"][3], 30);
+
+        // Unmodified universes are not sent
+        assert!(artnet_manager.send_modified_universes().is_empty());
+        assert!(tokio::time::timeout(Duration::from_millis(200), lines.next_line()).await.is_err());
+
+        cancel.cancel();
+    }
+}
+
+#[cfg(test)]
+mod test_artnet_packet {
+    use crate::artnet_manager::artnet_packet::{self, DMX_DATA_OFFSET, DMX_SEQ_OFFSET};
+
+    fn header(packet_bytes: &[u8]) -> &[u8] {
+        &packet_bytes[..DMX_DATA_OFFSET]
+    }
+
+    #[test]
+    fn test_artdmx_header() {
+        let cases: [(u8, u8, u8, usize, [u8; DMX_DATA_OFFSET]); 4] = [
+            (0, 0, 0, 2, *b"Art-Net\0\x00\x50\x00\x0e\x00\x00\x00\x00\x00\x02"),
+            (1, 2, 3, 306, *b"Art-Net\0\x00\x50\x00\x0e\x00\x00\x23\x01\x01\x32"),
+            (127, 15, 15, 511, *b"Art-Net\0\x00\x50\x00\x0e\x00\x00\xff\x7f\x02\x00"),
+            (0, 0, 1, 512, *b"Art-Net\0\x00\x50\x00\x0e\x00\x00\x01\x00\x02\x00"),
+        ];
+
+        for (net, subnet, universe, channel_count, expected) in cases {
+            let packet_bytes = artnet_packet::build_artdmx(net, subnet, universe, &vec![0; channel_count]);
+            let data_length = artnet_packet::get_data_length(channel_count);
+
+            assert_eq!(header(&packet_bytes), expected, "net {net} subnet {subnet} universe {universe} channels {channel_count}");
+            assert_eq!(packet_bytes.len(), DMX_DATA_OFFSET + data_length);
+            assert_eq!(u16::from_be_bytes([packet_bytes[16], packet_bytes[17]]) as usize, data_length);
+        }
+    }
+
+    #[test]
+    fn test_artdmx_odd_channel_count() {
+        assert_eq!(artnet_packet::get_data_length(1), 2);
+        assert_eq!(artnet_packet::get_data_length(305), 306);
+        assert_eq!(artnet_packet::get_data_length(306), 306);
+        assert_eq!(artnet_packet::get_data_length(511), 512);
+
+        // Odd length data is padded with a zero
+        let packet_bytes = artnet_packet::build_artdmx(0, 0, 0, &[1, 2, 3]);
+        assert_eq!(artnet_packet::get_data(&packet_bytes), [1, 2, 3, 0]);
+        assert_eq!(packet_bytes[16..18], [0x00, 0x04]);
+    }
+
+    #[test]
+    fn test_artdmx_sequence() {
+        let data = (0..=255).collect::<Vec<u8>>();
+        let mut packet_bytes = artnet_packet::build_artdmx(3, 4, 5, &data);
+        let original = packet_bytes.clone();
+
+        assert_eq!(artnet_packet::get_sequence(&packet_bytes), 0);
+        for expected in 1..=255 {
+            artnet_packet::next_sequence(&mut packet_bytes);
+            assert_eq!(artnet_packet::get_sequence(&packet_bytes), expected);
+        }
+        artnet_packet::next_sequence(&mut packet_bytes);
+        assert_eq!(artnet_packet::get_sequence(&packet_bytes), 0);
+
+        // Only the sequence byte is changed
+        for (i, (byte, original_byte)) in packet_bytes.iter().zip(original.iter()).enumerate() {
+            if i != DMX_SEQ_OFFSET {
+                assert_eq!(byte, original_byte, "byte {i} changed");
+            }
+        }
+    }
+
+    #[test]
+    fn test_artdmx_set_data() {
+        let mut packet_bytes = artnet_packet::build_artdmx(1, 2, 3, &[0; 4]);
+        let original_header = header(&packet_bytes).to_vec();
+
+        artnet_packet::next_sequence(&mut packet_bytes);
+        artnet_packet::set_data(&mut packet_bytes, &[10, 20, 30, 40]);
+        artnet_packet::get_data_mut(&mut packet_bytes)[3] = 50;
+
+        assert_eq!(artnet_packet::get_data(&packet_bytes), [10, 20, 30, 50]);
+        assert_eq!(artnet_packet::get_sequence(&packet_bytes), 1);
+        assert_eq!(header(&packet_bytes)[..DMX_SEQ_OFFSET], original_header[..DMX_SEQ_OFFSET]);
+        assert_eq!(header(&packet_bytes)[DMX_SEQ_OFFSET + 1..], original_header[DMX_SEQ_OFFSET + 1..]);
+    }
+}
+
+#[cfg(test)]
+mod test_effect_nodes {
+    use error_stack::Result;
+    use std::{net::IpAddr, str::FromStr, sync::Arc, time::Duration};
+
+    use crate::{
+        array_manager::{ArrayManager, DmxArrayError},
+        artnet_manager::runtime_nodes::{DelayEffectNode, ParallelEffectNode, SequenceEffectNode},
+        artnet_manager::{ArtnetError, ArtnetManager, EffectNodeRuntime, EffectTickBudget},
+        defs,
+        defs::{DmxArray, EffectMaxTicks, EffectStatus, EffectUsage, StopScope, UniverseDefinition},
+        dmx::{ChannelValue, DimmerValue, ChannelDefinition, ChannelLimits},
+        messages::ToMqttPublisherMessage,
+    };
+
+    fn get_universe_definition() -> UniverseDefinition {
+        UniverseDefinition {
+            description: "Test Universe".to_string(),
+            controller: IpAddr::from_str("10.0.1.228").unwrap(),
+            net: Some(0),
+            subnet: Some(0),
+            universe: Some(0),
+            channels: 306,
+            pad_to: None,
+            log: true,
+            disable_send: false,
+            allow_duplicate_port_address: false,
+            idle: None,
+            max_delta_per_tick: None,
+            initial: None,
+            initial_b64: None,
+            send_divisor: None,
+            quiet_hours: None,
+            keepalive_burst: None,
+        }
+    }
+
+    #[test]
+    fn test_fade_node1() {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "description": "Test array",
+            "lights": {
+                "all": "s:0"
+            },
+            "effects": {
+                "on": {
+                    "type": "fade",
+                    "lights": "@all",
+                    "ticks": 4,
+                    "target": "s(255); rgb(255,255,255); w(255,255,255)"
+                },
+                "off": {
+                    "type": "fade",
+                    "lights": "@all",
+                    "ticks": 8,
+                    "target": "s(0); rgb(0,0,0); w(0,0,0)"
+                }
+            }
+        }"#;
+
+        let mut array_manager = ArrayManager::new();
+        let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager
+            .add_universe("0", get_universe_definition())
+            .unwrap();
+
+        array_manager.add_array(Arc::from("test"), Box::new(array)).unwrap();
+        let node = array_manager
+            .get_usage_effect_runtime(
+                &defs::EffectUsage::On,
+                "test",
+                None,
+                defs::DIMMING_AMOUNT_MAX,
+            )
+            .unwrap();
+
+        println!("{:?}", node);
+
+        run_node(node, &mut artnet_manager);
+        println!("{:?}", artnet_manager.set_channel_log);
+
+        assert_eq!(artnet_manager.set_channel_log.len(), 4);
+        assert_eq!(
+            artnet_manager.set_channel_log,
+            [
+                ChannelValue {
+                    channel: ChannelDefinition::Single(0),
+                    value: DimmerValue::Single(64)
+                },
+                ChannelValue {
+                    channel: ChannelDefinition::Single(0),
+                    value: DimmerValue::Single(128)
+                },
+                ChannelValue {
+                    channel: ChannelDefinition::Single(0),
+                    value: DimmerValue::Single(191)
+                },
+                ChannelValue {
+                    channel: ChannelDefinition::Single(0),
+                    value: DimmerValue::Single(255)
+                },
+            ]
+        );
+
+        let node = array_manager
+            .get_usage_effect_runtime(
+                &defs::EffectUsage::Off,
+                "test",
+                None,
+                defs::DIMMING_AMOUNT_MAX,
+            )
+            .unwrap();
+
+        println!("{:?}", node);
+
+        run_node(node, &mut artnet_manager);
+        println!("{:?}", artnet_manager.set_channel_log);
+
+        assert_eq!(artnet_manager.set_channel_log.len(), 8);
+        assert_eq!(
+            artnet_manager.set_channel_log,
+            [
+                ChannelValue {
+                    channel: ChannelDefinition::Single(0),
+                    value: DimmerValue::Single(223)
+                },
+                ChannelValue {
+                    channel: ChannelDefinition::Single(0),
+                    value: DimmerValue::Single(191)
+                },
+                ChannelValue {
+                    channel: ChannelDefinition::Single(0),
+                    value: DimmerValue::Single(159)
+                },
+                ChannelValue {
+                    channel: ChannelDefinition::Single(0),
+                    value: DimmerValue::Single(127)
+                },
+                ChannelValue {
+                    channel: ChannelDefinition::Single(0),
+                    value: DimmerValue::Single(96)
+                },
+                ChannelValue {
+                    channel: ChannelDefinition::Single(0),
+                    value: DimmerValue::Single(64)
+                },
+                ChannelValue {
+                    channel: ChannelDefinition::Single(0),
+                    value: DimmerValue::Single(32)
+                },
+                ChannelValue {
+                    channel: ChannelDefinition::Single(0),
+                    value: DimmerValue::Single(0)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fade_node2() {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "description": "Test array",
+            "lights": {
+                "all": "rgb:0"
+            },
+            "effects": {
+                "on": {
+                    "type": "fade",
+                    "lights": "@all",
+                    "ticks": 4,
+                    "target": "s(255); rgb(255,255,255); w(255,255,255)"
+                },
+                "off": {
+                    "type": "fade",
+                    "lights": "@all",
+                    "ticks": 8,
+                    "target": "s(0); rgb(0,0,0); w(0,0,0)"
+                }
+            }
+        }"#;
+
+        let mut array_manager = ArrayManager::new();
+        let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager
+            .add_universe("0", get_universe_definition())
+            .unwrap();
+
+        array_manager.add_array(Arc::from("test"), Box::new(array)).unwrap();
+
+        let node = array_manager
+            .get_usage_effect_runtime(
+                &defs::EffectUsage::On,
+                "test",
+                None,
+                800, // 80% dimming
+            )
+            .unwrap();
+
+        println!("{:?}", node);
+
+        run_node(node, &mut artnet_manager);
+        println!("{:?}", artnet_manager.set_channel_log);
+
+        let node = array_manager
+            .get_usage_effect_runtime(
+                &defs::EffectUsage::On,
+                "test",
+                None,
+                defs::DIMMING_AMOUNT_MAX, // 100% dimming
+            )
+            .unwrap();
+
+        println!("{:?}", node);
+
+        run_node(node, &mut artnet_manager);
+        println!("{:?}", artnet_manager.set_channel_log);
+
+        let node = array_manager
+            .get_usage_effect_runtime(
+                &defs::EffectUsage::On,
+                "test",
+                None,
+                100, // 10% dimming
+            )
+            .unwrap();
+
+        println!("{:?}", node);
+
+        run_node(node, &mut artnet_manager);
+        println!("{:?}", artnet_manager.set_channel_log);
+
+        let node = array_manager
+            .get_usage_effect_runtime(
+                &defs::EffectUsage::On,
+                "test",
+                None,
+                0, // 0% dimming
+            )
+            .unwrap();
+
+        println!("{:?}", node);
+
+        run_node(node, &mut artnet_manager);
+        println!("{:?}", artnet_manager.set_channel_log);
+    }
+
+    #[test]
+    fn test_fade_from() {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "lights": { "all": "s:0,rgb:1" },
+            "effects": {
+                "plain": { "type": "fade", "lights": "@all", "ticks": 4, "target": "s(200);rgb(200,100,0)" },
+                "from": { "type": "fade", "lights": "@all", "ticks": 4, "target": "s(200);rgb(200,100,0)", "from": "s(40);rgb(0,40,80)" },
+                "same": { "type": "fade", "lights": "@all", "ticks": 4, "target": "s(200);rgb(200,100,0)", "from": "s(200);rgb(200,100,0)" }
+            }
+        }"#;
+
+        let mut array_manager = ArrayManager::new();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+
+        let preset = |artnet_manager: &mut ArtnetManager, single: u8, rgb: (u8, u8, u8)| {
+            artnet_manager.set_channel("0", &ChannelValue { channel: ChannelDefinition::Single(0), value: DimmerValue::Single(single) }).unwrap();
+            artnet_manager.set_channel("0", &ChannelValue { channel: ChannelDefinition::Rgb(1, 2, 3), value: DimmerValue::Rgb(rgb.0, rgb.1, rgb.2) }).unwrap();
+        };
+        let get_node = |effect_id: &str, dimming_amount: usize| {
+            array_manager.get_usage_effect_runtime(&EffectUsage::On, "test", Some(&Arc::from(effect_id)), dimming_amount).unwrap()
+        };
+
+        // Fade from the current value
+        preset(&mut artnet_manager, 40, (0, 40, 80));
+        run_node(get_node("plain", defs::DIMMING_AMOUNT_MAX), &mut artnet_manager);
+        let plain_log = artnet_manager.set_channel_log.clone();
+
+        // Same fade from an explicit value, regardless of the current value
+        preset(&mut artnet_manager, 255, (255, 255, 255));
+        run_node(get_node("from", defs::DIMMING_AMOUNT_MAX), &mut artnet_manager);
+
+        assert_eq!(artnet_manager.set_channel_log[..2], [
+            ChannelValue { channel: ChannelDefinition::Single(0), value: DimmerValue::Single(40) },
+            ChannelValue { channel: ChannelDefinition::Rgb(1, 2, 3), value: DimmerValue::Rgb(0, 40, 80) },
+        ]);
+        assert_eq!(artnet_manager.set_channel_log[2..], plain_log[..]);
+
+        // From value is dimmed like the target
+        run_node(get_node("from", 500), &mut artnet_manager);
+        assert_eq!(artnet_manager.set_channel_log[0].value, DimmerValue::Single(40).get_dimmed_value(500));
+        assert_eq!(artnet_manager.get_channel("0", &ChannelDefinition::Single(0)).unwrap().value, DimmerValue::Single(200).get_dimmed_value(500));
+
+        // From same as target is a single set
+        preset(&mut artnet_manager, 0, (0, 0, 0));
+        let mut node = get_node("same", defs::DIMMING_AMOUNT_MAX);
+        artnet_manager.set_channel_log.clear();
+        node.tick(&mut artnet_manager).unwrap();
+
+        assert!(node.is_done());
+        assert_eq!(artnet_manager.set_channel_log, vec![
+            ChannelValue { channel: ChannelDefinition::Single(0), value: DimmerValue::Single(200) },
+            ChannelValue { channel: ChannelDefinition::Rgb(1, 2, 3), value: DimmerValue::Rgb(200, 100, 0) },
+        ]);
+    }
+
+    #[test]
+    fn test_fade_skips_unchanged_channels() {
+        let lights = (0..100).map(|channel| format!("s:{channel}")).collect::<Vec<_>>().join(",");
+        let array_json = format!(r#"
+        {{
+            "universe_id": "0",
+            "lights": {{ "all": "@bar,@dim", "bar": "{lights}", "dim": "s:100" }},
+            "effects": {{
+                "on": {{ "type": "fade", "lights": "@bar", "ticks": 10, "target": "s(200)" }},
+                "slow": {{ "type": "fade", "lights": "@dim", "ticks": 10, "target": "s(5)" }}
+            }}
+        }}"#);
+
+        let mut array_manager = ArrayManager::new();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(&array_json).unwrap())).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+
+        // 97 of the 100 fixtures are already at the target
+        for channel in 3..100 {
+            artnet_manager.set_channel("0", &ChannelValue { channel: ChannelDefinition::Single(channel), value: DimmerValue::Single(200) }).unwrap();
+        }
+
+        let node = array_manager.get_usage_effect_runtime(&EffectUsage::On, "test", None, defs::DIMMING_AMOUNT_MAX).unwrap();
+        run_node(node, &mut artnet_manager);
+
+        assert_eq!(artnet_manager.set_channel_log.len(), 3 * 10);
+        assert!(artnet_manager.set_channel_log.iter().all(|v| matches!(v.channel, ChannelDefinition::Single(channel) if channel < 3)));
+        for channel in 0..100 {
+            assert_eq!(artnet_manager.get_channel("0", &ChannelDefinition::Single(channel)).unwrap().value, DimmerValue::Single(200));
+        }
+
+        // Fading again to the same target sets nothing
+        let node = array_manager.get_usage_effect_runtime(&EffectUsage::On, "test", None, defs::DIMMING_AMOUNT_MAX).unwrap();
+        run_node(node, &mut artnet_manager);
+        assert!(artnet_manager.set_channel_log.is_empty());
+
+        // A slow fade sets the channel only on the ticks its value changes
+        let node = array_manager.get_usage_effect_runtime(&EffectUsage::On, "test", Some(&Arc::from("slow")), defs::DIMMING_AMOUNT_MAX).unwrap();
+        run_node(node, &mut artnet_manager);
+        assert_eq!(artnet_manager.set_channel_log.iter().map(|v| v.value.clone()).collect::<Vec<_>>(), (1..=5).map(DimmerValue::Single).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_fade_limits() {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "description": "Test array",
+            "lights": {
+                "strip": "rgb:0",
+                "all": "@strip,s:9"
+            },
+            "limits": {
+                "strip": "rgb(200,100,50)"
+            },
+            "effects": {
+                "on": {
+                    "type": "fade",
+                    "lights": "@all",
+                    "ticks": 4,
+                    "target": "s(255); rgb(255,255,255)"
+                }
+            }
+        }"#;
+
+        let mut array_manager = ArrayManager::new();
+        let array = serde_json::from_str::<DmxArray>(array_json).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager
+            .add_universe("0", get_universe_definition())
+            .unwrap();
+
+        array_manager.add_array(Arc::from("test"), Box::new(array)).unwrap();
+        let node = array_manager
+            .get_usage_effect_runtime(
+                &defs::EffectUsage::On,
+                "test",
+                None,
+                defs::DIMMING_AMOUNT_MAX,
+            )
+            .unwrap();
+
+        run_node(node, &mut artnet_manager);
+
+        // The fade is done in 4 ticks even though its target is limited
+        assert_eq!(artnet_manager.set_channel_log.len(), 8);
+        assert_eq!(
+            artnet_manager
+                .get_channel("0", &ChannelDefinition::Rgb(0, 1, 2))
+                .unwrap()
+                .value,
+            DimmerValue::Rgb(200, 100, 50)
+        );
+        assert_eq!(
+            artnet_manager
+                .get_channel("0", &ChannelDefinition::Single(9))
+                .unwrap()
+                .value,
+            DimmerValue::Single(255)
+        );
+    }
+
+    #[test]
+    fn test_fade_group_dimming() {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "description": "Test array",
+            "lights": {
+                "accent": "s:0,s:1",
+                "task": "s:1,s:2",
+                "all": "@accent,@task,s:3"
+            },
+            "group_dimming": {
+                "accent": 600,
+                "task": 800
+            },
+            "effects": {
+                "on": { "type": "fade", "lights": "@all", "ticks": 2, "target": "s(255)" }
+            }
+        }"#;
+
+        let mut array_manager = ArrayManager::new();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+
+        let node = array_manager.get_usage_effect_runtime(&defs::EffectUsage::On, "test", None, 500).unwrap();
+        run_node(node, &mut artnet_manager);
+
+        let get_value = |artnet_manager: &ArtnetManager, channel: u16| {
+            artnet_manager.get_channel("0", &ChannelDefinition::Single(channel)).unwrap().value
+        };
+
+        // 50% command dimming: accent at 60% of it, s:1 is in both groups so the higher (task) factor is used, s:3 is not
+        // in any group with a factor
+        assert_eq!(get_value(&artnet_manager, 0), DimmerValue::Single(255).get_dimmed_value(300));
+        assert_eq!(get_value(&artnet_manager, 1), DimmerValue::Single(255).get_dimmed_value(400));
+        assert_eq!(get_value(&artnet_manager, 2), DimmerValue::Single(255).get_dimmed_value(400));
+        assert_eq!(get_value(&artnet_manager, 3), DimmerValue::Single(255).get_dimmed_value(500));
+        assert_eq!(get_value(&artnet_manager, 0), DimmerValue::Single(76));
+    }
+
+    fn run_relative_fade(artnet_manager: &mut ArtnetManager, target: &str, dimming_amount: usize) {
+        let array_json = format!(
+            r#"{{ "universe_id": "0", "description": "Test array", "lights": {{ "all": "s:9,rgb:0" }},
+                 "effects": {{ "on": {{ "type": "fade", "lights": "@all", "ticks": 4, "target": "{target}" }} }} }}"#
+        );
+        let mut array_manager = ArrayManager::new();
+        let array = serde_json::from_str::<DmxArray>(&array_json).unwrap();
+
+        array_manager.add_array(Arc::from("test"), Box::new(array)).unwrap();
+        let node = array_manager
+            .get_usage_effect_runtime(&defs::EffectUsage::On, "test", None, dimming_amount)
+            .unwrap();
+
+        run_node(node, artnet_manager);
+    }
+
+    #[test]
+    fn test_relative_fade() {
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager
+            .add_universe("0", get_universe_definition())
+            .unwrap();
+
+        let single = ChannelDefinition::Single(9);
+        let rgb = ChannelDefinition::Rgb(0, 1, 2);
+        let get_value = |artnet_manager: &ArtnetManager, channel| artnet_manager.get_channel("0", channel).unwrap().value;
+
+        artnet_manager.set_channel("0", &ChannelValue { channel: single.clone(), value: DimmerValue::Single(240) }).unwrap();
+        artnet_manager.set_channel("0", &ChannelValue { channel: rgb.clone(), value: DimmerValue::Rgb(5, 100, 200) }).unwrap();
+
+        // Clamped at both ends
+        run_relative_fade(&mut artnet_manager, "s(+20);rgb(-10,+10,0)", defs::DIMMING_AMOUNT_MAX);
+        assert_eq!(get_value(&artnet_manager, &single), DimmerValue::Single(255));
+        assert_eq!(get_value(&artnet_manager, &rgb), DimmerValue::Rgb(0, 110, 0));
+
+        // Dimming is applied to the resolved value, not to the delta
+        run_relative_fade(&mut artnet_manager, "s(-55);rgb(+100,+0,+0)", 500);
+        assert_eq!(get_value(&artnet_manager, &single), DimmerValue::Single(100));
+        assert_eq!(get_value(&artnet_manager, &rgb), DimmerValue::Rgb(50, 55, 0));
+    }
+
+    #[derive(Debug)]
+    struct UnknownLengthNode {}
+
+    impl EffectNodeRuntime for UnknownLengthNode {
+        fn tick(&mut self, _: &mut ArtnetManager) -> Result<(), ArtnetError> {
+            Ok(())
+        }
+
+        fn is_done(&self) -> bool {
+            false
+        }
+    }
+
+    fn get_remaining_ticks_test_array() -> DmxArray {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "description": "Test array",
+            "lights": {
+                "all": "s:0"
+            },
+            "effects": {
+                "on": {
+                    "type": "sequence",
+                    "nodes": [
+                        {
+                            "type": "fade",
+                            "lights": "@all",
+                            "ticks": 4,
+                            "target": "s(255)"
+                        },
+                        {
+                            "type": "parallel",
+                            "nodes": [
+                                {
+                                    "type": "delay",
+                                    "ticks": 3
+                                },
+                                {
+                                    "type": "sequence",
+                                    "nodes": [
+                                        { "type": "delay", "ticks": 2 },
+                                        { "type": "delay", "ticks": 4 }
+                                    ]
+                                }
+                            ]
+                        }
+                    ]
+                }
+            }
+        }"#;
+
+        serde_json::from_str::<DmxArray>(array_json).unwrap()
+    }
+
+    #[test]
+    fn test_remaining_ticks() {
+        let mut array_manager = ArrayManager::new();
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager
+            .add_universe("0", get_universe_definition())
+            .unwrap();
+
+        array_manager
+            .add_array(Arc::from("test"), Box::new(get_remaining_ticks_test_array()))
+            .unwrap();
+        let mut node = array_manager
+            .get_usage_effect_runtime(
+                &defs::EffectUsage::On,
+                "test",
+                None,
+                defs::DIMMING_AMOUNT_MAX,
+            )
+            .unwrap();
+
+        // fade (4) followed by the longest of delay (3) and sequence of delays (2 + 4)
+        assert_eq!(node.remaining_ticks(), Some(10));
+
+        let expected_remaining_ticks = [9, 8, 7, 6, 5, 4, 3, 2, 1, 0];
+
+        for expected in expected_remaining_ticks {
+            node.tick(&mut artnet_manager).unwrap();
+            assert_eq!(node.remaining_ticks(), Some(expected));
+        }
+
+        assert!(node.is_done());
+    }
+
+    #[test]
+    fn test_remaining_ticks_unknown() {
+        let delay_node = Box::new(DelayEffectNode {
+            ticks: 5,
+            current_tick: 0,
+        });
+        let parallel_node = ParallelEffectNode {
+            nodes: vec![delay_node, Box::new(UnknownLengthNode {})],
+        };
+        assert_eq!(parallel_node.remaining_ticks(), None);
+
+        let sequence_node = SequenceEffectNode {
+            nodes: vec![Box::new(UnknownLengthNode {}), Box::new(parallel_node)],
+            current_node: 0,
+        };
+        assert_eq!(sequence_node.remaining_ticks(), None);
+    }
+
+    #[test]
+    fn test_effect_status() {
+        let mut array_manager = ArrayManager::new();
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager
+            .add_universe("0", get_universe_definition())
+            .unwrap();
+
+        array_manager
+            .add_array(Arc::from("test"), Box::new(get_remaining_ticks_test_array()))
+            .unwrap();
+        let node = array_manager
+            .get_usage_effect_runtime(
+                &defs::EffectUsage::On,
+                "test",
+                None,
+                defs::DIMMING_AMOUNT_MAX,
+            )
+            .unwrap();
+
+        assert_eq!(
+            artnet_manager.get_effect_status("test").unwrap(),
+            EffectStatus {
+                running: false,
+                paused: false,
+                elapsed_ticks: None,
+                remaining_ticks: None,
+                remaining_ms: None,
+                last_command: None,
+                last_dimming_amount: None,
+                origin: None,
+                description: None,
+            }
+        );
+
+        artnet_manager.start_effect("test", node, None, Some(Arc::from("automation")), EffectMaxTicks::Default).unwrap();
+        assert!(artnet_manager.tick().is_empty());
+        assert!(artnet_manager.tick().is_empty());
+
+        assert_eq!(
+            artnet_manager.get_effect_status("test").unwrap(),
+            EffectStatus {
+                running: true,
+                paused: false,
+                elapsed_ticks: Some(2),
+                remaining_ticks: Some(8),
+                remaining_ms: Some(400),
+                last_command: None,
+                last_dimming_amount: None,
+                origin: Some(Arc::from("automation")),
+                description: None,
+            }
+        );
+    }
+
+    #[derive(Debug)]
+    struct SlowNode {
+        tick_duration: Duration,
+    }
+
+    impl EffectNodeRuntime for SlowNode {
+        fn tick(&mut self, _: &mut ArtnetManager) -> Result<(), ArtnetError> {
+            std::thread::sleep(self.tick_duration);
+            Ok(())
+        }
+
+        fn is_done(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_stop_effects() {
+        let mut artnet_manager = ArtnetManager::new();
+        let start = |artnet_manager: &mut ArtnetManager| {
+            artnet_manager.start_effect("lounge", Box::new(UnknownLengthNode {}), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+            artnet_manager.start_effect("lounge@candles", Box::new(UnknownLengthNode {}), Some(EffectUsage::Dim), None, EffectMaxTicks::Default).unwrap();
+            artnet_manager.start_effect("lounge@spots", Box::new(UnknownLengthNode {}), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+            artnet_manager.start_effect("lounge@flicker", Box::new(UnknownLengthNode {}), None, None, EffectMaxTicks::Default).unwrap();
+            artnet_manager.start_effect("kitchen", Box::new(UnknownLengthNode {}), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+            artnet_manager.start_effect("loungeroom", Box::new(UnknownLengthNode {}), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+            artnet_manager.start_effect("loungeroom@spots", Box::new(UnknownLengthNode {}), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+        };
+        let active_effects = |artnet_manager: &ArtnetManager| {
+            let mut effect_ids = artnet_manager.active_effects.keys().cloned().collect::<Vec<_>>();
+            effect_ids.sort();
+            effect_ids
+        };
+
+        start(&mut artnet_manager);
+        assert_eq!(artnet_manager.stop_effects("lounge", StopScope::Exact, None).unwrap(), vec![Arc::from("lounge")]);
+        assert_eq!(active_effects(&artnet_manager), vec!["kitchen", "lounge@candles", "lounge@flicker", "lounge@spots", "loungeroom", "loungeroom@spots"]);
+        assert!(artnet_manager.stop_effects("lounge", StopScope::Exact, None).unwrap().is_empty());
+
+        start(&mut artnet_manager);
+        assert_eq!(
+            artnet_manager.stop_effects("lounge", StopScope::Usage, Some(EffectUsage::On)).unwrap(),
+            vec![Arc::from("lounge"), Arc::from("lounge@spots")]
+        );
+        assert_eq!(active_effects(&artnet_manager), vec!["kitchen", "lounge@candles", "lounge@flicker", "loungeroom", "loungeroom@spots"]);
+
+        let e = artnet_manager.stop_effects("lounge", StopScope::Usage, None).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::MissingStopUsage(_)));
+
+        start(&mut artnet_manager);
+        assert_eq!(
+            artnet_manager.stop_effects("lounge", StopScope::All, None).unwrap(),
+            vec![Arc::from("lounge"), Arc::from("lounge@candles"), Arc::from("lounge@flicker"), Arc::from("lounge@spots")]
+        );
+        // Another array whose ID starts with the array ID is not affected
+        assert_eq!(active_effects(&artnet_manager), vec!["kitchen", "loungeroom", "loungeroom@spots"]);
+    }
+
+    #[test]
+    fn test_enqueue_effect() {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "lights": { "all": "s:0" },
+            "effects": {
+                "on": { "type": "fade", "lights": "@all", "ticks": 10, "target": "s(250)" },
+                "off": { "type": "fade", "lights": "@all", "ticks": 2, "target": "s(0)" }
+            }
+        }"#;
+        let mut array_manager = ArrayManager::new();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+
+        let get_node = |usage| array_manager.get_usage_effect_runtime(&usage, "test", None, defs::DIMMING_AMOUNT_MAX).unwrap();
+        let get_value = |artnet_manager: &ArtnetManager| artnet_manager.get_channel("0", &ChannelDefinition::Single(0)).unwrap().value;
+
+        // Nothing is active, so the effect is started now
+        artnet_manager.enqueue_effect("test", get_node(EffectUsage::On), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+        assert!(artnet_manager.queued_effects.is_empty());
+
+        assert!(artnet_manager.tick().is_empty());
+        artnet_manager.enqueue_effect("test", Box::new(UnknownLengthNode {}), None, None, EffectMaxTicks::Default).unwrap();
+        artnet_manager.enqueue_effect("test", get_node(EffectUsage::Off), Some(EffectUsage::Off), Some(Arc::from("automation")), EffectMaxTicks::Default).unwrap();
+
+        // The later request replaces the queued effect
+        let diagnostics = artnet_manager.get_diagnostics();
+        let queued = diagnostics.active_effects["test"].queued.as_ref().unwrap();
+        assert_eq!((queued.usage, queued.origin.as_deref()), (Some(EffectUsage::Off), Some("automation")));
+
+        for _ in 1..10 {
+            assert!(artnet_manager.tick().is_empty());
+        }
+
+        // On fade completed on this tick, the queued off effect replaced it and starts on the next tick
+        assert_eq!(get_value(&artnet_manager), DimmerValue::Single(250));
+        let effect = &artnet_manager.active_effects["test"];
+        assert_eq!((effect.usage, effect.elapsed_ticks), (Some(EffectUsage::Off), 0));
+        assert!(artnet_manager.queued_effects.is_empty());
+
+        assert!(artnet_manager.tick().is_empty());
+        assert_eq!(get_value(&artnet_manager), DimmerValue::Single(125));
+        assert!(artnet_manager.tick().is_empty());
+        assert_eq!(get_value(&artnet_manager), DimmerValue::Single(0));
+        assert!(!artnet_manager.get_effect_status("test").unwrap().running);
+
+        // Stop clears both the active and the queued effect, starting an effect now drops the queued one
+        artnet_manager.start_effect("test", get_node(EffectUsage::On), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+        artnet_manager.enqueue_effect("test", get_node(EffectUsage::Off), Some(EffectUsage::Off), None, EffectMaxTicks::Default).unwrap();
+        assert_eq!(artnet_manager.stop_effects("test", StopScope::Exact, None).unwrap(), vec![Arc::from("test")]);
+        assert!(artnet_manager.active_effects.is_empty() && artnet_manager.queued_effects.is_empty());
+
+        artnet_manager.start_effect("test", get_node(EffectUsage::On), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+        artnet_manager.enqueue_effect("test", get_node(EffectUsage::Off), Some(EffectUsage::Off), None, EffectMaxTicks::Default).unwrap();
+        artnet_manager.start_effect("test", get_node(EffectUsage::On), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+        assert!(artnet_manager.queued_effects.is_empty());
+    }
+
+    #[test]
+    fn test_rename_universe() {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "lights": { "all": "s:0" },
+            "effects": { "on": { "type": "fade", "lights": "@all", "ticks": 10, "target": "s(250)" } }
+        }"#;
+        let mut array_manager = ArrayManager::new();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+        artnet_manager.add_universe("1", UniverseDefinition { universe: Some(1), ..get_universe_definition() }).unwrap();
+
+        let get_node = || array_manager.get_usage_effect_runtime(&EffectUsage::On, "test", None, defs::DIMMING_AMOUNT_MAX).unwrap();
+        let get_value = |artnet_manager: &ArtnetManager, universe_id: &str, channel: u16| {
+            artnet_manager.get_channel(universe_id, &ChannelDefinition::Single(channel)).unwrap().value
+        };
+
+        artnet_manager.set_channel("0", &ChannelValue { channel: ChannelDefinition::Single(5), value: DimmerValue::Single(77) }).unwrap();
+        artnet_manager.start_effect("test", get_node(), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+        assert!(artnet_manager.tick().is_empty());
+        let packet_bytes = artnet_manager.universes["0"].get_packet_bytes().clone();
+
+        // Channel data and packet sequence move with the universe
+        artnet_manager.rename_universe("0", Arc::from("main")).unwrap();
+        assert!(!artnet_manager.universes.contains_key("0"));
+        assert_eq!(artnet_manager.universes["main"].get_packet_bytes(), &packet_bytes);
+        assert_eq!(get_value(&artnet_manager, "main", 5), DimmerValue::Single(77));
+
+        // The running effect and effects built from the array definition (still referring to 0) set the renamed universe
+        for _ in 1..10 {
+            assert!(artnet_manager.tick().is_empty());
+        }
+        assert_eq!(get_value(&artnet_manager, "main", 0), DimmerValue::Single(250));
+        artnet_manager.start_effect("test", get_node(), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+        assert!(artnet_manager.get_effect_status("test").unwrap().running);
+
+        // Commands use the current ID, the old ID is redirected only by the subscriber while its alias has not expired
+        let copy = |from_universe: &str, to_universe: &str| defs::CopyCommandParameters {
+            from_universe: from_universe.to_string(),
+            to_universe: to_universe.to_string(),
+            channels: Some("s:5".to_string()),
+            all: false,
+            to_offset: 1,
+            origin: None,
+        };
+        let set_channels = |universe_id: &str| defs::SetChannelsParameters {
+            universe_id: universe_id.to_string(),
+            channels: "s:7".to_string(),
+            target: "s(30)".to_string(),
+            dimming_amount: None,
+            origin: None,
+        };
+        artnet_manager.copy_channels(&copy("main", "main")).unwrap();
+        assert_eq!(get_value(&artnet_manager, "main", 6), DimmerValue::Single(77));
+        assert!(matches!(artnet_manager.copy_channels(&copy("0", "main")).unwrap_err().current_context(), ArtnetError::InvalidUniverse(_)));
+        assert!(matches!(artnet_manager.copy_channels(&copy("main", "0")).unwrap_err().current_context(), ArtnetError::InvalidUniverse(_)));
+        assert!(matches!(artnet_manager.set_channels(&set_channels("0")).unwrap_err().current_context(), ArtnetError::InvalidUniverse(_)));
+
+        // Limits of the array (defined with the old ID) apply to commands using the new ID
+        let mut limits = ChannelLimits::default();
+        limits.add("0", &ChannelDefinition::Single(7), &"s(20)".parse().unwrap());
+        artnet_manager.set_channel_limits("test", Some(Arc::new(limits))).unwrap();
+        artnet_manager.set_channels(&set_channels("main")).unwrap();
+        assert_eq!(get_value(&artnet_manager, "main", 7), DimmerValue::Single(20));
+        artnet_manager.set_channel_limits("test", None).unwrap();
+
+        assert!(matches!(artnet_manager.rename_universe("missing", Arc::from("other")).unwrap_err().current_context(), ArtnetError::InvalidUniverse(_)));
+        assert!(matches!(artnet_manager.rename_universe("main", Arc::from("1")).unwrap_err().current_context(), ArtnetError::UniverseAlreadyDefined(_)));
+
+        // Once the old ID is defined again, it no longer refers to the renamed universe
+        artnet_manager.add_universe("0", UniverseDefinition { universe: Some(2), ..get_universe_definition() }).unwrap();
+        assert_eq!(get_value(&artnet_manager, "0", 5), DimmerValue::Single(0));
+        assert_eq!(get_value(&artnet_manager, "main", 5), DimmerValue::Single(77));
+    }
+
+    #[test]
+    fn test_rename_array_effects() {
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+
+        for effect_id in ["test", "test@spots", "testing"] {
+            artnet_manager.start_effect(effect_id, Box::new(UnknownLengthNode {}), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+        }
+        artnet_manager.enqueue_effect("test", Box::new(UnknownLengthNode {}), Some(EffectUsage::Off), None, EffectMaxTicks::Default).unwrap();
+
+        // The effects of the array and its light groups are renamed, the effect of another array with the same prefix is not
+        assert_eq!(artnet_manager.rename_array_effects("test", Arc::from("lounge")).unwrap(), vec![Arc::from("lounge"), Arc::from("lounge@spots")]);
+
+        let mut effect_ids = artnet_manager.active_effects.keys().map(|effect_id| effect_id.as_str()).collect::<Vec<_>>();
+        effect_ids.sort();
+        assert_eq!(effect_ids, vec!["lounge", "lounge@spots", "testing"]);
+        assert_eq!(artnet_manager.active_effects["lounge"].usage, Some(EffectUsage::On));
+        assert_eq!(artnet_manager.queued_effects.keys().collect::<Vec<_>>(), vec!["lounge"]);
+    }
+
+    #[test]
+    fn test_effect_max_ticks() {
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+        artnet_manager.max_effect_ticks = Some(3);
+
+        let ticks = |artnet_manager: &mut ArtnetManager, count: usize| {
+            (0..count).flat_map(|_| {
+                assert!(artnet_manager.tick().is_empty());
+                artnet_manager.stop_effects_over_max_ticks()
+            }).collect::<Vec<_>>()
+        };
+        let get_max_ticks = |json: &str| serde_json::from_str::<defs::OnOffCommandParameters>(json).unwrap().get_max_ticks();
+
+        assert_eq!(get_max_ticks("{}"), EffectMaxTicks::Default);
+        assert_eq!(get_max_ticks(r#"{ "max_ticks": 5 }"#), EffectMaxTicks::Ticks(5));
+        assert_eq!(get_max_ticks(r#"{ "max_ticks": 5, "no_max_ticks": true }"#), EffectMaxTicks::Unlimited);
+
+        artnet_manager.start_effect("fade", Box::new(UnknownLengthNode {}), Some(EffectUsage::On), Some(Arc::from("typo")), EffectMaxTicks::Default).unwrap();
+        artnet_manager.enqueue_effect("fade", Box::new(UnknownLengthNode {}), Some(EffectUsage::Off), None, EffectMaxTicks::Ticks(1)).unwrap();
+        artnet_manager.start_effect("override", Box::new(UnknownLengthNode {}), None, None, EffectMaxTicks::Ticks(5)).unwrap();
+        artnet_manager.start_effect("flicker", Box::new(UnknownLengthNode {}), None, None, EffectMaxTicks::Unlimited).unwrap();
+
+        // The global limit stops the effect (as if it completed) so the effect queued after it starts
+        let warnings = ticks(&mut artnet_manager, 3);
+        match warnings.as_slice() {
+            [ToMqttPublisherMessage::Warning(warning)] => {
+                assert!(warning.contains("'fade'") && warning.contains("origin typo") && warning.contains("maximum of 3 ticks"), "{warning}")
+            }
+            _ => panic!("Expected a warning about fade, got {:?}", warnings),
+        }
+        let effect = &artnet_manager.active_effects["fade"];
+        assert_eq!((effect.usage, effect.elapsed_ticks), (Some(EffectUsage::Off), 0));
+
+        // The command max_ticks overrides the global limit
+        let warnings = ticks(&mut artnet_manager, 2);
+        assert_eq!(warnings.len(), 2);
+        assert!(matches!(&warnings[0], ToMqttPublisherMessage::Warning(warning) if warning.contains("'fade'") && warning.contains("maximum of 1 ticks")));
+        assert!(matches!(&warnings[1], ToMqttPublisherMessage::Warning(warning) if warning.contains("'override'") && warning.contains("maximum of 5 ticks")));
+
+        // Effects that opted out run until stopped
+        assert!(ticks(&mut artnet_manager, 100).is_empty());
+        assert_eq!(artnet_manager.active_effects.keys().collect::<Vec<_>>(), vec!["flicker"]);
+        assert_eq!(artnet_manager.active_effects["flicker"].elapsed_ticks, 105);
+
+        let stats = artnet_manager.get_effect_stats(false);
+        assert_eq!(stats.iter().map(|entry| entry.completed).sum::<usize>(), 3);
+    }
+
+    #[test]
+    fn test_effect_stats() {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "lights": { "all": "s:0" },
+            "effects": {
+                "on": { "type": "fade", "lights": "@all", "ticks": 4, "target": "s(250)" },
+                "off": { "type": "fade", "lights": "@all", "ticks": 2, "target": "s(0)" }
+            }
+        }"#;
+        let mut array_manager = ArrayManager::new();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+
+        let get_node = |usage| array_manager.get_usage_effect_runtime(&usage, "test", None, defs::DIMMING_AMOUNT_MAX).unwrap();
+        let ticks = |artnet_manager: &mut ArtnetManager, count: usize| (0..count).for_each(|_| assert!(artnet_manager.tick().is_empty()));
+
+        // On is replaced by Off before completing, Off completes and On then runs to completion
+        artnet_manager.start_effect("test", get_node(EffectUsage::On), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+        ticks(&mut artnet_manager, 2);
+        artnet_manager.start_effect("test", get_node(EffectUsage::Off), Some(EffectUsage::Off), None, EffectMaxTicks::Default).unwrap();
+        ticks(&mut artnet_manager, 2);
+        artnet_manager.start_effect("test", get_node(EffectUsage::On), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+        ticks(&mut artnet_manager, 4);
+
+        // Queued effects are counted when they start (lights are already on, so this On fade completes on its first tick)
+        artnet_manager.start_effect("test", get_node(EffectUsage::On), Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+        artnet_manager.enqueue_effect("test", get_node(EffectUsage::Off), Some(EffectUsage::Off), None, EffectMaxTicks::Default).unwrap();
+        ticks(&mut artnet_manager, 6);
+        artnet_manager.start_effect("flicker", Box::new(UnknownLengthNode {}), None, None, EffectMaxTicks::Default).unwrap();
+
+        let entry = |effect_id: &str, usage, started, completed, preempted, average_ticks| defs::EffectStatsEntry {
+            effect_id: Arc::from(effect_id),
+            usage,
+            started,
+            completed,
+            preempted,
+            average_ticks,
+        };
+
+        assert_eq!(artnet_manager.get_effect_stats(true), vec![
+            entry("flicker", None, 1, 0, 0, None),
+            entry("test", Some(EffectUsage::On), 3, 2, 1, Some(2.5)),
+            entry("test", Some(EffectUsage::Off), 2, 2, 0, Some(2.0)),
+        ]);
+        assert!(artnet_manager.get_effect_stats(false).is_empty());
+    }
+
+    #[test]
+    fn test_set_frame() {
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:0" }, "effects": { "on": { "type": "fade", "lights": "@all", "ticks": 4, "target": "s(255)" } } }"#;
+        let mut array_manager = ArrayManager::new();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+
+        let frame = |offset: u16, data_b64: &str, dimming_amount: Option<defs::DimmingAmount>, force: bool| defs::SetFrameCommandParameters {
+            universe_id: "0".to_string(),
+            offset,
+            data_b64: data_b64.to_string(),
+            dimming_amount,
+            force,
+            origin: None,
+        };
+        let get_values = |artnet_manager: &ArtnetManager, channels: std::ops::Range<u16>| {
+            channels.map(|c| artnet_manager.get_channel("0", &ChannelDefinition::Single(c)).unwrap().value).collect::<Vec<_>>()
+        };
+        let singles = |values: &[u8]| values.iter().map(|v| DimmerValue::Single(*v)).collect::<Vec<_>>();
+
+        // [1, 2, 3, 4] at offset 0 and 302 (the last channels)
+        artnet_manager.set_frame(&frame(0, "AQIDBA==", None, false)).unwrap();
+        artnet_manager.set_frame(&frame(302, "AQIDBA==", Some(500), false)).unwrap();
+        assert_eq!(get_values(&artnet_manager, 0..5), singles(&[1, 2, 3, 4, 0]));
+        assert_eq!(get_values(&artnet_manager, 301..306), singles(&[0, 0, 1, 1, 2]));
+        assert!(artnet_manager.universes["0"].modified);
+
+        let e = artnet_manager.set_frame(&frame(303, "AQIDBA==", None, false)).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::FrameOutOfRange(_, 303, 4, 306)));
+        let e = artnet_manager.set_frame(&frame(0, "", None, false)).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::FrameOutOfRange(_, 0, 0, 306)));
+        let e = artnet_manager.set_frame(&frame(0, "not base64!", None, false)).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidFrameData(..)));
+        let e = artnet_manager.set_frame(&defs::SetFrameCommandParameters { universe_id: "1".to_string(), ..frame(0, "AQIDBA==", None, false) }).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidUniverse(..)));
+
+        // A universe used by an active effect can be set only if forced
+        let node = array_manager.get_usage_effect_runtime(&EffectUsage::On, "test", None, defs::DIMMING_AMOUNT_MAX).unwrap();
+        artnet_manager.start_effect("test", node, Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+
+        let e = artnet_manager.set_frame(&frame(0, "CgoKCg==", None, false)).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::FrameUniverseInUse(_, effect_ids) if effect_ids == "test"));
+        assert_eq!(get_values(&artnet_manager, 0..4), singles(&[1, 2, 3, 4]));
+
+        artnet_manager.set_frame(&frame(0, "CgoKCg==", None, true)).unwrap();
+        assert_eq!(get_values(&artnet_manager, 0..4), singles(&[10, 10, 10, 10]));
+
+        // Array limits (set with the universe ID before a rename) and channel aliases apply to frames as to other channel values
+        let mut limits = ChannelLimits::default();
+        limits.add("0", &ChannelDefinition::Single(1), &"s(5)".parse().unwrap());
+        artnet_manager.set_channel_limits("test", Some(Arc::new(limits))).unwrap();
+        artnet_manager.rename_universe("0", Arc::from("main")).unwrap();
+        artnet_manager.set_channel_alias(&defs::AliasCommandParameters { universe_id: "main".to_string(), from: "s:2".to_string(), to: Some("s:20".to_string()) }).unwrap();
+
+        // [20, 20, 20, 20] to the renamed universe, the old ID is not accepted by commands
+        let e = artnet_manager.set_frame(&frame(0, "FBQUFA==", None, true)).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::InvalidUniverse(_)));
+        artnet_manager.set_frame(&defs::SetFrameCommandParameters { universe_id: "main".to_string(), ..frame(0, "FBQUFA==", None, true) }).unwrap();
+        assert_eq!(get_values(&artnet_manager, 0..4), singles(&[20, 5, 20, 20]));
+        assert_eq!(artnet_manager.universes["main"].get_channel(&ChannelDefinition::Single(2)).unwrap().value, DimmerValue::Single(10));
+        assert_eq!(artnet_manager.universes["main"].get_channel(&ChannelDefinition::Single(20)).unwrap().value, DimmerValue::Single(20));
+    }
+
+    #[test]
+    fn test_pause_effects() {
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:0" }, "effects": { "on": { "type": "fade", "lights": "@all", "ticks": 4, "target": "s(255)" } } }"#;
+        let mut array_manager = ArrayManager::new();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+
+        let node = array_manager.get_usage_effect_runtime(&EffectUsage::On, "test", None, defs::DIMMING_AMOUNT_MAX).unwrap();
+        artnet_manager.start_effect("test", node, Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+        artnet_manager.start_effect("other", Box::new(UnknownLengthNode {}), None, None, EffectMaxTicks::Default).unwrap();
+        let get_value = |artnet_manager: &ArtnetManager| artnet_manager.get_channel("0", &ChannelDefinition::Single(0)).unwrap().value;
+
+        assert!(artnet_manager.tick().is_empty());
+        assert!(artnet_manager.tick().is_empty());
+        assert_eq!(get_value(&artnet_manager), DimmerValue::Single(128));
+
+        // Paused effect keeps its state without changing channels
+        assert_eq!(artnet_manager.pause_effects(Some("test"), true).unwrap(), vec![Arc::from("test")]);
+        artnet_manager.set_channel_log.clear();
+
+        for _ in 0..3 {
+            assert!(artnet_manager.tick().is_empty());
+        }
+
+        assert!(artnet_manager.set_channel_log.is_empty());
+        assert_eq!(get_value(&artnet_manager), DimmerValue::Single(128));
+
+        let status = artnet_manager.get_effect_status("test").unwrap();
+        assert!(status.running && status.paused);
+        assert_eq!((status.elapsed_ticks, status.remaining_ticks), (Some(2), Some(2)));
+
+        // Fade continues from where it was paused
+        artnet_manager.pause_effects(Some("test"), false).unwrap();
+        assert!(artnet_manager.tick().is_empty());
+        assert_eq!(get_value(&artnet_manager), DimmerValue::Single(191));
+        assert!(artnet_manager.tick().is_empty());
+        assert_eq!(get_value(&artnet_manager), DimmerValue::Single(255));
+        assert!(!artnet_manager.get_effect_status("test").unwrap().running);
+
+        let e = artnet_manager.pause_effects(Some("test"), true).unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::EffectNotActive(id) if id == "test"));
+
+        artnet_manager.start_effect("another", Box::new(UnknownLengthNode {}), None, None, EffectMaxTicks::Default).unwrap();
+        assert_eq!(artnet_manager.pause_effects(None, true).unwrap(), vec![Arc::from("another"), Arc::from("other")]);
+        assert!(artnet_manager.active_effects.values().all(|effect| effect.paused));
+        artnet_manager.pause_effects(None, false).unwrap();
+        assert!(artnet_manager.active_effects.values().all(|effect| !effect.paused));
+    }
+
+    #[test]
+    fn test_effect_tick_budget() {
+        let mut artnet_manager = ArtnetManager::new().with_tick_budget(Some(EffectTickBudget {
+            max_tick_duration: Duration::from_millis(5),
+            max_over_budget_ticks: 3,
+        }));
+
+        artnet_manager
+            .start_effect("slow", Box::new(SlowNode { tick_duration: Duration::from_millis(10) }), None, None, EffectMaxTicks::Default)
+            .unwrap();
+        artnet_manager
+            .start_effect("fast", Box::new(UnknownLengthNode {}), None, None, EffectMaxTicks::Default)
+            .unwrap();
+
+        assert!(artnet_manager.tick().is_empty());
+        assert!(artnet_manager.tick().is_empty());
+        assert!(artnet_manager.get_effect_status("slow").unwrap().running);
+
+        let errors = artnet_manager.tick();
+        match errors.iter().map(|e| e.current_context()).collect::<Vec<_>>().as_slice() {
+            [ArtnetError::EffectTickBudgetExceeded(_, 3, stopped_effects)] => assert!(stopped_effects.starts_with("slow (")),
+            _ => panic!("Expected EffectTickBudgetExceeded error, got {:?}", errors),
+        }
+
+        assert!(!artnet_manager.get_effect_status("slow").unwrap().running);
+        assert!(artnet_manager.get_effect_status("fast").unwrap().running);
+
+        assert!(artnet_manager.tick().is_empty());
+        assert_eq!(artnet_manager.get_effect_status("fast").unwrap().elapsed_ticks, Some(4));
+    }
+
+    #[test]
+    fn test_hold_node() {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "lights": { "all": "s:1,$1,rgb:4" },
+            "effects": {
+                "flash": { "type": "hold", "lights": "@all", "ticks": 3, "target": "s(200); rgb(200,100,50)" },
+                "swell": { "type": "hold", "lights": "@all", "ticks": 2, "attack_ticks": 4, "target": "s(200); rgb(200,100,50)", "no_dimming": true }
+            }
+        }"#;
+
+        let mut array_manager = ArrayManager::new();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+        artnet_manager.add_universe("1", UniverseDefinition { universe: Some(1), ..get_universe_definition() }).unwrap();
+
+        let get_node = |effect_id: &str| {
+            array_manager.get_usage_effect_runtime(&EffectUsage::On, "test", Some(&Arc::from(effect_id)), 500).unwrap()
+        };
+
+        // Snap to the dimmed target on the first tick, then hold
+        let mut node = get_node("flash");
+        assert_eq!(node.remaining_ticks(), Some(3));
+
+        node.tick(&mut artnet_manager).unwrap();
+        assert_eq!(artnet_manager.set_channel_log.len(), 2);
+        assert!(artnet_manager.set_channel_log.contains(&ChannelValue { channel: ChannelDefinition::Single(1), value: DimmerValue::Single(100) }));
+        assert!(artnet_manager
+            .set_channel_log
+            .contains(&ChannelValue { channel: ChannelDefinition::Rgb(4, 5, 6), value: DimmerValue::Rgb(100, 50, 25) }));
+
+        node.tick(&mut artnet_manager).unwrap();
+        assert!(!node.is_done());
+        node.tick(&mut artnet_manager).unwrap();
+        assert!(node.is_done());
+        assert_eq!(node.remaining_ticks(), Some(0));
+        assert_eq!(artnet_manager.set_channel_log.len(), 2);
+
+        // Fade over attack ticks, then hold
+        let mut node = get_node("swell");
+        assert_eq!(node.remaining_ticks(), Some(6));
+        artnet_manager.set_channel_log.clear();
+
+        let mut ticks = 0;
+        while !node.is_done() {
+            node.tick(&mut artnet_manager).unwrap();
+            ticks += 1;
+        }
+
+        assert_eq!(ticks, 6);
+        assert_eq!(artnet_manager.set_channel_log.len(), 8);
+        assert_eq!(
+            artnet_manager.set_channel_log.iter().filter(|v| v.channel == ChannelDefinition::Single(1)).map(|v| v.value.clone()).collect::<Vec<_>>(),
+            [125, 150, 175, 200].map(DimmerValue::Single)
+        );
+        assert_eq!(artnet_manager.get_channel("1", &ChannelDefinition::Rgb(4, 5, 6)).unwrap().value, DimmerValue::Rgb(200, 100, 50));
+    }
+
+    #[test]
+    fn test_light_group_effect() {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "lights": { "reading": "s:0", "ceiling": "s:1", "all": "@reading, @ceiling" },
+            "effects": {
+                "on": { "type": "fade", "lights": "@all", "ticks": 2, "target": "s(255)" },
+                "ceiling_on": { "type": "fade", "lights": "@ceiling", "ticks": 2, "target": "s(100)" }
+            }
+        }"#;
+
+        let mut array_manager = ArrayManager::new();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+        let changed_channels = |artnet_manager: &ArtnetManager| {
+            let mut channels = artnet_manager.set_channel_log.iter().map(|v| v.channel.clone()).collect::<Vec<_>>();
+            channels.dedup();
+            channels
+        };
+
+        // @all is replaced by the requested group
+        let node = array_manager.get_group_effect_runtime(&EffectUsage::On, "test", None, Some("@reading"), defs::DIMMING_AMOUNT_MAX).unwrap().0;
+        run_node(node, &mut artnet_manager);
+        assert_eq!(changed_channels(&artnet_manager), vec![ChannelDefinition::Single(0)]);
+
+        // Effects explicitly naming another group ignore the override
+        let effect_id = Some(Arc::from("ceiling_on"));
+        let node = array_manager.get_group_effect_runtime(&EffectUsage::On, "test", effect_id.as_ref(), Some("@reading"), defs::DIMMING_AMOUNT_MAX).unwrap().0;
+        run_node(node, &mut artnet_manager);
+        assert_eq!(changed_channels(&artnet_manager), vec![ChannelDefinition::Single(1)]);
+
+        for lights in ["@kitchen", "reading"] {
+            let e = array_manager.get_group_effect_runtime(&EffectUsage::On, "test", None, Some(lights), defs::DIMMING_AMOUNT_MAX).unwrap_err();
+            assert!(matches!(e.current_context(), DmxArrayError::ArrayLightGroupNotFound(_, group) if group == lights));
+        }
+    }
+
+    #[test]
+    fn test_target_missing_channel_type_warning() {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "lights": { "strip": "rgb:0", "spot": "s:3", "all": "@strip, @spot" },
+            "effects": {
+                "on": { "type": "fade", "lights": "@all", "ticks": 2, "target": "rgb(255,255,255)" },
+                "full": { "type": "hold", "lights": "@all", "ticks": 2, "target": "s(255);rgb(255,255,255)" },
+                "strip_on": { "type": "fade", "lights": "@strip", "ticks": 2, "target": "rgb(255,0,0)" }
+            }
+        }"#;
+
+        let mut array_manager = ArrayManager::new();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+        let get_warnings = |effect_id: &str| {
+            let effect_id = Some(Arc::from(effect_id));
+            array_manager.get_group_effect_runtime(&EffectUsage::On, "test", effect_id.as_ref(), None, defs::DIMMING_AMOUNT_MAX).unwrap().1
+        };
+
+        // rgb only target over mixed lights leaves the single channel unchanged
+        let warnings = get_warnings("on");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("has no value for s channels of lights '@all'"), "{}", warnings[0]);
+
+        // All channel types present in the lights are covered
+        assert!(get_warnings("full").is_empty());
+        assert!(get_warnings("strip_on").is_empty());
+    }
+
+    #[test]
+    fn test_compiled_effect_channel_log() {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "lights": { "all": "rgb:1,s:4" },
+            "effects": {
+                "on": {
+                    "type": "sequence",
+                    "nodes": [
+                        { "type": "fade", "lights": "@all", "ticks": 3, "target": "rgb(255,128,0);s(`level=200`)" },
+                        { "type": "hold", "lights": "@all", "ticks": 2, "attack_ticks": 2, "target": "rgb(-50,+20,+10);s(-100)" },
+                        { "type": "parallel", "nodes": [{ "type": "delay", "ticks": 2 }, { "type": "fade", "lights": "@all", "ticks": 2, "target": "rgb(0,0,0);s(0)" }] }
+                    ]
+                }
+            }
+        }"#;
+
+        let get_channel_log = |array_manager: &ArrayManager| {
+            let mut artnet_manager = ArtnetManager::new();
+            artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+
+            let node = array_manager.get_usage_effect_runtime(&EffectUsage::On, "test", None, 700).unwrap();
+            run_node(node, &mut artnet_manager);
+            artnet_manager.set_channel_log.clone()
+        };
+
+        let mut array_manager = ArrayManager::new();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+        // Compiled, then instantiated from the cache (runtime nodes do not share state)
+        let uncached_log = get_channel_log(&array_manager);
+        assert!(!uncached_log.is_empty());
+        assert_eq!(get_channel_log(&array_manager), uncached_log);
+        assert_eq!(get_channel_log(&array_manager), uncached_log);
+    }
+
+    #[test]
+    fn test_effect_failure_context() {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "lights": { "all": "s:1,s:10" },
+            "effects": {
+                "on": {
+                    "type": "sequence",
+                    "nodes": [
+                        { "type": "delay", "ticks": 2 },
+                        { "type": "fade", "lights": "@all", "ticks": 4, "target": "s(255)" }
+                    ]
+                }
+            }
+        }"#;
+        let mut array_manager = ArrayManager::new();
+        array_manager.add_array(Arc::from("lounge"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+
+        let node = array_manager.get_usage_effect_runtime(&EffectUsage::On, "lounge", None, defs::DIMMING_AMOUNT_MAX).unwrap();
+        artnet_manager.start_effect("lounge", node, Some(EffectUsage::On), Some(Arc::from("automation")), EffectMaxTicks::Default).unwrap();
+        let node = array_manager.get_usage_effect_runtime(&EffectUsage::On, "lounge", None, defs::DIMMING_AMOUNT_MAX).unwrap();
+        artnet_manager.start_effect("hall", node, Some(EffectUsage::On), None, EffectMaxTicks::Default).unwrap();
+        assert!(artnet_manager.tick().is_empty());
+
+        // The universe is redefined with fewer channels while the effects are running, so both fades fail on the same
+        // tick and each failure is reported
+        artnet_manager.add_universe("0", UniverseDefinition { channels: 8, ..get_universe_definition() }).unwrap();
+        assert!(artnet_manager.tick().is_empty());
+        let errors = artnet_manager.tick();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0].current_context(), ArtnetError::EffectFailed(effect_id, ..) if effect_id == "hall"));
+        let e = &errors[1];
+        let message = e.to_string();
+
+        assert!(matches!(e.current_context(), ArtnetError::EffectFailed(effect_id, ..) if effect_id == "lounge"));
+        assert!(message.starts_with("Effect 'lounge' (started "), "{message}");
+        assert!(message.contains(" for usage On by origin automation, failed on tick 3) stopped: Invalid channel address for universe 0 (Test Universe): 10 (must be less than 8)"), "{message}");
+        assert!(message.ends_with(["Nodes when it failed:", "  sequence step 2/2", "    delay tick 2/2", "    fade 0: s:1,s:10; 0% complete; target s(255)"].join("\n").as_str()), "{message}");
+        assert!(e.frames().any(|frame| matches!(frame.downcast_ref::<ArtnetError>(), Some(ArtnetError::InvalidChannel(_, 10, 8)))));
+        assert!(artnet_manager.active_effects.is_empty());
+    }
+
+    #[test]
+    fn test_describe_effect_nodes() {
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "lights": { "all": "rgb:1,rgb:4,rgb:7,s:10" },
+            "effects": {
+                "on": {
+                    "type": "sequence",
+                    "nodes": [
+                        { "type": "fade", "lights": "@all", "ticks": 4, "target": "rgb(255,255,255);s(-20)" },
+                        { "type": "parallel", "nodes": [
+                            { "type": "delay", "ticks": 10 },
+                            { "type": "hold", "lights": "$0,s:10", "ticks": 2, "attack_ticks": 2, "target": "s(50)" }
+                        ] }
+                    ]
+                }
+            }
+        }"#;
+        let mut array_manager = ArrayManager::new();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+
+        let mut node = array_manager.get_usage_effect_runtime(&EffectUsage::On, "test", None, defs::DIMMING_AMOUNT_MAX).unwrap();
+        let mut tick = |node: &mut Box<dyn EffectNodeRuntime>, ticks: usize| {
+            for _ in 0..ticks {
+                node.tick(&mut artnet_manager).unwrap();
+            }
+        };
+
+        tick(&mut node, 1);
+        assert_eq!(node.describe(0), [
+            "sequence step 1/2",
+            "  fade 0: rgb:1,rgb:4 +2 more; 25% complete; target s(-20);rgb(255,255,255)",
+            "  parallel 0/2 done",
+            "    delay tick 0/10",
+            "    hold tick 0/4",
+            "      fade 0: s:10; 0% complete; target s(50)",
+        ].join("\n"));
+
+        tick(&mut node, 6);
+        assert_eq!(node.describe(1), [
+            "  sequence step 2/2",
+            "    fade 0: rgb:1,rgb:4 +2 more; 100% complete; target s(-20);rgb(255,255,255)",
+            "    parallel 0/2 done",
+            "      delay tick 3/10",
+            "      hold tick 3/4",
+            "        fade 0: s:10; 100% complete; target s(50)",
+        ].join("\n"));
+
+        tick(&mut node, 1);
+        assert!(node.describe(0).contains("  parallel 1/2 done\n    delay tick 4/10\n    hold tick 4/4\n"));
+
+        // Nodes without their own description use their Debug output
+        assert_eq!(UnknownLengthNode {}.describe(2), "    UnknownLengthNode");
+    }
+
+    #[test]
+    fn test_instant_off() {
+        // Off effect cannot be built (it refers to a value that is not defined)
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "lights": { "all": "s:1,rgb:2,w:5" },
+            "effects": {
+                "on": { "type": "fade", "lights": "@all", "ticks": 1, "target": "s(255); rgb(255,255,255); w(255,255,255)" },
+                "off": { "type": "fade", "lights": "@all", "ticks": "`missing_ticks`", "target": "s(0); rgb(0,0,0); w(0,0,0)" }
+            }
+        }"#;
+
+        let mut array_manager = ArrayManager::new();
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+        run_node(array_manager.get_usage_effect_runtime(&EffectUsage::On, "test", None, defs::DIMMING_AMOUNT_MAX).unwrap(), &mut artnet_manager);
+        assert!(array_manager.get_usage_effect_runtime(&EffectUsage::Off, "test", None, defs::DIMMING_AMOUNT_MAX).is_err());
+
+        let mut node = array_manager.get_instant_off_runtime("test", None).unwrap();
+        assert!(!node.is_done());
+        node.tick(&mut artnet_manager).unwrap();
+        assert!(node.is_done());
+
+        let zero_values = [
+            (ChannelDefinition::Single(1), DimmerValue::Single(0)),
+            (ChannelDefinition::Rgb(2, 3, 4), DimmerValue::Rgb(0, 0, 0)),
+            (ChannelDefinition::TriWhite(5, 6, 7), DimmerValue::TriWhite(0, 0, 0)),
+        ];
+
+        for (channel, value) in zero_values {
+            assert_eq!(artnet_manager.get_channel("0", &channel).unwrap().value, value);
+        }
+
+        // Ticking again does not set the channels
+        artnet_manager.set_channel_log.clear();
+        node.tick(&mut artnet_manager).unwrap();
+        assert!(artnet_manager.set_channel_log.is_empty());
+    }
+
+    #[test]
+    fn test_fade_light_component() {
+        // Sunrise brings red up before green and blue, @all owns the whole rgb light
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "lights": { "all": "rgb:10", "red": "rgb:10.r" },
+            "effects": {
+                "on": { "type": "fade", "lights": "@red", "ticks": 2, "target": "s(200)" }
+            }
+        }"#;
+
+        let mut array_manager = ArrayManager::new();
+        let mut artnet_manager = ArtnetManager::new();
+        artnet_manager.add_universe("0", get_universe_definition()).unwrap();
+        array_manager.add_array(Arc::from("test"), Box::new(serde_json::from_str::<DmxArray>(array_json).unwrap())).unwrap();
+
+        run_node(array_manager.get_usage_effect_runtime(&EffectUsage::On, "test", None, defs::DIMMING_AMOUNT_MAX).unwrap(), &mut artnet_manager);
+        assert_eq!(
+            artnet_manager.set_channel_log,
+            [
+                ChannelValue { channel: ChannelDefinition::Single(10), value: DimmerValue::Single(100) },
+                ChannelValue { channel: ChannelDefinition::Single(10), value: DimmerValue::Single(200) },
+            ]
+        );
+        assert_eq!(artnet_manager.get_channel("0", &ChannelDefinition::Rgb(10, 11, 12)).unwrap().value, DimmerValue::Rgb(200, 0, 0));
     }
 
     fn run_node(mut node: Box<dyn EffectNodeRuntime>, artnet_manager: &mut ArtnetManager) {
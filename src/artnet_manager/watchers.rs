@@ -0,0 +1,117 @@
+// Watchers publish a message to an arbitrary topic when a channel value crosses a threshold (e.g. publish to
+// AV/screen/down once the projector lift channel reaches 255)
+//
+// Watchers are edge triggered, a watcher fires once when its condition becomes true and can fire again only after the
+// value moved back past the threshold by more than the hysteresis (so a value oscillating around the threshold does
+// not fire repeatedly)
+
+use error_stack::{Result, ResultExt};
+use std::{str::FromStr, sync::Arc};
+
+use super::ArtnetError;
+use crate::defs::WatcherDefinition;
+use crate::dmx::ChannelDefinition;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum WatcherCondition {
+    AtLeast(u8),    // >=n (>n is parsed as >=n+1)
+    AtMost(u8),     // <=n (<n is parsed as <=n-1)
+    Equal(u8),      // ==n
+}
+
+impl FromStr for WatcherCondition {
+    type Err = ArtnetError;
+
+    /// Parse >=n, >n, <=n, <n or ==n
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || ArtnetError::InvalidWatcherCondition(s.to_string());
+        let s = s.trim();
+
+        let (operator, value) = match s.find(|c: char| !matches!(c, '<' | '>' | '=')) {
+            Some(i) => (&s[..i], s[i..].trim().parse::<u8>().map_err(|_| invalid())?),
+            None => return Err(invalid()),
+        };
+
+        match operator {
+            ">=" => Ok(WatcherCondition::AtLeast(value)),
+            ">" => value.checked_add(1).map(WatcherCondition::AtLeast).ok_or_else(invalid),
+            "<=" => Ok(WatcherCondition::AtMost(value)),
+            "<" => value.checked_sub(1).map(WatcherCondition::AtMost).ok_or_else(invalid),
+            "==" => Ok(WatcherCondition::Equal(value)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl WatcherCondition {
+    pub(super) fn is_met(&self, value: u8) -> bool {
+        match *self {
+            WatcherCondition::AtLeast(threshold) => value >= threshold,
+            WatcherCondition::AtMost(threshold) => value <= threshold,
+            WatcherCondition::Equal(threshold) => value == threshold,
+        }
+    }
+
+    // Value moved away from the threshold by more than the hysteresis, so the watcher can fire again
+    fn is_cleared(&self, value: u8, hysteresis: u8) -> bool {
+        match *self {
+            WatcherCondition::AtLeast(threshold) => (value as u16 + hysteresis as u16) < threshold as u16,
+            WatcherCondition::AtMost(threshold) => value as u16 > threshold as u16 + hysteresis as u16,
+            WatcherCondition::Equal(threshold) => value.abs_diff(threshold) > hysteresis,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct Watcher {
+    pub(super) universe_id: Arc<str>,
+    pub(super) channel: u16,
+    condition: WatcherCondition,
+    hysteresis: u8,
+    pub(super) topic: Arc<str>,
+    pub(super) payload: Arc<str>,
+    triggered: bool,        // Fired and the value did not move back past the threshold since
+}
+
+impl Watcher {
+    pub(super) fn new(name: &str, definition: WatcherDefinition) -> Result<Watcher, ArtnetError> {
+        let into_context = || ArtnetError::Context(format!("Setting watcher {name}"));
+
+        let channel = match definition.channel.parse::<ChannelDefinition>().change_context_lazy(into_context)? {
+            ChannelDefinition::Single(channel) => channel,
+            _ => return Err(ArtnetError::InvalidWatcherChannel(definition.channel)).change_context_lazy(into_context),
+        };
+
+        if definition.topic.is_empty() || definition.topic.contains(['+', '#']) {
+            return Err(ArtnetError::InvalidWatcherTopic(definition.topic)).change_context_lazy(into_context);
+        }
+
+        Ok(Watcher {
+            universe_id: definition.universe_id,
+            channel,
+            condition: definition.condition.parse::<WatcherCondition>().change_context_lazy(into_context)?,
+            hysteresis: definition.hysteresis,
+            topic: Arc::from(definition.topic),
+            payload: Arc::from(definition.payload),
+            triggered: false,
+        })
+    }
+
+    // A condition that is already met when the watcher is defined does not fire until it is cleared
+    pub(super) fn set_initial_value(&mut self, value: u8) {
+        self.triggered = self.condition.is_met(value);
+    }
+
+    // Returns true if the watcher fires (its condition became true)
+    pub(super) fn update(&mut self, value: u8) -> bool {
+        if self.triggered {
+            if self.condition.is_cleared(value, self.hysteresis) {
+                self.triggered = false;
+            }
+            false
+        } else {
+            self.triggered = self.condition.is_met(value);
+            self.triggered
+        }
+    }
+}
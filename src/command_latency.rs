@@ -0,0 +1,112 @@
+// Time from receiving a command (Publish packet) until it is handled. For On, Off, Dim and Toggle this includes the
+// acknowledge of the started effect by the artnet manager. Only the last LATENCY_SAMPLES of each command are kept, so
+// the percentiles follow recent behavior
+
+use std::{collections::{HashMap, VecDeque}, sync::Arc, time::Duration};
+
+use crate::defs::CommandLatencyEntry;
+
+pub const LATENCY_SAMPLES: usize = 100;
+
+#[derive(Debug, Default)]
+pub struct CommandLatency {
+    samples: HashMap<Arc<str>, VecDeque<Duration>>,
+}
+
+// Nearest rank percentile of sorted samples
+fn percentile(sorted_samples: &[Duration], percent: usize) -> Duration {
+    let rank = (sorted_samples.len() * percent).div_ceil(100).max(1);
+    sorted_samples[rank - 1]
+}
+
+fn to_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl CommandLatency {
+    pub fn add(&mut self, command: &str, latency: Duration) {
+        let samples = self.samples.entry(Arc::from(command)).or_default();
+
+        if samples.len() == LATENCY_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(latency);
+    }
+
+    pub fn reset(&mut self) {
+        self.samples.clear();
+    }
+
+    // Entries sorted by command
+    pub fn get_entries(&self) -> Vec<CommandLatencyEntry> {
+        let mut entries = self
+            .samples
+            .iter()
+            .filter(|(_, samples)| !samples.is_empty())
+            .map(|(command, samples)| {
+                let mut sorted_samples = samples.iter().copied().collect::<Vec<_>>();
+                sorted_samples.sort();
+
+                CommandLatencyEntry {
+                    command: command.clone(),
+                    samples: sorted_samples.len(),
+                    p50_ms: to_ms(percentile(&sorted_samples, 50)),
+                    p95_ms: to_ms(percentile(&sorted_samples, 95)),
+                    max_ms: to_ms(sorted_samples[sorted_samples.len() - 1]),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        entries.sort_by(|a, b| a.command.cmp(&b.command));
+        entries
+    }
+}
+
+#[cfg(test)]
+mod test_command_latency {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut latency = CommandLatency::default();
+
+        // 1..=20 ms in shuffled order
+        for i in [7, 1, 20, 13, 2, 19, 8, 14, 3, 18, 9, 15, 4, 17, 10, 16, 5, 12, 6, 11] {
+            latency.add("On", ms(i));
+        }
+        latency.add("Off", ms(30));
+
+        let entries = latency.get_entries();
+        assert_eq!(entries.iter().map(|entry| entry.command.as_ref()).collect::<Vec<_>>(), vec!["Off", "On"]);
+
+        let on = &entries[1];
+        assert_eq!((on.samples, on.p50_ms, on.p95_ms, on.max_ms), (20, 10.0, 19.0, 20.0));
+
+        // A single sample is every percentile
+        let off = &entries[0];
+        assert_eq!((off.samples, off.p50_ms, off.p95_ms, off.max_ms), (1, 30.0, 30.0, 30.0));
+
+        latency.reset();
+        assert!(latency.get_entries().is_empty());
+    }
+
+    #[test]
+    fn test_rolling_window() {
+        let mut latency = CommandLatency::default();
+
+        // A slow start is dropped once enough newer samples are added
+        latency.add("On", ms(500));
+        for _ in 0..LATENCY_SAMPLES - 1 {
+            latency.add("On", ms(2));
+        }
+        assert_eq!(latency.get_entries()[0].max_ms, 500.0);
+
+        latency.add("On", ms(4));
+        let on = &latency.get_entries()[0];
+        assert_eq!((on.samples, on.p50_ms, on.p95_ms, on.max_ms), (LATENCY_SAMPLES, 2.0, 2.0, 4.0));
+    }
+}
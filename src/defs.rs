@@ -1,42 +1,278 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::net::IpAddr;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
 use std::str::FromStr;
 use std::sync::Arc;
 
-#[derive(Debug, Deserialize, Clone)]
+use crate::dmx::ChannelType;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UniverseDefinition {
     pub description: String,
 
     pub controller: IpAddr,
-    pub net: u8,
-    pub subnet: u8,
-    pub universe: u8,
-    pub channels: u16,
+
+    // If all are omitted, they are derived from a numeric universe ID (the Art-Net port address, e.g. universe "18" is
+    // net 0, subnet 1, universe 2)
+    #[serde(default)]
+    pub net: Option<u8>,
+    #[serde(default)]
+    pub subnet: Option<u8>,
+    #[serde(default)]
+    pub universe: Option<u8>,
+
+    pub channels: u16,          // 1 to 512 (an odd count is rounded up since DMX data length must be even)
+
+    // Send frames of this length (zero padded beyond channels), for nodes that expect the same frame length on all
+    // their ports. Channels beyond channels still cannot be set
+    #[serde(default)]
+    pub pad_to: Option<u16>,
 
     #[serde(default)]
     pub log: bool,              // Log SetChannel calls for testing (applicable only if #cfg(test)
 
     #[serde(default)]
     pub disable_send: bool,     // Disable sending DMX packets for testing
+
+    #[serde(default)]
+    pub allow_duplicate_port_address: bool,     // Allow other universes with the same controller and net/subnet/universe
+
+    #[serde(default)]
+    pub idle: Option<UniverseIdleDefinition>,   // Values applied to channels that were not set for a while
+
+    #[serde(default)]
+    pub max_delta_per_tick: Option<u8>,     // Channels change at most this much per tick (overrides the service setting)
+
+    #[serde(default)]
+    pub initial: Option<UniverseInitialDefinition>,     // Channel values when the universe is added (e.g. the node's power-on scene)
+
+    #[serde(default)]
+    pub initial_b64: Option<String>,        // Base64 channel values from channel 0 when the universe is added (instead of initial)
+
+    // Send at most every send_divisor ticks (e.g. 4 sends 5 frames per second with the default 50ms tick). For
+    // universes that need a higher frame rate, lower the service tick_ms and give the other universes a divisor.
+    // Effects still run every tick, only the sending of packets is paced
+    #[serde(default)]
+    pub send_divisor: Option<u32>,
+
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHoursDefinition>,     // Daily window in which nothing is sent (e.g. exterior lights at night)
+
+    #[serde(default)]
+    pub keepalive_burst: Option<KeepaliveBurstDefinition>,     // Refresh of the unmodified universe (instead of every 4 seconds)
+}
+
+// For nodes that revert to their own show when they miss packets: once the universe was not modified for
+// every_seconds, its current frame is sent as a burst of `packets` identical packets (same sequence) one tick apart. A
+// modification sends the new frame as usual and ends the burst
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KeepaliveBurstDefinition {
+    pub every_seconds: u64,
+    pub packets: u32,
+}
+
+// Daily window (from inclusive, to exclusive) in which the universe output is all zeros. A final all-zero frame is sent
+// when the window starts and nothing is sent until it ends, while effects and commands keep changing the channels (sent
+// when the window ends). A window whose to is earlier than from spans midnight (e.g. 23:00 to 06:30)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuietHoursDefinition {
+    pub from: String,           // HH:MM
+    pub to: String,             // HH:MM
+    #[serde(default)]
+    pub utc_offset: Option<String>,     // Times are in this offset from UTC (e.g. +02:00) instead of the local time
+}
+
+// Once none of the channels was set (by an effect or Set command) for after_seconds, they are set to target
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UniverseIdleDefinition {
+    pub after_seconds: u64,
+    pub target: String,         // TargetValue syntax (e.g. s(10))
+    pub channels: String,       // Channel definitions (e.g. s:1,s:2,rgb:3)
+}
+
+// Channels are set to target when the universe is added (before anything is sent), other channels are 0
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UniverseInitialDefinition {
+    pub target: String,         // TargetValue syntax (e.g. s(10))
+    pub channels: String,       // Channel definitions (e.g. s:1,s:2,rgb:3)
+}
+
+// Published to: DMX/Universe/<universe_id>/SendStatus
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct UniverseSendStatus {
+    pub reachable: bool,                    // False once consecutive_failures reached the unreachable threshold
+    pub last_send_time: Option<chrono::DateTime<chrono::Utc>>,     // Last successful send
+    pub consecutive_failures: usize,
+    pub total_failures: usize,
+    pub send_disabled: bool,                // Packets are not sent (disable_send, see DMX/Command/EnableSend)
+    pub quiet: bool,                        // Within the universe quiet_hours, packets are not sent
+}
+
+// Sent to: DMX/Schedule/<name> (see scheduler.rs)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduleDefinition {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,               // [second] minute hour day-of-month month day-of-week
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_at: Option<String>,           // HH:MM
+    pub command: Arc<str>,                  // Command to run (as DMX/Command/<command>)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<serde_json::Value>,      // Command payload
+}
+
+// Sent to: DMX/Monitor/<name> (see artnet_manager/monitors.rs)
+#[derive(Deserialize, Debug, Clone)]
+pub struct MonitorDefinition {
+    pub universe_id: Arc<str>,
+    pub channel: String,                    // Single channel (n or s:n)
+    pub topic: String,                      // The channel value (0-255) is published to this topic
+    #[serde(default)]
+    pub min_interval_ms: u64,               // Minimum time between two publishes
+    #[serde(default = "default_monitor_only_on_change")]
+    pub only_on_change: bool,               // Otherwise the value is published every min_interval_ms even if it did not change
+}
+
+fn default_monitor_only_on_change() -> bool {
+    true
+}
+
+// Sent to: DMX/Watcher/<name> (see artnet_manager/watchers.rs)
+#[derive(Deserialize, Debug, Clone)]
+pub struct WatcherDefinition {
+    pub universe_id: Arc<str>,
+    pub channel: String,                    // Single channel (n or s:n)
+    pub condition: String,                  // >=n, >n, <=n, <n or ==n
+    #[serde(default = "default_watcher_hysteresis")]
+    pub hysteresis: u8,                     // Value must move back past the threshold by more than this to fire again
+    pub topic: String,                      // Published to when the condition becomes true
+    #[serde(default)]
+    pub payload: String,
+}
+
+fn default_watcher_hysteresis() -> u8 {
+    5
+}
+
+// Sent to: DMX/UniverseGroup/<name> (empty payload removes the group)
+//
+// Members must be defined universes when the group is defined. If a member universe is removed later, commands on the
+// group fail (without changing any universe) until the universe is defined again or the group is redefined
+#[derive(Deserialize, Debug, Clone)]
+pub struct UniverseGroupDefinition {
+    pub universes: Vec<Arc<str>>,
+}
+
+// Universe commands (Blackout, UniverseStatus) apply to a universe or to all the universes of a universe group
+#[derive(Debug, Clone)]
+pub enum UniverseTarget {
+    Universe(Arc<str>),
+    Group(Arc<str>),
+}
+
+impl std::fmt::Display for UniverseTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UniverseTarget::Universe(universe_id) => write!(f, "universe {universe_id}"),
+            UniverseTarget::Group(group) => write!(f, "universe group {group}"),
+        }
+    }
+}
+
+// Sent to: DMX/Command/UniverseStatus
+#[derive(Deserialize, Debug)]
+pub struct UniverseStatusCommandParameters {
+    pub universe_id: Option<Arc<str>>,
+    pub universe_group: Option<Arc<str>>,   // Status of each universe of the group (used if no universe_id)
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ValueDefinition {
-    pub value: Arc<str>,
+    pub value: SymbolValue,
+    #[serde(default)]
+    pub retrigger: bool,        // When the value changes, start again the last effect of arrays whose effect read it
 }
 
 pub type DimmingAmount = usize;
 pub const DIMMING_AMOUNT_MAX: DimmingAmount = 1000;
 
-pub type SymbolTable = HashMap<Arc<str>, String>;
+// Sent to: DMX/DimmingPreset/<name> (empty payload removes the preset)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DimmingPresetDefinition {
+    pub amount: DimmingAmount,
+}
+
+// Command dimming amount, either a number or the name of a dimming preset (resolved before the command is applied)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum DimmingAmountOrPreset {
+    Amount(DimmingAmount),
+    Preset(Arc<str>),
+}
+
+// Value of DMX/Value/<name>, command values and array default values. Expanded into strings as its text (so 10 and
+// "10" are the same), number parameters use numbers directly
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum SymbolValue {
+    Boolean(bool),
+    Number(serde_json::Number),
+    Text(String),
+}
+
+impl SymbolValue {
+    // Value and its type for error messages (e.g. the string "fast")
+    pub fn describe(&self) -> String {
+        match self {
+            SymbolValue::Boolean(value) => format!("the boolean {value}"),
+            SymbolValue::Number(value) => format!("the number {value}"),
+            SymbolValue::Text(value) => format!("the string {value:?}"),
+        }
+    }
+}
+
+impl std::fmt::Display for SymbolValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SymbolValue::Boolean(value) => write!(f, "{value}"),
+            SymbolValue::Number(value) => write!(f, "{value}"),
+            SymbolValue::Text(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl From<&str> for SymbolValue {
+    fn from(value: &str) -> Self {
+        SymbolValue::Text(value.to_string())
+    }
+}
+
+pub type SymbolTable = HashMap<Arc<str>, SymbolValue>;
+
+// Incremented whenever an array is (re)defined or removed, effects built from an older definition are not started
+pub type ArrayEpoch = u64;
+
+// Ids are used as MQTT topic levels (e.g. DMX/Array/<array_id>) so they cannot contain topic separator or wildcards
+const INVALID_ID_CHARACTERS: &[char] = &['/', '+', '#'];
+
+pub fn validate_id(id: &str) -> Result<(), String> {
+    if id.trim().is_empty() {
+        return Err("id must not be empty or whitespace only".to_string());
+    }
+
+    match id.chars().find(|c| INVALID_ID_CHARACTERS.contains(c)) {
+        Some(c) => Err(format!("id must not contain '{c}'")),
+        None => Ok(()),
+    }
+}
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DmxArray {
-    pub description: String,
-    
-    pub universe_id: String,        // Default universe to use
+    #[serde(default)]
+    pub description: String,        // Defaults to the array id
+
+    #[serde(default)]
+    pub universe_id: String,        // Default universe to use (defaults to the service default universe)
     pub lights: HashMap<String, String>,
     #[serde(default="default_on_effect_id")]
     pub on: Arc<str>,
@@ -48,6 +284,68 @@ pub struct DmxArray {
     pub effects: HashMap<String, EffectNodeDefinition>,
     #[serde(default)]
     pub default_values: SymbolTable,
+    #[serde(default)]
+    pub strict_values: bool,    // Value defaults (`name=default`) can only be used for values declared in default_values
+    #[serde(default)]
+    pub limits: HashMap<String, String>,   // Light group -> maximum value (TargetValue syntax)
+    #[serde(default)]
+    pub group_dimming: HashMap<String, DimmingAmount>,     // Light group -> dimming factor (0-1000) applied on top of the effect dimming amount
+    #[serde(default)]
+    pub max_lights_nesting: Option<usize>,  // Maximum depth of nested light groups (@group) references
+    #[serde(default)]
+    pub linked_effects: Vec<Arc<str>>,      // Ids of running effects (e.g. inline effect_id) to stop when the array is turned Off or stopped
+    #[serde(default, alias = "dimmer_level")]
+    pub default_dimming_amount: Option<DimmingAmount>,     // Used by On/Dim commands that do not specify dimming_amount (dimmer_level in older definitions)
+    #[serde(default="default_startup_effect_id")]
+    pub startup: Arc<str>,      // Effect turned On when the array is defined ("none" for no startup effect)
+    #[serde(default)]
+    pub startup_dimming_amount: Option<DimmingAmount>,     // Dimming amount of the startup effect (defaults to DIMMING_AMOUNT_MAX)
+    #[serde(default="default_startup_on_redefine")]
+    pub startup_on_redefine: bool,      // Run the startup effect also when an already defined array is redefined
+    #[serde(default="default_strict")]
+    pub strict: bool,       // Reject the definition if it has problems (otherwise it is added and the problems are reported as warnings)
+    #[serde(default)]
+    pub pre: Vec<PreCommand>,       // Commands run (in order) on other arrays before the effect of an On command is started
+}
+
+// Command on another array run before an array is turned On (see DmxArray::pre)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PreCommand {
+    pub array_id: Arc<str>,
+    pub usage: EffectUsage,
+    pub dimming_amount: Option<DimmingAmountOrPreset>,
+    #[serde(default)]
+    pub wait: bool,     // Wait for the started effect to complete before running the next command
+}
+
+// Sent to: DMX/Array/<id> instead of an array definition. The array definition is the template (DMX/ArrayTemplate/<name>,
+// a DmxArray document whose strings may contain {parameter} or {parameter+n} placeholders) with the parameters substituted
+#[derive(Debug, Deserialize, Clone)]
+pub struct ArrayTemplateInstance {
+    pub template: Arc<str>,
+    #[serde(default)]
+    pub parameters: BTreeMap<String, TemplateParameter>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum TemplateParameter {
+    Number(i64),
+    Text(String),
+}
+
+pub const NO_STARTUP_EFFECT: &str = "none";
+
+fn default_startup_effect_id() -> Arc<str> {
+    Arc::from(NO_STARTUP_EFFECT)
+}
+
+fn default_startup_on_redefine() -> bool {
+    true
+}
+
+fn default_strict() -> bool {
+    true
 }
 
 fn default_on_effect_id() -> Arc<str> {
@@ -69,20 +367,70 @@ pub struct TargetValue {
     pub tri_white: Option<(u8, u8, u8)>,
 }
 
-#[derive(Deserialize, Debug)]
+// Target value component, either absolute (n) or relative (+n/-n) to the current channel value
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TargetComponent {
+    Absolute(u8),
+    Relative(i16),
+}
+
+// Target value whose components may be relative to the current channel value (e.g. s(+20);rgb(-10,-10,-10))
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct RelativeTargetValue {
+    pub single: Option<TargetComponent>,
+    pub rgb: Option<(TargetComponent, TargetComponent, TargetComponent)>,
+    pub tri_white: Option<(TargetComponent, TargetComponent, TargetComponent)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum NumberOrVariable {
     Number(usize),
     Variable(String),
 }
 
-#[derive(Deserialize, Debug, Clone, Copy)]
+impl std::fmt::Display for NumberOrVariable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NumberOrVariable::Number(number) => write!(f, "{number}"),
+            NumberOrVariable::Variable(variable) => write!(f, "{variable}"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum EffectUsage {
     On,
     Off,
     Dim,
 }
 
+// Last command applied to an array
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArrayState {
+    pub usage: EffectUsage,
+    pub dimming_amount: DimmingAmount,     // Resolved dimming amount (command, array default or maximum)
+}
+
+// Array effect started again since a retrigger value it read has changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetriggerEffect {
+    pub array_id: Arc<str>,
+    pub usage: EffectUsage,
+    pub effect_id: Option<Arc<str>>,
+    pub dimming_amount: Option<DimmingAmount>,     // As given by the command (None for the array default)
+}
+
+impl std::fmt::Display for EffectUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EffectUsage::On => write!(f, "On"),
+            EffectUsage::Off => write!(f, "Off"),
+            EffectUsage::Dim => write!(f, "Dim"),
+        }
+    }
+}
+
 impl FromStr for EffectUsage {
     type Err = String;
 
@@ -95,9 +443,19 @@ impl FromStr for EffectUsage {
         }
     }
 }
+// Sent to: DMX/Effect/<effect_id>, the effect node with optional values used by this effect when they are not given by
+// the command, the array or the global values (e.g. { "type": "fade", ..., "defaults": { "ticks": 20 } })
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EffectDefinition {
+    #[serde(flatten)]
+    pub node: EffectNodeDefinition,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub defaults: SymbolTable,
+}
+
 /// Effect modes
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 pub enum EffectNodeDefinition {
@@ -105,47 +463,247 @@ pub enum EffectNodeDefinition {
     Parallel(ParallelEffectNodeDefinition),
     Delay(DelayEffectNodeDefinition),
     Fade(FadeEffectNodeDefinition),
+    Hold(HoldEffectNodeDefinition),
+    WaitFor(WaitForEffectNodeDefinition),
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SequenceEffectNodeDefinition {
     pub nodes: Vec<EffectNodeDefinition>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ParallelEffectNodeDefinition {
     pub nodes: Vec<EffectNodeDefinition>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DelayEffectNodeDefinition {
     pub ticks: NumberOrVariable,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FadeEffectNodeDefinition {
     pub lights: String,
     pub ticks: NumberOrVariable,
     pub target: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,   // Start value (TargetValue syntax) instead of the current channel value
     #[serde(default)]
     pub no_dimming: bool,    
 }
 
+// Set lights to target (snap, or fade over attack_ticks) and keep them there for ticks
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HoldEffectNodeDefinition {
+    pub lights: String,
+    pub ticks: NumberOrVariable,
+    pub target: String,
+    #[serde(default = "default_attack_ticks")]
+    pub attack_ticks: NumberOrVariable,
+    #[serde(default)]
+    pub no_dimming: bool,
+}
+
+fn default_attack_ticks() -> NumberOrVariable {
+    NumberOrVariable::Number(0)
+}
+
+// Wait until value_name equals `equals` (or until it changes if equals is not given), values are array values set by
+// On commands and global values (DMX/Value/<name>)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WaitForEffectNodeDefinition {
+    pub value_name: Arc<str>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equals: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ticks: Option<NumberOrVariable>,
+    #[serde(default)]
+    pub on_timeout: WaitForTimeout,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WaitForTimeout {
+    #[default]
+    Continue,   // Continue with the next node
+    Fail,       // Stop the effect with an error
+}
+
 // Commands
 //
+// Any command payload may include "correlation_id", it is included in the ack (DMX/Ack) or in the error (DMX/Error)
+// published for the command so it can be paired with its outcome. For a batch Set, the first entry that has
+// correlation_id is used
+#[derive(Deserialize, Debug, Default)]
+pub struct CommandCorrelation {
+    pub correlation_id: Option<Arc<str>>,
+}
+
+// Commands delivered as retained messages (e.g. a stray retained DMX/Command/On) are ignored unless the payload has
+// "allow_retained": true (or the service runs with --allow-retained-commands). For a batch Set, any entry may allow it
+#[derive(Deserialize, Debug, Default)]
+pub struct CommandRetainPolicy {
+    #[serde(default)]
+    pub allow_retained: bool,
+}
+
+// Published to: DMX/Ack when a command with correlation_id succeeded
+#[derive(Serialize, Debug)]
+pub struct CommandAck {
+    pub time: String,
+    pub command: Arc<str>,
+    pub correlation_id: Arc<str>,
+}
+
 // Sent to:  DMX/Command/On
 // or to: DMX/Command/Off
+// or to: DMX/Command/Toggle (On if the last command on the array was Off or unknown, otherwise Off)
 #[derive(Deserialize, Debug)]
 pub struct OnOffCommandParameters {
-    pub array_id: Arc<str>,
+    pub array_id: Option<Arc<str>>,
     pub effect_id: Option<Arc<str>>,
-    pub dimming_amount: Option<DimmingAmount>,
+    pub dimming_amount: Option<DimmingAmountOrPreset>,
     pub values: Option<SymbolTable>,
+    pub lights: Option<String>,                     // Lights ($universe,channel...) of inline effect (used if no array_id), or light group (@group) of array_id
+    pub effect: Option<EffectNodeDefinition>,       // Inline effect applied to lights, "@all" refers to lights
+    pub origin: Option<Arc<str>>,                   // Who sent the command (e.g. automation name), kept with the started effect
+    #[serde(default)]
+    pub when: CommandWhen,
+    #[serde(default)]
+    pub instant: bool,      // Off only: set the lights to zero on the next tick instead of running the off effect
+    pub max_ticks: Option<usize>,       // Stop the effect after this number of ticks (overrides the max_effect_ticks setting)
+    #[serde(default)]
+    pub no_max_ticks: bool,     // The effect runs until stopped (e.g. flicker), max_ticks and max_effect_ticks do not apply
 }
 
+impl OnOffCommandParameters {
+    pub fn get_max_ticks(&self) -> EffectMaxTicks {
+        match (self.no_max_ticks, self.max_ticks) {
+            (true, _) => EffectMaxTicks::Unlimited,
+            (false, Some(max_ticks)) => EffectMaxTicks::Ticks(max_ticks),
+            (false, None) => EffectMaxTicks::Default,
+        }
+    }
+}
+
+// Ticks after which an active effect is stopped (a safeguard against effects running much longer than intended)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EffectMaxTicks {
+    #[default]
+    Default,        // The max_effect_ticks setting (if set)
+    Ticks(usize),
+    Unlimited,
+}
+
+impl EffectMaxTicks {
+    pub fn get_limit(&self, max_effect_ticks: Option<usize>) -> Option<usize> {
+        match self {
+            EffectMaxTicks::Default => max_effect_ticks,
+            EffectMaxTicks::Ticks(max_ticks) => Some(*max_ticks),
+            EffectMaxTicks::Unlimited => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandWhen {
+    #[default]
+    Immediate,      // Replace the active effect (the new effect starts from the current light state)
+    AfterCurrent,   // Start when the active effect completes (replacing an effect queued before)
+}
+
+// Sent to: DMX/Command/Adjust (e.g. by a dimmer dial). The dimming amount of the last command on the array is changed
+// by delta (clamped to 0..1000) and an effect is run at the new amount. An array that is Off is adjusted from 0
+#[derive(Deserialize, Debug)]
+pub struct AdjustCommandParameters {
+    pub array_id: Arc<str>,
+    pub delta: i64,
+    #[serde(default)]
+    pub reapply: AdjustReapply,
+    pub origin: Option<Arc<str>>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AdjustReapply {
+    #[default]
+    Dim,        // Run the Dim effect of the array
+    Last,       // Run the effect of the last command (On if the array is Off)
+}
+
+// Sent to: DMX/Command/Rename. The array (with its values, state and running effects) or the universe (with its channel
+// data) is moved to the new ID. During alias_seconds commands using the old ID are redirected with a warning
+#[derive(Deserialize, Debug)]
+pub struct RenameCommandParameters {
+    pub kind: RenameKind,
+    pub from: Arc<str>,
+    pub to: Arc<str>,
+    pub alias_seconds: Option<u64>,
+    pub origin: Option<Arc<str>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum RenameKind {
+    Array,
+    Universe,
+}
+
+impl RenameKind {
+    // Names of the command parameters holding an ID of this kind
+    pub fn id_parameters(&self) -> &'static [&'static str] {
+        match self {
+            RenameKind::Array => &["array_id"],
+            RenameKind::Universe => &["universe_id", "from_universe", "to_universe"],
+        }
+    }
+}
+
+impl std::fmt::Display for RenameKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenameKind::Array => write!(f, "array"),
+            RenameKind::Universe => write!(f, "universe"),
+        }
+    }
+}
+
+// Sent to: DMX/Command/Reset. All effects are stopped, universes, arrays, effects, schedules and values are removed and the retained
+// definitions are received again from the broker
+#[derive(Deserialize, Debug)]
+pub struct ResetCommandParameters {
+    pub confirm: Option<String>,        // Must be RESET_CONFIRMATION
+    pub origin: Option<Arc<str>>,
+}
+
+pub const RESET_CONFIRMATION: &str = "RESET";
+
 #[derive(Deserialize, Debug)]
 pub struct StopCommandParameters {
     pub array_id: Arc<str>,
+    #[serde(default)]
+    pub scope: StopScope,
+    pub usage: Option<EffectUsage>,     // Required for "usage" scope
+    pub origin: Option<Arc<str>>,       // Who sent the command (logged)
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StopScope {
+    #[default]
+    Exact,      // Effect whose id is array_id
+    All,        // The array effect and its light group effects (array_id@group)
+    Usage,      // Effects of the array (as for All) that were started for the given usage (On, Off or Dim)
+}
+
+// Sent to: DMX/Command/Pause or DMX/Command/Resume
+#[derive(Deserialize, Debug)]
+pub struct PauseCommandParameters {
+    pub array_id: Option<Arc<str>>,
+    #[serde(default)]
+    pub all: bool,      // Pause (or resume) all active effects
 }
 
 #[derive(Deserialize, Debug)]
@@ -153,5 +711,419 @@ pub struct SetChannelsParameters {
     pub universe_id: String,
     pub channels: String,
     pub target: String,
+    pub dimming_amount: Option<DimmingAmountOrPreset>,
+    pub origin: Option<Arc<str>>,       // Who sent the command (logged)
+}
+
+// Sent to: DMX/Command/Alias
+#[derive(Deserialize, Debug)]
+pub struct AliasCommandParameters {
+    pub universe_id: String,
+    pub from: String,
+    pub to: Option<String>,     // Omitted (or same as from) removes the alias of from
+}
+
+// Sent to: DMX/Command/SetFrame to set consecutive channels of a universe from raw bytes (e.g. pixel mapping tools)
+#[derive(Deserialize, Debug)]
+pub struct SetFrameCommandParameters {
+    pub universe_id: String,
+    #[serde(default)]
+    pub offset: u16,            // Channel of the first byte
+    pub data_b64: String,       // Channel values (base64)
     pub dimming_amount: Option<DimmingAmount>,
+    #[serde(default)]
+    pub force: bool,            // Set the channels even if an active effect is using the universe
+    pub origin: Option<Arc<str>>,
+}
+
+// Sent to: DMX/Command/Copy to copy the current values of channels to another universe (or other channels of the same
+// universe). Channel n is copied to channel n + to_offset of to_universe
+#[derive(Deserialize, Debug)]
+pub struct CopyCommandParameters {
+    pub from_universe: String,
+    pub to_universe: String,
+    pub channels: Option<String>,       // Channels to copy (e.g. rgb:1,s:7), keeping their type
+    #[serde(default)]
+    pub all: bool,                      // Copy all the channels of from_universe (instead of channels)
+    #[serde(default)]
+    pub to_offset: i32,
+    pub origin: Option<Arc<str>>,
+}
+
+// Sent to: DMX/Command/Set either a single SetChannelsParameters object or an array of them (applied all or nothing)
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum SetChannelsCommandParameters {
+    Single(SetChannelsParameters),
+    Batch(Vec<SetChannelsParameters>),
+}
+
+// Sent to: DMX/Effect/<effect_id> to remove an effect even if arrays are using it
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RemoveEffectParameters {
+    pub force: bool,
+}
+
+// Sent to: DMX/Fixture/<name> (empty payload removes the fixture)
+// Template of a light whose channels are not contiguous, array lights use it as name:base (e.g. "par56:100")
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureDefinition {
+    #[serde(rename = "type")]
+    pub channel_type: ChannelType,
+    pub offsets: Vec<u16>,     // Channel offsets from the base channel (one for s, three for rgb and w)
+}
+
+// Sent to: DMX/Command/Blackout
+#[derive(Deserialize, Debug)]
+pub struct BlackoutCommandParameters {
+    pub universe_id: Option<Arc<str>>,
+    pub universe_group: Option<Arc<str>>,   // Blackout all universes of the group (used if no universe_id)
+    #[serde(default)]
+    pub restore: bool,      // Restore the universe channels that were saved when it was blacked out
+}
+
+// Sent to: DMX/Command/EnableSend (the universe definition disable_send is applied again if the universe is redefined)
+#[derive(Deserialize, Debug)]
+pub struct EnableSendCommandParameters {
+    pub universe_id: Option<Arc<str>>,
+    pub universe_group: Option<Arc<str>>,   // Used if no universe_id
+    pub enable: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EffectStatusCommandParameters {
+    pub array_id: Arc<str>,
+    #[serde(default)]
+    pub verbose: bool,      // Include the description of the running effect node tree
+}
+
+// Published to: DMX/Array/<array_id>/EffectStatus
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct EffectStatus {
+    pub running: bool,
+    pub paused: bool,
+    pub elapsed_ticks: Option<usize>,
+    pub remaining_ticks: Option<usize>,
+    pub remaining_ms: Option<u64>,
+    pub last_command: Option<EffectUsage>,     // Last On/Off/Dim command applied to the array
+    pub last_dimming_amount: Option<DimmingAmount>,
+    pub origin: Option<Arc<str>>,      // Origin of the command that started the running effect
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,   // Running effect node tree and its progress (verbose status)
+}
+
+// Sent to: DMX/Command/EffectStats (empty payload publishes the statistics without resetting them)
+#[derive(Deserialize, Debug, Default)]
+pub struct EffectStatsCommandParameters {
+    #[serde(default)]
+    pub reset: bool,        // Clear the counters after publishing them
+}
+
+// Published to: DMX/EffectStats (one entry for each effect id and usage)
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct EffectStatsEntry {
+    pub effect_id: Arc<str>,
+    pub usage: Option<EffectUsage>,
+    pub started: usize,
+    pub completed: usize,       // Ran until done
+    pub preempted: usize,       // Replaced by another effect with the same id before completing
+    pub average_ticks: Option<f64>,     // Average ticks of the completed runs
+}
+
+// Sent to: DMX/Command/Latency (empty payload publishes the latency without resetting it)
+#[derive(Deserialize, Debug, Default)]
+pub struct LatencyCommandParameters {
+    #[serde(default)]
+    pub reset: bool,        // Clear the samples after publishing them
+}
+
+// Published to: DMX/Latency (one entry for each command, see command_latency.rs)
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CommandLatencyEntry {
+    pub command: Arc<str>,
+    pub samples: usize,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+}
+
+// Sent to: DMX/Command/Verify (see definition_hash.rs for how the hash is computed)
+#[derive(Deserialize, Debug)]
+pub struct VerifyCommandParameters {
+    pub kind: DefinitionKind,
+    pub id: Arc<str>,
+    pub sha256: String,         // Hex SHA-256 of the canonical JSON of the expected definition
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DefinitionKind {
+    Universe,
+    Array,
+    Effect,
+}
+
+impl std::fmt::Display for DefinitionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DefinitionKind::Universe => write!(f, "universe"),
+            DefinitionKind::Array => write!(f, "array"),
+            DefinitionKind::Effect => write!(f, "effect"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifyStatus {
+    Match,
+    Mismatch,
+    NotLoaded,
+}
+
+// Published to: DMX/Verify
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct VerifyResult {
+    pub kind: DefinitionKind,
+    pub id: Arc<str>,
+    pub result: VerifyStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,     // Hash of the definition loaded by the service (None if not loaded)
+}
+
+// Sent to: DMX/Command/Validate to check a definition (as it would be sent to DMX/<kind>/<id>) without storing it
+#[derive(Deserialize, Debug)]
+pub struct ValidateCommandParameters {
+    pub kind: DefinitionKind,
+    pub id: Arc<str>,
+    pub definition: serde_json::Value,
+}
+
+// Published to: DMX/Validate
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct ValidateResult {
+    pub kind: DefinitionKind,
+    pub id: Arc<str>,
+    pub valid: bool,                // No errors, the definition would be accepted
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+// Sent to: DMX/Command/ResolveEffect to find which definition On, Off or Dim of the array would use
+#[derive(Deserialize, Debug)]
+pub struct ResolveEffectCommandParameters {
+    pub array_id: Arc<str>,
+    pub usage: EffectUsage,
+    #[serde(default)]
+    pub effect_id: Option<Arc<str>>,    // The effect given by the command (the array's effect for the usage if omitted)
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EffectSource {
+    Array,          // Effect in the array effects
+    Global,         // Global effect (DMX/Effect/<id>)
+    Default,        // Built-in default effect of the usage
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct EffectResolution {
+    pub usage: EffectUsage,
+    pub effect_id: Arc<str>,
+    pub source: EffectSource,
+    pub summary: String,        // Node tree of the definition (see EffectNodeDefinition::get_summary)
+}
+
+// Published to: DMX/ResolveEffect
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct ResolveEffectResult {
+    pub array_id: Arc<str>,
+    #[serde(flatten)]
+    pub resolution: EffectResolution,
+}
+
+// Sent to: DMX/Command/ExportPatch (empty payload for JSON)
+#[derive(Deserialize, Debug, Default)]
+pub struct ExportPatchCommandParameters {
+    #[serde(default)]
+    pub format: PatchFormat,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PatchFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+// Published to: DMX/Patch
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct Patch {
+    pub universes: BTreeMap<String, Vec<PatchEntry>>,      // Universe ID -> channels sorted by channel and array ID
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct PatchEntry {
+    pub channel: u16,
+    pub array_id: Arc<str>,
+    pub groups: Vec<String>,        // Light groups of the array that include the channel (sorted)
+    pub role: &'static str,         // s, r, g, b, w1, w2 or w3
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conflict: Option<String>,   // Channel is used by other arrays, or with different roles by light groups of the array
+}
+
+impl Patch {
+    // One row per entry: universe,channel,array,groups,role,conflict (groups are separated by spaces)
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("universe,channel,array,groups,role,conflict\n");
+
+        for (universe_id, entries) in self.universes.iter() {
+            for entry in entries.iter() {
+                let fields = [
+                    universe_id.as_str(),
+                    &entry.channel.to_string(),
+                    &entry.array_id,
+                    &entry.groups.join(" "),
+                    entry.role,
+                    entry.conflict.as_deref().unwrap_or_default(),
+                ];
+
+                csv.push_str(&fields.map(get_csv_field).join(","));
+                csv.push('\n');
+            }
+        }
+
+        csv
+    }
+}
+
+fn get_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// Sent to: DMX/Command/Diagnostics
+#[derive(Deserialize, Debug, Default)]
+pub struct DiagnosticsCommandParameters {
+    #[serde(default)]
+    pub include_values: bool,       // Include value strings (otherwise only their length is reported)
+}
+
+// Published (retained) to: DMX/Schema when connecting to the broker and by DMX/Command/Schema (see schema.rs)
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    pub version: String,                    // Service version
+    pub schema_version: u32,                // Incremented on incompatible payload format changes
+    pub commands: Vec<&'static str>,        // DMX/Command/<name>
+    pub effect_node_types: Vec<&'static str>,       // Effect node "type" values
+    pub features: BTreeMap<&'static str, bool>,     // Optional capabilities and whether they are enabled
+}
+
+// Served by the HTTP status endpoint: GET /health
+#[derive(Serialize, Debug)]
+pub struct HealthStatus {
+    pub version: String,
+    pub uptime_seconds: u64,
+    pub broker_connected: bool,
+}
+
+// Published to: DMX/Diagnostics (also served by the HTTP status endpoint: GET /status)
+#[derive(Serialize, Debug)]
+pub struct Diagnostics {
+    pub version: String,
+    pub uptime_seconds: u64,
+    pub arrays: ArrayManagerDiagnostics,
+    pub artnet: ArtnetManagerDiagnostics,
+    pub queues: BTreeMap<String, QueueDiagnostics>,     // Queues from the subscriber to the managers (see manager_channel)
+}
+
+#[derive(Serialize, Debug)]
+pub struct QueueDiagnostics {
+    pub capacity: usize,
+    pub queued: usize,
+    pub sent: u64,
+    pub slow_sends: u64,        // Sends that waited for room in the queue longer than SLOW_SEND_THRESHOLD
+    pub high_water_mark: usize,
+    pub max_send_wait_ms: f64,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum DiagnosticsValue {
+    Value(String),
+    Redacted { length: usize },
+}
+
+impl DiagnosticsValue {
+    pub fn new(value: &str, include_values: bool) -> DiagnosticsValue {
+        if include_values {
+            DiagnosticsValue::Value(value.to_string())
+        } else {
+            DiagnosticsValue::Redacted { length: value.len() }
+        }
+    }
+
+    pub fn from_symbol_table(values: &SymbolTable, include_values: bool) -> BTreeMap<Arc<str>, DiagnosticsValue> {
+        values.iter().map(|(name, value)| (name.clone(), DiagnosticsValue::new(&value.to_string(), include_values))).collect()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ArrayManagerDiagnostics {
+    pub arrays: BTreeMap<Arc<str>, ArrayDiagnostics>,
+    pub global_values: BTreeMap<Arc<str>, DiagnosticsValue>,
+    pub effects: Vec<Arc<str>>,
+    pub compiled_effects: usize,       // Cached compiled effects, and number of runtime nodes instantiated from the cache or compiled
+    pub compiled_effect_hits: u64,
+    pub compiled_effect_misses: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ArrayDiagnostics {
+    pub universe_id: String,
+    pub epoch: ArrayEpoch,
+    pub values: BTreeMap<Arc<str>, DiagnosticsValue>,
+    pub usage_effects: Vec<EffectResolution>,      // Definitions used by On, Off and Dim
+}
+
+#[derive(Serialize, Debug)]
+pub struct ArtnetManagerDiagnostics {
+    pub universes: BTreeMap<String, UniverseDiagnostics>,
+    pub active_effects: BTreeMap<String, ActiveEffectDiagnostics>,
+    pub channel_aliases: BTreeMap<String, BTreeMap<String, String>>,       // Universe ID -> aliased channels -> actual channels
+    pub max_messages_per_tick: usize,       // Most messages handled between two ticks since the service started
+}
+
+#[derive(Serialize, Debug)]
+pub struct UniverseDiagnostics {
+    pub description: String,
+    pub send_disabled: bool,
+    pub channels: u16,
+    pub modified: bool,
+    pub non_modified_ticks: usize,
+    pub sequence: u8,
+    pub send_divisor: u32,
+    pub sent_ticks: u64,        // Ticks on which the universe was sent
+    pub paced_ticks: u64,       // Ticks on which the universe was modified but waited for its send divisor
+}
+
+#[derive(Serialize, Debug)]
+pub struct ActiveEffectDiagnostics {
+    pub elapsed_ticks: usize,
+    pub paused: bool,
+    pub origin: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queued: Option<QueuedEffectDiagnostics>,      // Effect started when this one completes
+}
+
+#[derive(Serialize, Debug)]
+pub struct QueuedEffectDiagnostics {
+    pub usage: Option<EffectUsage>,
+    pub origin: Option<Arc<str>>,
 }
@@ -1,14 +1,84 @@
-use crate::artnet_manager::ArtnetError;
-use crate::defs::{DimmingAmount, TargetValue};
+use crate::defs::{DimmingAmount, DIMMING_AMOUNT_MAX, RelativeTargetValue, TargetComponent, TargetValue};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
+use thiserror::Error;
 
-// #[derive(Debug, PartialEq, Eq, Copy, Clone)]
-// pub enum ChannelType {
-//     Rgb,
-//     TriWhite,
-//     Single,
-// }
+// Errors of parsing channel definitions, dimmer values and targets. The managers convert them into their own error
+// types, adding where the value came from
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum DmxParseError {
+    #[error("Invalid channel '{0}': {1} (expected n, s:n, rgb:n, w:n, rgb:r/g/b or w:w1/w2/w3)")]
+    InvalidChannel(String, &'static str),
+
+    #[error("Invalid dimmer value '{0}' (expected s(n), rgb(r,g,b) or w(w1,w2,w3))")]
+    InvalidDimmerValue(String),
+
+    #[error("Invalid dimmer value '{0}': component {1} is {2} but must be {3}")]
+    DimmerComponentOutOfRange(String, usize, String, &'static str),
+
+    #[error("Ambiguous target value '{0}': a value for this channel type is already given")]
+    AmbiguousTarget(String),
+
+    #[error("Relative target value (+n or -n) is not allowed: '{0}'")]
+    RelativeTargetNotAllowed(String),
+
+    #[error("Invalid light component '{0}' (expected r, g, b, w1, w2 or w3)")]
+    InvalidComponent(String),
+
+    #[error("Entry {1} of '{0}': {2}")]
+    Entry(String, usize, Box<DmxParseError>),
+}
+
+impl DmxParseError {
+    /// Add the (zero based) index of the invalid entry in a separated list. Errors of single entry lists are not
+    /// wrapped since the position adds nothing
+    pub fn at_entry(self, list: &str, separator: char, index: usize) -> DmxParseError {
+        if list.contains(separator) {
+            DmxParseError::Entry(list.to_string(), index + 1, Box::new(self))
+        } else {
+            self
+        }
+    }
+}
+
+/// Parse a separated list (e.g. channels "1,rgb:2,w:5"), the error tells which entry is invalid
+pub fn parse_list<T: FromStr<Err = DmxParseError>>(list: &str, separator: char) -> std::result::Result<Vec<T>, DmxParseError> {
+    list.split(separator)
+        .enumerate()
+        .map(|(index, entry)| entry.parse::<T>().map_err(|e| e.at_entry(list, separator, index)))
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ChannelType {
+    #[serde(rename = "s")]
+    Single,
+    #[serde(rename = "rgb")]
+    Rgb,
+    #[serde(rename = "w")]
+    TriWhite,
+}
+
+impl ChannelType {
+    pub fn get_channel_count(&self) -> usize {
+        match self {
+            ChannelType::Single => 1,
+            ChannelType::Rgb | ChannelType::TriWhite => 3,
+        }
+    }
+}
+
+impl Display for ChannelType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelType::Single => write!(f, "s"),
+            ChannelType::Rgb => write!(f, "rgb"),
+            ChannelType::TriWhite => write!(f, "w"),
+        }
+    }
+}
 
 // #[derive(Debug, PartialEq, Eq)]
 // pub struct ChannelDefinition {
@@ -24,7 +94,7 @@ pub enum ChannelDefinition {
 }
 
 impl FromStr for ChannelDefinition {
-    type Err = ArtnetError;
+    type Err = DmxParseError;
 
     /// Parse a string into a ChannelDefinition
     ///
@@ -34,15 +104,13 @@ impl FromStr for ChannelDefinition {
     /// rgb:n -> ChannelDefinition { channel: n, channel_type: ChannelType::RGB }
     /// w:n -> ChannelDefinition { channel: n, channel_type: ChannelType::TriWhite }
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        fn is_diff(c1: u16, c2: u16, c3: u16) -> std::result::Result<(), ArtnetError> {
+        let invalid = |reason: &'static str| DmxParseError::InvalidChannel(s.trim().to_string(), reason);
+        let is_diff = |c1: u16, c2: u16, c3: u16| {
             if c1 == c2 || c1 == c3 || c2 == c3 {
-                return Err(ArtnetError::InvalidChannelAddress(format!(
-                    "rgb or w individual channel addresses must be different: {}, {}, {}",
-                    c1, c2, c3
-                )));
+                return Err(invalid("rgb or w individual channel addresses must be different"));
             }
             Ok(())
-        }
+        };
         let column = s.find(':');
 
         let (channel_type, channel) = match column {
@@ -55,13 +123,9 @@ impl FromStr for ChannelDefinition {
             .map(|c| {
                 c.trim()
                     .parse::<u16>()
-                    .map_err(|_| ArtnetError::InvalidChannelAddress(s.to_string()))
+                    .map_err(|_| invalid("channel address is not a number (0-65535)"))
             })
-            .collect::<std::result::Result<Vec<u16>, ArtnetError>>()?;
-
-        if channels.is_empty() {
-            return Err(ArtnetError::InvalidChannelAddress(s.to_string()));
-        }
+            .collect::<std::result::Result<Vec<u16>, DmxParseError>>()?;
 
         Ok(match channel_type.to_lowercase().as_str() {
             "rgb" => match channels.len() {
@@ -70,7 +134,7 @@ impl FromStr for ChannelDefinition {
                     is_diff(channels[0], channels[1], channels[2])?;
                     ChannelDefinition::Rgb(channels[0], channels[1], channels[2])
                 }
-                _ => return Err(ArtnetError::InvalidChannelAddress(s.to_string())),
+                _ => return Err(invalid("rgb channel needs 1 or 3 channel addresses")),
             },
             "w" => match channels.len() {
                 1 => ChannelDefinition::TriWhite(channels[0], channels[0]+1, channels[0]+2),
@@ -78,17 +142,101 @@ impl FromStr for ChannelDefinition {
                     is_diff(channels[0], channels[1], channels[2])?;
                     ChannelDefinition::TriWhite(channels[0], channels[1], channels[2])
                 }
-                _ => return Err(ArtnetError::InvalidChannelAddress(s.to_string())),
+                _ => return Err(invalid("w channel needs 1 or 3 channel addresses")),
             },
             "s" => match channels.len() {
                 1 => ChannelDefinition::Single(channels[0]),
-                _ => return Err(ArtnetError::InvalidChannelAddress(s.to_string())),
+                _ => return Err(invalid("s channel needs a single channel address")),
             },
-            _ => return Err(ArtnetError::InvalidChannelAddress(s.to_string())),
+            _ => return Err(invalid("unknown channel type")),
         })
     }
 }
 
+impl ChannelDefinition {
+    pub fn channel_type(&self) -> ChannelType {
+        match self {
+            ChannelDefinition::Single(_) => ChannelType::Single,
+            ChannelDefinition::Rgb(..) => ChannelType::Rgb,
+            ChannelDefinition::TriWhite(..) => ChannelType::TriWhite,
+        }
+    }
+
+    /// Channel definition of a light whose channels are base plus offsets (None if offsets do not match the channel
+    /// type or a channel is out of range)
+    pub fn from_offsets(channel_type: ChannelType, base: u16, offsets: &[u16]) -> Option<ChannelDefinition> {
+        let channels = offsets.iter().map(|offset| base.checked_add(*offset)).collect::<Option<Vec<u16>>>()?;
+
+        match (channel_type, channels.as_slice()) {
+            (ChannelType::Single, [c]) => Some(ChannelDefinition::Single(*c)),
+            (ChannelType::Rgb, [c1, c2, c3]) => Some(ChannelDefinition::Rgb(*c1, *c2, *c3)),
+            (ChannelType::TriWhite, [c1, c2, c3]) => Some(ChannelDefinition::TriWhite(*c1, *c2, *c3)),
+            _ => None,
+        }
+    }
+
+    /// Individual channel addresses (one for s, three for rgb and w)
+    pub fn channels(&self) -> Vec<u16> {
+        match *self {
+            ChannelDefinition::Single(c) => vec![c],
+            ChannelDefinition::Rgb(c1, c2, c3) | ChannelDefinition::TriWhite(c1, c2, c3) => vec![c1, c2, c3],
+        }
+    }
+
+    /// Channel address of a component of an rgb or w light (None if the light has no such component)
+    pub fn get_component(&self, component: ChannelComponent) -> Option<u16> {
+        match (self, component) {
+            (ChannelDefinition::Rgb(r, _, _), ChannelComponent::R) => Some(*r),
+            (ChannelDefinition::Rgb(_, g, _), ChannelComponent::G) => Some(*g),
+            (ChannelDefinition::Rgb(_, _, b), ChannelComponent::B) => Some(*b),
+            (ChannelDefinition::TriWhite(w1, _, _), ChannelComponent::W1) => Some(*w1),
+            (ChannelDefinition::TriWhite(_, w2, _), ChannelComponent::W2) => Some(*w2),
+            (ChannelDefinition::TriWhite(_, _, w3), ChannelComponent::W3) => Some(*w3),
+            _ => None,
+        }
+    }
+}
+
+// Component of an rgb or w light selected in lights (e.g. rgb:10.r), it is set as a single channel
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ChannelComponent {
+    R,
+    G,
+    B,
+    W1,
+    W2,
+    W3,
+}
+
+impl FromStr for ChannelComponent {
+    type Err = DmxParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "r" => Ok(ChannelComponent::R),
+            "g" => Ok(ChannelComponent::G),
+            "b" => Ok(ChannelComponent::B),
+            "w1" => Ok(ChannelComponent::W1),
+            "w2" => Ok(ChannelComponent::W2),
+            "w3" => Ok(ChannelComponent::W3),
+            _ => Err(DmxParseError::InvalidComponent(s.trim().to_string())),
+        }
+    }
+}
+
+impl Display for ChannelComponent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            ChannelComponent::R => write!(f, "r"),
+            ChannelComponent::G => write!(f, "g"),
+            ChannelComponent::B => write!(f, "b"),
+            ChannelComponent::W1 => write!(f, "w1"),
+            ChannelComponent::W2 => write!(f, "w2"),
+            ChannelComponent::W3 => write!(f, "w3"),
+        }
+    }
+}
+
 impl Display for ChannelDefinition {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -103,6 +251,7 @@ impl Display for ChannelDefinition {
 pub struct UniverseChannelDefinitions {
     pub universe_id: String,
     pub channels: Vec<ChannelDefinition>,
+    pub components: HashMap<u16, ChannelComponent>,      // Single channels that are a component of an rgb or w light
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -118,8 +267,56 @@ pub struct ChannelValue {
     pub value: DimmerValue,
 }
 
+// Error parsing one component of a dimmer value, parse_dimmer_value adds the value and the component position
+enum ComponentError {
+    Invalid,
+    OutOfRange(String, &'static str),      // Component and its valid range
+}
+
+// Parse value_type(v1, v2, ...) into the value type and its components
+fn parse_dimmer_value<T>(
+    s: &str,
+    parse_component: impl Fn(&str) -> std::result::Result<T, ComponentError>,
+) -> std::result::Result<(String, Vec<T>), DmxParseError> {
+    let open_parenthesis = s
+        .find('(')
+        .ok_or_else(|| DmxParseError::InvalidDimmerValue(s.to_string()))?;
+    let close_parenthesis = s
+        .find(')')
+        .ok_or_else(|| DmxParseError::InvalidDimmerValue(s.to_string()))?;
+    let value_type = s[..open_parenthesis].trim().to_lowercase();
+    let values = s[open_parenthesis + 1..close_parenthesis]
+        .split(',')
+        .enumerate()
+        .map(|(i, v)| parse_component(v.trim()).map_err(|e| match e {
+            ComponentError::Invalid => DmxParseError::InvalidDimmerValue(s.to_string()),
+            ComponentError::OutOfRange(v, range) => DmxParseError::DimmerComponentOutOfRange(s.to_string(), i + 1, v, range),
+        }))
+        .collect::<std::result::Result<Vec<T>, _>>()?;
+
+    Ok((value_type, values))
+}
+
+// Parse n (0-255) or n% (0%-100%, rounded half up to 0-255)
+fn parse_absolute_component(v: &str) -> std::result::Result<u8, ComponentError> {
+    match v.strip_suffix('%') {
+        Some(percent) => {
+            let percent = percent.trim().parse::<u16>().map_err(|_| ComponentError::Invalid)?;
+
+            if percent > 100 {
+                return Err(ComponentError::OutOfRange(v.to_string(), "0%-100%"));
+            }
+            Ok(((percent as u32 * 255 + 50) / 100) as u8)
+        }
+        None => {
+            let value = v.parse::<u16>().map_err(|_| ComponentError::Invalid)?;
+            u8::try_from(value).map_err(|_| ComponentError::OutOfRange(v.to_string(), "0-255"))
+        }
+    }
+}
+
 impl FromStr for DimmerValue {
-    type Err = ArtnetError;
+    type Err = DmxParseError;
 
     /// Parse a string into a DimmerValue
     ///
@@ -128,25 +325,37 @@ impl FromStr for DimmerValue {
     /// rgb(r,g,b) -> DimmerValue::Rgb(r,g,b)
     /// w(w1, w2, w3) -> DimmerValue::TriWhite(w1, w2, w3)
     ///
+    /// Each component is either 0-255 or a percentage (e.g. rgb(100%,50%,0%))
+    ///
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        let open_parenthesis = s
-            .find('(')
-            .ok_or_else(|| ArtnetError::InvalidDimmerValue(s.to_string()))?;
-        let close_parenthesis = s
-            .find(')')
-            .ok_or_else(|| ArtnetError::InvalidDimmerValue(s.to_string()))?;
-        let value_type = s[..open_parenthesis].trim();
-        let values = s[open_parenthesis + 1..close_parenthesis]
-            .split(',')
-            .map(|v| v.trim().parse::<u8>())
-            .collect::<std::result::Result<Vec<u8>, _>>()
-            .map_err(|_| ArtnetError::InvalidDimmerValue(s.to_string()))?;
-
-        match value_type.to_lowercase().as_str() {
+        let (value_type, values) = parse_dimmer_value(s, parse_absolute_component)?;
+
+        match value_type.as_str() {
             "s" if values.len() == 1 => Ok(DimmerValue::Single(values[0])),
             "rgb" if values.len() == 3 => Ok(DimmerValue::Rgb(values[0], values[1], values[2])),
             "w" if values.len() == 3 => Ok(DimmerValue::TriWhite(values[0], values[1], values[2])),
-            _ => Err(ArtnetError::InvalidDimmerValue(s.to_string())),
+            _ => Err(DmxParseError::InvalidDimmerValue(s.to_string())),
+        }
+    }
+}
+
+impl DimmerValue {
+    /// Component values in the order of the channels of a matching ChannelDefinition
+    pub fn components(&self) -> Vec<u8> {
+        match *self {
+            DimmerValue::Rgb(r, g, b) => vec![r, g, b],
+            DimmerValue::TriWhite(w1, w2, w3) => vec![w1, w2, w3],
+            DimmerValue::Single(v) => vec![v],
+        }
+    }
+
+    pub fn get_dimmed_value(&self, dimming_amount: DimmingAmount) -> DimmerValue {
+        let dim = |v: u8| (v as DimmingAmount * dimming_amount / 1000) as u8;
+
+        match *self {
+            DimmerValue::Rgb(r, g, b) => DimmerValue::Rgb(dim(r), dim(g), dim(b)),
+            DimmerValue::TriWhite(w1, w2, w3) => DimmerValue::TriWhite(dim(w1), dim(w2), dim(w3)),
+            DimmerValue::Single(v) => DimmerValue::Single(dim(v)),
         }
     }
 }
@@ -171,32 +380,10 @@ impl TargetValue {
             ChannelDefinition::Single(_) => self.single.map(DimmerValue::Single),
         }
     }
-
-    pub fn get_dimmed_value(&self, dimming_amount: DimmingAmount) -> TargetValue {
-        TargetValue {
-            rgb: self.rgb.map(|(r, g, b)| {
-                (
-                    (r as DimmingAmount * dimming_amount / 1000) as u8,
-                    (g as DimmingAmount * dimming_amount / 1000) as u8,
-                    (b as DimmingAmount * dimming_amount / 1000) as u8,
-                )
-            }),
-            tri_white: self.tri_white.map(|(w1, w2, w3)| {
-                (
-                    (w1 as DimmingAmount * dimming_amount / 1000) as u8,
-                    (w2 as DimmingAmount * dimming_amount / 1000) as u8,
-                    (w3 as DimmingAmount * dimming_amount / 1000) as u8,
-                )
-            }),
-            single: self
-                .single
-                .map(|v| (v as DimmingAmount * dimming_amount / 1000) as u8),
-        }
-    }
 }
 
 impl FromStr for TargetValue {
-    type Err = ArtnetError;
+    type Err = DmxParseError;
 
     /// Parse a string into a TargetValue
     ///
@@ -204,37 +391,283 @@ impl FromStr for TargetValue {
     ///  [s(n)];[rgb(r,g,b)];[w(w1,w2,w3)]
     ///
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        let values = s
-            .split(';')
-            .map(|v| v.trim().parse::<DimmerValue>())
-            .collect::<std::result::Result<Vec<DimmerValue>, _>>()?;
-
-        let mut target_value = TargetValue::default();
-        for value in values {
-            match value {
-                DimmerValue::Single(s) => {
-                    target_value.single = target_value.single.map_or(Ok(Some(s)), |_| {
-                        Err(ArtnetError::AmbiguousTargetValue(s.to_string()))
-                    })?
-                }
-                DimmerValue::Rgb(r, g, b) => {
-                    target_value.rgb = target_value.rgb.map_or(Ok(Some((r, g, b))), |_| {
-                        Err(ArtnetError::AmbiguousTargetValue(s.to_string()))
-                    })?
-                }
-                DimmerValue::TriWhite(w1, w2, w3) => {
-                    target_value.tri_white =
-                        target_value.tri_white.map_or(Ok(Some((w1, w2, w3))), |_| {
-                            Err(ArtnetError::AmbiguousTargetValue(s.to_string()))
-                        })?
-                }
+        s.parse::<RelativeTargetValue>()?
+            .get_absolute()
+            .ok_or_else(|| DmxParseError::RelativeTargetNotAllowed(s.to_string()))
+    }
+}
+
+impl TargetComponent {
+    /// Get the component value given the current channel value (clamped to 0..=255)
+    pub fn resolve(&self, current_value: u8) -> u8 {
+        match *self {
+            TargetComponent::Absolute(v) => v,
+            TargetComponent::Relative(delta) => (current_value as i16 + delta).clamp(0, 255) as u8,
+        }
+    }
+
+    fn get_absolute(&self) -> Option<u8> {
+        match *self {
+            TargetComponent::Absolute(v) => Some(v),
+            TargetComponent::Relative(_) => None,
+        }
+    }
+
+    /// Parse n, n%, +n or -n
+    fn parse(s: &str) -> std::result::Result<Self, ComponentError> {
+        let parse_delta = |v: &str| {
+            if v.starts_with(['+', '-']) {
+                Err(ComponentError::Invalid)
+            } else {
+                v.parse::<u8>().map_err(|_| ComponentError::Invalid)
+            }
+        };
+
+        if let Some(delta) = s.strip_prefix('+') {
+            Ok(TargetComponent::Relative(parse_delta(delta)? as i16))
+        } else if let Some(delta) = s.strip_prefix('-') {
+            Ok(TargetComponent::Relative(-(parse_delta(delta)? as i16)))
+        } else {
+            Ok(TargetComponent::Absolute(parse_absolute_component(s)?))
+        }
+    }
+}
+
+impl Display for TargetComponent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            TargetComponent::Absolute(v) => write!(f, "{}", v),
+            TargetComponent::Relative(delta) => write!(f, "{:+}", delta),
+        }
+    }
+}
+
+impl Display for RelativeTargetValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut values = Vec::new();
+
+        if let Some(v) = self.single {
+            values.push(format!("s({})", v));
+        }
+
+        if let Some((r, g, b)) = self.rgb {
+            values.push(format!("rgb({},{},{})", r, g, b));
+        }
+
+        if let Some((w1, w2, w3)) = self.tri_white {
+            values.push(format!("w({},{},{})", w1, w2, w3));
+        }
+
+        write!(f, "{}", values.join(";"))
+    }
+}
+
+impl RelativeTargetValue {
+    /// Get the target value for a channel given its current value
+    pub fn get(&self, current_value: &DimmerValue) -> Option<DimmerValue> {
+        match *current_value {
+            DimmerValue::Rgb(r, g, b) => self
+                .rgb
+                .map(|(target_r, target_g, target_b)| DimmerValue::Rgb(target_r.resolve(r), target_g.resolve(g), target_b.resolve(b))),
+            DimmerValue::TriWhite(w1, w2, w3) => self
+                .tri_white
+                .map(|(target_w1, target_w2, target_w3)| DimmerValue::TriWhite(target_w1.resolve(w1), target_w2.resolve(w2), target_w3.resolve(w3))),
+            DimmerValue::Single(v) => self.single.map(|target| DimmerValue::Single(target.resolve(v))),
+        }
+    }
+
+    /// True if the target has a value for channels of this type (channels without a value are not changed)
+    pub fn has_value_for(&self, channel_type: ChannelType) -> bool {
+        match channel_type {
+            ChannelType::Single => self.single.is_some(),
+            ChannelType::Rgb => self.rgb.is_some(),
+            ChannelType::TriWhite => self.tri_white.is_some(),
+        }
+    }
+
+    /// Get the equivalent TargetValue, or None if any component is relative
+    pub fn get_absolute(&self) -> Option<TargetValue> {
+        let absolute3 = |(c1, c2, c3): (TargetComponent, TargetComponent, TargetComponent)| {
+            Some((c1.get_absolute()?, c2.get_absolute()?, c3.get_absolute()?))
+        };
+
+        Some(TargetValue {
+            single: match self.single {
+                Some(c) => Some(c.get_absolute()?),
+                None => None,
+            },
+            rgb: match self.rgb {
+                Some(c) => Some(absolute3(c)?),
+                None => None,
+            },
+            tri_white: match self.tri_white {
+                Some(c) => Some(absolute3(c)?),
+                None => None,
+            },
+        })
+    }
+}
+
+impl FromStr for RelativeTargetValue {
+    type Err = DmxParseError;
+
+    /// Parse a string into a RelativeTargetValue
+    ///
+    /// string syntax (each component is either n, n%, +n or -n):
+    ///  [s(n)];[rgb(r,g,b)];[w(w1,w2,w3)]
+    ///
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut target_value = RelativeTargetValue::default();
+
+        for (index, value) in s.split(';').map(|v| v.trim()).enumerate() {
+            let (value_type, components) = parse_dimmer_value(value, TargetComponent::parse).map_err(|e| e.at_entry(s, ';', index))?;
+
+            let is_ambiguous = match (value_type.as_str(), &components[..]) {
+                ("s", &[v]) => target_value.single.replace(v).is_some(),
+                ("rgb", &[r, g, b]) => target_value.rgb.replace((r, g, b)).is_some(),
+                ("w", &[w1, w2, w3]) => target_value.tri_white.replace((w1, w2, w3)).is_some(),
+                _ => return Err(DmxParseError::InvalidDimmerValue(value.to_string()).at_entry(s, ';', index)),
             };
+
+            if is_ambiguous {
+                return Err(DmxParseError::AmbiguousTarget(value.to_string()).at_entry(s, ';', index));
+            }
         }
 
         Ok(target_value)
     }
 }
 
+// Maximum value of individual DMX channel addresses (per universe)
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ChannelLimits {
+    limits: HashMap<String, HashMap<u16, u8>>,
+}
+
+impl ChannelLimits {
+    /// Add the limit for a channel, if the channel is already limited the lower limit is used
+    pub fn add(&mut self, universe_id: &str, channel_definition: &ChannelDefinition, limit: &TargetValue) {
+        let universe_limits = self.limits.entry(universe_id.to_string()).or_default();
+        let mut add_limit = |channel: u16, limit: u8| {
+            universe_limits
+                .entry(channel)
+                .and_modify(|v| *v = (*v).min(limit))
+                .or_insert(limit);
+        };
+
+        match (channel_definition, limit.get(channel_definition)) {
+            (ChannelDefinition::Single(c), Some(DimmerValue::Single(v))) => add_limit(*c, v),
+            (ChannelDefinition::Rgb(r_channel, g_channel, b_channel), Some(DimmerValue::Rgb(r, g, b))) => {
+                add_limit(*r_channel, r);
+                add_limit(*g_channel, g);
+                add_limit(*b_channel, b);
+            }
+            (ChannelDefinition::TriWhite(w1_channel, w2_channel, w3_channel), Some(DimmerValue::TriWhite(w1, w2, w3))) => {
+                add_limit(*w1_channel, w1);
+                add_limit(*w2_channel, w2);
+                add_limit(*w3_channel, w3);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.limits.values().all(|universe_limits| universe_limits.is_empty())
+    }
+
+    /// Add the limits of other, channels limited by both use the lower limit
+    pub fn merge(&mut self, other: &ChannelLimits) {
+        for (universe_id, other_universe_limits) in other.limits.iter() {
+            let universe_limits = self.limits.entry(universe_id.clone()).or_default();
+
+            for (channel, limit) in other_universe_limits.iter() {
+                universe_limits
+                    .entry(*channel)
+                    .and_modify(|v| *v = (*v).min(*limit))
+                    .or_insert(*limit);
+            }
+        }
+    }
+
+    /// Move the limits of a universe to another universe ID, channels limited in both use the lower limit
+    pub fn rename_universe(&mut self, from: &str, to: &str) {
+        if let Some(from_limits) = self.limits.remove(from) {
+            let universe_limits = self.limits.entry(to.to_string()).or_default();
+
+            for (channel, limit) in from_limits {
+                universe_limits
+                    .entry(channel)
+                    .and_modify(|v| *v = (*v).min(limit))
+                    .or_insert(limit);
+            }
+        }
+    }
+
+    /// Return the value of a single channel clamped to the channel limit
+    pub fn limit_channel(&self, universe_id: &str, channel: u16, value: u8) -> u8 {
+        self.limits
+            .get(universe_id)
+            .and_then(|universe_limits| universe_limits.get(&channel))
+            .map_or(value, |limit| value.min(*limit))
+    }
+
+    /// Return the channel value clamped to the channel limits
+    pub fn limit(&self, universe_id: &str, v: &ChannelValue) -> ChannelValue {
+        let universe_limits = match self.limits.get(universe_id) {
+            Some(universe_limits) => universe_limits,
+            None => return v.clone(),
+        };
+        let limit = |channel: u16, value: u8| {
+            universe_limits
+                .get(&channel)
+                .map_or(value, |limit| value.min(*limit))
+        };
+
+        let value = match (&v.channel, &v.value) {
+            (ChannelDefinition::Single(c), DimmerValue::Single(value)) => DimmerValue::Single(limit(*c, *value)),
+            (ChannelDefinition::Rgb(r_channel, g_channel, b_channel), DimmerValue::Rgb(r, g, b)) => {
+                DimmerValue::Rgb(limit(*r_channel, *r), limit(*g_channel, *g), limit(*b_channel, *b))
+            }
+            (ChannelDefinition::TriWhite(w1_channel, w2_channel, w3_channel), DimmerValue::TriWhite(w1, w2, w3)) => {
+                DimmerValue::TriWhite(limit(*w1_channel, *w1), limit(*w2_channel, *w2), limit(*w3_channel, *w3))
+            }
+            (_, value) => value.clone(),
+        };
+
+        ChannelValue {
+            channel: v.channel.clone(),
+            value,
+        }
+    }
+}
+
+// Dimming factor of individual lights (per universe and first channel of the light), lights without a factor are dimmed
+// only by the effect dimming amount
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ChannelDimming {
+    factors: HashMap<String, HashMap<u16, DimmingAmount>>,
+}
+
+impl ChannelDimming {
+    /// Add the dimming factor of a light, if the light already has a factor the higher factor is used
+    pub fn add(&mut self, universe_id: &str, channel_definition: &ChannelDefinition, factor: DimmingAmount) {
+        self.factors
+            .entry(universe_id.to_string())
+            .or_default()
+            .entry(channel_definition.channels()[0])
+            .and_modify(|v| *v = (*v).max(factor))
+            .or_insert(factor);
+    }
+
+    /// Return the dimming amount of a light, which is the effect dimming amount scaled by the light dimming factor
+    pub fn get_dimming_amount(&self, universe_id: &str, channel_definition: &ChannelDefinition, dimming_amount: DimmingAmount) -> DimmingAmount {
+        self.factors
+            .get(universe_id)
+            .and_then(|universe_factors| universe_factors.get(&channel_definition.channels()[0]))
+            .map_or(dimming_amount, |factor| dimming_amount * factor / DIMMING_AMOUNT_MAX)
+    }
+}
+
 #[cfg(test)]
 mod test_parse_value {
     use super::*;
@@ -251,6 +684,53 @@ mod test_parse_value {
         assert_eq!(v, DimmerValue::TriWhite(5, 6, 7));
     }
 
+    #[test]
+    fn test_dimmer_value_out_of_range() {
+        let e = "rgb(300,0,0)".parse::<DimmerValue>().unwrap_err();
+        assert!(matches!(e, DmxParseError::DimmerComponentOutOfRange(_, 1, _, _)));
+        assert_eq!(e.to_string(), "Invalid dimmer value 'rgb(300,0,0)': component 1 is 300 but must be 0-255");
+
+        let e = "w(1, 2, 256)".parse::<DimmerValue>().unwrap_err();
+        assert_eq!(e.to_string(), "Invalid dimmer value 'w(1, 2, 256)': component 3 is 256 but must be 0-255");
+
+        let e = "rgb(0,101%,0)".parse::<DimmerValue>().unwrap_err();
+        assert_eq!(e.to_string(), "Invalid dimmer value 'rgb(0,101%,0)': component 2 is 101% but must be 0%-100%");
+
+        let e = "s(10);rgb(1,2,999)".parse::<TargetValue>().unwrap_err();
+        assert!(matches!(e, DmxParseError::Entry(_, 2, ref v) if matches!(**v, DmxParseError::DimmerComponentOutOfRange(ref v, 3, _, _) if v == "rgb(1,2,999)")));
+        assert_eq!(
+            e.to_string(),
+            "Entry 2 of 's(10);rgb(1,2,999)': Invalid dimmer value 'rgb(1,2,999)': component 3 is 999 but must be 0-255"
+        );
+
+        assert!(matches!("s(abc)".parse::<DimmerValue>(), Err(DmxParseError::InvalidDimmerValue(_))));
+        assert!(matches!("s(-1)".parse::<DimmerValue>(), Err(DmxParseError::InvalidDimmerValue(_))));
+        assert!(matches!("s(70000)".parse::<DimmerValue>(), Err(DmxParseError::InvalidDimmerValue(_))));
+    }
+
+    #[test]
+    fn test_dimmer_value_percent() {
+        assert_eq!("s(0%)".parse::<DimmerValue>().unwrap(), DimmerValue::Single(0));
+        assert_eq!("s(100%)".parse::<DimmerValue>().unwrap(), DimmerValue::Single(255));
+        assert_eq!("s(33%)".parse::<DimmerValue>().unwrap(), DimmerValue::Single(84));
+        assert_eq!("s(50%)".parse::<DimmerValue>().unwrap(), DimmerValue::Single(128));     // 127.5 rounded half up
+        assert_eq!("s(75 %)".parse::<DimmerValue>().unwrap(), DimmerValue::Single(191));
+        assert_eq!("rgb(100%,50%,0%)".parse::<DimmerValue>().unwrap(), DimmerValue::Rgb(255, 128, 0));
+
+        // Percent and integer components can be mixed
+        assert_eq!("w(10%, 200, 0)".parse::<DimmerValue>().unwrap(), DimmerValue::TriWhite(26, 200, 0));
+
+        let v = "s(75%);rgb(255,50%,+0)".parse::<RelativeTargetValue>().unwrap();
+        assert_eq!(v.single, Some(TargetComponent::Absolute(191)));
+        assert_eq!(v.rgb, Some((TargetComponent::Absolute(255), TargetComponent::Absolute(128), TargetComponent::Relative(0))));
+
+        let v = "rgb(100%,50%,0%)".parse::<TargetValue>().unwrap();
+        assert_eq!(v.get(&ChannelDefinition::Rgb(1, 2, 3)), Some(DimmerValue::Rgb(255, 128, 0)));
+
+        assert!(matches!("s(%)".parse::<DimmerValue>(), Err(DmxParseError::InvalidDimmerValue(_))));
+        assert!(matches!("s(+10%)".parse::<RelativeTargetValue>(), Err(DmxParseError::InvalidDimmerValue(_))));
+    }
+
     #[test]
     fn test_channel_definition() {
         let v = "rgb:1".parse::<ChannelDefinition>().unwrap();
@@ -270,6 +750,27 @@ mod test_parse_value {
 
         let v = "w:3/10/400".parse::<ChannelDefinition>().unwrap();
         assert_eq!(v, ChannelDefinition::TriWhite(3, 10, 400));
+
+        let e = "rgb:1/2".parse::<ChannelDefinition>().unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Invalid channel 'rgb:1/2': rgb channel needs 1 or 3 channel addresses (expected n, s:n, rgb:n, w:n, rgb:r/g/b or w:w1/w2/w3)"
+        );
+        assert!(matches!("x:1".parse::<ChannelDefinition>(), Err(DmxParseError::InvalidChannel(_, "unknown channel type"))));
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let channels = parse_list::<ChannelDefinition>("1, rgb:2,w:10", ',').unwrap();
+        assert_eq!(channels, vec![ChannelDefinition::Single(1), ChannelDefinition::Rgb(2, 3, 4), ChannelDefinition::TriWhite(10, 11, 12)]);
+
+        let e = parse_list::<ChannelDefinition>("1,rgb:2,rgb:x", ',').unwrap_err();
+        assert!(matches!(e, DmxParseError::Entry(_, 3, _)));
+        assert!(e.to_string().starts_with("Entry 3 of '1,rgb:2,rgb:x': Invalid channel 'rgb:x'"));
+
+        // The position of a single entry is not reported
+        let e = parse_list::<ChannelDefinition>("abc", ',').unwrap_err();
+        assert!(matches!(e, DmxParseError::InvalidChannel(ref token, _) if token == "abc"));
     }
 
     #[test]
@@ -311,9 +812,40 @@ mod test_parse_value {
 
         let v = "s(10);s(20)".parse::<TargetValue>();
 
-        if let Err(ArtnetError::AmbiguousTargetValue(_)) = v {
+        if let Err(DmxParseError::Entry(_, 2, e)) = v {
+            assert_eq!(*e, DmxParseError::AmbiguousTarget("s(20)".to_string()));
         } else {
-            panic!("Expected AmbiguousTargetValue error");
+            panic!("Expected AmbiguousTarget error at entry 2");
         }
     }
+
+    #[test]
+    fn test_relative_target_value() {
+        let v = "s(+20); rgb(-10, 5, +0); w(1,2,3)".parse::<RelativeTargetValue>().unwrap();
+        assert_eq!(v.single, Some(TargetComponent::Relative(20)));
+        assert_eq!(
+            v.rgb,
+            Some((TargetComponent::Relative(-10), TargetComponent::Absolute(5), TargetComponent::Relative(0)))
+        );
+        assert!(v.get_absolute().is_none());
+
+        // Relative components are clamped to 0..=255
+        assert_eq!(v.get(&DimmerValue::Single(100)), Some(DimmerValue::Single(120)));
+        assert_eq!(v.get(&DimmerValue::Single(250)), Some(DimmerValue::Single(255)));
+        assert_eq!(v.get(&DimmerValue::Rgb(5, 100, 200)), Some(DimmerValue::Rgb(0, 5, 200)));
+        assert_eq!(v.get(&DimmerValue::TriWhite(9, 9, 9)), Some(DimmerValue::TriWhite(1, 2, 3)));
+
+        let v = "s(-255)".parse::<RelativeTargetValue>().unwrap();
+        assert_eq!(v.get(&DimmerValue::Single(255)), Some(DimmerValue::Single(0)));
+        assert_eq!(v.get(&DimmerValue::Rgb(1, 2, 3)), None);
+
+        assert!(matches!("s(+256)".parse::<RelativeTargetValue>(), Err(DmxParseError::InvalidDimmerValue(_))));
+        assert!(matches!("s(++5)".parse::<RelativeTargetValue>(), Err(DmxParseError::InvalidDimmerValue(_))));
+        assert!(matches!("s(+5);s(-5)".parse::<RelativeTargetValue>(), Err(DmxParseError::Entry(_, 2, _))));
+
+        // Absolute only target values reject relative components
+        assert!(matches!("rgb(+5,0,0)".parse::<TargetValue>(), Err(DmxParseError::RelativeTargetNotAllowed(_))));
+        let v = "s(10);rgb(1,2,3)".parse::<TargetValue>().unwrap();
+        assert_eq!(v.get(&ChannelDefinition::Rgb(1, 2, 3)), Some(DimmerValue::Rgb(1, 2, 3)));
+    }
 }
@@ -0,0 +1,202 @@
+use log::{info, warn};
+use serde::Serialize;
+use std::time::Duration;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    select,
+    time::timeout,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::mqtt_subscriber::MqttSubscriber;
+
+// Read only HTTP status endpoint for monitoring tools that scrape HTTP instead of subscribing to MQTT
+//
+//  GET /health -> { "version": "...", "uptime_seconds": 12, "broker_connected": true }
+//  GET /status -> the DMX/Diagnostics document (values are redacted)
+//  GET /metrics -> counters and gauges in Prometheus text format (see metrics.rs)
+//
+// Nothing can be changed through HTTP. Each connection is answered once and closed (no keep-alive), and a request
+// that is not received within REQUEST_TIMEOUT or is larger than MAX_REQUEST_SIZE is dropped
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+const JSON_CONTENT_TYPE: &str = "application/json";
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+}
+
+// Listener on all the interfaces, None if no HTTP port is configured
+pub async fn bind(http_port: Option<u16>) -> std::io::Result<Option<TcpListener>> {
+    match http_port {
+        Some(http_port) => TcpListener::bind(("0.0.0.0", http_port)).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+pub async fn serve(cancel: CancellationToken, listener: TcpListener, mqtt_subscriber: MqttSubscriber) {
+    loop {
+        select! {
+            _ = cancel.cancelled() => break,
+
+            connection = listener.accept() => match connection {
+                Ok((stream, address)) => {
+                    let mqtt_subscriber = mqtt_subscriber.clone();
+
+                    tokio::spawn(async move {
+                        if let Err(e) = serve_connection(stream, mqtt_subscriber).await {
+                            info!("HTTP status request from {} failed: {}", address, e);
+                        }
+                    });
+                },
+                Err(e) => warn!("HTTP status listener accept failed: {}", e),
+            }
+        }
+    }
+
+    info!("HTTP status listener stopped");
+}
+
+// Request line (e.g. GET /health HTTP/1.1) of a request whose headers were received, None if the connection was closed
+// before that
+async fn read_request_line(stream: &mut TcpStream) -> std::io::Result<Option<String>> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_SIZE {
+            return Ok(None);
+        }
+
+        let count = stream.read(&mut buffer).await?;
+
+        if count == 0 {
+            return Ok(None);
+        }
+        request.extend_from_slice(&buffer[..count]);
+    }
+
+    Ok(String::from_utf8_lossy(&request).lines().next().map(|line| line.to_string()))
+}
+
+async fn serve_connection(mut stream: TcpStream, mqtt_subscriber: MqttSubscriber) -> std::io::Result<()> {
+    let request_line = match timeout(REQUEST_TIMEOUT, read_request_line(&mut stream)).await {
+        Ok(request_line) => request_line?,
+        Err(_) => None,
+    };
+
+    let Some(request_line) = request_line else { return Ok(()) };
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default().split('?').next().unwrap_or_default();
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/health") => ("200 OK", JSON_CONTENT_TYPE, serde_json::to_vec(&mqtt_subscriber.get_health())),
+        ("GET", "/status") => ("200 OK", JSON_CONTENT_TYPE, serde_json::to_vec(&mqtt_subscriber.get_diagnostics(false).await)),
+        ("GET", "/metrics") => ("200 OK", METRICS_CONTENT_TYPE, Ok(mqtt_subscriber.get_metrics().into_bytes())),
+        ("GET", _) => ("404 Not Found", JSON_CONTENT_TYPE, serde_json::to_vec(&ErrorBody { error: "not found (use /health, /status or /metrics)" })),
+        _ => ("405 Method Not Allowed", JSON_CONTENT_TYPE, serde_json::to_vec(&ErrorBody { error: "only GET is supported" })),
+    };
+    let body = body.map_err(std::io::Error::other)?;
+    let header = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+
+    stream.write_all(header.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod test_http_status {
+    use super::*;
+    use crate::{array_manager::ArrayManager, artnet_manager::ArtnetManager, manager_channel, messages};
+
+    fn get_mqtt_subscriber(cancel: &CancellationToken) -> MqttSubscriber {
+        let (to_artnet_tx, to_artnet_rx) = manager_channel::channel::<messages::ToArtnetManagerMessage>("artnet", 10);
+        let (to_array_tx, to_array_rx) = manager_channel::channel::<messages::ToArrayManagerMessage>("array", 10);
+        let (to_mqtt_publisher_tx, _) = async_channel::bounded(10);
+        let (to_scheduler_tx, _) = tokio::sync::mpsc::channel(10);
+
+        let cancel_instance = cancel.clone();
+        let to_mqtt_publisher_tx_instance = to_mqtt_publisher_tx.clone();
+        tokio::spawn(async move {
+            ArtnetManager::new().run(cancel_instance, to_artnet_rx, to_mqtt_publisher_tx_instance).await;
+        });
+
+        let cancel_instance = cancel.clone();
+        tokio::spawn(async move {
+            ArrayManager::new().run(cancel_instance, to_array_rx).await;
+        });
+
+        MqttSubscriber::new(to_artnet_tx, to_array_tx, to_mqtt_publisher_tx, to_scheduler_tx, true)
+    }
+
+    // Header and body of the response
+    async fn request_text(port: u16, request: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let mut response = String::new();
+
+        stream.write_all(request.as_bytes()).await.unwrap();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let (header, body) = response.split_once("\r\n\r\n").unwrap();
+        (header.to_string(), body.to_string())
+    }
+
+    // Status line and JSON body of the response
+    async fn request(port: u16, request: &str) -> (String, serde_json::Value) {
+        let (header, body) = request_text(port, request).await;
+        (header.lines().next().unwrap().to_string(), serde_json::from_str(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_endpoints() {
+        let cancel = CancellationToken::new();
+        let mqtt_subscriber = get_mqtt_subscriber(&cancel);
+        let listener = bind(Some(0)).await.unwrap().unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(serve(cancel.clone(), listener, mqtt_subscriber.clone()));
+
+        let (status, health) = request(port, "GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert!(health["version"].as_str().unwrap().starts_with("mqtt_dmx"));
+        assert!(health["uptime_seconds"].is_u64());
+        assert_eq!(health["broker_connected"], false);
+
+        mqtt_subscriber.set_broker_connected(true);
+        let (_, health) = request(port, "GET /health?probe=1 HTTP/1.1\r\n\r\n").await;
+        assert_eq!(health["broker_connected"], true);
+
+        // Same document as DMX/Diagnostics
+        let (status, diagnostics) = request(port, "GET /status HTTP/1.1\r\n\r\n").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        for field in ["version", "uptime_seconds", "arrays", "artnet", "queues"] {
+            assert!(diagnostics.get(field).is_some(), "missing {field}");
+        }
+        assert!(diagnostics["queues"].get("artnet").is_some());
+
+        let (header, metrics) = request_text(port, "GET /metrics HTTP/1.1\r\n\r\n").await;
+        assert!(header.starts_with("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n"));
+        assert!(metrics.contains("# TYPE commands_total counter\n"));
+        assert!(metrics.contains("\ntick_duration_seconds_count "));
+
+        let (status, _) = request(port, "GET /Command/Off HTTP/1.1\r\n\r\n").await;
+        assert_eq!(status, "HTTP/1.1 404 Not Found");
+        let (status, _) = request(port, "POST /health HTTP/1.1\r\nContent-Length: 0\r\n\r\n").await;
+        assert_eq!(status, "HTTP/1.1 405 Method Not Allowed");
+
+        cancel.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_disabled() {
+        assert!(bind(None).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,106 @@
+// Lenient JSON for hand maintained definitions (universes, arrays, effects and values)
+//
+// Removes `// line` and `/* block */` comments and trailing commas (before } or ]) so the result can be parsed
+// by serde_json. Removed text is replaced by spaces (new lines are kept) so that line and column numbers in parse
+// errors still refer to the original text.
+
+pub fn to_strict_json(input: &[u8]) -> Vec<u8> {
+    remove_trailing_commas(remove_comments(input))
+}
+
+fn remove_comments(input: &[u8]) -> Vec<u8> {
+    let mut output = input.to_vec();
+    let mut in_string = false;
+    let mut i = 0;
+
+    while i < output.len() {
+        match output[i] {
+            b'\\' if in_string => i += 1, // Skip the escaped character
+            b'"' => in_string = !in_string,
+            b'/' if !in_string && output.get(i + 1) == Some(&b'/') => {
+                while i < output.len() && output[i] != b'\n' {
+                    output[i] = b' ';
+                    i += 1;
+                }
+            }
+            b'/' if !in_string && output.get(i + 1) == Some(&b'*') => {
+                let end = output[i + 2..]
+                    .windows(2)
+                    .position(|w| w == b"*/")
+                    .map(|p| i + 2 + p + 2)
+                    .unwrap_or(output.len());
+
+                for c in output[i..end].iter_mut().filter(|c| **c != b'\n') {
+                    *c = b' ';
+                }
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    output
+}
+
+fn remove_trailing_commas(mut input: Vec<u8>) -> Vec<u8> {
+    let mut in_string = false;
+    let mut i = 0;
+
+    while i < input.len() {
+        match input[i] {
+            b'\\' if in_string => i += 1,
+            b'"' => in_string = !in_string,
+            b',' if !in_string => {
+                let next = input[i + 1..].iter().find(|c| !c.is_ascii_whitespace());
+
+                if matches!(next, Some(b'}') | Some(b']')) {
+                    input[i] = b' ';
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    input
+}
+
+#[cfg(test)]
+mod test_lenient_json {
+    use super::*;
+
+    fn parse(json: &str) -> serde_json::Value {
+        serde_json::from_slice(&to_strict_json(json.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn test_lenient_array_definition() {
+        let clean = r#"{ "universe_id": "0", "lights": { "all": "rgb:1,s:4", "note": "a // b /* c */ d,}" }, "effects": {} }"#;
+        let annotated = r#"
+            // Living room array
+            {
+                "universe_id": "0",     /* default universe */
+                "lights": {
+                    "all": "rgb:1,s:4", // spots and candle
+                    "note": "a // b /* c */ d,}",
+                },
+                "effects": { /* none yet, */ },
+            }
+        "#;
+
+        assert_eq!(parse(annotated), parse(clean));
+        assert_eq!(parse(r#"{ "s": "quote \" // not a comment", }"#)["s"], "quote \" // not a comment");
+        assert_eq!(parse("[1, 2, /* 3 */ ]"), parse("[1, 2]"));
+    }
+
+    #[test]
+    fn test_error_position_is_kept() {
+        let json = "{\n  // comment\n  \"a\": 1,\n  \"b\": x\n}";
+        let e = serde_json::from_slice::<serde_json::Value>(&to_strict_json(json.as_bytes())).unwrap_err();
+
+        assert_eq!(e.line(), 4);
+        assert_eq!(e.column(), 8);
+    }
+}
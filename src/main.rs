@@ -1,6 +1,7 @@
 
 mod service;
 mod defs;
+mod definition_hash;
 mod mqtt_publisher;
 mod mqtt_subscriber;
 mod dmx;
@@ -8,16 +9,44 @@ mod artnet_manager;
 mod array_manager;
 //mod effects_manager;
 mod messages;
+mod sim;
+mod http_status;
+mod lenient_json;
+mod scheduler;
+mod command_latency;
+mod metrics;
+mod schema;
+#[cfg(test)]
+mod soak;
+mod manager_channel;
 
 use log::info;
 use rustop::opts;
 use service::ServiceConfig;
+use artnet_manager::EffectTickBudget;
+use std::time::Duration;
 
 #[tokio::main]
 async fn main() {
     let (args, _) = opts! {
         synopsis "MQTT DMX Controller";
         param mqtt:String, desc: "MQTT broker to connect";
+        opt effect_budget_ms:Option<u64>, desc: "Stop effects whose tick consistently takes longer than this (milliseconds)";
+        opt effect_budget_ticks:usize=20, desc: "Number of consecutive over budget ticks before an effect is stopped";
+        opt default_universe:Option<String>, desc: "Universe of arrays that do not specify universe_id";
+        opt sim_port:Option<u16>, desc: "Serve universe frames as JSON lines to local viewers on this TCP port";
+        opt sim_fps:u32=20, desc: "Maximum frames per second sent to each sim viewer per universe";
+        opt strict_json:bool, desc: "Do not allow comments and trailing commas in universe, array, effect and value definitions";
+        opt unreachable_after:usize=5, desc: "Report a universe as unreachable after this number of consecutive send failures";
+        opt controller_retention:u64=0, desc: "Keep a controller socket for this number of seconds after its last universe is removed";
+        opt publish_queue:usize=10, desc: "Number of messages waiting to be published to MQTT (messages from the DMX tick loop are dropped when full)";
+        opt artnet_queue:usize=10, desc: "Number of messages waiting to be handled by the artnet manager";
+        opt array_queue:usize=10, desc: "Number of messages waiting to be handled by the array manager";
+        opt max_delta_per_tick:Option<u8>, desc: "Limit channel change per tick, larger changes are spread over several ticks (soft start)";
+        opt max_payload_kb:usize=256, desc: "Reject MQTT messages whose payload is larger than this (KiB)";
+        opt http_port:Option<u16>, desc: "Serve read only GET /health, /status (JSON) and /metrics (Prometheus) on this HTTP port";
+        opt allow_retained_commands:bool, desc: "Run commands delivered as retained MQTT messages (otherwise only commands with allow_retained are run)";
+        opt max_effect_ticks:Option<usize>, desc: "Stop effects that run for more than this number of ticks (unless the command sets max_ticks or no_max_ticks)";
     }.parse_or_exit();
 
     let d = tracing_init::TracingInit::builder("mqtt_dmx")
@@ -35,6 +64,24 @@ async fn main() {
 
     let config = ServiceConfig {
         mqtt_broker_address: args.mqtt,
+        effect_tick_budget: args.effect_budget_ms.map(|ms| EffectTickBudget {
+            max_tick_duration: Duration::from_millis(ms),
+            max_over_budget_ticks: args.effect_budget_ticks,
+        }),
+        default_universe_id: args.default_universe,
+        sim_port: args.sim_port,
+        sim_max_frames_per_second: args.sim_fps,
+        strict_json: args.strict_json,
+        unreachable_threshold: args.unreachable_after,
+        controller_retention: Duration::from_secs(args.controller_retention),
+        publisher_queue_size: args.publish_queue,
+        artnet_queue_size: args.artnet_queue,
+        array_queue_size: args.array_queue,
+        max_delta_per_tick: args.max_delta_per_tick,
+        max_payload_size: args.max_payload_kb.saturating_mul(1024),
+        http_port: args.http_port,
+        max_effect_ticks: args.max_effect_ticks,
+        allow_retained_commands: args.allow_retained_commands,
     };
 
     let service = service::Service::new(config);
@@ -0,0 +1,149 @@
+// Channel from the subscriber to a manager (artnet or array). Messages are received in the order they are sent, so a
+// command is handled after the definitions sent before it. The sender cannot see how long the receiver takes, so a send
+// that waits for room in a full queue longer than SLOW_SEND_THRESHOLD is logged. The queue counters are reported by
+// the Diagnostics command
+
+use std::sync::{atomic::{AtomicU64, AtomicUsize, Ordering}, Arc};
+use log::warn;
+use tokio::{sync::mpsc, time::{Duration, Instant}};
+
+use crate::defs::QueueDiagnostics;
+
+pub const SLOW_SEND_THRESHOLD: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Default)]
+struct QueueCounters {
+    sent: AtomicU64,
+    slow_sends: AtomicU64,
+    high_water_mark: AtomicUsize,      // Most messages queued (including the one being sent)
+    max_send_wait_us: AtomicU64,
+}
+
+pub struct ManagerSender<T> {
+    name: String,
+    sender: mpsc::Sender<T>,
+    counters: Arc<QueueCounters>,
+}
+
+impl<T> Clone for ManagerSender<T> {
+    fn clone(&self) -> Self {
+        ManagerSender {
+            name: self.name.clone(),
+            sender: self.sender.clone(),
+            counters: self.counters.clone(),
+        }
+    }
+}
+
+impl<T> ManagerSender<T> {
+    pub async fn send(&self, message: T) -> Result<(), mpsc::error::SendError<T>> {
+        let capacity = self.sender.max_capacity();
+        let queued = capacity - self.sender.capacity();
+
+        self.counters.high_water_mark.fetch_max((queued + 1).min(capacity), Ordering::Relaxed);
+
+        let start = Instant::now();
+        let result = self.sender.send(message).await;
+        let wait = start.elapsed();
+
+        self.counters.sent.fetch_add(1, Ordering::Relaxed);
+        self.counters.max_send_wait_us.fetch_max(wait.as_micros() as u64, Ordering::Relaxed);
+
+        if wait >= SLOW_SEND_THRESHOLD {
+            self.counters.slow_sends.fetch_add(1, Ordering::Relaxed);
+            warn!("Sending to {} queue waited {} ms (queue of {} messages is full)", self.name, wait.as_millis(), capacity);
+        }
+
+        result
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_diagnostics(&self) -> QueueDiagnostics {
+        QueueDiagnostics {
+            capacity: self.sender.max_capacity(),
+            queued: self.sender.max_capacity() - self.sender.capacity(),
+            sent: self.counters.sent.load(Ordering::Relaxed),
+            slow_sends: self.counters.slow_sends.load(Ordering::Relaxed),
+            high_water_mark: self.counters.high_water_mark.load(Ordering::Relaxed),
+            max_send_wait_ms: self.counters.max_send_wait_us.load(Ordering::Relaxed) as f64 / 1000.0,
+        }
+    }
+}
+
+pub struct ManagerReceiver<T> {
+    receiver: mpsc::Receiver<T>,
+}
+
+impl<T> ManagerReceiver<T> {
+    // None once all senders are dropped
+    pub async fn recv(&mut self) -> Option<T> {
+        self.receiver.recv().await
+    }
+
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.receiver.len()
+    }
+}
+
+// Queue of the given capacity named after the manager (e.g. artnet)
+pub fn channel<T>(name: &str, capacity: usize) -> (ManagerSender<T>, ManagerReceiver<T>) {
+    let (sender, receiver) = mpsc::channel(capacity.max(1));
+
+    (
+        ManagerSender { name: name.to_string(), sender, counters: Arc::new(QueueCounters::default()) },
+        ManagerReceiver { receiver },
+    )
+}
+
+#[cfg(test)]
+mod test_manager_channel {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_send() {
+        let (sender, mut receiver) = channel::<usize>("test", 2);
+
+        sender.send(1).await.unwrap();
+        sender.send(2).await.unwrap();
+
+        // The queue is full, the next send waits until the (slow) receiver takes a message
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            while receiver.recv().await.is_some() {}
+        });
+        sender.send(3).await.unwrap();
+
+        let diagnostics = sender.get_diagnostics();
+        assert_eq!((diagnostics.capacity, diagnostics.sent, diagnostics.slow_sends, diagnostics.high_water_mark), (2, 3, 1, 2));
+        assert!(diagnostics.max_send_wait_ms >= 250.0);
+
+        // Sends with room in the queue are not slow
+        sender.send(4).await.unwrap();
+        assert_eq!(sender.get_diagnostics().slow_sends, 1);
+    }
+
+    #[tokio::test]
+    async fn test_receive_order() {
+        let (sender, mut receiver) = channel::<usize>("test", 10);
+
+        for i in 0..5 {
+            sender.send(i).await.unwrap();
+        }
+
+        assert_eq!(receiver.recv().await, Some(0));
+        assert_eq!(receiver.try_recv(), Some(1));
+        assert_eq!(receiver.len(), 3);
+
+        drop(sender);
+        assert_eq!(std::iter::from_fn(|| receiver.try_recv()).collect::<Vec<_>>(), [2, 3, 4]);
+        assert_eq!(receiver.recv().await, None);
+    }
+}
@@ -1,38 +1,154 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use error_stack::Result;
 
 use tokio::sync::oneshot::Sender;
 use crate::artnet_manager::EffectNodeRuntime;
-use crate::defs::{self, EffectUsage, SymbolTable};
-use crate::{artnet_manager::ArtnetError, array_manager::DmxArrayError};
+use crate::defs::{self, ArrayEpoch, ArrayState, DimmingAmount, EffectUsage, SymbolTable};
+use crate::dmx::ChannelLimits;
+use crate::mqtt_publisher::ErrorCategory;
+use crate::service::ServiceSettings;
+use crate::{artnet_manager::ArtnetError, array_manager::DmxArrayError, scheduler::SchedulerError};
+
+// Runtime node of an array effect and the epoch of the array definition it was built from
+pub type ArrayEffectRuntime = (Box<dyn EffectNodeRuntime>, ArrayEpoch, Vec<String>);     // Runtime node, array epoch and warnings
+
+// Epoch of an added array definition and the verification problems of a non strict array
+pub type AddedArray = (ArrayEpoch, Vec<String>);
+
+// Arrays removed by the Reset command and their new epochs
+pub type RemovedArrays = Vec<(Arc<str>, ArrayEpoch)>;
+
+// Send status of each universe of a universe command target (see defs::UniverseTarget)
+pub type UniversesSendStatus = Vec<(Arc<str>, defs::UniverseSendStatus)>;
+
+// Array ID -> effects referenced by the array (on, off, dim or startup) that are not defined (yet)
+pub type UnresolvedEffects = BTreeMap<Arc<str>, Vec<Arc<str>>>;
+
+// Errors and warnings found when validating a definition (see DMX/Command/Validate)
+pub type ValidationProblems = (Vec<String>, Vec<String>);
+
+// Effect to be started by the artnet manager
+#[derive(Debug)]
+pub struct StartEffectRequest {
+    pub effect_id: Arc<str>,
+    pub node: Box<dyn EffectNodeRuntime>,
+    pub usage: Option<EffectUsage>,
+    pub epoch: Option<ArrayEpoch>,      // Epoch of the array the node was built from (None for effects not built from an array)
+    pub origin: Option<Arc<str>>,
+    pub enqueue: bool,      // Start when the active effect with this id completes
+    pub max_ticks: defs::EffectMaxTicks,
+}
 
 #[derive(Debug)]
 pub enum ToArtnetManagerMessage {
-    AddUniverse(Arc<str>, defs::UniverseDefinition, Sender<Result<(), ArtnetError>>),
-    RemoveUniverse(Arc<str>, Sender<Result<(), ArtnetError>>),
+    AddUniverse(Arc<str>, defs::UniverseDefinition, Sender<Result<Vec<String>, ArtnetError>>),      // Warnings about the added universe
+    ValidateUniverse(Arc<str>, defs::UniverseDefinition, Sender<Result<(), ArtnetError>>),      // Check the definition without adding it
+    RemoveUniverse(Arc<str>, Sender<Result<Vec<Arc<str>>, ArtnetError>>),      // Replies with the ids of the stopped effects
+    ClearAll(Sender<Result<Vec<Arc<str>>, ArtnetError>>),      // Reset command, replies with the stopped effects
+    RenameUniverse(Arc<str>, Arc<str>, Sender<Result<(), ArtnetError>>),      // From, to
+    BlackoutUniverse(defs::UniverseTarget, Sender<Result<(), ArtnetError>>),
+    RestoreUniverse(defs::UniverseTarget, Sender<Result<(), ArtnetError>>),
+    SetSendEnabled(defs::UniverseTarget, bool, Sender<Result<(), ArtnetError>>),      // Enable or disable sending (overrides disable_send)
+    SetUniverseGroup(Arc<str>, Option<defs::UniverseGroupDefinition>, Sender<Result<(), ArtnetError>>),      // None removes the group
 
-    StartEffect(Arc<str>, Box<dyn EffectNodeRuntime>, Sender<Result<(), ArtnetError>>),
-    StopEffect(Arc<str>, Sender<Result<(), ArtnetError>>),
+    StartEffect(StartEffectRequest, Sender<Result<(), ArtnetError>>),
+    StopEffects(Arc<str>, defs::StopScope, Option<EffectUsage>, Sender<Result<Vec<Arc<str>>, ArtnetError>>),
+    PauseEffects(Option<Arc<str>>, bool, Sender<Result<Vec<Arc<str>>, ArtnetError>>),     // Effect id (None for all), pause or resume
+    WaitEffectDone(Arc<str>, Sender<()>),      // The sender is dropped when the active effect with this id completes, is stopped or is replaced
 
     SetChannels(defs::SetChannelsParameters, Sender<Result<(), ArtnetError>>),
+    SetChannelsBatch(Vec<defs::SetChannelsParameters>, Sender<Result<(), ArtnetError>>),
+    SetChannelAlias(defs::AliasCommandParameters, Sender<Result<(), ArtnetError>>),
+    SetFrame(defs::SetFrameCommandParameters, Sender<Result<(), ArtnetError>>),
+    CopyChannels(defs::CopyCommandParameters, Sender<Result<(), ArtnetError>>),
+
+    GetEffectStatus(Arc<str>, bool, Sender<Result<defs::EffectStatus, ArtnetError>>),     // Effect id, verbose
+
+    SetChannelLimits(Arc<str>, Option<Arc<ChannelLimits>>, Sender<Result<(), ArtnetError>>),
+    SetArrayEpoch(Arc<str>, ArrayEpoch, Sender<Result<(), ArtnetError>>),
+    RenameArrayEffects(Arc<str>, Arc<str>, Sender<Result<Vec<Arc<str>>, ArtnetError>>),      // Move effects, limits and values of a renamed array (replies with the new ids of the moved effects)
+    SetEffectValue(Option<Arc<str>>, Arc<str>, Option<String>, Sender<Result<(), ArtnetError>>),     // Array (None for global), value name, value (None removes)
+    GetUniverseSendStatus(defs::UniverseTarget, Sender<Result<UniversesSendStatus, ArtnetError>>),
+    SetWatcher(Arc<str>, Option<defs::WatcherDefinition>, Sender<Result<(), ArtnetError>>),      // None removes the watcher
+    SetMonitor(Arc<str>, Option<defs::MonitorDefinition>, Sender<Result<(), ArtnetError>>),      // None removes the monitor
+    GetDiagnostics(Sender<defs::ArtnetManagerDiagnostics>),
+    GetEffectStats(bool, Sender<Vec<defs::EffectStatsEntry>>),      // Reset the counters after getting them
+    GetUniverseDefinitionHash(Arc<str>, Sender<Option<String>>),      // None if the universe is not defined
 }
 
 #[derive(Debug)]
 pub enum ToMqttPublisherMessage {
-    Error(String),
+    Error(String, Option<Arc<str>>, ErrorCategory),       // Error, the correlation id of the command that failed and the error category
+    Warning(String),                       // Published to DMX/Error with warning severity (LastError is not changed)
+    CommandAck(defs::CommandAck),
+    EffectStatus(Arc<str>, defs::EffectStatus),
+    ArrayLastError(Arc<str>, Option<String>),      // None clears the array last error
+    ExportedEffects(BTreeMap<Arc<str>, defs::EffectDefinition>),
+    StoppedEffects(Arc<str>, Vec<Arc<str>>),       // Effects stopped by Stop command on array
+    UniverseSendStatus(Arc<str>, defs::UniverseSendStatus),
+    Schedules(BTreeMap<Arc<str>, defs::ScheduleDefinition>),
+    DimmingPresets(BTreeMap<Arc<str>, defs::DimmingPresetDefinition>),
+    Publish(Arc<str>, Arc<str>),       // Topic and payload of a fired watcher
+    Diagnostics(Box<defs::Diagnostics>),
+    EffectStats(Vec<defs::EffectStatsEntry>),
+    Latency(Vec<defs::CommandLatencyEntry>),
+    EffectiveConfig(ServiceSettings),      // Published (retained) after DMX/Config is handled
+    Verify(defs::VerifyResult),
+    Validate(defs::ValidateResult),
+    ResolveEffect(defs::ResolveEffectResult),
+    Patch(defs::Patch, defs::PatchFormat),
+    Schema(defs::Schema),      // Published (retained) to DMX/Schema
+    Resubscribe,       // Subscribe to DMX/# again, so the retained definitions are received again (Reset command)
+}
+
+#[derive(Debug)]
+pub enum ToSchedulerMessage {
+    SetSchedule(Arc<str>, Option<defs::ScheduleDefinition>, Sender<Result<(), SchedulerError>>),      // None removes the schedule
+    GetSchedules(Sender<BTreeMap<Arc<str>, defs::ScheduleDefinition>>),
+    ClearAll(Sender<()>),      // Reset command
 }
 
 #[derive(Debug)]
 pub enum ToArrayManagerMessage {
-    AddArray(Arc<str>, Box<defs::DmxArray>, Sender<Result<(), DmxArrayError>>),
-    RemoveArray(Arc<str>, Sender<Result<(), DmxArrayError>>),
+    AddArray(Arc<str>, Box<defs::DmxArray>, Sender<Result<AddedArray, DmxArrayError>>),
+    SetArrayTemplate(Arc<str>, Option<serde_json::Value>, Sender<Result<(), DmxArrayError>>),      // None removes the template
+    InstantiateArrayTemplate(defs::ArrayTemplateInstance, Sender<Result<serde_json::Value, DmxArrayError>>),      // Replies with the array definition
+    ValidateArray(Arc<str>, Box<defs::DmxArray>, Sender<ValidationProblems>),      // Check the definition without adding it
+    RemoveArray(Arc<str>, Sender<Result<ArrayEpoch, DmxArrayError>>),
+    ClearAll(Sender<Result<RemovedArrays, DmxArrayError>>),      // Reset command
+    RenameArray(Arc<str>, Arc<str>, Sender<Result<(ArrayEpoch, ArrayEpoch), DmxArrayError>>),     // From, to (replies with the epochs of both IDs)
+    GetArrayLimits(Arc<str>, Sender<Result<Arc<ChannelLimits>, DmxArrayError>>),
+    SetArrayState(Arc<str>, EffectUsage, Option<DimmingAmount>, Sender<Result<(), DmxArrayError>>),
+    GetArrayState(Arc<str>, Sender<Result<Option<ArrayState>, DmxArrayError>>),
+    GetLinkedEffects(Arc<str>, Sender<Result<Vec<Arc<str>>, DmxArrayError>>),
+    GetPreCommands(Arc<str>, Sender<Result<Vec<defs::PreCommand>, DmxArrayError>>),
+    ResolveEffect(Arc<str>, EffectUsage, Option<Arc<str>>, Sender<Result<defs::EffectResolution, DmxArrayError>>),     // Array, usage, effect id
 
-    AddEffect(Arc<str>, defs::EffectNodeDefinition, Sender<Result<(), DmxArrayError>>),
-    RemoveEffect(Arc<str>, Sender<Result<(), DmxArrayError>>),
+    AddEffect(Arc<str>, defs::EffectDefinition, Sender<Result<(), DmxArrayError>>),
+    ValidateEffect(Arc<str>, defs::EffectDefinition, Sender<ValidationProblems>),      // Check the effect with the arrays using it
+    RemoveEffect(Arc<str>, bool, Sender<Result<(), DmxArrayError>>),
+    AddFixture(Arc<str>, defs::FixtureDefinition, Sender<Result<(), DmxArrayError>>),
+    RemoveFixture(Arc<str>, Sender<Result<(), DmxArrayError>>),
+    GetEffects(Sender<Result<BTreeMap<Arc<str>, defs::EffectDefinition>, DmxArrayError>>),
+    GetArrayDefinitionHash(Arc<str>, Sender<Option<String>>),       // None if the array is not defined
+    GetEffectDefinitionHash(Arc<str>, Sender<Option<String>>),      // None if the global effect is not defined
 
-    GetEffectRuntime(Arc<str>, EffectUsage, Option<Arc<str>>, usize, Sender<Result<Box<dyn EffectNodeRuntime>, DmxArrayError>>),
+    GetEffectRuntime(Arc<str>, EffectUsage, Option<Arc<str>>, Option<String>, Option<DimmingAmount>, Sender<Result<ArrayEffectRuntime, DmxArrayError>>),     // Array, usage, effect id, light group, dimming amount
+
+    GetInstantOffRuntime(Arc<str>, Option<String>, Sender<Result<ArrayEffectRuntime, DmxArrayError>>),     // Array, light group (None for all lights)
+
+    GetInlineEffectRuntime(String, defs::EffectNodeDefinition, usize, Sender<Result<Box<dyn EffectNodeRuntime>, DmxArrayError>>),
 
     InitializeArrayValues(Arc<str>, SymbolTable, Sender<Result<(), DmxArrayError>>),
-    AddGlobalValue(Arc<str>, Arc<str>, Sender<Result<(), DmxArrayError>>),
+    AddGlobalValue(Arc<str>, defs::SymbolValue, bool, Sender<Result<Vec<defs::RetriggerEffect>, DmxArrayError>>),      // Value name, value, retrigger (replies with the effects to start again)
     RemoveGlobalValue(Arc<str>, Sender<Result<(), DmxArrayError>>),
+
+    SetDimmingPreset(Arc<str>, Option<defs::DimmingPresetDefinition>, Sender<Result<(), DmxArrayError>>),      // None removes the preset
+    ResolveDimmingAmount(Option<defs::DimmingAmountOrPreset>, Sender<Result<Option<DimmingAmount>, DmxArrayError>>),      // Command dimming amount (None for the array default)
+    GetDimmingPresets(Sender<BTreeMap<Arc<str>, defs::DimmingPresetDefinition>>),
+
+    GetDiagnostics(bool, Sender<defs::ArrayManagerDiagnostics>),      // Include value strings
+    GetPatch(Sender<defs::Patch>),
+    GetUnresolvedEffects(Sender<UnresolvedEffects>),
 }
@@ -0,0 +1,146 @@
+// Counters and gauges served in Prometheus text exposition format by the HTTP status endpoint (GET /metrics). The
+// artnet manager counts sent packets, send failures and tick durations, the subscriber counts commands. Counters are
+// kept for the lifetime of the service (a removed universe keeps its counters) and are never reset
+
+use std::{collections::BTreeMap, sync::{Arc, Mutex}, time::Duration};
+
+#[derive(Debug, Default)]
+struct MetricValues {
+    packets_sent: BTreeMap<Arc<str>, u64>,      // Universe ID -> packets sent to its controller
+    send_failures: BTreeMap<Arc<str>, u64>,
+    commands: BTreeMap<Arc<str>, u64>,      // Command (On, Off...) -> commands handled (including failed ones)
+    command_errors: BTreeMap<Arc<str>, u64>,
+    active_effects: usize,
+    ticks: u64,
+    tick_duration_sum: Duration,
+    max_tick_duration: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    values: Mutex<MetricValues>,
+}
+
+fn increment(counters: &mut BTreeMap<Arc<str>, u64>, label: &str) {
+    match counters.get_mut(label) {
+        Some(count) => *count += 1,
+        None => {
+            counters.insert(Arc::from(label), 1);
+        }
+    }
+}
+
+// Label values may contain any character, backslash, double quote and line feed are escaped
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn write_header(text: &mut String, name: &str, kind: &str, help: &str) {
+    text.push_str(&format!("# HELP {name} {help}\n"));
+    text.push_str(&format!("# TYPE {name} {kind}\n"));
+}
+
+fn write_counters(text: &mut String, name: &str, help: &str, label: &str, counters: &BTreeMap<Arc<str>, u64>) {
+    write_header(text, name, "counter", help);
+
+    for (label_value, count) in counters {
+        text.push_str(&format!("{name}{{{label}=\"{}\"}} {count}\n", escape_label_value(label_value)));
+    }
+}
+
+impl Metrics {
+    pub fn add_packet_sent(&self, universe_id: &str) {
+        increment(&mut self.values.lock().unwrap().packets_sent, universe_id);
+    }
+
+    pub fn add_send_failure(&self, universe_id: &str) {
+        increment(&mut self.values.lock().unwrap().send_failures, universe_id);
+    }
+
+    pub fn add_command(&self, command: &str, succeeded: bool) {
+        let mut values = self.values.lock().unwrap();
+
+        increment(&mut values.commands, command);
+        if !succeeded {
+            increment(&mut values.command_errors, command);
+        }
+    }
+
+    pub fn add_tick(&self, duration: Duration, active_effects: usize) {
+        let mut values = self.values.lock().unwrap();
+
+        values.ticks += 1;
+        values.tick_duration_sum += duration;
+        values.max_tick_duration = values.max_tick_duration.max(duration);
+        values.active_effects = active_effects;
+    }
+
+    // Prometheus text exposition format (version 0.0.4)
+    pub fn render(&self) -> String {
+        let values = self.values.lock().unwrap();
+        let mut text = String::new();
+
+        write_counters(&mut text, "packets_sent_total", "Art-Net packets sent to the universe controller", "universe", &values.packets_sent);
+        write_counters(&mut text, "send_failures_total", "Art-Net packets that failed to be sent", "universe", &values.send_failures);
+        write_counters(&mut text, "commands_total", "Commands handled (DMX/Command/<type>)", "type", &values.commands);
+        write_counters(&mut text, "command_errors_total", "Commands that failed", "type", &values.command_errors);
+
+        write_header(&mut text, "active_effects", "gauge", "Effects running after the last tick");
+        text.push_str(&format!("active_effects {}\n", values.active_effects));
+
+        write_header(&mut text, "tick_duration_seconds", "summary", "Time taken by ticks (effects, watchers, monitors and sending)");
+        text.push_str(&format!("tick_duration_seconds_sum {}\n", values.tick_duration_sum.as_secs_f64()));
+        text.push_str(&format!("tick_duration_seconds_count {}\n", values.ticks));
+
+        write_header(&mut text, "tick_duration_max_seconds", "gauge", "Longest tick since the service started");
+        text.push_str(&format!("tick_duration_max_seconds {}\n", values.max_tick_duration.as_secs_f64()));
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod test_metrics {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+
+        // Families without samples still have their header
+        let text = metrics.render();
+        assert!(text.contains("# HELP packets_sent_total Art-Net packets sent to the universe controller\n# TYPE packets_sent_total counter\n# HELP"));
+        assert!(text.contains("active_effects 0\n"));
+        assert!(text.contains("tick_duration_seconds_count 0\n"));
+
+        metrics.add_packet_sent("kitchen");
+        metrics.add_packet_sent("kitchen");
+        metrics.add_packet_sent("garden");
+        metrics.add_send_failure("say \"hi\"\\\n");
+        metrics.add_command("On", true);
+        metrics.add_command("On", false);
+        metrics.add_command("Off", true);
+        metrics.add_tick(Duration::from_millis(10), 3);
+        metrics.add_tick(Duration::from_millis(30), 2);
+
+        let text = metrics.render();
+        let samples = text.lines().filter(|line| !line.starts_with('#')).collect::<Vec<_>>();
+
+        assert_eq!(samples, vec![
+            "packets_sent_total{universe=\"garden\"} 1",
+            "packets_sent_total{universe=\"kitchen\"} 2",
+            "send_failures_total{universe=\"say \\\"hi\\\"\\\\\\n\"} 1",
+            "commands_total{type=\"Off\"} 1",
+            "commands_total{type=\"On\"} 2",
+            "command_errors_total{type=\"On\"} 1",
+            "active_effects 2",
+            "tick_duration_seconds_sum 0.04",
+            "tick_duration_seconds_count 2",
+            "tick_duration_max_seconds 0.03",
+        ]);
+
+        // Every family has one HELP and one TYPE line
+        assert_eq!(text.lines().filter(|line| line.starts_with("# TYPE")).count(), 7);
+        assert!(text.ends_with('\n'));
+    }
+}
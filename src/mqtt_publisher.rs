@@ -1,15 +1,193 @@
-use error_stack::{ResultExt, Result};
+use error_stack::{Context, Report, ResultExt, Result};
 use async_channel::Receiver;
 use rumqttc::AsyncClient;
 use serde::Serialize;
-use log::{error, info};
+use log::{error, info, warn};
+use std::sync::Arc;
 
-use crate::{messages::ToMqttPublisherMessage, service::MqttError};
+use crate::{
+    array_manager::DmxArrayError,
+    artnet_manager::ArtnetError,
+    defs,
+    messages::ToMqttPublisherMessage,
+    scheduler::SchedulerError,
+    service::MqttError,
+};
+
+// Errors are published to DMX/Error and also to the subtopic of their category, so alerts can tell definition
+// mistakes from command mistakes and from runtime (hardware) faults
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    Config,         // Universe, array, effect (and other) definition problems
+    Command,        // Invalid command payloads, unknown arrays or effects
+    Runtime,        // Send failures, effects that failed while running
+}
+
+impl ErrorCategory {
+    pub fn get_topic(&self) -> &'static str {
+        match self {
+            ErrorCategory::Config => "DMX/Error/Config",
+            ErrorCategory::Command => "DMX/Error/Command",
+            ErrorCategory::Runtime => "DMX/Error/Runtime",
+        }
+    }
+}
+
+// Category of an error, given by the first error in the report (from the outermost context) whose category does not
+// depend on where it happened. Otherwise (e.g. JSON parse errors) the category of the message being handled is used
+pub fn get_error_category<C: Context>(e: &Report<C>, default_category: ErrorCategory) -> ErrorCategory {
+    e.frames()
+        .find_map(|frame| {
+            if let Some(e) = frame.downcast_ref::<MqttError>() {
+                get_mqtt_error_category(e)
+            } else if let Some(e) = frame.downcast_ref::<ArtnetError>() {
+                get_artnet_error_category(e)
+            } else if let Some(e) = frame.downcast_ref::<DmxArrayError>() {
+                get_array_error_category(e)
+            } else if let Some(e) = frame.downcast_ref::<SchedulerError>() {
+                get_scheduler_error_category(e)
+            } else {
+                None
+            }
+        })
+        .unwrap_or(default_category)
+}
+
+fn get_mqtt_error_category(e: &MqttError) -> Option<ErrorCategory> {
+    match e {
+        MqttError::MissingUniverseId(..)
+        | MqttError::MissingArrayId(..)
+        | MqttError::TooManyTopicLevels(..)
+        | MqttError::InvalidId(..)
+        | MqttError::WrongDefinitionTopic(..)
+        | MqttError::ImportEffectsFailed(..)
+        | MqttError::InvalidConfig(..) => Some(ErrorCategory::Config),
+
+        MqttError::MissingSubtopic
+        | MqttError::InvalidSubtopic(..)
+        | MqttError::MissingCommand
+        | MqttError::InvalidCommand(..)
+        | MqttError::InstantNotOff(..)
+        | MqttError::MissingArrayIdOrAll(..)
+        | MqttError::MissingUniverseIdOrGroup(..)
+        | MqttError::MissingArrayOrInlineEffect(..)
+        | MqttError::ResetNotConfirmed => Some(ErrorCategory::Command),
+
+        MqttError::Context(..) | MqttError::JsonParseError(..) | MqttError::MissingField(..) | MqttError::PayloadTooLarge(..) => None,
+    }
+}
+
+fn get_artnet_error_category(e: &ArtnetError) -> Option<ErrorCategory> {
+    match e {
+        ArtnetError::InvalidUniverseNumber(..)
+        | ArtnetError::InvalidUniverseId(..)
+        | ArtnetError::InvalidSubnet(..)
+        | ArtnetError::InvalidNet(..)
+        | ArtnetError::MissingPortAddress(..)
+        | ArtnetError::InconsistentPortAddress(..)
+        | ArtnetError::DuplicatePortAddress(..)
+        | ArtnetError::UniverseGroupMemberNotFound(..)
+        | ArtnetError::EmptyUniverseGroup(..)
+        | ArtnetError::TooManyChannels(..)
+        | ArtnetError::NoChannels
+        | ArtnetError::InvalidPadTo(..)
+        | ArtnetError::InvalidSendDivisor(..)
+        | ArtnetError::InvalidKeepaliveBurst(..)
+        | ArtnetError::InvalidQuietHours(..)
+        | ArtnetError::ConflictingInitialValues(..)
+        | ArtnetError::InvalidInitialData(..)
+        | ArtnetError::InitialDataOutOfRange(..)
+        | ArtnetError::EffectUniverseNotFound(..)
+        | ArtnetError::InvalidWatcherCondition(..)
+        | ArtnetError::InvalidWatcherChannel(..)
+        | ArtnetError::InvalidWatcherTopic(..)
+        | ArtnetError::InvalidMonitorChannel(..)
+        | ArtnetError::InvalidMonitorTopic(..) => Some(ErrorCategory::Config),
+
+        ArtnetError::InvalidUniverse(..)
+        | ArtnetError::UniverseAlreadyDefined(..)
+        | ArtnetError::UniverseGroupNotFound(..)
+        | ArtnetError::InvalidChannel(..)
+        | ArtnetError::MissingTargetValue(..)
+        | ArtnetError::MissingTargetValues(..)
+        | ArtnetError::InvalidChannelAlias(..)
+        | ArtnetError::ChannelAliasCollision(..)
+        | ArtnetError::InvalidFrameData(..)
+        | ArtnetError::FrameOutOfRange(..)
+        | ArtnetError::FrameUniverseInUse(..)
+        | ArtnetError::InvalidCopyChannels(..)
+        | ArtnetError::CopyOutOfRange(..)
+        | ArtnetError::InvalidSetChannelsBatchEntry(..)
+        | ArtnetError::ChannelValueMismatch(..)
+        | ArtnetError::EffectNotActive(..)
+        | ArtnetError::MissingStopUsage(..)
+        | ArtnetError::WatcherNotFound(..)
+        | ArtnetError::MonitorNotFound(..)
+        | ArtnetError::UnresolvedDimmingPreset(..) => Some(ErrorCategory::Command),
+
+        ArtnetError::StaleArrayEpoch(..)
+        | ArtnetError::EffectFailed(..)
+        | ArtnetError::WaitForTimeout(..)
+        | ArtnetError::EffectTickBudgetExceeded(..) => Some(ErrorCategory::Runtime),
+
+        ArtnetError::Context(..) | ArtnetError::Parse(..) => None,
+    }
+}
+
+fn get_array_error_category(e: &DmxArrayError) -> Option<ErrorCategory> {
+    match e {
+        DmxArrayError::ArrayNotFound(..)
+        | DmxArrayError::ArrayAlreadyDefined(..)
+        | DmxArrayError::ArrayLightGroupNotFound(..)
+        | DmxArrayError::DimmingPresetNotFound(..) => Some(ErrorCategory::Command),
+
+        DmxArrayError::EffectNotFound(..) | DmxArrayError::ValueError(..) | DmxArrayError::ValueTypeMismatch(..) | DmxArrayError::Parse(..) => None,
+
+        DmxArrayError::ArrayTemplateNotFound(..)
+        | DmxArrayError::InvalidArrayTemplate(..)
+        | DmxArrayError::MissingTemplateParameter(..)
+        | DmxArrayError::InvalidTemplatePlaceholder(..)
+        | DmxArrayError::InvalidArrayId(..)
+        | DmxArrayError::ArrayMissingUniverseId(..)
+        | DmxArrayError::ArrayLightsNotFound(..)
+        | DmxArrayError::ArrayLightsCircularReference(..)
+        | DmxArrayError::ArrayLightsNestingTooDeep(..)
+        | DmxArrayError::ArrayLightsInvalidChannel(..)
+        | DmxArrayError::ArrayLightsInvalidComponent(..)
+        | DmxArrayError::ArrayLightsFixtureNotFound(..)
+        | DmxArrayError::ArrayLightsInvalidExpression(..)
+        | DmxArrayError::InvalidFixture(..)
+        | DmxArrayError::FixtureInUse(..)
+        | DmxArrayError::EffectInUse(..)
+        | DmxArrayError::ArrayValueNotFound(..)
+        | DmxArrayError::StrictValueNotDeclared(..)
+        | DmxArrayError::ValueExpressionNotTerminated(..)
+        | DmxArrayError::ArrayLightChannelUsageMismatch(..)
+        | DmxArrayError::ArrayLightChannelNotInAllGroup(..)
+        | DmxArrayError::ArrayLimitGroupNotFound(..)
+        | DmxArrayError::ArrayInvalidLimit(..)
+        | DmxArrayError::ArrayGroupDimmingGroupNotFound(..)
+        | DmxArrayError::ArrayInvalidGroupDimming(..)
+        | DmxArrayError::InvalidDimmingPreset(..)
+        | DmxArrayError::ArrayPreCycle(..) => Some(ErrorCategory::Config),
+    }
+}
+
+fn get_scheduler_error_category(e: &SchedulerError) -> Option<ErrorCategory> {
+    match e {
+        SchedulerError::ScheduleNotFound(..) => Some(ErrorCategory::Command),
+        SchedulerError::MissingScheduleTime(..) | SchedulerError::InvalidCron(..) | SchedulerError::InvalidDailyAt(..) => Some(ErrorCategory::Config),
+    }
+}
 
 #[derive(Serialize, Debug)]
 struct MqttErrorMessageBody {
     time: String,
     message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    severity: Option<&'static str>,     // Set only for warnings
 }
 
 pub async fn session(mqtt_client: AsyncClient, to_mqtt_publisher_rx: Receiver<ToMqttPublisherMessage>) -> Result<(), MqttError> {
@@ -18,10 +196,12 @@ pub async fn session(mqtt_client: AsyncClient, to_mqtt_publisher_rx: Receiver<To
 
     loop {
         match to_mqtt_publisher_rx.recv().await.change_context_lazy(into_context)? {
-            ToMqttPublisherMessage::Error(error) => {
+            ToMqttPublisherMessage::Error(error, correlation_id, category) => {
                 let error_message_body = MqttErrorMessageBody {
                     time: chrono::Utc::now().to_rfc3339(),
                     message: error,
+                    correlation_id,
+                    severity: None,
                 };
 
                 error!("Error: {:?}", error_message_body);
@@ -29,7 +209,146 @@ pub async fn session(mqtt_client: AsyncClient, to_mqtt_publisher_rx: Receiver<To
                 let error_message_body = serde_json::to_vec(&error_message_body).change_context_lazy(into_context)?;
 
                 mqtt_client.publish("DMX/LastError", rumqttc::QoS::AtLeastOnce, true, error_message_body.clone()).await.change_context_lazy(into_context)?;
-                mqtt_client.publish("DMX/Error", rumqttc::QoS::AtLeastOnce, false, error_message_body).await.change_context_lazy(into_context)?;
+                mqtt_client.publish("DMX/Error", rumqttc::QoS::AtLeastOnce, false, error_message_body.clone()).await.change_context_lazy(into_context)?;
+                mqtt_client.publish(category.get_topic(), rumqttc::QoS::AtLeastOnce, false, error_message_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::Warning(warning) => {
+                let warning_message_body = MqttErrorMessageBody {
+                    time: chrono::Utc::now().to_rfc3339(),
+                    message: warning,
+                    correlation_id: None,
+                    severity: Some("warning"),
+                };
+
+                warn!("Warning: {:?}", warning_message_body);
+
+                let warning_message_body = serde_json::to_vec(&warning_message_body).change_context_lazy(into_context)?;
+                mqtt_client.publish("DMX/Error", rumqttc::QoS::AtLeastOnce, false, warning_message_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::EffectStatus(array_id, effect_status) => {
+                let effect_status_body = serde_json::to_vec(&effect_status).change_context_lazy(into_context)?;
+
+                mqtt_client.publish(format!("DMX/Array/{array_id}/EffectStatus"), rumqttc::QoS::AtLeastOnce, false, effect_status_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::ArrayLastError(array_id, error) => {
+                let topic = format!("DMX/Array/{array_id}/LastError");
+
+                let error_message_body = match error {
+                    Some(error) => serde_json::to_vec(&MqttErrorMessageBody {
+                        time: chrono::Utc::now().to_rfc3339(),
+                        message: error,
+                        correlation_id: None,
+                        severity: None,
+                    }).change_context_lazy(into_context)?,
+                    None => Vec::new(),     // Empty retained message clears the last error
+                };
+
+                mqtt_client.publish(topic, rumqttc::QoS::AtLeastOnce, true, error_message_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::StoppedEffects(array_id, effect_ids) => {
+                let effect_ids_body = serde_json::to_vec(&effect_ids).change_context_lazy(into_context)?;
+
+                mqtt_client.publish(format!("DMX/Array/{array_id}/StoppedEffects"), rumqttc::QoS::AtLeastOnce, false, effect_ids_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::UniverseSendStatus(universe_id, send_status) => {
+                let send_status_body = serde_json::to_vec(&send_status).change_context_lazy(into_context)?;
+
+                mqtt_client.publish(format!("DMX/Universe/{universe_id}/SendStatus"), rumqttc::QoS::AtLeastOnce, true, send_status_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::Schedules(schedules) => {
+                let schedules_body = serde_json::to_vec(&schedules).change_context_lazy(into_context)?;
+
+                mqtt_client.publish("DMX/Schedules", rumqttc::QoS::AtLeastOnce, false, schedules_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::EffectStats(entries) => {
+                let entries_body = serde_json::to_vec(&entries).change_context_lazy(into_context)?;
+
+                mqtt_client.publish("DMX/EffectStats", rumqttc::QoS::AtLeastOnce, false, entries_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::Latency(entries) => {
+                let entries_body = serde_json::to_vec(&entries).change_context_lazy(into_context)?;
+
+                mqtt_client.publish("DMX/Latency", rumqttc::QoS::AtLeastOnce, false, entries_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::EffectiveConfig(settings) => {
+                let settings_body = serde_json::to_vec(&settings).change_context_lazy(into_context)?;
+
+                mqtt_client.publish("DMX/Config/Effective", rumqttc::QoS::AtLeastOnce, true, settings_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::DimmingPresets(presets) => {
+                let presets_body = serde_json::to_vec(&presets).change_context_lazy(into_context)?;
+
+                mqtt_client.publish("DMX/DimmingPresets", rumqttc::QoS::AtLeastOnce, false, presets_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::CommandAck(ack) => {
+                let ack_body = serde_json::to_vec(&ack).change_context_lazy(into_context)?;
+
+                mqtt_client.publish("DMX/Ack", rumqttc::QoS::AtLeastOnce, false, ack_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::Publish(topic, payload) => {
+                mqtt_client.publish(topic.as_ref(), rumqttc::QoS::AtLeastOnce, false, payload.as_bytes().to_vec()).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::Diagnostics(diagnostics) => {
+                let diagnostics_body = serde_json::to_vec_pretty(&diagnostics).change_context_lazy(into_context)?;
+
+                mqtt_client.publish("DMX/Diagnostics", rumqttc::QoS::AtLeastOnce, false, diagnostics_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::Verify(verify_result) => {
+                let verify_result_body = serde_json::to_vec(&verify_result).change_context_lazy(into_context)?;
+
+                mqtt_client.publish("DMX/Verify", rumqttc::QoS::AtLeastOnce, false, verify_result_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::Validate(validate_result) => {
+                let validate_result_body = serde_json::to_vec(&validate_result).change_context_lazy(into_context)?;
+
+                mqtt_client.publish("DMX/Validate", rumqttc::QoS::AtLeastOnce, false, validate_result_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::ResolveEffect(resolve_effect_result) => {
+                let resolve_effect_result_body = serde_json::to_vec(&resolve_effect_result).change_context_lazy(into_context)?;
+
+                mqtt_client.publish("DMX/ResolveEffect", rumqttc::QoS::AtLeastOnce, false, resolve_effect_result_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::ExportedEffects(effects) => {
+                let effects_body = serde_json::to_vec(&effects).change_context_lazy(into_context)?;
+
+                mqtt_client.publish("DMX/ExportedEffects", rumqttc::QoS::AtLeastOnce, false, effects_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::Patch(patch, format) => {
+                let patch_body = match format {
+                    defs::PatchFormat::Json => serde_json::to_vec_pretty(&patch).change_context_lazy(into_context)?,
+                    defs::PatchFormat::Csv => patch.to_csv().into_bytes(),
+                };
+
+                mqtt_client.publish("DMX/Patch", rumqttc::QoS::AtLeastOnce, false, patch_body).await.change_context_lazy(into_context)?;
+            }
+
+            ToMqttPublisherMessage::Schema(schema) => {
+                let schema_body = serde_json::to_vec(&schema).change_context_lazy(into_context)?;
+
+                mqtt_client.publish("DMX/Schema", rumqttc::QoS::AtLeastOnce, true, schema_body).await.change_context_lazy(into_context)?;
+            }
+
+            // Subscribing again to the same topic filter makes the broker send its retained messages again
+            ToMqttPublisherMessage::Resubscribe => {
+                mqtt_client.subscribe("DMX/#", rumqttc::QoS::AtLeastOnce).await.change_context_lazy(into_context)?;
             }
         }
     }
@@ -41,19 +360,54 @@ mod test {
     use tokio::time::{sleep, Duration};
     use rumqttc::{AsyncClient, MqttOptions};
 
+    #[test]
+    fn test_error_category() {
+        let context = || MqttError::Context("handling message".to_string());
+
+        // Errors wrapped in neutral contexts are categorized by the wrapped error
+        let e = Report::new(ArtnetError::EffectFailed("blink".to_string(), String::new(), "failed".to_string(), String::new())).change_context(context());
+        assert_eq!(get_error_category(&e, ErrorCategory::Command), ErrorCategory::Runtime);
+
+        let e = Report::new(ArtnetError::InvalidSubnet(20)).change_context(context());
+        assert_eq!(get_error_category(&e, ErrorCategory::Command), ErrorCategory::Config);
+
+        let e = Report::new(ArtnetError::InvalidUniverse("5".to_string()));
+        assert_eq!(get_error_category(&e, ErrorCategory::Config), ErrorCategory::Command);
+
+        let e = Report::new(DmxArrayError::ArrayNotFound(Arc::from("lounge"))).change_context(context());
+        assert_eq!(get_error_category(&e, ErrorCategory::Config), ErrorCategory::Command);
+
+        let e = Report::new(DmxArrayError::ArrayLightsNotFound("lounge".to_string(), "@all".to_string(), "spot".to_string()));
+        assert_eq!(get_error_category(&e, ErrorCategory::Command), ErrorCategory::Config);
+
+        let e = Report::new(MqttError::InvalidCommand("Blink".to_string()));
+        assert_eq!(get_error_category(&e, ErrorCategory::Config), ErrorCategory::Command);
+
+        let e = Report::new(SchedulerError::InvalidDailyAt(Arc::from("morning"), "25:00".to_string())).change_context(context());
+        assert_eq!(get_error_category(&e, ErrorCategory::Command), ErrorCategory::Config);
+
+        // Parse errors depend on the message being handled
+        let parse_error = serde_json::from_str::<u8>("x").unwrap_err();
+        let e = Report::new(MqttError::JsonParseError(Arc::from("array"), Arc::from("x"), parse_error));
+        assert_eq!(get_error_category(&e, ErrorCategory::Config), ErrorCategory::Config);
+        assert_eq!(get_error_category(&e, ErrorCategory::Command), ErrorCategory::Command);
+
+        assert_eq!(ErrorCategory::Runtime.get_topic(), "DMX/Error/Runtime");
+    }
+
     #[tokio::test]
     async fn test_mqtt_publisher() {
         let mut mqtt_options = MqttOptions::new("DMX", "localhost", 1883);
         mqtt_options.set_keep_alive(Duration::from_secs(5));
         let (mqtt_client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
 
-        let (to_mqtt_publisher_tx, to_mqtt_publisher_rx) = tokio::sync::mpsc::channel::<ToMqttPublisherMessage>(10);
+        let (to_mqtt_publisher_tx, to_mqtt_publisher_rx) = async_channel::bounded::<ToMqttPublisherMessage>(10);
 
-        let _ = tokio::spawn(async move {
-            session(mqtt_client, to_mqtt_publisher_rx).await;
+        tokio::spawn(async move {
+            let _ = session(mqtt_client, to_mqtt_publisher_rx).await;
         });
 
-        to_mqtt_publisher_tx.send(ToMqttPublisherMessage::Error("Test error".to_string())).await.unwrap();
+        to_mqtt_publisher_tx.send(ToMqttPublisherMessage::Error("Test error".to_string(), None, ErrorCategory::Command)).await.unwrap();
 
         let timeout = sleep(Duration::from_millis(500));
         tokio::pin!(timeout);
@@ -1,62 +1,372 @@
-use error_stack::{Result, ResultExt};
-use std::sync::Arc;
+use error_stack::{Report, Result, ResultExt};
+use std::{collections::{BTreeMap, HashMap}, sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc, Mutex}, time::{Duration, Instant}};
 
 use bytes::Bytes;
-use log::{error, info};
+use log::{debug, error, info};
 use rumqttc::{EventLoop, Packet};
-use tokio::sync::{mpsc::Sender, oneshot};
+use tokio::sync::{mpsc::Sender, oneshot, watch};
 
 use crate::{
     array_manager::DmxArrayError,
+    command_latency::CommandLatency,
     artnet_manager::{ArtnetError, EffectNodeRuntime},
-    defs::{self, EffectNodeDefinition, DIMMING_AMOUNT_MAX},
-    defs::{EffectUsage, UniverseDefinition},
+    defs::{self, DIMMING_AMOUNT_MAX},
+    defs::{ArrayEpoch, ArrayState, DimmingAmount, EffectUsage, UniverseDefinition},
+    dmx::ChannelLimits,
+    get_version,
+    manager_channel::ManagerSender,
     messages,
-    service::MqttError,
+    metrics::Metrics,
+    schema,
+    mqtt_publisher::{get_error_category, ErrorCategory},
+    lenient_json,
+    scheduler::{ScheduledCommand, SchedulerError},
+    service::{MqttError, ServiceSettings},
 };
 
-struct MqttSubscriber {
-    to_artnet_tx: Sender<messages::ToArtnetManagerMessage>,
-    to_array_tx: Sender<messages::ToArrayManagerMessage>,
+// Per array subtopics (DMX/Array/<array_id>/<subtopic>) published by this service
+const ARRAY_STATUS_SUBTOPICS: &[&str] = &["EffectStatus", "LastError", "StoppedEffects"];
+
+// Per universe subtopics (DMX/Universe/<universe_id>/<subtopic>) published by this service
+const UNIVERSE_STATUS_SUBTOPICS: &[&str] = &["SendStatus"];
+
+// Time after subscribing in which retained universe or array definitions are expected to be received
+pub const DEFINITIONS_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+// Payloads larger than this are rejected unless the service is configured with another limit
+pub const DEFAULT_MAX_PAYLOAD_SIZE: usize = 256 * 1024;
+
+fn validate_id(kind: &str, id: &str) -> Result<Arc<str>, MqttError> {
+    match defs::validate_id(id) {
+        Ok(()) => Ok(Arc::from(id)),
+        Err(e) => Err(MqttError::InvalidId(kind.to_string(), id.to_string(), e).into()),
+    }
+}
+
+// Name the missing field explicitly since serde error only gives its position in the JSON
+fn json_parse_error(kind: &str, id: Arc<str>, e: serde_json::Error) -> MqttError {
+    let missing_field = e
+        .to_string()
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split_once('`'))
+        .map(|(field, _)| field.to_string());
+
+    match missing_field {
+        Some(field) => MqttError::MissingField(Arc::from(kind), id, field),
+        None => MqttError::JsonParseError(Arc::from(format!("{} definition", kind.to_lowercase())), id, e),
+    }
+}
+
+// Definitions that can be posted to DMX/<kind>/<id> (kind, description, check that a payload is this definition)
+type DefinitionShape = (&'static str, &'static str, fn(&[u8]) -> bool);
+
+const DEFINITION_SHAPES: &[DefinitionShape] = &[
+    ("Universe", "a universe", |json| serde_json::from_slice::<UniverseDefinition>(json).is_ok()),
+    ("Array", "an array", |json| serde_json::from_slice::<defs::DmxArray>(json).is_ok()),
+    ("Effect", "an effect", |json| serde_json::from_slice::<defs::EffectDefinition>(json).is_ok()),
+];
+
+// A definition posted to the wrong topic (e.g. an array to DMX/Universe/<id>) gives confusing errors about missing
+// fields, so check if the payload is one of the other definitions and suggest the topic it was probably meant for
+fn definition_parse_error(kind: &str, id: Arc<str>, json: &[u8], e: serde_json::Error) -> Report<MqttError> {
+    let report = Report::new(json_parse_error(kind, id.clone(), e));
+
+    match DEFINITION_SHAPES.iter().find(|(other_kind, _, is_shape)| *other_kind != kind && is_shape(json)) {
+        Some((other_kind, description, _)) => {
+            let error = report.current_context().to_string();
+            report.change_context(MqttError::WrongDefinitionTopic(error, description, format!("DMX/{other_kind}/{id}")))
+        }
+        None => report,
+    }
+}
+
+// Attached to the error of a command that has correlation_id (see defs::CommandCorrelation)
+#[derive(Debug, Clone)]
+pub struct CorrelationId(pub Arc<str>);
+
+// The category of errors that do not tell it themselves is given by the message being handled (see get_error_category)
+fn get_error_message(e: &Report<MqttError>, default_category: ErrorCategory) -> messages::ToMqttPublisherMessage {
+    messages::ToMqttPublisherMessage::Error(
+        e.to_string(),
+        e.downcast_ref::<CorrelationId>().map(|id| id.0.clone()),
+        get_error_category(e, default_category),
+    )
+}
+
+// Category of errors handling a message posted to this topic
+fn get_topic_error_category(topic: &str) -> ErrorCategory {
+    if topic.starts_with("DMX/Command") {
+        ErrorCategory::Command
+    } else {
+        ErrorCategory::Config
+    }
+}
+
+// Universe commands use universe_id if given, otherwise universe_group
+fn get_universe_target(command: &str, universe_id: Option<Arc<str>>, universe_group: Option<Arc<str>>) -> Result<defs::UniverseTarget, MqttError> {
+    match (universe_id, universe_group) {
+        (Some(universe_id), _) => Ok(defs::UniverseTarget::Universe(universe_id)),
+        (None, Some(universe_group)) => Ok(defs::UniverseTarget::Group(universe_group)),
+        (None, None) => Err(MqttError::MissingUniverseIdOrGroup(command.to_string()).into()),
+    }
+}
+
+fn get_correlation_id(payload: &Bytes) -> Option<Arc<str>> {
+    match serde_json::from_slice::<defs::CommandCorrelation>(payload) {
+        Ok(correlation) => correlation.correlation_id,
+        Err(_) => serde_json::from_slice::<Vec<defs::CommandCorrelation>>(payload)
+            .ok()?
+            .into_iter()
+            .find_map(|correlation| correlation.correlation_id),
+    }
+}
+
+fn is_retained_command_allowed(payload: &Bytes) -> bool {
+    match serde_json::from_slice::<defs::CommandRetainPolicy>(payload) {
+        Ok(policy) => policy.allow_retained,
+        Err(_) => serde_json::from_slice::<Vec<defs::CommandRetainPolicy>>(payload)
+            .is_ok_and(|policies| policies.iter().any(|policy| policy.allow_retained)),
+    }
+}
+
+// Universe and array definitions received since the service started (shared by all the subscriber clones)
+#[derive(Debug, Default)]
+struct DefinitionCounts {
+    universes: AtomicUsize,
+    arrays: AtomicUsize,
+}
+
+// Old ID of a renamed array or universe -> new ID and when the alias expires (see Rename command)
+type RenameAliases = HashMap<(defs::RenameKind, Arc<str>), (Arc<str>, Instant)>;
+
+#[derive(Clone)]
+pub struct MqttSubscriber {
+    to_artnet_tx: ManagerSender<messages::ToArtnetManagerMessage>,
+    to_array_tx: ManagerSender<messages::ToArrayManagerMessage>,
     to_mqtt_publisher_tx: async_channel::Sender<messages::ToMqttPublisherMessage>,
+    to_scheduler_tx: Sender<messages::ToSchedulerMessage>,
+    lenient_json: bool,     // Allow comments and trailing commas in definitions (universe, array, effect and value)
+    ignore_retained_commands: bool,     // Drop commands delivered as retained messages (unless they set allow_retained)
+    settings: Arc<watch::Sender<ServiceSettings>>,     // Changed by DMX/Config, watched by the other components
+    started: Instant,       // Service start time (reported as uptime by the Diagnostics command)
+    definition_counts: Arc<DefinitionCounts>,
+    deferred_startups: Arc<Mutex<HashMap<Arc<str>, defs::OnOffCommandParameters>>>,      // Array ID -> startup command waiting for its effect to be defined
+    rename_aliases: Arc<Mutex<RenameAliases>>,
+    command_latency: Arc<Mutex<CommandLatency>>,        // Published by the Latency command
+    broker_connected: Arc<AtomicBool>,      // Set once the broker acknowledged the connection, cleared when the session ends
+    metrics: Arc<Metrics>,      // Shared with the artnet manager, served by GET /metrics
+    schema: Arc<defs::Schema>,      // Published to DMX/Schema (see schema.rs)
 }
 
 pub async fn session(
     mut event_loop: EventLoop,
-    to_artnet_tx: Sender<messages::ToArtnetManagerMessage>,
-    to_array_tx: Sender<messages::ToArrayManagerMessage>,
-    to_mqtt_publisher_tx: async_channel::Sender<messages::ToMqttPublisherMessage>,
+    mqtt_subscriber: MqttSubscriber,
+    broker_address: Arc<str>,       // Without credentials (used in messages)
 ) -> Result<(), MqttError> {
     info!("Starting MQTT subscriber session");
     let into_context = || MqttError::Context("In MQTT subscriber session".to_string());
 
-    let mqtt_subscriber = MqttSubscriber {
-        to_artnet_tx,
-        to_array_tx,
-        to_mqtt_publisher_tx,
-    };
-
     loop {
         let event = event_loop.poll().await.change_context_lazy(into_context)?;
 
-        if let rumqttc::Event::Incoming(Packet::Publish(publish_packet)) = event {
-            let topic = publish_packet.topic;
-            let payload = publish_packet.payload;
+        match event {
+            rumqttc::Event::Incoming(Packet::Publish(publish_packet)) => {
+                let received = Instant::now();
+                let topic = publish_packet.topic;
+                let payload = publish_packet.payload;
 
-            if let Err(e) = mqtt_subscriber.handle_message(&topic, &payload).await {
-                error!("Error while handling MQTT message: {:?}", e);
-                mqtt_subscriber
-                    .to_mqtt_publisher_tx
-                    .send(messages::ToMqttPublisherMessage::Error(e.to_string()))
-                    .await
-                    .change_context_lazy(into_context)?;
+                if let Err(e) = mqtt_subscriber.handle_message(&topic, &payload, publish_packet.retain, received).await {
+                    error!("Error while handling MQTT message: {:?}", e);
+                    mqtt_subscriber
+                        .to_mqtt_publisher_tx
+                        .send(get_error_message(&e, get_topic_error_category(&topic)))
+                        .await
+                        .change_context_lazy(into_context)?;
+                }
+            }
+
+            rumqttc::Event::Incoming(Packet::ConnAck(_)) => mqtt_subscriber.set_broker_connected(true),
+
+            rumqttc::Event::Incoming(Packet::SubAck(_)) => {
+                info!("Subscription to DMX topics on broker {} is active", broker_address);
+
+                let mqtt_subscriber = mqtt_subscriber.clone();
+                let broker_address = broker_address.clone();
+                tokio::spawn(async move {
+                    mqtt_subscriber.check_definitions_received(&broker_address, DEFINITIONS_GRACE_PERIOD).await;
+                });
             }
+
+            _ => {}
         }
     }
 }
 
 impl MqttSubscriber {
-    async fn handle_message(&self, topic: &str, payload: &Bytes) -> Result<(), MqttError> {
+    pub fn new(
+        to_artnet_tx: ManagerSender<messages::ToArtnetManagerMessage>,
+        to_array_tx: ManagerSender<messages::ToArrayManagerMessage>,
+        to_mqtt_publisher_tx: async_channel::Sender<messages::ToMqttPublisherMessage>,
+        to_scheduler_tx: Sender<messages::ToSchedulerMessage>,
+        lenient_json: bool,
+    ) -> Self {
+        MqttSubscriber {
+            to_artnet_tx,
+            to_array_tx,
+            to_mqtt_publisher_tx,
+            to_scheduler_tx,
+            lenient_json,
+            ignore_retained_commands: true,
+            settings: Arc::new(watch::Sender::new(ServiceSettings::default())),
+            started: Instant::now(),
+            definition_counts: Arc::new(DefinitionCounts::default()),
+            deferred_startups: Arc::new(Mutex::new(HashMap::new())),
+            rename_aliases: Arc::new(Mutex::new(HashMap::new())),
+            command_latency: Arc::new(Mutex::new(CommandLatency::default())),
+            broker_connected: Arc::new(AtomicBool::new(false)),
+            metrics: Arc::new(Metrics::default()),
+            schema: Arc::new(schema::get_schema(BTreeMap::new())),
+        }
+    }
+
+    pub fn set_broker_connected(&self, connected: bool) {
+        self.broker_connected.store(connected, Ordering::Relaxed);
+    }
+
+    // Reported by the HTTP status endpoint (see http_status.rs)
+    pub fn get_health(&self) -> defs::HealthStatus {
+        defs::HealthStatus {
+            version: get_version(),
+            uptime_seconds: self.started.elapsed().as_secs(),
+            broker_connected: self.broker_connected.load(Ordering::Relaxed),
+        }
+    }
+
+    // Published by the Diagnostics command and served by the HTTP status endpoint
+    pub async fn get_diagnostics(&self, include_values: bool) -> defs::Diagnostics {
+        let (tx_array, rx_array) = oneshot::channel();
+
+        self.to_array_tx
+            .send(messages::ToArrayManagerMessage::GetDiagnostics(include_values, tx_array))
+            .await
+            .unwrap();
+
+        let (tx_artnet, rx_artnet) = oneshot::channel();
+
+        self.to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::GetDiagnostics(tx_artnet))
+            .await
+            .unwrap();
+
+        let queues = BTreeMap::from([
+            (self.to_artnet_tx.name().to_string(), self.to_artnet_tx.get_diagnostics()),
+            (self.to_array_tx.name().to_string(), self.to_array_tx.get_diagnostics()),
+        ]);
+
+        defs::Diagnostics {
+            version: get_version(),
+            uptime_seconds: self.started.elapsed().as_secs(),
+            arrays: rx_array.await.unwrap(),
+            artnet: rx_artnet.await.unwrap(),
+            queues,
+        }
+    }
+
+    pub fn with_settings(mut self, settings: watch::Sender<ServiceSettings>) -> Self {
+        self.settings = Arc::new(settings);
+        self
+    }
+
+    pub fn with_retained_commands(mut self, allow_retained_commands: bool) -> Self {
+        self.ignore_retained_commands = !allow_retained_commands;
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn with_schema(mut self, schema: defs::Schema) -> Self {
+        self.schema = Arc::new(schema);
+        self
+    }
+
+    // Published when connecting to the broker and by the Schema command
+    pub fn get_schema(&self) -> Arc<defs::Schema> {
+        self.schema.clone()
+    }
+
+    // Served by the HTTP status endpoint in Prometheus text format (see metrics.rs)
+    pub fn get_metrics(&self) -> String {
+        self.metrics.render()
+    }
+
+    // Number of universe and array definitions received (definitions that failed to be added are also counted)
+    pub fn get_definition_counts(&self) -> (usize, usize) {
+        (
+            self.definition_counts.universes.load(Ordering::Relaxed),
+            self.definition_counts.arrays.load(Ordering::Relaxed),
+        )
+    }
+
+    // Warn if no universe or array definitions were received within the grace period after subscribing. Definitions
+    // are retained, so this usually means the service is connected to the wrong (or a fresh) broker
+    pub async fn check_definitions_received(&self, broker_address: &str, grace_period: Duration) {
+        tokio::time::sleep(grace_period).await;
+
+        if self.get_definition_counts() == (0, 0) {
+            let _ = self
+                .to_mqtt_publisher_tx
+                .send(messages::ToMqttPublisherMessage::Warning(format!(
+                    "Connected to broker {broker_address}, subscription active, but no retained definitions were received - is this the right broker?"
+                )))
+                .await;
+        }
+    }
+
+    // Run a command of a due schedule in the same way as a command posted to DMX/Command/<command>
+    // Commands are run one after the other, in the order they became due (e.g. Off of a missed minute before a later On)
+    pub async fn handle_scheduled_commands(&self, scheduled_commands: Vec<ScheduledCommand>) {
+        for scheduled_command in scheduled_commands {
+            self.handle_scheduled_command(scheduled_command).await;
+        }
+    }
+
+    pub async fn handle_scheduled_command(&self, scheduled_command: ScheduledCommand) {
+        let ScheduledCommand { schedule_name, command, payload } = scheduled_command;
+
+        debug!("Running schedule {}: {} command", schedule_name, command);
+
+        if let Err(e) = self.handle_command_message(command.clone(), &payload, Instant::now()).await {
+            error!("Error while running {} command of schedule {}: {:?}", command, schedule_name, e);
+            let _ = self
+                .to_mqtt_publisher_tx
+                .send(messages::ToMqttPublisherMessage::Error(
+                    format!("Schedule {schedule_name}: {e}"),
+                    e.downcast_ref::<CorrelationId>().map(|id| id.0.clone()),
+                    get_error_category(&e, ErrorCategory::Command),
+                ))
+                .await;
+        }
+    }
+
+    fn get_definition_json(&self, payload: &Bytes) -> Bytes {
+        if self.lenient_json {
+            Bytes::from(lenient_json::to_strict_json(payload))
+        } else {
+            payload.clone()
+        }
+    }
+
+    // Definitions are retained on the broker (that is how they persist), commands are not expected to be retained
+    pub(crate) async fn handle_message(&self, topic: &str, payload: &Bytes, retain: bool, received: Instant) -> Result<(), MqttError> {
+        let max_payload_size = self.settings.borrow().max_payload_size;
+
+        if payload.len() > max_payload_size {
+            return Err(MqttError::PayloadTooLarge(topic.to_string(), payload.len(), max_payload_size).into());
+        }
+
         let topic_parts: Vec<&str> = topic.split('/').collect();
 
         if topic_parts.len() < 2 {
@@ -64,46 +374,157 @@ impl MqttSubscriber {
         } else {
             match topic_parts[1] {
                 "Universe" => {
-                    if topic_parts.len() != 3 {
+                    if topic_parts.len() == 4 && UNIVERSE_STATUS_SUBTOPICS.contains(&topic_parts[3]) {
+                        Ok(()) // Ignore universe status messages since they are published by this service
+                    } else if topic_parts.len() < 3 {
                         Err(MqttError::MissingUniverseId(topic_parts[1].to_string()).into())
+                    } else if topic_parts.len() > 3 {
+                        Err(MqttError::TooManyTopicLevels(topic.to_string()).into())
+                    } else {
+                        self.handle_universe_message(validate_id("universe", topic_parts[2])?, payload)
+                            .await
+                    }
+                }
+                "UniverseGroup" => {
+                    if topic_parts.len() < 3 {
+                        Err(MqttError::MissingCommand.into())
+                    } else if topic_parts.len() > 3 {
+                        Err(MqttError::TooManyTopicLevels(topic.to_string()).into())
                     } else {
-                        self.handle_universe_message(Arc::from(topic_parts[2]), payload)
+                        self.handle_universe_group_message(validate_id("universe group", topic_parts[2])?, payload)
                             .await
                     }
                 }
                 "Array" => {
-                    if topic_parts.len() != 3 {
+                    if topic_parts.len() == 4 && ARRAY_STATUS_SUBTOPICS.contains(&topic_parts[3]) {
+                        Ok(()) // Ignore array status messages since they are published by this service
+                    } else if topic_parts.len() < 3 {
                         Err(MqttError::MissingArrayId(topic_parts[1].to_string()).into())
+                    } else if topic_parts.len() > 3 {
+                        Err(MqttError::TooManyTopicLevels(topic.to_string()).into())
                     } else {
-                        self.handle_array_message(Arc::from(topic_parts[2]), payload)
+                        self.handle_array_message(validate_id("array", topic_parts[2])?, payload)
                             .await
                     }
                 }
                 "Command" => {
-                    if topic_parts.len() != 3 {
+                    if topic_parts.len() < 3 {
                         Err(MqttError::MissingCommand.into())
+                    } else if topic_parts.len() > 3 {
+                        Err(MqttError::TooManyTopicLevels(topic.to_string()).into())
+                    } else if retain && topic_parts[2] == "Reset" {
+                        // Never run, since the reset makes the broker send the retained messages (including this one) again
+                        let _ = self
+                            .to_mqtt_publisher_tx
+                            .send(messages::ToMqttPublisherMessage::Warning(format!(
+                                "Ignored {topic} since it was delivered as a retained message (clear the retained message on the broker)"
+                            )))
+                            .await;
+                        Ok(())
+                    } else if retain && self.ignore_retained_commands && !is_retained_command_allowed(payload) {
+                        info!("Ignoring retained command message on {}", topic);
+                        let _ = self
+                            .to_mqtt_publisher_tx
+                            .send(messages::ToMqttPublisherMessage::Warning(format!(
+                                "Ignored {topic} since it was delivered as a retained message (clear the retained message on the broker, or add \"allow_retained\": true to run it)"
+                            )))
+                            .await;
+                        Ok(())
                     } else {
-                        self.handle_command_message(Arc::from(topic_parts[2]), payload)
+                        self.handle_command_message(Arc::from(topic_parts[2]), payload, received)
                             .await
                     }
                 }
                 "Value" => {
-                    if topic_parts.len() != 3 {
+                    if topic_parts.len() < 3 {
                         Err(MqttError::MissingCommand.into())
+                    } else if topic_parts.len() > 3 {
+                        Err(MqttError::TooManyTopicLevels(topic.to_string()).into())
                     } else {
-                        self.handle_value_message(Arc::from(topic_parts[2]), payload)
+                        self.handle_value_message(validate_id("value", topic_parts[2])?, payload)
                             .await
                     }
                 }
                 "Effect" => {
-                    if topic_parts.len() != 3 {
+                    if topic_parts.len() < 3 {
+                        Err(MqttError::MissingCommand.into())
+                    } else if topic_parts.len() > 3 {
+                        Err(MqttError::TooManyTopicLevels(topic.to_string()).into())
+                    } else {
+                        self.handle_effect_message(validate_id("effect", topic_parts[2])?, payload)
+                            .await
+                    }
+                }
+                "Fixture" => {
+                    if topic_parts.len() < 3 {
+                        Err(MqttError::MissingCommand.into())
+                    } else if topic_parts.len() > 3 {
+                        Err(MqttError::TooManyTopicLevels(topic.to_string()).into())
+                    } else {
+                        self.handle_fixture_message(validate_id("fixture", topic_parts[2])?, payload)
+                            .await
+                    }
+                }
+                "Schedule" => {
+                    if topic_parts.len() < 3 {
+                        Err(MqttError::MissingCommand.into())
+                    } else if topic_parts.len() > 3 {
+                        Err(MqttError::TooManyTopicLevels(topic.to_string()).into())
+                    } else {
+                        self.handle_schedule_message(validate_id("schedule", topic_parts[2])?, payload)
+                            .await
+                    }
+                }
+                "ArrayTemplate" => {
+                    if topic_parts.len() < 3 {
+                        Err(MqttError::MissingCommand.into())
+                    } else if topic_parts.len() > 3 {
+                        Err(MqttError::TooManyTopicLevels(topic.to_string()).into())
+                    } else {
+                        self.handle_array_template_message(validate_id("array template", topic_parts[2])?, payload)
+                            .await
+                    }
+                }
+                "DimmingPreset" => {
+                    if topic_parts.len() < 3 {
+                        Err(MqttError::MissingCommand.into())
+                    } else if topic_parts.len() > 3 {
+                        Err(MqttError::TooManyTopicLevels(topic.to_string()).into())
+                    } else {
+                        self.handle_dimming_preset_message(validate_id("dimming preset", topic_parts[2])?, payload)
+                            .await
+                    }
+                }
+                "Monitor" => {
+                    if topic_parts.len() < 3 {
+                        Err(MqttError::MissingCommand.into())
+                    } else if topic_parts.len() > 3 {
+                        Err(MqttError::TooManyTopicLevels(topic.to_string()).into())
+                    } else {
+                        self.handle_monitor_message(validate_id("monitor", topic_parts[2])?, payload)
+                            .await
+                    }
+                }
+                "Config" => {
+                    if topic_parts.len() == 3 && topic_parts[2] == "Effective" {
+                        Ok(()) // Ignore the effective configuration since it is published by this service
+                    } else if topic_parts.len() > 2 {
+                        Err(MqttError::TooManyTopicLevels(topic.to_string()).into())
+                    } else {
+                        self.handle_config_message(payload).await
+                    }
+                }
+                "Watcher" => {
+                    if topic_parts.len() < 3 {
                         Err(MqttError::MissingCommand.into())
+                    } else if topic_parts.len() > 3 {
+                        Err(MqttError::TooManyTopicLevels(topic.to_string()).into())
                     } else {
-                        self.handle_effect_message(Arc::from(topic_parts[2]), payload)
+                        self.handle_watcher_message(validate_id("watcher", topic_parts[2])?, payload)
                             .await
                     }
                 }
-                "Error" | "LastError" | "Active" | "Version" => Ok(()), // Ignore any message posted to Error subtopic since it is published by this service
+                "Error" | "LastError" | "Active" | "Version" | "ExportedEffects" | "Schedules" | "DimmingPresets" | "EffectStats" | "Latency" | "Diagnostics" | "Ack" | "Verify" | "Validate" | "ResolveEffect" | "Status" | "Patch" | "Schema" => Ok(()), // Ignore any message posted to Error subtopic since it is published by this service
                 _ => Err(MqttError::InvalidSubtopic(topic_parts[1].to_string()).into()),
             }
         }
@@ -116,25 +537,41 @@ impl MqttSubscriber {
     ) -> Result<(), MqttError> {
         // If no payload is given, remove the universe
         if payload.is_empty() {
-            let (tx_artnet_reply, rx_artnet_reply) = oneshot::channel::<Result<(), ArtnetError>>();
+            let (tx_artnet_reply, rx_artnet_reply) = oneshot::channel::<Result<Vec<Arc<str>>, ArtnetError>>();
 
             self.to_artnet_tx
                 .send(messages::ToArtnetManagerMessage::RemoveUniverse(
-                    universe_id,
+                    universe_id.clone(),
                     tx_artnet_reply,
                 ))
                 .await
                 .unwrap();
 
-            if let Err(e) = rx_artnet_reply.await.unwrap() {
-                return Err(e)
-                    .change_context_lazy(|| MqttError::Context(String::from("removing universe")));
+            let stopped_effects = rx_artnet_reply
+                .await
+                .unwrap()
+                .change_context_lazy(|| MqttError::Context(String::from("removing universe")))?;
+
+            // Reported once for all the effects, rather than each of them failing on its next tick
+            if !stopped_effects.is_empty() {
+                let _ = self
+                    .to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::Error(
+                        format!("Universe {universe_id} was removed, stopped effects using it: {}", stopped_effects.join(", ")),
+                        None,
+                        ErrorCategory::Runtime,
+                    ))
+                    .await;
             }
         } else {
-            match serde_json::from_slice::<UniverseDefinition>(payload) {
+            self.definition_counts.universes.fetch_add(1, Ordering::Relaxed);
+            self.remove_rename_alias(defs::RenameKind::Universe, &universe_id);
+            let definition_json = self.get_definition_json(payload);
+
+            match serde_json::from_slice::<UniverseDefinition>(&definition_json) {
                 Ok(definition) => {
                     let (tx_artnet_reply, rx_artnet_reply) =
-                        oneshot::channel::<Result<(), ArtnetError>>();
+                        oneshot::channel::<Result<Vec<String>, ArtnetError>>();
 
                     self.to_artnet_tx
                         .send(messages::ToArtnetManagerMessage::AddUniverse(
@@ -145,18 +582,16 @@ impl MqttSubscriber {
                         .await
                         .unwrap();
 
-                    if let Err(e) = rx_artnet_reply.await.unwrap() {
-                        return Err(e).change_context_lazy(|| {
-                            MqttError::Context(format!("adding universe {universe_id}"))
-                        });
+                    let warnings = rx_artnet_reply.await.unwrap().change_context_lazy(|| {
+                        MqttError::Context(format!("adding universe {universe_id}"))
+                    })?;
+
+                    for warning in warnings {
+                        let _ = self.to_mqtt_publisher_tx.send(messages::ToMqttPublisherMessage::Warning(warning)).await;
                     }
                 }
                 Err(e) => {
-                    return Err(MqttError::JsonParseError(
-                        Arc::from("universe definition"),
-                        universe_id.clone(),
-                        e,
-                    ))
+                    return Err(definition_parse_error("Universe", universe_id.clone(), &definition_json, e))
                     .change_context_lazy(|| {
                         MqttError::Context(format!("parsing universe definition {universe_id}"))
                     });
@@ -174,7 +609,7 @@ impl MqttSubscriber {
     ) -> Result<(), MqttError> {
         // If no payload is given, remove the array
         if payload.is_empty() {
-            let (tx, rx) = oneshot::channel::<Result<(), DmxArrayError>>();
+            let (tx, rx) = oneshot::channel::<Result<ArrayEpoch, DmxArrayError>>();
 
             self.to_array_tx
                 .send(messages::ToArrayManagerMessage::RemoveArray(
@@ -184,17 +619,45 @@ impl MqttSubscriber {
                 .await
                 .unwrap();
 
-            if let Err(e) = rx.await.unwrap() {
-                return Err(e).change_context_lazy(|| {
-                    MqttError::Context(format!("removing array {array_id}"))
-                });
-            }
+            let epoch = rx.await.unwrap().change_context_lazy(|| {
+                MqttError::Context(format!("removing array {array_id}"))
+            })?;
+
+            self.deferred_startups.lock().unwrap().remove(&array_id);
+
+            self.set_array_epoch(array_id.clone(), epoch).await?;
+            self.set_channel_limits(array_id, None).await?;
         } else {
+            self.definition_counts.arrays.fetch_add(1, Ordering::Relaxed);
+            self.remove_rename_alias(defs::RenameKind::Array, &array_id);
             let into_context = || MqttError::Context(format!("adding array {array_id}"));
 
-            match serde_json::from_slice::<defs::DmxArray>(payload) {
+            let definition_json = self.get_definition_json(payload);
+
+            // Array given as a template and its parameters (see DMX/ArrayTemplate/<name>)
+            let definition_json = match serde_json::from_slice::<defs::ArrayTemplateInstance>(&definition_json) {
+                Ok(instance) => self.instantiate_array_template(instance).await.change_context_lazy(into_context)?,
+                Err(_) => definition_json,
+            };
+
+            match serde_json::from_slice::<defs::DmxArray>(&definition_json) {
                 Ok(definition) => {
-                    let (tx, rx) = oneshot::channel::<Result<(), DmxArrayError>>();
+                    let startup_command = (definition.startup.as_ref() != defs::NO_STARTUP_EFFECT).then(|| defs::OnOffCommandParameters {
+                        array_id: Some(array_id.clone()),
+                        effect_id: Some(definition.startup.clone()),
+                        dimming_amount: Some(defs::DimmingAmountOrPreset::Amount(definition.startup_dimming_amount.unwrap_or(defs::DIMMING_AMOUNT_MAX))),
+                        values: None,
+                        lights: None,
+                        effect: None,
+                        origin: Some(Arc::from("startup")),
+                        when: defs::CommandWhen::Immediate,
+                        instant: false,
+                        max_ticks: None,
+                        no_max_ticks: false,
+                    });
+                    let redefined = self.get_array_state(array_id.clone()).await.is_ok();
+                    let run_startup = !redefined || definition.startup_on_redefine;
+                    let (tx, rx) = oneshot::channel::<Result<messages::AddedArray, DmxArrayError>>();
 
                     self.to_array_tx
                         .send(messages::ToArrayManagerMessage::AddArray(
@@ -205,17 +668,205 @@ impl MqttSubscriber {
                         .await
                         .unwrap();
 
-                    if let Err(e) = rx.await.unwrap() {
-                        return Err(e).change_context_lazy(into_context);
+                    let (epoch, warnings) = rx.await.unwrap().change_context_lazy(into_context)?;
+                    self.set_array_epoch(array_id.clone(), epoch).await?;
+
+                    for warning in warnings {
+                        let _ = self.to_mqtt_publisher_tx.send(messages::ToMqttPublisherMessage::Warning(warning)).await;
+                    }
+
+                    // Let the artnet manager enforce the array limits also for channels that are set directly
+                    let (tx, rx) = oneshot::channel();
+
+                    self.to_array_tx
+                        .send(messages::ToArrayManagerMessage::GetArrayLimits(
+                            array_id.clone(),
+                            tx,
+                        ))
+                        .await
+                        .unwrap();
+
+                    let limits = rx.await.unwrap().change_context_lazy(into_context)?;
+                    self.set_channel_limits(array_id.clone(), Some(limits)).await?;
+
+                    self.deferred_startups.lock().unwrap().remove(&array_id);
+
+                    // A startup effect that is not defined yet (e.g. global effect whose retained message was not
+                    // received yet) is started once it is defined
+                    if let Some(startup_command) = startup_command.filter(|_| run_startup) {
+                        let startup_effect_id = startup_command.effect_id.clone().unwrap_or_default();
+
+                        if self.get_unresolved_effects().await.get(&array_id).is_some_and(|effect_ids| effect_ids.contains(&startup_effect_id)) {
+                            info!("Array {} startup effect {} is deferred until it is defined", array_id, startup_effect_id);
+                            self.deferred_startups.lock().unwrap().insert(array_id.clone(), startup_command);
+                        } else {
+                            self.start_startup_effect(array_id.clone(), &startup_command).await;
+                        }
                     }
                 }
-                Err(e) => return Err(e).change_context_lazy(into_context),
+                Err(e) => return Err(definition_parse_error("Array", array_id.clone(), &definition_json, e)).change_context_lazy(into_context),
             }
         }
 
         Ok(())
     }
 
+    // Failing to start the startup effect is reported, but the array remains defined
+    async fn start_startup_effect(&self, array_id: Arc<str>, startup_command: &defs::OnOffCommandParameters) {
+        if let Err(e) = self.start_usage_effect("On", array_id.clone(), startup_command).await {
+            error!("Error while starting startup effect of array {}: {:?}", array_id, e);
+            let _ = self
+                .to_mqtt_publisher_tx
+                .send(messages::ToMqttPublisherMessage::Error(format!("Array {array_id} startup effect: {e}"), None, ErrorCategory::Runtime))
+                .await;
+        }
+    }
+
+    // Start the deferred startup effects that are now defined
+    async fn start_deferred_startup_effects(&self) {
+        if self.deferred_startups.lock().unwrap().is_empty() {
+            return;
+        }
+
+        let unresolved_effects = self.get_unresolved_effects().await;
+        let mut startup_commands = {
+            let mut deferred_startups = self.deferred_startups.lock().unwrap();
+            let array_ids = deferred_startups
+                .iter()
+                .filter(|(array_id, startup_command)| {
+                    !unresolved_effects.get(*array_id).is_some_and(|effect_ids| effect_ids.iter().any(|id| Some(id) == startup_command.effect_id.as_ref()))
+                })
+                .map(|(array_id, _)| array_id.clone())
+                .collect::<Vec<_>>();
+
+            array_ids.into_iter().filter_map(|array_id| deferred_startups.remove_entry(&array_id)).collect::<Vec<_>>()
+        };
+
+        startup_commands.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        for (array_id, startup_command) in startup_commands {
+            info!("Starting deferred startup effect of array {}", array_id);
+            self.start_startup_effect(array_id, &startup_command).await;
+        }
+    }
+
+    async fn get_unresolved_effects(&self) -> messages::UnresolvedEffects {
+        let (tx, rx) = oneshot::channel();
+
+        self.to_array_tx
+            .send(messages::ToArrayManagerMessage::GetUnresolvedEffects(tx))
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
+    async fn set_channel_limits(
+        &self,
+        array_id: Arc<str>,
+        limits: Option<Arc<ChannelLimits>>,
+    ) -> Result<(), MqttError> {
+        let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+        self.to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::SetChannelLimits(
+                array_id.clone(),
+                limits,
+                tx,
+            ))
+            .await
+            .unwrap();
+
+        rx.await.unwrap().change_context_lazy(|| {
+            MqttError::Context(format!("setting channel limits of array {array_id}"))
+        })
+    }
+
+    // Let the artnet manager reject effects built from a previous definition of the array
+    async fn set_array_epoch(&self, array_id: Arc<str>, epoch: ArrayEpoch) -> Result<(), MqttError> {
+        let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+        self.to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::SetArrayEpoch(array_id.clone(), epoch, tx))
+            .await
+            .unwrap();
+
+        rx.await.unwrap().change_context_lazy(|| {
+            MqttError::Context(format!("setting epoch of array {array_id}"))
+        })
+    }
+
+    // Let effect runtime nodes (e.g. wait_for) see value changes accepted by the array manager
+    async fn set_effect_value(&self, array_id: Option<Arc<str>>, value_name: Arc<str>, value: Option<String>) -> Result<(), MqttError> {
+        let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+        self.to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::SetEffectValue(array_id, value_name.clone(), value, tx))
+            .await
+            .unwrap();
+
+        rx.await.unwrap().change_context_lazy(|| {
+            MqttError::Context(format!("forwarding value {value_name} to effects"))
+        })
+    }
+
+    async fn get_array_state(&self, array_id: Arc<str>) -> Result<Option<ArrayState>, DmxArrayError> {
+        let (tx, rx) = oneshot::channel::<Result<Option<ArrayState>, DmxArrayError>>();
+
+        self.to_array_tx
+            .send(messages::ToArrayManagerMessage::GetArrayState(array_id, tx))
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
+    async fn stop_effects(
+        &self,
+        array_id: Arc<str>,
+        scope: defs::StopScope,
+        usage: Option<EffectUsage>,
+    ) -> Result<Vec<Arc<str>>, ArtnetError> {
+        let (tx, rx) = oneshot::channel::<Result<Vec<Arc<str>>, ArtnetError>>();
+
+        self.to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::StopEffects(array_id, scope, usage, tx))
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
+    // Stop the effects linked to the array (e.g. an infinite flicker effect on part of the array lights), returns
+    // the ids of the linked effects that were running
+    async fn stop_linked_effects(&self, array_id: Arc<str>) -> Result<Vec<Arc<str>>, MqttError> {
+        let into_context = || MqttError::Context(format!("stopping linked effects of array {array_id}"));
+        let (tx, rx) = oneshot::channel::<Result<Vec<Arc<str>>, DmxArrayError>>();
+
+        self.to_array_tx
+            .send(messages::ToArrayManagerMessage::GetLinkedEffects(array_id.clone(), tx))
+            .await
+            .unwrap();
+
+        let linked_effects = match rx.await.unwrap() {
+            Ok(linked_effects) => linked_effects,
+            // Stop may be used for effects that are not arrays (e.g. inline effects)
+            Err(e) if matches!(e.current_context(), DmxArrayError::ArrayNotFound(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e).change_context_lazy(into_context),
+        };
+        let mut stopped_effects = Vec::new();
+
+        for effect_id in linked_effects {
+            stopped_effects.extend(
+                self.stop_effects(effect_id, defs::StopScope::Exact, None)
+                    .await
+                    .change_context_lazy(into_context)?,
+            );
+        }
+
+        Ok(stopped_effects)
+    }
+
     async fn handle_value_message(
         &self,
         value_name: Arc<str>,
@@ -237,24 +888,31 @@ impl MqttSubscriber {
                     MqttError::Context(format!("removing global value {value_name}"))
                 });
             }
+
+            self.set_effect_value(None, value_name, None).await?;
         } else {
             let into_context = || MqttError::Context(format!("adding global value {value_name}"));
 
-            match serde_json::from_slice::<defs::ValueDefinition>(payload) {
+            match serde_json::from_slice::<defs::ValueDefinition>(&self.get_definition_json(payload)) {
                 Ok(value_definition) => {
-                    let (tx, rx) = oneshot::channel::<Result<(), DmxArrayError>>();
+                    let (tx, rx) = oneshot::channel::<Result<Vec<defs::RetriggerEffect>, DmxArrayError>>();
 
                     self.to_array_tx
                         .send(messages::ToArrayManagerMessage::AddGlobalValue(
                             value_name.clone(),
-                            value_definition.value,
+                            value_definition.value.clone(),
+                            value_definition.retrigger,
                             tx,
                         ))
                         .await
                         .unwrap();
 
-                    if let Err(e) = rx.await.unwrap() {
-                        return Err(e).change_context_lazy(into_context);
+                    let retrigger_effects = rx.await.unwrap().change_context_lazy(into_context)?;
+
+                    self.set_effect_value(None, value_name.clone(), Some(value_definition.value.to_string())).await?;
+
+                    for retrigger_effect in retrigger_effects {
+                        self.retrigger_effect(&value_name, retrigger_effect).await;
                     }
                 }
                 Err(e) => return Err(e).change_context_lazy(into_context),
@@ -264,17 +922,53 @@ impl MqttSubscriber {
         Ok(())
     }
 
+    // Start again the last effect of an array since a retrigger value it read has changed. Only values set by
+    // DMX/Value/<name> retrigger effects (values given by commands do not), so a started effect cannot retrigger itself
+    async fn retrigger_effect(&self, value_name: &str, retrigger_effect: defs::RetriggerEffect) {
+        let array_id = retrigger_effect.array_id;
+        let command_parameters = defs::OnOffCommandParameters {
+            array_id: Some(array_id.clone()),
+            effect_id: retrigger_effect.effect_id,
+            dimming_amount: retrigger_effect.dimming_amount.map(defs::DimmingAmountOrPreset::Amount),
+            values: None,
+            lights: None,
+            effect: None,
+            origin: Some(Arc::from(format!("value {value_name}"))),
+            when: defs::CommandWhen::Immediate,
+            instant: false,
+            max_ticks: None,
+            no_max_ticks: false,
+        };
+
+        info!("Value {} changed, restarting {} effect of array {}", value_name, retrigger_effect.usage, array_id);
+        if let Err(e) = self.start_usage_effect(&retrigger_effect.usage.to_string(), array_id.clone(), &command_parameters).await {
+            error!("Error while restarting effect of array {}: {:?}", array_id, e);
+            let _ = self
+                .to_mqtt_publisher_tx
+                .send(messages::ToMqttPublisherMessage::Error(format!("Array {array_id} effect restarted by value {value_name}: {e}"), None, ErrorCategory::Runtime))
+                .await;
+        }
+    }
+
     async fn handle_effect_message(
         &self,
         effect_id: Arc<str>,
         payload: &Bytes,
     ) -> Result<(), MqttError> {
-        if payload.is_empty() {
+        // Empty payload removes the effect, {"force": true} removes it even if arrays are using it
+        let remove_parameters = if payload.is_empty() {
+            Some(defs::RemoveEffectParameters { force: false })
+        } else {
+            serde_json::from_slice::<defs::RemoveEffectParameters>(&self.get_definition_json(payload)).ok()
+        };
+
+        if let Some(remove_parameters) = remove_parameters {
             let (tx, rx) = oneshot::channel::<Result<(), DmxArrayError>>();
 
             self.to_array_tx
                 .send(messages::ToArrayManagerMessage::RemoveEffect(
                     effect_id.clone(),
+                    remove_parameters.force,
                     tx,
                 ))
                 .await
@@ -288,7 +982,9 @@ impl MqttSubscriber {
         } else {
             let into_context = || MqttError::Context(format!("adding effect {effect_id}"));
 
-            match serde_json::from_slice::<EffectNodeDefinition>(payload) {
+            let definition_json = self.get_definition_json(payload);
+
+            match serde_json::from_slice::<defs::EffectDefinition>(&definition_json) {
                 Ok(effect_definition) => {
                     let (tx, rx) = oneshot::channel::<Result<(), DmxArrayError>>();
 
@@ -304,141 +1000,3516 @@ impl MqttSubscriber {
                     if let Err(e) = rx.await.unwrap() {
                         return Err(e).change_context_lazy(into_context);
                     }
+
+                    self.start_deferred_startup_effects().await;
                 }
 
-                Err(e) => return Err(e).change_context_lazy(into_context),
+                Err(e) => return Err(definition_parse_error("Effect", effect_id.clone(), &definition_json, e)).change_context_lazy(into_context),
             }
         }
         Ok(())
     }
 
-    async fn handle_command_message(
+    async fn handle_fixture_message(
         &self,
-        command: Arc<str>,
+        fixture_name: Arc<str>,
         payload: &Bytes,
     ) -> Result<(), MqttError> {
-        match command.as_ref() {
-            "On" | "Off" | "Dim" => {
-                let usage = command.parse::<EffectUsage>().unwrap();
+        let (tx, rx) = oneshot::channel::<Result<(), DmxArrayError>>();
 
-                let command_parameters =
-                    serde_json::from_slice::<defs::OnOffCommandParameters>(payload)
-                        .change_context_lazy(|| {
-                            MqttError::Context(format!("parsing{command} command parameters"))
-                        })?;
+        // If no payload is given, remove the fixture
+        if payload.is_empty() {
+            self.to_array_tx
+                .send(messages::ToArrayManagerMessage::RemoveFixture(fixture_name.clone(), tx))
+                .await
+                .unwrap();
 
-                let array_id = command_parameters.array_id.clone();
-                let into_context =
-                    || MqttError::Context(format!("{command} command on array {array_id}"));
+            rx.await.unwrap().change_context_lazy(|| MqttError::Context(format!("removing fixture {fixture_name}")))?;
+        } else {
+            let into_context = || MqttError::Context(format!("adding fixture {fixture_name}"));
+            let definition_json = self.get_definition_json(payload);
+            let fixture = serde_json::from_slice::<defs::FixtureDefinition>(&definition_json)
+                .map_err(|e| definition_parse_error("Fixture", fixture_name.clone(), &definition_json, e))
+                .change_context_lazy(into_context)?;
 
-                // If values were provided, set them as the array values
-                if let Some(initial_values) = command_parameters.values {
-                    let (tx, rx) = oneshot::channel::<Result<(), DmxArrayError>>();
+            self.to_array_tx
+                .send(messages::ToArrayManagerMessage::AddFixture(fixture_name.clone(), fixture, tx))
+                .await
+                .unwrap();
 
-                    self.to_array_tx
-                        .send(messages::ToArrayManagerMessage::InitializeArrayValues(
-                            command_parameters.array_id.clone(),
-                            initial_values,
-                            tx,
-                        ))
-                        .await
-                        .unwrap();
+            rx.await.unwrap().change_context_lazy(into_context)?;
+        }
 
-                    let _ = rx.await.unwrap();
-                }
+        Ok(())
+    }
 
-                let (tx, rx) =
-                    oneshot::channel::<Result<Box<dyn EffectNodeRuntime>, DmxArrayError>>();
+    async fn handle_schedule_message(
+        &self,
+        schedule_name: Arc<str>,
+        payload: &Bytes,
+    ) -> Result<(), MqttError> {
+        let into_context = || MqttError::Context(format!("setting schedule {schedule_name}"));
 
-                // Use the array ID as the effect ID
-                let effect_id = command_parameters.array_id.clone();
+        // Empty payload removes the schedule
+        let definition = if payload.is_empty() {
+            None
+        } else {
+            let definition_json = self.get_definition_json(payload);
 
-                self.to_array_tx
-                    .send(messages::ToArrayManagerMessage::GetEffectRuntime(
-                        command_parameters.array_id,
-                        usage,
-                        command_parameters.effect_id,
-                        command_parameters
-                            .dimming_amount
-                            .unwrap_or(DIMMING_AMOUNT_MAX),
-                        tx,
-                    ))
-                    .await
-                    .unwrap();
+            match serde_json::from_slice::<defs::ScheduleDefinition>(&definition_json) {
+                Ok(definition) => Some(definition),
+                Err(e) => return Err(definition_parse_error("Schedule", schedule_name.clone(), &definition_json, e)).change_context_lazy(into_context),
+            }
+        };
 
-                let result = rx.await.unwrap();
+        let (tx, rx) = oneshot::channel::<Result<(), SchedulerError>>();
 
-                match result {
-                    Err(e) => return Err(e).change_context_lazy(into_context),
-                    Ok(effect_runtime_node) => {
-                        let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+        self.to_scheduler_tx
+            .send(messages::ToSchedulerMessage::SetSchedule(schedule_name.clone(), definition, tx))
+            .await
+            .unwrap();
 
-                        self.to_artnet_tx
-                            .send(messages::ToArtnetManagerMessage::StartEffect(
-                                effect_id,
-                                effect_runtime_node,
-                                tx,
-                            ))
-                            .await
-                            .unwrap();
+        rx.await.unwrap().change_context_lazy(into_context)
+    }
 
-                        if let Err(e) = rx.await.unwrap() {
-                            return Err(e).change_context_lazy(into_context);
-                        }
-                    }
+    async fn handle_array_template_message(
+        &self,
+        template_name: Arc<str>,
+        payload: &Bytes,
+    ) -> Result<(), MqttError> {
+        let into_context = || MqttError::Context(format!("setting array template {template_name}"));
+
+        // Empty payload removes the template (arrays already instantiated from it are not changed)
+        let template = if payload.is_empty() {
+            None
+        } else {
+            let template_json = self.get_definition_json(payload);
+
+            match serde_json::from_slice::<serde_json::Value>(&template_json) {
+                Ok(template) => Some(template),
+                Err(e) => return Err(json_parse_error("ArrayTemplate", template_name.clone(), e)).change_context_lazy(into_context),
+            }
+        };
+
+        let (tx, rx) = oneshot::channel::<Result<(), DmxArrayError>>();
+
+        self.to_array_tx
+            .send(messages::ToArrayManagerMessage::SetArrayTemplate(template_name.clone(), template, tx))
+            .await
+            .unwrap();
+
+        rx.await.unwrap().change_context_lazy(into_context)
+    }
+
+    // Array definition (JSON) of an array instantiated from a template
+    async fn instantiate_array_template(&self, instance: defs::ArrayTemplateInstance) -> Result<Bytes, DmxArrayError> {
+        let (tx, rx) = oneshot::channel::<Result<serde_json::Value, DmxArrayError>>();
+
+        self.to_array_tx
+            .send(messages::ToArrayManagerMessage::InstantiateArrayTemplate(instance, tx))
+            .await
+            .unwrap();
+
+        let definition = rx.await.unwrap()?;
+        Ok(Bytes::from(definition.to_string()))
+    }
+
+    async fn handle_dimming_preset_message(
+        &self,
+        preset_name: Arc<str>,
+        payload: &Bytes,
+    ) -> Result<(), MqttError> {
+        let into_context = || MqttError::Context(format!("setting dimming preset {preset_name}"));
+
+        // Empty payload removes the preset
+        let definition = if payload.is_empty() {
+            None
+        } else {
+            let definition_json = self.get_definition_json(payload);
+
+            match serde_json::from_slice::<defs::DimmingPresetDefinition>(&definition_json) {
+                Ok(definition) => Some(definition),
+                Err(e) => return Err(definition_parse_error("DimmingPreset", preset_name.clone(), &definition_json, e)).change_context_lazy(into_context),
+            }
+        };
+
+        let (tx, rx) = oneshot::channel::<Result<(), DmxArrayError>>();
+
+        self.to_array_tx
+            .send(messages::ToArrayManagerMessage::SetDimmingPreset(preset_name.clone(), definition, tx))
+            .await
+            .unwrap();
+
+        rx.await.unwrap().change_context_lazy(into_context)
+    }
+
+    // Apply the settings in the payload (settings that are not in the payload keep their value)
+    async fn handle_config_message(&self, payload: &Bytes) -> Result<(), MqttError> {
+        let into_context = || MqttError::Context("setting service configuration".to_string());
+        let config_json = self.get_definition_json(payload);
+
+        let changes = match serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&config_json) {
+            Ok(changes) => changes,
+            Err(e) => return Err(json_parse_error("Config", Arc::from("service"), e)).change_context_lazy(into_context),
+        };
+
+        let (settings, unknown_names) = self.settings.borrow().with_changes(changes).change_context_lazy(into_context)?;
+
+        if !unknown_names.is_empty() {
+            let warning = format!(
+                "Unknown settings in DMX/Config: {} (valid settings: {})",
+                unknown_names.join(", "),
+                ServiceSettings::get_names().join(", ")
+            );
+
+            let _ = self.to_mqtt_publisher_tx.send(messages::ToMqttPublisherMessage::Warning(warning)).await;
+        }
+
+        self.settings.send_if_modified(|current| {
+            if *current != settings {
+                info!("Service configuration changed to {:?}", settings);
+                *current = settings.clone();
+                true
+            } else {
+                false
+            }
+        });
+
+        let _ = self.to_mqtt_publisher_tx.send(messages::ToMqttPublisherMessage::EffectiveConfig(settings)).await;
+        Ok(())
+    }
+
+    // Resolve a command dimming amount given as the name of a dimming preset (see DMX/DimmingPreset/<name>)
+    async fn resolve_dimming_amount(&self, dimming_amount: Option<defs::DimmingAmountOrPreset>) -> Result<Option<DimmingAmount>, DmxArrayError> {
+        let (tx, rx) = oneshot::channel::<Result<Option<DimmingAmount>, DmxArrayError>>();
+
+        self.to_array_tx
+            .send(messages::ToArrayManagerMessage::ResolveDimmingAmount(dimming_amount, tx))
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
+    async fn resolve_set_channels_dimming_amount(&self, parameters: &mut defs::SetChannelsParameters) -> Result<(), DmxArrayError> {
+        parameters.dimming_amount = self
+            .resolve_dimming_amount(parameters.dimming_amount.take())
+            .await?
+            .map(defs::DimmingAmountOrPreset::Amount);
+        Ok(())
+    }
+
+    async fn handle_watcher_message(
+        &self,
+        watcher_name: Arc<str>,
+        payload: &Bytes,
+    ) -> Result<(), MqttError> {
+        let into_context = || MqttError::Context(format!("setting watcher {watcher_name}"));
+
+        // Empty payload removes the watcher
+        let definition = if payload.is_empty() {
+            None
+        } else {
+            let definition_json = self.get_definition_json(payload);
+
+            match serde_json::from_slice::<defs::WatcherDefinition>(&definition_json) {
+                Ok(definition) => Some(definition),
+                Err(e) => return Err(definition_parse_error("Watcher", watcher_name.clone(), &definition_json, e)).change_context_lazy(into_context),
+            }
+        };
+
+        let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+        self.to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::SetWatcher(watcher_name.clone(), definition, tx))
+            .await
+            .unwrap();
+
+        rx.await.unwrap().change_context_lazy(into_context)
+    }
+
+    async fn handle_monitor_message(
+        &self,
+        monitor_name: Arc<str>,
+        payload: &Bytes,
+    ) -> Result<(), MqttError> {
+        let into_context = || MqttError::Context(format!("setting monitor {monitor_name}"));
+
+        // Empty payload removes the monitor
+        let definition = if payload.is_empty() {
+            None
+        } else {
+            let definition_json = self.get_definition_json(payload);
+
+            match serde_json::from_slice::<defs::MonitorDefinition>(&definition_json) {
+                Ok(definition) => Some(definition),
+                Err(e) => return Err(definition_parse_error("Monitor", monitor_name.clone(), &definition_json, e)).change_context_lazy(into_context),
+            }
+        };
+
+        let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+        self.to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::SetMonitor(monitor_name.clone(), definition, tx))
+            .await
+            .unwrap();
+
+        rx.await.unwrap().change_context_lazy(into_context)
+    }
+
+    async fn handle_universe_group_message(
+        &self,
+        group_name: Arc<str>,
+        payload: &Bytes,
+    ) -> Result<(), MqttError> {
+        let into_context = || MqttError::Context(format!("setting universe group {group_name}"));
+
+        // Empty payload removes the group
+        let definition = if payload.is_empty() {
+            None
+        } else {
+            let definition_json = self.get_definition_json(payload);
+
+            match serde_json::from_slice::<defs::UniverseGroupDefinition>(&definition_json) {
+                Ok(definition) => Some(definition),
+                Err(e) => return Err(definition_parse_error("UniverseGroup", group_name.clone(), &definition_json, e)).change_context_lazy(into_context),
+            }
+        };
+
+        let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+        self.to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::SetUniverseGroup(group_name.clone(), definition, tx))
+            .await
+            .unwrap();
+
+        rx.await.unwrap().change_context_lazy(into_context)
+    }
+
+    async fn start_usage_effect(
+        &self,
+        command: &str,
+        array_id: Arc<str>,
+        command_parameters: &defs::OnOffCommandParameters,
+    ) -> Result<(), MqttError> {
+        let into_context =
+            || MqttError::Context(format!("{command} command on array {array_id}"));
+
+        let usage = if command == "Toggle" {
+            match self.get_array_state(array_id.clone()).await.change_context_lazy(into_context)?.map(|state| state.usage) {
+                Some(EffectUsage::On) | Some(EffectUsage::Dim) => EffectUsage::Off,
+                Some(EffectUsage::Off) | None => EffectUsage::On,
+            }
+        } else {
+            command.parse::<EffectUsage>().unwrap()
+        };
+
+        // Toggle turning the array On ignores instant
+        if command_parameters.instant && command != "Off" && command != "Toggle" {
+            return Err(MqttError::InstantNotOff(command.to_string())).change_context_lazy(into_context);
+        }
+        let instant = command_parameters.instant && usage == EffectUsage::Off;
+
+        // If values were provided, set them as the array values
+        if let Some(initial_values) = &command_parameters.values {
+            let (tx, rx) = oneshot::channel::<Result<(), DmxArrayError>>();
+
+            self.to_array_tx
+                .send(messages::ToArrayManagerMessage::InitializeArrayValues(
+                    array_id.clone(),
+                    initial_values.clone(),
+                    tx,
+                ))
+                .await
+                .unwrap();
+
+            if rx.await.unwrap().is_ok() {
+                for (value_name, value) in initial_values.iter() {
+                    self.set_effect_value(Some(array_id.clone()), value_name.clone(), Some(value.to_string())).await?;
                 }
             }
+        }
 
-            "Stop" => {
-                let command_parameters =
-                    serde_json::from_slice::<defs::StopCommandParameters>(payload)
-                        .change_context_lazy(|| {
-                            MqttError::Context("parsing Stop command parameters".to_string())
-                        })?;
+        let dimming_amount = self
+            .resolve_dimming_amount(command_parameters.dimming_amount.clone())
+            .await
+            .change_context_lazy(into_context)?;
+        let (tx, rx) =
+            oneshot::channel::<Result<messages::ArrayEffectRuntime, DmxArrayError>>();
+
+        // Use the array ID as the effect ID, effects of a light group have their own ID so they do not replace the
+        // whole array effect (Stop with "all" scope stops both)
+        let lights = command_parameters.lights.as_ref().map(|lights| lights.trim().to_string());
+        let effect_id: Arc<str> = match &lights {
+            Some(lights) => Arc::from(format!("{array_id}{lights}")),
+            None => array_id.clone(),
+        };
+
+        // Instant off does not use the off effect, so it works even if the effect cannot be built
+        let message = if instant {
+            messages::ToArrayManagerMessage::GetInstantOffRuntime(array_id.clone(), lights.clone(), tx)
+        } else {
+            messages::ToArrayManagerMessage::GetEffectRuntime(
+                array_id.clone(),
+                usage,
+                command_parameters.effect_id.clone(),
+                lights.clone(),
+                dimming_amount,       // If not specified, the array manager uses the array default
+                tx,
+            )
+        };
+
+        self.to_array_tx.send(message).await.unwrap();
+
+        let result = rx.await.unwrap();
+
+        match result {
+            Err(e) => return Err(e).change_context_lazy(into_context),
+            Ok((effect_runtime_node, epoch, warnings)) => {
+                for warning in warnings {
+                    let _ = self.to_mqtt_publisher_tx.send(messages::ToMqttPublisherMessage::Warning(warning)).await;
+                }
 
-                let array_id = command_parameters.array_id.clone();
                 let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
 
                 self.to_artnet_tx
-                    .send(messages::ToArtnetManagerMessage::StopEffect(
-                        command_parameters.array_id,
+                    .send(messages::ToArtnetManagerMessage::StartEffect(
+                        messages::StartEffectRequest {
+                            effect_id,
+                            node: effect_runtime_node,
+                            usage: Some(usage),
+                            epoch: Some(epoch),
+                            origin: command_parameters.origin.clone(),
+                            enqueue: !instant && command_parameters.when == defs::CommandWhen::AfterCurrent,
+                            max_ticks: command_parameters.get_max_ticks(),
+                        },
                         tx,
                     ))
                     .await
                     .unwrap();
+
                 if let Err(e) = rx.await.unwrap() {
-                    return Err(e).change_context_lazy(|| {
-                        MqttError::Context(format!("stopping effect on array {array_id}"))
-                    });
+                    return Err(e).change_context_lazy(into_context);
                 }
-            }
 
-            "Set" => {
-                let command_parameters =
-                    serde_json::from_slice::<defs::SetChannelsParameters>(payload)
-                        .change_context_lazy(|| {
-                            MqttError::Context("parsing Set command parameters".to_string())
-                        })?;
-                let universe_id = command_parameters.universe_id.clone();
+                // Array state and linked effects refer to the whole array
+                if lights.is_some() {
+                    return Ok(());
+                }
 
-                let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+                let (tx, rx) = oneshot::channel::<Result<(), DmxArrayError>>();
 
-                self.to_artnet_tx
-                    .send(messages::ToArtnetManagerMessage::SetChannels(
-                        command_parameters,
+                self.to_array_tx
+                    .send(messages::ToArrayManagerMessage::SetArrayState(
+                        array_id.clone(),
+                        usage,
+                        dimming_amount,
                         tx,
                     ))
                     .await
                     .unwrap();
+
                 if let Err(e) = rx.await.unwrap() {
-                    return Err(e).change_context_lazy(|| {
-                        MqttError::Context(format!("setting channels on universe {universe_id}"))
-                    });
+                    return Err(e).change_context_lazy(into_context);
+                }
+
+                if usage == EffectUsage::Off {
+                    self.stop_linked_effects(array_id).await?;
                 }
             }
-            _ => return Err(MqttError::InvalidCommand(command.to_string()).into()),
         }
 
         Ok(())
     }
+
+    async fn run_array_command(
+        &self,
+        command: &str,
+        array_id: Arc<str>,
+        command_parameters: &defs::OnOffCommandParameters,
+    ) -> Result<(), MqttError> {
+        let mut result = self.start_usage_effect(command, array_id.clone(), command_parameters).await;
+
+        // The array was redefined while the command was processed, retry (once) with the new definition
+        if let Err(e) = &result {
+            if e.frames().any(|f| matches!(f.downcast_ref::<ArtnetError>(), Some(ArtnetError::StaleArrayEpoch(_, _, _)))) {
+                info!("Retrying {command} command on array {array_id}: {e}");
+                result = self.start_usage_effect(command, array_id.clone(), command_parameters).await;
+            }
+        }
+
+        // Keep the last error of each array in a retained topic, cleared by the next successful command
+        self.to_mqtt_publisher_tx
+            .send(messages::ToMqttPublisherMessage::ArrayLastError(
+                array_id,
+                result.as_ref().err().map(|e| e.to_string()),
+            ))
+            .await
+            .change_context_lazy(|| MqttError::Context("publishing array last error".to_string()))?;
+
+        result
+    }
+
+    // Adjust is handled in order with the other messages (not in the background), so rapid adjustments accumulate: each
+    // one starts from the dimming amount of the previous one and its effect replaces the previous effect
+    async fn adjust_dimming(&self, parameters: defs::AdjustCommandParameters) -> Result<(), MqttError> {
+        let array_id = parameters.array_id.clone();
+        let into_context = || MqttError::Context(format!("Adjust command on array {array_id}"));
+
+        let (last_usage, dimming_amount) = match self.get_array_state(array_id.clone()).await.change_context_lazy(into_context)? {
+            Some(ArrayState { usage: EffectUsage::Off, .. }) | None => (EffectUsage::On, 0),
+            Some(state) => (state.usage, state.dimming_amount),
+        };
+        let dimming_amount = (dimming_amount as i64 + parameters.delta).clamp(0, DIMMING_AMOUNT_MAX as i64) as DimmingAmount;
+        let usage = match parameters.reapply {
+            defs::AdjustReapply::Dim => EffectUsage::Dim,
+            defs::AdjustReapply::Last => last_usage,
+        };
+
+        let command_parameters = defs::OnOffCommandParameters {
+            array_id: Some(array_id.clone()),
+            effect_id: None,
+            dimming_amount: Some(defs::DimmingAmountOrPreset::Amount(dimming_amount)),
+            values: None,
+            lights: None,
+            effect: None,
+            origin: parameters.origin,
+            when: defs::CommandWhen::Immediate,
+            instant: false,
+            max_ticks: None,
+            no_max_ticks: false,
+        };
+
+        info!("Adjusting array {} by {} to dimming amount {} ({})", array_id, parameters.delta, dimming_amount, usage);
+        self.run_array_command(&usage.to_string(), array_id, &command_parameters).await
+    }
+
+    // Clear everything and receive the retained definitions again. Effects are stopped before their universes are
+    // removed, commands handled while the definitions are loaded again fail like commands on undefined arrays
+    async fn reset(&self, parameters: defs::ResetCommandParameters) -> Result<(), MqttError> {
+        if parameters.confirm.as_deref() != Some(defs::RESET_CONFIRMATION) {
+            return Err(MqttError::ResetNotConfirmed.into());
+        }
+
+        info!("Resetting all state (origin {})", parameters.origin.as_deref().unwrap_or("unknown"));
+        let into_context = || MqttError::Context("Reset command".to_string());
+
+        let (tx, rx) = oneshot::channel::<Result<Vec<Arc<str>>, ArtnetError>>();
+
+        self.to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::ClearAll(tx))
+            .await
+            .unwrap();
+
+        let stopped_effects = rx.await.unwrap().change_context_lazy(into_context)?;
+        let (tx, rx) = oneshot::channel::<Result<messages::RemovedArrays, DmxArrayError>>();
+
+        self.to_array_tx
+            .send(messages::ToArrayManagerMessage::ClearAll(tx))
+            .await
+            .unwrap();
+
+        // Effects built from a removed array before the reset are not started
+        let removed_arrays = rx.await.unwrap().change_context_lazy(into_context)?;
+        for (array_id, epoch) in removed_arrays.iter() {
+            self.set_array_epoch(array_id.clone(), *epoch).await?;
+        }
+
+        let (tx, rx) = oneshot::channel::<()>();
+
+        self.to_scheduler_tx
+            .send(messages::ToSchedulerMessage::ClearAll(tx))
+            .await
+            .unwrap();
+        rx.await.unwrap();
+
+        self.deferred_startups.lock().unwrap().clear();
+        self.rename_aliases.lock().unwrap().clear();
+
+        let _ = self
+            .to_mqtt_publisher_tx
+            .send(messages::ToMqttPublisherMessage::Warning(format!(
+                "Reset: all universes, arrays, effects, schedules and values were removed (running effects stopped: {}, arrays removed: {}), loading the retained definitions again",
+                stopped_effects.len(),
+                removed_arrays.len(),
+            )))
+            .await;
+        let _ = self.to_mqtt_publisher_tx.send(messages::ToMqttPublisherMessage::Resubscribe).await;
+
+        Ok(())
+    }
+
+    // Move an array (with its state, values and running effects) or a universe (with its channel data) to a new ID. The
+    // retained definition on the broker still has the old ID, so the notice asks to move it
+    async fn rename(&self, parameters: defs::RenameCommandParameters) -> Result<(), MqttError> {
+        let defs::RenameCommandParameters { kind, from, to, alias_seconds, origin } = parameters;
+        let into_context = || MqttError::Context(format!("renaming {kind} {from} to {to}"));
+
+        info!("Renaming {} {} to {} (origin {})", kind, from, to, origin.as_deref().unwrap_or("unknown"));
+
+        let subtopic = match kind {
+            defs::RenameKind::Array => {
+                let (tx, rx) = oneshot::channel::<Result<(ArrayEpoch, ArrayEpoch), DmxArrayError>>();
+
+                self.to_array_tx
+                    .send(messages::ToArrayManagerMessage::RenameArray(from.clone(), to.clone(), tx))
+                    .await
+                    .unwrap();
+
+                let (from_epoch, to_epoch) = rx.await.unwrap().change_context_lazy(into_context)?;
+                self.set_array_epoch(from.clone(), from_epoch).await?;
+                self.set_array_epoch(to.clone(), to_epoch).await?;
+
+                let (tx, rx) = oneshot::channel::<Result<Vec<Arc<str>>, ArtnetError>>();
+
+                self.to_artnet_tx
+                    .send(messages::ToArtnetManagerMessage::RenameArrayEffects(from.clone(), to.clone(), tx))
+                    .await
+                    .unwrap();
+
+                rx.await.unwrap().change_context_lazy(into_context)?;
+
+                let mut deferred_startups = self.deferred_startups.lock().unwrap();
+
+                if let Some(mut startup_command) = deferred_startups.remove(&from) {
+                    startup_command.array_id = Some(to.clone());
+                    deferred_startups.insert(to.clone(), startup_command);
+                }
+
+                "Array"
+            }
+            defs::RenameKind::Universe => {
+                let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+                self.to_artnet_tx
+                    .send(messages::ToArtnetManagerMessage::RenameUniverse(from.clone(), to.clone(), tx))
+                    .await
+                    .unwrap();
+
+                rx.await.unwrap().change_context_lazy(into_context)?;
+                "Universe"
+            }
+        };
+
+        {
+            let mut rename_aliases = self.rename_aliases.lock().unwrap();
+
+            // The new ID is no longer an alias, and aliases of earlier renames now lead to the new ID
+            rename_aliases.remove(&(kind, to.clone()));
+            for (_, (alias_to, _)) in rename_aliases.iter_mut().filter(|((alias_kind, _), (alias_to, _))| *alias_kind == kind && *alias_to == from) {
+                *alias_to = to.clone();
+            }
+
+            if let Some(alias_seconds) = alias_seconds {
+                rename_aliases.insert((kind, from.clone()), (to.clone(), Instant::now() + Duration::from_secs(alias_seconds)));
+            }
+        }
+
+        let alias = match alias_seconds {
+            Some(alias_seconds) => format!(", commands using {from} are redirected to {to} for {alias_seconds} seconds"),
+            None => String::new(),
+        };
+        let _ = self
+            .to_mqtt_publisher_tx
+            .send(messages::ToMqttPublisherMessage::Warning(format!(
+                "{subtopic} {from} was renamed to {to}{alias} (move the retained DMX/{subtopic}/{from} definition to DMX/{subtopic}/{to}, otherwise {from} is defined again when the service restarts)"
+            )))
+            .await;
+
+        Ok(())
+    }
+
+    // A definition for the old ID of a renamed array or universe defines it again, so it is no longer an alias
+    fn remove_rename_alias(&self, kind: defs::RenameKind, id: &Arc<str>) {
+        self.rename_aliases.lock().unwrap().remove(&(kind, id.clone()));
+    }
+
+    async fn get_pre_commands(&self, array_id: Arc<str>) -> Result<Vec<defs::PreCommand>, DmxArrayError> {
+        let (tx, rx) = oneshot::channel::<Result<Vec<defs::PreCommand>, DmxArrayError>>();
+
+        self.to_array_tx
+            .send(messages::ToArrayManagerMessage::GetPreCommands(array_id, tx))
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
+    // Run the commands on other arrays that precede turning the array On, a failing command prevents turning it On
+    async fn run_pre_commands(&self, array_id: &Arc<str>, pre_commands: &[defs::PreCommand]) -> Result<(), MqttError> {
+        let into_context = || MqttError::Context(format!("pre commands of array {array_id}"));
+
+        for pre_command in pre_commands {
+            let usage = pre_command.usage.to_string();
+            let command_parameters = defs::OnOffCommandParameters {
+                array_id: Some(pre_command.array_id.clone()),
+                effect_id: None,
+                dimming_amount: pre_command.dimming_amount.clone(),
+                values: None,
+                lights: None,
+                effect: None,
+                origin: Some(Arc::from(format!("pre of {array_id}"))),
+                when: defs::CommandWhen::Immediate,
+                instant: false,
+                max_ticks: None,
+                no_max_ticks: false,
+            };
+
+            info!("Running {} command on array {} before turning array {} On", usage, pre_command.array_id, array_id);
+            self.start_usage_effect(&usage, pre_command.array_id.clone(), &command_parameters)
+                .await
+                .change_context_lazy(into_context)?;
+
+            if pre_command.wait {
+                let (tx, rx) = oneshot::channel::<()>();
+
+                self.to_artnet_tx
+                    .send(messages::ToArtnetManagerMessage::WaitEffectDone(pre_command.array_id.clone(), tx))
+                    .await
+                    .unwrap();
+
+                let _ = rx.await;       // The sender is dropped once the effect is done
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn start_inline_effect(
+        &self,
+        command: &str,
+        command_parameters: defs::OnOffCommandParameters,
+    ) -> Result<(), MqttError> {
+        let max_ticks = command_parameters.get_max_ticks();
+        let (lights, effect) = match (command_parameters.lights, command_parameters.effect) {
+            (Some(lights), Some(effect)) => (lights, effect),
+            _ => return Err(MqttError::MissingArrayOrInlineEffect(command.to_string()).into()),
+        };
+        let into_context = || MqttError::Context(format!("{command} command on lights {lights}"));
+
+        // Unless given, use the lights as the effect ID, so a new effect on the same lights replaces the previous one
+        let effect_id = command_parameters.effect_id.unwrap_or_else(|| Arc::from(lights.as_str()));
+        let dimming_amount = self
+            .resolve_dimming_amount(command_parameters.dimming_amount)
+            .await
+            .change_context_lazy(into_context)?;
+        let (tx, rx) = oneshot::channel::<Result<Box<dyn EffectNodeRuntime>, DmxArrayError>>();
+
+        self.to_array_tx
+            .send(messages::ToArrayManagerMessage::GetInlineEffectRuntime(
+                lights.clone(),
+                effect,
+                dimming_amount.unwrap_or(DIMMING_AMOUNT_MAX),
+                tx,
+            ))
+            .await
+            .unwrap();
+
+        let effect_runtime_node = rx.await.unwrap().change_context_lazy(into_context)?;
+        let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+        self.to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::StartEffect(
+                messages::StartEffectRequest {
+                    effect_id,
+                    node: effect_runtime_node,
+                    usage: None,
+                    epoch: None,
+                    origin: command_parameters.origin,
+                    enqueue: command_parameters.when == defs::CommandWhen::AfterCurrent,
+                    max_ticks,
+                },
+                tx,
+            ))
+            .await
+            .unwrap();
+
+        rx.await.unwrap().change_context_lazy(into_context)
+    }
+
+    // Run the checks done when the definition is added, without changing anything
+    async fn validate_definition(&self, kind: defs::DefinitionKind, id: Arc<str>, definition: serde_json::Value) -> messages::ValidationProblems {
+        let kind_name = match kind {
+            defs::DefinitionKind::Universe => "Universe",
+            defs::DefinitionKind::Array => "Array",
+            defs::DefinitionKind::Effect => "Effect",
+        };
+
+        if let Err(e) = validate_id(kind_name, &id) {
+            return (vec![e.to_string()], Vec::new());
+        }
+
+        let parse_error = |e: serde_json::Error| (vec![json_parse_error(kind_name, id.clone(), e).to_string()], Vec::new());
+        let (tx, rx) = oneshot::channel();
+
+        match kind {
+            defs::DefinitionKind::Universe => {
+                let definition = match serde_json::from_value::<UniverseDefinition>(definition) {
+                    Ok(definition) => definition,
+                    Err(e) => return parse_error(e),
+                };
+                let (universe_tx, universe_rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+                self.to_artnet_tx.send(messages::ToArtnetManagerMessage::ValidateUniverse(id.clone(), definition, universe_tx)).await.unwrap();
+                let errors = universe_rx.await.unwrap().err().map(|e| e.to_string()).into_iter().collect();
+                return (errors, Vec::new());
+            }
+            defs::DefinitionKind::Array => match serde_json::from_value::<defs::DmxArray>(definition) {
+                Ok(definition) => self.to_array_tx.send(messages::ToArrayManagerMessage::ValidateArray(id.clone(), Box::new(definition), tx)).await.unwrap(),
+                Err(e) => return parse_error(e),
+            },
+            defs::DefinitionKind::Effect => match serde_json::from_value::<defs::EffectDefinition>(definition) {
+                Ok(definition) => self.to_array_tx.send(messages::ToArrayManagerMessage::ValidateEffect(id.clone(), definition, tx)).await.unwrap(),
+                Err(e) => return parse_error(e),
+            },
+        }
+
+        rx.await.unwrap()
+    }
+
+    // Commands with correlation_id are acknowledged when they succeed, their errors carry the correlation id
+    async fn handle_command_message(
+        &self,
+        command: Arc<str>,
+        payload: &Bytes,
+        received: Instant,      // When the command was received (or became due, for scheduled commands)
+    ) -> Result<(), MqttError> {
+        let correlation_id = get_correlation_id(payload);
+        let payload = &self.resolve_rename_aliases(&command, payload).await;
+        let result = self.do_handle_command_message(command.clone(), payload).await;
+
+        self.metrics.add_command(&command, result.is_ok());
+
+        // Failed commands are not measured since they may fail before doing most of the work
+        if result.is_ok() && command.as_ref() != "Latency" {
+            self.command_latency.lock().unwrap().add(&command, received.elapsed());
+        }
+
+        match (result, correlation_id) {
+            (Ok(()), Some(correlation_id)) => {
+                let ack = defs::CommandAck {
+                    time: chrono::Utc::now().to_rfc3339(),
+                    command: command.clone(),
+                    correlation_id,
+                };
+
+                self.to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::CommandAck(ack))
+                    .await
+                    .change_context_lazy(|| MqttError::Context(format!("acknowledging {command} command")))
+            }
+            (Err(e), Some(correlation_id)) => Err(e.attach(CorrelationId(correlation_id))),
+            (result, None) => result,
+        }
+    }
+
+    // Commands whose array_id or universe_id is the old ID of a renamed array or universe are redirected to the new ID
+    // (with a warning) until the alias expires. Resolved before the command is handled, so the effects it starts are
+    // identified by the new ID
+    async fn resolve_rename_aliases(&self, command: &str, payload: &Bytes) -> Bytes {
+        let (payload, warnings) = {
+            let mut rename_aliases = self.rename_aliases.lock().unwrap();
+            let now = Instant::now();
+
+            rename_aliases.retain(|_, (_, expires)| *expires > now);
+
+            if rename_aliases.is_empty() {
+                return payload.clone();
+            }
+
+            let mut parameters = match serde_json::from_slice::<serde_json::Value>(payload) {
+                Ok(serde_json::Value::Object(parameters)) => parameters,
+                _ => return payload.clone(),
+            };
+            let mut warnings = Vec::new();
+
+            for kind in [defs::RenameKind::Array, defs::RenameKind::Universe] {
+                for id_parameter in kind.id_parameters() {
+                    if let Some(serde_json::Value::String(id)) = parameters.get_mut(*id_parameter) {
+                        if let Some((to, expires)) = rename_aliases.get(&(kind, Arc::from(id.as_str()))) {
+                            warnings.push(format!(
+                                "{command} command uses {kind} {id} which was renamed to {to}, the old ID is accepted for another {} seconds (use {to} instead)",
+                                expires.saturating_duration_since(now).as_secs()
+                            ));
+                            *id = to.to_string();
+                        }
+                    }
+                }
+            }
+
+            if warnings.is_empty() {
+                return payload.clone();
+            }
+
+            (Bytes::from(serde_json::to_vec(&parameters).unwrap()), warnings)
+        };
+
+        for warning in warnings {
+            info!("{}", warning);
+            let _ = self.to_mqtt_publisher_tx.send(messages::ToMqttPublisherMessage::Warning(warning)).await;
+        }
+
+        payload
+    }
+
+    async fn do_handle_command_message(
+        &self,
+        command: Arc<str>,
+        payload: &Bytes,
+    ) -> Result<(), MqttError> {
+        match command.as_ref() {
+            "On" | "Off" | "Dim" | "Toggle" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::OnOffCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context(format!("parsing{command} command parameters"))
+                        })?;
+
+                // Without an array, the command must provide the lights and the effect to apply on them
+                let array_id = match command_parameters.array_id.clone() {
+                    Some(array_id) => array_id,
+                    None => return self.start_inline_effect(&command, command_parameters).await,
+                };
+
+                // A command sent before the deferred startup effect was defined supersedes it
+                if command_parameters.lights.is_none() && self.deferred_startups.lock().unwrap().remove(&array_id).is_some() {
+                    info!("Deferred startup effect of array {array_id} is superseded by {command} command");
+                }
+
+                // Pre commands are run before turning the whole array On (an undefined array is reported by the command itself)
+                let pre_commands = match command.as_ref() {
+                    "On" if command_parameters.lights.is_none() => self.get_pre_commands(array_id.clone()).await.unwrap_or_default(),
+                    _ => Vec::new(),
+                };
+
+                // Waiting for effects to complete must not hold back other messages, so the commands run in the background
+                // and their errors are published when they happen
+                if pre_commands.iter().any(|pre_command| pre_command.wait) {
+                    let subscriber = self.clone();
+
+                    tokio::spawn(async move {
+                        let mut result = subscriber.run_pre_commands(&array_id, &pre_commands).await;
+
+                        if result.is_ok() {
+                            result = subscriber.run_array_command(&command, array_id.clone(), &command_parameters).await;
+                        }
+
+                        if let Err(e) = result {
+                            error!("Error while turning array {} On after its pre commands: {:?}", array_id, e);
+                            let _ = subscriber.to_mqtt_publisher_tx.send(get_error_message(&e, ErrorCategory::Command)).await;
+                        }
+                    });
+
+                    return Ok(());
+                }
+
+                self.run_pre_commands(&array_id, &pre_commands).await?;
+                self.run_array_command(&command, array_id, &command_parameters).await?;
+            }
+
+            "Stop" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::StopCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing Stop command parameters".to_string())
+                        })?;
+
+                let array_id = command_parameters.array_id.clone();
+                let into_context = || MqttError::Context(format!("stopping effects of array {array_id}"));
+
+                info!("Stopping effects of array {array_id} (origin {})", command_parameters.origin.as_deref().unwrap_or("unknown"));
+
+                let mut stopped_effects = self
+                    .stop_effects(array_id.clone(), command_parameters.scope, command_parameters.usage)
+                    .await
+                    .change_context_lazy(into_context)?;
+
+                // Linked effects are not started for any usage, so they are not stopped when stopping by usage
+                if command_parameters.scope != defs::StopScope::Usage {
+                    for effect_id in self.stop_linked_effects(array_id.clone()).await? {
+                        if !stopped_effects.contains(&effect_id) {
+                            stopped_effects.push(effect_id);
+                        }
+                    }
+                }
+
+                self.to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::StoppedEffects(array_id.clone(), stopped_effects))
+                    .await
+                    .change_context_lazy(into_context)?;
+            }
+
+            "Adjust" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::AdjustCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing Adjust command parameters".to_string())
+                        })?;
+
+                self.adjust_dimming(command_parameters).await?;
+            }
+
+            "Rename" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::RenameCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing Rename command parameters".to_string())
+                        })?;
+
+                self.rename(command_parameters).await?;
+            }
+
+            "Reset" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::ResetCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing Reset command parameters".to_string())
+                        })?;
+
+                self.reset(command_parameters).await?;
+            }
+
+            "Pause" | "Resume" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::PauseCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context(format!("parsing {command} command parameters"))
+                        })?;
+
+                // Without "all", only the effect of the given array is paused (or resumed)
+                let effect_id = match (command_parameters.all, command_parameters.array_id) {
+                    (true, _) => None,
+                    (false, Some(array_id)) => Some(array_id),
+                    (false, None) => return Err(MqttError::MissingArrayIdOrAll(command.to_string()).into()),
+                };
+
+                let (tx, rx) = oneshot::channel::<Result<Vec<Arc<str>>, ArtnetError>>();
+
+                self.to_artnet_tx
+                    .send(messages::ToArtnetManagerMessage::PauseEffects(effect_id, command.as_ref() == "Pause", tx))
+                    .await
+                    .unwrap();
+
+                rx.await.unwrap().change_context_lazy(|| MqttError::Context(format!("{command} command")))?;
+            }
+
+            "Set" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::SetChannelsCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing Set command parameters".to_string())
+                        })?;
+                let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+                let (message, description) = match command_parameters {
+                    defs::SetChannelsCommandParameters::Single(mut parameters) => {
+                        let description = format!("universe {}", parameters.universe_id);
+                        self.resolve_set_channels_dimming_amount(&mut parameters).await.change_context_lazy(|| {
+                            MqttError::Context(format!("setting channels on {description}"))
+                        })?;
+                        (messages::ToArtnetManagerMessage::SetChannels(parameters, tx), description)
+                    }
+                    defs::SetChannelsCommandParameters::Batch(mut batch) => {
+                        let description = format!("{} entries batch", batch.len());
+                        for parameters in batch.iter_mut() {
+                            self.resolve_set_channels_dimming_amount(parameters).await.change_context_lazy(|| {
+                                MqttError::Context(format!("setting channels on {description}"))
+                            })?;
+                        }
+                        (messages::ToArtnetManagerMessage::SetChannelsBatch(batch, tx), description)
+                    }
+                };
+
+                self.to_artnet_tx.send(message).await.unwrap();
+                if let Err(e) = rx.await.unwrap() {
+                    return Err(e).change_context_lazy(|| {
+                        MqttError::Context(format!("setting channels on {description}"))
+                    });
+                }
+            }
+            "Alias" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::AliasCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing Alias command parameters".to_string())
+                        })?;
+                let description = format!("aliasing channels {} of universe {}", command_parameters.from, command_parameters.universe_id);
+                let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+                self.to_artnet_tx
+                    .send(messages::ToArtnetManagerMessage::SetChannelAlias(command_parameters, tx))
+                    .await
+                    .unwrap();
+
+                rx.await.unwrap().change_context(MqttError::Context(description))?;
+            }
+            "SetFrame" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::SetFrameCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing SetFrame command parameters".to_string())
+                        })?;
+                let description = format!("setting frame of universe {}", command_parameters.universe_id);
+                let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+                self.to_artnet_tx
+                    .send(messages::ToArtnetManagerMessage::SetFrame(command_parameters, tx))
+                    .await
+                    .unwrap();
+
+                rx.await.unwrap().change_context(MqttError::Context(description))?;
+            }
+            "Copy" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::CopyCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing Copy command parameters".to_string())
+                        })?;
+                let description = format!("copying channels of universe {} to universe {}", command_parameters.from_universe, command_parameters.to_universe);
+                let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+                self.to_artnet_tx
+                    .send(messages::ToArtnetManagerMessage::CopyChannels(command_parameters, tx))
+                    .await
+                    .unwrap();
+
+                rx.await.unwrap().change_context(MqttError::Context(description))?;
+            }
+            "Blackout" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::BlackoutCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing Blackout command parameters".to_string())
+                        })?;
+                let target = get_universe_target(&command, command_parameters.universe_id, command_parameters.universe_group)?;
+                let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+                let message = if command_parameters.restore {
+                    messages::ToArtnetManagerMessage::RestoreUniverse(target.clone(), tx)
+                } else {
+                    messages::ToArtnetManagerMessage::BlackoutUniverse(target.clone(), tx)
+                };
+
+                self.to_artnet_tx.send(message).await.unwrap();
+                if let Err(e) = rx.await.unwrap() {
+                    return Err(e).change_context_lazy(|| {
+                        MqttError::Context(format!("blackout of {target}"))
+                    });
+                }
+            }
+            "EnableSend" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::EnableSendCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing EnableSend command parameters".to_string())
+                        })?;
+                let target = get_universe_target(&command, command_parameters.universe_id, command_parameters.universe_group)?;
+                let (tx, rx) = oneshot::channel::<Result<(), ArtnetError>>();
+
+                self.to_artnet_tx
+                    .send(messages::ToArtnetManagerMessage::SetSendEnabled(target.clone(), command_parameters.enable, tx))
+                    .await
+                    .unwrap();
+
+                rx.await.unwrap().change_context_lazy(|| {
+                    MqttError::Context(format!("setting send enabled of {target}"))
+                })?;
+            }
+            "ImportEffects" => {
+                let effects = serde_json::from_slice::<BTreeMap<Arc<str>, serde_json::Value>>(payload)
+                    .change_context_lazy(|| {
+                        MqttError::Context("parsing ImportEffects command parameters".to_string())
+                    })?;
+
+                let effect_count = effects.len();
+                let mut errors = Vec::<String>::new();
+
+                // Import all effects, collect the failures instead of stopping at the first one
+                for (effect_id, effect) in effects {
+                    let effect_definition = defs::validate_id(&effect_id).and_then(|_| {
+                        serde_json::from_value::<defs::EffectDefinition>(effect).map_err(|e| e.to_string())
+                    });
+
+                    let result = match effect_definition {
+                        Ok(effect_definition) => {
+                            let (tx, rx) = oneshot::channel::<Result<(), DmxArrayError>>();
+
+                            self.to_array_tx
+                                .send(messages::ToArrayManagerMessage::AddEffect(
+                                    effect_id.clone(),
+                                    effect_definition,
+                                    tx,
+                                ))
+                                .await
+                                .unwrap();
+
+                            rx.await.unwrap().map_err(|e| e.to_string())
+                        }
+                        Err(e) => Err(e),
+                    };
+
+                    if let Err(e) = result {
+                        errors.push(format!("{effect_id} ({e})"));
+                    }
+                }
+
+                if !errors.is_empty() {
+                    return Err(MqttError::ImportEffectsFailed(
+                        errors.len(),
+                        effect_count,
+                        errors.join(", "),
+                    )
+                    .into());
+                }
+            }
+
+            "ExportEffects" => {
+                let into_context = || MqttError::Context("exporting effects".to_string());
+                let (tx, rx) = oneshot::channel();
+
+                self.to_array_tx
+                    .send(messages::ToArrayManagerMessage::GetEffects(tx))
+                    .await
+                    .unwrap();
+
+                let effects = rx.await.unwrap().change_context_lazy(into_context)?;
+
+                self.to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::ExportedEffects(effects))
+                    .await
+                    .change_context_lazy(into_context)?;
+            }
+
+            // Report array references to effects that are still not defined (once all definitions were received)
+            "CheckConfig" => {
+                let unresolved_effects = self.get_unresolved_effects().await;
+
+                for (array_id, effect_ids) in unresolved_effects.iter() {
+                    for effect_id in effect_ids.iter() {
+                        let _ = self
+                            .to_mqtt_publisher_tx
+                            .send(messages::ToMqttPublisherMessage::Warning(format!("Array {array_id} refers to effect {effect_id} which is not defined")))
+                            .await;
+                    }
+                }
+
+                info!("Configuration check: {} arrays refer to effects that are not defined", unresolved_effects.len());
+            }
+
+            "ExportPatch" => {
+                let into_context = || MqttError::Context("exporting patch".to_string());
+
+                let command_parameters = if payload.is_empty() {
+                    defs::ExportPatchCommandParameters::default()
+                } else {
+                    serde_json::from_slice::<defs::ExportPatchCommandParameters>(payload)
+                        .change_context_lazy(|| MqttError::Context("parsing ExportPatch command parameters".to_string()))?
+                };
+
+                let (tx, rx) = oneshot::channel();
+
+                self.to_array_tx
+                    .send(messages::ToArrayManagerMessage::GetPatch(tx))
+                    .await
+                    .unwrap();
+
+                self.to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::Patch(rx.await.unwrap(), command_parameters.format))
+                    .await
+                    .change_context_lazy(into_context)?;
+            }
+
+            "Verify" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::VerifyCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing Verify command parameters".to_string())
+                        })?;
+
+                let (kind, id) = (command_parameters.kind, command_parameters.id.clone());
+                let into_context = || MqttError::Context(format!("verifying {kind} {id}"));
+                let (tx, rx) = oneshot::channel();
+
+                match kind {
+                    defs::DefinitionKind::Universe => self.to_artnet_tx.send(messages::ToArtnetManagerMessage::GetUniverseDefinitionHash(id.clone(), tx)).await.unwrap(),
+                    defs::DefinitionKind::Array => self.to_array_tx.send(messages::ToArrayManagerMessage::GetArrayDefinitionHash(id.clone(), tx)).await.unwrap(),
+                    defs::DefinitionKind::Effect => self.to_array_tx.send(messages::ToArrayManagerMessage::GetEffectDefinitionHash(id.clone(), tx)).await.unwrap(),
+                }
+
+                let sha256 = rx.await.unwrap();
+                let result = match &sha256 {
+                    None => defs::VerifyStatus::NotLoaded,
+                    Some(sha256) if sha256.eq_ignore_ascii_case(command_parameters.sha256.trim()) => defs::VerifyStatus::Match,
+                    Some(_) => defs::VerifyStatus::Mismatch,
+                };
+
+                self.to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::Verify(defs::VerifyResult {
+                        kind,
+                        id: id.clone(),
+                        result,
+                        sha256,
+                    }))
+                    .await
+                    .change_context_lazy(into_context)?;
+            }
+
+            "Validate" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::ValidateCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing Validate command parameters".to_string())
+                        })?;
+
+                let (kind, id) = (command_parameters.kind, command_parameters.id.clone());
+                let into_context = || MqttError::Context(format!("validating {kind} {id}"));
+                let (errors, warnings) = self.validate_definition(kind, id.clone(), command_parameters.definition).await;
+
+                self.to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::Validate(defs::ValidateResult {
+                        kind,
+                        id: id.clone(),
+                        valid: errors.is_empty(),
+                        errors,
+                        warnings,
+                    }))
+                    .await
+                    .change_context_lazy(into_context)?;
+            }
+
+            "ResolveEffect" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::ResolveEffectCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing ResolveEffect command parameters".to_string())
+                        })?;
+
+                let array_id = command_parameters.array_id.clone();
+                let into_context = || MqttError::Context(format!("resolving {:?} effect of array {}", command_parameters.usage, array_id));
+                let (tx, rx) = oneshot::channel();
+
+                self.to_array_tx
+                    .send(messages::ToArrayManagerMessage::ResolveEffect(array_id.clone(), command_parameters.usage, command_parameters.effect_id.clone(), tx))
+                    .await
+                    .unwrap();
+
+                let resolution = rx.await.unwrap().change_context_lazy(into_context)?;
+
+                self.to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::ResolveEffect(defs::ResolveEffectResult { array_id: array_id.clone(), resolution }))
+                    .await
+                    .change_context_lazy(into_context)?;
+            }
+
+            "DumpSchedules" => {
+                let (tx, rx) = oneshot::channel();
+
+                self.to_scheduler_tx
+                    .send(messages::ToSchedulerMessage::GetSchedules(tx))
+                    .await
+                    .unwrap();
+
+                let schedules = rx.await.unwrap();
+
+                self.to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::Schedules(schedules))
+                    .await
+                    .change_context_lazy(|| MqttError::Context("dumping schedules".to_string()))?;
+            }
+
+            "DumpDimmingPresets" => {
+                let (tx, rx) = oneshot::channel();
+
+                self.to_array_tx
+                    .send(messages::ToArrayManagerMessage::GetDimmingPresets(tx))
+                    .await
+                    .unwrap();
+
+                let presets = rx.await.unwrap();
+
+                self.to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::DimmingPresets(presets))
+                    .await
+                    .change_context_lazy(|| MqttError::Context("dumping dimming presets".to_string()))?;
+            }
+
+            "EffectStats" => {
+                let command_parameters = if payload.is_empty() {
+                    defs::EffectStatsCommandParameters::default()
+                } else {
+                    serde_json::from_slice::<defs::EffectStatsCommandParameters>(payload)
+                        .change_context_lazy(|| MqttError::Context("parsing EffectStats command parameters".to_string()))?
+                };
+
+                let (tx, rx) = oneshot::channel();
+
+                self.to_artnet_tx
+                    .send(messages::ToArtnetManagerMessage::GetEffectStats(command_parameters.reset, tx))
+                    .await
+                    .unwrap();
+
+                let entries = rx.await.unwrap();
+
+                self.to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::EffectStats(entries))
+                    .await
+                    .change_context_lazy(|| MqttError::Context("publishing effect statistics".to_string()))?;
+            }
+
+            "Latency" => {
+                let command_parameters = if payload.is_empty() {
+                    defs::LatencyCommandParameters::default()
+                } else {
+                    serde_json::from_slice::<defs::LatencyCommandParameters>(payload)
+                        .change_context_lazy(|| MqttError::Context("parsing Latency command parameters".to_string()))?
+                };
+
+                let entries = {
+                    let mut command_latency = self.command_latency.lock().unwrap();
+                    let entries = command_latency.get_entries();
+
+                    if command_parameters.reset {
+                        command_latency.reset();
+                    }
+                    entries
+                };
+
+                self.to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::Latency(entries))
+                    .await
+                    .change_context_lazy(|| MqttError::Context("publishing command latency".to_string()))?;
+            }
+
+            "Diagnostics" => {
+                let into_context = || MqttError::Context("gathering diagnostics".to_string());
+
+                let command_parameters = if payload.is_empty() {
+                    defs::DiagnosticsCommandParameters::default()
+                } else {
+                    serde_json::from_slice::<defs::DiagnosticsCommandParameters>(payload)
+                        .change_context_lazy(|| MqttError::Context("parsing Diagnostics command parameters".to_string()))?
+                };
+
+                let diagnostics = self.get_diagnostics(command_parameters.include_values).await;
+
+                self.to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::Diagnostics(Box::new(diagnostics)))
+                    .await
+                    .change_context_lazy(into_context)?;
+            }
+
+            "Schema" => {
+                self.to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::Schema(self.schema.as_ref().clone()))
+                    .await
+                    .change_context_lazy(|| MqttError::Context("publishing schema".to_string()))?;
+            }
+
+            "EffectStatus" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::EffectStatusCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing EffectStatus command parameters".to_string())
+                        })?;
+
+                let array_id = command_parameters.array_id.clone();
+                let into_context =
+                    || MqttError::Context(format!("getting effect status of array {array_id}"));
+                let (tx, rx) = oneshot::channel::<Result<defs::EffectStatus, ArtnetError>>();
+
+                self.to_artnet_tx
+                    .send(messages::ToArtnetManagerMessage::GetEffectStatus(
+                        command_parameters.array_id.clone(),
+                        command_parameters.verbose,
+                        tx,
+                    ))
+                    .await
+                    .unwrap();
+
+                let mut effect_status = rx.await.unwrap().change_context_lazy(into_context)?;
+                let array_state = self
+                    .get_array_state(array_id.clone())
+                    .await
+                    .change_context_lazy(into_context)?;
+
+                effect_status.last_command = array_state.map(|state| state.usage);
+                effect_status.last_dimming_amount = array_state.map(|state| state.dimming_amount);
+
+                self.to_mqtt_publisher_tx
+                    .send(messages::ToMqttPublisherMessage::EffectStatus(
+                        command_parameters.array_id,
+                        effect_status,
+                    ))
+                    .await
+                    .change_context_lazy(into_context)?;
+            }
+
+            "UniverseStatus" => {
+                let command_parameters =
+                    serde_json::from_slice::<defs::UniverseStatusCommandParameters>(payload)
+                        .change_context_lazy(|| {
+                            MqttError::Context("parsing UniverseStatus command parameters".to_string())
+                        })?;
+
+                let target = get_universe_target(&command, command_parameters.universe_id, command_parameters.universe_group)?;
+                let into_context =
+                    || MqttError::Context(format!("getting send status of {target}"));
+                let (tx, rx) = oneshot::channel::<Result<messages::UniversesSendStatus, ArtnetError>>();
+
+                self.to_artnet_tx
+                    .send(messages::ToArtnetManagerMessage::GetUniverseSendStatus(target.clone(), tx))
+                    .await
+                    .unwrap();
+
+                // Status of each universe is published to its own DMX/Universe/<id>/SendStatus topic
+                for (universe_id, send_status) in rx.await.unwrap().change_context_lazy(into_context)? {
+                    self.to_mqtt_publisher_tx
+                        .send(messages::ToMqttPublisherMessage::UniverseSendStatus(universe_id, send_status))
+                        .await
+                        .change_context_lazy(into_context)?;
+                }
+            }
+            _ => return Err(MqttError::InvalidCommand(command.to_string()).into()),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{array_manager::ArrayManager, artnet_manager::ArtnetManager, definition_hash::get_definition_hash, manager_channel, messages::ToMqttPublisherMessage, scheduler::Scheduler};
+    use tokio_util::sync::CancellationToken;
+
+    // Runs the artnet and array managers and feeds MQTT messages directly into the subscriber
+    struct SubscriberHarness {
+        subscriber: MqttSubscriber,
+        to_mqtt_publisher_rx: async_channel::Receiver<ToMqttPublisherMessage>,
+        cancel: CancellationToken,
+    }
+
+    impl SubscriberHarness {
+        fn new() -> Self {
+            let cancel = CancellationToken::new();
+            let (to_artnet_tx, to_artnet_rx) = manager_channel::channel::<messages::ToArtnetManagerMessage>("artnet", 10);
+            let (to_array_tx, to_array_rx) = manager_channel::channel::<messages::ToArrayManagerMessage>("array", 10);
+            let (to_mqtt_publisher_tx, to_mqtt_publisher_rx) = async_channel::bounded(10);
+            let metrics = Arc::new(Metrics::default());
+
+            let cancel_instance = cancel.clone();
+            let to_mqtt_publisher_tx_instance = to_mqtt_publisher_tx.clone();
+            let metrics_instance = metrics.clone();
+            tokio::spawn(async move {
+                ArtnetManager::new()
+                    .with_metrics(metrics_instance)
+                    .run(cancel_instance, to_artnet_rx, to_mqtt_publisher_tx_instance)
+                    .await;
+            });
+
+            let cancel_instance = cancel.clone();
+            tokio::spawn(async move {
+                ArrayManager::new().run(cancel_instance, to_array_rx).await;
+            });
+
+            let (to_scheduler_tx, to_scheduler_rx) = tokio::sync::mpsc::channel(10);
+            let subscriber = MqttSubscriber::new(to_artnet_tx, to_array_tx, to_mqtt_publisher_tx, to_scheduler_tx, true).with_metrics(metrics);
+
+            let cancel_instance = cancel.clone();
+            let subscriber_instance = subscriber.clone();
+            tokio::spawn(async move {
+                Scheduler::new().run(cancel_instance, to_scheduler_rx, subscriber_instance).await;
+            });
+
+            SubscriberHarness {
+                subscriber,
+                to_mqtt_publisher_rx,
+                cancel,
+            }
+        }
+
+        async fn publish(&self, topic: &str, payload: &str) -> Result<(), MqttError> {
+            self.subscriber
+                .handle_message(topic, &Bytes::from(payload.to_string()), false, Instant::now())
+                .await
+        }
+
+        async fn publish_retained(&self, topic: &str, payload: &str) -> Result<(), MqttError> {
+            self.subscriber
+                .handle_message(topic, &Bytes::from(payload.to_string()), true, Instant::now())
+                .await
+        }
+
+        // Test universes disable sending, so the (throttled) send disabled warnings published on ticks are skipped
+        fn published(&self) -> Vec<ToMqttPublisherMessage> {
+            let mut messages = Vec::new();
+
+            while let Ok(message) = self.to_mqtt_publisher_rx.try_recv() {
+                if !matches!(&message, ToMqttPublisherMessage::Warning(warning) if warning.contains("sending is disabled")) {
+                    messages.push(message);
+                }
+            }
+            messages
+        }
+    }
+
+    impl Drop for SubscriberHarness {
+        fn drop(&mut self) {
+            self.cancel.cancel();
+        }
+    }
+
+    async fn add_test_array(harness: &SubscriberHarness) {
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "description": "Test array", "lights": { "all": "rgb:1" } }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/test", array_json).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_patch() {
+        let harness = SubscriberHarness::new();
+        add_test_array(&harness).await;
+
+        harness.publish("DMX/Command/ExportPatch", "").await.unwrap();
+        harness.publish("DMX/Command/ExportPatch", r#"{ "format": "csv" }"#).await.unwrap();
+
+        match harness.published().as_slice() {
+            [ToMqttPublisherMessage::Patch(patch, defs::PatchFormat::Json), ToMqttPublisherMessage::Patch(_, defs::PatchFormat::Csv)] => {
+                let roles = patch.universes["0"].iter().map(|entry| (entry.channel, entry.role)).collect::<Vec<_>>();
+                assert_eq!(roles, vec![(1, "r"), (2, "g"), (3, "b")]);
+            }
+            messages => panic!("Expected Patch messages, got {:?}", messages),
+        }
+
+        // The published patch is not a command
+        harness.publish("DMX/Patch", "universe,channel,array,groups,role,conflict").await.unwrap();
+        assert!(harness.published().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_toggle() {
+        let harness = SubscriberHarness::new();
+        add_test_array(&harness).await;
+
+        let toggle = r#"{ "array_id": "test" }"#;
+
+        assert_eq!(harness.subscriber.get_array_state(Arc::from("test")).await.unwrap().map(|state| state.usage), None);
+
+        harness.publish("DMX/Command/Toggle", toggle).await.unwrap();
+        assert_eq!(harness.subscriber.get_array_state(Arc::from("test")).await.unwrap().map(|state| state.usage), Some(EffectUsage::On));
+
+        harness.publish("DMX/Command/Toggle", toggle).await.unwrap();
+        assert_eq!(harness.subscriber.get_array_state(Arc::from("test")).await.unwrap().map(|state| state.usage), Some(EffectUsage::Off));
+
+        harness.publish("DMX/Command/On", toggle).await.unwrap();
+        harness.publish("DMX/Command/Off", toggle).await.unwrap();
+        harness.publish("DMX/Command/Toggle", toggle).await.unwrap();
+        assert_eq!(harness.subscriber.get_array_state(Arc::from("test")).await.unwrap().map(|state| state.usage), Some(EffectUsage::On));
+
+        harness.published();
+        harness.publish("DMX/Command/EffectStatus", toggle).await.unwrap();
+        match &harness.published()[..] {
+            [ToMqttPublisherMessage::EffectStatus(array_id, effect_status)] => {
+                assert_eq!(array_id.as_ref(), "test");
+                assert_eq!(effect_status.last_command, Some(EffectUsage::On));
+            }
+            messages => panic!("Expected EffectStatus message, got {:?}", messages),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_command_origin() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" }, "startup": "slow_on",
+            "effects": { "slow_on": { "type": "fade", "lights": "@all", "ticks": 100000, "target": "s(255)" } } }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/kitchen", array_json).await.unwrap();
+
+        let harness = &harness;
+        let get_origins = || async move {
+            harness.published();
+            harness.publish("DMX/Command/EffectStatus", r#"{ "array_id": "kitchen" }"#).await.unwrap();
+            harness.publish("DMX/Command/Diagnostics", "").await.unwrap();
+
+            match &harness.published()[..] {
+                [ToMqttPublisherMessage::EffectStatus(_, effect_status), ToMqttPublisherMessage::Diagnostics(diagnostics)] => (
+                    effect_status.origin.as_deref().map(str::to_string),
+                    serde_json::to_value(&diagnostics.artnet.active_effects).unwrap(),
+                ),
+                messages => panic!("Expected EffectStatus and Diagnostics messages, got {:?}", messages),
+            }
+        };
+
+        let (origin, active_effects) = get_origins().await;
+        assert_eq!(origin.as_deref(), Some("startup"));
+        assert_eq!(active_effects["kitchen"]["origin"], "startup");
+
+        // Replacing effect brings its own origin
+        harness.publish("DMX/Command/On", r#"{ "array_id": "kitchen", "effect_id": "slow_on", "origin": "alice-night-mode" }"#).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "lights": "$0, s:2", "effect": { "type": "delay", "ticks": 100000 }, "origin": "bob" }"#).await.unwrap();
+
+        let (origin, active_effects) = get_origins().await;
+        assert_eq!(origin.as_deref(), Some("alice-night-mode"));
+        assert_eq!(active_effects["kitchen"]["origin"], "alice-night-mode");
+        assert_eq!(active_effects["$0, s:2"]["origin"], "bob");
+
+        // Commands without origin
+        harness.publish("DMX/Command/On", r#"{ "array_id": "kitchen", "effect_id": "slow_on" }"#).await.unwrap();
+        let (origin, active_effects) = get_origins().await;
+        assert_eq!(origin, None);
+        assert!(active_effects["kitchen"]["origin"].is_null());
+
+        harness.publish("DMX/Command/Set", r#"{ "universe_id": "0", "channels": "s:3", "target": "s(10)", "origin": "carol" }"#).await.unwrap();
+        harness.publish("DMX/Command/Stop", r#"{ "array_id": "kitchen", "origin": "carol" }"#).await.unwrap();
+        assert!(!is_effect_running(harness, "kitchen").await);
+    }
+
+    #[tokio::test]
+    async fn test_default_dimming_amount() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" }, "default_dimming_amount": 250 }"#;
+        let bedroom = r#"{ "array_id": "bedroom" }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/bedroom", array_json).await.unwrap();
+
+        let get_last_dimming_amount = || async {
+            harness.published();
+            harness.publish("DMX/Command/EffectStatus", bedroom).await.unwrap();
+
+            match &harness.published()[..] {
+                [ToMqttPublisherMessage::EffectStatus(_, effect_status)] => effect_status.last_dimming_amount,
+                messages => panic!("Expected EffectStatus message, got {:?}", messages),
+            }
+        };
+
+        harness.publish("DMX/Command/Dim", bedroom).await.unwrap();
+        assert_eq!(get_last_dimming_amount().await, Some(250));
+
+        harness.publish("DMX/Command/On", r#"{ "array_id": "bedroom", "dimming_amount": 800 }"#).await.unwrap();
+        assert_eq!(get_last_dimming_amount().await, Some(800));
+
+        harness.publish("DMX/Command/Off", bedroom).await.unwrap();
+        assert_eq!(get_last_dimming_amount().await, Some(DIMMING_AMOUNT_MAX));
+    }
+
+    #[tokio::test]
+    async fn test_dimming_presets() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" } }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/bedroom", array_json).await.unwrap();
+        harness.publish("DMX/DimmingPreset/night", r#"{ "amount": 250 }"#).await.unwrap();
+        harness.publish("DMX/DimmingPreset/evening", r#"{ "amount": 600 }"#).await.unwrap();
+
+        let e = harness.publish("DMX/DimmingPreset/bad", r#"{ "amount": 1200 }"#).await.unwrap_err();
+        assert!(format!("{:?}", e).contains("invalid amount 1200"));
+
+        let get_last_dimming_amount = || async {
+            harness.published();
+            harness.publish("DMX/Command/EffectStatus", r#"{ "array_id": "bedroom" }"#).await.unwrap();
+
+            match &harness.published()[..] {
+                [ToMqttPublisherMessage::EffectStatus(_, effect_status)] => effect_status.last_dimming_amount,
+                messages => panic!("Expected EffectStatus message, got {:?}", messages),
+            }
+        };
+
+        // Numeric amounts are used as is, names are resolved to the preset amount
+        harness.publish("DMX/Command/Dim", r#"{ "array_id": "bedroom", "dimming_amount": 400 }"#).await.unwrap();
+        assert_eq!(get_last_dimming_amount().await, Some(400));
+
+        harness.publish("DMX/Command/Dim", r#"{ "array_id": "bedroom", "dimming_amount": "night" }"#).await.unwrap();
+        assert_eq!(get_last_dimming_amount().await, Some(250));
+
+        let e = harness.publish("DMX/Command/On", r#"{ "array_id": "bedroom", "dimming_amount": "party" }"#).await.unwrap_err();
+        assert!(format!("{:?}", e).contains("Dimming preset 'party' is not defined (defined presets: evening, night)"));
+        assert_eq!(get_last_dimming_amount().await, Some(250));
+
+        harness.publish("DMX/Command/Set", r#"{ "universe_id": "0", "channels": "s:2", "target": "s(200)", "dimming_amount": 500 }"#).await.unwrap();
+        harness.publish("DMX/Command/Set", r#"{ "universe_id": "0", "channels": "s:2", "target": "s(200)", "dimming_amount": "evening" }"#).await.unwrap();
+        harness
+            .publish("DMX/Command/Set", r#"[{ "universe_id": "0", "channels": "s:2", "target": "s(200)" }, { "universe_id": "0", "channels": "s:3", "target": "s(200)", "dimming_amount": "night" }]"#)
+            .await
+            .unwrap();
+
+        let e = harness.publish("DMX/Command/Set", r#"{ "universe_id": "0", "channels": "s:2", "target": "s(200)", "dimming_amount": "party" }"#).await.unwrap_err();
+        assert!(format!("{:?}", e).contains("Dimming preset 'party' is not defined"));
+
+        // Removed presets are no longer listed
+        harness.publish("DMX/DimmingPreset/evening", "").await.unwrap();
+        harness.published();
+        harness.publish("DMX/Command/DumpDimmingPresets", "").await.unwrap();
+
+        match &harness.published()[..] {
+            [ToMqttPublisherMessage::DimmingPresets(presets)] => {
+                assert_eq!(presets.keys().map(|name| name.as_ref()).collect::<Vec<_>>(), vec!["night"]);
+                assert_eq!(presets["night"].amount, 250);
+            }
+            messages => panic!("Expected DimmingPresets message, got {:?}", messages),
+        }
+
+        let e = harness.publish("DMX/Command/Set", r#"{ "universe_id": "0", "channels": "s:2", "target": "s(200)", "dimming_amount": "evening" }"#).await.unwrap_err();
+        assert!(format!("{:?}", e).contains("(defined presets: night)"));
+    }
+
+    fn start_effect_request(effect_id: &str, node: Box<dyn EffectNodeRuntime>, epoch: ArrayEpoch) -> messages::StartEffectRequest {
+        messages::StartEffectRequest {
+            effect_id: Arc::from(effect_id),
+            node,
+            usage: Some(EffectUsage::On),
+            epoch: Some(epoch),
+            origin: None,
+            enqueue: false,
+            max_ticks: defs::EffectMaxTicks::Default,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_array_redefined_during_command() {
+        let harness = SubscriberHarness::new();
+        let array_json = r#"{ "universe_id": "0", "description": "Test array", "lights": { "all": "rgb:1" } }"#;
+        add_test_array(&harness).await;
+
+        // Effect runtime is built from the current definition of the array
+        let (tx, rx) = oneshot::channel();
+        harness
+            .subscriber
+            .to_array_tx
+            .send(messages::ToArrayManagerMessage::GetEffectRuntime(Arc::from("test"), EffectUsage::On, None, None, None, tx))
+            .await
+            .unwrap();
+        let (effect_runtime_node, epoch, _) = rx.await.unwrap().unwrap();
+
+        // The array is redefined before the effect is started
+        harness.publish("DMX/Array/test", array_json).await.unwrap();
+
+        let (tx, rx) = oneshot::channel();
+        harness
+            .subscriber
+            .to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::StartEffect(start_effect_request("test", effect_runtime_node, epoch), tx))
+            .await
+            .unwrap();
+        let e = rx.await.unwrap().unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::StaleArrayEpoch(array_id, 1, 2) if array_id == "test"));
+
+        // Commands use the new definition
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test" }"#).await.unwrap();
+
+        // Command is retried once, if the epoch is still stale the command fails
+        let (tx, rx) = oneshot::channel();
+        harness
+            .subscriber
+            .to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::SetArrayEpoch(Arc::from("test"), 10, tx))
+            .await
+            .unwrap();
+        rx.await.unwrap().unwrap();
+
+        let e = harness.publish("DMX/Command/On", r#"{ "array_id": "test" }"#).await.unwrap_err();
+        assert!(e.frames().any(|f| matches!(f.downcast_ref::<ArtnetError>(), Some(ArtnetError::StaleArrayEpoch(_, 2, 10)))));
+
+        // Removing and adding the array again also brings the artnet manager epoch up to date
+        harness.publish("DMX/Array/test", "").await.unwrap();
+        harness.publish("DMX/Array/test", array_json).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test" }"#).await.unwrap();
+
+        // Light group effects (test@all) are checked against the epoch of their array
+        let (tx, rx) = oneshot::channel();
+        harness
+            .subscriber
+            .to_array_tx
+            .send(messages::ToArrayManagerMessage::GetEffectRuntime(Arc::from("test"), EffectUsage::On, None, Some("@all".to_string()), None, tx))
+            .await
+            .unwrap();
+        let (effect_runtime_node, epoch, _) = rx.await.unwrap().unwrap();
+
+        harness.publish("DMX/Array/test", array_json).await.unwrap();
+
+        let (tx, rx) = oneshot::channel();
+        harness
+            .subscriber
+            .to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::StartEffect(start_effect_request("test@all", effect_runtime_node, epoch), tx))
+            .await
+            .unwrap();
+        let e = rx.await.unwrap().unwrap_err();
+        assert!(matches!(e.current_context(), ArtnetError::StaleArrayEpoch(array_id, stale_epoch, _) if array_id == "test" && *stale_epoch == epoch));
+        assert!(!is_effect_running(&harness, "test@all").await);
+
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test", "lights": "@all" }"#).await.unwrap();
+        assert!(is_effect_running(&harness, "test@all").await);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_ids() {
+        let harness = SubscriberHarness::new();
+
+        for (topic, kind, id, error) in [
+            ("DMX/Array/a+b", "array", "a+b", "id must not contain '+'"),
+            ("DMX/Array/a#b", "array", "a#b", "id must not contain '#'"),
+            ("DMX/Universe/+", "universe", "+", "id must not contain '+'"),
+            ("DMX/Effect/#", "effect", "#", "id must not contain '#'"),
+            ("DMX/Value/ ", "value", " ", "id must not be empty or whitespace only"),
+            ("DMX/Array/", "array", "", "id must not be empty or whitespace only"),
+        ] {
+            let e = harness.publish(topic, "").await.unwrap_err();
+
+            match e.current_context() {
+                MqttError::InvalidId(k, i, message) => {
+                    assert_eq!((k.as_str(), i.as_str(), message.as_str()), (kind, id, error));
+                }
+                _ => panic!("Expected InvalidId error for {topic}, got {:?}", e),
+            }
+        }
+
+        let e = harness
+            .publish("DMX/Array/kitchen/spots", "")
+            .await
+            .unwrap_err();
+        assert!(matches!(e.current_context(), MqttError::TooManyTopicLevels(topic) if topic == "DMX/Array/kitchen/spots"));
+
+        let e = harness
+            .publish("DMX/Command/On/now", "")
+            .await
+            .unwrap_err();
+        assert!(matches!(e.current_context(), MqttError::TooManyTopicLevels(_)));
+
+        // Status subtopics published by this service are still ignored
+        harness
+            .publish("DMX/Array/kitchen/EffectStatus", "")
+            .await
+            .unwrap();
+
+        let e = harness
+            .publish("DMX/Command/ImportEffects", r#"{"a/b": {"type": "delay", "ticks": 1}}"#)
+            .await
+            .unwrap_err();
+        assert!(matches!(e.current_context(), MqttError::ImportEffectsFailed(1, 1, errors) if errors == "a/b (id must not contain '/')"));
+    }
+
+    #[tokio::test]
+    async fn test_force_remove_effect() {
+        let harness = SubscriberHarness::new();
+        add_test_array(&harness).await;
+
+        harness
+            .publish("DMX/Effect/on", r#"{ "type": "delay", "ticks": 1 }"#)
+            .await
+            .unwrap();
+
+        let e = harness.publish("DMX/Effect/on", "").await.unwrap_err();
+        assert!(matches!(e.current_context(), MqttError::Context(_)));
+        assert!(e.frames().any(|frame| matches!(frame.downcast_ref::<DmxArrayError>(), Some(DmxArrayError::EffectInUse(_, _)))));
+
+        harness
+            .publish("DMX/Effect/on", r#"{ "force": true }"#)
+            .await
+            .unwrap();
+
+        harness.publish("DMX/Command/ExportEffects", "").await.unwrap();
+        match &harness.published()[..] {
+            [ToMqttPublisherMessage::ExportedEffects(effects)] => assert!(effects.is_empty()),
+            messages => panic!("Expected ExportedEffects message, got {:?}", messages),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_array_missing_field() {
+        let harness = SubscriberHarness::new();
+
+        let e = harness
+            .publish("DMX/Array/kitchen", r#"{ "description": "Kitchen" }"#)
+            .await
+            .unwrap_err();
+
+        assert!(e.frames().any(|frame| matches!(
+            frame.downcast_ref::<MqttError>(),
+            Some(MqttError::MissingField(kind, id, field)) if kind.as_ref() == "Array" && id.as_ref() == "kitchen" && field == "lights"
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_wrong_definition_topic() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" } }"#;
+        let effect_json = r#"{ "type": "delay", "ticks": 10 }"#;
+
+        let cases = [
+            ("DMX/Universe/x", array_json, "an array", "DMX/Array/x"),
+            ("DMX/Universe/x", effect_json, "an effect", "DMX/Effect/x"),
+            ("DMX/Array/x", universe_json, "a universe", "DMX/Universe/x"),
+            ("DMX/Array/x", effect_json, "an effect", "DMX/Effect/x"),
+            ("DMX/Effect/x", universe_json, "a universe", "DMX/Universe/x"),
+            ("DMX/Effect/x", array_json, "an array", "DMX/Array/x"),
+        ];
+
+        for (topic, payload, expected_description, expected_topic) in cases {
+            let e = harness.publish(topic, payload).await.unwrap_err();
+
+            assert!(
+                e.frames().any(|f| matches!(f.downcast_ref::<MqttError>(),
+                    Some(MqttError::WrongDefinitionTopic(_, description, topic)) if *description == expected_description && topic == expected_topic)),
+                "{topic}: {e:?}"
+            );
+            assert!(e.frames().any(|f| matches!(f.downcast_ref::<MqttError>(), Some(MqttError::MissingField(_, _, _)))));
+        }
+
+        // No hint if the payload is not any other definition
+        let e = harness.publish("DMX/Universe/x", r#"{ "description": "Test universe" }"#).await.unwrap_err();
+        assert!(!e.frames().any(|f| matches!(f.downcast_ref::<MqttError>(), Some(MqttError::WrongDefinitionTopic(_, _, _)))));
+    }
+
+    #[tokio::test]
+    async fn test_watchers() {
+        let harness = SubscriberHarness::new();
+        let screen = r#"{ "universe_id": "0", "channel": "s:7", "condition": ">=200", "topic": "AV/screen/down", "payload": "true" }"#;
+
+        harness.publish("DMX/Watcher/screen", screen).await.unwrap();
+
+        let e = harness.publish("DMX/Watcher/bad", &screen.replace(">=200", "~200")).await.unwrap_err();
+        assert!(e.frames().any(|f| matches!(f.downcast_ref::<ArtnetError>(), Some(ArtnetError::InvalidWatcherCondition(_)))));
+
+        harness.publish("DMX/Watcher/screen", "").await.unwrap();
+        let e = harness.publish("DMX/Watcher/screen", "").await.unwrap_err();
+        assert!(e.frames().any(|f| matches!(f.downcast_ref::<ArtnetError>(), Some(ArtnetError::WatcherNotFound(_)))));
+    }
+
+    #[tokio::test]
+    async fn test_schedules() {
+        let harness = SubscriberHarness::new();
+        let porch = r#"{ "daily_at": "17:30", "command": "On", "parameters": { "array_id": "porch" }, }"#;
+
+        harness.publish("DMX/Schedule/porch", porch).await.unwrap();
+        harness.publish("DMX/Command/DumpSchedules", "").await.unwrap();
+
+        match &harness.published()[..] {
+            [ToMqttPublisherMessage::Schedules(schedules)] => {
+                assert_eq!(schedules.len(), 1);
+                assert_eq!(schedules["porch"].daily_at.as_deref(), Some("17:30"));
+                assert_eq!(schedules["porch"].parameters, Some(serde_json::json!({ "array_id": "porch" })));
+            }
+            messages => panic!("Expected Schedules message, got {:?}", messages),
+        }
+
+        let e = harness.publish("DMX/Schedule/bad", r#"{ "cron": "30 17 * *", "command": "On" }"#).await.unwrap_err();
+        assert!(e.frames().any(|f| matches!(f.downcast_ref::<SchedulerError>(), Some(SchedulerError::InvalidCron(_, _, _)))));
+
+        // Empty payload removes the schedule
+        harness.publish("DMX/Schedule/porch", "").await.unwrap();
+        harness.publish("DMX/Command/DumpSchedules", "").await.unwrap();
+        assert!(matches!(&harness.published()[..], [ToMqttPublisherMessage::Schedules(schedules)] if schedules.is_empty()));
+
+        // The published list of schedules is ignored
+        harness.publish("DMX/Schedules", "{}").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_commands_order() {
+        let harness = SubscriberHarness::new();
+        add_test_array(&harness).await;
+
+        // Names are sorted opposite to the due minutes, the commands must still run in minute order
+        let mut scheduler = Scheduler::new();
+        let at = |minute| chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(23, minute, 0).unwrap();
+        let on = r#"{ "daily_at": "23:01", "command": "On", "parameters": { "array_id": "test" } }"#;
+        let off = r#"{ "daily_at": "23:02", "command": "Off", "parameters": { "array_id": "test" } }"#;
+
+        scheduler.set_schedule(Arc::from("a_off"), Some(serde_json::from_str(off).unwrap())).unwrap();
+        scheduler.set_schedule(Arc::from("b_on"), Some(serde_json::from_str(on).unwrap())).unwrap();
+        assert!(scheduler.get_due_commands(at(0)).is_empty());
+
+        // A late check catches up on both minutes
+        let due_commands = scheduler.get_due_commands(at(3));
+        let names = due_commands.iter().map(|c| c.schedule_name.to_string()).collect::<Vec<_>>();
+        assert_eq!(names, ["b_on", "a_off"]);
+
+        harness.subscriber.handle_scheduled_commands(due_commands).await;
+        assert_eq!(harness.subscriber.get_array_state(Arc::from("test")).await.unwrap().map(|state| state.usage), Some(EffectUsage::Off));
+        assert!(harness.published().iter().all(|message| !matches!(message, ToMqttPublisherMessage::Error(_, _, _))));
+    }
+
+    #[tokio::test]
+    async fn test_array_last_error() {
+        let harness = SubscriberHarness::new();
+        add_test_array(&harness).await;
+
+        let e = harness
+            .publish("DMX/Command/On", r#"{ "array_id": "test", "effect_id": "missing" }"#)
+            .await
+            .unwrap_err();
+
+        match &harness.published()[..] {
+            [ToMqttPublisherMessage::ArrayLastError(array_id, Some(error))] => {
+                assert_eq!(array_id.as_ref(), "test");
+                assert_eq!(error, &e.to_string());
+            }
+            messages => panic!("Expected ArrayLastError message, got {:?}", messages),
+        }
+
+        harness
+            .publish("DMX/Command/On", r#"{ "array_id": "test" }"#)
+            .await
+            .unwrap();
+
+        match &harness.published()[..] {
+            [ToMqttPublisherMessage::ArrayLastError(array_id, None)] => assert_eq!(array_id.as_ref(), "test"),
+            messages => panic!("Expected ArrayLastError clear message, got {:?}", messages),
+        }
+
+        // Last error published by this service is ignored
+        harness.publish("DMX/Array/test/LastError", "").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_inline_effect() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Garden", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 1, "channels": 16, "disable_send": true }"#;
+
+        harness.publish("DMX/Universe/garden", universe_json).await.unwrap();
+        harness
+            .publish(
+                "DMX/Command/On",
+                r#"{ "lights": "$garden,s:12", "effect": { "type": "fade", "lights": "@all", "ticks": 40, "target": "s(255)" } }"#,
+            )
+            .await
+            .unwrap();
+
+        let e = harness
+            .publish("DMX/Command/On", r#"{ "lights": "$garden,s:12" }"#)
+            .await
+            .unwrap_err();
+        assert!(matches!(e.current_context(), MqttError::MissingArrayOrInlineEffect(command) if command == "On"));
+
+        // Inline effects are not associated with an array, so no array last error is published
+        assert!(harness.published().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_lenient_json_definitions() {
+        let mut harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true, }"#;
+        let array_json = r#"
+            {
+                // Spots at the entrance
+                "universe_id": "0",
+                "lights": { "all": "rgb:1", /* "spots": "rgb:4" */ },
+            }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/test", array_json).await.unwrap();
+        harness.publish("DMX/Value/level", r#"{ "value": "50", }"#).await.unwrap();
+
+        // Command payloads are not lenient
+        let e = harness.publish("DMX/Command/On", r#"{ "array_id": "test", }"#).await.unwrap_err();
+        assert!(matches!(e.current_context(), MqttError::Context(_)));
+
+        harness.subscriber.lenient_json = false;
+        let e = harness.publish("DMX/Array/test", array_json).await.unwrap_err();
+        assert!(e.frames().any(|f| matches!(f.downcast_ref::<MqttError>(), Some(MqttError::JsonParseError(_, _, _)))));
+    }
+
+    #[tokio::test]
+    async fn test_fixture_messages() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Fixture/par56", r#"{ "type": "rgb", "offsets": [0, 4, 8] }"#).await.unwrap();
+        harness.publish("DMX/Array/test", r#"{ "universe_id": "0", "lights": { "all": "par56:1" } }"#).await.unwrap();
+
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test" }"#).await.unwrap();
+        assert!(is_effect_running(&harness, "test").await);
+
+        let e = harness.publish("DMX/Fixture/par56", "").await.unwrap_err();
+        assert!(e.frames().any(|f| matches!(f.downcast_ref::<DmxArrayError>(), Some(DmxArrayError::FixtureInUse(_, _)))));
+        let e = harness.publish("DMX/Fixture/spot", r#"{ "type": "rgb", "offsets": [0, 4, 8], "dimmer": 1 }"#).await.unwrap_err();
+        assert!(e.frames().any(|f| matches!(f.downcast_ref::<MqttError>(), Some(MqttError::JsonParseError(_, _, _)))));
+
+        harness.publish("DMX/Array/test", "").await.unwrap();
+        harness.publish("DMX/Fixture/par56", "").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_non_strict_array_warnings() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "strict": false, "lights": { "all": "s:1", "spot": "s:2", "frame": "s:3" } }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/test", array_json).await.unwrap();
+
+        let warnings = harness
+            .published()
+            .into_iter()
+            .filter_map(|message| match message {
+                ToMqttPublisherMessage::Warning(warning) => Some(warning),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("@frame") && warnings[1].contains("@spot"));
+
+        // The valid part of the array can be used
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test" }"#).await.unwrap();
+        assert!(is_effect_running(&harness, "test").await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_definitions_received_check() {
+        let harness = SubscriberHarness::new();
+        let has_warning = |messages: Vec<ToMqttPublisherMessage>| {
+            messages.iter().any(|message| matches!(message, ToMqttPublisherMessage::Warning(warning) if warning.contains("broker.local")))
+        };
+
+        // Nothing received during the grace period
+        harness.subscriber.check_definitions_received("broker.local", DEFINITIONS_GRACE_PERIOD).await;
+        assert!(has_warning(harness.published()));
+
+        // Connection status published by the service is not a definition
+        harness.publish("DMX/Status/Connection", r#"{ "broker": "broker.local" }"#).await.unwrap();
+        harness.publish("DMX/Array/test", "").await.unwrap();
+        assert_eq!(harness.subscriber.get_definition_counts(), (0, 0));
+
+        let check = tokio::spawn({
+            let subscriber = harness.subscriber.clone();
+            async move { subscriber.check_definitions_received("broker.local", DEFINITIONS_GRACE_PERIOD).await }
+        });
+
+        add_test_array(&harness).await;
+        check.await.unwrap();
+
+        assert_eq!(harness.subscriber.get_definition_counts(), (1, 1));
+        assert!(!has_warning(harness.published()));
+    }
+
+    #[tokio::test]
+    async fn test_max_payload_size() {
+        let harness = SubscriberHarness::new();
+        let value_json = r#"{ "value": "50" }"#;
+
+        assert_eq!(harness.subscriber.settings.borrow().max_payload_size, DEFAULT_MAX_PAYLOAD_SIZE);
+        harness.subscriber.settings.send_modify(|settings| settings.max_payload_size = value_json.len());
+        harness.publish("DMX/Value/level", value_json).await.unwrap();
+
+        harness.subscriber.settings.send_modify(|settings| settings.max_payload_size = value_json.len() - 1);
+        let e = harness.publish("DMX/Value/level", value_json).await.unwrap_err();
+        assert!(matches!(e.current_context(), MqttError::PayloadTooLarge(topic, size, _) if topic == "DMX/Value/level" && *size == value_json.len()));
+
+        // Rejected before parsing, so invalid JSON reports the size and not a parse error
+        let e = harness.publish("DMX/Array/test", &"x".repeat(value_json.len())).await.unwrap_err();
+        assert!(matches!(e.current_context(), MqttError::PayloadTooLarge(_, _, _)));
+        assert!(e.current_context().to_string().contains("DMX/Array/test"));
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics() {
+        let harness = SubscriberHarness::new();
+        add_test_array(&harness).await;
+
+        harness.publish("DMX/Value/level", r#"{ "value": "50" }"#).await.unwrap();
+        harness.publish("DMX/Effect/blink", r#"{ "type": "delay", "ticks": 100 }"#).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test", "effect_id": "blink", "values": { "secret": "1234" } }"#).await.unwrap();
+        harness.published();
+
+        let harness = &harness;
+        let get_diagnostics = |payload: &'static str| async move {
+            harness.publish("DMX/Command/Diagnostics", payload).await.unwrap();
+
+            match &harness.published()[..] {
+                [ToMqttPublisherMessage::Diagnostics(diagnostics)] => serde_json::to_value(diagnostics).unwrap(),
+                messages => panic!("Expected Diagnostics message, got {:?}", messages),
+            }
+        };
+
+        let diagnostics = get_diagnostics("").await;
+
+        assert_eq!(diagnostics["version"], get_version());
+        assert!(diagnostics["uptime_seconds"].is_u64());
+
+        let array = &diagnostics["arrays"]["arrays"]["test"];
+        assert_eq!(array["universe_id"], "0");
+        assert_eq!(array["epoch"], 1);
+        assert_eq!(array["values"]["secret"], serde_json::json!({ "length": 4 }));
+        assert_eq!(diagnostics["arrays"]["global_values"]["level"], serde_json::json!({ "length": 2 }));
+        assert_eq!(diagnostics["arrays"]["effects"], serde_json::json!(["blink"]));
+
+        let universe = &diagnostics["artnet"]["universes"]["0"];
+        assert_eq!(universe["description"], "0 (Test universe)");
+        assert_eq!(universe["channels"], 16);
+        assert!(universe["modified"].is_boolean() && universe["non_modified_ticks"].is_u64() && universe["sequence"].is_u64());
+
+        let effect = &diagnostics["artnet"]["active_effects"]["test"];
+        assert!(effect["elapsed_ticks"].is_u64());
+        assert_eq!(effect["paused"], false);
+
+        let queues = &diagnostics["queues"];
+        assert_eq!(queues.as_object().unwrap().keys().collect::<Vec<_>>(), vec!["array", "artnet"]);
+        assert_eq!(queues["artnet"]["capacity"], 10);
+        assert!(queues["artnet"]["sent"].as_u64().unwrap() >= 1);
+        assert_eq!(queues["artnet"]["slow_sends"], 0);
+
+        // Values are included only if requested
+        let diagnostics = get_diagnostics(r#"{ "include_values": true }"#).await;
+        assert_eq!(diagnostics["arrays"]["arrays"]["test"]["values"]["secret"], "1234");
+        assert_eq!(diagnostics["arrays"]["global_values"]["level"], "50");
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "description": "Test array", "lights": { "all": "rgb:1" } }"#;
+        let effect_json = r#"{ "type": "delay", "ticks": 100 }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/test", array_json).await.unwrap();
+        harness.publish("DMX/Effect/blink", effect_json).await.unwrap();
+        harness.published();
+
+        let universe_hash = get_definition_hash(&serde_json::from_str::<defs::UniverseDefinition>(universe_json).unwrap());
+        let array_hash = get_definition_hash(&serde_json::from_str::<defs::DmxArray>(array_json).unwrap());
+        let effect_hash = get_definition_hash(&serde_json::from_str::<defs::EffectDefinition>(effect_json).unwrap());
+        let other_hash = "0".repeat(64);
+
+        let harness = &harness;
+        let verify = |kind: &'static str, id: &'static str, sha256: String| async move {
+            let payload = serde_json::json!({ "kind": kind, "id": id, "sha256": sha256 }).to_string();
+            harness.publish("DMX/Command/Verify", &payload).await.unwrap();
+
+            match harness.published().pop() {
+                Some(ToMqttPublisherMessage::Verify(verify_result)) => (verify_result.result, verify_result.sha256),
+                message => panic!("Expected Verify message, got {:?}", message),
+            }
+        };
+
+        for (kind, id, hash) in [("universe", "0", &universe_hash), ("array", "test", &array_hash), ("effect", "blink", &effect_hash)] {
+            assert_eq!(verify(kind, id, hash.clone()).await, (defs::VerifyStatus::Match, Some(hash.clone())));
+            assert_eq!(verify(kind, id, hash.to_uppercase()).await.0, defs::VerifyStatus::Match);
+            assert_eq!(verify(kind, id, other_hash.clone()).await, (defs::VerifyStatus::Mismatch, Some(hash.clone())));
+            assert_eq!(verify(kind, "unknown", hash.clone()).await, (defs::VerifyStatus::NotLoaded, None));
+        }
+
+        // A failed redefinition leaves the loaded definition unchanged
+        let new_array_json = r#"{ "universe_id": "0", "description": "Test array", "lights": { "all": "@missing" } }"#;
+        assert!(harness.publish("DMX/Array/test", new_array_json).await.is_err());
+        let new_array_hash = get_definition_hash(&serde_json::from_str::<defs::DmxArray>(new_array_json).unwrap());
+        assert_eq!(verify("array", "test", new_array_hash).await, (defs::VerifyStatus::Mismatch, Some(array_hash)));
+
+        assert!(harness.publish("DMX/Command/Verify", r#"{ "kind": "schedule", "id": "x", "sha256": "" }"#).await.is_err());
+
+        // Verify results are published by this service and received back by its subscription
+        harness.publish("DMX/Verify", r#"{ "kind": "array", "id": "test", "result": "match" }"#).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "description": "Test array", "lights": { "all": "rgb:1" }, "on": "glow" }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/test", array_json).await.unwrap();
+        harness.published();
+
+        let harness = &harness;
+        let validate = |kind: &'static str, id: &'static str, definition: &'static str| async move {
+            let definition = serde_json::from_str::<serde_json::Value>(definition).unwrap();
+            let payload = serde_json::json!({ "kind": kind, "id": id, "definition": definition }).to_string();
+            harness.publish("DMX/Command/Validate", &payload).await.unwrap();
+
+            match harness.published().as_slice() {
+                [ToMqttPublisherMessage::Validate(result)] => (result.valid, result.errors.clone(), result.warnings.clone()),
+                messages => panic!("Expected Validate message, got {:?}", messages),
+            }
+        };
+        let is_loaded = |kind: &'static str, id: &'static str| async move {
+            let payload = serde_json::json!({ "kind": kind, "id": id, "sha256": "" }).to_string();
+            harness.publish("DMX/Command/Verify", &payload).await.unwrap();
+            !matches!(harness.published().pop(), Some(ToMqttPublisherMessage::Verify(defs::VerifyResult { result: defs::VerifyStatus::NotLoaded, .. })))
+        };
+
+        // Universes
+        let (valid, errors, _) = validate("universe", "1", r#"{ "description": "Other", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 1, "channels": 16 }"#).await;
+        assert!(valid && errors.is_empty());
+        let (valid, errors, _) = validate("universe", "1", r#"{ "description": "Other", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 1, "channels": 0 }"#).await;
+        assert!(!valid && errors[0].contains("no channels"), "{errors:?}");
+        let (valid, errors, _) = validate("universe", "2", r#"{ "description": "Other", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16 }"#).await;
+        assert!(!valid && errors[0].contains("same controller and port address"), "{errors:?}");
+        let (valid, errors, _) = validate("universe", "1", r#"{ "description": "Other", "channels": 16 }"#).await;
+        assert!(!valid && errors[0].contains("controller"), "{errors:?}");
+        assert!(!is_loaded("universe", "1").await && !is_loaded("universe", "2").await);
+
+        // Arrays, a non strict array with problems is valid (its problems are warnings)
+        assert_eq!(
+            validate("array", "lounge", r#"{ "universe_id": "0", "lights": { "all": "rgb:4" }, "on": "warm" }"#).await,
+            (true, vec![], vec!["Array lounge refers to effect warm which is not defined".to_string()])
+        );
+        let (valid, errors, _) = validate("array", "lounge", r#"{ "universe_id": "0", "lights": { "all": "@missing" } }"#).await;
+        assert!(!valid && errors.len() == 1);
+        let (valid, errors, warnings) = validate("array", "lounge", r#"{ "universe_id": "0", "lights": { "all": "@missing" }, "strict": false }"#).await;
+        assert!(valid && errors.is_empty() && warnings.len() == 1);
+        let (valid, errors, _) = validate("array", "a/b", r#"{ "universe_id": "0", "lights": { "all": "rgb:4" } }"#).await;
+        assert!(!valid && errors.len() == 1);
+        assert!(!is_loaded("array", "lounge").await);
+
+        // Effects are compiled for the arrays using them
+        assert_eq!(validate("effect", "glow", r#"{ "type": "fade", "lights": "@all", "ticks": 10, "target": "rgb(1,2,3)" }"#).await, (true, vec![], vec![]));
+        let (valid, errors, _) = validate("effect", "glow", r#"{ "type": "fade", "lights": "@all", "ticks": 10, "target": "bright" }"#).await;
+        assert!(!valid && errors[0].starts_with("Array test: "), "{errors:?}");
+        let (valid, _, warnings) = validate("effect", "glow", r#"{ "type": "fade", "lights": "@all", "ticks": 10, "target": "`level`" }"#).await;
+        assert!(valid && warnings.len() == 1);
+        let (valid, errors, _) = validate("effect", "glow", r#"{ "type": "blink" }"#).await;
+        assert!(!valid && errors.len() == 1);
+        assert!(!is_loaded("effect", "glow").await);
+
+        // Nothing was changed, the array still refers to an undefined effect
+        harness.publish("DMX/Command/CheckConfig", "").await.unwrap();
+        assert_eq!(harness.published().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_effect() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" }, "on": "warmwhite", "effects": { "dim": { "type": "delay", "ticks": 5 } } }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/cove", array_json).await.unwrap();
+        match harness.published().as_slice() {
+            [ToMqttPublisherMessage::Warning(warning)] => assert!(warning.contains("on effect warmwhite is neither an array effect nor a global effect"), "{warning}"),
+            messages => panic!("Expected fallback warning, got {:?}", messages),
+        }
+
+        let resolve = |payload: &'static str| async {
+            harness.publish("DMX/Command/ResolveEffect", payload).await.unwrap();
+
+            match harness.published().as_slice() {
+                [ToMqttPublisherMessage::ResolveEffect(result)] => (result.resolution.effect_id.to_string(), result.resolution.source, result.resolution.summary.clone()),
+                messages => panic!("Expected ResolveEffect message, got {:?}", messages),
+            }
+        };
+
+        assert_eq!(resolve(r#"{ "array_id": "cove", "usage": "On" }"#).await.1, defs::EffectSource::Default);
+        assert_eq!(resolve(r#"{ "array_id": "cove", "usage": "Dim" }"#).await, ("dim".to_string(), defs::EffectSource::Array, "delay 5 ticks".to_string()));
+
+        harness.publish("DMX/Effect/warmwhite", r#"{ "type": "hold", "lights": "@all", "ticks": 10, "target": "s(40)" }"#).await.unwrap();
+        assert_eq!(
+            resolve(r#"{ "array_id": "cove", "usage": "On" }"#).await,
+            ("warmwhite".to_string(), defs::EffectSource::Global, "hold @all at s(40) for 10 ticks".to_string())
+        );
+
+        let e = harness.publish("DMX/Command/ResolveEffect", r#"{ "array_id": "cove", "usage": "On", "effect_id": "missing" }"#).await.unwrap_err();
+        assert!(matches!(e.current_context(), MqttError::Context(_)));
+        assert!(harness.publish("DMX/Command/ResolveEffect", r#"{ "array_id": "other", "usage": "On" }"#).await.is_err());
+
+        // Resolutions are published by this service and received back by its subscription
+        harness.published();
+        harness.publish("DMX/ResolveEffect", r#"{ "array_id": "cove", "usage": "On", "effect_id": "warmwhite", "source": "Global" }"#).await.unwrap();
+        assert!(harness.published().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_correlation_id() {
+        let harness = SubscriberHarness::new();
+        add_test_array(&harness).await;
+
+        let acks = |harness: &SubscriberHarness| harness.published().into_iter().filter_map(|message| match message {
+            ToMqttPublisherMessage::CommandAck(ack) => Some((ack.command.to_string(), ack.correlation_id.to_string())),
+            _ => None,
+        }).collect::<Vec<_>>();
+        let failed_correlation_id = |e: Report<MqttError>| match get_error_message(&e, ErrorCategory::Command) {
+            ToMqttPublisherMessage::Error(_, correlation_id, _) => correlation_id.map(|id| id.to_string()),
+            message => panic!("Expected Error message, got {:?}", message),
+        };
+
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test", "correlation_id": "on-1" }"#).await.unwrap();
+        assert_eq!(acks(&harness), vec![("On".to_string(), "on-1".to_string())]);
+
+        harness.publish("DMX/Command/Set", r#"{ "universe_id": "0", "channels": "s:1", "target": "s(10)", "correlation_id": "set-1" }"#).await.unwrap();
+        assert_eq!(acks(&harness), vec![("Set".to_string(), "set-1".to_string())]);
+
+        harness
+            .publish("DMX/Command/Set", r#"[{ "universe_id": "0", "channels": "s:1", "target": "s(10)" }, { "universe_id": "0", "channels": "s:2", "target": "s(20)", "correlation_id": "set-2" }]"#)
+            .await
+            .unwrap();
+        assert_eq!(acks(&harness), vec![("Set".to_string(), "set-2".to_string())]);
+
+        // Failed commands publish the correlation id with the error
+        let e = harness.publish("DMX/Command/On", r#"{ "array_id": "missing", "correlation_id": "on-2" }"#).await.unwrap_err();
+        assert_eq!(failed_correlation_id(e), Some("on-2".to_string()));
+
+        let e = harness.publish("DMX/Command/Set", r#"{ "universe_id": "9", "channels": "s:1", "target": "s(10)", "correlation_id": "set-3" }"#).await.unwrap_err();
+        assert_eq!(failed_correlation_id(e), Some("set-3".to_string()));
+        assert!(acks(&harness).is_empty());
+
+        // Without correlation id nothing is acknowledged
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test" }"#).await.unwrap();
+        assert!(acks(&harness).is_empty());
+
+        let e = harness.publish("DMX/Command/On", r#"{ "array_id": "missing" }"#).await.unwrap_err();
+        assert_eq!(failed_correlation_id(e), None);
+    }
+
+    #[tokio::test]
+    async fn test_set_command() {
+        let harness = SubscriberHarness::new();
+        add_test_array(&harness).await;
+
+        harness
+            .publish("DMX/Command/Set", r#"{ "universe_id": "0", "channels": "s:1", "target": "s(10)" }"#)
+            .await
+            .unwrap();
+        harness
+            .publish(
+                "DMX/Command/Set",
+                r#"[{ "universe_id": "0", "channels": "s:1", "target": "s(20)" }, { "universe_id": "0", "channels": "s:2", "target": "s(30)" }]"#,
+            )
+            .await
+            .unwrap();
+
+        let e = harness
+            .publish(
+                "DMX/Command/Set",
+                r#"[{ "universe_id": "0", "channels": "s:1", "target": "s(20)" }, { "universe_id": "none", "channels": "s:2", "target": "s(30)" }]"#,
+            )
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("2 entries batch"));
+        assert!(e.frames().any(|f| matches!(f.downcast_ref::<ArtnetError>(), Some(ArtnetError::InvalidSetChannelsBatchEntry(1, _)))));
+    }
+
+    async fn is_effect_running(harness: &SubscriberHarness, effect_id: &str) -> bool {
+        let (tx, rx) = oneshot::channel();
+
+        harness
+            .subscriber
+            .to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::GetEffectStatus(Arc::from(effect_id), false, tx))
+            .await
+            .unwrap();
+
+        rx.await.unwrap().unwrap().running
+    }
+
+    #[tokio::test]
+    async fn test_linked_effects() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "rgb:1,s:4", "candles": "s:4" }, "linked_effects": ["candles"] }"#;
+        let array_on = r#"{ "array_id": "test" }"#;
+        let flicker = |effect_id: &str| format!(
+            r#"{{ "effect_id": "{effect_id}", "lights": "$0,s:4", "effect": {{ "type": "fade", "lights": "@all", "ticks": 100000, "target": "s(128)" }} }}"#
+        );
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/test", array_json).await.unwrap();
+
+        harness.publish("DMX/Command/On", array_on).await.unwrap();
+        harness.publish("DMX/Command/On", &flicker("candles")).await.unwrap();
+        harness.publish("DMX/Command/On", &flicker("other")).await.unwrap();
+        assert!(is_effect_running(&harness, "test").await);
+        assert!(is_effect_running(&harness, "candles").await);
+
+        // Off stops the linked effects, other effects keep running
+        harness.publish("DMX/Command/Off", array_on).await.unwrap();
+        assert!(!is_effect_running(&harness, "candles").await);
+        assert!(is_effect_running(&harness, "other").await);
+
+        harness.publish("DMX/Command/On", array_on).await.unwrap();
+        harness.publish("DMX/Command/On", &flicker("candles")).await.unwrap();
+
+        // Stop stops both the array effect and the linked effects
+        harness.publish("DMX/Command/Stop", array_on).await.unwrap();
+        assert!(!is_effect_running(&harness, "test").await);
+        assert!(!is_effect_running(&harness, "candles").await);
+        assert!(is_effect_running(&harness, "other").await);
+
+        // Stopping an effect which is not an array
+        harness.publish("DMX/Command/Stop", r#"{ "array_id": "other" }"#).await.unwrap();
+        assert!(!is_effect_running(&harness, "other").await);
+    }
+
+    #[tokio::test]
+    async fn test_light_group_command() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "reading": "s:4", "all": "rgb:1,@reading" }, "on": "slow_on", "effects": { "slow_on": { "type": "fade", "lights": "@all", "ticks": 100000, "target": "s(255)" } } }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/lounge", array_json).await.unwrap();
+
+        // Group effect has its own ID so it does not replace the whole array effect
+        harness.publish("DMX/Command/On", r#"{ "array_id": "lounge" }"#).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "lounge", "lights": "@reading" }"#).await.unwrap();
+        assert!(is_effect_running(&harness, "lounge").await);
+        assert!(is_effect_running(&harness, "lounge@reading").await);
+
+        harness.publish("DMX/Command/Stop", r#"{ "array_id": "lounge", "scope": "all" }"#).await.unwrap();
+        assert!(!is_effect_running(&harness, "lounge@reading").await);
+
+        let e = harness.publish("DMX/Command/On", r#"{ "array_id": "lounge", "lights": "@kitchen" }"#).await.unwrap_err();
+        assert!(e.frames().any(|f| matches!(f.downcast_ref::<DmxArrayError>(), Some(DmxArrayError::ArrayLightGroupNotFound(_, _)))));
+    }
+
+    #[tokio::test]
+    async fn test_numeric_universe_id() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Second universe", "controller": "10.0.1.228", "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1, $3, s:2" } }"#;
+
+        // Port address is derived from the universe ID, which lights refer to with $n
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/lounge", array_json).await.unwrap();
+
+        let e = harness.publish("DMX/Command/On", r#"{ "array_id": "lounge" }"#).await.unwrap_err();
+        assert!(e.frames().any(|f| matches!(f.downcast_ref::<ArtnetError>(), Some(ArtnetError::EffectUniverseNotFound(effect_id, universe_id))
+            if effect_id == "lounge" && universe_id == "3")));
+
+        harness.publish("DMX/Universe/3", universe_json).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "lounge" }"#).await.unwrap();
+        assert!(is_effect_running(&harness, "lounge").await);
+
+        let e = harness.publish("DMX/Universe/kitchen", universe_json).await.unwrap_err();
+        assert!(e.frames().any(|f| matches!(f.downcast_ref::<ArtnetError>(), Some(ArtnetError::MissingPortAddress(_)))));
+    }
+
+    #[tokio::test]
+    async fn test_remove_universe_stops_effects() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "left": "s:1", "right": "$1, s:1", "all": "@left, @right" },
+            "effects": { "slow_on": { "type": "parallel", "nodes": [
+                { "type": "fade", "lights": "@left", "ticks": 100000, "target": "s(255)" },
+                { "type": "fade", "lights": "@right", "ticks": 100000, "target": "s(255)" }
+            ] } } }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Universe/1", universe_json).await.unwrap();
+        harness.publish("DMX/Universe/2", universe_json).await.unwrap();
+        harness.publish("DMX/Array/hall", array_json).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "hall", "effect_id": "slow_on" }"#).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "lights": "$2, s:1", "effect": { "type": "fade", "lights": "@all", "ticks": 100000, "target": "s(255)" }, "effect_id": "other" }"#).await.unwrap();
+        harness.published();
+
+        // The whole effect is stopped even though it also uses universe 0
+        harness.publish("DMX/Universe/1", "").await.unwrap();
+        assert!(!is_effect_running(&harness, "hall").await);
+        assert!(is_effect_running(&harness, "other").await);
+
+        match &harness.published()[..] {
+            [ToMqttPublisherMessage::Error(error, None, ErrorCategory::Runtime)] => assert_eq!(error, "Universe 1 was removed, stopped effects using it: hall"),
+            messages => panic!("Expected a single Error message, got {:?}", messages),
+        }
+
+        // Nothing to report if no effect used the universe
+        harness.publish("DMX/Universe/0", "").await.unwrap();
+        assert!(harness.published().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_startup_effect() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = |extra: &str| format!(
+            r#"{{ "universe_id": "0", "lights": {{ "all": "s:4" }}, "effects": {{ "glow": {{ "type": "fade", "lights": "@all", "ticks": 100000, "target": "s(40)" }} }}{extra} }}"#
+        );
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+
+        // No startup effect by default
+        harness.publish("DMX/Array/cove", &array_json("")).await.unwrap();
+        assert!(!is_effect_running(&harness, "cove").await);
+        harness.publish("DMX/Array/cove", "").await.unwrap();
+
+        harness.publish("DMX/Array/cove", &array_json(r#", "startup": "glow", "startup_on_redefine": false"#)).await.unwrap();
+        assert!(is_effect_running(&harness, "cove").await);
+
+        harness.publish("DMX/Command/Stop", r#"{ "array_id": "cove" }"#).await.unwrap();
+        harness.publish("DMX/Array/cove", &array_json(r#", "startup": "glow", "startup_on_redefine": false"#)).await.unwrap();
+        assert!(!is_effect_running(&harness, "cove").await);
+
+        harness.publish("DMX/Array/cove", &array_json(r#", "startup": "glow""#)).await.unwrap();
+        assert!(is_effect_running(&harness, "cove").await);
+
+        // Failing startup effect is reported but the array is still defined
+        harness.published();
+        harness.publish("DMX/Array/lounge", r#"{ "universe_id": "0", "lights": { "all": "s:4" }, "effects": { "bad": { "type": "fade", "lights": "@missing", "ticks": 1, "target": "s(40)" } }, "startup": "bad" }"#).await.unwrap();
+        let errors = harness.published().into_iter().filter_map(|m| match m {
+            ToMqttPublisherMessage::Error(error, _, _) => Some(error),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("Array lounge startup effect:"), "{}", errors[0]);
+
+        harness.publish("DMX/Command/On", r#"{ "array_id": "lounge", "effect_id": "bad" }"#).await.unwrap_err();
+        harness.publish("DMX/Array/lounge", &array_json("")).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "lounge", "effect_id": "glow" }"#).await.unwrap();
+        assert!(is_effect_running(&harness, "lounge").await);
+    }
+
+    #[tokio::test]
+    async fn test_deferred_startup_effect() {
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:4" }, "on": "warm_white", "startup": "warm_white" }"#;
+        let effect_json = r#"{ "type": "fade", "lights": "@all", "ticks": 100000, "target": "s(40)" }"#;
+
+        // Replaying the definitions in either order ends with the startup effect running and no errors (an array added
+        // before its on effect is warned about falling back to the default effect)
+        for (definitions, expected_warnings) in [
+            ([("DMX/Universe/0", universe_json), ("DMX/Effect/warm_white", effect_json), ("DMX/Array/cove", array_json)], 0),
+            ([("DMX/Universe/0", universe_json), ("DMX/Array/cove", array_json), ("DMX/Effect/warm_white", effect_json)], 1),
+        ] {
+            let harness = SubscriberHarness::new();
+
+            for (topic, payload) in definitions {
+                harness.publish(topic, payload).await.unwrap();
+            }
+
+            harness.publish("DMX/Command/CheckConfig", "").await.unwrap();
+            assert!(is_effect_running(&harness, "cove").await);
+            let published = harness.published();
+            assert!(published.iter().all(|m| !matches!(m, ToMqttPublisherMessage::Error(..))));
+            assert_eq!(published.iter().filter(|m| matches!(m, ToMqttPublisherMessage::Warning(_))).count(), expected_warnings);
+        }
+
+        // Still unresolved references are reported by CheckConfig, a command supersedes the deferred startup effect
+        let harness = SubscriberHarness::new();
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/cove", array_json).await.unwrap();
+        assert!(!is_effect_running(&harness, "cove").await);
+        harness.published();
+
+        harness.publish("DMX/Command/CheckConfig", "").await.unwrap();
+        match harness.published().as_slice() {
+            [ToMqttPublisherMessage::Warning(warning)] => assert_eq!(warning, "Array cove refers to effect warm_white which is not defined"),
+            messages => panic!("Expected warning, got {:?}", messages),
+        }
+
+        harness.publish("DMX/Command/Off", r#"{ "array_id": "cove" }"#).await.unwrap();
+        harness.publish("DMX/Effect/warm_white", effect_json).await.unwrap();
+        assert_eq!(harness.subscriber.get_array_state(Arc::from("cove")).await.unwrap().map(|state| state.usage), Some(EffectUsage::Off));
+
+        harness.publish("DMX/Command/CheckConfig", "").await.unwrap();
+        assert!(harness.published().iter().all(|m| !matches!(m, ToMqttPublisherMessage::Warning(_))));
+    }
+
+    async fn wait_for_effect_done(harness: &SubscriberHarness, effect_id: &str) {
+        for _ in 0..100 {
+            if !is_effect_running(harness, effect_id).await {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        panic!("Effect {effect_id} is still running");
+    }
+
+    #[tokio::test]
+    async fn test_pre_commands() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = |lights: &str, pre: &str| format!(r#"{{ "universe_id": "0", "lights": {{ "all": "{lights}" }}, "pre": {pre} }}"#);
+        let get_state = |array_id: &'static str| {
+            let subscriber = harness.subscriber.clone();
+            async move { subscriber.get_array_state(Arc::from(array_id)).await.unwrap().map(|state| (state.usage, state.dimming_amount)) }
+        };
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/lounge", &array_json("s:1", "[]")).await.unwrap();
+        harness.publish("DMX/Array/hall", &array_json("s:2", r#"[{ "array_id": "lounge", "usage": "On" }]"#)).await.unwrap();
+        harness.publish("DMX/Array/cinema", &array_json("s:3", r#"[
+            { "array_id": "hall", "usage": "On" },
+            { "array_id": "lounge", "usage": "Off" },
+            { "array_id": "hall", "usage": "Dim", "dimming_amount": 200 }
+        ]"#)).await.unwrap();
+
+        // Pre commands run in order, the pre commands of arrays turned On first (lounge is turned On by hall pre commands)
+        harness.publish("DMX/Command/On", r#"{ "array_id": "cinema" }"#).await.unwrap();
+        assert_eq!(get_state("lounge").await, Some((EffectUsage::Off, DIMMING_AMOUNT_MAX)));
+        assert_eq!(get_state("hall").await, Some((EffectUsage::Dim, 200)));
+        assert_eq!(get_state("cinema").await, Some((EffectUsage::On, DIMMING_AMOUNT_MAX)));
+
+        // Other commands and commands on light groups do not run the pre commands
+        harness.publish("DMX/Command/On", r#"{ "array_id": "lounge" }"#).await.unwrap();
+        harness.publish("DMX/Command/Dim", r#"{ "array_id": "cinema" }"#).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "cinema", "lights": "@all" }"#).await.unwrap();
+        assert_eq!(get_state("lounge").await, Some((EffectUsage::On, DIMMING_AMOUNT_MAX)));
+
+        // Arrays whose pre commands lead back to them are rejected
+        let e = harness.publish("DMX/Array/lounge", &array_json("s:1", r#"[{ "array_id": "cinema", "usage": "Off" }]"#)).await.unwrap_err();
+        assert!(format!("{:?}", e).contains("lounge -> cinema -> hall -> lounge"), "{:?}", e);
+        let e = harness.publish("DMX/Array/lounge", &array_json("s:1", r#"[{ "array_id": "lounge", "usage": "Off" }]"#)).await.unwrap_err();
+        assert!(format!("{:?}", e).contains("lounge -> lounge"), "{:?}", e);
+    }
+
+    #[tokio::test]
+    async fn test_pre_commands_wait() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let hall_json = r#"{ "universe_id": "0", "lights": { "all": "s:2" }, "effects": { "off": { "type": "fade", "lights": "@all", "ticks": 6, "target": "s(0)" } } }"#;
+        let cinema_json = r#"{ "universe_id": "0", "lights": { "all": "s:3" }, "pre": [{ "array_id": "hall", "usage": "Off", "wait": true }] }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/hall", hall_json).await.unwrap();
+        harness.publish("DMX/Array/cinema", cinema_json).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "hall" }"#).await.unwrap();
+        wait_for_effect_done(&harness, "hall").await;
+
+        // The cinema effect is started only once the hall Off effect completes
+        harness.publish("DMX/Command/On", r#"{ "array_id": "cinema" }"#).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(is_effect_running(&harness, "hall").await);
+        assert!(!is_effect_running(&harness, "cinema").await);
+
+        wait_for_effect_done(&harness, "hall").await;
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let state = harness.subscriber.get_array_state(Arc::from("cinema")).await.unwrap();
+        assert_eq!(state.map(|state| state.usage), Some(EffectUsage::On));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_node() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"
+        {
+            "universe_id": "0",
+            "lights": { "all": "s:4" },
+            "effects": {
+                "scene": { "type": "sequence", "nodes": [
+                    { "type": "fade", "lights": "@all", "ticks": 1, "target": "s(100)" },
+                    { "type": "wait_for", "value_name": "stage", "equals": "2" },
+                    { "type": "fade", "lights": "@all", "ticks": 1, "target": "s(200)" }
+                ] },
+                "next": { "type": "wait_for", "value_name": "stage" },
+                "timeout": { "type": "wait_for", "value_name": "stage", "timeout_ticks": 2 },
+                "fail": { "type": "wait_for", "value_name": "stage", "timeout_ticks": 2, "on_timeout": "fail" }
+            }
+        }"#;
+        let pause = || tokio::time::sleep(std::time::Duration::from_millis(300));
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/test", array_json).await.unwrap();
+
+        // Sequence is blocked until the value equals "2"
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test", "effect_id": "scene" }"#).await.unwrap();
+        pause().await;
+        assert!(is_effect_running(&harness, "test").await);
+
+        harness.publish("DMX/Value/stage", r#"{ "value": "1" }"#).await.unwrap();
+        pause().await;
+        assert!(is_effect_running(&harness, "test").await);
+
+        harness.publish("DMX/Value/stage", r#"{ "value": "2" }"#).await.unwrap();
+        wait_for_effect_done(&harness, "test").await;
+
+        // Without equals, any change of the value (here an array value set by On command) ends the wait
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test", "effect_id": "next" }"#).await.unwrap();
+        pause().await;
+        assert!(is_effect_running(&harness, "test").await);
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test", "effect_id": "scene", "values": { "stage": "2" } }"#).await.unwrap();
+        wait_for_effect_done(&harness, "test").await;
+
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test", "effect_id": "timeout" }"#).await.unwrap();
+        wait_for_effect_done(&harness, "test").await;
+
+        harness.published();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test", "effect_id": "fail" }"#).await.unwrap();
+        wait_for_effect_done(&harness, "test").await;
+        assert!(harness.published().iter().any(|m| matches!(m, ToMqttPublisherMessage::Error(e, _, _) if e.contains("did not match wait_for"))));
+    }
+
+    // Description and origin of the running effect
+    async fn get_verbose_status(harness: &SubscriberHarness, effect_id: &str) -> (String, Option<Arc<str>>) {
+        let (tx, rx) = oneshot::channel();
+
+        harness
+            .subscriber
+            .to_artnet_tx
+            .send(messages::ToArtnetManagerMessage::GetEffectStatus(Arc::from(effect_id), true, tx))
+            .await
+            .unwrap();
+
+        let status = rx.await.unwrap().unwrap();
+        (status.description.unwrap_or_default(), status.origin)
+    }
+
+    #[tokio::test]
+    async fn test_retrigger_value() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = |channel: u16, target: &str| format!(
+            r#"{{ "universe_id": "0", "lights": {{ "all": "s:{channel}" }}, "effects": {{ "on": {{ "type": "fade", "lights": "@all", "ticks": 100000, "target": "{target}" }} }} }}"#
+        );
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Value/cct", r#"{ "value": "s(100)", "retrigger": true }"#).await.unwrap();
+        harness.publish("DMX/Array/cove", &array_json(4, "`cct`")).await.unwrap();
+        harness.publish("DMX/Array/desk", &array_json(5, "s(50)")).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "cove" }"#).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "desk", "dimming_amount": 500 }"#).await.unwrap();
+        assert!(get_verbose_status(&harness, "cove").await.0.ends_with("target s(100)"));
+
+        // The running fade is started again with the new target, arrays whose effect did not read the value are not
+        harness.publish("DMX/Value/cct", r#"{ "value": "s(200)", "retrigger": true }"#).await.unwrap();
+        let (description, origin) = get_verbose_status(&harness, "cove").await;
+        assert!(description.ends_with("target s(200)"), "{description}");
+        assert_eq!(origin.as_deref(), Some("value cct"));
+        assert_eq!(get_verbose_status(&harness, "desk").await.1, None);
+
+        // Setting the same value, or a change without retrigger, does not restart the effect
+        harness.publish("DMX/Command/On", r#"{ "array_id": "cove", "origin": "test" }"#).await.unwrap();
+        harness.publish("DMX/Value/cct", r#"{ "value": "s(200)", "retrigger": true }"#).await.unwrap();
+        harness.publish("DMX/Value/cct", r#"{ "value": "s(150)" }"#).await.unwrap();
+        assert_eq!(get_verbose_status(&harness, "cove").await.1.as_deref(), Some("test"));
+
+        // The usage and dimming amount of the last command are used
+        harness.publish("DMX/Array/desk", &array_json(5, "`cct`")).await.unwrap();
+        harness.publish("DMX/Command/Dim", r#"{ "array_id": "desk", "effect_id": "on", "dimming_amount": 500 }"#).await.unwrap();
+        harness.publish("DMX/Value/cct", r#"{ "value": "s(40)", "retrigger": true }"#).await.unwrap();
+        let (description, origin) = get_verbose_status(&harness, "desk").await;
+        assert!(description.ends_with("target s(40)") && origin.as_deref() == Some("value cct"), "{description}");
+        let state = harness.subscriber.get_array_state(Arc::from("desk")).await.unwrap().unwrap();
+        assert_eq!((state.usage, state.dimming_amount), (EffectUsage::Dim, 500));
+        assert!(harness.published().iter().all(|m| !matches!(m, ToMqttPublisherMessage::Error(..))));
+    }
+
+    #[tokio::test]
+    async fn test_universe_group_commands() {
+        let harness = SubscriberHarness::new();
+        let universe_json = |universe: u8| format!(
+            r#"{{ "description": "Pixel bar", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": {universe}, "channels": 16, "disable_send": true }}"#
+        );
+
+        for universe in 4..=7 {
+            harness.publish(&format!("DMX/Universe/{universe}"), &universe_json(universe)).await.unwrap();
+        }
+
+        let e = harness.publish("DMX/UniverseGroup/bars", r#"{ "universes": ["4", "5", "8"] }"#).await.unwrap_err();
+        assert!(e.frames().any(|f| matches!(f.downcast_ref::<ArtnetError>(), Some(ArtnetError::UniverseGroupMemberNotFound(_, member)) if member == "8")));
+
+        harness.publish("DMX/UniverseGroup/bars", r#"{ "universes": ["4", "5", "6", "7"] }"#).await.unwrap();
+        harness.publish("DMX/Command/Blackout", r#"{ "universe_group": "bars" }"#).await.unwrap();
+        harness.publish("DMX/Command/Blackout", r#"{ "universe_group": "bars", "restore": true }"#).await.unwrap();
+
+        harness.published();
+        harness.publish("DMX/Command/UniverseStatus", r#"{ "universe_group": "bars" }"#).await.unwrap();
+        let universe_ids = harness.published().into_iter().filter_map(|m| match m {
+            ToMqttPublisherMessage::UniverseSendStatus(universe_id, _) => Some(universe_id.to_string()),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(universe_ids, vec!["4", "5", "6", "7"]);
+
+        // Membership changes take effect immediately
+        harness.publish("DMX/UniverseGroup/bars", r#"{ "universes": ["6"] }"#).await.unwrap();
+        harness.published();
+        harness.publish("DMX/Command/UniverseStatus", r#"{ "universe_group": "bars" }"#).await.unwrap();
+        assert_eq!(harness.published().len(), 1);
+
+        harness.publish("DMX/UniverseGroup/bars", "").await.unwrap();
+        let e = harness.publish("DMX/Command/Blackout", r#"{ "universe_group": "bars" }"#).await.unwrap_err();
+        assert!(e.frames().any(|f| matches!(f.downcast_ref::<ArtnetError>(), Some(ArtnetError::UniverseGroupNotFound(_)))));
+
+        let e = harness.publish("DMX/Command/Blackout", r#"{ "restore": true }"#).await.unwrap_err();
+        assert!(matches!(e.current_context(), MqttError::MissingUniverseIdOrGroup(_)));
+    }
+
+    async fn stop_lounge(harness: &SubscriberHarness, payload: &str) -> Vec<String> {
+        harness.published();
+        harness.publish("DMX/Command/Stop", payload).await.unwrap();
+
+        match &harness.published()[..] {
+            [ToMqttPublisherMessage::StoppedEffects(array_id, effect_ids)] => {
+                assert_eq!(array_id.as_ref(), "lounge");
+                effect_ids.iter().map(|id| id.to_string()).collect()
+            }
+            messages => panic!("Expected StoppedEffects message, got {:?}", messages),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_scope() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "rgb:1,s:4" }, "linked_effects": ["candles"] }"#;
+        let flicker = |effect_id: &str| format!(
+            r#"{{ "effect_id": "{effect_id}", "lights": "$0,s:4", "effect": {{ "type": "fade", "lights": "@all", "ticks": 100000, "target": "s(128)" }} }}"#
+        );
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/lounge", array_json).await.unwrap();
+        let stop = |payload| stop_lounge(&harness, payload);
+
+        harness.publish("DMX/Command/On", r#"{ "array_id": "lounge" }"#).await.unwrap();
+        harness.publish("DMX/Command/On", &flicker("lounge@spots")).await.unwrap();
+        harness.publish("DMX/Command/On", &flicker("candles")).await.unwrap();
+        harness.publish("DMX/Command/On", &flicker("loungeroom")).await.unwrap();
+
+        // Effects started by array commands are stopped by usage, inline effects have no usage
+        assert_eq!(stop(r#"{ "array_id": "lounge", "scope": "usage", "usage": "Dim" }"#).await, Vec::<String>::new());
+        assert_eq!(stop(r#"{ "array_id": "lounge", "scope": "usage", "usage": "On" }"#).await, vec!["lounge"]);
+        assert!(is_effect_running(&harness, "lounge@spots").await);
+
+        harness.publish("DMX/Command/On", r#"{ "array_id": "lounge" }"#).await.unwrap();
+        assert_eq!(stop(r#"{ "array_id": "lounge" }"#).await, vec!["lounge", "candles"]);
+        assert!(is_effect_running(&harness, "lounge@spots").await);
+
+        harness.publish("DMX/Command/On", r#"{ "array_id": "lounge" }"#).await.unwrap();
+        harness.publish("DMX/Command/On", &flicker("candles")).await.unwrap();
+        assert_eq!(stop(r#"{ "array_id": "lounge", "scope": "all" }"#).await, vec!["lounge", "lounge@spots", "candles"]);
+        assert!(is_effect_running(&harness, "loungeroom").await);     // Another array whose ID starts with the array ID
+
+        let e = harness.publish("DMX/Command/Stop", r#"{ "array_id": "lounge", "scope": "usage" }"#).await.unwrap_err();
+        assert!(e.frames().any(|f| matches!(f.downcast_ref::<ArtnetError>(), Some(ArtnetError::MissingStopUsage(_)))));
+    }
+
+    #[tokio::test]
+    async fn test_import_export_effects() {
+        let harness = SubscriberHarness::new();
+
+        let effects_json = r#"
+        {
+            "fade_in": {
+                "type": "fade",
+                "lights": "@all",
+                "ticks": 10,
+                "target": "s(255)"
+            },
+            "broken": {
+                "type": "blink",
+                "lights": "@all"
+            },
+            "wait": {
+                "type": "delay",
+                "ticks": "`wait_ticks=5`"
+            },
+            "also_broken": {
+                "type": "delay"
+            }
+        }"#;
+
+        // Import continues after failures and reports all of them in a single error
+        let e = harness
+            .publish("DMX/Command/ImportEffects", effects_json)
+            .await
+            .unwrap_err();
+
+        match e.current_context() {
+            MqttError::ImportEffectsFailed(2, 4, errors) => {
+                assert!(errors.starts_with("also_broken ("));
+                assert!(errors.contains(", broken ("));
+            }
+            _ => panic!("Expected ImportEffectsFailed error, got {:?}", e),
+        }
+
+        harness
+            .publish("DMX/Command/ExportEffects", "")
+            .await
+            .unwrap();
+
+        let published = harness.published();
+        assert_eq!(published.len(), 1);
+
+        match &published[0] {
+            ToMqttPublisherMessage::ExportedEffects(effects) => {
+                let effect_ids: Vec<&str> = effects.keys().map(|id| id.as_ref()).collect();
+                assert_eq!(effect_ids, ["fade_in", "wait"]);
+
+                let exported_json = serde_json::to_string(effects).unwrap();
+                assert_eq!(
+                    exported_json,
+                    r#"{"fade_in":{"type":"fade","lights":"@all","ticks":10,"target":"s(255)","no_dimming":false},"wait":{"type":"delay","ticks":"`wait_ticks=5`"}}"#
+                );
+            }
+            message => panic!("Expected ExportedEffects message, got {:?}", message),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_import_round_trip() {
+        let harness = SubscriberHarness::new();
+
+        let effects_json = r#"{"a":{"type":"sequence","nodes":[{"type":"delay","ticks":1},{"type":"parallel","nodes":[]}]}}"#;
+
+        harness
+            .publish("DMX/Command/ImportEffects", effects_json)
+            .await
+            .unwrap();
+        harness
+            .publish("DMX/Command/ExportEffects", "")
+            .await
+            .unwrap();
+
+        match &harness.published()[..] {
+            [ToMqttPublisherMessage::ExportedEffects(effects)] => {
+                assert_eq!(serde_json::to_string(effects).unwrap(), effects_json);
+            }
+            messages => panic!("Expected ExportedEffects message, got {:?}", messages),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_config() {
+        let harness = SubscriberHarness::new();
+        let mut settings_rx = harness.subscriber.settings.subscribe();
+
+        harness.publish("DMX/Config", r#"{ "tick_ms": 25 }"#).await.unwrap();
+        assert!(settings_rx.has_changed().unwrap());
+
+        let settings = settings_rx.borrow_and_update().clone();
+        assert_eq!(settings, ServiceSettings { tick_ms: 25, ..ServiceSettings::default() });
+
+        match harness.published().as_slice() {
+            [ToMqttPublisherMessage::EffectiveConfig(effective)] => assert_eq!(*effective, settings),
+            messages => panic!("Expected EffectiveConfig message, got {:?}", messages),
+        }
+
+        // Partial update leaves the other settings intact, unknown settings are reported
+        harness.publish("DMX/Config", r#"{ "reconnect_delay_seconds": 3, "tick": 10 }"#).await.unwrap();
+        assert_eq!(*settings_rx.borrow_and_update(), ServiceSettings { tick_ms: 25, reconnect_delay_seconds: 3, ..ServiceSettings::default() });
+
+        match harness.published().as_slice() {
+            [ToMqttPublisherMessage::Warning(warning), ToMqttPublisherMessage::EffectiveConfig(_)] => {
+                assert!(warning.contains("tick") && warning.contains("valid settings: ") && warning.contains("tick_ms"));
+            }
+            messages => panic!("Expected Warning and EffectiveConfig messages, got {:?}", messages),
+        }
+
+        // Invalid values do not change any setting
+        for config_json in [r#"{ "tick_ms": 5, "reconnect_delay_seconds": 1 }"#, r#"{ "max_payload_size": "big" }"#] {
+            let e = harness.publish("DMX/Config", config_json).await.unwrap_err();
+            assert!(format!("{:?}", e).contains("Invalid service configuration"));
+        }
+        assert!(!settings_rx.has_changed().unwrap());
+        assert_eq!(settings_rx.borrow().tick_ms, 25);
+
+        // Effective configuration published by the service is ignored
+        harness.publish("DMX/Config/Effective", r#"{ "tick_ms": 100 }"#).await.unwrap();
+        assert!(!settings_rx.has_changed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_retained_commands() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" }, "effects": { "on": { "type": "fade", "lights": "@all", "ticks": 100000, "target": "s(255)" } } }"#;
+        let is_warning = |message: &ToMqttPublisherMessage| matches!(message, ToMqttPublisherMessage::Warning(_));
+
+        // Retained definitions are accepted
+        harness.publish_retained("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish_retained("DMX/Array/garden", array_json).await.unwrap();
+        harness.publish_retained("DMX/Value/level", r#"{ "value": 10 }"#).await.unwrap();
+        assert!(!harness.published().iter().any(is_warning));
+
+        // A retained command is dropped, the warning names its topic
+        harness.publish_retained("DMX/Command/On", r#"{ "array_id": "garden" }"#).await.unwrap();
+        assert!(!is_effect_running(&harness, "garden").await);
+        match harness.published().as_slice() {
+            [ToMqttPublisherMessage::Warning(warning)] => assert!(warning.contains("DMX/Command/On") && warning.contains("retained"), "{warning}"),
+            messages => panic!("Expected a retained command warning, got {:?}", messages),
+        }
+
+        harness.publish_retained("DMX/Command/On", r#"{ "array_id": "garden", "allow_retained": true }"#).await.unwrap();
+        assert!(is_effect_running(&harness, "garden").await);
+        assert!(!harness.published().iter().any(is_warning));
+
+        // Unless retained commands are allowed for the service
+        harness.publish("DMX/Command/Off", r#"{ "array_id": "garden", "instant": true }"#).await.unwrap();
+        let subscriber = harness.subscriber.clone().with_retained_commands(true);
+        subscriber.handle_message("DMX/Command/On", &Bytes::from(r#"{ "array_id": "garden" }"#), true, Instant::now()).await.unwrap();
+        assert!(is_effect_running(&harness, "garden").await);
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" }, "effects": { "on": { "type": "fade", "lights": "@all", "ticks": 100000, "target": "s(255)" } } }"#;
+        let get_sample = |name: &str| {
+            let metrics = harness.subscriber.get_metrics();
+            metrics.lines().find_map(|line| line.strip_prefix(name).and_then(|value| value.strip_prefix(' ')).map(|value| value.to_string()))
+        };
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/garden", array_json).await.unwrap();
+        assert_eq!(get_sample("commands_total{type=\"On\"}"), None);
+
+        // Definitions are not commands, failed commands are counted in both counters
+        harness.publish("DMX/Command/On", r#"{ "array_id": "garden" }"#).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "garden" }"#).await.unwrap();
+        assert!(harness.publish("DMX/Command/Off", r#"{ "array_id": "missing" }"#).await.is_err());
+        assert!(is_effect_running(&harness, "garden").await);
+
+        assert_eq!(get_sample("commands_total{type=\"On\"}").as_deref(), Some("2"));
+        assert_eq!(get_sample("commands_total{type=\"Off\"}").as_deref(), Some("1"));
+        assert_eq!(get_sample("command_errors_total{type=\"Off\"}").as_deref(), Some("1"));
+        assert_eq!(get_sample("command_errors_total{type=\"On\"}"), None);
+
+        // Ticks are counted by the artnet manager, sending of the test universe is disabled so no packets are counted
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        assert_eq!(get_sample("active_effects").as_deref(), Some("1"));
+        assert!(get_sample("tick_duration_seconds_count").unwrap().parse::<u64>().unwrap() > 0);
+        assert!(!harness.subscriber.get_metrics().contains("packets_sent_total{"));
+    }
+
+    #[tokio::test]
+    async fn test_adjust_command() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" }, "effects": {
+            "on": { "type": "fade", "lights": "@all", "ticks": 100000, "target": "s(200)" },
+            "dim": { "type": "fade", "lights": "@all", "ticks": 100000, "target": "s(100)" }
+        } }"#;
+        let get_state = || async {
+            let state = harness.subscriber.get_array_state(Arc::from("lounge")).await.unwrap().unwrap();
+            (state.usage, state.dimming_amount)
+        };
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/lounge", array_json).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "lounge" }"#).await.unwrap();
+
+        // Each adjustment continues from the previous one and replaces its effect
+        for _ in 0..3 {
+            harness.publish("DMX/Command/Adjust", r#"{ "array_id": "lounge", "delta": -100, "origin": "dial" }"#).await.unwrap();
+        }
+        assert_eq!(get_state().await, (EffectUsage::Dim, 700));
+        let (description, origin) = get_verbose_status(&harness, "lounge").await;
+        assert!(description.ends_with("target s(100)"), "{description}");        // The dim effect
+        assert_eq!(origin.as_deref(), Some("dial"));
+
+        // Re-running the last usage, clamped to the maximum
+        harness.publish("DMX/Command/On", r#"{ "array_id": "lounge", "dimming_amount": 500 }"#).await.unwrap();
+        harness.publish("DMX/Command/Adjust", r#"{ "array_id": "lounge", "delta": 800, "reapply": "last" }"#).await.unwrap();
+        assert_eq!(get_state().await, (EffectUsage::On, 1000));
+        assert!(get_verbose_status(&harness, "lounge").await.0.ends_with("target s(200)"));
+
+        // An array that is Off is adjusted from 0 (and turned On by reapplying the last usage)
+        harness.publish("DMX/Command/Off", r#"{ "array_id": "lounge", "instant": true }"#).await.unwrap();
+        harness.publish("DMX/Command/Adjust", r#"{ "array_id": "lounge", "delta": -10 }"#).await.unwrap();
+        assert_eq!(get_state().await, (EffectUsage::Dim, 0));
+        harness.publish("DMX/Command/Off", r#"{ "array_id": "lounge", "instant": true }"#).await.unwrap();
+        harness.publish("DMX/Command/Adjust", r#"{ "array_id": "lounge", "delta": 250, "reapply": "last" }"#).await.unwrap();
+        assert_eq!(get_state().await, (EffectUsage::On, 250));
+        assert!(get_verbose_status(&harness, "lounge").await.0.ends_with("target s(200)"));
+
+        assert!(harness.publish("DMX/Command/Adjust", r#"{ "array_id": "missing", "delta": 100 }"#).await.is_err());
+        assert!(harness.publish("DMX/Command/Adjust", r#"{ "array_id": "lounge" }"#).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rename_command() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" }, "effects": { "on": { "type": "fade", "lights": "@all", "ticks": 100000, "target": "s(200)" } } }"#;
+        let get_state = || async {
+            let state = harness.subscriber.get_array_state(Arc::from("den")).await.unwrap().unwrap();
+            (state.usage, state.dimming_amount)
+        };
+        let warnings = || {
+            harness.published().into_iter().filter_map(|message| match message {
+                ToMqttPublisherMessage::Warning(warning) => Some(warning),
+                _ => None,
+            }).collect::<Vec<_>>()
+        };
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/lounge", array_json).await.unwrap();
+        harness.publish("DMX/Array/hall", array_json).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "lounge", "dimming_amount": 600 }"#).await.unwrap();
+        harness.published();
+
+        // State and running effect move to the new ID
+        harness.publish("DMX/Command/Rename", r#"{ "kind": "array", "from": "lounge", "to": "den", "alias_seconds": 3600 }"#).await.unwrap();
+        assert_eq!(get_state().await, (EffectUsage::On, 600));
+        assert!(harness.subscriber.get_array_state(Arc::from("lounge")).await.is_err());
+        assert!(is_effect_running(&harness, "den").await);
+        assert!(!is_effect_running(&harness, "lounge").await);
+        match warnings().as_slice() {
+            [warning] => assert!(warning.starts_with("Array lounge was renamed to den, commands using lounge are redirected") && warning.contains("DMX/Array/den"), "{warning}"),
+            warnings => panic!("Unexpected warnings: {warnings:?}"),
+        }
+
+        // The old ID is redirected with a deprecation warning
+        harness.publish("DMX/Command/Dim", r#"{ "array_id": "lounge", "dimming_amount": 300 }"#).await.unwrap();
+        assert_eq!(get_state().await, (EffectUsage::Dim, 300));
+        assert!(is_effect_running(&harness, "den").await);
+        assert!(!is_effect_running(&harness, "lounge").await);
+        match warnings().as_slice() {
+            [warning] => assert!(warning.starts_with("Dim command uses array lounge which was renamed to den"), "{warning}"),
+            warnings => panic!("Unexpected warnings: {warnings:?}"),
+        }
+
+        // The new ID must not be defined
+        assert!(harness.publish("DMX/Command/Rename", r#"{ "kind": "array", "from": "den", "to": "hall" }"#).await.is_err());
+        assert!(harness.publish("DMX/Command/Rename", r#"{ "kind": "array", "from": "missing", "to": "attic" }"#).await.is_err());
+        assert!(harness.publish("DMX/Command/Rename", r#"{ "kind": "universe", "from": "0", "to": "0" }"#).await.is_err());
+        assert_eq!(get_state().await, (EffectUsage::Dim, 300));
+
+        // Once the alias expires the old ID is unknown
+        for (_, expires) in harness.subscriber.rename_aliases.lock().unwrap().values_mut() {
+            *expires = Instant::now();
+        }
+        assert!(harness.publish("DMX/Command/Dim", r#"{ "array_id": "lounge", "dimming_amount": 100 }"#).await.is_err());
+        assert!(harness.subscriber.rename_aliases.lock().unwrap().is_empty());
+
+        // Universe commands are redirected as well
+        harness.publish("DMX/Command/Rename", r#"{ "kind": "universe", "from": "0", "to": "main", "alias_seconds": 60 }"#).await.unwrap();
+        harness.published();
+        harness.publish("DMX/Command/EnableSend", r#"{ "universe_id": "0", "enable": true }"#).await.unwrap();
+        assert!(warnings().iter().any(|warning| warning.starts_with("EnableSend command uses universe 0 which was renamed to main")));
+        harness.publish("DMX/Command/Copy", r#"{ "from_universe": "0", "to_universe": "main", "channels": "s:1", "to_offset": 1 }"#).await.unwrap();
+        assert!(warnings().iter().any(|warning| warning.starts_with("Copy command uses universe 0 which was renamed to main")));
+
+        // Defining the old ID again ends the alias
+        harness.publish("DMX/Universe/0", &universe_json.replace(r#""universe": 0"#, r#""universe": 1"#)).await.unwrap();
+        assert!(harness.subscriber.rename_aliases.lock().unwrap().is_empty());
+
+        // Once the alias expires, commands using the old ID fail, while effects of arrays defined with it still set the renamed universe
+        harness.publish("DMX/Command/Rename", r#"{ "kind": "universe", "from": "0", "to": "porch", "alias_seconds": 60 }"#).await.unwrap();
+        for (_, expires) in harness.subscriber.rename_aliases.lock().unwrap().values_mut() {
+            *expires = Instant::now();
+        }
+        assert!(harness.publish("DMX/Command/Copy", r#"{ "from_universe": "porch", "to_universe": "0", "channels": "s:1" }"#).await.is_err());
+        assert!(harness.publish("DMX/Command/Set", r#"{ "universe_id": "0", "channels": "s:1", "target": "s(10)" }"#).await.is_err());
+        assert!(harness.publish("DMX/Command/SetFrame", r#"{ "universe_id": "0", "data_b64": "AQ==" }"#).await.is_err());
+        harness.publish("DMX/Command/On", r#"{ "array_id": "hall" }"#).await.unwrap();
+        assert!(is_effect_running(&harness, "hall").await);
+    }
+
+    #[tokio::test]
+    async fn test_commands_after_definitions() {
+        let harness = SubscriberHarness::new();
+        let array_json = r#"{ "universe_id": "main", "lights": { "all": "s:1" } }"#;
+
+        // Each command is handled after the definition received just before it
+        add_test_array(&harness).await;
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test" }"#).await.unwrap();
+        assert!(is_effect_running(&harness, "test").await);
+
+        harness.publish("DMX/Command/Rename", r#"{ "kind": "universe", "from": "0", "to": "main" }"#).await.unwrap();
+        harness.publish("DMX/Command/Set", r#"{ "universe_id": "main", "channels": "s:1", "target": "s(10)" }"#).await.unwrap();
+        assert!(harness.publish("DMX/Command/Set", r#"{ "universe_id": "0", "channels": "s:1", "target": "s(10)" }"#).await.is_err());
+
+        harness.publish("DMX/Array/porch", array_json).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "porch" }"#).await.unwrap();
+        assert!(is_effect_running(&harness, "porch").await);
+
+        harness.publish("DMX/Array/porch", "").await.unwrap();
+        assert!(harness.publish("DMX/Command/On", r#"{ "array_id": "porch" }"#).await.is_err());
+
+        harness.publish("DMX/Command/Reset", r#"{ "confirm": "RESET" }"#).await.unwrap();
+        assert!(harness.publish("DMX/Command/On", r#"{ "array_id": "test" }"#).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_schema_command() {
+        let harness = SubscriberHarness::new();
+
+        // Every listed command is handled (it may fail on its parameters, but it is not an unknown command)
+        for command in schema::COMMANDS {
+            if let Err(e) = harness.publish(&format!("DMX/Command/{command}"), "{}").await {
+                assert!(!matches!(e.current_context(), MqttError::InvalidCommand(_)), "{command}: {e:?}");
+            }
+        }
+        let e = harness.publish("DMX/Command/NoSuchCommand", "{}").await.unwrap_err();
+        assert!(matches!(e.current_context(), MqttError::InvalidCommand(_)));
+
+        harness.published();
+        harness.publish("DMX/Command/Schema", "").await.unwrap();
+        match harness.published().as_slice() {
+            [ToMqttPublisherMessage::Schema(schema)] => {
+                assert_eq!(schema.schema_version, schema::SCHEMA_VERSION);
+                assert_eq!(schema.commands, schema::COMMANDS);
+            }
+            messages => panic!("Unexpected messages: {messages:?}"),
+        }
+
+        // The retained schema published by the service is not a definition
+        harness.publish("DMX/Schema", r#"{ "schema_version": 1 }"#).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reset_command() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" }, "effects": { "on": { "type": "fade", "lights": "@all", "ticks": 100000, "target": "s(255)" } } }"#;
+        let define = || async {
+            harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+            harness.publish("DMX/Array/test", array_json).await.unwrap();
+            harness.publish("DMX/Value/level", r#"{ "value": "s(10)" }"#).await.unwrap();
+            harness.publish("DMX/Command/On", r#"{ "array_id": "test" }"#).await.unwrap();
+            assert!(is_effect_running(&harness, "test").await);
+        };
+        let get_diagnostics = || async {
+            let (artnet_tx, artnet_rx) = oneshot::channel();
+            let (array_tx, array_rx) = oneshot::channel();
+
+            harness.subscriber.to_artnet_tx.send(messages::ToArtnetManagerMessage::GetDiagnostics(artnet_tx)).await.unwrap();
+            harness.subscriber.to_array_tx.send(messages::ToArrayManagerMessage::GetDiagnostics(false, array_tx)).await.unwrap();
+            (artnet_rx.await.unwrap(), array_rx.await.unwrap())
+        };
+
+        define().await;
+        harness.publish("DMX/Schedule/porch", r#"{ "daily_at": "17:30", "command": "On", "parameters": { "array_id": "test" } }"#).await.unwrap();
+        harness.published();
+
+        // Without confirmation, or delivered as a retained message, nothing is reset
+        let e = harness.publish("DMX/Command/Reset", "{}").await.unwrap_err();
+        assert!(matches!(e.current_context(), MqttError::ResetNotConfirmed));
+        harness.publish_retained("DMX/Command/Reset", r#"{ "confirm": "RESET", "allow_retained": true }"#).await.unwrap();
+        assert!(is_effect_running(&harness, "test").await);
+        harness.published();
+
+        harness.publish("DMX/Command/Reset", r#"{ "confirm": "RESET" }"#).await.unwrap();
+        match harness.published().as_slice() {
+            [ToMqttPublisherMessage::Warning(notice), ToMqttPublisherMessage::Resubscribe] => {
+                assert!(notice.contains("(running effects stopped: 1, arrays removed: 1)"), "{notice}")
+            }
+            messages => panic!("Unexpected messages: {messages:?}"),
+        }
+
+        let (artnet_diagnostics, array_diagnostics) = get_diagnostics().await;
+        assert!(artnet_diagnostics.universes.is_empty() && artnet_diagnostics.active_effects.is_empty());
+        assert!(array_diagnostics.arrays.is_empty() && array_diagnostics.global_values.is_empty());
+
+        harness.publish("DMX/Command/DumpSchedules", "").await.unwrap();
+        assert!(matches!(&harness.published()[..], [ToMqttPublisherMessage::Schedules(schedules)] if schedules.is_empty()));
+
+        // Resetting again does nothing more, commands sent before the definitions are received again fail
+        harness.publish("DMX/Command/Reset", r#"{ "confirm": "RESET" }"#).await.unwrap();
+        assert!(harness.publish("DMX/Command/On", r#"{ "array_id": "test" }"#).await.is_err());
+        assert!(harness.publish("DMX/Command/Set", r#"{ "universe_id": "0", "channels": "s:1", "target": "s(10)" }"#).await.is_err());
+
+        // The retained definitions received again work as before
+        define().await;
+        let (artnet_diagnostics, array_diagnostics) = get_diagnostics().await;
+        assert_eq!(artnet_diagnostics.universes.len(), 1);
+        assert_eq!(array_diagnostics.global_values.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_universe_frame_length_warning() {
+        let harness = SubscriberHarness::new();
+        let universe_json = |universe: u8, channels: u16, pad_to: &str| format!(
+            r#"{{ "description": "Node port", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": {universe}, "channels": {channels}, {pad_to} "disable_send": true }}"#
+        );
+
+        harness.publish("DMX/Universe/main", &universe_json(0, 512, "")).await.unwrap();
+        harness.publish("DMX/Universe/porch", &universe_json(1, 64, r#""pad_to": 512,"#)).await.unwrap();
+        assert!(harness.published().is_empty());
+
+        harness.publish("DMX/Universe/garden", &universe_json(2, 64, "")).await.unwrap();
+        match harness.published().as_slice() {
+            [ToMqttPublisherMessage::Warning(warning)] => assert!(warning.starts_with("Universe garden sends 64 channel frames"), "{warning}"),
+            messages => panic!("Unexpected messages: {messages:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_instant_off() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let array_json = r#"{ "universe_id": "0", "lights": { "all": "s:1" }, "effects": { "off": { "type": "fade", "lights": "@all", "ticks": "`missing_ticks`", "target": "s(0)" } } }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/Array/test", array_json).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test" }"#).await.unwrap();
+        wait_for_effect_done(&harness, "test").await;
+
+        assert!(harness.publish("DMX/Command/Off", r#"{ "array_id": "test" }"#).await.is_err());
+        let e = harness.publish("DMX/Command/On", r#"{ "array_id": "test", "instant": true }"#).await.unwrap_err();
+        assert!(format!("{:?}", e).contains("supported only by Off"));
+
+        harness.publish("DMX/Command/Off", r#"{ "array_id": "test", "instant": true }"#).await.unwrap();
+        wait_for_effect_done(&harness, "test").await;
+
+        let state = harness.subscriber.get_array_state(Arc::from("test")).await.unwrap();
+        assert_eq!(state.map(|state| state.usage), Some(EffectUsage::Off));
+    }
+
+    #[tokio::test]
+    async fn test_latency() {
+        let harness = SubscriberHarness::new();
+        add_test_array(&harness).await;
+
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test" }"#).await.unwrap();
+        harness.publish("DMX/Command/Off", r#"{ "array_id": "test" }"#).await.unwrap();
+        harness.publish("DMX/Command/On", r#"{ "array_id": "test" }"#).await.unwrap();
+        assert!(harness.publish("DMX/Command/Off", r#"{ "array_id": "missing" }"#).await.is_err());
+        harness.published();
+
+        harness.publish("DMX/Command/Latency", r#"{ "reset": true }"#).await.unwrap();
+        match harness.published().as_slice() {
+            [ToMqttPublisherMessage::Latency(entries)] => {
+                let samples = entries.iter().map(|entry| (entry.command.as_ref(), entry.samples)).collect::<Vec<_>>();
+                assert_eq!(samples, vec![("Off", 1), ("On", 2)]);
+                assert!(entries.iter().all(|entry| entry.p50_ms <= entry.p95_ms && entry.p95_ms <= entry.max_ms));
+            }
+            messages => panic!("Expected Latency message, got {:?}", messages),
+        }
+
+        harness.publish("DMX/Command/Latency", "").await.unwrap();
+        assert!(matches!(harness.published().as_slice(), [ToMqttPublisherMessage::Latency(entries)] if entries.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_array_template() {
+        let harness = SubscriberHarness::new();
+        let universe_json = r#"{ "description": "Test universe", "controller": "10.0.1.228", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }"#;
+        let template_json = r#"{ "universe_id": "0", "lights": { "all": "s:{base}, s:{base+1}" } }"#;
+
+        harness.publish("DMX/Universe/0", universe_json).await.unwrap();
+        harness.publish("DMX/ArrayTemplate/room", template_json).await.unwrap();
+        harness.publish("DMX/Array/room1", r#"{ "template": "room", "parameters": { "base": 1 } }"#).await.unwrap();
+        harness.publish("DMX/Array/room2", r#"{ "template": "room", "parameters": { "base": 3 } }"#).await.unwrap();
+
+        harness.publish("DMX/Command/On", r#"{ "array_id": "room2" }"#).await.unwrap();
+        assert!(harness.subscriber.get_array_state(Arc::from("room2")).await.unwrap().is_some());
+
+        // The instantiated definition is verified as any other array definition
+        let invalid = harness.publish("DMX/Array/room3", r#"{ "template": "room", "parameters": { "base": "x" } }"#).await.unwrap_err();
+        assert!(format!("{:?}", invalid).contains("adding array room3"));
+
+        let missing = harness.publish("DMX/Array/room4", r#"{ "template": "room" }"#).await.unwrap_err();
+        assert!(format!("{:?}", missing).contains("parameter 'base' is not given"));
+
+        harness.publish("DMX/ArrayTemplate/room", "").await.unwrap();
+        let not_found = harness.publish("DMX/Array/room5", r#"{ "template": "room", "parameters": { "base": 5 } }"#).await.unwrap_err();
+        assert!(format!("{:?}", not_found).contains("Array template 'room' not found"));
+    }
+
+    #[tokio::test]
+    async fn test_enable_send() {
+        let harness = SubscriberHarness::new();
+        add_test_array(&harness).await;
+
+        let get_send_disabled = || async {
+            harness.publish("DMX/Command/UniverseStatus", r#"{ "universe_id": "0" }"#).await.unwrap();
+
+            match &harness.published()[..] {
+                [ToMqttPublisherMessage::UniverseSendStatus(_, status)] => status.send_disabled,
+                messages => panic!("Expected UniverseSendStatus message, got {:?}", messages),
+            }
+        };
+
+        assert!(get_send_disabled().await);
+        harness.publish("DMX/Command/EnableSend", r#"{ "universe_id": "0", "enable": true }"#).await.unwrap();
+        assert!(!get_send_disabled().await);
+        harness.publish("DMX/Command/EnableSend", r#"{ "universe_id": "0", "enable": false }"#).await.unwrap();
+        assert!(get_send_disabled().await);
+
+        assert!(harness.publish("DMX/Command/EnableSend", r#"{ "enable": true }"#).await.is_err());
+        assert!(harness.publish("DMX/Command/EnableSend", r#"{ "universe_id": "9", "enable": true }"#).await.is_err());
+    }
 }
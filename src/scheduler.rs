@@ -0,0 +1,417 @@
+use bytes::Bytes;
+use chrono::{Datelike, Duration as ChronoDuration, NaiveDateTime, NaiveTime, Timelike};
+use error_stack::Result;
+use log::info;
+use std::{collections::BTreeMap, str::FromStr, sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::{select, sync::mpsc::Receiver, time::interval};
+use tokio_util::sync::CancellationToken;
+
+use crate::{defs::ScheduleDefinition, messages::ToSchedulerMessage, mqtt_subscriber::MqttSubscriber};
+
+// Scene scheduler
+//
+// Schedules are defined by posting to DMX/Schedule/<name> (empty payload removes the schedule):
+//
+//  { "cron": "0 30 17 * * *", "command": "On", "parameters": { "array_id": "porch" } }
+//  { "daily_at": "17:30", "command": "On", "parameters": { "array_id": "porch" } }
+//
+// cron has 5 (minute hour day-of-month month day-of-week) or 6 (with leading seconds) fields, each field is *, n,
+// a-b, */n or a-b/n or a comma separated list of those. Schedules are checked once a minute (in local time) so
+// the seconds field is ignored. A due schedule runs its command exactly as if it was posted to DMX/Command/<command>.
+//
+// Schedules are kept in memory only, posting them as retained messages restores them when the service restarts.
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_CATCH_UP_MINUTES: i64 = 5; // Minutes missed (e.g. delayed check) that are still run, larger gaps (clock change) are skipped
+
+#[derive(Debug, Error)]
+pub enum SchedulerError {
+    #[error("Schedule '{0}' not found")]
+    ScheduleNotFound(Arc<str>),
+
+    #[error("Schedule '{0}' must have either 'cron' or 'daily_at'")]
+    MissingScheduleTime(Arc<str>),
+
+    #[error("Schedule '{0}' has invalid cron expression '{1}': {2}")]
+    InvalidCron(Arc<str>, String, String),
+
+    #[error("Schedule '{0}' has invalid daily_at time '{1}' (expected HH:MM)")]
+    InvalidDailyAt(Arc<str>, String),
+}
+
+// Set of allowed values of a cron field (bit n is set if value n is allowed)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CronField {
+    values: u64,
+    is_any: bool, // Field is '*' (used for the day of month/day of week rule)
+}
+
+impl CronField {
+    fn parse(field: &str, min: u32, max: u32) -> std::result::Result<CronField, String> {
+        let mut values = 0u64;
+
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().map_err(|_| format!("invalid step '{step}'"))?),
+                None => (part, 1),
+            };
+
+            if step == 0 {
+                return Err(format!("invalid step in '{part}'"));
+            }
+
+            let parse_value = |value: &str| match value.parse::<u32>() {
+                Ok(value) if (min..=max).contains(&value) => Ok(value),
+                _ => Err(format!("'{value}' is not in range {min}-{max}")),
+            };
+
+            let (first, last) = match range {
+                "*" => (min, max),
+                range => match range.split_once('-') {
+                    Some((first, last)) => (parse_value(first)?, parse_value(last)?),
+                    None if step == 1 => (parse_value(range)?, parse_value(range)?),
+                    None => (parse_value(range)?, max),
+                },
+            };
+
+            if first > last {
+                return Err(format!("invalid range '{range}'"));
+            }
+
+            for value in (first..=last).step_by(step as usize) {
+                values |= 1 << value;
+            }
+        }
+
+        Ok(CronField { values, is_any: field == "*" })
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.values & (1 << value) != 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CronSpec {
+    minutes: CronField,
+    hours: CronField,
+    days_of_month: CronField,
+    months: CronField,
+    days_of_week: CronField,
+}
+
+impl FromStr for CronSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+
+        let fields = match fields.len() {
+            5 => &fields[..],
+            6 => {
+                CronField::parse(fields[0], 0, 59).map_err(|e| format!("seconds: {e}"))?;
+                &fields[1..]
+            }
+            n => return Err(format!("expected 5 or 6 fields, got {n}")),
+        };
+
+        let mut days_of_week = CronField::parse(fields[4], 0, 7).map_err(|e| format!("day of week: {e}"))?;
+
+        // Both 0 and 7 are Sunday
+        if days_of_week.matches(7) {
+            days_of_week.values |= 1;
+        }
+
+        Ok(CronSpec {
+            minutes: CronField::parse(fields[0], 0, 59).map_err(|e| format!("minute: {e}"))?,
+            hours: CronField::parse(fields[1], 0, 23).map_err(|e| format!("hour: {e}"))?,
+            days_of_month: CronField::parse(fields[2], 1, 31).map_err(|e| format!("day of month: {e}"))?,
+            months: CronField::parse(fields[3], 1, 12).map_err(|e| format!("month: {e}"))?,
+            days_of_week,
+        })
+    }
+}
+
+impl CronSpec {
+    fn matches(&self, time: &NaiveDateTime) -> bool {
+        let day_of_month = self.days_of_month.matches(time.day());
+        let day_of_week = self.days_of_week.matches(time.weekday().num_days_from_sunday());
+
+        // As in cron, if both day of month and day of week are restricted, either one matching is enough
+        let day = match (self.days_of_month.is_any, self.days_of_week.is_any) {
+            (false, false) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        };
+
+        day && self.minutes.matches(time.minute()) && self.hours.matches(time.hour()) && self.months.matches(time.month())
+    }
+}
+
+#[derive(Debug)]
+enum ScheduleTime {
+    Cron(CronSpec),
+    DailyAt(NaiveTime),
+}
+
+#[derive(Debug)]
+struct Schedule {
+    definition: ScheduleDefinition,
+    time: ScheduleTime,
+}
+
+impl Schedule {
+    fn new(name: &Arc<str>, definition: ScheduleDefinition) -> Result<Schedule, SchedulerError> {
+        let time = match (&definition.cron, &definition.daily_at) {
+            (Some(cron), None) => ScheduleTime::Cron(
+                cron.parse().map_err(|e| SchedulerError::InvalidCron(name.clone(), cron.clone(), e))?,
+            ),
+            (None, Some(daily_at)) => ScheduleTime::DailyAt(
+                NaiveTime::parse_from_str(daily_at, "%H:%M")
+                    .map_err(|_| SchedulerError::InvalidDailyAt(name.clone(), daily_at.clone()))?,
+            ),
+            _ => return Err(SchedulerError::MissingScheduleTime(name.clone()).into()),
+        };
+
+        Ok(Schedule { definition, time })
+    }
+
+    fn is_due(&self, time: &NaiveDateTime) -> bool {
+        match &self.time {
+            ScheduleTime::Cron(cron) => cron.matches(time),
+            ScheduleTime::DailyAt(daily_at) => time.hour() == daily_at.hour() && time.minute() == daily_at.minute(),
+        }
+    }
+
+    fn get_payload(&self) -> Bytes {
+        match &self.definition.parameters {
+            Some(parameters) => Bytes::from(parameters.to_string()),
+            None => Bytes::new(),
+        }
+    }
+}
+
+// Command to run when a schedule is due
+#[derive(Debug, PartialEq, Eq)]
+pub struct ScheduledCommand {
+    pub schedule_name: Arc<str>,
+    pub command: Arc<str>,
+    pub payload: Bytes,
+}
+
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    schedules: BTreeMap<Arc<str>, Schedule>,
+    last_checked: Option<NaiveDateTime>, // Last minute whose schedules were run
+}
+
+fn truncate_to_minute(time: NaiveDateTime) -> NaiveDateTime {
+    time.with_second(0).and_then(|t| t.with_nanosecond(0)).unwrap()
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_schedule(&mut self, name: Arc<str>, definition: Option<ScheduleDefinition>) -> Result<(), SchedulerError> {
+        match definition {
+            Some(definition) => {
+                let schedule = Schedule::new(&name, definition)?;
+                self.schedules.insert(name, schedule);
+            }
+            None => {
+                if self.schedules.remove(&name).is_none() {
+                    return Err(SchedulerError::ScheduleNotFound(name).into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Remove all the schedules (Reset command), they are defined again when the retained definitions are received
+    pub fn clear_all(&mut self) {
+        self.schedules.clear();
+    }
+
+    pub fn get_schedules(&self) -> BTreeMap<Arc<str>, ScheduleDefinition> {
+        self.schedules.iter().map(|(name, schedule)| (name.clone(), schedule.definition.clone())).collect()
+    }
+
+    // Get the commands of schedules due in the minutes since the last check up to (and including) now
+    pub fn get_due_commands(&mut self, now: NaiveDateTime) -> Vec<ScheduledCommand> {
+        let now = truncate_to_minute(now);
+
+        let first_minute = match self.last_checked {
+            Some(last_checked) if last_checked >= now => return Vec::new(),
+            Some(last_checked) if now - last_checked <= ChronoDuration::minutes(MAX_CATCH_UP_MINUTES) => {
+                last_checked + ChronoDuration::minutes(1)
+            }
+            _ => now,
+        };
+
+        self.last_checked = Some(now);
+
+        let mut due_commands = Vec::new();
+        let mut minute = first_minute;
+
+        while minute <= now {
+            for (name, schedule) in self.schedules.iter().filter(|(_, schedule)| schedule.is_due(&minute)) {
+                due_commands.push(ScheduledCommand {
+                    schedule_name: name.clone(),
+                    command: schedule.definition.command.clone(),
+                    payload: schedule.get_payload(),
+                });
+            }
+
+            minute += ChronoDuration::minutes(1);
+        }
+
+        due_commands
+    }
+
+    fn handle_message(&mut self, message: ToSchedulerMessage) {
+        match message {
+            ToSchedulerMessage::SetSchedule(name, definition, reply_tx) => {
+                reply_tx.send(self.set_schedule(name, definition)).unwrap()
+            }
+            ToSchedulerMessage::GetSchedules(reply_tx) => reply_tx.send(self.get_schedules()).unwrap(),
+            ToSchedulerMessage::ClearAll(reply_tx) => {
+                self.clear_all();
+                reply_tx.send(()).unwrap()
+            }
+        }
+    }
+
+    pub async fn run(&mut self, cancel: CancellationToken, mut receiver: Receiver<ToSchedulerMessage>, command_handler: MqttSubscriber) {
+        let mut check_timer = interval(CHECK_INTERVAL);
+
+        loop {
+            select! {
+                _ = cancel.cancelled() => break,
+
+                _ = check_timer.tick() => {
+                    let due_commands = self.get_due_commands(chrono::Local::now().naive_local());
+
+                    if !due_commands.is_empty() {
+                        let command_handler = command_handler.clone();
+
+                        // Commands are run in their own task, they may need the scheduler (e.g. DumpSchedules)
+                        tokio::spawn(async move {
+                            command_handler.handle_scheduled_commands(due_commands).await;
+                        });
+                    }
+                },
+
+                message = receiver.recv() => match message {
+                    None => break,
+                    Some(message) => self.handle_message(message),
+                },
+            }
+        }
+
+        info!("Scheduler stopped");
+    }
+}
+
+#[cfg(test)]
+mod test_scheduler {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, minute: u32, second: u32) -> NaiveDateTime {
+        // 2024-01-01 is a Monday
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(hour, minute, second).unwrap()
+    }
+
+    fn schedule(json: &str) -> Option<ScheduleDefinition> {
+        Some(serde_json::from_str(json).unwrap())
+    }
+
+    fn due_schedules(scheduler: &mut Scheduler, now: NaiveDateTime) -> Vec<String> {
+        scheduler.get_due_commands(now).iter().map(|c| c.schedule_name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_cron_spec() {
+        let cron = "0 30 17 * * *".parse::<CronSpec>().unwrap();
+        assert!(cron.matches(&at(1, 17, 30, 0)));
+        assert!(!cron.matches(&at(1, 17, 31, 0)));
+
+        // Every 15 minutes between 8 and 10 on weekdays
+        let cron = "*/15 8-10 * * 1-5".parse::<CronSpec>().unwrap();
+        assert!(cron.matches(&at(1, 8, 45, 0)));
+        assert!(!cron.matches(&at(1, 8, 50, 0)));
+        assert!(!cron.matches(&at(1, 11, 0, 0)));
+        assert!(!cron.matches(&at(7, 9, 0, 0))); // Sunday
+
+        // 7 is also Sunday, day of month or day of week
+        let cron = "0 12 15 * 7".parse::<CronSpec>().unwrap();
+        assert!(cron.matches(&at(7, 12, 0, 0)));
+        assert!(cron.matches(&at(15, 12, 0, 0)));
+        assert!(!cron.matches(&at(16, 12, 0, 0)));
+
+        assert!("0 30 17 * *  * *".parse::<CronSpec>().is_err());
+        assert!("60 17 * * *".parse::<CronSpec>().is_err());
+        assert!("*/0 17 * * *".parse::<CronSpec>().is_err());
+        assert!("0 20-17 * * *".parse::<CronSpec>().is_err());
+    }
+
+    #[test]
+    fn test_due_commands() {
+        let mut scheduler = Scheduler::new();
+        scheduler
+            .set_schedule(Arc::from("porch"), schedule(r#"{ "daily_at": "17:30", "command": "On", "parameters": { "array_id": "porch" } }"#))
+            .unwrap();
+        scheduler
+            .set_schedule(Arc::from("night"), schedule(r#"{ "cron": "0 0 23 * * *", "command": "Off", "parameters": { "array_id": "porch" } }"#))
+            .unwrap();
+
+        assert!(due_schedules(&mut scheduler, at(1, 17, 29, 10)).is_empty());
+
+        let due_commands = scheduler.get_due_commands(at(1, 17, 30, 5));
+        assert_eq!(
+            due_commands,
+            [ScheduledCommand {
+                schedule_name: Arc::from("porch"),
+                command: Arc::from("On"),
+                payload: Bytes::from(r#"{"array_id":"porch"}"#),
+            }]
+        );
+
+        // Runs once even if checked again during the same minute
+        assert!(due_schedules(&mut scheduler, at(1, 17, 30, 50)).is_empty());
+
+        // A late check still runs the missed minutes, a large gap does not
+        assert!(due_schedules(&mut scheduler, at(1, 22, 58, 0)).is_empty());
+        assert_eq!(due_schedules(&mut scheduler, at(1, 23, 2, 0)), ["night"]);
+        assert!(due_schedules(&mut scheduler, at(2, 17, 40, 0)).is_empty());
+
+        assert_eq!(due_schedules(&mut scheduler, at(3, 17, 30, 0)), ["porch"]);
+    }
+
+    #[test]
+    fn test_set_and_remove_schedule() {
+        let mut scheduler = Scheduler::new();
+        let porch: Arc<str> = Arc::from("porch");
+
+        let e = scheduler.set_schedule(porch.clone(), schedule(r#"{ "command": "On" }"#)).unwrap_err();
+        assert!(matches!(e.current_context(), SchedulerError::MissingScheduleTime(_)));
+        let e = scheduler.set_schedule(porch.clone(), schedule(r#"{ "daily_at": "25:00", "command": "On" }"#)).unwrap_err();
+        assert!(matches!(e.current_context(), SchedulerError::InvalidDailyAt(_, _)));
+        let e = scheduler.set_schedule(porch.clone(), schedule(r#"{ "cron": "0 17 * *", "command": "On" }"#)).unwrap_err();
+        assert!(matches!(e.current_context(), SchedulerError::InvalidCron(_, _, _)));
+
+        scheduler.set_schedule(porch.clone(), schedule(r#"{ "daily_at": "17:30", "command": "ExportEffects" }"#)).unwrap();
+        assert_eq!(scheduler.get_schedules().len(), 1);
+        assert_eq!(scheduler.get_due_commands(at(1, 17, 30, 0))[0].payload, Bytes::new());
+
+        scheduler.set_schedule(porch.clone(), None).unwrap();
+        assert!(scheduler.get_schedules().is_empty());
+        assert!(due_schedules(&mut scheduler, at(2, 17, 30, 0)).is_empty());
+
+        let e = scheduler.set_schedule(porch, None).unwrap_err();
+        assert!(matches!(e.current_context(), SchedulerError::ScheduleNotFound(_)));
+    }
+}
@@ -0,0 +1,83 @@
+// Schema document published (retained) to DMX/Schema, so clients can find out which commands, effect node types and
+// optional capabilities the running service supports before they send anything.
+//
+// Bump SCHEMA_VERSION when a payload format changes in a way an existing client would misread: a field is removed or
+// renamed, its type or meaning changes, or a topic moves. Adding a command, an effect node type, a feature or an
+// optional field does not bump it, clients look for them in the lists instead
+
+use std::collections::BTreeMap;
+
+use crate::{defs, get_version, service::ServiceConfig};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+// Commands handled by the subscriber (DMX/Command/<name>)
+pub const COMMANDS: &[&str] = &[
+    "On", "Off", "Dim", "Toggle", "Stop", "Adjust", "Rename", "Pause", "Resume", "Set", "Alias", "SetFrame", "Copy",
+    "Blackout", "EnableSend", "ImportEffects", "ExportEffects", "CheckConfig", "ExportPatch", "Verify", "Validate",
+    "DumpSchedules", "DumpDimmingPresets", "EffectStats", "Latency", "Diagnostics", "EffectStatus", "UniverseStatus",
+    "Schema", "Reset", "ResolveEffect",
+];
+
+// The "type" of each defs::EffectNodeDefinition variant
+pub const EFFECT_NODE_TYPES: &[&str] = &["sequence", "parallel", "delay", "fade", "hold", "wait_for"];
+
+// Capabilities that depend on how the service was started
+pub fn get_features(config: &ServiceConfig) -> BTreeMap<&'static str, bool> {
+    BTreeMap::from([
+        ("lenient_json", !config.strict_json),                  // Definitions may have comments and trailing commas
+        ("retained_commands", config.allow_retained_commands),  // Commands delivered as retained messages are run
+        ("http_status", config.http_port.is_some()),            // GET /health, /status and /metrics
+        ("sim", config.sim_port.is_some()),                     // Universe frames served to sim viewers
+        ("default_universe", config.default_universe_id.is_some()),     // Arrays may omit universe_id
+    ])
+}
+
+pub fn get_schema(features: BTreeMap<&'static str, bool>) -> defs::Schema {
+    defs::Schema {
+        version: get_version(),
+        schema_version: SCHEMA_VERSION,
+        commands: COMMANDS.to_vec(),
+        effect_node_types: EFFECT_NODE_TYPES.to_vec(),
+        features,
+    }
+}
+
+#[cfg(test)]
+mod test_schema {
+    use super::*;
+
+    // Serde lists the variants it expects when the type is unknown, so adding a node type without listing it fails here
+    #[test]
+    fn test_effect_node_types() {
+        let e = serde_json::from_str::<defs::EffectNodeDefinition>(r#"{ "type": "no_such_type" }"#).unwrap_err().to_string();
+        let expected = e.split_once("expected one of ").map(|(_, variants)| variants).unwrap_or_else(|| panic!("{e}"));
+        let mut variants = expected.split(", ").map(|variant| variant.split('`').nth(1).unwrap()).collect::<Vec<_>>();
+        let mut effect_node_types = EFFECT_NODE_TYPES.to_vec();
+
+        variants.sort();
+        effect_node_types.sort();
+        assert_eq!(variants, effect_node_types);
+
+        // Each listed type is a variant (fails on its missing fields, not on its type)
+        for effect_node_type in EFFECT_NODE_TYPES {
+            let json = format!(r#"{{ "type": "{effect_node_type}", "unknown_field": 1 }}"#);
+
+            if let Err(e) = serde_json::from_str::<defs::EffectNodeDefinition>(&json) {
+                assert!(!e.to_string().contains("unknown variant"), "{effect_node_type}: {e}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_schema() {
+        let schema = get_schema(BTreeMap::from([("http_status", true), ("sim", false)]));
+        let json = serde_json::to_value(&schema).unwrap();
+
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        assert_eq!(json["version"], get_version());
+        assert_eq!(json["commands"].as_array().unwrap().len(), COMMANDS.len());
+        assert_eq!(json["effect_node_types"][3], "fade");
+        assert_eq!(json["features"], serde_json::json!({ "http_status": true, "sim": false }));
+    }
+}
@@ -1,25 +1,132 @@
 use error_stack::{Result, ResultExt};
-use log::info;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
 use rumqttc::{AsyncClient, EventLoop, LastWill, MqttOptions, QoS};
 use std::{marker::PhantomData, sync::Arc};
 use thiserror::Error;
-use tokio::sync::mpsc::Sender;
-use tokio::{task::JoinSet, time::Duration};
+use tokio::{sync::watch, task::JoinSet, time::Duration};
 use tokio_util::sync::CancellationToken;
 
 use crate::{
     array_manager,
-    artnet_manager::ArtnetManager,
+    artnet_manager::{ArtnetManager, EffectTickBudget, DEFAULT_TICK_DURATION},
+    defs,
     get_version,
-    messages::{self, ToArtnetManagerMessage},
-    mqtt_publisher, mqtt_subscriber,
+    http_status,
+    manager_channel,
+    messages,
+    metrics::Metrics,
+    mqtt_publisher, mqtt_subscriber, sim,
+    mqtt_subscriber::MqttSubscriber,
+    scheduler::Scheduler,
+    schema,
 };
 
+// MQTT topic (up to 64K) and fixed/variable header on top of the payload
+const MAX_PACKET_OVERHEAD: usize = 64 * 1024 + 16;
+
+// Published (retained) to DMX/Status/Connection whenever a session connects to the broker
+#[derive(Serialize, Debug)]
+struct ConnectionStatus<'a> {
+    broker: &'a str,
+    connected_at: String,
+}
+
+// Broker address without credentials (user:password@host), so it can be published and logged
+fn get_sanitized_broker_address(mqtt_broker: &str) -> &str {
+    mqtt_broker.rsplit_once('@').map_or(mqtt_broker, |(_, host)| host)
+}
+
 pub struct Started {}
 pub struct Stopped {}
 
 pub struct ServiceConfig {
     pub mqtt_broker_address: String,
+    pub effect_tick_budget: Option<EffectTickBudget>,  // If set, effects that consistently exceed this budget are stopped
+    pub default_universe_id: Option<String>,           // Universe of arrays that do not specify universe_id
+    pub sim_port: Option<u16>,                         // If set, universe frames are served to local viewers on this port
+    pub sim_max_frames_per_second: u32,
+    pub strict_json: bool,                             // Do not allow comments and trailing commas in definitions
+    pub unreachable_threshold: usize,                  // Consecutive send failures before a universe is reported unreachable
+    pub controller_retention: Duration,                // Keep a controller socket this long after its last universe is removed
+    pub publisher_queue_size: usize,                   // Messages waiting to be published, messages from the DMX tick loop are dropped when full
+    pub artnet_queue_size: usize,                      // Messages waiting for the artnet manager
+    pub array_queue_size: usize,                       // Messages waiting for the array manager
+    pub max_delta_per_tick: Option<u8>,                // Default channel slew limit of universes that do not set max_delta_per_tick
+    pub max_payload_size: usize,                       // Larger MQTT payloads are rejected before they are parsed
+    pub http_port: Option<u16>,                        // If set, GET /health, /status and /metrics are served on this port
+    pub max_effect_ticks: Option<usize>,               // Initial max_effect_ticks setting (can be changed by DMX/Config)
+    pub allow_retained_commands: bool,                 // Run commands delivered as retained messages (ignored by default)
+}
+
+// Service parameters that can be changed while running by posting some (or all) of them to DMX/Config. Changes are
+// distributed to the components through a watch channel, the effective settings are published (retained) to
+// DMX/Config/Effective
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct ServiceSettings {
+    pub tick_ms: u64,                       // Effect tick period (effect durations are given in ticks)
+    pub max_payload_size: usize,            // Larger MQTT payloads are rejected before they are parsed
+    pub reconnect_delay_seconds: u64,       // Wait before connecting again when the MQTT session ends
+    pub max_effect_ticks: Option<usize>,    // Effects are stopped after this number of ticks (unless the command sets max_ticks or no_max_ticks)
+}
+
+const MIN_TICK_MS: u64 = 10;
+const MAX_TICK_MS: u64 = 1000;
+
+impl Default for ServiceSettings {
+    fn default() -> Self {
+        ServiceSettings {
+            tick_ms: DEFAULT_TICK_DURATION.as_millis() as u64,
+            max_payload_size: mqtt_subscriber::DEFAULT_MAX_PAYLOAD_SIZE,
+            reconnect_delay_seconds: 10,
+            max_effect_ticks: None,
+        }
+    }
+}
+
+impl ServiceSettings {
+    pub fn get_names() -> Vec<String> {
+        match serde_json::to_value(ServiceSettings::default()) {
+            Ok(serde_json::Value::Object(values)) => values.keys().cloned().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    // Settings with the changes applied (settings that are not changed keep their value), and the names of the
+    // changes that are not settings
+    pub fn with_changes(&self, changes: serde_json::Map<String, serde_json::Value>) -> Result<(ServiceSettings, Vec<String>), MqttError> {
+        let mut values = match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(values)) => values,
+            _ => serde_json::Map::new(),
+        };
+        let mut unknown_names = Vec::new();
+
+        for (name, value) in changes {
+            if values.contains_key(&name) {
+                values.insert(name, value);
+            } else {
+                unknown_names.push(name);
+            }
+        }
+
+        let settings = serde_json::from_value::<ServiceSettings>(serde_json::Value::Object(values))
+            .map_err(|e| MqttError::InvalidConfig(e.to_string()))?;
+
+        if !(MIN_TICK_MS..=MAX_TICK_MS).contains(&settings.tick_ms) {
+            return Err(MqttError::InvalidConfig(format!("tick_ms is {} (must be {MIN_TICK_MS}-{MAX_TICK_MS})", settings.tick_ms)).into());
+        }
+
+        if settings.max_payload_size == 0 || settings.reconnect_delay_seconds == 0 {
+            return Err(MqttError::InvalidConfig("max_payload_size and reconnect_delay_seconds must not be 0".to_string()).into());
+        }
+
+        if settings.max_effect_ticks == Some(0) {
+            return Err(MqttError::InvalidConfig("max_effect_ticks must not be 0 (use null for no limit)".to_string()).into());
+        }
+
+        Ok((settings, unknown_names))
+    }
 }
 
 pub struct Service<Status = Stopped> {
@@ -43,17 +150,53 @@ pub enum MqttError {
     #[error("Missing Array ID in DMX topic: '{0}'")]
     MissingArrayId(String),
 
+    #[error("DMX topic '{0}' has too many levels (IDs must not contain '/')")]
+    TooManyTopicLevels(String),
+
+    #[error("Invalid {0} ID '{1}': {2}")]
+    InvalidId(String, String, String),
+
     #[error("{0}")]
     Context(String),
 
     #[error("Error parsing {0} ('{1}'): {2}")]
     JsonParseError(Arc<str>, Arc<str>, #[source] serde_json::Error),
 
-    #[error("Missing command (topic should be DMX/Command/[On, Off, Stop])")]
+    #[error("{0} '{1}' is missing required field '{2}'")]
+    MissingField(Arc<str>, Arc<str>, String),
+
+    #[error("{0} (this payload looks like {1} definition, did you mean topic {2}?)")]
+    WrongDefinitionTopic(String, &'static str, String),
+
+    #[error("Missing command (topic should be DMX/Command/[On, Off, Toggle, Stop])")]
     MissingCommand,
 
-    #[error("Invalid command: '{0}' (topic should be DMX/Command/[On, Off, Stop])")]
+    #[error("Invalid command: '{0}' (topic should be DMX/Command/[On, Off, Toggle, Stop])")]
     InvalidCommand(String),
+
+    #[error("\"instant\": true is supported only by Off and Toggle commands (not {0})")]
+    InstantNotOff(String),
+
+    #[error("{0} command requires either array_id or \"all\": true")]
+    MissingArrayIdOrAll(String),
+
+    #[error("{0} command requires either universe_id or universe_group")]
+    MissingUniverseIdOrGroup(String),
+
+    #[error("{0} command requires either array_id, or lights and effect")]
+    MissingArrayOrInlineEffect(String),
+
+    #[error("Reset command requires \"confirm\": \"RESET\" (it removes all universes, arrays, effects and values)")]
+    ResetNotConfirmed,
+
+    #[error("Importing effects: {0} of {1} effects failed: {2}")]
+    ImportEffectsFailed(usize, usize, String),
+
+    #[error("Payload of '{0}' is {1} bytes (maximum is {2} bytes)")]
+    PayloadTooLarge(String, usize, usize),
+
+    #[error("Invalid service configuration: {0}")]
+    InvalidConfig(String),
 }
 
 impl Service {
@@ -67,6 +210,8 @@ impl Service {
 
     async fn connect_to_mqtt_broker(
         mqtt_broker: &str,
+        max_payload_size: usize,
+        schema: &defs::Schema,
     ) -> Result<(AsyncClient, EventLoop), MqttError> {
         let into_context =
             || MqttError::Context(format!("Connecting to MQTT broker {mqtt_broker}"));
@@ -74,8 +219,12 @@ impl Service {
         let last_will_topic = "DMX/Active".to_string();
         let version_topic = "DMX/Version".to_string();
         let last_will = LastWill::new(&last_will_topic, "false".as_bytes(), QoS::AtLeastOnce, true);
+        // Leave room for the topic and packet header, so a slightly oversized payload still reaches the subscriber and is
+        // reported as too large instead of dropping the connection
+        let max_packet_size = max_payload_size.saturating_add(MAX_PACKET_OVERHEAD);
         mqtt_options
             .set_keep_alive(Duration::from_secs(5))
+            .set_max_packet_size(max_packet_size, max_packet_size)
             .set_last_will(last_will);
 
         let (mqtt_client, event_loop) = AsyncClient::new(mqtt_options, 10);
@@ -94,6 +243,29 @@ impl Service {
             )
             .await
             .change_context_lazy(into_context)?;
+        mqtt_client
+            .publish(
+                "DMX/Schema",
+                QoS::AtLeastOnce,
+                true,
+                serde_json::to_vec(schema).change_context_lazy(into_context)?,
+            )
+            .await
+            .change_context_lazy(into_context)?;
+
+        let connection_status = ConnectionStatus {
+            broker: get_sanitized_broker_address(mqtt_broker),
+            connected_at: chrono::Utc::now().to_rfc3339(),
+        };
+        mqtt_client
+            .publish(
+                "DMX/Status/Connection",
+                QoS::AtLeastOnce,
+                true,
+                serde_json::to_vec(&connection_status).change_context_lazy(into_context)?,
+            )
+            .await
+            .change_context_lazy(into_context)?;
 
         // Subscribe to commands
         mqtt_client
@@ -105,29 +277,23 @@ impl Service {
 
     async fn mqtt_session(
         broker_address: &str,
-        to_artnet_tx: Sender<ToArtnetManagerMessage>,
-        to_array_tx: Sender<messages::ToArrayManagerMessage>,
+        max_payload_size: usize,
         to_mqtt_publisher_rx: async_channel::Receiver<messages::ToMqttPublisherMessage>,
-        to_mqtt_publisher_tx: async_channel::Sender<messages::ToMqttPublisherMessage>,
+        mqtt_subscriber: MqttSubscriber,
     ) -> Result<(), MqttError> {
         let mut mqtt_workers = JoinSet::new();
 
         let (mqtt_client, mqtt_event_loop) =
-            Service::connect_to_mqtt_broker(broker_address).await?;
+            Service::connect_to_mqtt_broker(broker_address, max_payload_size, &mqtt_subscriber.get_schema()).await?;
 
         mqtt_workers.spawn(async move {
             let e = mqtt_publisher::session(mqtt_client, to_mqtt_publisher_rx).await;
             info!("MQTT publisher session ended: {:?}", e)
         });
 
+        let sanitized_broker_address = Arc::from(get_sanitized_broker_address(broker_address));
         mqtt_workers.spawn(async move {
-            let e = mqtt_subscriber::session(
-                mqtt_event_loop,
-                to_artnet_tx,
-                to_array_tx,
-                to_mqtt_publisher_tx,
-            )
-            .await;
+            let e = mqtt_subscriber::session(mqtt_event_loop, mqtt_subscriber, sanitized_broker_address).await;
             info!("MQTT subscriber session ended: {:?}", e)
         });
 
@@ -137,25 +303,27 @@ impl Service {
         Ok(())
     }
 
+    // The maximum packet size follows max_payload_size changes on the next session
     async fn mqtt(
         broker_address: &str,
-        to_artnet_tx: Sender<ToArtnetManagerMessage>,
-        to_array_tx: Sender<messages::ToArrayManagerMessage>,
+        settings: watch::Receiver<ServiceSettings>,
         to_mqtt_publisher_rx: async_channel::Receiver<messages::ToMqttPublisherMessage>,
-        to_mqtt_publisher_tx: async_channel::Sender<messages::ToMqttPublisherMessage>,
+        mqtt_subscriber: MqttSubscriber,
     ) {
         loop {
+            let max_payload_size = settings.borrow().max_payload_size;
             let _ = Self::mqtt_session(
                     broker_address,
-                    to_artnet_tx.clone(),
-                    to_array_tx.clone(),
+                    max_payload_size,
                     to_mqtt_publisher_rx.clone(),
-                    to_mqtt_publisher_tx.clone(),
+                    mqtt_subscriber.clone(),
                 )
                 .await;
+            mqtt_subscriber.set_broker_connected(false);
 
-            info!("MQTT session ended, restarting in 10 seconds");
-            tokio::time::sleep(Duration::from_secs(10)).await;
+            let reconnect_delay_seconds = settings.borrow().reconnect_delay_seconds;
+            info!("MQTT session ended, restarting in {} seconds", reconnect_delay_seconds);
+            tokio::time::sleep(Duration::from_secs(reconnect_delay_seconds)).await;
         }
     }
 }
@@ -166,18 +334,61 @@ impl Service<Stopped> {
 
         // Create the channels for the workers
         let (to_artnet_tx, to_artnet_rx) =
-            tokio::sync::mpsc::channel::<messages::ToArtnetManagerMessage>(10);
+            manager_channel::channel::<messages::ToArtnetManagerMessage>("artnet", self.config.artnet_queue_size);
         let (to_array_tx, to_array_rx) =
-            tokio::sync::mpsc::channel::<messages::ToArrayManagerMessage>(10);
+            manager_channel::channel::<messages::ToArrayManagerMessage>("array", self.config.array_queue_size);
         let (to_mqtt_publisher_tx, to_mqtt_publisher_rx) =
-            async_channel::bounded(10);
+            async_channel::bounded(self.config.publisher_queue_size.max(1));
+        let (to_scheduler_tx, to_scheduler_rx) =
+            tokio::sync::mpsc::channel::<messages::ToSchedulerMessage>(10);
 
         let to_mqtt_publisher_tx_instance = to_mqtt_publisher_tx.clone();
 
+        // Settings changed by DMX/Config (handled by the subscriber) are watched by the other components
+        let (settings_tx, settings_rx) = watch::channel(ServiceSettings {
+            max_payload_size: self.config.max_payload_size,
+            max_effect_ticks: self.config.max_effect_ticks,
+            ..ServiceSettings::default()
+        });
+
+        // Counted by the artnet manager and the subscriber
+        let metrics = Arc::new(Metrics::default());
+
+        // Create sim listener worker
+        let sim_frames = self.config.sim_port.map(|_| sim::channel());
+
+        if let (Some(sim_port), Some(sim_frames)) = (self.config.sim_port, sim_frames.clone()) {
+            let cancel_instance = cancel.clone();
+            let max_frames_per_second = self.config.sim_max_frames_per_second;
+
+            self.workers.spawn(async move {
+                match tokio::net::TcpListener::bind(("127.0.0.1", sim_port)).await {
+                    Ok(listener) => {
+                        info!("Serving sim frames on port {}", sim_port);
+                        sim::serve(cancel_instance, listener, sim_frames, max_frames_per_second).await;
+                    }
+                    Err(e) => error!("Sim listener on port {} failed: {}", sim_port, e),
+                }
+            });
+        }
+
         // Create Artnet manager worker
         let cancel_instance = cancel.clone();
+        let effect_tick_budget = self.config.effect_tick_budget;
+        let unreachable_threshold = self.config.unreachable_threshold;
+        let controller_retention = self.config.controller_retention;
+        let max_delta_per_tick = self.config.max_delta_per_tick;
+        let settings_rx_instance = settings_rx.clone();
+        let metrics_instance = metrics.clone();
         self.workers.spawn(async move {
-            let mut artnet_manager = ArtnetManager::new();
+            let mut artnet_manager = ArtnetManager::new()
+                .with_tick_budget(effect_tick_budget)
+                .with_unreachable_threshold(unreachable_threshold)
+                .with_controller_retention(controller_retention)
+                .with_max_delta_per_tick(max_delta_per_tick)
+                .with_sim(sim_frames)
+                .with_settings(settings_rx_instance)
+                .with_metrics(metrics_instance);
 
             artnet_manager
                 .run(cancel_instance, to_artnet_rx, to_mqtt_publisher_tx_instance)
@@ -186,24 +397,56 @@ impl Service<Stopped> {
 
         // Create array manager worker
         let cancel_instance = cancel.clone();
+        let default_universe_id = self.config.default_universe_id.clone();
 
         self.workers.spawn(async move {
-            let mut array_manager = array_manager::ArrayManager::new();
+            let mut array_manager = array_manager::ArrayManager::new().with_default_universe(default_universe_id);
 
             array_manager.run(cancel_instance, to_array_rx).await;
         });
 
+        let mqtt_subscriber = MqttSubscriber::new(
+            to_artnet_tx,
+            to_array_tx,
+            to_mqtt_publisher_tx,
+            to_scheduler_tx,
+            !self.config.strict_json,
+        )
+        .with_settings(settings_tx)
+        .with_retained_commands(self.config.allow_retained_commands)
+        .with_metrics(metrics)
+        .with_schema(schema::get_schema(schema::get_features(&self.config)));
+
+        // Create scheduler worker, due schedules run their command as if it was received by the subscriber
+        let cancel_instance = cancel.clone();
+        let mqtt_subscriber_instance = mqtt_subscriber.clone();
+
+        self.workers.spawn(async move {
+            let mut scheduler = Scheduler::new();
+
+            scheduler.run(cancel_instance, to_scheduler_rx, mqtt_subscriber_instance).await;
+        });
+
+        // Create HTTP status listener worker
+        let http_port = self.config.http_port;
+        let cancel_instance = cancel.clone();
+        let mqtt_subscriber_instance = mqtt_subscriber.clone();
+
+        self.workers.spawn(async move {
+            match http_status::bind(http_port).await {
+                Ok(Some(listener)) => {
+                    info!("Serving HTTP status on port {}", http_port.unwrap_or_default());
+                    http_status::serve(cancel_instance, listener, mqtt_subscriber_instance).await;
+                }
+                Ok(None) => {}
+                Err(e) => error!("HTTP status listener on port {} failed: {}", http_port.unwrap_or_default(), e),
+            }
+        });
+
         let broker_address = self.config.mqtt_broker_address.clone();
 
         self.workers.spawn(async move {
-            Self::mqtt(
-                &broker_address,
-                to_artnet_tx,
-                to_array_tx,
-                to_mqtt_publisher_rx,
-                to_mqtt_publisher_tx,
-            )
-            .await;
+            Self::mqtt(&broker_address, settings_rx, to_mqtt_publisher_rx, mqtt_subscriber).await;
         });
 
         info!("Service started");
@@ -0,0 +1,97 @@
+use log::{info, warn};
+use serde::Serialize;
+use std::{collections::HashMap, time::Duration};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    select,
+    sync::broadcast::{self, error::RecvError},
+    time::interval,
+};
+use tokio_util::sync::CancellationToken;
+
+// Simulation (headless) mode
+//
+// Every frame sent to a universe is also pushed into a broadcast channel. Clients connecting to the sim TCP port
+// receive the frames as newline delimited JSON:
+//
+//  { "universe_id": "0", "channels": [0, 255, 12, ...] }
+//
+// Each client gets at most max_frames_per_second frames per universe, if a universe is modified more often only its
+// latest frame is sent. Frames are dropped for clients that are too slow to read them.
+
+const SIM_CHANNEL_CAPACITY: usize = 64;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SimFrame {
+    pub universe_id: String,
+    pub channels: Vec<u8>,
+}
+
+pub fn channel() -> broadcast::Sender<SimFrame> {
+    broadcast::channel(SIM_CHANNEL_CAPACITY).0
+}
+
+pub async fn serve(
+    cancel: CancellationToken,
+    listener: TcpListener,
+    frames: broadcast::Sender<SimFrame>,
+    max_frames_per_second: u32,
+) {
+    let frame_interval = Duration::from_secs(1) / max_frames_per_second.max(1);
+
+    loop {
+        select! {
+            _ = cancel.cancelled() => break,
+
+            connection = listener.accept() => match connection {
+                Ok((stream, address)) => {
+                    info!("Sim client {} connected", address);
+                    let receiver = frames.subscribe();
+                    let cancel = cancel.clone();
+
+                    tokio::spawn(async move {
+                        serve_client(cancel, stream, receiver, frame_interval).await;
+                        info!("Sim client {} disconnected", address);
+                    });
+                },
+                Err(e) => warn!("Sim listener accept failed: {}", e),
+            }
+        }
+    }
+
+    info!("Sim listener stopped");
+}
+
+async fn serve_client(
+    cancel: CancellationToken,
+    mut stream: TcpStream,
+    mut receiver: broadcast::Receiver<SimFrame>,
+    frame_interval: Duration,
+) {
+    let mut pending = HashMap::<String, SimFrame>::new();
+    let mut frame_timer = interval(frame_interval);
+
+    loop {
+        select! {
+            _ = cancel.cancelled() => break,
+
+            frame = receiver.recv() => match frame {
+                Ok(frame) => { pending.insert(frame.universe_id.clone(), frame); },
+                Err(RecvError::Lagged(count)) => info!("Sim client is too slow, {} frames dropped", count),
+                Err(RecvError::Closed) => break,
+            },
+
+            _ = frame_timer.tick() => {
+                for (_, frame) in pending.drain() {
+                    let mut line = serde_json::to_vec(&frame).unwrap();
+                    line.push(b'\n');
+
+                    if stream.write_all(&line).await.is_err() {
+                        return;
+                    }
+                }
+            },
+        }
+    }
+}
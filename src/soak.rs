@@ -0,0 +1,315 @@
+// Soak tests: the managers run for many simulated ticks (paused tokio time) while random On, Off, Set and Value commands
+// are sent, and arrays and universes are removed and defined again. Afterwards the managers are checked for state
+// left behind by removed arrays and universes (see find_leaks), and the allocations still alive on the test thread are
+// compared between two equal length phases, so a leak that grows with the number of commands fails the test.
+//
+// The scaled down test runs with the other tests, longer runs can be done by changing its SoakConfig
+
+use bytes::Bytes;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    sync::Arc,
+    time::Instant,
+};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    array_manager::ArrayManager,
+    artnet_manager::{ArtnetManager, DEFAULT_TICK_DURATION},
+    manager_channel,
+    messages::{self, ToMqttPublisherMessage},
+    metrics::Metrics,
+    mqtt_subscriber::MqttSubscriber,
+};
+
+// Counts the allocations alive on each thread. The soak test runs the managers on the (single threaded) test runtime,
+// so allocations made by other tests running in parallel are not counted
+struct CountingAllocator;
+
+thread_local! {
+    static LIVE_ALLOCATIONS: Cell<i64> = const { Cell::new(0) };
+}
+
+fn add_live_allocations(delta: i64) {
+    let _ = LIVE_ALLOCATIONS.try_with(|live_allocations| live_allocations.set(live_allocations.get() + delta));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        add_live_allocations(1);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        add_live_allocations(-1);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        add_live_allocations(1);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn get_live_allocations() -> i64 {
+    LIVE_ALLOCATIONS.with(|live_allocations| live_allocations.get())
+}
+
+struct SoakConfig {
+    universes: usize,
+    arrays: usize,          // Array n uses universe n % universes
+    commands_per_tick: usize,
+    ticks: usize,       // Simulated ticks of each phase
+    seed: u64,
+}
+
+// Small deterministic generator (xorshift64), so a failing run can be repeated with the same seed
+struct SoakRandom(u64);
+
+impl SoakRandom {
+    fn next(&mut self, below: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % below as u64) as usize
+    }
+}
+
+struct SoakHarness {
+    config: SoakConfig,
+    random: SoakRandom,
+    subscriber: MqttSubscriber,
+    to_mqtt_publisher_rx: async_channel::Receiver<ToMqttPublisherMessage>,
+    _to_scheduler_rx: tokio::sync::mpsc::Receiver<messages::ToSchedulerMessage>,
+    artnet_manager: JoinHandle<ArtnetManager>,
+    array_manager: JoinHandle<ArrayManager>,
+    cancel: CancellationToken,
+    defined_universes: Vec<bool>,
+    defined_arrays: Vec<bool>,
+    commands: usize,
+    failed_commands: usize,     // Commands on removed arrays and universes are expected to fail
+}
+
+impl SoakHarness {
+    // Must run on a single threaded runtime with paused time (tokio::test(start_paused = true))
+    async fn new(config: SoakConfig) -> Self {
+        let cancel = CancellationToken::new();
+        let (to_artnet_tx, to_artnet_rx) = manager_channel::channel::<messages::ToArtnetManagerMessage>("artnet", 10);
+        let (to_array_tx, to_array_rx) = manager_channel::channel::<messages::ToArrayManagerMessage>("array", 10);
+        let (to_mqtt_publisher_tx, to_mqtt_publisher_rx) = async_channel::bounded(10);
+        let (to_scheduler_tx, to_scheduler_rx) = tokio::sync::mpsc::channel(10);
+        let metrics = Arc::new(Metrics::default());
+
+        let cancel_instance = cancel.clone();
+        let to_mqtt_publisher_tx_instance = to_mqtt_publisher_tx.clone();
+        let metrics_instance = metrics.clone();
+        let artnet_manager = tokio::spawn(async move {
+            let mut artnet_manager = ArtnetManager::new().with_metrics(metrics_instance);
+
+            artnet_manager.run(cancel_instance, to_artnet_rx, to_mqtt_publisher_tx_instance).await;
+            artnet_manager
+        });
+
+        let cancel_instance = cancel.clone();
+        let array_manager = tokio::spawn(async move {
+            let mut array_manager = ArrayManager::new();
+
+            array_manager.run(cancel_instance, to_array_rx).await;
+            array_manager
+        });
+
+        let mut harness = SoakHarness {
+            random: SoakRandom(config.seed.max(1)),
+            subscriber: MqttSubscriber::new(to_artnet_tx, to_array_tx, to_mqtt_publisher_tx, to_scheduler_tx, true).with_metrics(metrics),
+            to_mqtt_publisher_rx,
+            _to_scheduler_rx: to_scheduler_rx,
+            artnet_manager,
+            array_manager,
+            cancel,
+            defined_universes: vec![false; config.universes],
+            defined_arrays: vec![false; config.arrays],
+            commands: 0,
+            failed_commands: 0,
+            config,
+        };
+
+        harness.define_all().await;
+        harness
+    }
+
+    async fn publish(&mut self, topic: &str, payload: &str) {
+        let result = self.subscriber.handle_message(topic, &Bytes::from(payload.to_string()), false, Instant::now()).await;
+
+        self.commands += 1;
+        if result.is_err() {
+            self.failed_commands += 1;
+        }
+
+        // The publisher channel is bounded, so published messages are dropped before the subscriber waits for room
+        while self.to_mqtt_publisher_rx.try_recv().is_ok() {}
+    }
+
+    async fn tick(&mut self) {
+        tokio::time::sleep(DEFAULT_TICK_DURATION).await;
+        while self.to_mqtt_publisher_rx.try_recv().is_ok() {}
+    }
+
+    // Each universe has its own controller, so removing a universe releases its controller
+    async fn set_universe(&mut self, universe: usize, define: bool) {
+        let payload = match define {
+            true => format!(
+                r#"{{ "description": "Soak universe", "controller": "10.0.{}.{}", "net": 0, "subnet": 0, "universe": 0, "channels": 16, "disable_send": true }}"#,
+                universe / 200,
+                universe % 200 + 1
+            ),
+            false => String::new(),
+        };
+
+        self.publish(&format!("DMX/Universe/soak-u{universe}"), &payload).await;
+        self.defined_universes[universe] = define;
+    }
+
+    async fn set_array(&mut self, array: usize, define: bool) {
+        let payload = match define {
+            true => format!(
+                r#"{{ "universe_id": "soak-u{}", "lights": {{ "all": "s:{}" }} }}"#,
+                array % self.config.universes,
+                array / self.config.universes % 16 + 1
+            ),
+            false => String::new(),
+        };
+
+        self.publish(&format!("DMX/Array/soak-a{array}"), &payload).await;
+        self.defined_arrays[array] = define;
+    }
+
+    async fn define_all(&mut self) {
+        for universe in 0..self.config.universes {
+            self.set_universe(universe, true).await;
+        }
+
+        for array in 0..self.config.arrays {
+            self.set_array(array, true).await;
+        }
+    }
+
+    async fn run_random_command(&mut self) {
+        let array = self.random.next(self.config.arrays);
+        let universe = self.random.next(self.config.universes);
+        let value = self.random.next(256);
+
+        match self.random.next(40) {
+            0..=13 => self.publish("DMX/Command/On", &format!(r#"{{ "array_id": "soak-a{array}", "values": {{ "level": "{value}" }} }}"#)).await,
+            14..=23 => self.publish("DMX/Command/Off", &format!(r#"{{ "array_id": "soak-a{array}" }}"#)).await,
+            24..=31 => {
+                let channel = self.random.next(16) + 1;
+                self.publish("DMX/Command/Set", &format!(r#"{{ "universe_id": "soak-u{universe}", "channels": "s:{channel}", "target": "s({value})" }}"#)).await
+            }
+            32..=35 => {
+                let payload = match value % 4 {
+                    0 => String::new(),
+                    _ => format!(r#"{{ "value": "s({value})" }}"#),
+                };
+                self.publish(&format!("DMX/Value/soak-v{}", value % 8), &payload).await
+            }
+            36..=37 if self.defined_arrays[array] => self.set_array(array, false).await,
+            38..=39 if self.defined_universes[universe] => self.set_universe(universe, false).await,
+            _ => {}
+        }
+    }
+
+    // Removed arrays and universes are defined again after a while
+    async fn define_random_removed(&mut self) {
+        let array = self.random.next(self.config.arrays);
+        let universe = self.random.next(self.config.universes);
+
+        if !self.defined_arrays[array] {
+            self.set_array(array, true).await;
+        }
+
+        if !self.defined_universes[universe] {
+            self.set_universe(universe, true).await;
+        }
+    }
+
+    // Random commands for the configured number of ticks, then everything is defined again and all effects are
+    // stopped, so each phase ends in the same state
+    async fn run_phase(&mut self) {
+        for _ in 0..self.config.ticks {
+            for _ in 0..self.config.commands_per_tick {
+                self.run_random_command().await;
+            }
+            self.define_random_removed().await;
+            self.tick().await;
+        }
+
+        self.define_all().await;
+        self.stop_all().await;
+    }
+
+    async fn stop_all(&mut self) {
+        for array in 0..self.config.arrays {
+            self.publish("DMX/Command/Stop", &format!(r#"{{ "array_id": "soak-a{array}" }}"#)).await;
+        }
+
+        self.tick().await;
+    }
+
+    async fn remove_some(&mut self) {
+        for array in (0..self.config.arrays).step_by(2) {
+            self.set_array(array, false).await;
+        }
+
+        for universe in (0..self.config.universes).step_by(2) {
+            self.set_universe(universe, false).await;
+        }
+
+        self.tick().await;
+    }
+
+    // Stop the managers and return them for checking their state
+    async fn finish(self) -> (ArtnetManager, ArrayManager) {
+        self.cancel.cancel();
+        (self.artnet_manager.await.unwrap(), self.array_manager.await.unwrap())
+    }
+}
+
+mod test_soak {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_soak() {
+        let mut harness = SoakHarness::new(SoakConfig {
+            universes: 4,
+            arrays: 12,
+            commands_per_tick: 5,
+            ticks: 200,
+            seed: 0x50a6,
+        }).await;
+
+        // The first phase warms up caches and grows the maps to their final capacity
+        harness.run_phase().await;
+        let live_allocations = get_live_allocations();
+        harness.run_phase().await;
+        let growth = get_live_allocations() - live_allocations;
+
+        // A leak of one allocation every 10 commands would be noticed
+        assert!(growth < 100, "{growth} more live allocations after the second phase");
+        assert!(harness.failed_commands < harness.commands / 2, "{} of {} commands failed", harness.failed_commands, harness.commands);
+
+        harness.remove_some().await;
+        let (artnet_manager, array_manager) = harness.finish().await;
+
+        assert_eq!(artnet_manager.find_leaks(), Vec::<String>::new());
+        assert_eq!(array_manager.find_leaks(), Vec::<String>::new());
+    }
+}